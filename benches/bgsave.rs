@@ -0,0 +1,72 @@
+//! Write latency during a background save of a 1M-key dataset.
+//!
+//! Run with `cargo bench --bench bgsave`. Compares the batched
+//! `snapshot::save` with serializing under a single read lock, reporting
+//! save time and the worst wait of a concurrent writer.
+
+use hexagondb::db::{StringOps, DB};
+use hexagondb::persistence::snapshot;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+const KEYS: usize = 1_000_000;
+const VALUE_SIZE: usize = 100;
+
+/// Set keys until `done`, returning the number of writes and the worst
+/// time spent waiting for the write lock
+async fn write_until(db: Arc<RwLock<DB>>, done: Arc<AtomicBool>) -> (usize, Duration) {
+    let (mut writes, mut worst) = (0, Duration::ZERO);
+    while !done.load(Ordering::Relaxed) {
+        let start = Instant::now();
+        let mut guard = db.write().await;
+        worst = worst.max(start.elapsed());
+        guard.set(format!("writer:{}", writes % 1000), writes.to_string());
+        drop(guard);
+        writes += 1;
+        tokio::task::yield_now().await;
+    }
+    (writes, worst)
+}
+
+async fn run(name: &str, db: &Arc<RwLock<DB>>, path: &Path, batched: bool) {
+    let done = Arc::new(AtomicBool::new(false));
+    let writer = tokio::spawn(write_until(Arc::clone(db), Arc::clone(&done)));
+
+    let start = Instant::now();
+    if batched {
+        snapshot::save(path, db).await.unwrap();
+    } else {
+        let guard = db.read().await;
+        snapshot::save_db(path, &guard).unwrap();
+    }
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::Relaxed);
+    let (writes, worst) = writer.await.unwrap();
+
+    println!(
+        "{:<12} save {:>8.1} ms   writes during save {:>8}   worst write wait {:>8.2} ms",
+        name,
+        elapsed.as_secs_f64() * 1000.0,
+        writes,
+        worst.as_secs_f64() * 1000.0
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let mut db = DB::with_capacity(KEYS);
+    for i in 0..KEYS {
+        db.set(format!("key:{:07}", i), format!("{:0>width$}", i, width = VALUE_SIZE));
+    }
+    let db = Arc::new(RwLock::new(db));
+    let path = std::env::temp_dir().join(format!("hexagondb-bench-{}.rdb", std::process::id()));
+
+    println!("BGSAVE with {} keys of {} bytes", KEYS, VALUE_SIZE);
+    run("single lock", &db, &path, false).await;
+    run("batched", &db, &path, true).await;
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,53 @@
+//! Pushing and popping both ends of a 1M-element list.
+//!
+//! Run with `cargo bench --bench list`. Times LPUSH and LPOP of every
+//! element, and a queue fed with RPUSH and drained with LPOP, reporting
+//! the average cost of each operation.
+
+use hexagondb::db::{ListOps, DB};
+use std::time::Instant;
+
+const ELEMENTS: usize = 1_000_000;
+const KEY: &str = "bench:list";
+
+fn report(name: &str, ops: usize, start: Instant) {
+    let elapsed = start.elapsed();
+    println!(
+        "{:<12} {:>8} ops {:>10.1} ms {:>10.3} us/op",
+        name,
+        ops,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() * 1_000_000.0 / ops as f64
+    );
+}
+
+fn main() {
+    let mut db = DB::new();
+    let key = || KEY.to_string();
+
+    let start = Instant::now();
+    for i in 0..ELEMENTS {
+        db.lpush(key(), vec![i.to_string()]).unwrap();
+    }
+    report("LPUSH", ELEMENTS, start);
+
+    let start = Instant::now();
+    for _ in 0..ELEMENTS {
+        db.lpop(key()).unwrap().unwrap();
+    }
+    report("LPOP", ELEMENTS, start);
+
+    let start = Instant::now();
+    for i in 0..ELEMENTS {
+        db.rpush(key(), vec![i.to_string()]).unwrap();
+    }
+    report("RPUSH", ELEMENTS, start);
+
+    let start = Instant::now();
+    for i in 0..ELEMENTS {
+        assert_eq!(db.lpop(key()).unwrap(), Some(i.to_string()));
+    }
+    report("LPOP (queue)", ELEMENTS, start);
+
+    assert_eq!(db.llen(&key()).unwrap(), 0);
+}
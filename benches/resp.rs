@@ -0,0 +1,65 @@
+//! Reply serialization for GET-heavy pipelines.
+//!
+//! Run with `cargo bench --bench resp`. Encodes pipelines of GET replies
+//! (100-byte values with some misses) the way the connection handler used
+//! to, one `serialize` String per reply copied into the output, and with
+//! `serialize_into` writing straight into a reused buffer.
+
+use bytes::BytesMut;
+use hexagondb::network::resp::RespValue;
+use std::time::Instant;
+
+const PIPELINE: usize = 100;
+const BATCHES: usize = 20_000;
+const VALUE_SIZE: usize = 100;
+
+fn report(name: &str, bytes: usize, start: Instant) {
+    let elapsed = start.elapsed();
+    let replies = PIPELINE * BATCHES;
+    println!(
+        "{:<16} {:>8} replies {:>9.1} ms {:>8.1} ns/reply {:>8.0} MB/s",
+        name,
+        replies,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() * 1e9 / replies as f64,
+        bytes as f64 / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let replies: Vec<RespValue> = (0..PIPELINE)
+        .map(|i| {
+            // One GET in ten misses
+            if i % 10 == 9 {
+                RespValue::BulkString(None)
+            } else {
+                RespValue::BulkString(Some(format!("{:0>width$}", i, width = VALUE_SIZE)))
+            }
+        })
+        .collect();
+
+    println!("{} pipelines of {} GET replies", BATCHES, PIPELINE);
+
+    let start = Instant::now();
+    let mut bytes = 0;
+    for _ in 0..BATCHES {
+        let mut output = Vec::new();
+        for reply in &replies {
+            output.extend_from_slice(reply.serialize().as_bytes());
+        }
+        bytes += std::hint::black_box(output).len();
+    }
+    report("serialize", bytes, start);
+
+    let start = Instant::now();
+    let mut bytes = 0;
+    let mut output = BytesMut::new();
+    for _ in 0..BATCHES {
+        for reply in &replies {
+            reply.serialize_into(&mut output);
+        }
+        bytes += std::hint::black_box(&output).len();
+        output.clear();
+    }
+    report("serialize_into", bytes, start);
+}
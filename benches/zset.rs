@@ -0,0 +1,84 @@
+//! Sorted set queries on a 1M-member set.
+//!
+//! Run with `cargo bench --bench zset`. Times ZADD of every member, then
+//! rank lookups and small range reads spread across the set, reporting the
+//! average cost of each operation.
+
+use hexagondb::db::{ZSetOps, DB};
+use rand::Rng;
+use std::time::Instant;
+
+const MEMBERS: usize = 1_000_000;
+const QUERIES: usize = 10_000;
+const KEY: &str = "bench:zset";
+
+fn report(name: &str, ops: usize, start: Instant) {
+    let elapsed = start.elapsed();
+    println!(
+        "{:<28} {:>8} ops {:>10.1} ms {:>10.2} us/op",
+        name,
+        ops,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_secs_f64() * 1_000_000.0 / ops as f64
+    );
+}
+
+fn main() {
+    let mut db = DB::new();
+    let mut rng = rand::thread_rng();
+    let key = || KEY.to_string();
+
+    let start = Instant::now();
+    for i in 0..MEMBERS {
+        db.zadd(key(), vec![(rng.gen_range(0.0..1_000_000.0), format!("member:{:07}", i))]).unwrap();
+    }
+    report("ZADD", MEMBERS, start);
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        let member = format!("member:{:07}", rng.gen_range(0..MEMBERS));
+        assert!(db.zrank(&key(), &member).is_some());
+    }
+    report("ZRANK", QUERIES, start);
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        let rank = rng.gen_range(0..MEMBERS as i64 - 10);
+        assert_eq!(db.zrange(&key(), rank, rank + 9, true).len(), 10);
+    }
+    report("ZRANGE 10 members", QUERIES, start);
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        let rank = rng.gen_range(10..MEMBERS as i64);
+        assert_eq!(db.zrevrange(&key(), rank - 10, rank - 1, false).len(), 10);
+    }
+    report("ZREVRANGE 10 members", QUERIES, start);
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        let min = rng.gen_range(0.0..999_000.0);
+        db.zcount(&key(), min, min + 1000.0);
+    }
+    report("ZCOUNT 0.1% of scores", QUERIES, start);
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        let min = rng.gen_range(0.0..500_000.0);
+        db.zrangebyscore(&key(), min, 1_000_000.0, false, Some(1000), Some(10));
+    }
+    report("ZRANGEBYSCORE LIMIT 1000 10", QUERIES, start);
+
+    let start = Instant::now();
+    for _ in 0..QUERIES {
+        let member = format!("member:{:07}", rng.gen_range(0..MEMBERS));
+        db.zadd(key(), vec![(rng.gen_range(0.0..1_000_000.0), member)]).unwrap();
+    }
+    report("ZADD score update", QUERIES, start);
+
+    let start = Instant::now();
+    db.zpopmin(key(), Some(QUERIES));
+    report("ZPOPMIN", QUERIES, start);
+
+    assert_eq!(db.zcard(&key()), MEMBERS - QUERIES);
+}
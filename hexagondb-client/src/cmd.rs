@@ -0,0 +1,155 @@
+//! Command and pipeline builders.
+
+use crate::resp::encode_command;
+
+/// A value that can be sent as one command argument
+pub trait ToArg {
+    fn to_arg(&self) -> Vec<u8>;
+}
+
+impl ToArg for str {
+    fn to_arg(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToArg for String {
+    fn to_arg(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl ToArg for [u8] {
+    fn to_arg(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl ToArg for Vec<u8> {
+    fn to_arg(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+macro_rules! to_arg_display {
+    ($($t:ty),*) => {$(
+        impl ToArg for $t {
+            fn to_arg(&self) -> Vec<u8> {
+                self.to_string().into_bytes()
+            }
+        }
+    )*};
+}
+
+to_arg_display!(i64, i32, u64, u32, usize, f64);
+
+impl<T: ToArg + ?Sized> ToArg for &T {
+    fn to_arg(&self) -> Vec<u8> {
+        (**self).to_arg()
+    }
+}
+
+/// A command and its arguments
+///
+/// ```
+/// use hexagondb_client::Cmd;
+///
+/// let cmd = Cmd::new("ZADD").arg("scores").arg(1.5).arg("alice");
+/// assert_eq!(cmd.name(), "ZADD");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    args: Vec<Vec<u8>>,
+}
+
+impl Cmd {
+    pub fn new(name: &str) -> Self {
+        Cmd {
+            args: vec![name.as_bytes().to_vec()],
+        }
+    }
+
+    /// Append one argument
+    pub fn arg<A: ToArg>(mut self, arg: A) -> Self {
+        self.args.push(arg.to_arg());
+        self
+    }
+
+    /// Append every argument of an iterator
+    pub fn args<I>(mut self, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: ToArg,
+    {
+        self.args.extend(args.into_iter().map(|a| a.to_arg()));
+        self
+    }
+
+    /// Command name as given to `new`
+    pub fn name(&self) -> &str {
+        std::str::from_utf8(&self.args[0]).unwrap_or_default()
+    }
+
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        encode_command(&self.args, out);
+    }
+}
+
+/// Commands sent in one write, whose replies are read back together.
+/// Other clients' commands may run in between; this saves round trips,
+/// it is not a transaction.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    cmds: Vec<Cmd>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cmd(&mut self, cmd: Cmd) -> &mut Self {
+        self.cmds.push(cmd);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.cmds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    /// All commands as one buffer
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for cmd in &self.cmds {
+            cmd.encode(&mut out);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_args() {
+        let mut out = Vec::new();
+        Cmd::new("ZADD").arg("z").arg(1.5).arg("m".to_string()).encode(&mut out);
+        assert_eq!(out, b"*4\r\n$4\r\nZADD\r\n$1\r\nz\r\n$3\r\n1.5\r\n$1\r\nm\r\n");
+
+        let cmd = Cmd::new("DEL").args(["a", "b"]).arg(b"c".as_slice());
+        assert_eq!(cmd.args.len(), 4);
+    }
+
+    #[test]
+    fn test_pipeline_encoding() {
+        let mut pipe = Pipeline::new();
+        pipe.cmd(Cmd::new("INCR").arg("n")).cmd(Cmd::new("PING"));
+        assert_eq!(pipe.len(), 2);
+        assert_eq!(pipe.encode(), b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n*1\r\n$4\r\nPING\r\n");
+    }
+}
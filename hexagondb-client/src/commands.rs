@@ -0,0 +1,229 @@
+//! Typed command methods on [`Connection`].
+//!
+//! Commands without a method here can be sent with
+//! [`Connection::query`] and a [`Cmd`].
+
+use crate::cmd::{Cmd, ToArg};
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::value::{FromValue, Value};
+
+/// Key counts from DBSTATS
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DbStats {
+    pub keys: u64,
+    /// Key count per type name, sorted by name
+    pub types: Vec<(String, u64)>,
+}
+
+impl FromValue for DbStats {
+    fn from_value(value: Value) -> Result<Self> {
+        // ["keys", n, "types", [name, count, ...]]
+        let fields = Vec::<Value>::from_value(value)?;
+        let mut stats = DbStats::default();
+        let mut fields = fields.into_iter();
+        while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+            match String::from_value(name)?.as_str() {
+                "keys" => stats.keys = u64::from_value(value)?,
+                "types" => {
+                    let flat = Vec::<Value>::from_value(value)?;
+                    let mut flat = flat.into_iter();
+                    while let (Some(name), Some(count)) = (flat.next(), flat.next()) {
+                        stats.types.push((String::from_value(name)?, u64::from_value(count)?));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// File format of a [`Connection::dumpall`] download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Rdb,
+    Aof,
+}
+
+impl Connection {
+    pub async fn ping(&mut self) -> Result<String> {
+        self.query(&Cmd::new("PING")).await
+    }
+
+    // ===== Strings and keys =====
+
+    /// GET; use `Option<T>` to tell a missing key from an empty value
+    pub async fn get<T: FromValue>(&mut self, key: impl ToArg) -> Result<T> {
+        self.query(&Cmd::new("GET").arg(key)).await
+    }
+
+    pub async fn set(&mut self, key: impl ToArg, value: impl ToArg) -> Result<()> {
+        self.query(&Cmd::new("SET").arg(key).arg(value)).await
+    }
+
+    pub async fn mget<T: FromValue>(&mut self, keys: &[impl ToArg]) -> Result<Vec<T>> {
+        self.query(&Cmd::new("MGET").args(keys)).await
+    }
+
+    pub async fn incr(&mut self, key: impl ToArg) -> Result<i64> {
+        self.query(&Cmd::new("INCR").arg(key)).await
+    }
+
+    pub async fn incr_by(&mut self, key: impl ToArg, by: i64) -> Result<i64> {
+        self.query(&Cmd::new("INCRBY").arg(key).arg(by)).await
+    }
+
+    /// Number of keys removed
+    pub async fn del(&mut self, keys: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("DEL").args(keys)).await
+    }
+
+    /// Like `del`, but large values are freed in the background
+    pub async fn unlink(&mut self, keys: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("UNLINK").args(keys)).await
+    }
+
+    pub async fn exists(&mut self, keys: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("EXISTS").args(keys)).await
+    }
+
+    /// True if the timeout was set
+    pub async fn expire(&mut self, key: impl ToArg, seconds: u64) -> Result<bool> {
+        self.query(&Cmd::new("EXPIRE").arg(key).arg(seconds)).await
+    }
+
+    /// Seconds to live; -1 without an expiry, -2 if the key is missing
+    pub async fn ttl(&mut self, key: impl ToArg) -> Result<i64> {
+        self.query(&Cmd::new("TTL").arg(key)).await
+    }
+
+    // ===== Hashes =====
+
+    /// Number of fields added
+    pub async fn hset(&mut self, key: impl ToArg, field: impl ToArg, value: impl ToArg) -> Result<u64> {
+        self.query(&Cmd::new("HSET").arg(key).arg(field).arg(value)).await
+    }
+
+    pub async fn hget<T: FromValue>(&mut self, key: impl ToArg, field: impl ToArg) -> Result<T> {
+        self.query(&Cmd::new("HGET").arg(key).arg(field)).await
+    }
+
+    /// All fields and values, e.g. as a `HashMap<String, String>`
+    pub async fn hgetall<T: FromValue>(&mut self, key: impl ToArg) -> Result<T> {
+        self.query(&Cmd::new("HGETALL").arg(key)).await
+    }
+
+    pub async fn hdel(&mut self, key: impl ToArg, fields: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("HDEL").arg(key).args(fields)).await
+    }
+
+    // ===== Lists =====
+
+    /// Length of the list after the push
+    pub async fn lpush(&mut self, key: impl ToArg, values: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("LPUSH").arg(key).args(values)).await
+    }
+
+    pub async fn rpush(&mut self, key: impl ToArg, values: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("RPUSH").arg(key).args(values)).await
+    }
+
+    pub async fn lrange<T: FromValue>(&mut self, key: impl ToArg, start: i64, stop: i64) -> Result<Vec<T>> {
+        self.query(&Cmd::new("LRANGE").arg(key).arg(start).arg(stop)).await
+    }
+
+    // ===== Sets =====
+
+    pub async fn sadd(&mut self, key: impl ToArg, members: &[impl ToArg]) -> Result<u64> {
+        self.query(&Cmd::new("SADD").arg(key).args(members)).await
+    }
+
+    pub async fn smembers<T: FromValue>(&mut self, key: impl ToArg) -> Result<Vec<T>> {
+        self.query(&Cmd::new("SMEMBERS").arg(key)).await
+    }
+
+    // ===== Sorted sets =====
+
+    /// Number of members added
+    pub async fn zadd(&mut self, key: impl ToArg, score: f64, member: impl ToArg) -> Result<u64> {
+        self.query(&Cmd::new("ZADD").arg(key).arg(score).arg(member)).await
+    }
+
+    pub async fn zrange<T: FromValue>(&mut self, key: impl ToArg, start: i64, stop: i64) -> Result<Vec<T>> {
+        self.query(&Cmd::new("ZRANGE").arg(key).arg(start).arg(stop)).await
+    }
+
+    pub async fn zscore(&mut self, key: impl ToArg, member: impl ToArg) -> Result<Option<f64>> {
+        self.query(&Cmd::new("ZSCORE").arg(key).arg(member)).await
+    }
+
+    // ===== Pub/sub =====
+
+    /// Number of subscribers that received the message
+    pub async fn publish(&mut self, channel: impl ToArg, message: impl ToArg) -> Result<u64> {
+        self.query(&Cmd::new("PUBLISH").arg(channel).arg(message)).await
+    }
+
+    // ===== HexagonDB commands =====
+
+    /// Bring back a key removed while the recycle bin is enabled
+    pub async fn undelete(&mut self, key: impl ToArg) -> Result<bool> {
+        self.query(&Cmd::new("UNDELETE").arg(key)).await
+    }
+
+    /// Key count, total and per type
+    pub async fn dbstats(&mut self) -> Result<DbStats> {
+        self.query(&Cmd::new("DBSTATS")).await
+    }
+
+    /// Approximate bytes used by a key, `None` if it does not exist
+    pub async fn memory_usage(&mut self, key: impl ToArg) -> Result<Option<u64>> {
+        self.query(&Cmd::new("MEMORY").arg("USAGE").arg(key)).await
+    }
+
+    /// The whole dataset as an RDB snapshot or a standalone AOF file
+    pub async fn dumpall(&mut self, format: DumpFormat) -> Result<Vec<u8>> {
+        let format = match format {
+            DumpFormat::Rdb => "RDB",
+            DumpFormat::Aof => "AOF",
+        };
+        let hex: String = self.query(&Cmd::new("DUMPALL").arg(format)).await?;
+        decode_hex(&hex).ok_or_else(|| Error::Type("DUMPALL reply is not hex".to_string()))
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dbstats_reply() {
+        let data = |s: &str| Value::Data(s.as_bytes().to_vec());
+        let reply = Value::Array(vec![
+            data("keys"),
+            Value::Int(3),
+            data("types"),
+            Value::Array(vec![data("list"), Value::Int(1), data("string"), Value::Int(2)]),
+        ]);
+        assert_eq!(
+            DbStats::from_value(reply).unwrap(),
+            DbStats {
+                keys: 3,
+                types: vec![("list".to_string(), 1), ("string".to_string(), 2)],
+            }
+        );
+        assert_eq!(decode_hex("00ff"), Some(vec![0, 255]));
+        assert_eq!(decode_hex("0"), None);
+    }
+}
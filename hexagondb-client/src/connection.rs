@@ -0,0 +1,234 @@
+//! A single connection to the server.
+
+use crate::cmd::{Cmd, Pipeline};
+use crate::error::{Error, Result};
+use crate::pubsub::PubSub;
+use crate::resp;
+use crate::value::{FromValue, Value};
+use bytes::{Buf, BytesMut};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Protocol version spoken on a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    /// Plain RESP2; no handshake beyond AUTH
+    #[default]
+    Resp2,
+    /// Send `HELLO 3` on connect. Replies are decoded the same way either
+    /// way; this only asks the server for RESP3 types.
+    Resp3,
+}
+
+/// Where and how to connect
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    addr: String,
+    username: Option<String>,
+    password: Option<String>,
+    protocol: Protocol,
+    connect_timeout: Duration,
+}
+
+impl ConnectOptions {
+    /// Options for `host:port`, unauthenticated, RESP2
+    pub fn new(addr: impl Into<String>) -> Self {
+        ConnectOptions {
+            addr: addr.into(),
+            username: None,
+            password: None,
+            protocol: Protocol::Resp2,
+            connect_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// ACL user to log in as (with `password`)
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+}
+
+/// A connection to the server. Commands are sent one at a time with
+/// [`query`](Connection::query) or the typed methods, or batched with
+/// [`pipeline`](Connection::pipeline).
+pub struct Connection {
+    stream: TcpStream,
+    buf: BytesMut,
+    /// Set while a request is in flight, so a connection whose request was
+    /// cancelled or failed halfway is never handed out again by a pool
+    broken: bool,
+}
+
+impl Connection {
+    /// Connect and log in
+    pub async fn connect(options: &ConnectOptions) -> Result<Self> {
+        let stream = tokio::time::timeout(options.connect_timeout, TcpStream::connect(&options.addr))
+            .await
+            .map_err(|_| Error::ConnectTimeout(options.addr.clone()))??;
+        stream.set_nodelay(true)?;
+        let mut conn = Connection {
+            stream,
+            buf: BytesMut::with_capacity(8 * 1024),
+            broken: false,
+        };
+        conn.handshake(options).await?;
+        Ok(conn)
+    }
+
+    async fn handshake(&mut self, options: &ConnectOptions) -> Result<()> {
+        let user = options.username.as_deref();
+        match (options.protocol, options.password.as_deref()) {
+            (Protocol::Resp3, password) => {
+                let mut hello = Cmd::new("HELLO").arg("3");
+                if let Some(password) = password {
+                    hello = hello.arg("AUTH").arg(user.unwrap_or("default")).arg(password);
+                }
+                self.query::<()>(&hello).await
+            }
+            (Protocol::Resp2, Some(password)) => {
+                let auth = match user {
+                    Some(user) => Cmd::new("AUTH").arg(user).arg(password),
+                    None => Cmd::new("AUTH").arg(password),
+                };
+                self.query::<()>(&auth).await
+            }
+            (Protocol::Resp2, None) => Ok(()),
+        }
+    }
+
+    /// Send a command and convert its reply. An error reply is returned
+    /// as [`Error::Server`].
+    pub async fn query<T: FromValue>(&mut self, cmd: &Cmd) -> Result<T> {
+        let mut out = Vec::new();
+        cmd.encode(&mut out);
+        self.broken = true;
+        self.stream.write_all(&out).await?;
+        let value = self.read_reply().await?;
+        self.broken = false;
+        T::from_value(value.into_result()?)
+    }
+
+    /// Send all commands of a pipeline in one write and read their replies.
+    /// An error reply of one command is kept as [`Value::Error`] in its
+    /// slot.
+    pub async fn pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<Value>> {
+        self.broken = true;
+        self.stream.write_all(&pipeline.encode()).await?;
+        let mut replies = Vec::with_capacity(pipeline.len());
+        for _ in 0..pipeline.len() {
+            replies.push(self.read_reply().await?);
+        }
+        self.broken = false;
+        Ok(replies)
+    }
+
+    /// Whether the last request failed or was cancelled before its reply
+    /// was read. Such a connection may be out of step with the server.
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Turn this connection into a pub/sub subscriber
+    pub fn into_pubsub(self) -> PubSub {
+        PubSub::new(self)
+    }
+
+    /// Next reply that is not an out-of-band push
+    async fn read_reply(&mut self) -> Result<Value> {
+        loop {
+            match self.read_value().await? {
+                Value::Push(_) => continue,
+                value => return Ok(value),
+            }
+        }
+    }
+
+    pub(crate) async fn write_cmd(&mut self, cmd: &Cmd) -> Result<()> {
+        let mut out = Vec::new();
+        cmd.encode(&mut out);
+        self.stream.write_all(&out).await?;
+        Ok(())
+    }
+
+    /// Next value from the socket, pushes included
+    pub(crate) async fn read_value(&mut self) -> Result<Value> {
+        loop {
+            if let Some((value, used)) = resp::decode(&self.buf)? {
+                self.buf.advance(used);
+                return Ok(value);
+            }
+            if self.stream.read_buf(&mut self.buf).await? == 0 {
+                return Err(Error::Closed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+
+    /// A tiny server: PING, GET and INCR of one counter
+    fn counter() -> impl FnMut(&[String]) -> Vec<u8> {
+        let mut n = 0;
+        move |args| match args[0].to_uppercase().as_str() {
+            "PING" => b"+PONG\r\n".to_vec(),
+            "INCR" => {
+                n += 1;
+                format!(":{}\r\n", n).into_bytes()
+            }
+            "GET" => b"$5\r\nhello\r\n".to_vec(),
+            _ => b"-ERR unknown command\r\n".to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_and_pipeline() {
+        let addr = serve(counter).await;
+        let mut conn = Connection::connect(&ConnectOptions::new(addr)).await.unwrap();
+
+        assert_eq!(conn.ping().await.unwrap(), "PONG");
+        let value: Option<String> = conn.get("k").await.unwrap();
+        assert_eq!(value.as_deref(), Some("hello"));
+        let err = conn.query::<()>(&Cmd::new("NOPE")).await.unwrap_err();
+        assert_eq!(err.code(), Some("ERR"));
+        assert!(!conn.is_broken());
+
+        let mut pipe = Pipeline::new();
+        pipe.cmd(Cmd::new("INCR").arg("n")).cmd(Cmd::new("NOPE")).cmd(Cmd::new("INCR").arg("n"));
+        let replies = conn.pipeline(&pipe).await.unwrap();
+        assert_eq!(replies, vec![Value::Int(1), Value::Error("ERR unknown command".into()), Value::Int(2)]);
+        assert_eq!(conn.incr("n").await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_closed_connection_is_broken() {
+        let addr = serve(|| |_: &[String]| b"+PON".to_vec()).await;
+        let mut conn = Connection::connect(&ConnectOptions::new(addr)).await.unwrap();
+        let reply = tokio::time::timeout(Duration::from_millis(100), conn.ping()).await;
+        assert!(reply.is_err());
+        assert!(conn.is_broken());
+    }
+}
@@ -0,0 +1,48 @@
+//! Client errors.
+
+use std::io;
+
+/// Result type of client operations
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Errors returned by the client
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Socket error; the connection is not reused afterwards
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The server closed the connection
+    #[error("connection closed by server")]
+    Closed,
+
+    /// An error reply from the server (`-ERR ...`, `-WRONGTYPE ...`)
+    #[error("server error: {0}")]
+    Server(String),
+
+    /// The server sent something that is not valid RESP
+    #[error("protocol error: {0}")]
+    Protocol(String),
+
+    /// A reply could not be converted to the requested type
+    #[error("unexpected reply: {0}")]
+    Type(String),
+
+    /// No pooled connection became free within the acquire timeout
+    #[error("timed out waiting for a pooled connection")]
+    PoolTimeout,
+
+    /// Connecting took longer than the connect timeout
+    #[error("timed out connecting to {0}")]
+    ConnectTimeout(String),
+}
+
+impl Error {
+    /// Error code of a server error: `WRONGTYPE`, `NOAUTH`, `ERR`, ...
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Server(msg) => msg.split_whitespace().next(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! Async Rust client for HexagonDB.
+//!
+//! - Typed methods for common commands and HexagonDB's own (DBSTATS,
+//!   DUMPALL, UNDELETE, ...), plus [`Connection::query`] for any command
+//! - RESP2 and RESP3 replies, decoded into [`Value`] and converted with
+//!   [`FromValue`]
+//! - Pipelines: many commands per round trip
+//! - Pub/sub subscriptions as a [`Stream`](futures_core::Stream)
+//! - A connection [`Pool`]
+//!
+//! ```no_run
+//! # async fn demo() -> hexagondb_client::Result<()> {
+//! use hexagondb_client::{Cmd, ConnectOptions, Connection, Pipeline};
+//!
+//! let mut conn = Connection::connect(&ConnectOptions::new("127.0.0.1:2112")).await?;
+//! conn.set("visits", 0).await?;
+//! let visits = conn.incr("visits").await?;
+//! let name: Option<String> = conn.get("name").await?;
+//!
+//! let mut pipe = Pipeline::new();
+//! pipe.cmd(Cmd::new("INCR").arg("visits")).cmd(Cmd::new("GET").arg("visits"));
+//! let replies = conn.pipeline(&pipe).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod cmd;
+mod commands;
+mod connection;
+mod error;
+mod pool;
+mod pubsub;
+pub mod resp;
+#[cfg(test)]
+mod testing;
+mod value;
+
+pub use cmd::{Cmd, Pipeline, ToArg};
+pub use commands::{DbStats, DumpFormat};
+pub use connection::{ConnectOptions, Connection, Protocol};
+pub use error::{Error, Result};
+pub use pool::{Pool, PoolConfig, PooledConnection};
+pub use pubsub::{Message, MessageStream, PubSub};
+pub use value::{FromValue, Value};
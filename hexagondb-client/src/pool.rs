@@ -0,0 +1,169 @@
+//! Connection pool.
+
+use crate::connection::{ConnectOptions, Connection};
+use crate::error::{Error, Result};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Pool limits
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Connections open at once, idle and in use together
+    pub max_size: usize,
+    /// How long [`Pool::get`] waits for a connection to become free
+    pub acquire_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_size: 16,
+            acquire_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+struct PoolInner {
+    options: ConnectOptions,
+    config: PoolConfig,
+    idle: Mutex<Vec<Connection>>,
+    permits: Arc<Semaphore>,
+}
+
+/// A cloneable pool of connections to one server. Connections are opened
+/// on demand up to `max_size` and returned to the pool when the
+/// [`PooledConnection`] is dropped, unless a request on them failed.
+///
+/// ```no_run
+/// # async fn demo() -> hexagondb_client::Result<()> {
+/// use hexagondb_client::{ConnectOptions, Pool, PoolConfig};
+///
+/// let pool = Pool::new(ConnectOptions::new("127.0.0.1:6379"), PoolConfig::default());
+/// let mut conn = pool.get().await?;
+/// conn.set("greeting", "hello").await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<PoolInner>,
+}
+
+impl Pool {
+    pub fn new(options: ConnectOptions, config: PoolConfig) -> Self {
+        let permits = Arc::new(Semaphore::new(config.max_size));
+        Pool {
+            inner: Arc::new(PoolInner {
+                options,
+                config,
+                idle: Mutex::new(Vec::new()),
+                permits,
+            }),
+        }
+    }
+
+    /// Take an idle connection, or open one if the pool is below
+    /// `max_size`; otherwise wait up to `acquire_timeout` for one to be
+    /// returned.
+    pub async fn get(&self) -> Result<PooledConnection> {
+        let permit = tokio::time::timeout(
+            self.inner.config.acquire_timeout,
+            Arc::clone(&self.inner.permits).acquire_owned(),
+        )
+        .await
+        .map_err(|_| Error::PoolTimeout)?
+        .expect("pool semaphore is never closed");
+
+        let idle = self.inner.idle.lock().expect("pool lock poisoned").pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => Connection::connect(&self.inner.options).await?,
+        };
+        Ok(PooledConnection {
+            conn: Some(conn),
+            pool: Arc::clone(&self.inner),
+            _permit: permit,
+        })
+    }
+
+    /// Connections waiting in the pool
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().expect("pool lock poisoned").len()
+    }
+
+    /// Connections that could still be taken before `get` has to wait
+    pub fn available(&self) -> usize {
+        self.inner.permits.available_permits()
+    }
+}
+
+/// A connection borrowed from a [`Pool`]; derefs to [`Connection`]
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    pool: Arc<PoolInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledConnection {
+    /// Keep the connection out of the pool, e.g. to turn it into a
+    /// subscriber. Its slot is freed.
+    pub fn detach(mut self) -> Connection {
+        self.conn.take().expect("connection present until drop")
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection present until drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn.as_mut().expect("connection present until drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if !conn.is_broken() {
+                self.pool.idle.lock().expect("pool lock poisoned").push(conn);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::serve;
+
+    #[tokio::test]
+    async fn test_pool_reuses_and_limits() {
+        let addr = serve(|| |_: &[String]| b"+PONG\r\n".to_vec()).await;
+        let config = PoolConfig {
+            max_size: 1,
+            acquire_timeout: Duration::from_millis(50),
+        };
+        let pool = Pool::new(ConnectOptions::new(addr), config);
+
+        let mut conn = pool.get().await.unwrap();
+        assert_eq!(conn.ping().await.unwrap(), "PONG");
+        assert_eq!(pool.available(), 0);
+        assert!(matches!(pool.get().await, Err(Error::PoolTimeout)));
+
+        drop(conn);
+        assert_eq!((pool.idle(), pool.available()), (1, 1));
+        let conn = pool.get().await.unwrap();
+        assert_eq!(pool.idle(), 0);
+
+        // A detached connection does not come back
+        drop(conn.detach());
+        assert_eq!((pool.idle(), pool.available()), (0, 1));
+    }
+}
@@ -0,0 +1,223 @@
+//! Pub/sub subscriptions.
+
+use crate::cmd::Cmd;
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::value::{FromValue, Value};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Messages buffered by a [`MessageStream`] before the reader waits
+const STREAM_BUFFER: usize = 256;
+
+/// A message published to a subscribed channel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    /// Pattern that matched, for PSUBSCRIBE subscriptions
+    pub pattern: Option<String>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Payload as UTF-8 text
+    pub fn payload_str(&self) -> Result<&str> {
+        std::str::from_utf8(&self.payload).map_err(|e| Error::Type(format!("payload is not UTF-8: {}", e)))
+    }
+
+    /// `message`/`pmessage` frames; `None` for confirmations and other replies
+    fn from_frame(frame: Value) -> Result<Option<Self>> {
+        let items = match frame {
+            Value::Array(items) | Value::Push(items) => items,
+            Value::Error(msg) => return Err(Error::Server(msg)),
+            _ => return Ok(None),
+        };
+        let mut items = items.into_iter();
+        let kind = match items.next() {
+            Some(kind) => String::from_value(kind)?,
+            None => return Ok(None),
+        };
+        let rest: Vec<Value> = items.collect();
+        let mut rest = rest.into_iter();
+        let mut next = || rest.next().ok_or_else(|| Error::Protocol(format!("short {} frame", kind)));
+        match kind.to_ascii_lowercase().as_str() {
+            "message" => Ok(Some(Message {
+                channel: String::from_value(next()?)?,
+                pattern: None,
+                payload: Vec::<u8>::from_value(next()?)?,
+            })),
+            "pmessage" => Ok(Some(Message {
+                pattern: Some(String::from_value(next()?)?),
+                channel: String::from_value(next()?)?,
+                payload: Vec::<u8>::from_value(next()?)?,
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A connection in subscribe mode. Only pub/sub commands can be sent on
+/// it; messages are read with [`next_message`](PubSub::next_message) or
+/// as a [`Stream`] with [`into_stream`](PubSub::into_stream).
+pub struct PubSub {
+    conn: Connection,
+    /// Messages read while waiting for a subscribe confirmation
+    pending: VecDeque<Message>,
+}
+
+impl PubSub {
+    pub(crate) fn new(conn: Connection) -> Self {
+        PubSub {
+            conn,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Subscribe to a channel and wait for the confirmation
+    pub async fn subscribe(&mut self, channel: &str) -> Result<()> {
+        self.request(Cmd::new("SUBSCRIBE").arg(channel), "subscribe").await
+    }
+
+    /// Subscribe to every channel matching a glob pattern
+    pub async fn psubscribe(&mut self, pattern: &str) -> Result<()> {
+        self.request(Cmd::new("PSUBSCRIBE").arg(pattern), "psubscribe").await
+    }
+
+    async fn request(&mut self, cmd: Cmd, confirmation: &str) -> Result<()> {
+        self.conn.write_cmd(&cmd).await?;
+        loop {
+            let frame = self.conn.read_value().await?;
+            if is_confirmation(&frame, confirmation) {
+                return Ok(());
+            }
+            if let Some(message) = Message::from_frame(frame)? {
+                self.pending.push_back(message);
+            }
+        }
+    }
+
+    /// Wait for the next published message
+    pub async fn next_message(&mut self) -> Result<Message> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(message);
+        }
+        loop {
+            if let Some(message) = Message::from_frame(self.conn.read_value().await?)? {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Read messages on a background task and yield them as a stream.
+    /// The stream ends after the first error; dropping it closes the
+    /// connection.
+    pub fn into_stream(mut self) -> MessageStream {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let task = tokio::spawn(async move {
+            loop {
+                let next = self.next_message().await;
+                let failed = next.is_err();
+                if tx.send(next).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        MessageStream { rx, task }
+    }
+}
+
+fn is_confirmation(frame: &Value, kind: &str) -> bool {
+    match frame {
+        Value::Array(items) | Value::Push(items) => match items.first() {
+            Some(Value::Data(k)) => k.eq_ignore_ascii_case(kind.as_bytes()),
+            Some(Value::Status(k)) => k.eq_ignore_ascii_case(kind),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Published messages as a [`Stream`]
+pub struct MessageStream {
+    rx: mpsc::Receiver<Result<Message>>,
+    task: JoinHandle<()>,
+}
+
+impl Stream for MessageStream {
+    type Item = Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl Drop for MessageStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ConnectOptions;
+    use crate::testing::serve;
+
+    fn data(s: &str) -> Value {
+        Value::Data(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_message_frames() {
+        let message = Message::from_frame(Value::Array(vec![data("message"), data("news"), data("hi")]))
+            .unwrap()
+            .unwrap();
+        assert_eq!((message.channel.as_str(), message.payload_str().unwrap()), ("news", "hi"));
+
+        let message = Message::from_frame(Value::Push(vec![data("pmessage"), data("n*"), data("news"), data("x")]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.pattern.as_deref(), Some("n*"));
+
+        let confirmation = Value::Array(vec![data("subscribe"), data("news"), Value::Int(1)]);
+        assert!(is_confirmation(&confirmation, "subscribe"));
+        assert_eq!(Message::from_frame(confirmation).unwrap(), None);
+        assert!(Message::from_frame(Value::Array(vec![data("message"), data("news")])).is_err());
+    }
+
+    async fn next(stream: &mut MessageStream) -> Option<Result<Message>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_subscription_stream() {
+        let addr = serve(|| {
+            |args: &[String]| {
+                assert_eq!(args[0], "SUBSCRIBE");
+                format!(
+                    "*3\r\n$9\r\nsubscribe\r\n${ch}\r\n{}\r\n:1\r\n\
+                     *3\r\n$7\r\nmessage\r\n${ch}\r\n{}\r\n$3\r\none\r\n\
+                     >3\r\n+message\r\n+{}\r\n+two\r\n",
+                    args[1],
+                    args[1],
+                    args[1],
+                    ch = args[1].len()
+                )
+                .into_bytes()
+            }
+        })
+        .await;
+        let conn = Connection::connect(&ConnectOptions::new(addr)).await.unwrap();
+        let mut pubsub = conn.into_pubsub();
+        pubsub.subscribe("news").await.unwrap();
+
+        let mut stream = pubsub.into_stream();
+        let first = next(&mut stream).await.unwrap().unwrap();
+        assert_eq!((first.channel.as_str(), first.payload_str().unwrap()), ("news", "one"));
+        assert_eq!(next(&mut stream).await.unwrap().unwrap().payload, b"two");
+    }
+}
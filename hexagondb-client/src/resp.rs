@@ -0,0 +1,224 @@
+//! RESP2/RESP3 encoding and incremental decoding.
+
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// Encode a command as a RESP array of bulk strings
+pub fn encode_command(args: &[Vec<u8>], out: &mut Vec<u8>) {
+    out.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Decode one reply from the start of `buf`. Returns the value and the
+/// number of bytes it used, or `None` if `buf` does not hold a complete
+/// reply yet. Attributes (`|`) are skipped.
+pub fn decode(buf: &[u8]) -> Result<Option<(Value, usize)>> {
+    let mut pos = 0;
+    match parse(buf, &mut pos)? {
+        Some(value) => Ok(Some((value, pos))),
+        None => Ok(None),
+    }
+}
+
+fn protocol(msg: impl Into<String>) -> Error {
+    Error::Protocol(msg.into())
+}
+
+/// Next `\r\n`-terminated line, without the terminator
+fn line<'a>(buf: &'a [u8], pos: &mut usize) -> Result<Option<&'a str>> {
+    let start = *pos;
+    let Some(end) = buf[start..].windows(2).position(|w| w == b"\r\n") else {
+        return Ok(None);
+    };
+    *pos = start + end + 2;
+    std::str::from_utf8(&buf[start..start + end])
+        .map(Some)
+        .map_err(|_| protocol("header line is not UTF-8"))
+}
+
+fn number(s: &str) -> Result<i64> {
+    s.parse().map_err(|_| protocol(format!("invalid length or integer: {:?}", s)))
+}
+
+/// Exactly `len` bytes followed by `\r\n`
+fn blob(buf: &[u8], pos: &mut usize, len: i64) -> Result<Option<Vec<u8>>> {
+    let len = usize::try_from(len).map_err(|_| protocol("negative length"))?;
+    if buf.len() < *pos + len + 2 {
+        return Ok(None);
+    }
+    let data = buf[*pos..*pos + len].to_vec();
+    if &buf[*pos + len..*pos + len + 2] != b"\r\n" {
+        return Err(protocol("bulk string not terminated by CRLF"));
+    }
+    *pos += len + 2;
+    Ok(Some(data))
+}
+
+fn items(buf: &[u8], pos: &mut usize, len: i64) -> Result<Option<Vec<Value>>> {
+    let mut items = Vec::with_capacity(len.clamp(0, 1024) as usize);
+    for _ in 0..len {
+        match parse(buf, pos)? {
+            Some(item) => items.push(item),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(items))
+}
+
+fn parse(buf: &[u8], pos: &mut usize) -> Result<Option<Value>> {
+    let Some(&kind) = buf.get(*pos) else {
+        return Ok(None);
+    };
+    *pos += 1;
+    let Some(header) = line(buf, pos)? else {
+        return Ok(None);
+    };
+    if header.starts_with('?') {
+        return Err(protocol("streamed aggregates are not supported"));
+    }
+
+    let value = match kind {
+        b'+' => Value::Status(header.to_string()),
+        b'-' => Value::Error(header.to_string()),
+        b':' => Value::Int(number(header)?),
+        b'_' => Value::Nil,
+        b'#' => match header {
+            "t" => Value::Boolean(true),
+            "f" => Value::Boolean(false),
+            _ => return Err(protocol(format!("invalid boolean: {:?}", header))),
+        },
+        b',' => Value::Double(match header {
+            "inf" => f64::INFINITY,
+            "-inf" => f64::NEG_INFINITY,
+            "nan" => f64::NAN,
+            _ => header.parse().map_err(|_| protocol(format!("invalid double: {:?}", header)))?,
+        }),
+        b'(' => Value::BigNumber(header.to_string()),
+        b'$' | b'!' | b'=' => {
+            let len = number(header)?;
+            if len < 0 {
+                return Ok(Some(Value::Nil));
+            }
+            let Some(data) = blob(buf, pos, len)? else {
+                return Ok(None);
+            };
+            match kind {
+                b'$' => Value::Data(data),
+                b'!' => Value::Error(String::from_utf8_lossy(&data).into_owned()),
+                _ => {
+                    // "txt:" prefix names the format
+                    let text = String::from_utf8_lossy(&data).into_owned();
+                    match text.split_once(':') {
+                        Some((format, rest)) if format.len() == 3 => Value::Verbatim {
+                            format: format.to_string(),
+                            text: rest.to_string(),
+                        },
+                        _ => return Err(protocol("verbatim string without a format")),
+                    }
+                }
+            }
+        }
+        b'*' | b'~' | b'>' => {
+            let len = number(header)?;
+            if len < 0 {
+                return Ok(Some(Value::Nil));
+            }
+            let Some(items) = items(buf, pos, len)? else {
+                return Ok(None);
+            };
+            match kind {
+                b'*' => Value::Array(items),
+                b'~' => Value::Set(items),
+                _ => Value::Push(items),
+            }
+        }
+        b'%' | b'|' => {
+            let len = number(header)?;
+            let Some(flat) = items(buf, pos, len.max(0) * 2)? else {
+                return Ok(None);
+            };
+            let mut flat = flat.into_iter();
+            let mut pairs = Vec::with_capacity(len.max(0) as usize);
+            while let (Some(k), Some(v)) = (flat.next(), flat.next()) {
+                pairs.push((k, v));
+            }
+            if kind == b'|' {
+                // Attributes describe the reply that follows; skip them
+                return parse(buf, pos);
+            }
+            Value::Map(pairs)
+        }
+        other => return Err(protocol(format!("unknown reply type byte {:?}", other as char))),
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full(input: &[u8]) -> Value {
+        let (value, used) = decode(input).unwrap().expect("complete reply");
+        assert_eq!(used, input.len());
+        value
+    }
+
+    #[test]
+    fn test_encode_command() {
+        let mut out = Vec::new();
+        encode_command(&[b"SET".to_vec(), b"k".to_vec(), b"a\r\nb".to_vec()], &mut out);
+        assert_eq!(out, b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$4\r\na\r\nb\r\n");
+    }
+
+    #[test]
+    fn test_decode_resp2() {
+        assert_eq!(full(b"+OK\r\n"), Value::Status("OK".into()));
+        assert_eq!(full(b"-ERR nope\r\n"), Value::Error("ERR nope".into()));
+        assert_eq!(full(b":-3\r\n"), Value::Int(-3));
+        assert_eq!(full(b"$-1\r\n"), Value::Nil);
+        assert_eq!(full(b"$4\r\na\r\nb\r\n"), Value::Data(b"a\r\nb".to_vec()));
+        assert_eq!(
+            full(b"*2\r\n*1\r\n:1\r\n$0\r\n\r\n"),
+            Value::Array(vec![Value::Array(vec![Value::Int(1)]), Value::Data(Vec::new())])
+        );
+    }
+
+    #[test]
+    fn test_decode_resp3() {
+        assert_eq!(full(b"_\r\n"), Value::Nil);
+        assert_eq!(full(b"#t\r\n"), Value::Boolean(true));
+        assert_eq!(full(b",1.5\r\n"), Value::Double(1.5));
+        assert_eq!(full(b"(12345678901234567890\r\n"), Value::BigNumber("12345678901234567890".into()));
+        assert_eq!(
+            full(b"=8\r\ntxt:hey!\r\n"),
+            Value::Verbatim { format: "txt".into(), text: "hey!".into() }
+        );
+        assert_eq!(
+            full(b"%1\r\n+a\r\n:1\r\n"),
+            Value::Map(vec![(Value::Status("a".into()), Value::Int(1))])
+        );
+        assert_eq!(full(b"~1\r\n:1\r\n"), Value::Set(vec![Value::Int(1)]));
+        assert_eq!(full(b">2\r\n+message\r\n+x\r\n"), Value::Push(vec![Value::Status("message".into()), Value::Status("x".into())]));
+        assert_eq!(full(b"|1\r\n+ttl\r\n:3\r\n:7\r\n"), Value::Int(7));
+        assert_eq!(full(b"!5\r\nERR x\r\n"), Value::Error("ERR x".into()));
+    }
+
+    #[test]
+    fn test_decode_partial_and_invalid() {
+        let input = b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
+        for cut in 0..input.len() {
+            assert!(decode(&input[..cut]).unwrap().is_none(), "cut at {}", cut);
+        }
+        // Two replies back to back: only the first is consumed
+        let (_, used) = decode(b":1\r\n:2\r\n").unwrap().unwrap();
+        assert_eq!(used, 4);
+
+        assert!(decode(b"@x\r\n").is_err());
+        assert!(decode(b":abc\r\n").is_err());
+        assert!(decode(b"$3\r\nfooXX").is_err());
+    }
+}
@@ -0,0 +1,46 @@
+//! Scripted server for tests.
+
+use crate::resp;
+use crate::value::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Listen on a free port and answer every command with `reply(args)`,
+/// one `reply` per connection. Returns the address to connect to.
+pub async fn serve<F, R>(make_reply: F) -> String
+where
+    F: Fn() -> R + Send + 'static,
+    R: FnMut(&[String]) -> Vec<u8> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut reply = make_reply();
+            tokio::spawn(async move {
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                loop {
+                    while let Some((Value::Array(items), used)) = resp::decode(&buf).unwrap() {
+                        buf.drain(..used);
+                        let args: Vec<String> = items
+                            .into_iter()
+                            .map(|v| match v {
+                                Value::Data(d) => String::from_utf8(d).unwrap(),
+                                other => panic!("unexpected argument {:?}", other),
+                            })
+                            .collect();
+                        if socket.write_all(&reply(&args)).await.is_err() {
+                            return;
+                        }
+                    }
+                    match socket.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    }
+                }
+            });
+        }
+    });
+    addr
+}
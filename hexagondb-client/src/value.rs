@@ -0,0 +1,253 @@
+//! Reply values and conversions into Rust types.
+
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A decoded RESP2 or RESP3 reply
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Null bulk string, null array or RESP3 null
+    Nil,
+    Int(i64),
+    /// Bulk string
+    Data(Vec<u8>),
+    /// Simple string (`+OK`)
+    Status(String),
+    /// An error inside an aggregate, e.g. one command of an EXEC or a
+    /// pipeline. Top-level errors are returned as [`Error::Server`].
+    Error(String),
+    Array(Vec<Value>),
+    /// RESP3 map, in server order
+    Map(Vec<(Value, Value)>),
+    /// RESP3 set
+    Set(Vec<Value>),
+    /// RESP3 double
+    Double(f64),
+    /// RESP3 boolean
+    Boolean(bool),
+    /// RESP3 big number, as its decimal digits
+    BigNumber(String),
+    /// RESP3 verbatim string
+    Verbatim { format: String, text: String },
+    /// RESP3 out-of-band push (pub/sub messages, invalidations)
+    Push(Vec<Value>),
+}
+
+impl Value {
+    /// Turn an error value into `Err`, leaving other values alone
+    pub fn into_result(self) -> Result<Value> {
+        match self {
+            Value::Error(msg) => Err(Error::Server(msg)),
+            value => Ok(value),
+        }
+    }
+
+    fn type_error<T>(self, wanted: &str) -> Result<T> {
+        match self {
+            Value::Error(msg) => Err(Error::Server(msg)),
+            other => Err(Error::Type(format!("cannot convert {:?} to {}", other, wanted))),
+        }
+    }
+}
+
+/// Conversion from a reply into a Rust type
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+/// Accepts any reply that is not an error
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self> {
+        value.into_result().map(|_| ())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Data(bytes) => String::from_utf8(bytes)
+                .map_err(|e| Error::Type(format!("reply is not UTF-8: {}", e))),
+            Value::Status(s) | Value::BigNumber(s) => Ok(s),
+            Value::Verbatim { text, .. } => Ok(text),
+            Value::Int(n) => Ok(n.to_string()),
+            Value::Double(d) => Ok(d.to_string()),
+            other => other.type_error("String"),
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Data(bytes) => Ok(bytes),
+            Value::Status(s) => Ok(s.into_bytes()),
+            Value::Verbatim { text, .. } => Ok(text.into_bytes()),
+            other => other.type_error("bytes"),
+        }
+    }
+}
+
+macro_rules! from_value_int {
+    ($($t:ty),*) => {$(
+        impl FromValue for $t {
+            fn from_value(value: Value) -> Result<Self> {
+                let parsed = match &value {
+                    Value::Int(n) => <$t>::try_from(*n).ok(),
+                    Value::Data(bytes) => std::str::from_utf8(bytes).ok().and_then(|s| s.parse().ok()),
+                    Value::Status(s) | Value::BigNumber(s) => s.parse().ok(),
+                    Value::Boolean(b) => Some(*b as $t),
+                    _ => None,
+                };
+                match parsed {
+                    Some(n) => Ok(n),
+                    None => value.type_error(stringify!($t)),
+                }
+            }
+        }
+    )*};
+}
+
+from_value_int!(i64, i32, u64, u32, usize);
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self> {
+        let parsed = match &value {
+            Value::Double(d) => Some(*d),
+            Value::Int(n) => Some(*n as f64),
+            Value::Data(bytes) => std::str::from_utf8(bytes).ok().and_then(parse_float),
+            Value::Status(s) => parse_float(s),
+            _ => None,
+        };
+        match parsed {
+            Some(d) => Ok(d),
+            None => value.type_error("f64"),
+        }
+    }
+}
+
+/// Parse a float as the server writes it, including `inf` and `-inf`
+fn parse_float(s: &str) -> Option<f64> {
+    match s {
+        "inf" | "+inf" => Some(f64::INFINITY),
+        "-inf" => Some(f64::NEG_INFINITY),
+        _ => s.parse().ok(),
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            Value::Int(n) => Ok(n != 0),
+            Value::Status(ref s) if s == "OK" => Ok(true),
+            Value::Nil => Ok(false),
+            other => other.type_error("bool"),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Nil => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Array(items) | Value::Set(items) | Value::Push(items) => {
+                items.into_iter().map(T::from_value).collect()
+            }
+            Value::Nil => Ok(Vec::new()),
+            other => other.type_error("Vec"),
+        }
+    }
+}
+
+impl<A: FromValue, B: FromValue> FromValue for (A, B) {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Array(items) if items.len() == 2 => {
+                let mut items = items.into_iter();
+                let a = A::from_value(items.next().expect("two items"))?;
+                let b = B::from_value(items.next().expect("two items"))?;
+                Ok((a, b))
+            }
+            other => other.type_error("pair"),
+        }
+    }
+}
+
+/// From a RESP3 map, or a flat RESP2 array of alternating keys and values
+impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V> {
+    fn from_value(value: Value) -> Result<Self> {
+        match value {
+            Value::Map(pairs) => pairs
+                .into_iter()
+                .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+                .collect(),
+            Value::Array(items) if items.len() % 2 == 0 => {
+                let mut map = HashMap::with_capacity(items.len() / 2);
+                let mut items = items.into_iter();
+                while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                    map.insert(K::from_value(k)?, V::from_value(v)?);
+                }
+                Ok(map)
+            }
+            Value::Nil => Ok(HashMap::new()),
+            other => other.type_error("HashMap"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(s: &str) -> Value {
+        Value::Data(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_scalar_conversions() {
+        assert_eq!(String::from_value(data("hi")).unwrap(), "hi");
+        assert_eq!(i64::from_value(data("-42")).unwrap(), -42);
+        assert_eq!(u64::from_value(Value::Int(7)).unwrap(), 7);
+        assert!(u64::from_value(Value::Int(-1)).is_err());
+        assert_eq!(f64::from_value(data("1.5")).unwrap(), 1.5);
+        assert_eq!(f64::from_value(data("-inf")).unwrap(), f64::NEG_INFINITY);
+        assert!(bool::from_value(Value::Int(1)).unwrap());
+        assert_eq!(Option::<String>::from_value(Value::Nil).unwrap(), None);
+        assert!(matches!(String::from_value(Value::Error("ERR x".into())), Err(Error::Server(_))));
+        assert!(matches!(i64::from_value(data("x")), Err(Error::Type(_))));
+    }
+
+    #[test]
+    fn test_aggregate_conversions() {
+        let flat = Value::Array(vec![data("a"), data("1"), data("b"), data("2")]);
+        let map: HashMap<String, i64> = FromValue::from_value(flat).unwrap();
+        assert_eq!(map["b"], 2);
+
+        let resp3 = Value::Map(vec![(data("a"), Value::Int(1))]);
+        let map: HashMap<String, i64> = FromValue::from_value(resp3).unwrap();
+        assert_eq!(map["a"], 1);
+
+        let list: Vec<String> = FromValue::from_value(Value::Array(vec![data("x"), data("y")])).unwrap();
+        assert_eq!(list, vec!["x", "y"]);
+        let bytes: Vec<u8> = FromValue::from_value(data("raw")).unwrap();
+        assert_eq!(bytes, b"raw");
+
+        let pair: (String, f64) = FromValue::from_value(Value::Array(vec![data("m"), data("2.5")])).unwrap();
+        assert_eq!(pair, ("m".to_string(), 2.5));
+    }
+}
@@ -0,0 +1,95 @@
+//! Sampled key access log.
+//!
+//! Records 1-in-N keyed commands into an internal stream (key, command,
+//! client) so the working set can be analysed offline without the cost of
+//! MONITOR-style full tracing.
+
+use crate::config::AccessLogConfig;
+use crate::db::{StreamOps, StreamTrim, DB};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Sampled access logger backed by a stream in the keyspace
+pub struct AccessLog {
+    /// Log one out of every `sample_rate` commands
+    sample_rate: u64,
+    /// Stream that receives the samples
+    stream_key: String,
+    /// Maximum stream length (oldest samples are trimmed)
+    max_len: usize,
+    /// Commands seen so far
+    counter: AtomicU64,
+}
+
+impl AccessLog {
+    /// Create a new access log
+    pub fn new(sample_rate: u64, stream_key: String, max_len: usize) -> Self {
+        AccessLog {
+            sample_rate: sample_rate.max(1),
+            stream_key,
+            max_len,
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    /// Build an access log from config, or None when disabled
+    pub fn from_config(config: &AccessLogConfig) -> Option<Self> {
+        if !config.enabled {
+            return None;
+        }
+        Some(Self::new(
+            config.sample_rate,
+            config.stream_key.clone(),
+            config.max_len,
+        ))
+    }
+
+    /// Stream key that receives the samples
+    pub fn stream_key(&self) -> &str {
+        &self.stream_key
+    }
+
+    /// Decide whether a command that succeeded on `key` should be logged
+    pub fn should_sample(&self, key: &str) -> bool {
+        if key == self.stream_key {
+            return false;
+        }
+        self.counter
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.sample_rate)
+    }
+
+    /// Append a sample to the access log stream
+    pub fn record(&self, db: &mut DB, command: &str, key: &str, client: Option<&str>) {
+        let fields = vec![
+            ("key".to_string(), key.to_string()),
+            ("command".to_string(), command.to_string()),
+            ("client".to_string(), client.unwrap_or("unknown").to_string()),
+        ];
+        if db.xadd(self.stream_key.clone(), None, fields).is_ok() {
+            let _ = db.xtrim(self.stream_key.clone(), StreamTrim::MaxLen(self.max_len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_rate() {
+        let log = AccessLog::new(3, "__accesslog__".to_string(), 100);
+        let sampled = (0..9).filter(|_| log.should_sample("key")).count();
+        assert_eq!(sampled, 3);
+        assert!(!log.should_sample("__accesslog__"));
+    }
+
+    #[test]
+    fn test_record_trims_stream() {
+        let mut db = DB::new();
+        let log = AccessLog::new(1, "__accesslog__".to_string(), 2);
+        for key in ["a", "b", "c"] {
+            log.record(&mut db, "GET", key, Some("127.0.0.1:5000"));
+        }
+        assert_eq!(db.xlen("__accesslog__"), Ok(2));
+    }
+}
@@ -0,0 +1,328 @@
+//! Keyspace analytics jobs.
+//!
+//! Backs the ANALYZE admin command: walks every key matching a pattern in a
+//! background task and reports key counts, approximate memory and TTL
+//! distribution grouped by type and by first key segment (`user:`,
+//! `session:`, ...), along with the biggest keys. Keys are read in batches so
+//! the read lock is released between batches. Results stay available through
+//! ANALYZE RESULT. DBSTATS FULL runs the same walk over the whole keyspace.
+
+use crate::db::{introspect, GenericOps, DB};
+use parking_lot::{Mutex, RwLock as SyncRwLock};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Finished jobs kept around for STATUS/RESULT queries
+const MAX_FINISHED_JOBS: usize = 32;
+
+/// Largest keys kept in a report
+pub const BIGGEST_KEYS: usize = 10;
+
+/// Group name for keys without a `:` separator
+pub const NO_PREFIX: &str = "(none)";
+
+/// TTL buckets, as (label, upper bound); the last bucket is open-ended
+const TTL_BUCKETS: &[(&str, Option<Duration>)] = &[
+    ("ttl<1m", Some(Duration::from_secs(60))),
+    ("ttl<1h", Some(Duration::from_secs(3600))),
+    ("ttl<1d", Some(Duration::from_secs(86400))),
+    ("ttl>=1d", None),
+];
+
+/// Statistics of one group of keys
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    pub keys: usize,
+    /// Approximate bytes: key length plus serialized value length
+    pub memory: usize,
+    pub no_ttl: usize,
+    /// Keys per TTL bucket, in `TTL_BUCKETS` order
+    pub ttl: [usize; 4],
+}
+
+impl GroupStats {
+    fn add(&mut self, memory: usize, ttl: Option<Duration>) {
+        self.keys += 1;
+        self.memory += memory;
+        match ttl {
+            None => self.no_ttl += 1,
+            Some(ttl) => {
+                let bucket = TTL_BUCKETS
+                    .iter()
+                    .position(|(_, bound)| bound.is_none_or(|b| ttl < b))
+                    .unwrap_or(TTL_BUCKETS.len() - 1);
+                self.ttl[bucket] += 1;
+            }
+        }
+    }
+
+    /// Render as field/value pairs
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("keys".to_string(), self.keys.to_string()),
+            ("memory".to_string(), self.memory.to_string()),
+            ("no_ttl".to_string(), self.no_ttl.to_string()),
+        ];
+        fields.extend(
+            TTL_BUCKETS
+                .iter()
+                .zip(self.ttl)
+                .map(|((label, _), count)| (label.to_string(), count.to_string())),
+        );
+        fields
+    }
+}
+
+/// A key among the biggest in a report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigKey {
+    pub key: String,
+    pub type_name: String,
+    /// Approximate bytes, as in `GroupStats::memory`
+    pub memory: usize,
+}
+
+/// Analysis result, grouped by type and by first key segment
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeReport {
+    pub total: GroupStats,
+    pub by_type: BTreeMap<String, GroupStats>,
+    pub by_prefix: BTreeMap<String, GroupStats>,
+    /// The `BIGGEST_KEYS` largest keys, biggest first
+    pub biggest: Vec<BigKey>,
+}
+
+impl AnalyzeReport {
+    fn add(&mut self, key: &str, type_name: String, memory: usize, ttl: Option<Duration>) {
+        self.total.add(memory, ttl);
+        self.by_type.entry(type_name.clone()).or_default().add(memory, ttl);
+        self.by_prefix
+            .entry(key_prefix(key).to_string())
+            .or_default()
+            .add(memory, ttl);
+
+        if self.biggest.len() < BIGGEST_KEYS || self.biggest.last().is_some_and(|k| memory > k.memory) {
+            let at = self.biggest.partition_point(|k| k.memory >= memory);
+            self.biggest.insert(
+                at,
+                BigKey {
+                    key: key.to_string(),
+                    type_name,
+                    memory,
+                },
+            );
+            self.biggest.truncate(BIGGEST_KEYS);
+        }
+    }
+}
+
+/// First key segment including the separator (`user:42` -> `user:`)
+pub fn key_prefix(key: &str) -> &str {
+    match key.find(':') {
+        Some(pos) => &key[..=pos],
+        None => NO_PREFIX,
+    }
+}
+
+/// Progress and result of a single analysis
+#[derive(Debug)]
+pub struct AnalyzeJob {
+    pub id: u64,
+    pub pattern: String,
+    /// Keys matching the pattern when the job started
+    pub matched: AtomicUsize,
+    /// Keys visited so far
+    pub processed: AtomicUsize,
+    /// Whether the job has finished
+    pub done: AtomicBool,
+    report: Mutex<AnalyzeReport>,
+}
+
+impl AnalyzeJob {
+    /// Render job progress as field/value pairs
+    pub fn status(&self) -> Vec<(String, String)> {
+        let state = if self.done.load(Ordering::Acquire) {
+            "done"
+        } else {
+            "running"
+        };
+        vec![
+            ("id".to_string(), self.id.to_string()),
+            ("pattern".to_string(), self.pattern.clone()),
+            ("state".to_string(), state.to_string()),
+            ("matched".to_string(), self.matched.load(Ordering::Relaxed).to_string()),
+            ("processed".to_string(), self.processed.load(Ordering::Relaxed).to_string()),
+        ]
+    }
+
+    /// The report, once the job has finished
+    pub fn report(&self) -> Option<AnalyzeReport> {
+        self.done
+            .load(Ordering::Acquire)
+            .then(|| self.report.lock().clone())
+    }
+}
+
+/// Registry of analysis jobs shared by all connections
+pub struct AnalyzeJobs {
+    next_id: AtomicU64,
+    jobs: SyncRwLock<BTreeMap<u64, Arc<AnalyzeJob>>>,
+}
+
+impl AnalyzeJobs {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        AnalyzeJobs {
+            next_id: AtomicU64::new(1),
+            jobs: SyncRwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Register a new job and spawn it in the background
+    pub fn start(&self, pattern: String, batch_size: usize, db: Arc<RwLock<DB>>) -> Arc<AnalyzeJob> {
+        let job = Arc::new(AnalyzeJob {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            pattern,
+            matched: AtomicUsize::new(0),
+            processed: AtomicUsize::new(0),
+            done: AtomicBool::new(false),
+            report: Mutex::new(AnalyzeReport::default()),
+        });
+
+        {
+            let mut jobs = self.jobs.write();
+            jobs.insert(job.id, Arc::clone(&job));
+            // Forget the oldest finished jobs
+            let finished: Vec<u64> = jobs
+                .values()
+                .filter(|j| j.done.load(Ordering::Acquire))
+                .map(|j| j.id)
+                .collect();
+            for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS)) {
+                jobs.remove(id);
+            }
+        }
+
+        tokio::spawn(run(Arc::clone(&job), batch_size.max(1), db));
+        job
+    }
+
+    /// Look up a job by id
+    pub fn get(&self, id: u64) -> Option<Arc<AnalyzeJob>> {
+        self.jobs.read().get(&id).cloned()
+    }
+
+    /// All known jobs, oldest first
+    pub fn list(&self) -> Vec<Arc<AnalyzeJob>> {
+        self.jobs.read().values().cloned().collect()
+    }
+}
+
+impl Default for AnalyzeJobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collect statistics for all matching keys, one batch per read lock
+async fn run(job: Arc<AnalyzeJob>, batch_size: usize, db: Arc<RwLock<DB>>) {
+    let keys = db.read().await.keys(&job.pattern);
+    job.matched.store(keys.len(), Ordering::Relaxed);
+    let report = collect(&db, &keys, batch_size, |n| {
+        job.processed.fetch_add(n, Ordering::Relaxed);
+    })
+    .await;
+
+    *job.report.lock() = report;
+    job.done.store(true, Ordering::Release);
+    info!(
+        "ANALYZE job {} finished: {} keys matching {}",
+        job.id,
+        job.matched.load(Ordering::Relaxed),
+        job.pattern
+    );
+}
+
+/// Statistics for every key, without registering a job (DBSTATS FULL)
+pub async fn scan_all(db: &Arc<RwLock<DB>>, batch_size: usize) -> AnalyzeReport {
+    let keys = db.read().await.keys("*");
+    collect(db, &keys, batch_size.max(1), |_| {}).await
+}
+
+/// Walk `keys` one batch per read lock, calling `progress` after each batch
+async fn collect(
+    db: &Arc<RwLock<DB>>,
+    keys: &[String],
+    batch_size: usize,
+    progress: impl Fn(usize),
+) -> AnalyzeReport {
+    let mut report = AnalyzeReport::default();
+    for batch in keys.chunks(batch_size) {
+        {
+            let db = db.read().await;
+            let now = Instant::now();
+            for key in batch {
+                let (entry, type_name) = match (db.items.get(key), db.type_of(key)) {
+                    (Some(entry), Some(type_name)) => (entry, type_name),
+                    // Deleted since the job started
+                    _ => continue,
+                };
+                let ttl = match entry.expires_at {
+                    Some(at) if at <= now => continue,
+                    Some(at) => Some(at - now),
+                    None => None,
+                };
+                let memory = key.len() + introspect::serialized_length(&entry.value);
+                report.add(key, type_name, memory, ttl);
+            }
+        }
+        progress(batch.len());
+
+        // Let other clients get at the database between batches
+        tokio::task::yield_now().await;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ListOps, StringOps};
+
+    #[tokio::test]
+    async fn test_analyze() {
+        let db = Arc::new(RwLock::new(DB::new()));
+        {
+            let mut guard = db.write().await;
+            for i in 0..5 {
+                guard.set(format!("user:{}", i), "v".to_string());
+            }
+            guard.expire("user:0", 30);
+            guard.expire("user:1", 7200);
+            guard.rpush("session:a".to_string(), vec!["x".to_string()]).unwrap();
+            guard.set("plain".to_string(), "v".to_string());
+        }
+
+        let jobs = AnalyzeJobs::new();
+        let job = jobs.start("*".to_string(), 2, Arc::clone(&db));
+        while !job.done.load(Ordering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+
+        let report = job.report().unwrap();
+        assert_eq!(report.total.keys, 7);
+        assert_eq!(report.by_type["string"].keys, 6);
+        assert_eq!(report.by_type["list"].keys, 1);
+        let users = &report.by_prefix["user:"];
+        assert_eq!((users.keys, users.no_ttl, users.ttl), (5, 3, [1, 0, 1, 0]));
+        assert_eq!(report.by_prefix[NO_PREFIX].keys, 1);
+        assert!(report.by_prefix["session:"].memory > "session:a".len());
+        assert_eq!(report.biggest.len(), 7);
+        assert!(report.biggest.windows(2).all(|w| w[0].memory >= w[1].memory));
+        assert!(jobs.get(job.id).is_some());
+    }
+}
@@ -0,0 +1,485 @@
+//! Load generator behind the `hexagondb-benchmark` binary.
+//!
+//! Opens `-c` connections, sends `-n` requests in total, `-P` at a time per
+//! connection, and reports throughput and latency percentiles per workload.
+//! Each test in `-t` runs on its own; `--mix` instead blends several
+//! commands by weight into a single run (`--mix get=80,set=20`). With
+//! `-r N`, keys are drawn at random from `N` names per command type, so the
+//! same run can measure a hot key or a large keyspace.
+
+use crate::network::resp::{RespHandler, RespValue};
+use clap::{ArgAction, Parser};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// HexagonDB benchmark - measure throughput and latency of a running server
+#[derive(Parser, Debug, Clone)]
+#[command(name = "hexagondb-benchmark")]
+#[command(author = "HexagonDB Contributors")]
+#[command(version = "0.1.0")]
+#[command(disable_help_flag = true)]
+pub struct BenchArgs {
+    /// Print help (`-h` is the server hostname, as in redis-benchmark)
+    #[arg(long, action = ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// Server hostname
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Server port
+    #[arg(short, long, default_value_t = 6379)]
+    pub port: u16,
+
+    /// Password for authentication
+    #[arg(short = 'a', long)]
+    pub password: Option<String>,
+
+    /// Number of parallel connections
+    #[arg(short = 'c', long, default_value_t = 50)]
+    pub clients: usize,
+
+    /// Total number of requests per test
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    pub requests: u64,
+
+    /// Requests sent at once per connection
+    #[arg(short = 'P', long, default_value_t = 1)]
+    pub pipeline: usize,
+
+    /// Value size in bytes for SET, LPUSH and ZADD members
+    #[arg(short = 'd', long, default_value_t = 3)]
+    pub data_size: usize,
+
+    /// Use random keys out of this many per command type (0 uses a single key)
+    #[arg(short = 'r', long, default_value_t = 0)]
+    pub keyspace: u64,
+
+    /// Comma-separated tests to run one after another
+    #[arg(short = 't', long, default_value = "set,get,incr,lpush,zadd")]
+    pub tests: String,
+
+    /// Weighted command mix run as a single test, e.g. `get=80,set=20`
+    #[arg(long)]
+    pub mix: Option<String>,
+
+    /// Print one line per test
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+}
+
+/// A benchmarked command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Set,
+    Get,
+    Incr,
+    Lpush,
+    Zadd,
+}
+
+impl Op {
+    /// Parse a test name (case-insensitive)
+    pub fn parse(name: &str) -> Option<Op> {
+        match name.trim().to_lowercase().as_str() {
+            "set" => Some(Op::Set),
+            "get" => Some(Op::Get),
+            "incr" => Some(Op::Incr),
+            "lpush" => Some(Op::Lpush),
+            "zadd" => Some(Op::Zadd),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Op::Set => "SET",
+            Op::Get => "GET",
+            Op::Incr => "INCR",
+            Op::Lpush => "LPUSH",
+            Op::Zadd => "ZADD",
+        }
+    }
+
+    /// Arguments of one request on key number `n`
+    pub fn command(self, n: u64, value: &str, rng: &mut impl Rng) -> Vec<String> {
+        let owned = |parts: &[&str]| parts.iter().map(|s| s.to_string()).collect();
+        match self {
+            Op::Set => owned(&["SET", &format!("key:{:012}", n), value]),
+            Op::Get => owned(&["GET", &format!("key:{:012}", n)]),
+            Op::Incr => owned(&["INCR", &format!("counter:{:012}", n)]),
+            Op::Lpush => owned(&["LPUSH", &format!("list:{:012}", n), value]),
+            Op::Zadd => {
+                let score = rng.gen_range(0..1_000_000).to_string();
+                let member = format!("element:{}", rng.gen_range(0..1_000_000));
+                owned(&["ZADD", &format!("zset:{:012}", n), &score, &member])
+            }
+        }
+    }
+}
+
+/// Commands of one test with their relative weights
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workload {
+    pub name: String,
+    pub ops: Vec<(Op, u32)>,
+}
+
+impl Workload {
+    /// Pick a command according to the weights
+    fn pick(&self, rng: &mut impl Rng) -> Op {
+        let total: u32 = self.ops.iter().map(|(_, w)| w).sum();
+        let mut roll = rng.gen_range(0..total);
+        for &(op, weight) in &self.ops {
+            if roll < weight {
+                return op;
+            }
+            roll -= weight;
+        }
+        self.ops[0].0
+    }
+}
+
+/// Workloads selected by `-t` or `--mix`
+pub fn workloads(args: &BenchArgs) -> Result<Vec<Workload>, String> {
+    if let Some(mix) = &args.mix {
+        return parse_mix(mix).map(|workload| vec![workload]);
+    }
+    args.tests
+        .split(',')
+        .filter(|t| !t.trim().is_empty())
+        .map(|t| {
+            let op = Op::parse(t).ok_or_else(|| format!("unknown test '{}'", t.trim()))?;
+            Ok(Workload {
+                name: op.name().to_string(),
+                ops: vec![(op, 1)],
+            })
+        })
+        .collect()
+}
+
+/// Parse `op=weight,op=weight` into a single workload
+pub fn parse_mix(mix: &str) -> Result<Workload, String> {
+    let mut ops = Vec::new();
+    for part in mix.split(',').filter(|p| !p.trim().is_empty()) {
+        let (name, weight) = part
+            .split_once('=')
+            .ok_or_else(|| format!("expected op=weight in '{}'", part))?;
+        let op = Op::parse(name).ok_or_else(|| format!("unknown test '{}'", name.trim()))?;
+        let weight = weight
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("invalid weight in '{}'", part))?;
+        if weight > 0 {
+            ops.push((op, weight));
+        }
+    }
+    if ops.is_empty() {
+        return Err("empty command mix".to_string());
+    }
+    let name = ops
+        .iter()
+        .map(|(op, weight)| format!("{}={}", op.name(), weight))
+        .collect::<Vec<_>>()
+        .join(",");
+    Ok(Workload {
+        name: format!("MIX {}", name),
+        ops,
+    })
+}
+
+/// Latency distribution of a finished test
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyStats {
+    /// Latencies in microseconds, sorted
+    samples: Vec<u64>,
+}
+
+impl LatencyStats {
+    pub fn new(mut samples: Vec<u64>) -> Self {
+        samples.sort_unstable();
+        LatencyStats { samples }
+    }
+
+    /// Latency at or below which `p` percent of requests completed
+    pub fn percentile(&self, p: f64) -> u64 {
+        if self.samples.is_empty() {
+            return 0;
+        }
+        let rank = ((p / 100.0) * self.samples.len() as f64).ceil() as usize;
+        self.samples[rank.clamp(1, self.samples.len()) - 1]
+    }
+
+    pub fn min(&self) -> u64 {
+        self.samples.first().copied().unwrap_or(0)
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<u64>() as f64 / self.samples.len() as f64
+    }
+
+    pub fn max(&self) -> u64 {
+        self.samples.last().copied().unwrap_or(0)
+    }
+}
+
+/// Outcome of one test
+#[derive(Debug)]
+pub struct Report {
+    pub name: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub elapsed: Duration,
+    pub latency: LatencyStats,
+}
+
+impl Report {
+    pub fn requests_per_sec(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Run every selected test and print its report
+pub async fn run(args: BenchArgs) -> Result<(), String> {
+    if args.clients == 0 || args.pipeline == 0 {
+        return Err("clients and pipeline must be at least 1".to_string());
+    }
+    for workload in workloads(&args)? {
+        let report = run_workload(&args, &workload).await?;
+        print_report(&args, &report);
+    }
+    Ok(())
+}
+
+/// Send `args.requests` requests of a workload over `args.clients` connections
+pub async fn run_workload(args: &BenchArgs, workload: &Workload) -> Result<Report, String> {
+    let value = "x".repeat(args.data_size);
+    let remaining = Arc::new(AtomicU64::new(args.requests));
+    let mut connections = Vec::with_capacity(args.clients);
+    for _ in 0..args.clients {
+        connections.push(Connection::open(args).await?);
+    }
+
+    let start = Instant::now();
+    let tasks: Vec<_> = connections
+        .into_iter()
+        .map(|connection| {
+            let remaining = Arc::clone(&remaining);
+            let workload = workload.clone();
+            let value = value.clone();
+            let (pipeline, keyspace) = (args.pipeline, args.keyspace);
+            tokio::spawn(async move {
+                connection
+                    .drive(&workload, &remaining, pipeline, keyspace, &value)
+                    .await
+            })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(args.requests as usize);
+    let mut errors = 0;
+    for task in tasks {
+        let (samples, task_errors) = task.await.map_err(|e| e.to_string())??;
+        latencies.extend(samples);
+        errors += task_errors;
+    }
+
+    Ok(Report {
+        name: workload.name.clone(),
+        requests: latencies.len() as u64,
+        errors,
+        elapsed: start.elapsed(),
+        latency: LatencyStats::new(latencies),
+    })
+}
+
+fn print_report(args: &BenchArgs, report: &Report) {
+    let ms = |us: u64| us as f64 / 1000.0;
+    let latency = &report.latency;
+    if args.quiet {
+        println!(
+            "{}: {:.2} requests per second, p50={:.3} msec",
+            report.name,
+            report.requests_per_sec(),
+            ms(latency.percentile(50.0))
+        );
+        return;
+    }
+    println!("====== {} ======", report.name);
+    println!(
+        "  {} requests completed in {:.2} seconds",
+        report.requests,
+        report.elapsed.as_secs_f64()
+    );
+    println!(
+        "  {} parallel clients, {} bytes payload, pipeline {}, keyspace {}",
+        args.clients, args.data_size, args.pipeline, args.keyspace
+    );
+    if report.errors > 0 {
+        println!("  {} error replies", report.errors);
+    }
+    println!("  {:.2} requests per second", report.requests_per_sec());
+    println!(
+        "  latency (msec): avg {:.3}  p50 {:.3}  p95 {:.3}  p99 {:.3}  p99.9 {:.3}  max {:.3}",
+        latency.mean() / 1000.0,
+        ms(latency.percentile(50.0)),
+        ms(latency.percentile(95.0)),
+        ms(latency.percentile(99.0)),
+        ms(latency.percentile(99.9)),
+        ms(latency.max())
+    );
+    println!();
+}
+
+/// One benchmark connection
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    async fn open(args: &BenchArgs) -> Result<Self, String> {
+        let stream = TcpStream::connect((args.host.as_str(), args.port))
+            .await
+            .map_err(|e| format!("Could not connect to {}:{}: {}", args.host, args.port, e))?;
+        stream.set_nodelay(true).map_err(|e| e.to_string())?;
+        let mut connection = Connection {
+            stream,
+            buffer: Vec::new(),
+        };
+        if let Some(password) = &args.password {
+            connection.send(&[vec!["AUTH".to_string(), password.clone()]]).await?;
+            if let RespValue::Error(e) = connection.read_reply().await? {
+                return Err(format!("Authentication failed: {}", e));
+            }
+        }
+        Ok(connection)
+    }
+
+    /// Send pipelines until no requests remain. Returns the latency of
+    /// every request in microseconds and the number of error replies.
+    async fn drive(
+        mut self,
+        workload: &Workload,
+        remaining: &AtomicU64,
+        pipeline: usize,
+        keyspace: u64,
+        value: &str,
+    ) -> Result<(Vec<u64>, u64), String> {
+        let mut rng = StdRng::from_entropy();
+        let mut latencies = Vec::new();
+        let mut errors = 0;
+        loop {
+            // Claim up to `pipeline` of the remaining requests
+            let claimed = remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    (left > 0).then(|| left.saturating_sub(pipeline as u64))
+                })
+                .map(|left| left.min(pipeline as u64) as usize)
+                .unwrap_or(0);
+            if claimed == 0 {
+                return Ok((latencies, errors));
+            }
+
+            let commands: Vec<Vec<String>> = (0..claimed)
+                .map(|_| {
+                    let n = if keyspace > 0 { rng.gen_range(0..keyspace) } else { 0 };
+                    workload.pick(&mut rng).command(n, value, &mut rng)
+                })
+                .collect();
+            let sent = Instant::now();
+            self.send(&commands).await?;
+            for _ in 0..claimed {
+                if let RespValue::Error(_) = self.read_reply().await? {
+                    errors += 1;
+                }
+                latencies.push(sent.elapsed().as_micros() as u64);
+            }
+        }
+    }
+
+    async fn send(&mut self, commands: &[Vec<String>]) -> Result<(), String> {
+        let mut payload = String::new();
+        for cmd in commands {
+            let parts = cmd.iter().map(|s| RespValue::BulkString(Some(s.clone()))).collect();
+            payload.push_str(&RespValue::Array(Some(parts)).serialize());
+        }
+        self.stream
+            .write_all(payload.as_bytes())
+            .await
+            .map_err(|e| format!("write error: {}", e))
+    }
+
+    async fn read_reply(&mut self) -> Result<RespValue, String> {
+        let mut chunk = [0u8; 16384];
+        loop {
+            if let Some((value, used)) = RespHandler::parse_request(&self.buffer)? {
+                self.buffer.drain(..used);
+                return Ok(value);
+            }
+            let n = self
+                .stream
+                .read(&mut chunk)
+                .await
+                .map_err(|e| format!("read error: {}", e))?;
+            if n == 0 {
+                return Err("server closed the connection".to_string());
+            }
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workloads() {
+        let args = BenchArgs::parse_from(["hexagondb-benchmark", "-t", "set, GET"]);
+        let names: Vec<_> = workloads(&args).unwrap().into_iter().map(|w| w.name).collect();
+        assert_eq!(names, vec!["SET", "GET"]);
+        let args = BenchArgs::parse_from(["hexagondb-benchmark", "-t", "set,flushall"]);
+        assert!(workloads(&args).is_err());
+
+        let mix = parse_mix("get=80,set=20,incr=0").unwrap();
+        assert_eq!(mix.ops, vec![(Op::Get, 80), (Op::Set, 20)]);
+        assert_eq!(mix.name, "MIX GET=80,SET=20");
+        assert!(parse_mix("get").is_err());
+        assert!(parse_mix("get=0").is_err());
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let picks = (0..1000).filter(|_| mix.pick(&mut rng) == Op::Get).count();
+        assert!((700..900).contains(&picks));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(Op::Set.command(42, "xx", &mut rng), vec!["SET", "key:000000000042", "xx"]);
+        assert_eq!(Op::Incr.command(0, "xx", &mut rng), vec!["INCR", "counter:000000000000"]);
+        let zadd = Op::Zadd.command(1, "xx", &mut rng);
+        assert_eq!(zadd[..2], ["ZADD", "zset:000000000001"]);
+        assert!(zadd[2].parse::<u64>().is_ok());
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let stats = LatencyStats::new((1..=100).rev().collect());
+        assert_eq!(stats.percentile(50.0), 50);
+        assert_eq!(stats.percentile(99.0), 99);
+        assert_eq!(stats.percentile(99.9), 100);
+        assert_eq!(stats.percentile(0.0), 1);
+        assert_eq!(stats.max(), 100);
+        assert_eq!(stats.mean(), 50.5);
+        assert_eq!(LatencyStats::new(Vec::new()).percentile(99.0), 0);
+    }
+}
@@ -0,0 +1,15 @@
+//! HexagonDB Benchmark - Main Entry Point
+//!
+//! Load generator for measuring throughput and latency between releases.
+
+use clap::Parser;
+use hexagondb::benchmark::{self, BenchArgs};
+
+#[tokio::main]
+async fn main() {
+    let args = BenchArgs::parse();
+    if let Err(e) = benchmark::run(args).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
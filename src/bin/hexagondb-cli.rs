@@ -4,16 +4,34 @@
 
 use clap::Parser;
 use hexagondb::cli::{
+    analysis::run_analysis,
     client::RespClient,
     colors::Colors,
     config::CliArgs,
-    repl::{run_command, run_interactive, run_pipe},
+    download::run_download,
+    latency::run_latency,
+    offline::run_offline,
+    repl::{run_command, run_interactive, run_pipe, run_scan},
 };
 
 fn main() {
-    let args = CliArgs::parse();
+    let mut args = CliArgs::parse();
     let colors = Colors::new(!args.no_color);
 
+    if let Err(e) = args.apply_uri() {
+        eprintln!("{}Error: {}{}", colors.red(), e, colors.reset());
+        std::process::exit(1);
+    }
+
+    // Offline analysis needs no server
+    if let Some(ref cmd) = args.offline {
+        if let Err(e) = run_offline(cmd, &colors) {
+            eprintln!("{}Error: {}{}", colors.red(), e, colors.reset());
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Connect to server
     let client = match RespClient::connect(&args.host, args.port, args.timeout) {
         Ok(c) => c,
@@ -30,7 +48,15 @@ fn main() {
         }
     };
 
-    let result = if args.pipe {
+    let result = if let Some(mode) = args.analysis_mode() {
+        run_analysis(client, mode, &args)
+    } else if let Some((format, path)) = args.download() {
+        run_download(client, format, path, &args)
+    } else if args.latency || args.latency_history {
+        run_latency(client, args.latency_history, &args)
+    } else if args.scan {
+        run_scan(client, &args)
+    } else if args.pipe {
         run_pipe(client, &args)
     } else if let Some(ref cmd) = args.command {
         run_command(client, cmd, &args)
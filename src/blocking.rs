@@ -0,0 +1,38 @@
+//! Blocking pops (BLMPOP, BZMPOP).
+//!
+//! A blocking pop that finds nothing to pop does not wait inside the
+//! interpreter, which would hold the command scheduler's turn and stall
+//! every other client. It hands a `Blocked` back to the connection instead,
+//! which gives up its turn and retries the non-blocking form whenever a
+//! list or sorted set receives elements, until it succeeds or times out.
+
+use crate::network::resp::RespValue;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Woken whenever elements are pushed to a list or added to a sorted set
+pub static KEYS_READY: Notify = Notify::const_new();
+
+/// Wake the clients blocked on a pop so they retry
+pub fn signal_ready() {
+    KEYS_READY.notify_waiters();
+}
+
+/// A pop waiting for elements
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blocked {
+    /// The non-blocking command to retry (LMPOP or ZMPOP)
+    pub retry: RespValue,
+    /// How long to wait before replying with nil, None to wait forever
+    pub timeout: Option<Duration>,
+}
+
+/// Parse a blocking timeout in seconds (fractions allowed, 0 = forever)
+pub fn parse_timeout(value: &str) -> Result<Option<Duration>, &'static str> {
+    match value.parse::<f64>() {
+        Ok(secs) if secs < 0.0 => Err("timeout is negative"),
+        Ok(0.0) => Ok(None),
+        Ok(secs) if secs.is_finite() => Ok(Some(Duration::from_secs_f64(secs))),
+        _ => Err("timeout is not a float or out of range"),
+    }
+}
@@ -0,0 +1,221 @@
+//! Bulk TTL jobs.
+//!
+//! Backs the EXPIREMANY admin command: sets or clears the TTL of every key
+//! matching a pattern in a background task. Keys are processed in batches so
+//! the write lock is released between batches, and progress can be polled
+//! with EXPIREMANY STATUS.
+
+use crate::db::{GenericOps, DB};
+use crate::persistence::aof::Aof;
+use parking_lot::RwLock as SyncRwLock;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// Finished jobs kept around for STATUS queries
+const MAX_FINISHED_JOBS: usize = 32;
+
+/// What a bulk TTL job does to each matching key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkTtlAction {
+    /// Set a TTL in seconds
+    Expire(u64),
+    /// Remove the TTL
+    Persist,
+}
+
+/// Progress of a single bulk TTL job
+#[derive(Debug)]
+pub struct BulkTtlJob {
+    pub id: u64,
+    pub pattern: String,
+    pub action: BulkTtlAction,
+    /// Keys matching the pattern when the job started
+    pub matched: AtomicUsize,
+    /// Keys visited so far
+    pub processed: AtomicUsize,
+    /// Keys whose TTL actually changed
+    pub updated: AtomicUsize,
+    /// Whether the job has finished
+    pub done: AtomicBool,
+}
+
+impl BulkTtlJob {
+    /// Render job progress as field/value pairs
+    pub fn status(&self) -> Vec<(String, String)> {
+        let action = match self.action {
+            BulkTtlAction::Expire(secs) => format!("expire {}", secs),
+            BulkTtlAction::Persist => "persist".to_string(),
+        };
+        let state = if self.done.load(Ordering::Acquire) {
+            "done"
+        } else {
+            "running"
+        };
+        vec![
+            ("id".to_string(), self.id.to_string()),
+            ("pattern".to_string(), self.pattern.clone()),
+            ("action".to_string(), action),
+            ("state".to_string(), state.to_string()),
+            ("matched".to_string(), self.matched.load(Ordering::Relaxed).to_string()),
+            ("processed".to_string(), self.processed.load(Ordering::Relaxed).to_string()),
+            ("updated".to_string(), self.updated.load(Ordering::Relaxed).to_string()),
+        ]
+    }
+}
+
+/// Registry of bulk TTL jobs shared by all connections
+pub struct BulkTtlJobs {
+    next_id: AtomicU64,
+    jobs: SyncRwLock<BTreeMap<u64, Arc<BulkTtlJob>>>,
+}
+
+impl BulkTtlJobs {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        BulkTtlJobs {
+            next_id: AtomicU64::new(1),
+            jobs: SyncRwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Register a new job and spawn it in the background
+    pub fn start(
+        &self,
+        pattern: String,
+        action: BulkTtlAction,
+        batch_size: usize,
+        db: Arc<RwLock<DB>>,
+        aof: Arc<RwLock<Aof>>,
+    ) -> Arc<BulkTtlJob> {
+        let job = Arc::new(BulkTtlJob {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            pattern,
+            action,
+            matched: AtomicUsize::new(0),
+            processed: AtomicUsize::new(0),
+            updated: AtomicUsize::new(0),
+            done: AtomicBool::new(false),
+        });
+
+        {
+            let mut jobs = self.jobs.write();
+            jobs.insert(job.id, Arc::clone(&job));
+            // Forget the oldest finished jobs
+            let finished: Vec<u64> = jobs
+                .values()
+                .filter(|j| j.done.load(Ordering::Acquire))
+                .map(|j| j.id)
+                .collect();
+            for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS)) {
+                jobs.remove(id);
+            }
+        }
+
+        tokio::spawn(run(Arc::clone(&job), batch_size.max(1), db, aof));
+        job
+    }
+
+    /// Look up a job by id
+    pub fn get(&self, id: u64) -> Option<Arc<BulkTtlJob>> {
+        self.jobs.read().get(&id).cloned()
+    }
+
+    /// All known jobs, oldest first
+    pub fn list(&self) -> Vec<Arc<BulkTtlJob>> {
+        self.jobs.read().values().cloned().collect()
+    }
+}
+
+impl Default for BulkTtlJobs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Apply the job's action to all matching keys, one batch per write lock
+async fn run(job: Arc<BulkTtlJob>, batch_size: usize, db: Arc<RwLock<DB>>, aof: Arc<RwLock<Aof>>) {
+    let keys = db.read().await.keys(&job.pattern);
+    job.matched.store(keys.len(), Ordering::Relaxed);
+
+    for batch in keys.chunks(batch_size) {
+        {
+            let mut db = db.write().await;
+            let mut aof_commands = Vec::new();
+            for key in batch {
+                let changed = match job.action {
+                    BulkTtlAction::Expire(secs) => db.expire(key, secs),
+                    BulkTtlAction::Persist => db.persist(key),
+                };
+                if changed {
+                    job.updated.fetch_add(1, Ordering::Relaxed);
+                    aof_commands.push(match job.action {
+                        BulkTtlAction::Expire(secs) => {
+                            vec!["EXPIRE".to_string(), key.clone(), secs.to_string()]
+                        }
+                        BulkTtlAction::Persist => vec!["PERSIST".to_string(), key.clone()],
+                    });
+                }
+            }
+
+            // Log the batch before releasing the keyspace so a concurrent
+            // write to the same key can't land in the AOF ahead of it
+            if !aof_commands.is_empty() {
+                let mut aof = aof.write().await;
+                for cmd in aof_commands {
+                    if let Err(e) = aof.append(cmd) {
+                        error!("AOF write error: {}", e);
+                    }
+                }
+            }
+        }
+        job.processed.fetch_add(batch.len(), Ordering::Relaxed);
+
+        // Let other clients get at the database between batches
+        tokio::task::yield_now().await;
+    }
+
+    job.done.store(true, Ordering::Release);
+    info!(
+        "EXPIREMANY job {} finished: {} of {} keys updated",
+        job.id,
+        job.updated.load(Ordering::Relaxed),
+        job.matched.load(Ordering::Relaxed)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::StringOps;
+
+    #[tokio::test]
+    async fn test_bulk_expire() {
+        let db = Arc::new(RwLock::new(DB::new()));
+        {
+            let mut guard = db.write().await;
+            for i in 0..10 {
+                guard.set(format!("tmp:{}", i), "v".to_string());
+            }
+            guard.set("keep".to_string(), "v".to_string());
+        }
+        let path = std::env::temp_dir().join(format!("hexagondb-test-{}.aof", uuid::Uuid::new_v4()));
+        let aof = Arc::new(RwLock::new(Aof::new(&path).unwrap()));
+        let _ = std::fs::remove_file(&path);
+
+        let jobs = BulkTtlJobs::new();
+        let job = jobs.start("tmp:*".to_string(), BulkTtlAction::Expire(60), 3, Arc::clone(&db), aof);
+        while !job.done.load(Ordering::Acquire) {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(job.matched.load(Ordering::Relaxed), 10);
+        assert_eq!(job.updated.load(Ordering::Relaxed), 10);
+        let mut guard = db.write().await;
+        assert!(guard.ttl("tmp:3") > 0);
+        assert_eq!(guard.ttl("keep"), -1);
+        assert!(jobs.get(job.id).is_some());
+    }
+}
@@ -0,0 +1,268 @@
+//! Keyspace Analysis
+//!
+//! `--bigkeys`, `--memkeys` and `--hotkeys`: walk a live server and print a
+//! summary, as redis-cli does. `--bigkeys` and `--memkeys` iterate with SCAN
+//! and size each key (element count or MEMORY USAGE); `--hotkeys` counts the
+//! keys in the server's sampled access log.
+
+use super::client::{RespClient, RespResponse};
+use super::colors::Colors;
+use super::config::CliArgs;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::time::Duration;
+
+/// Keys requested per SCAN call
+const SCAN_COUNT: &str = "100";
+
+/// Hot keys printed by `--hotkeys`
+const HOT_KEYS: usize = 16;
+
+/// What an analysis run measures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Element count (bytes for strings)
+    BigKeys,
+    /// Approximate memory from MEMORY USAGE
+    MemKeys,
+    /// Access count from the access log
+    HotKeys,
+}
+
+/// Totals and largest key of one type
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TypeSummary {
+    pub keys: u64,
+    pub total: u64,
+    pub biggest: Option<(String, u64)>,
+}
+
+/// Per-type totals of a scan, keyed by type name
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub types: BTreeMap<String, TypeSummary>,
+    pub scanned: u64,
+}
+
+impl Summary {
+    /// Count a key of `type_name` with the given size
+    pub fn add(&mut self, type_name: &str, key: &str, size: u64) {
+        self.scanned += 1;
+        let summary = self.types.entry(type_name.to_string()).or_default();
+        summary.keys += 1;
+        summary.total += size;
+        if summary.biggest.as_ref().is_none_or(|(_, biggest)| size > *biggest) {
+            summary.biggest = Some((key.to_string(), size));
+        }
+    }
+}
+
+/// Count how often each key appears in access log entries, most accessed first
+pub fn hot_keys(entries: &[RespResponse]) -> Vec<(String, u64)> {
+    let mut counts: HashMap<String, u64> = HashMap::new();
+    for entry in entries {
+        // [id, [field, value, ...]]
+        let fields = match entry {
+            RespResponse::Array(parts) => match parts.get(1) {
+                Some(RespResponse::Array(fields)) => fields,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        for pair in fields.chunks(2) {
+            if let [RespResponse::Bulk(name), RespResponse::Bulk(key)] = pair {
+                if name == "key" {
+                    *counts.entry(key.clone()).or_default() += 1;
+                }
+            }
+        }
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Run an analysis against the server and print its summary. `-i`
+/// seconds are slept between SCAN calls to go easy on a busy server.
+pub fn run_analysis(mut client: RespClient, mode: Mode, args: &CliArgs) -> io::Result<()> {
+    let colors = Colors::new(!args.no_color);
+
+    // Authenticate if password provided
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
+        if response.is_error() {
+            eprintln!("Authentication failed");
+            std::process::exit(1);
+        }
+    }
+
+    if mode == Mode::HotKeys {
+        return print_hot_keys(&mut client, &args.access_log_key, &colors);
+    }
+
+    let unit = if mode == Mode::MemKeys { "bytes" } else { "elements" };
+    let mut summary = Summary::default();
+    let mut cursor = "0".to_string();
+    loop {
+        let reply = client.send_command(&["SCAN", &cursor, "COUNT", SCAN_COUNT])?;
+        let (next, keys) = match reply {
+            RespResponse::Array(parts) => match parts.as_slice() {
+                [RespResponse::Bulk(next), RespResponse::Array(keys)] => (next.clone(), keys.clone()),
+                _ => return Err(unexpected("SCAN")),
+            },
+            RespResponse::Error(e) => return Err(io::Error::other(e)),
+            _ => return Err(unexpected("SCAN")),
+        };
+        for key in keys {
+            let RespResponse::Bulk(key) = key else { continue };
+            let type_name = match client.send_command(&["TYPE", &key])? {
+                RespResponse::Simple(t) if t != "none" => t,
+                // Deleted since the SCAN
+                _ => continue,
+            };
+            let size = match mode {
+                Mode::MemKeys => integer(client.send_command(&["MEMORY", "USAGE", &key])?),
+                _ => match size_command(&type_name) {
+                    Some(cmd) => integer(client.send_command(&[cmd, &key])?),
+                    None => Some(0),
+                },
+            };
+            if let Some(size) = size {
+                summary.add(&type_name, &key, size);
+            }
+        }
+        if next == "0" {
+            break;
+        }
+        cursor = next;
+        if args.interval > 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(args.interval));
+        }
+    }
+
+    print_summary(&summary, unit, &colors);
+    Ok(())
+}
+
+/// Command that sizes a key of a type in `--bigkeys` mode
+fn size_command(type_name: &str) -> Option<&'static str> {
+    match type_name {
+        "string" => Some("STRLEN"),
+        "list" => Some("LLEN"),
+        "hash" => Some("HLEN"),
+        "set" => Some("SCARD"),
+        "zset" => Some("ZCARD"),
+        "stream" => Some("XLEN"),
+        _ => None,
+    }
+}
+
+fn integer(reply: RespResponse) -> Option<u64> {
+    match reply {
+        RespResponse::Integer(n) => u64::try_from(n).ok(),
+        _ => None,
+    }
+}
+
+fn unexpected(command: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("unexpected {} reply", command))
+}
+
+fn print_summary(summary: &Summary, unit: &str, colors: &Colors) {
+    println!(
+        "{}{}Scanned {} keys{}",
+        colors.bold(),
+        colors.green(),
+        summary.scanned,
+        colors.reset()
+    );
+    println!();
+    println!("  {:<8} {:>10} {:>14} {:>12}  biggest", "type", "keys", unit, "avg");
+    for (name, stats) in &summary.types {
+        let (key, size) = stats.biggest.clone().unwrap_or_default();
+        println!(
+            "  {:<8} {:>10} {:>14} {:>12.1}  {}{}{} ({} {})",
+            name,
+            stats.keys,
+            stats.total,
+            stats.total as f64 / stats.keys.max(1) as f64,
+            colors.cyan(),
+            key,
+            colors.reset(),
+            size,
+            unit
+        );
+    }
+}
+
+fn print_hot_keys(client: &mut RespClient, access_log_key: &str, colors: &Colors) -> io::Result<()> {
+    // [[stream, [entry ...]]], or nil when the stream is empty
+    let entries = match client.send_command(&["XREAD", "STREAMS", access_log_key, "0"])? {
+        RespResponse::Array(mut streams) => match streams.pop() {
+            Some(RespResponse::Array(mut stream)) => match stream.pop() {
+                Some(RespResponse::Array(entries)) => entries,
+                _ => Vec::new(),
+            },
+            _ => Vec::new(),
+        },
+        RespResponse::Error(e) => return Err(io::Error::other(e)),
+        _ => Vec::new(),
+    };
+    if entries.is_empty() {
+        println!(
+            "No samples in {}. Enable [access_log] on the server to track hot keys.",
+            access_log_key
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{}{}Hot keys in {} sampled accesses{}",
+        colors.bold(),
+        colors.green(),
+        entries.len(),
+        colors.reset()
+    );
+    println!();
+    for (key, count) in hot_keys(&entries).into_iter().take(HOT_KEYS) {
+        println!("  {:>10}  {}{}{}", count, colors.cyan(), key, colors.reset());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_tracks_biggest_per_type() {
+        let mut summary = Summary::default();
+        summary.add("string", "a", 3);
+        summary.add("string", "b", 10);
+        summary.add("list", "l", 4);
+        summary.add("string", "c", 10);
+
+        assert_eq!(summary.scanned, 4);
+        let strings = &summary.types["string"];
+        assert_eq!((strings.keys, strings.total), (3, 23));
+        assert_eq!(strings.biggest, Some(("b".to_string(), 10)));
+        assert_eq!(summary.types["list"].biggest, Some(("l".to_string(), 4)));
+    }
+
+    #[test]
+    fn test_hot_keys_counts_access_log() {
+        let entry = |key: &str| {
+            RespResponse::Array(vec![
+                RespResponse::Bulk("1-0".to_string()),
+                RespResponse::Array(
+                    ["key", key, "command", "GET"]
+                        .iter()
+                        .map(|s| RespResponse::Bulk(s.to_string()))
+                        .collect(),
+                ),
+            ])
+        };
+        let entries = vec![entry("a"), entry("b"), entry("b"), RespResponse::Null];
+        assert_eq!(hot_keys(&entries), vec![("b".to_string(), 2), ("a".to_string(), 1)]);
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! TCP client for RESP protocol communication.
 
-use std::io::{self, BufRead, BufReader, Read, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use std::net::TcpStream;
 use std::time::Duration;
 
@@ -10,6 +10,7 @@ use std::time::Duration;
 pub struct RespClient {
     stream: TcpStream,
     reader: BufReader<TcpStream>,
+    timeout: Option<Duration>,
 }
 
 impl RespClient {
@@ -18,15 +19,22 @@ impl RespClient {
         let addr = format!("{}:{}", host, port);
         let stream = TcpStream::connect(&addr)?;
         
-        stream.set_read_timeout(Some(Duration::from_secs(timeout_secs)))?;
-        stream.set_write_timeout(Some(Duration::from_secs(timeout_secs)))?;
+        let timeout = Some(Duration::from_secs(timeout_secs));
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
         
         let reader = BufReader::new(stream.try_clone()?);
-        Ok(RespClient { stream, reader })
+        Ok(RespClient { stream, reader, timeout })
     }
 
     /// Send a command and get response
     pub fn send_command(&mut self, parts: &[&str]) -> io::Result<RespResponse> {
+        self.write_command(parts)?;
+        self.read_response()
+    }
+
+    /// Send a command without waiting for its reply
+    pub fn write_command(&mut self, parts: &[&str]) -> io::Result<()> {
         // Build RESP array
         let mut cmd = format!("*{}\r\n", parts.len());
         for part in parts {
@@ -34,71 +42,116 @@ impl RespClient {
         }
 
         self.stream.write_all(cmd.as_bytes())?;
-        self.stream.flush()?;
-
-        self.read_response()
+        self.stream.flush()
     }
 
-    /// Read a RESP response
-    fn read_response(&mut self) -> io::Result<RespResponse> {
-        let mut line = String::new();
-        self.reader.read_line(&mut line)?;
-        
-        if line.is_empty() {
-            return Err(io::Error::new(
-                io::ErrorKind::ConnectionReset,
-                "Connection closed",
-            ));
-        }
+    /// Change how long a reply may take; `None` waits forever
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.timeout = timeout;
+        self.stream.set_read_timeout(timeout)
+    }
 
-        let line = line.trim_end();
-        
-        match line.chars().next() {
-            Some('+') => Ok(RespResponse::Simple(line[1..].to_string())),
-            Some('-') => Ok(RespResponse::Error(line[1..].to_string())),
-            Some(':') => {
-                let num: i64 = line[1..].parse().unwrap_or(0);
-                Ok(RespResponse::Integer(num))
+    /// Wait up to `wait` for the next pushed message (subscribe mode).
+    /// Returns `None` when nothing arrived, so the caller can check for
+    /// Ctrl+C between polls. Once a message starts arriving it is read
+    /// with the normal timeout, so a slow message is never cut in half.
+    pub fn read_push(&mut self, wait: Duration) -> io::Result<Option<RespResponse>> {
+        if self.reader.buffer().is_empty() {
+            self.stream.set_read_timeout(Some(wait))?;
+            let ready = self.reader.fill_buf().map(|_| ());
+            self.stream.set_read_timeout(self.timeout)?;
+            match ready {
+                Ok(()) => {}
+                Err(e) if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+                ) => return Ok(None),
+                Err(e) => return Err(e),
             }
-            Some('$') => self.read_bulk_string(&line[1..]),
-            Some('*') => self.read_array(&line[1..]),
-            _ => Ok(RespResponse::Simple(line.to_string())),
         }
+        self.read_response().map(Some)
     }
 
-    fn read_bulk_string(&mut self, len_str: &str) -> io::Result<RespResponse> {
-        let len: i64 = len_str.parse().unwrap_or(-1);
-        
-        if len < 0 {
-            return Ok(RespResponse::Null);
-        }
+    /// Read a RESP response
+    pub fn read_response(&mut self) -> io::Result<RespResponse> {
+        read_value(&mut self.reader)
+    }
 
-        let mut data = vec![0u8; len as usize + 2];
-        self.reader.read_exact(&mut data)?;
-        
-        let s = String::from_utf8_lossy(&data[..len as usize]).to_string();
-        Ok(RespResponse::Bulk(s))
+    /// Check if connection is alive
+    pub fn ping(&mut self) -> bool {
+        matches!(self.send_command(&["PING"]), Ok(RespResponse::Simple(s)) if s == "PONG")
     }
+}
 
-    fn read_array(&mut self, len_str: &str) -> io::Result<RespResponse> {
-        let len: i64 = len_str.parse().unwrap_or(-1);
-        
-        if len < 0 {
-            return Ok(RespResponse::Null);
-        }
+/// Decode one complete reply from `reader`, reading as much as it takes.
+/// Bulk strings are read by their length, so they may hold `\r\n` and be
+/// any size; arrays and maps recurse.
+pub fn read_value<R: BufRead>(reader: &mut R) -> io::Result<RespResponse> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    if line.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "Connection closed",
+        ));
+    }
 
-        let mut items = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            items.push(self.read_response()?);
-        }
-        
-        Ok(RespResponse::Array(items))
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    match line.chars().next() {
+        Some('+') => Ok(RespResponse::Simple(line[1..].to_string())),
+        Some('-') => Ok(RespResponse::Error(line[1..].to_string())),
+        Some(':') => Ok(RespResponse::Integer(parse_header(line)?)),
+        Some('$') => read_bulk_string(reader, parse_header(line)?),
+        Some('*') => read_array(reader, parse_header(line)?),
+        Some('%') => read_map(reader, parse_header(line)?),
+        Some('_') => Ok(RespResponse::Null),
+        _ => Ok(RespResponse::Simple(line.to_string())),
     }
+}
 
-    /// Check if connection is alive
-    pub fn ping(&mut self) -> bool {
-        matches!(self.send_command(&["PING"]), Ok(RespResponse::Simple(s)) if s == "PONG")
+/// Number after the type byte of an integer or length line
+fn parse_header(line: &str) -> io::Result<i64> {
+    line[1..].parse().map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("invalid RESP header: {}", line))
+    })
+}
+
+fn read_bulk_string<R: BufRead>(reader: &mut R, len: i64) -> io::Result<RespResponse> {
+    if len < 0 {
+        return Ok(RespResponse::Null);
+    }
+
+    let mut data = vec![0u8; len as usize + 2];
+    reader.read_exact(&mut data)?;
+
+    let s = String::from_utf8_lossy(&data[..len as usize]).to_string();
+    Ok(RespResponse::Bulk(s))
+}
+
+fn read_array<R: BufRead>(reader: &mut R, len: i64) -> io::Result<RespResponse> {
+    if len < 0 {
+        return Ok(RespResponse::Null);
     }
+
+    let mut items = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        items.push(read_value(reader)?);
+    }
+
+    Ok(RespResponse::Array(items))
+}
+
+/// RESP3 map: `len` key/value pairs
+fn read_map<R: BufRead>(reader: &mut R, len: i64) -> io::Result<RespResponse> {
+    let len = usize::try_from(len).unwrap_or(0);
+    let mut pairs = Vec::with_capacity(len);
+    for _ in 0..len {
+        let key = read_value(reader)?;
+        pairs.push((key, read_value(reader)?));
+    }
+    Ok(RespResponse::Map(pairs))
 }
 
 /// RESP response types
@@ -109,6 +162,8 @@ pub enum RespResponse {
     Integer(i64),
     Bulk(String),
     Array(Vec<RespResponse>),
+    /// RESP3 map, in server order
+    Map(Vec<(RespResponse, RespResponse)>),
     Null,
 }
 
@@ -132,3 +187,65 @@ impl RespResponse {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_read_push_polls_until_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut client = RespClient::connect("127.0.0.1", port, 5).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let poll = Duration::from_millis(20);
+        assert!(client.read_push(poll).unwrap().is_none());
+
+        server.write_all(b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n").unwrap();
+        match client.read_push(Duration::from_secs(5)).unwrap() {
+            Some(RespResponse::Array(parts)) => assert_eq!(parts.len(), 3),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(client.read_push(poll).unwrap().is_none());
+
+        drop(server);
+        assert!(client.read_push(poll).is_err());
+    }
+
+    fn decode(input: &[u8]) -> io::Result<RespResponse> {
+        read_value(&mut io::Cursor::new(input))
+    }
+
+    #[test]
+    fn test_read_value_bulk_with_crlf() {
+        match decode(b"$7\r\na\r\nb\r\nc\r\n").unwrap() {
+            RespResponse::Bulk(s) => assert_eq!(s, "a\r\nb\r\nc"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_value_large_and_nested() {
+        let big = "x".repeat(64 * 1024);
+        let input = format!("*2\r\n${}\r\n{}\r\n*2\r\n:1\r\n*1\r\n$-1\r\n", big.len(), big);
+        match decode(input.as_bytes()).unwrap() {
+            RespResponse::Array(items) => match items.as_slice() {
+                [RespResponse::Bulk(s), RespResponse::Array(inner)] => {
+                    assert_eq!(s.len(), big.len());
+                    assert!(matches!(inner.as_slice(), [RespResponse::Integer(1), RespResponse::Array(n)] if n[0].is_null()));
+                }
+                other => panic!("unexpected {:?}", other),
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_value_rejects_bad_header() {
+        assert_eq!(decode(b"$abc\r\n").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        // Truncated bulk string
+        assert!(decode(b"$10\r\nshort\r\n").is_err());
+    }
+}
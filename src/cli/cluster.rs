@@ -0,0 +1,178 @@
+//! Cluster Redirects
+//!
+//! `--cluster` mode, as `redis-cli -c`: a reply of `MOVED <slot> <host:port>`
+//! or `ASK <slot> <host:port>` is followed to the named node. MOVED targets
+//! are remembered in a slot map, so later commands on keys of that slot go
+//! straight to the right node. HexagonDB itself runs a single node; this
+//! lets the CLI talk to clustered deployments of compatible servers.
+
+use super::client::{RespClient, RespResponse};
+use super::config::CliArgs;
+use std::collections::HashMap;
+use std::io;
+
+/// Number of hash slots in a cluster
+pub const CLUSTER_SLOTS: u16 = 16384;
+
+/// Redirects followed for one command before giving up
+const MAX_REDIRECTS: usize = 5;
+
+/// CRC16-CCITT (XMODEM), the checksum behind key slots
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Hash slot of a key. Only the part inside the first non-empty `{...}`
+/// is hashed, so keys sharing a hash tag land in the same slot.
+pub fn key_slot(key: &str) -> u16 {
+    let bytes = key.as_bytes();
+    let hashed = match bytes.iter().position(|&b| b == b'{') {
+        Some(open) => match bytes[open + 1..].iter().position(|&b| b == b'}') {
+            Some(len) if len > 0 => &bytes[open + 1..open + 1 + len],
+            _ => bytes,
+        },
+        None => bytes,
+    };
+    crc16(hashed) % CLUSTER_SLOTS
+}
+
+/// A redirect reply from a cluster node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    /// The slot now lives on `addr`
+    Moved { slot: u16, addr: String },
+    /// Retry this one command on `addr`, preceded by ASKING
+    Ask { slot: u16, addr: String },
+}
+
+/// Recognize a MOVED or ASK error reply
+pub fn parse_redirect(reply: &RespResponse) -> Option<Redirect> {
+    let mut parts = reply.error_message()?.split_whitespace();
+    let kind = parts.next()?;
+    let slot = parts.next()?.parse().ok()?;
+    let addr = parts.next()?.to_string();
+    match kind {
+        "MOVED" => Some(Redirect::Moved { slot, addr }),
+        "ASK" => Some(Redirect::Ask { slot, addr }),
+        _ => None,
+    }
+}
+
+/// Routes commands to cluster nodes by slot and follows redirects
+pub struct ClusterRouter {
+    /// Node address of each slot learned from MOVED replies
+    slots: HashMap<u16, String>,
+    /// Connections to nodes other than the one the CLI started with
+    nodes: HashMap<String, RespClient>,
+    seed: String,
+    auth: Option<Vec<String>>,
+    timeout: u64,
+}
+
+impl ClusterRouter {
+    pub fn new(args: &CliArgs) -> Self {
+        ClusterRouter {
+            slots: HashMap::new(),
+            nodes: HashMap::new(),
+            seed: args.address(),
+            auth: args
+                .auth_command()
+                .map(|auth| auth.into_iter().map(String::from).collect()),
+            timeout: args.timeout,
+        }
+    }
+
+    /// Send a command to the node owning its key (the first argument),
+    /// following MOVED/ASK redirects. Returns the reply and the address of
+    /// the node that gave it.
+    pub fn send_command(
+        &mut self,
+        seed: &mut RespClient,
+        parts: &[&str],
+    ) -> io::Result<(RespResponse, String)> {
+        let mut target = parts
+            .get(1)
+            .and_then(|key| self.slots.get(&key_slot(key)))
+            .cloned()
+            .unwrap_or_else(|| self.seed.clone());
+        let mut asking = false;
+
+        for _ in 0..=MAX_REDIRECTS {
+            let client = if target == self.seed { &mut *seed } else { self.node(&target)? };
+            if asking {
+                client.send_command(&["ASKING"])?;
+            }
+            let reply = client.send_command(parts)?;
+            match parse_redirect(&reply) {
+                Some(Redirect::Moved { slot, addr }) => {
+                    self.slots.insert(slot, addr.clone());
+                    target = addr;
+                    asking = false;
+                }
+                Some(Redirect::Ask { addr, .. }) => {
+                    target = addr;
+                    asking = true;
+                }
+                None => return Ok((reply, target)),
+            }
+        }
+        Err(io::Error::other(format!("too many cluster redirects for {}", parts[0])))
+    }
+
+    /// Connection to `addr`, opened (and authenticated) on first use
+    fn node(&mut self, addr: &str) -> io::Result<&mut RespClient> {
+        if !self.nodes.contains_key(addr) {
+            let (host, port) = addr
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("bad node address {}", addr)))?;
+            let mut client = RespClient::connect(host, port, self.timeout)?;
+            if let Some(ref auth) = self.auth {
+                let auth: Vec<&str> = auth.iter().map(String::as_str).collect();
+                if let RespResponse::Error(e) = client.send_command(&auth)? {
+                    return Err(io::Error::other(e));
+                }
+            }
+            self.nodes.insert(addr.to_string(), client);
+        }
+        Ok(self.nodes.get_mut(addr).expect("inserted above"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(key_slot("foo"), 12182);
+        assert_eq!(key_slot("{user1000}.following"), key_slot("{user1000}.followers"));
+        assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+        // Empty tag: the whole key is hashed
+        assert_eq!(key_slot("foo{}{bar}"), crc16(b"foo{}{bar}") % CLUSTER_SLOTS);
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let moved = RespResponse::Error("MOVED 3999 127.0.0.1:6381".to_string());
+        assert_eq!(
+            parse_redirect(&moved),
+            Some(Redirect::Moved { slot: 3999, addr: "127.0.0.1:6381".to_string() })
+        );
+        let ask = RespResponse::Error("ASK 12 10.0.0.2:7000".to_string());
+        assert_eq!(
+            parse_redirect(&ask),
+            Some(Redirect::Ask { slot: 12, addr: "10.0.0.2:7000".to_string() })
+        );
+        assert_eq!(parse_redirect(&RespResponse::Error("ERR no such key".to_string())), None);
+        assert_eq!(parse_redirect(&RespResponse::Simple("MOVED 1 a:1".to_string())), None);
+    }
+}
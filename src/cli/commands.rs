@@ -59,7 +59,7 @@ pub fn print_help(colors: &Colors) {
     println!();
     
     println!("  {}Sorted Set:{}", colors.yellow(), colors.reset());
-    println!("    ZADD, ZREM, ZRANGE, ZSCORE, ZRANK, ZCARD, ZINCRBY");
+    println!("    ZADD, ZREM, ZRANGE, ZSCORE, ZRANK, ZAROUND, ZCARD, ZINCRBY");
     println!();
     
     println!("  {}Bitmap:{}", colors.yellow(), colors.reset());
@@ -78,6 +78,19 @@ pub fn print_help(colors: &Colors) {
     println!("    PFADD, PFCOUNT, PFMERGE");
     println!();
     
+    println!("  {}Time Series:{}", colors.yellow(), colors.reset());
+    println!("    TS.CREATE, TS.ADD, TS.GET, TS.RANGE, TS.MRANGE, TS.CREATERULE, TS.DELETERULE");
+    println!();
+    
+    println!("  {}Bloom / Cuckoo Filters:{}", colors.yellow(), colors.reset());
+    println!("    BF.RESERVE, BF.ADD, BF.MADD, BF.EXISTS, BF.MEXISTS, BF.INFO");
+    println!("    CF.RESERVE, CF.ADD, CF.ADDNX, CF.EXISTS, CF.MEXISTS, CF.COUNT, CF.DEL, CF.INFO");
+    println!();
+    
+    println!("  {}Vector:{}", colors.yellow(), colors.reset());
+    println!("    VECTOR.ADD, VECTOR.GET, VECTOR.DEL, VECTOR.CARD, VECTOR.SEARCH");
+    println!();
+    
     println!("  {}Key Management:{}", colors.yellow(), colors.reset());
     println!("    KEYS, SCAN, TYPE, DEL, EXISTS, EXPIRE, TTL, RENAME");
     println!();
@@ -91,7 +104,7 @@ pub fn print_help(colors: &Colors) {
     println!();
     
     println!("  {}Pub/Sub:{}", colors.yellow(), colors.reset());
-    println!("    PUBLISH, SUBSCRIBE, PSUBSCRIBE, UNSUBSCRIBE");
+    println!("    PUBLISH, SUBSCRIBE, PSUBSCRIBE, UNSUBSCRIBE, SPUBLISH, SSUBSCRIBE, PUBSUB");
     println!();
     
     println!("  {}CLI Special:{}", colors.yellow(), colors.reset());
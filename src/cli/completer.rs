@@ -26,10 +26,13 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     ("STRLEN", "key", "Get string length"),
     
     // List commands
+    ("BLMPOP", "timeout numkeys key [key ...] LEFT|RIGHT [COUNT count]", "Pop from the first non-empty list, waiting"),
     ("LINDEX", "key index", "Get element by index"),
     ("LINSERT", "key BEFORE|AFTER pivot value", "Insert element"),
     ("LLEN", "key", "Get list length"),
+    ("LMPOP", "numkeys key [key ...] LEFT|RIGHT [COUNT count]", "Pop from the first non-empty list"),
     ("LPOP", "key [count]", "Remove and get first elements"),
+    ("LPOS", "key element [RANK rank] [COUNT num] [MAXLEN len]", "Find element positions"),
     ("LPUSH", "key value [value ...]", "Prepend values"),
     ("LPUSHX", "key value", "Prepend value if exists"),
     ("LRANGE", "key start stop", "Get range of elements"),
@@ -43,6 +46,8 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     // Hash commands
     ("HDEL", "key field [field ...]", "Delete fields"),
     ("HEXISTS", "key field", "Check field exists"),
+    ("HEXPIRE", "key seconds [NX|XX|GT|LT] FIELDS numfields field [field ...]", "Set field TTLs"),
+    ("HEXPIREAT", "key unix-time-seconds [NX|XX|GT|LT] FIELDS numfields field [field ...]", "Set field expiry times"),
     ("HGET", "key field", "Get field value"),
     ("HGETALL", "key", "Get all fields and values"),
     ("HINCRBY", "key field increment", "Increment field"),
@@ -51,11 +56,19 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     ("HLEN", "key", "Get number of fields"),
     ("HMGET", "key field [field ...]", "Get multiple fields"),
     ("HMSET", "key field value [field value ...]", "Set multiple fields"),
-    ("HSCAN", "key cursor [MATCH pattern] [COUNT count]", "Scan fields"),
+    ("HPERSIST", "key FIELDS numfields field [field ...]", "Remove field TTLs"),
+    ("HPEXPIRE", "key milliseconds [NX|XX|GT|LT] FIELDS numfields field [field ...]", "Set field TTLs in ms"),
+    ("HPEXPIREAT", "key unix-time-milliseconds [NX|XX|GT|LT] FIELDS numfields field [field ...]", "Set field expiry times in ms"),
+    ("HPTTL", "key FIELDS numfields field [field ...]", "Get field TTLs in ms"),
+    ("HSCAN", "key cursor [MATCH pattern] [COUNT count] [NOVALUES]", "Scan fields"),
     ("HSET", "key field value [field value ...]", "Set field(s)"),
     ("HSETNX", "key field value", "Set field if not exists"),
     ("HSTRLEN", "key field", "Get field value length"),
+    ("HTTL", "key FIELDS numfields field [field ...]", "Get field TTLs"),
     ("HVALS", "key", "Get all values"),
+    ("SESSION.CREATE", "key seconds field value [field value ...]", "Create session hash"),
+    ("SESSION.GET", "key seconds [field ...]", "Read session, reset TTL"),
+    ("SESSION.REFRESH", "key seconds [field value ...]", "Update session, reset TTL"),
     
     // Set commands
     ("SADD", "key member [member ...]", "Add members"),
@@ -63,6 +76,7 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     ("SDIFF", "key [key ...]", "Get difference"),
     ("SDIFFSTORE", "destination key [key ...]", "Store difference"),
     ("SINTER", "key [key ...]", "Get intersection"),
+    ("SINTERCARD", "numkeys key [key ...] [LIMIT limit]", "Count intersection"),
     ("SINTERSTORE", "destination key [key ...]", "Store intersection"),
     ("SISMEMBER", "key member", "Check membership"),
     ("SMEMBERS", "key", "Get all members"),
@@ -75,18 +89,21 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     ("SUNIONSTORE", "destination key [key ...]", "Store union"),
     
     // Sorted Set commands
+    ("BZMPOP", "timeout numkeys key [key ...] MIN|MAX [COUNT count]", "Pop from the first non-empty sorted set, waiting"),
     ("ZADD", "key [NX|XX] [GT|LT] [CH] score member [score member ...]", "Add members"),
+    ("ZAROUND", "key member count [REV]", "Get member with neighbours by rank"),
     ("ZCARD", "key", "Get sorted set size"),
     ("ZCOUNT", "key min max", "Count members in score range"),
     ("ZINCRBY", "key increment member", "Increment member score"),
     ("ZINTERSTORE", "destination numkeys key [key ...] [WEIGHTS weight ...]", "Store intersection"),
+    ("ZMPOP", "numkeys key [key ...] MIN|MAX [COUNT count]", "Pop from the first non-empty sorted set"),
     ("ZRANGE", "key start stop [WITHSCORES]", "Get range by index"),
     ("ZRANGEBYSCORE", "key min max [WITHSCORES] [LIMIT offset count]", "Get range by score"),
-    ("ZRANK", "key member", "Get member rank"),
+    ("ZRANK", "key member [WITHSCORE]", "Get member rank"),
     ("ZREM", "key member [member ...]", "Remove members"),
     ("ZREVRANGE", "key start stop [WITHSCORES]", "Get range by index (reverse)"),
     ("ZREVRANGEBYSCORE", "key max min [WITHSCORES] [LIMIT offset count]", "Get range by score (reverse)"),
-    ("ZREVRANK", "key member", "Get member rank (reverse)"),
+    ("ZREVRANK", "key member [WITHSCORE]", "Get member rank (reverse)"),
     ("ZSCORE", "key member", "Get member score"),
     ("ZUNIONSTORE", "destination numkeys key [key ...] [WEIGHTS weight ...]", "Store union"),
     
@@ -98,14 +115,14 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     ("SETBIT", "key offset value", "Set bit value"),
     
     // Stream commands
-    ("XADD", "key ID field value [field value ...]", "Add entry"),
+    ("XADD", "key [MAXLEN|MINID [=|~] threshold] ID field value [field value ...]", "Add entry"),
     ("XDEL", "key ID [ID ...]", "Delete entries"),
     ("XINFO", "STREAM key", "Get stream info"),
     ("XLEN", "key", "Get stream length"),
     ("XRANGE", "key start end [COUNT count]", "Get range of entries"),
-    ("XREAD", "COUNT count STREAMS key [key ...] ID [ID ...]", "Read entries"),
+    ("XREAD", "[COUNT count] [MAXENTRIES total] STREAMS key [key ...] ID [ID ...]", "Read entries"),
     ("XREVRANGE", "key end start [COUNT count]", "Get range (reverse)"),
-    ("XTRIM", "key MAXLEN [~] count", "Trim stream"),
+    ("XTRIM", "key MAXLEN|MINID [=|~] threshold", "Trim stream"),
     
     // Geo commands
     ("GEOADD", "key longitude latitude member [longitude latitude member ...]", "Add locations"),
@@ -120,20 +137,64 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     ("PFCOUNT", "key [key ...]", "Count unique elements"),
     ("PFMERGE", "destkey sourcekey [sourcekey ...]", "Merge HLLs"),
     
+    // Bloom and cuckoo filter commands
+    ("BF.ADD", "key item", "Add an item to a bloom filter"),
+    ("BF.EXISTS", "key item", "Check if an item may have been added"),
+    ("BF.INFO", "key", "Bloom filter information"),
+    ("BF.MADD", "key item [item ...]", "Add items to a bloom filter"),
+    ("BF.MEXISTS", "key item [item ...]", "Check if items may have been added"),
+    ("BF.RESERVE", "key error_rate capacity [EXPANSION expansion] [NONSCALING]", "Create a bloom filter"),
+    ("CF.ADD", "key item", "Add an item to a cuckoo filter"),
+    ("CF.ADDNX", "key item", "Add an item if it may not exist"),
+    ("CF.COUNT", "key item", "Count an item's copies"),
+    ("CF.DEL", "key item", "Delete one copy of an item"),
+    ("CF.EXISTS", "key item", "Check if an item may have been added"),
+    ("CF.INFO", "key", "Cuckoo filter information"),
+    ("CF.MEXISTS", "key item [item ...]", "Check if items may have been added"),
+    ("CF.RESERVE", "key capacity [BUCKETSIZE n] [MAXITERATIONS n] [EXPANSION n]", "Create a cuckoo filter"),
+    
+    // Vector commands
+    ("VECTOR.ADD", "key id x1,x2,... [field value ...]", "Add or replace a vector"),
+    ("VECTOR.CARD", "key", "Count vectors"),
+    ("VECTOR.DEL", "key id [id ...]", "Remove vectors"),
+    ("VECTOR.GET", "key id", "Get a vector and its metadata"),
+    ("VECTOR.SEARCH", "key x1,x2,... k [METRIC COSINE|L2] [FILTER field value] [WITHMETA]", "Nearest vectors"),
+    
+    // Priority queue commands
+    ("PQ.ACK", "key id [id ...]", "Acknowledge messages"),
+    ("PQ.POP", "key visibility-seconds [COUNT count]", "Lease messages"),
+    ("PQ.PUSH", "key priority payload [priority payload ...]", "Add messages"),
+    
+    // Time series commands
+    ("TS.ADD", "key timestamp|* value [RETENTION ms] [LABELS label value ...]", "Append a sample"),
+    ("TS.CREATE", "key [RETENTION ms] [LABELS label value ...]", "Create a series"),
+    ("TS.CREATERULE", "source dest AGGREGATION avg|min|max|sum|count bucket-ms", "Downsample into another series"),
+    ("TS.DELETERULE", "source dest", "Remove a downsampling rule"),
+    ("TS.GET", "key", "Get the last sample"),
+    ("TS.MRANGE", "from to [COUNT count] [AGGREGATION aggregator bucket-ms] [WITHLABELS] FILTER label=value ...", "Query series by label"),
+    ("TS.RANGE", "key from to [COUNT count] [AGGREGATION aggregator bucket-ms]", "Query samples"),
+    
     // Key commands
     ("COPY", "source destination [REPLACE]", "Copy key"),
     ("DEL", "key [key ...]", "Delete keys"),
+    ("DUMP", "key", "Serialize key value"),
     ("EXISTS", "key [key ...]", "Check key existence"),
-    ("EXPIRE", "key seconds", "Set key expiry"),
-    ("EXPIREAT", "key timestamp", "Set key expiry at timestamp"),
+    ("EXPIRE", "key seconds [NX|XX|GT|LT]", "Set key expiry"),
+    ("EXPIREAT", "key timestamp [NX|XX|GT|LT]", "Set key expiry at timestamp"),
+    ("EXPIRETIME", "key", "Get key expiry timestamp"),
     ("KEYS", "pattern", "Find keys matching pattern"),
+    ("MIGRATE", "host port key|\"\" db timeout [COPY] [REPLACE] [KEYS key [key ...]]", "Move keys to another instance"),
     ("PERSIST", "key", "Remove key expiry"),
-    ("PEXPIRE", "key milliseconds", "Set key expiry in ms"),
+    ("PEXPIRE", "key milliseconds [NX|XX|GT|LT]", "Set key expiry in ms"),
+    ("PEXPIREAT", "key ms-timestamp [NX|XX|GT|LT]", "Set key expiry at ms timestamp"),
+    ("PEXPIRETIME", "key", "Get key expiry timestamp in ms"),
     ("PTTL", "key", "Get key TTL in ms"),
     ("RANDOMKEY", "-", "Get random key"),
     ("RENAME", "key newkey", "Rename key"),
     ("RENAMENX", "key newkey", "Rename if new key doesn't exist"),
+    ("RESTORE", "key ttl payload [REPLACE] [ABSTTL]", "Create key from DUMP payload"),
     ("SCAN", "cursor [MATCH pattern] [COUNT count] [TYPE type]", "Scan keys"),
+    ("SORT", "key [BY pattern] [LIMIT offset count] [GET pattern ...] [ASC|DESC] [ALPHA] [STORE destination]", "Sort a list, set or sorted set"),
     ("TOUCH", "key [key ...]", "Touch keys"),
     ("TTL", "key", "Get key TTL"),
     ("TYPE", "key", "Get key type"),
@@ -141,16 +202,26 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     
     // Server commands
     ("AUTH", "password", "Authenticate"),
+    ("BACKUP", "TO dir", "Write a timestamped snapshot and AOF copy"),
     ("BGSAVE", "-", "Background save"),
-    ("CLIENT", "LIST|KILL|SETNAME [args]", "Client management"),
+    ("CLIENT", "LIST", "List connected clients"),
     ("CONFIG", "GET|SET parameter [value]", "Get/set config"),
     ("DBSIZE", "-", "Get number of keys"),
+    ("DBSTATS", "[FULL]", "Key counts per type, or a full memory and TTL breakdown"),
+    ("DUMPALL", "[RDB|AOF]", "Whole dataset as a hex-encoded RDB or AOF file"),
+    ("FCALL", "function numkeys key [key ...] arg [arg ...]", "Call a server-side function"),
+    ("FCALL_RO", "function numkeys key [key ...] arg [arg ...]", "Call a read-only server-side function"),
     ("FLUSHALL", "[ASYNC]", "Delete all keys"),
     ("FLUSHDB", "[ASYNC]", "Delete keys in current DB"),
+    ("FUNCTION", "LIST|STATS|LOAD|DELETE", "List, load or delete server-side functions"),
     ("INFO", "[section]", "Get server info"),
+    ("LATENCY", "LATEST|HISTORY|RESET|DOCTOR [event ...]", "Latency spike diagnostics"),
     ("PING", "[message]", "Test connection"),
     ("QUIT", "-", "Close connection"),
+    ("READONLY", "-", "Reject writes on this connection"),
+    ("READWRITE", "-", "Allow writes on this connection again"),
     ("SAVE", "-", "Synchronous save"),
+    ("SCHEDULE", "ADD name trigger command|DEL name|LIST", "Run commands on a schedule or when keys expire"),
     ("SELECT", "index", "Select database"),
     ("SHUTDOWN", "[NOSAVE|SAVE]", "Shutdown server"),
     ("SLOWLOG", "GET|LEN|RESET [count]", "Slow log management"),
@@ -166,8 +237,12 @@ pub static COMMANDS: &[(&str, &str, &str)] = &[
     // Pub/Sub commands
     ("PSUBSCRIBE", "pattern [pattern ...]", "Subscribe to patterns"),
     ("PUBLISH", "channel message", "Publish message"),
+    ("PUBSUB", "CHANNELS|NUMSUB|NUMPAT|SHARDCHANNELS|SHARDNUMSUB [arg ...]", "Inspect pub/sub state"),
     ("PUNSUBSCRIBE", "[pattern [pattern ...]]", "Unsubscribe from patterns"),
+    ("SPUBLISH", "shardchannel message", "Publish message to a shard channel"),
+    ("SSUBSCRIBE", "shardchannel [shardchannel ...]", "Subscribe to shard channels"),
     ("SUBSCRIBE", "channel [channel ...]", "Subscribe to channels"),
+    ("SUNSUBSCRIBE", "[shardchannel [shardchannel ...]]", "Unsubscribe from shard channels"),
     ("UNSUBSCRIBE", "[channel [channel ...]]", "Unsubscribe from channels"),
     
     // Replication commands
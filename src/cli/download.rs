@@ -0,0 +1,54 @@
+//! Dump Download
+//!
+//! `--rdb <file>` and `--aof <file>`: ask the server for the whole dataset
+//! with DUMPALL and write it to a local file, so a backup can be taken
+//! without access to the server's filesystem. The file arrives hex-encoded
+//! in one reply, so the CLI waits for it without a read timeout.
+
+use super::client::{RespClient, RespResponse};
+use super::colors::Colors;
+use super::config::CliArgs;
+use crate::persistence::dump::{decode_hex, DumpFormat};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Fetch a DUMPALL in `format` and write it to `path`
+pub fn run_download(mut client: RespClient, format: DumpFormat, path: &Path, args: &CliArgs) -> io::Result<()> {
+    let colors = Colors::new(!args.no_color);
+
+    // Authenticate if password provided
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
+        if response.is_error() {
+            eprintln!("Authentication failed");
+            std::process::exit(1);
+        }
+    }
+
+    let kind = match format {
+        DumpFormat::Rdb => "RDB",
+        DumpFormat::Aof => "AOF",
+    };
+    eprintln!("Requesting {} dump from {}...", kind, args.address());
+    client.set_read_timeout(None)?;
+    let payload = match client.send_command(&["DUMPALL", kind])? {
+        RespResponse::Bulk(payload) => payload,
+        RespResponse::Error(e) => return Err(io::Error::other(e)),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected DUMPALL reply")),
+    };
+    let bytes = decode_hex(&payload)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "DUMPALL reply is not hex"))?;
+
+    // Write next to the target and rename, so a failed transfer leaves no partial file
+    let temp = PathBuf::from(format!("{}.part", path.display()));
+    std::fs::write(&temp, &bytes)?;
+    std::fs::rename(&temp, path)?;
+    println!(
+        "{}Transferred {} bytes to {}{}",
+        colors.green(),
+        bytes.len(),
+        path.display(),
+        colors.reset()
+    );
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! Latency Probing
+//!
+//! `--latency` and `--latency-history`, as in redis-cli: PING the server in
+//! a loop and report round-trip times. A PING does no work on the server,
+//! so high numbers here point at the network or a busy event loop rather
+//! than at slow commands.
+
+use super::client::{RespClient, RespResponse};
+use super::config::CliArgs;
+use crate::benchmark::LatencyStats;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Pause between PINGs
+const PING_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often the `--latency` line is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default `--latency-history` window when `-i` is not given
+const DEFAULT_HISTORY_WINDOW: f64 = 15.0;
+
+/// One line of latency figures, in milliseconds
+pub fn latency_line(stats: &LatencyStats, samples: usize) -> String {
+    let ms = |us: u64| us as f64 / 1000.0;
+    format!(
+        "min: {:.2}, max: {:.2}, avg: {:.2}, p50: {:.2}, p99: {:.2} ms ({} samples)",
+        ms(stats.min()),
+        ms(stats.max()),
+        stats.mean() / 1000.0,
+        ms(stats.percentile(50.0)),
+        ms(stats.percentile(99.0)),
+        samples
+    )
+}
+
+/// PING until interrupted. With `history`, print one line per `-i`
+/// seconds window and start over; otherwise keep one line updated in place.
+pub fn run_latency(mut client: RespClient, history: bool, args: &CliArgs) -> io::Result<()> {
+    // Authenticate if password provided
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
+        if response.is_error() {
+            eprintln!("Authentication failed");
+            std::process::exit(1);
+        }
+    }
+
+    let window = Duration::from_secs_f64(if args.interval > 0.0 {
+        args.interval
+    } else {
+        DEFAULT_HISTORY_WINDOW
+    });
+    let mut stdout = io::stdout().lock();
+    let mut samples: Vec<u64> = Vec::new();
+    let mut window_start = Instant::now();
+    let mut last_redraw = Instant::now();
+
+    loop {
+        let start = Instant::now();
+        match client.send_command(&["PING"])? {
+            RespResponse::Error(e) => return Err(io::Error::other(e)),
+            _ => samples.push(start.elapsed().as_micros() as u64),
+        }
+
+        if history {
+            if window_start.elapsed() >= window {
+                let count = samples.len();
+                let stats = LatencyStats::new(std::mem::take(&mut samples));
+                writeln!(
+                    stdout,
+                    "{} -- {:.2} seconds range",
+                    latency_line(&stats, count),
+                    window_start.elapsed().as_secs_f64()
+                )?;
+                window_start = Instant::now();
+            }
+        } else if last_redraw.elapsed() >= REDRAW_INTERVAL {
+            let count = samples.len();
+            let stats = LatencyStats::new(samples.clone());
+            write!(stdout, "\r\x1b[K{}", latency_line(&stats, count))?;
+            stdout.flush()?;
+            last_redraw = Instant::now();
+        }
+
+        std::thread::sleep(PING_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_line() {
+        let stats = LatencyStats::new(vec![300, 100, 200, 1200]);
+        assert_eq!(
+            latency_line(&stats, 4),
+            "min: 0.10, max: 1.20, avg: 0.45, p50: 0.20, p99: 1.20 ms (4 samples)"
+        );
+    }
+}
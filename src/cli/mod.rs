@@ -2,16 +2,22 @@
 //!
 //! Modular CLI client for HexagonDB.
 
+pub mod analysis;
 pub mod client;
+pub mod cluster;
 pub mod colors;
 pub mod commands;
 pub mod completer;
 pub mod config;
+pub mod download;
 pub mod highlighter;
 pub mod hinter;
+pub mod latency;
+pub mod offline;
 pub mod output;
 pub mod parser;
 pub mod repl;
+pub mod validator;
 
 // Re-export main helper for editors
 pub use rustyline;
@@ -0,0 +1,63 @@
+//! Offline Analysis
+//!
+//! `aof-stats` and `rdb-stats`: summarize persistence files without a server.
+
+use super::colors::Colors;
+use super::config::OfflineCommand;
+use crate::persistence::stats::{aof_stats, rdb_stats, LargeRecord};
+use std::io;
+
+/// Run an offline subcommand and print its summary
+pub fn run_offline(cmd: &OfflineCommand, colors: &Colors) -> io::Result<()> {
+    match cmd {
+        OfflineCommand::AofStats { file } => {
+            let stats = aof_stats(file)?;
+            print_heading(&format!("AOF {}", file.display()), colors);
+            println!("  records: {}", stats.records);
+            println!("  bytes:   {}", stats.bytes);
+            println!("  keys:    {}", stats.keys);
+            println!();
+            print_heading("Commands", colors);
+            let mut commands: Vec<_> = stats.commands.iter().collect();
+            commands.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            for (name, count) in commands {
+                println!("  {:<20} {}", name, count);
+            }
+            println!();
+            print_largest("Largest records", &stats.largest, colors);
+        }
+        OfflineCommand::RdbStats { file } => {
+            let stats = rdb_stats(file)?;
+            print_heading(&format!("RDB {}", file.display()), colors);
+            println!("  keys:    {}", stats.keys);
+            println!("  expires: {}", stats.expires);
+            println!("  bytes:   {}", stats.bytes);
+            println!();
+            print_heading("Types", colors);
+            for (name, (keys, bytes)) in &stats.types {
+                println!("  {:<12} {:>10} keys {:>14} bytes", name, keys, bytes);
+            }
+            println!();
+            print_largest("Largest keys", &stats.largest, colors);
+        }
+    }
+    Ok(())
+}
+
+fn print_heading(title: &str, colors: &Colors) {
+    println!("{}{}{}:{}", colors.bold(), colors.green(), title, colors.reset());
+}
+
+fn print_largest(title: &str, largest: &[LargeRecord], colors: &Colors) {
+    print_heading(title, colors);
+    for record in largest {
+        println!(
+            "  {:>12} bytes  {:<12} {}{}{}",
+            record.bytes,
+            record.kind,
+            colors.cyan(),
+            record.key,
+            colors.reset()
+        );
+    }
+}
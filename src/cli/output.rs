@@ -1,10 +1,30 @@
 //! Output Formatting
 //!
-//! Formats RESP responses for display.
+//! Formats RESP responses for display: human-readable (the default), raw,
+//! or JSON and CSV for scripts.
 
 use super::client::RespResponse;
 use super::colors::Colors;
 
+/// How responses are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Raw,
+    Json,
+    Csv,
+}
+
+/// Format a response in the given format
+pub fn format_output(response: &RespResponse, format: OutputFormat, colors: &Colors) -> String {
+    match format {
+        OutputFormat::Human => format_response(response, colors),
+        OutputFormat::Raw => format_raw(response),
+        OutputFormat::Json => format_json(response),
+        OutputFormat::Csv => format_csv(response),
+    }
+}
+
 /// Format a RESP response for display
 pub fn format_response(response: &RespResponse, colors: &Colors) -> String {
     match response {
@@ -19,6 +39,13 @@ pub fn format_response(response: &RespResponse, colors: &Colors) -> String {
             format!("{}\"{}\"{}",  colors.green(), s, colors.reset())
         }
         RespResponse::Array(items) => format_array(items, colors, 0),
+        RespResponse::Map(pairs) => {
+            let items: Vec<RespResponse> = pairs
+                .iter()
+                .flat_map(|(k, v)| [k.clone(), v.clone()])
+                .collect();
+            format_array(&items, colors, 0)
+        }
         RespResponse::Null => {
             format!("{}(nil){}", colors.yellow(), colors.reset())
         }
@@ -36,6 +63,7 @@ fn format_array(items: &[RespResponse], colors: &Colors, indent: usize) -> Strin
     for (i, item) in items.iter().enumerate() {
         let formatted = match item {
             RespResponse::Array(nested) => format_array(nested, colors, indent + 3),
+            RespResponse::Map(_) => format_response(item, colors),
             other => format_response(other, colors),
         };
         result.push_str(&format!("{}{}) {}\n", prefix, i + 1, formatted));
@@ -57,6 +85,125 @@ pub fn format_raw(response: &RespResponse) -> String {
                 .collect::<Vec<_>>()
                 .join("\n")
         }
+        RespResponse::Map(pairs) => {
+            pairs.iter()
+                .flat_map(|(k, v)| [format_raw(k), format_raw(v)])
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
         RespResponse::Null => String::new(),
     }
 }
+
+/// Format as a single line of JSON: arrays become arrays, maps objects,
+/// nil `null` and errors `{"error": "..."}`
+pub fn format_json(response: &RespResponse) -> String {
+    match response {
+        RespResponse::Simple(s) | RespResponse::Bulk(s) => json_string(s),
+        RespResponse::Error(s) => format!("{{\"error\":{}}}", json_string(s)),
+        RespResponse::Integer(n) => n.to_string(),
+        RespResponse::Array(items) => {
+            format!("[{}]", items.iter().map(format_json).collect::<Vec<_>>().join(","))
+        }
+        RespResponse::Map(pairs) => {
+            let fields: Vec<String> = pairs
+                .iter()
+                .map(|(k, v)| {
+                    // Object keys must be strings
+                    let key = match k {
+                        RespResponse::Simple(s) | RespResponse::Bulk(s) => json_string(s),
+                        other => json_string(&format_json(other)),
+                    };
+                    format!("{}:{}", key, format_json(v))
+                })
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        RespResponse::Null => "null".to_string(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Format as one CSV row: nested arrays and maps are flattened, strings
+/// quoted with `""` escaping, nil is `NULL` and errors `ERROR,"..."`
+pub fn format_csv(response: &RespResponse) -> String {
+    let mut fields = Vec::new();
+    csv_fields(response, &mut fields);
+    fields.join(",")
+}
+
+fn csv_fields(response: &RespResponse, fields: &mut Vec<String>) {
+    match response {
+        RespResponse::Simple(s) | RespResponse::Bulk(s) => fields.push(csv_string(s)),
+        RespResponse::Error(s) => {
+            fields.push("ERROR".to_string());
+            fields.push(csv_string(s));
+        }
+        RespResponse::Integer(n) => fields.push(n.to_string()),
+        RespResponse::Array(items) => items.iter().for_each(|item| csv_fields(item, fields)),
+        RespResponse::Map(pairs) => pairs.iter().for_each(|(k, v)| {
+            csv_fields(k, fields);
+            csv_fields(v, fields);
+        }),
+        RespResponse::Null => fields.push("NULL".to_string()),
+    }
+}
+
+fn csv_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> RespResponse {
+        RespResponse::Bulk(s.to_string())
+    }
+
+    #[test]
+    fn test_format_json() {
+        let response = RespResponse::Array(vec![
+            bulk("a \"quoted\"\nline"),
+            RespResponse::Integer(-3),
+            RespResponse::Null,
+            RespResponse::Array(vec![]),
+            RespResponse::Map(vec![(bulk("k"), bulk("v")), (RespResponse::Integer(1), RespResponse::Simple("OK".to_string()))]),
+        ]);
+        assert_eq!(
+            format_json(&response),
+            r#"["a \"quoted\"\nline",-3,null,[],{"k":"v","1":"OK"}]"#
+        );
+        assert_eq!(format_json(&RespResponse::Error("ERR no".to_string())), r#"{"error":"ERR no"}"#);
+        assert_eq!(format_json(&bulk("\u{1}")), r#""\u0001""#);
+    }
+
+    #[test]
+    fn test_format_csv() {
+        let response = RespResponse::Array(vec![
+            bulk("say \"hi\", bye"),
+            RespResponse::Integer(7),
+            RespResponse::Array(vec![bulk("x"), RespResponse::Null]),
+        ]);
+        assert_eq!(format_csv(&response), r#""say ""hi"", bye",7,"x",NULL"#);
+        assert_eq!(format_csv(&RespResponse::Error("ERR no".to_string())), r#"ERROR,"ERR no""#);
+        assert_eq!(format_csv(&RespResponse::Array(vec![])), "");
+    }
+}
@@ -2,22 +2,25 @@
 //!
 //! Interactive shell with vim mode, auto-complete, and hints.
 
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use rustyline::error::ReadlineError;
 use rustyline::history::DefaultHistory;
-use rustyline::validate::MatchingBracketValidator;
 use rustyline::{Completer, Editor, Helper, Highlighter, Hinter, Validator, Config, EditMode};
 
-use super::client::RespClient;
+use super::client::{RespClient, RespResponse};
+use super::cluster::ClusterRouter;
 use super::colors::Colors;
 use super::commands::{clear_screen, print_help};
 use super::completer::{CommandCompleter, get_command_help, COMMANDS};
 use super::config::CliArgs;
 use super::highlighter::CommandHighlighter;
 use super::hinter::CommandHinter;
-use super::output::{format_raw, format_response};
+use super::output::{format_output, OutputFormat};
 use super::parser::parse_command;
+use super::validator::{join_continuations, CommandValidator};
 
 /// Combined helper for rustyline
 #[derive(Completer, Helper, Highlighter, Hinter, Validator)]
@@ -29,7 +32,7 @@ pub struct CliHelper {
     #[rustyline(Hinter)]
     hinter: CommandHinter,
     #[rustyline(Validator)]
-    validator: MatchingBracketValidator,
+    validator: CommandValidator,
 }
 
 impl CliHelper {
@@ -38,19 +41,26 @@ impl CliHelper {
             completer: CommandCompleter,
             highlighter: CommandHighlighter { enabled: colors_enabled },
             hinter: CommandHinter,
-            validator: MatchingBracketValidator::new(),
+            validator: CommandValidator::new(),
         }
     }
 }
 
+/// How often streaming mode checks for Ctrl+C while no message arrives
+const PUSH_POLL: Duration = Duration::from_millis(100);
+
+/// Set by the SIGINT handler while streaming
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 /// Get history file path
 fn history_path() -> Option<std::path::PathBuf> {
-    dirs::data_dir().map(|p| p.join("hexagondb").join("cli_history"))
+    crate::persistence::paths::platform_data_dir().map(|p| p.join("cli_history"))
 }
 
 /// Run interactive REPL with vim mode
 pub fn run_interactive(mut client: RespClient, args: &CliArgs) -> io::Result<()> {
     let colors = Colors::new(!args.no_color);
+    let mut router = args.cluster.then(|| ClusterRouter::new(args));
 
     // Configure rustyline
     let config = Config::builder()
@@ -74,8 +84,8 @@ pub fn run_interactive(mut client: RespClient, args: &CliArgs) -> io::Result<()>
     }
 
     // Authenticate if password provided
-    if let Some(ref password) = args.password {
-        let response = client.send_command(&["AUTH", password])?;
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
         if response.is_error() {
             println!(
                 "{}Authentication failed: {:?}{}",
@@ -89,11 +99,13 @@ pub fn run_interactive(mut client: RespClient, args: &CliArgs) -> io::Result<()>
     }
 
     // Main REPL loop
-    let prompt = format!("{}:{} > ", args.host, args.port);
+    let mut transaction = Transaction::default();
 
     loop {
+        let prompt = transaction.prompt(&args.address());
         match rl.readline(&prompt) {
             Ok(line) => {
+                let line = join_continuations(&line);
                 let input = line.trim();
                 if input.is_empty() {
                     continue;
@@ -138,20 +150,40 @@ pub fn run_interactive(mut client: RespClient, args: &CliArgs) -> io::Result<()>
 
                 let refs: Vec<&str> = parts.iter().map(|s| s.as_str()).collect();
 
-                match client.send_command(&refs) {
+                // Inside MULTI a SUBSCRIBE is only queued
+                if is_subscribe(refs[0]) && !transaction.is_open() {
+                    // The connection stays subscribed after Ctrl+C, so start over
+                    if let Err(e) = stream_messages(&mut client, &refs, args, &colors) {
+                        println!("{}Error: {}{}", colors.red(), e, colors.reset());
+                    }
+                    match reconnect(args) {
+                        Ok(new_client) => client = new_client,
+                        Err(e) => {
+                            println!("{}Failed: {}{}", colors.red(), e, colors.reset());
+                            break;
+                        }
+                    }
+                    continue;
+                }
+
+                match send(&mut client, &mut router, &refs, args, &colors) {
                     Ok(response) => {
-                        let output = if args.raw {
-                            format_raw(&response)
-                        } else {
-                            format_response(&response, &colors)
-                        };
-                        println!("{}", output);
+                        transaction.observe(refs[0], &response);
+                        println!("{}", format_output(&response, args.output_format(), &colors));
                     }
                     Err(e) => {
                         println!("{}Error: {}{}", colors.red(), e, colors.reset());
+                        if let Some(queued) = transaction.abort() {
+                            println!(
+                                "{}Connection lost inside MULTI: the transaction and its {} queued command(s) were discarded{}",
+                                colors.yellow(),
+                                queued,
+                                colors.reset()
+                            );
+                        }
                         // Try to reconnect
                         println!("{}Reconnecting...{}", colors.yellow(), colors.reset());
-                        match RespClient::connect(&args.host, args.port, args.timeout) {
+                        match reconnect(args) {
                             Ok(new_client) => {
                                 client = new_client;
                                 println!("{}OK{}", colors.green(), colors.reset());
@@ -193,6 +225,151 @@ pub fn run_interactive(mut client: RespClient, args: &CliArgs) -> io::Result<()>
     Ok(())
 }
 
+/// MULTI state of the interactive connection, shown in the prompt
+#[derive(Debug, Default)]
+struct Transaction {
+    /// Commands queued since MULTI, `None` outside a transaction
+    queued: Option<usize>,
+}
+
+impl Transaction {
+    fn is_open(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    /// Track MULTI/EXEC/DISCARD and queued commands from a reply
+    fn observe(&mut self, command: &str, reply: &RespResponse) {
+        match command.to_uppercase().as_str() {
+            "MULTI" if !reply.is_error() => self.queued = Some(0),
+            // EXEC ends the transaction even when it was aborted (EXECABORT)
+            "EXEC" | "DISCARD" | "RESET" => self.queued = None,
+            _ => {
+                if let (Some(queued), RespResponse::Simple(s)) = (&mut self.queued, reply) {
+                    if s == "QUEUED" {
+                        *queued += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Forget an open transaction, returning how many commands it had
+    fn abort(&mut self) -> Option<usize> {
+        self.queued.take()
+    }
+
+    fn prompt(&self, address: &str) -> String {
+        match self.queued {
+            Some(queued) => format!("{}(TX {}) > ", address, queued),
+            None => format!("{} > ", address),
+        }
+    }
+}
+
+/// Send a command, through the cluster router in --cluster mode. In
+/// verbose mode the node that answered is printed first.
+fn send(
+    client: &mut RespClient,
+    router: &mut Option<ClusterRouter>,
+    parts: &[&str],
+    args: &CliArgs,
+    colors: &Colors,
+) -> io::Result<RespResponse> {
+    let Some(router) = router else {
+        return client.send_command(parts);
+    };
+    let (reply, node) = router.send_command(client, parts)?;
+    if args.verbose {
+        println!("{}-> {}{}", colors.yellow(), node, colors.reset());
+    }
+    Ok(reply)
+}
+
+/// Commands that switch the connection into subscribe mode
+fn is_subscribe(command: &str) -> bool {
+    ["SUBSCRIBE", "PSUBSCRIBE", "SSUBSCRIBE"].iter().any(|c| command.eq_ignore_ascii_case(c))
+}
+
+/// Open a fresh connection and authenticate it
+fn reconnect(args: &CliArgs) -> io::Result<RespClient> {
+    let mut client = RespClient::connect(&args.host, args.port, args.timeout)?;
+    if let Some(auth) = args.auth_command() {
+        if let RespResponse::Error(e) = client.send_command(&auth)? {
+            return Err(io::Error::other(e));
+        }
+    }
+    Ok(client)
+}
+
+/// Send a SUBSCRIBE/PSUBSCRIBE/SSUBSCRIBE and print every pushed message as it
+/// arrives, until Ctrl+C or the server closes the connection. The
+/// connection is left subscribed.
+fn stream_messages(
+    client: &mut RespClient,
+    parts: &[&str],
+    args: &CliArgs,
+    colors: &Colors,
+) -> io::Result<()> {
+    client.write_command(parts)?;
+    let first = client.read_response()?;
+    println!("{}", format_output(&first, args.output_format(), colors));
+    if first.is_error() {
+        return Ok(());
+    }
+    if args.output_format() == OutputFormat::Human {
+        println!(
+            "{}Reading messages... (press Ctrl+C to quit){}",
+            colors.yellow(),
+            colors.reset()
+        );
+    }
+
+    let _sigint = SigintGuard::install();
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some(message) = client.read_push(PUSH_POLL)? {
+            println!("{}", format_output(&message, args.output_format(), colors));
+        }
+    }
+}
+
+/// Turns Ctrl+C into a flag for the streaming loop while it is alive.
+/// Elsewhere than unix Ctrl+C keeps its default behaviour and ends the process.
+struct SigintGuard {
+    #[cfg(unix)]
+    previous: libc::sighandler_t,
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+impl SigintGuard {
+    fn install() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        SigintGuard {
+            // SAFETY: the handler only stores to an atomic
+            #[cfg(unix)]
+            previous: unsafe {
+                libc::signal(libc::SIGINT, on_sigint as *const () as libc::sighandler_t)
+            },
+        }
+    }
+}
+
+impl Drop for SigintGuard {
+    fn drop(&mut self) {
+        // SAFETY: reinstalls the handler that was replaced in install()
+        #[cfg(unix)]
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+    }
+}
+
 /// Handle vim-like hidden commands
 fn handle_vim_command(input: &str, colors: &Colors) -> bool {
     let cmd = &input[1..]; // Remove leading :
@@ -255,6 +432,7 @@ fn print_vim_help(colors: &Colors) {
     println!("  ESC           - Normal mode");
     println!("  Ctrl+R        - Search history");
     println!("  Tab           - Auto-complete");
+    println!("  \\ at line end - Continue on the next line");
     println!("  Ctrl+C        - Cancel input");
     println!("  Ctrl+D        - Exit");
 }
@@ -322,10 +500,11 @@ fn search_commands(query: &str, colors: &Colors) {
 /// Run a single command
 pub fn run_command(mut client: RespClient, command: &str, args: &CliArgs) -> io::Result<()> {
     let colors = Colors::new(!args.no_color);
+    let mut router = args.cluster.then(|| ClusterRouter::new(args));
 
     // Authenticate if password provided
-    if let Some(ref password) = args.password {
-        let response = client.send_command(&["AUTH", password])?;
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
         if response.is_error() {
             eprintln!("Authentication failed");
             std::process::exit(1);
@@ -344,14 +523,17 @@ pub fn run_command(mut client: RespClient, command: &str, args: &CliArgs) -> io:
 
         let refs: Vec<&str> = parts.iter().map(|s| s.as_str()).collect();
 
-        match client.send_command(&refs) {
+        if is_subscribe(refs[0]) {
+            if let Err(e) = stream_messages(&mut client, &refs, args, &colors) {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+            break;
+        }
+
+        match send(&mut client, &mut router, &refs, args, &colors) {
             Ok(response) => {
-                let output = if args.raw {
-                    format_raw(&response)
-                } else {
-                    format_response(&response, &colors)
-                };
-                println!("{}", output);
+                println!("{}", format_output(&response, args.output_format(), &colors));
             }
             Err(e) => {
                 eprintln!("Error: {}", e);
@@ -363,13 +545,65 @@ pub fn run_command(mut client: RespClient, command: &str, args: &CliArgs) -> io:
     Ok(())
 }
 
+/// List keys with SCAN, one per line, following the cursor until it
+/// returns to 0. Keys are printed raw so the output can go to xargs.
+pub fn run_scan(mut client: RespClient, args: &CliArgs) -> io::Result<()> {
+    // Authenticate if password provided
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
+        if response.is_error() {
+            eprintln!("Authentication failed");
+            std::process::exit(1);
+        }
+    }
+
+    let count = args.count.map(|c| c.to_string());
+    let mut stdout = io::stdout().lock();
+    let mut cursor = "0".to_string();
+    loop {
+        let mut parts = vec!["SCAN", cursor.as_str()];
+        if let Some(ref pattern) = args.pattern {
+            parts.extend(["MATCH", pattern.as_str()]);
+        }
+        if let Some(ref count) = count {
+            parts.extend(["COUNT", count.as_str()]);
+        }
+        let (next, keys) = match client.send_command(&parts)? {
+            RespResponse::Array(reply) => match reply.as_slice() {
+                [RespResponse::Bulk(next), RespResponse::Array(keys)] => (next.clone(), keys.clone()),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected SCAN reply")),
+            },
+            RespResponse::Error(e) => return Err(io::Error::other(e)),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected SCAN reply")),
+        };
+        for key in keys {
+            if let RespResponse::Bulk(key) = key {
+                match writeln!(stdout, "{}", key) {
+                    Ok(()) => {}
+                    // Reader went away (`| head`)
+                    Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if next == "0" {
+            return Ok(());
+        }
+        cursor = next;
+        if args.interval > 0.0 {
+            std::thread::sleep(std::time::Duration::from_secs_f64(args.interval));
+        }
+    }
+}
+
 /// Run in pipe mode (read commands from stdin)
 pub fn run_pipe(mut client: RespClient, args: &CliArgs) -> io::Result<()> {
     let colors = Colors::new(!args.no_color);
+    let mut router = args.cluster.then(|| ClusterRouter::new(args));
 
     // Authenticate if password provided
-    if let Some(ref password) = args.password {
-        let response = client.send_command(&["AUTH", password])?;
+    if let Some(auth) = args.auth_command() {
+        let response = client.send_command(&auth)?;
         if response.is_error() {
             eprintln!("Authentication failed");
             std::process::exit(1);
@@ -392,17 +626,20 @@ pub fn run_pipe(mut client: RespClient, args: &CliArgs) -> io::Result<()> {
 
         let refs: Vec<&str> = parts.iter().map(|s| s.as_str()).collect();
 
-        match client.send_command(&refs) {
+        if is_subscribe(refs[0]) {
+            // Nothing but pub/sub commands can follow on this connection
+            if args.verbose {
+                println!("> {}", line);
+            }
+            return stream_messages(&mut client, &refs, args, &colors);
+        }
+
+        match send(&mut client, &mut router, &refs, args, &colors) {
             Ok(response) => {
                 if args.verbose {
                     println!("> {}", line);
                 }
-                let output = if args.raw {
-                    format_raw(&response)
-                } else {
-                    format_response(&response, &colors)
-                };
-                println!("{}", output);
+                println!("{}", format_output(&response, args.output_format(), &colors));
             }
             Err(e) => {
                 eprintln!("Error: {}", e);
@@ -412,3 +649,35 @@ pub fn run_pipe(mut client: RespClient, args: &CliArgs) -> io::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_prompt() {
+        let queued = RespResponse::Simple("QUEUED".to_string());
+        let mut tx = Transaction::default();
+        assert_eq!(tx.prompt("127.0.0.1:6379"), "127.0.0.1:6379 > ");
+
+        tx.observe("multi", &RespResponse::Simple("OK".to_string()));
+        tx.observe("SET", &queued);
+        tx.observe("INCR", &queued);
+        tx.observe("NOSUCH", &RespResponse::Error("ERR unknown command".to_string()));
+        assert_eq!(tx.prompt("127.0.0.1:6379"), "127.0.0.1:6379(TX 2) > ");
+
+        tx.observe("EXEC", &RespResponse::Array(vec![]));
+        assert!(!tx.is_open());
+
+        tx.observe("MULTI", &RespResponse::Simple("OK".to_string()));
+        tx.observe("SET", &queued);
+        assert_eq!(tx.abort(), Some(1));
+        assert_eq!(tx.abort(), None);
+
+        // Nested MULTI is an error and keeps the count
+        tx.observe("MULTI", &RespResponse::Simple("OK".to_string()));
+        tx.observe("SET", &queued);
+        tx.observe("MULTI", &RespResponse::Error("ERR MULTI calls can not be nested".to_string()));
+        assert_eq!(tx.queued, Some(1));
+    }
+}
@@ -0,0 +1,74 @@
+//! Line Continuation
+//!
+//! A line ending in an unescaped backslash continues on the next line, so
+//! long commands can be typed over several lines. As in a shell, the
+//! backslash and the line break are removed when the lines are joined.
+
+use rustyline::validate::{MatchingBracketValidator, ValidationContext, ValidationResult, Validator};
+use rustyline::Result;
+
+/// Validator that waits for more input after a trailing backslash
+pub struct CommandValidator {
+    brackets: MatchingBracketValidator,
+}
+
+impl CommandValidator {
+    pub fn new() -> Self {
+        CommandValidator {
+            brackets: MatchingBracketValidator::new(),
+        }
+    }
+}
+
+impl Default for CommandValidator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Validator for CommandValidator {
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
+        if continues(ctx.input()) {
+            return Ok(ValidationResult::Incomplete);
+        }
+        self.brackets.validate(ctx)
+    }
+}
+
+/// Check if a line ends in a backslash that is not itself escaped
+pub fn continues(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// Join continued lines into one command line
+pub fn join_continuations(input: &str) -> String {
+    let mut joined = String::with_capacity(input.len());
+    let mut lines = input.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        if lines.peek().is_none() {
+            joined.push_str(line);
+        } else if continues(line) {
+            joined.push_str(&line[..line.len() - 1]);
+        } else {
+            joined.push_str(line);
+            joined.push('\n');
+        }
+    }
+    joined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_continuation() {
+        assert!(continues("SET key \\"));
+        assert!(!continues("SET key \\\\"));
+        assert!(!continues("SET key value"));
+
+        assert_eq!(join_continuations("SET key \\\nvalue"), "SET key value");
+        assert_eq!(join_continuations("SET key \"a\\\nb\""), "SET key \"ab\"");
+        assert_eq!(join_continuations("ECHO \\\\\nx"), "ECHO \\\\\nx");
+    }
+}
@@ -1,11 +1,35 @@
-use crate::config::Config;
-use crate::db::pubsub::PubSub;
-use crate::db::DB;
-use crate::db::{GenericOps, HashOps, ListOps, SetOps, StringOps, ZSetOps, BitmapOps, StreamOps, GeoOps, HyperLogLogOps};
+use crate::access_log::AccessLog;
+use crate::analyze::{self, AnalyzeJobs, GroupStats};
+use crate::blocking::{self, Blocked};
+use crate::bulk_ttl::{BulkTtlAction, BulkTtlJobs};
+use crate::config::{CapPolicy, Config};
+use crate::db::pubsub::{self, PubSub};
+use crate::failpoint::{self, FailAction};
+use crate::flush::FlushConfirmations;
+use crate::functions::{self, FunctionContext};
+use crate::latency;
+use crate::db::{introspect, DB};
+use crate::db::ops::generic::{instant_from_unix_millis, unix_millis_from_instant, unix_millis_now, ExpireCondition};
+use crate::db::ops::hash::FieldTtlArgs;
+use crate::db::ops::string::CounterBounds;
+use crate::db::ops::bloom::BloomOptions;
+use crate::db::ops::cuckoo::CuckooOptions;
+use crate::db::ops::timeseries::{LabelFilter, RangeQuery, SeriesOptions};
+use crate::db::ops::vector::{parse_vector, VectorQuery};
+use crate::db::types::CompactionRule;
+use crate::db::ops::zset::ZAggregate;
+use crate::db::{GenericOps, HashOps, ListOps, SetOps, StringOps, ZSetOps, BitmapOps, StreamEntries, StreamOps, StreamTrim, GeoOps, HyperLogLogOps, QueueOps, SortOps, SortOptions, TimeSeriesOps, BloomOps, CuckooOps, VectorOps};
+use crate::network::migrate;
 use crate::network::resp::RespValue;
 use crate::observability::metrics::{METRIC_COMMANDS_TOTAL, METRIC_COMMAND_LATENCY};
 use crate::persistence::aof::Aof;
-use crate::server_info::ServerInfo;
+use crate::persistence::dump::{self, DumpFormat};
+use crate::persistence::{backup, bgsave, loading, paths, relocate};
+use crate::scheduler;
+use crate::security::{self, Security, DEFAULT_USER};
+use crate::server_info::{DatasetStats, ServerInfo};
+use crate::slowlog::SlowLog;
+use crate::subscription::Subscriber;
 use metrics::{counter, histogram};
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -17,16 +41,38 @@ pub struct Interpreter {
     db: Arc<RwLock<DB>>,
     aof: Arc<RwLock<Aof>>,
     server_info: Arc<ServerInfo>,
-    #[allow(dead_code)]
     config: Arc<RwLock<Config>>,
     pubsub: Arc<PubSub>,
+    /// Remote address of the connected client (if known)
+    client_addr: Option<String>,
+    /// Sampled key access log (if enabled)
+    access_log: Option<Arc<AccessLog>>,
+    /// Background EXPIREMANY jobs
+    bulk_ttl_jobs: Arc<BulkTtlJobs>,
+    /// Background ANALYZE jobs
+    analyze_jobs: Arc<AnalyzeJobs>,
+    /// Flushes waiting for FLUSHALL CONFIRM
+    flush_confirmations: Arc<FlushConfirmations>,
+    /// Commands slower than the configured threshold
+    slowlog: Arc<SlowLog>,
+    /// Authentication, ACLs and IP filtering
+    security: Arc<Security>,
+    /// User this connection is logged in as (None until AUTH succeeds)
+    user: Option<String>,
+    /// Set by READONLY: write commands are rejected until READWRITE
+    read_only: bool,
 }
 
-use tokio::sync::broadcast;
+/// Reply to a write on a read-only connection or instance
+pub const READONLY_ERROR: &str = "READONLY You can't write against a read only replica.";
 
 pub enum ExecutionResult {
     Response(RespValue),
-    Subscribe(String, broadcast::Receiver<String>),
+    /// The client entered subscriber mode: its subscriptions and the
+    /// confirmations (and retained messages) to send first
+    Subscribe(Subscriber, Vec<RespValue>),
+    /// A blocking pop found nothing; the connection waits and retries
+    Block(Blocked),
 }
 
 struct LatencyGuard {
@@ -56,12 +102,982 @@ impl Interpreter {
             server_info,
             config,
             pubsub,
+            client_addr: None,
+            access_log: None,
+            bulk_ttl_jobs: Arc::new(BulkTtlJobs::new()),
+            analyze_jobs: Arc::new(AnalyzeJobs::new()),
+            flush_confirmations: Arc::new(FlushConfirmations::new()),
+            slowlog: Arc::new(SlowLog::new()),
+            security: Arc::new(Security::new()),
+            user: None,
+            read_only: false,
         }
     }
 
+    /// Set the remote address of the client served by this interpreter
+    pub fn with_client_addr(mut self, addr: impl Into<String>) -> Self {
+        self.client_addr = Some(addr.into());
+        self
+    }
+
+    /// Remote address of the client, if known
+    pub fn client_addr(&self) -> Option<&str> {
+        self.client_addr.as_deref()
+    }
+
+    /// Enable sampled access logging
+    pub fn with_access_log(mut self, access_log: Option<Arc<AccessLog>>) -> Self {
+        self.access_log = access_log;
+        self
+    }
+
+    /// Share the EXPIREMANY job registry with other connections
+    pub fn with_bulk_ttl_jobs(mut self, jobs: Arc<BulkTtlJobs>) -> Self {
+        self.bulk_ttl_jobs = jobs;
+        self
+    }
+
+    /// Share the ANALYZE job registry with other connections
+    pub fn with_analyze_jobs(mut self, jobs: Arc<AnalyzeJobs>) -> Self {
+        self.analyze_jobs = jobs;
+        self
+    }
+
+    /// Share the pending flush confirmation with other connections
+    pub fn with_flush_confirmations(mut self, flushes: Arc<FlushConfirmations>) -> Self {
+        self.flush_confirmations = flushes;
+        self
+    }
+
+    /// Return the connection to its initial state: log out, so it acts as
+    /// the default user again if that needs no password, and leave
+    /// read-only mode (RESET)
+    pub fn reset(&mut self) {
+        self.user = None;
+        self.read_only = false;
+    }
+
+    /// Whether a command would write to the keyspace or the schedule
+    fn writes(cmd_upper: &str, args: &[String]) -> bool {
+        match cmd_upper {
+            "FCALL" => args
+                .first()
+                .and_then(|name| functions::registry().get(name))
+                .is_some_and(|function| !function.read_only()),
+            "SCHEDULE" => args.first().is_some_and(|sub| !sub.eq_ignore_ascii_case("LIST")),
+            _ => security::is_write_command(cmd_upper),
+        }
+    }
+
+    /// Share the slow log with other connections
+    pub fn with_slowlog(mut self, slowlog: Arc<SlowLog>) -> Self {
+        self.slowlog = slowlog;
+        self
+    }
+
+    /// Act as `user` (None = the default user), returning the previous one.
+    /// Used to run scheduled jobs as the user who added them.
+    pub fn set_user(&mut self, user: Option<String>) -> Option<String> {
+        std::mem::replace(&mut self.user, user)
+    }
+
+    /// Share the security manager (password, ACL users) with other connections
+    pub fn with_security(mut self, security: Arc<Security>) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Güvenlik yöneticisi (bağlantı başına hız sınırı için)
+    pub fn security(&self) -> &Arc<Security> {
+        &self.security
+    }
+
+    /// Sunucu istatistikleri (bağlantı ve ağ sayaçları için)
+    pub fn server_info(&self) -> &Arc<ServerInfo> {
+        &self.server_info
+    }
+
     /// İstemciden gelen komutu işler ve cevabı döndürür.
-    #[tracing::instrument(skip(self, request), fields(cmd, key))]
     pub async fn execute(&mut self, request: RespValue) -> ExecutionResult {
+        let new_keys = self.default_ttl_candidates(&request).await;
+        let slow_command = self.slowlog.is_enabled().then(|| request_args(&request));
+        let logged_command = self.access_log.is_some().then(|| request_args(&request));
+        let start = std::time::Instant::now();
+        let result = self.execute_command(request).await;
+        if let Some(command) = logged_command {
+            if matches!(&result, ExecutionResult::Response(reply) if !matches!(reply, RespValue::Error(_))) {
+                self.record_access(&command).await;
+            }
+        }
+        self.server_info.increment_commands();
+        if !new_keys.is_empty() {
+            self.apply_default_ttl(new_keys).await;
+        }
+        latency::record(latency::EVENT_COMMAND, start.elapsed());
+        if let Some(command) = slow_command {
+            let client_addr = self.client_addr.clone().unwrap_or_default();
+            self.slowlog.log_if_slow(start, command, client_addr, None);
+        }
+        result
+    }
+
+    /// Sample a successful keyed command into the access log
+    async fn record_access(&self, command: &[String]) {
+        let (Some(log), Some((name, args))) = (&self.access_log, command.split_first()) else {
+            return;
+        };
+        let cmd_upper = name.to_uppercase();
+        if let Some(key) = command_keys(&cmd_upper, args).first() {
+            if log.should_sample(key) {
+                let mut db = self.db.write().await;
+                log.record(&mut db, &cmd_upper, key, self.client_addr.as_deref());
+            }
+        }
+    }
+
+    /// LMPOP numkeys key [key ...] LEFT|RIGHT [COUNT count] and
+    /// ZMPOP numkeys key [key ...] MIN|MAX [COUNT count]: pop from the first
+    /// non-empty key. The reply is the key and what was popped, or nil when
+    /// every key is empty.
+    async fn mpop(&self, cmd_upper: &str, args: &[String]) -> RespValue {
+        let wrong_args = || RespValue::Error(format!("wrong number of arguments for '{}' command", cmd_upper));
+        let syntax_error = || RespValue::Error("syntax error".to_string());
+        let numkeys = match args.first().map(|n| n.parse::<usize>()) {
+            None => return wrong_args(),
+            Some(Ok(n)) if n > 0 => n,
+            Some(_) => return RespValue::Error("numkeys should be greater than 0".to_string()),
+        };
+        let keys = match args.get(1..1 + numkeys) {
+            Some(keys) => keys,
+            None => return wrong_args(),
+        };
+        let (direction, count) = match &args[1 + numkeys..] {
+            [direction] => (direction, 1),
+            [direction, option, count] if option.eq_ignore_ascii_case("COUNT") => match count.parse::<usize>() {
+                Ok(count) if count > 0 => (direction, count),
+                _ => return RespValue::Error("count should be greater than 0".to_string()),
+            },
+            [] => return wrong_args(),
+            _ => return syntax_error(),
+        };
+        let from_head = match (cmd_upper, direction.to_uppercase().as_str()) {
+            ("LMPOP", "LEFT") | ("ZMPOP", "MIN") => true,
+            ("LMPOP", "RIGHT") | ("ZMPOP", "MAX") => false,
+            _ => return syntax_error(),
+        };
+
+        let bulk = |s: String| RespValue::BulkString(Some(s));
+        let mut db = self.db.write().await;
+        for key in keys {
+            // Logged as the single-key pop it amounts to
+            let (logged, popped) = if cmd_upper == "LMPOP" {
+                let result = if from_head {
+                    db.lpop_count(key.clone(), count)
+                } else {
+                    db.rpop_count(key.clone(), count)
+                };
+                let values = match result {
+                    Ok(values) if values.is_empty() => continue,
+                    Ok(values) => values,
+                    Err(e) => return e.into(),
+                };
+                let pop = if from_head { "LPOP" } else { "RPOP" };
+                let logged = vec![pop.to_string(), key.clone(), values.len().to_string()];
+                (logged, values.into_iter().map(bulk).collect())
+            } else {
+                match db.type_of(key).as_deref() {
+                    Some("zset") => {}
+                    Some(_) => return crate::db::error::DbError::WrongType.into(),
+                    None => continue,
+                }
+                let members = if from_head {
+                    db.zpopmin(key.clone(), Some(count))
+                } else {
+                    db.zpopmax(key.clone(), Some(count))
+                };
+                if members.is_empty() {
+                    continue;
+                }
+                let mut logged = vec!["ZREM".to_string(), key.clone()];
+                logged.extend(members.iter().map(|(member, _)| member.clone()));
+                let pairs = members
+                    .into_iter()
+                    .map(|(member, score)| RespValue::Array(Some(vec![bulk(member), bulk(score.to_string())])))
+                    .collect();
+                (logged, pairs)
+            };
+            if let Err(e) = self.aof.write().await.append(logged) {
+                error!("AOF write error: {}", e);
+            }
+            return RespValue::Array(Some(vec![bulk(key.clone()), RespValue::Array(Some(popped))]));
+        }
+        RespValue::Array(None)
+    }
+
+    /// FCALL / FCALL_RO name numkeys key [key ...] arg [arg ...]
+    async fn fcall(&self, cmd_upper: &str, args: &[String]) -> RespValue {
+        let (Some(name), Some(numkeys)) = (args.first(), args.get(1)) else {
+            return RespValue::Error(format!("wrong number of arguments for '{}' command", cmd_upper));
+        };
+        let numkeys = match numkeys.parse::<usize>() {
+            Ok(n) if n <= args.len() - 2 => n,
+            Ok(_) => return RespValue::Error("Number of keys can't be greater than number of args".to_string()),
+            Err(_) => return RespValue::Error("value is not an integer or out of range".to_string()),
+        };
+        let Some(function) = functions::registry().get(name) else {
+            return RespValue::Error("Function not found".to_string());
+        };
+        if cmd_upper == "FCALL_RO" && !function.read_only() {
+            return RespValue::Error("Can not execute a function with write flag using fcall_ro.".to_string());
+        }
+        if let Err(e) = self.security.check_function(self.user.as_deref(), function.name(), function.acl_categories()) {
+            return RespValue::Error(e);
+        }
+
+        let (keys, function_args) = args[2..].split_at(numkeys);
+        let mut db = self.db.write().await;
+        let started = std::time::Instant::now();
+        let result = function.call(&mut FunctionContext::new(&mut db, keys, function_args));
+        functions::registry().record(function.name(), started.elapsed(), result.is_err());
+
+        if !function.read_only() {
+            // The function is not needed on replay: log the keys it was given
+            // as their resulting values
+            let mut aof = self.aof.write().await;
+            for key in keys {
+                let record = match db.items.get(key).filter(|_| db.exists(key)) {
+                    Some(entry) => vec![
+                        "RESTORE".to_string(),
+                        key.clone(),
+                        entry.expires_at.map(unix_millis_from_instant).unwrap_or(0).to_string(),
+                        crate::persistence::dump::dump(entry),
+                        "REPLACE".to_string(),
+                        "ABSTTL".to_string(),
+                    ],
+                    None => vec!["DEL".to_string(), key.clone()],
+                };
+                if let Err(e) = aof.append(record) {
+                    error!("AOF write error: {}", e);
+                }
+            }
+            if !keys.is_empty() {
+                blocking::signal_ready();
+            }
+        }
+        result.unwrap_or_else(RespValue::Error)
+    }
+
+    /// FUNCTION LIST | STATS | LOAD | DELETE
+    fn function(&self, args: &[String]) -> RespValue {
+        let bulk = |s: &str| RespValue::BulkString(Some(s.to_string()));
+        let names = |items: &[&str]| RespValue::Array(Some(items.iter().map(|s| bulk(s)).collect()));
+        match (args.first().map(|s| s.to_uppercase()).as_deref(), args.len()) {
+            (Some("LIST"), 1) => RespValue::Array(Some(
+                functions::registry()
+                    .list()
+                    .into_iter()
+                    .map(|f| {
+                        let flags: &[&str] = if f.read_only() { &["no-writes"] } else { &[] };
+                        RespValue::Array(Some(vec![
+                            bulk("name"),
+                            bulk(f.name()),
+                            bulk("description"),
+                            bulk(f.description()),
+                            bulk("flags"),
+                            names(flags),
+                            bulk("acl_categories"),
+                            names(f.acl_categories()),
+                        ]))
+                    })
+                    .collect(),
+            )),
+            (Some("STATS"), 1) => RespValue::Array(Some(
+                functions::registry()
+                    .stats()
+                    .into_iter()
+                    .map(|(name, stats)| {
+                        RespValue::Array(Some(vec![
+                            bulk("name"),
+                            bulk(&name),
+                            bulk("calls"),
+                            RespValue::Integer(stats.calls as i64),
+                            bulk("errors"),
+                            RespValue::Integer(stats.errors as i64),
+                            bulk("usec"),
+                            RespValue::Integer(stats.total_us as i64),
+                        ]))
+                    })
+                    .collect(),
+            )),
+            (Some("LOAD"), _) => {
+                let (replace, rest) = match args.get(2).map(|s| s.to_uppercase()).as_deref() {
+                    Some("REPLACE") => (true, &args[3..]),
+                    _ => (false, args.get(2..).unwrap_or_default()),
+                };
+                let [library, code] = rest else {
+                    return RespValue::Error("wrong number of arguments for 'function|load' command".to_string());
+                };
+                if !args[1].eq_ignore_ascii_case("WASM") {
+                    return RespValue::Error(format!("unsupported engine '{}'. Try WASM.", args[1]));
+                }
+                match functions::wasm::load(library, code, replace) {
+                    Ok(_) => {
+                        if let Err(e) = functions::wasm::save(library, &functions::wasm::library_dir()) {
+                            error!("Error saving function library {}: {}", library, e);
+                        }
+                        bulk(library)
+                    }
+                    Err(e) => RespValue::Error(e),
+                }
+            }
+            (Some("DELETE"), 2) => {
+                if !functions::wasm::delete(&args[1]) {
+                    return RespValue::Error("Library not found".to_string());
+                }
+                if let Err(e) = functions::wasm::forget(&args[1], &functions::wasm::library_dir()) {
+                    error!("Error removing function library {}: {}", args[1], e);
+                }
+                RespValue::SimpleString("OK".to_string())
+            }
+            (Some("LIST" | "STATS" | "DELETE"), _) => RespValue::Error(format!(
+                "wrong number of arguments for 'function|{}' command",
+                args[0].to_lowercase()
+            )),
+            _ => RespValue::Error(format!(
+                "unknown subcommand '{}'. Try FUNCTION HELP.",
+                args.first().map(String::as_str).unwrap_or("")
+            )),
+        }
+    }
+
+    /// SCHEDULE ADD name trigger command | DEL name | LIST
+    async fn schedule(&self, args: &[String]) -> RespValue {
+        let key = scheduler::SCHEDULE_KEY.to_string();
+        match (args.first().map(|s| s.to_uppercase()).as_deref(), args.len()) {
+            (Some("ADD"), 4) => {
+                let job = match scheduler::Job::new(&args[1], &args[2], &args[3]) {
+                    Ok(job) => job.with_owner(self.user.clone()),
+                    Err(e) => return RespValue::Error(e),
+                };
+                let mut db = self.db.write().await;
+                if let Err(e) = db.hset(key.clone(), job.name.clone(), job.encode()) {
+                    return e.into();
+                }
+                if let Err(e) = self.aof.write().await.append(vec!["HSET".to_string(), key, job.name.clone(), job.encode()]) {
+                    error!("AOF write error: {}", e);
+                }
+                RespValue::SimpleString("OK".to_string())
+            }
+            (Some("DEL"), 2) => {
+                let mut db = self.db.write().await;
+                match db.hdel(key.clone(), args[1].clone()) {
+                    Ok(0) => RespValue::Integer(0),
+                    Ok(n) => {
+                        if let Err(e) = self.aof.write().await.append(vec!["HDEL".to_string(), key, args[1].clone()]) {
+                            error!("AOF write error: {}", e);
+                        }
+                        RespValue::Integer(n as i64)
+                    }
+                    Err(e) => e.into(),
+                }
+            }
+            (Some("LIST"), 1) => {
+                let mut jobs = scheduler::jobs(&mut *self.db.write().await);
+                jobs.sort_by(|a, b| a.name.cmp(&b.name));
+                let bulk = |s: String| RespValue::BulkString(Some(s));
+                RespValue::Array(Some(
+                    jobs.into_iter()
+                        .map(|job| RespValue::Array(Some(vec![bulk(job.name), bulk(job.spec), bulk(job.command)])))
+                        .collect(),
+                ))
+            }
+            (Some(sub @ ("ADD" | "DEL" | "LIST")), _) => RespValue::Error(format!(
+                "wrong number of arguments for 'schedule|{}' command",
+                sub.to_lowercase()
+            )),
+            _ => RespValue::Error(format!(
+                "unknown subcommand '{}'. Try SCHEDULE ADD, DEL or LIST.",
+                args.first().map(String::as_str).unwrap_or("")
+            )),
+        }
+    }
+
+    /// DBSTATS [FULL]: key counts per type from the keyspace's counters, or
+    /// with FULL a batched walk adding memory, TTL distribution and the biggest keys
+    async fn dbstats(&self, args: &[String]) -> RespValue {
+        let bulk = |s: String| RespValue::BulkString(Some(s));
+        match args.first().map(|s| s.to_uppercase()).as_deref() {
+            None => {
+                let db = self.db.read().await;
+                let types = db
+                    .items
+                    .type_counts()
+                    .into_iter()
+                    .flat_map(|(name, count)| [bulk(name.to_string()), RespValue::Integer(count as i64)])
+                    .collect();
+                RespValue::Array(Some(vec![
+                    bulk("keys".to_string()),
+                    RespValue::Integer(db.dbsize() as i64),
+                    bulk("types".to_string()),
+                    RespValue::Array(Some(types)),
+                ]))
+            }
+            Some("FULL") if args.len() == 1 => {
+                let report = analyze::scan_all(&self.db, 1000).await;
+                let types = report
+                    .by_type
+                    .into_iter()
+                    .flat_map(|(name, stats)| [bulk(name), field_pairs(stats.fields())])
+                    .collect();
+                RespValue::Array(Some(vec![
+                    bulk("total".to_string()),
+                    field_pairs(report.total.fields()),
+                    bulk("types".to_string()),
+                    RespValue::Array(Some(types)),
+                    bulk("biggest".to_string()),
+                    biggest_keys_reply(report.biggest),
+                ]))
+            }
+            _ => RespValue::Error("syntax error".to_string()),
+        }
+    }
+
+    /// SETBIT/GETBIT offset: bitmaps are dense, so offsets stop at the bits
+    /// of a `proto_max_bulk_len` string (4 Gbit with the 512 MB default)
+    async fn bit_offset(&self, arg: &str) -> Result<usize, RespValue> {
+        let max_bits = self.config.read().await.server.proto_max_bulk_len.saturating_mul(8);
+        match arg.parse::<usize>() {
+            Ok(offset) if offset < max_bits => Ok(offset),
+            _ => Err(RespValue::Error("bit offset is not an integer or out of range".to_string())),
+        }
+    }
+
+    /// PUBSUB CHANNELS | NUMSUB | NUMPAT | SHARDCHANNELS | SHARDNUMSUB
+    async fn pubsub_command(&self, args: &[String]) -> RespValue {
+        let bulk = |s: String| RespValue::BulkString(Some(s));
+        let names = |mut names: Vec<String>| {
+            names.sort();
+            RespValue::Array(Some(names.into_iter().map(bulk).collect()))
+        };
+        let sub = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+        match (sub.as_str(), args.len()) {
+            ("CHANNELS", 1 | 2) => names(self.pubsub.channels(args.get(1).map(String::as_str)).await),
+            ("SHARDCHANNELS", 1 | 2) => names(self.pubsub.shard_channels(args.get(1).map(String::as_str)).await),
+            ("NUMPAT", 1) => RespValue::Integer(self.pubsub.numpat().await as i64),
+            ("NUMSUB" | "SHARDNUMSUB", _) => {
+                let mut reply = Vec::with_capacity((args.len() - 1) * 2);
+                for channel in &args[1..] {
+                    let count = if sub == "NUMSUB" {
+                        self.pubsub.numsub(channel).await
+                    } else {
+                        self.pubsub.shard_numsub(channel).await
+                    };
+                    reply.push(bulk(channel.clone()));
+                    reply.push(RespValue::Integer(count as i64));
+                }
+                RespValue::Array(Some(reply))
+            }
+            _ => RespValue::Error(format!(
+                "ERR unknown subcommand or wrong number of arguments for 'pubsub|{}' command",
+                sub.to_lowercase()
+            )),
+        }
+    }
+
+    /// TS.CREATE | TS.ADD | TS.GET | TS.RANGE | TS.MRANGE | TS.CREATERULE | TS.DELETERULE
+    async fn timeseries_command(&self, cmd_upper: &str, cmd_string: &str, args: &[String]) -> RespValue {
+        let wrong_arity = || {
+            RespValue::Error(format!("wrong number of arguments for '{}' command", cmd_upper))
+        };
+        let parse_value = |value: &str| value.parse::<f64>().ok().filter(|v| v.is_finite());
+        let parse_bound = |bound: &str, default: u64| match bound {
+            "-" | "+" => Some(default),
+            _ => bound.parse::<u64>().ok(),
+        };
+        let samples = |samples: Vec<(u64, f64)>| {
+            RespValue::Array(Some(
+                samples
+                    .into_iter()
+                    .map(|(ts, value)| {
+                        RespValue::Array(Some(vec![
+                            RespValue::Integer(ts as i64),
+                            RespValue::BulkString(Some(value.to_string())),
+                        ]))
+                    })
+                    .collect(),
+            ))
+        };
+
+        match cmd_upper {
+            // TS.CREATE key [RETENTION ms] [LABELS label value ...]
+            "TS.CREATE" => {
+                let Some((key, rest)) = args.split_first() else { return wrong_arity() };
+                let options = match SeriesOptions::parse(rest) {
+                    Ok(options) => options,
+                    Err(e) => return e.into(),
+                };
+                if let Err(e) = self.db.write().await.ts_create(key.clone(), options) {
+                    return e.into();
+                }
+                self.log_write(cmd_string, args).await;
+                RespValue::ok()
+            }
+            // TS.ADD key timestamp|* value [RETENTION ms] [LABELS label value ...]
+            "TS.ADD" => {
+                let [key, timestamp, value, rest @ ..] = args else { return wrong_arity() };
+                let timestamp = match timestamp.as_str() {
+                    "*" => unix_millis_now(),
+                    ts => match ts.parse::<u64>() {
+                        Ok(ts) => ts,
+                        Err(_) => return RespValue::Error("TSDB: invalid timestamp".to_string()),
+                    },
+                };
+                let Some(value) = parse_value(value) else {
+                    return RespValue::Error("TSDB: invalid value".to_string());
+                };
+                let options = match SeriesOptions::parse(rest) {
+                    Ok(options) => options,
+                    Err(e) => return e.into(),
+                };
+                if let Err(e) = self.db.write().await.ts_add(key.clone(), timestamp, value, options) {
+                    return e.into();
+                }
+                // Logged with the resolved timestamp so replay adds the same sample
+                let mut logged = args.to_vec();
+                logged[1] = timestamp.to_string();
+                self.log_write(cmd_string, &logged).await;
+                RespValue::Integer(timestamp as i64)
+            }
+            "TS.GET" => {
+                let [key] = args else { return wrong_arity() };
+                match self.db.write().await.ts_get(key) {
+                    Ok(Some((ts, value))) => RespValue::Array(Some(vec![
+                        RespValue::Integer(ts as i64),
+                        RespValue::BulkString(Some(value.to_string())),
+                    ])),
+                    Ok(None) => RespValue::Array(Some(vec![])),
+                    Err(e) => e.into(),
+                }
+            }
+            // TS.RANGE key from to [COUNT count] [AGGREGATION aggregator bucket-ms]
+            "TS.RANGE" => {
+                let [key, from, to, rest @ ..] = args else { return wrong_arity() };
+                let (Some(from), Some(to)) = (parse_bound(from, 0), parse_bound(to, u64::MAX)) else {
+                    return RespValue::Error("TSDB: invalid timestamp".to_string());
+                };
+                let query = match RangeQuery::parse(rest) {
+                    Ok((query, [])) => query,
+                    Ok(_) => return RespValue::Error("syntax error".to_string()),
+                    Err(e) => return e.into(),
+                };
+                match self.db.write().await.ts_range(key, from, to, &query) {
+                    Ok(range) => samples(range),
+                    Err(e) => e.into(),
+                }
+            }
+            // TS.MRANGE from to [COUNT count] [AGGREGATION aggregator bucket-ms] [WITHLABELS] FILTER filter ...
+            "TS.MRANGE" => {
+                let [from, to, rest @ ..] = args else { return wrong_arity() };
+                let (Some(from), Some(to)) = (parse_bound(from, 0), parse_bound(to, u64::MAX)) else {
+                    return RespValue::Error("TSDB: invalid timestamp".to_string());
+                };
+                let (query, mut rest) = match RangeQuery::parse(rest) {
+                    Ok(parsed) => parsed,
+                    Err(e) => return e.into(),
+                };
+                let with_labels = rest.first().is_some_and(|arg| arg.eq_ignore_ascii_case("WITHLABELS"));
+                if with_labels {
+                    rest = &rest[1..];
+                }
+                let filters = match rest {
+                    [filter, exprs @ ..] if filter.eq_ignore_ascii_case("FILTER") && !exprs.is_empty() => {
+                        match exprs.iter().map(|expr| LabelFilter::parse(expr)).collect::<Option<Vec<_>>>() {
+                            Some(filters) => filters,
+                            None => return RespValue::Error("TSDB: failed parsing labels".to_string()),
+                        }
+                    }
+                    _ => return RespValue::Error("TSDB: missing FILTER argument".to_string()),
+                };
+                let series = self.db.write().await.ts_mrange(from, to, &query, &filters);
+                RespValue::Array(Some(
+                    series
+                        .into_iter()
+                        .map(|(key, labels, range)| {
+                            let labels = if with_labels { labels } else { Vec::new() };
+                            let labels = labels
+                                .into_iter()
+                                .map(|(label, value)| {
+                                    RespValue::Array(Some(vec![
+                                        RespValue::BulkString(Some(label)),
+                                        RespValue::BulkString(Some(value)),
+                                    ]))
+                                })
+                                .collect();
+                            RespValue::Array(Some(vec![
+                                RespValue::BulkString(Some(key)),
+                                RespValue::Array(Some(labels)),
+                                samples(range),
+                            ]))
+                        })
+                        .collect(),
+                ))
+            }
+            // TS.CREATERULE source dest AGGREGATION aggregator bucket-ms
+            "TS.CREATERULE" => {
+                let [source, dest, rest @ ..] = args else { return wrong_arity() };
+                let (aggregation, bucket_ms) = match RangeQuery::parse(rest) {
+                    Ok((RangeQuery { count: None, aggregation: Some(aggregation) }, [])) => aggregation,
+                    Ok(_) => return RespValue::Error("TSDB: missing AGGREGATION argument".to_string()),
+                    Err(e) => return e.into(),
+                };
+                let rule = CompactionRule { dest: dest.clone(), aggregation, bucket_ms };
+                if let Err(e) = self.db.write().await.ts_createrule(source, rule) {
+                    return e.into();
+                }
+                self.log_write(cmd_string, args).await;
+                RespValue::ok()
+            }
+            "TS.DELETERULE" => {
+                let [source, dest] = args else { return wrong_arity() };
+                if let Err(e) = self.db.write().await.ts_deleterule(source, dest) {
+                    return e.into();
+                }
+                self.log_write(cmd_string, args).await;
+                RespValue::ok()
+            }
+            _ => RespValue::Error(format!("unknown command '{}'", cmd_string)),
+        }
+    }
+
+    /// BF.RESERVE | BF.ADD | BF.MADD | BF.EXISTS | BF.MEXISTS | BF.INFO and
+    /// CF.RESERVE | CF.ADD | CF.ADDNX | CF.EXISTS | CF.MEXISTS | CF.COUNT | CF.DEL | CF.INFO
+    async fn filter_command(&self, cmd_upper: &str, cmd_string: &str, args: &[String]) -> RespValue {
+        let wrong_arity = || {
+            RespValue::Error(format!("wrong number of arguments for '{}' command", cmd_upper))
+        };
+        let flags = |flags: Vec<bool>| {
+            RespValue::Array(Some(flags.into_iter().map(|flag| RespValue::Integer(flag as i64)).collect()))
+        };
+        let info = |fields: Vec<(&str, i64)>| {
+            RespValue::Array(Some(
+                fields
+                    .into_iter()
+                    .flat_map(|(name, value)| [RespValue::BulkString(Some(name.to_string())), RespValue::Integer(value)])
+                    .collect(),
+            ))
+        };
+        let Some((key, items)) = args.split_first() else { return wrong_arity() };
+        let mut db = self.db.write().await;
+
+        let reply = match (cmd_upper, items) {
+            ("BF.RESERVE", _) => {
+                let reserved = BloomOptions::parse(items).and_then(|options| db.bf_reserve(key.clone(), options));
+                reserved.map(|_| (RespValue::ok(), true))
+            }
+            ("BF.ADD", [_]) | ("BF.MADD", [_, ..]) => db.bf_madd(key.clone(), items).map(|added| {
+                let changed = added.contains(&Ok(true));
+                let mut replies: Vec<RespValue> = added
+                    .into_iter()
+                    .map(|added| match added {
+                        Ok(added) => RespValue::Integer(added as i64),
+                        Err(e) => e.into(),
+                    })
+                    .collect();
+                match cmd_upper {
+                    "BF.ADD" => (replies.remove(0), changed),
+                    _ => (RespValue::Array(Some(replies)), changed),
+                }
+            }),
+            ("BF.EXISTS", [_]) => db.bf_mexists(key, items).map(|found| (RespValue::Integer(found[0] as i64), false)),
+            ("BF.MEXISTS", [_, ..]) => db.bf_mexists(key, items).map(|found| (flags(found), false)),
+            ("BF.INFO", []) => db.bf_info(key).map(|fields| (info(fields), false)),
+            ("CF.RESERVE", _) => {
+                let reserved = CuckooOptions::parse(items).and_then(|options| db.cf_reserve(key.clone(), options));
+                reserved.map(|_| (RespValue::ok(), true))
+            }
+            ("CF.ADD" | "CF.ADDNX", [item]) => db
+                .cf_add(key.clone(), item, cmd_upper == "CF.ADDNX")
+                .map(|added| (RespValue::Integer(added as i64), added)),
+            ("CF.EXISTS", [_]) => db.cf_mexists(key, items).map(|found| (RespValue::Integer(found[0] as i64), false)),
+            ("CF.MEXISTS", [_, ..]) => db.cf_mexists(key, items).map(|found| (flags(found), false)),
+            ("CF.COUNT", [item]) => db.cf_count(key, item).map(|count| (RespValue::Integer(count as i64), false)),
+            ("CF.DEL", [item]) => db.cf_del(key, item).map(|deleted| (RespValue::Integer(deleted as i64), deleted)),
+            ("CF.INFO", []) => db.cf_info(key).map(|fields| (info(fields), false)),
+            _ => return wrong_arity(),
+        };
+        drop(db);
+
+        match reply {
+            Ok((reply, changed)) => {
+                if changed {
+                    self.log_write(cmd_string, args).await;
+                }
+                reply
+            }
+            Err(e) => e.into(),
+        }
+    }
+
+    /// VECTOR.ADD | VECTOR.GET | VECTOR.DEL | VECTOR.CARD | VECTOR.SEARCH
+    async fn vector_command(&self, cmd_upper: &str, cmd_string: &str, args: &[String]) -> RespValue {
+        let wrong_arity = || {
+            RespValue::Error(format!("wrong number of arguments for '{}' command", cmd_upper))
+        };
+        let pairs = |pairs: Vec<(String, String)>| {
+            RespValue::Array(Some(
+                pairs
+                    .into_iter()
+                    .flat_map(|(field, value)| [RespValue::BulkString(Some(field)), RespValue::BulkString(Some(value))])
+                    .collect(),
+            ))
+        };
+
+        match (cmd_upper, args) {
+            // VECTOR.ADD key id vector [field value ...]
+            ("VECTOR.ADD", [key, id, vector, meta @ ..]) if meta.len().is_multiple_of(2) => {
+                let values = match parse_vector(vector) {
+                    Ok(values) => values,
+                    Err(e) => return e.into(),
+                };
+                let meta = meta.chunks_exact(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+                let added = self.db.write().await.vector_add(key.clone(), id.clone(), values, meta);
+                match added {
+                    Ok(added) => {
+                        self.log_write(cmd_string, args).await;
+                        RespValue::Integer(added as i64)
+                    }
+                    Err(e) => e.into(),
+                }
+            }
+            ("VECTOR.GET", [key, id]) => match self.db.write().await.vector_get(key, id) {
+                Ok(Some(entry)) => {
+                    let values: Vec<String> = entry.values.iter().map(|v| v.to_string()).collect();
+                    RespValue::Array(Some(vec![RespValue::BulkString(Some(values.join(","))), pairs(entry.meta)]))
+                }
+                Ok(None) => RespValue::Array(None),
+                Err(e) => e.into(),
+            },
+            ("VECTOR.DEL", [key, ids @ ..]) if !ids.is_empty() => {
+                let removed = self.db.write().await.vector_del(key, ids);
+                match removed {
+                    Ok(removed) => {
+                        if removed > 0 {
+                            self.log_write(cmd_string, args).await;
+                        }
+                        RespValue::Integer(removed as i64)
+                    }
+                    Err(e) => e.into(),
+                }
+            }
+            ("VECTOR.CARD", [key]) => match self.db.write().await.vector_card(key) {
+                Ok(count) => RespValue::Integer(count as i64),
+                Err(e) => e.into(),
+            },
+            // VECTOR.SEARCH key vector k [METRIC COSINE|L2] [FILTER field value] [WITHMETA]
+            ("VECTOR.SEARCH", [key, vector, k, rest @ ..]) => {
+                let Ok(k) = k.parse::<usize>() else {
+                    return RespValue::Error("value is not an integer or out of range".to_string());
+                };
+                let (query, options) = match parse_vector(vector).and_then(|q| Ok((q, VectorQuery::parse(rest)?))) {
+                    Ok(parsed) => parsed,
+                    Err(e) => return e.into(),
+                };
+                match self.db.write().await.vector_search(key, &query, k, &options) {
+                    Ok(hits) => RespValue::Array(Some(
+                        hits.into_iter()
+                            .map(|(id, distance, meta)| {
+                                let mut hit = vec![
+                                    RespValue::BulkString(Some(id)),
+                                    RespValue::BulkString(Some(distance.to_string())),
+                                ];
+                                if options.with_meta {
+                                    hit.push(pairs(meta));
+                                }
+                                RespValue::Array(Some(hit))
+                            })
+                            .collect(),
+                    )),
+                    Err(e) => e.into(),
+                }
+            }
+            _ => wrong_arity(),
+        }
+    }
+
+    /// Append a write to the AOF
+    async fn log_write(&self, cmd_string: &str, args: &[String]) {
+        if let Err(e) = self.aof.write().await.append_command(cmd_string, args) {
+            error!("AOF write error: {}", e);
+        }
+    }
+
+    /// ACL WHOAMI | USERS | LIST | GETUSER | SETUSER | DELUSER | CAT | GENPASS | SAVE | LOAD
+    fn acl(&self, args: &[String]) -> RespValue {
+        let bulk = |s: String| RespValue::BulkString(Some(s));
+        let sub = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+        match (sub.as_str(), args.len()) {
+            ("WHOAMI", 1) => bulk(self.user.clone().unwrap_or_else(|| DEFAULT_USER.to_string())),
+            ("USERS", 1) => {
+                let mut users = self.security.acl_list();
+                users.sort();
+                RespValue::Array(Some(users.into_iter().map(bulk).collect()))
+            }
+            ("LIST", 1) => RespValue::Array(Some(self.security.acl_rules().into_iter().map(bulk).collect())),
+            ("GETUSER", 2) => match self.security.acl_getuser(&args[1]) {
+                Some(user) => {
+                    // "user ad on #hash +cmd ~key &chan" → bayraklar, şifreler, komutlar, anahtarlar, kanallar
+                    let rules = user.rules();
+                    let pick = |prefixes: &[char]| {
+                        rules
+                            .split(' ')
+                            .skip(2)
+                            .filter(|r| r.starts_with(prefixes))
+                            .collect::<Vec<_>>()
+                    };
+                    let flag = if user.enabled { "on" } else { "off" };
+                    RespValue::Array(Some(vec![
+                        bulk("flags".to_string()),
+                        RespValue::Array(Some(vec![bulk(flag.to_string())])),
+                        bulk("passwords".to_string()),
+                        RespValue::Array(Some(
+                            pick(&['#']).into_iter().map(|p| bulk(p[1..].to_string())).collect(),
+                        )),
+                        bulk("commands".to_string()),
+                        bulk(pick(&['+', '-']).join(" ")),
+                        bulk("keys".to_string()),
+                        bulk(pick(&['~']).join(" ")),
+                        bulk("channels".to_string()),
+                        bulk(pick(&['&']).join(" ")),
+                    ]))
+                }
+                None => RespValue::BulkString(None),
+            },
+            ("SETUSER", n) if n >= 2 => {
+                let mut rules = Vec::with_capacity(n - 2);
+                for rule in &args[2..] {
+                    match security::parse_acl_rule(rule) {
+                        Some(r) => rules.push(r),
+                        None => return RespValue::Error(format!("Error in ACL SETUSER modifier '{}': Syntax error", rule)),
+                    }
+                }
+                match self.security.acl_setuser(args[1].clone(), rules) {
+                    Ok(()) => RespValue::ok(),
+                    Err(e) => RespValue::Error(e),
+                }
+            }
+            ("DELUSER", n) if n >= 2 => RespValue::Integer(self.security.acl_deluser(args[1..].to_vec()) as i64),
+            ("CAT", 1) => RespValue::Array(Some(
+                security::ACL_CATEGORIES.iter().map(|(name, _)| bulk(name.to_string())).collect(),
+            )),
+            ("CAT", 2) => match security::category_commands(&args[1]) {
+                Some(commands) => RespValue::Array(Some(commands.iter().map(|c| bulk(c.to_lowercase())).collect())),
+                None => RespValue::Error(format!("Unknown category '{}'", args[1])),
+            },
+            ("GENPASS", 1 | 2) => {
+                let bits = match args.get(1).map(|b| b.parse::<usize>()) {
+                    None => 256,
+                    Some(Ok(bits)) if (1..=4096).contains(&bits) => bits,
+                    Some(_) => return RespValue::Error("ACL GENPASS argument must be the number of bits for the output password, a positive number up to 4096".to_string()),
+                };
+                bulk(security::generate_password(bits))
+            }
+            ("SAVE", 1) => match self.security.save_acl(paths::acl_path()) {
+                Ok(_) => RespValue::ok(),
+                Err(e) => RespValue::Error(format!("There was an error trying to save the ACLs: {}", e)),
+            },
+            ("LOAD", 1) => match self.security.load_acl(paths::acl_path()) {
+                Ok(_) => RespValue::ok(),
+                Err(e) => RespValue::Error(format!("Error loading ACLs, no changes applied: {}", e)),
+            },
+            ("WHOAMI" | "USERS" | "LIST" | "GETUSER" | "SETUSER" | "DELUSER" | "CAT" | "GENPASS" | "SAVE" | "LOAD", _) => {
+                RespValue::Error(format!("wrong number of arguments for 'acl|{}' command", sub.to_lowercase()))
+            }
+            _ => RespValue::Error(format!(
+                "unknown subcommand '{}'. Try ACL HELP.",
+                args.first().map(String::as_str).unwrap_or("")
+            )),
+        }
+    }
+
+    /// Keys that may need a default TTL once the command has run: the
+    /// command's keys that do not exist yet, if rules are configured.
+    async fn default_ttl_candidates(&self, request: &RespValue) -> Vec<String> {
+        if self.config.read().await.default_ttl.is_empty() {
+            return Vec::new();
+        }
+        let command = request_args(request);
+        let Some((name, args)) = command.split_first() else {
+            return Vec::new();
+        };
+        let db = self.db.read().await;
+        command_keys(&name.to_uppercase(), args)
+            .into_iter()
+            .filter(|key| !db.exists(key))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Apply the first matching default TTL rule to each key created without an expiry
+    async fn apply_default_ttl(&self, keys: Vec<String>) {
+        let config = self.config.read().await;
+        let mut db = self.db.write().await;
+
+        for key in keys {
+            let key_type = match db.items.get(&key) {
+                Some(entry) if entry.expires_at.is_none() => db.type_of(&key).unwrap_or_default(),
+                _ => continue,
+            };
+            let Some(rule) = config.default_ttl.iter().find(|r| r.matches(&key, &key_type)) else {
+                continue;
+            };
+            let Some(at) = std::time::Instant::now().checked_add(std::time::Duration::from_secs(rule.ttl_seconds)) else {
+                continue;
+            };
+
+            db.expire_with(&key, at, ExpireCondition::Always);
+            // Log the absolute time so replay does not extend the TTL
+            let mut aof = self.aof.write().await;
+            if let Err(e) = aof.append(vec![
+                "PEXPIREAT".to_string(),
+                key,
+                unix_millis_from_instant(at).to_string(),
+            ]) {
+                error!("AOF write error: {}", e);
+            }
+        }
+    }
+
+    /// Append a published message to its channel's log stream, trimmed to
+    /// `max_len` entries when that is non-zero
+    async fn log_published(&self, channel: &str, message: &str, max_len: usize) {
+        let key = pubsub::log_key(channel);
+        let mut db = self.db.write().await;
+        let fields = vec![("message".to_string(), message.to_string())];
+        let id = match db.xadd(key.clone(), None, fields) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Pub/Sub log {} not written: {}", key, e);
+                return;
+            }
+        };
+
+        let mut aof = self.aof.write().await;
+        if let Err(e) = aof.append(vec![
+            "XADD".to_string(),
+            key.clone(),
+            id,
+            "message".to_string(),
+            message.to_string(),
+        ]) {
+            error!("AOF write error: {}", e);
+        }
+        if max_len > 0 {
+            let _ = trim_stream(&mut db, &mut aof, key, StreamTrim::MaxLen(max_len));
+        }
+    }
+
+    #[tracing::instrument(skip(self, request), fields(cmd, key))]
+    async fn execute_command(&mut self, request: RespValue) -> ExecutionResult {
         counter!(METRIC_COMMANDS_TOTAL).increment(1);
         let _guard = LatencyGuard {
             start: std::time::Instant::now(),
@@ -75,10 +1091,11 @@ impl Interpreter {
                     ));
                 }
 
-                // İlk eleman komut ismidir (SET, GET vs.)
-                let cmd_string = match &tokens[0] {
-                    RespValue::BulkString(Some(s)) => s.clone(),
-                    RespValue::SimpleString(s) => s.clone(),
+                // İlk eleman komut ismidir (SET, GET vs.). Token'lar kopyalanmadan
+                // sahiplenilir: her komutta argüman başına bir String tasarrufu.
+                let mut tokens = tokens.into_iter();
+                let cmd_string = match tokens.next() {
+                    Some(RespValue::BulkString(Some(s))) | Some(RespValue::SimpleString(s)) => s,
                     _ => {
                         return ExecutionResult::Response(RespValue::Error(
                             "invalid command format".to_string(),
@@ -89,75 +1106,213 @@ impl Interpreter {
                 let cmd_upper = cmd_string.to_uppercase();
                 tracing::Span::current().record("cmd", &cmd_upper);
                 tracing::info!("Processing command");
+
                 let args: Vec<String> = tokens
-                    .iter()
-                    .skip(1)
                     .filter_map(|t| match t {
-                        RespValue::BulkString(Some(s)) => Some(s.clone()),
+                        RespValue::BulkString(Some(s)) | RespValue::SimpleString(s) => Some(s),
                         _ => None,
                     })
                     .collect();
 
-                // Komut argümanlarını string listesine çevir (AOF için lazım)
-                let mut full_cmd_args = vec![cmd_string.clone()];
-                full_cmd_args.extend(args.clone());
+                // Kimlik doğrulama ve ACL: komut ve anahtarları kullanıcıya açık mı?
+                let keys = command_keys(&cmd_upper, &args);
+                if let Err(e) = self.security.check_command(self.user.as_deref(), &cmd_upper, &keys) {
+                    return ExecutionResult::Response(RespValue::Error(e));
+                }
+                // The job store is only reachable through SCHEDULE
+                if keys.contains(&scheduler::SCHEDULE_KEY) {
+                    return ExecutionResult::Response(RespValue::Error(format!(
+                        "ERR the '{}' key is reserved for SCHEDULE",
+                        scheduler::SCHEDULE_KEY
+                    )));
+                }
+                if let Err(e) = self.security.check_channels(self.user.as_deref(), &cmd_upper, &args) {
+                    return ExecutionResult::Response(RespValue::Error(e));
+                }
 
-                // --- Komutları İşle ---
+                // Veri seti hâlâ yükleniyorsa yalnızca izin verilen komutları çalıştır
+                if self.server_info.loading().is_loading() {
+                    let serve_reads = self.config.read().await.server.serve_reads_while_loading;
+                    if !loading::allowed_while_loading(&cmd_upper, serve_reads) {
+                        return ExecutionResult::Response(RespValue::Error(loading::LOADING_ERROR.to_string()));
+                    }
+                }
 
-                if cmd_upper == "PING" {
-                    return ExecutionResult::Response(RespValue::SimpleString("PONG".to_string()));
+                // Salt okunur bağlantı veya sunucu: yazma komutlarını reddet
+                if (self.read_only || self.config.read().await.server.read_only) && Self::writes(&cmd_upper, &args) {
+                    return ExecutionResult::Response(RespValue::Error(READONLY_ERROR.to_string()));
                 }
 
-                if cmd_upper == "ECHO" {
-                    if let Some(arg) = args.get(0) {
-                        return ExecutionResult::Response(RespValue::BulkString(Some(arg.clone())));
-                    } else {
-                        return ExecutionResult::Response(RespValue::Error(
-                            "wrong number of arguments for 'ECHO' command".to_string(),
-                        ));
+                // Okuma komutlarının anahtar isabet/ıskalama sayaçları (INFO stats)
+                let read_keys = keyspace_read_keys(&cmd_upper, &args);
+                if !read_keys.is_empty() {
+                    let db = self.db.read().await;
+                    for key in read_keys {
+                        self.server_info.record_keyspace_lookup(db.exists(key));
                     }
                 }
 
-                if cmd_upper == "INFO" {
-                    let db_guard = self.db.read().await;
-                    let db_size = db_guard.items.len();
-                    drop(db_guard);
+                // --- Komutları İşle ---
 
-                    let info_str = self.server_info.generate_info(db_size);
-                    return ExecutionResult::Response(RespValue::BulkString(Some(info_str)));
+                if cmd_upper == "PING" {
+                    return ExecutionResult::Response(RespValue::SimpleString("PONG".to_string()));
                 }
 
-                // Anahtar gerektiren komutlar için kontrol
-                let key = if let Some(k) = args.get(0) {
-                    k.clone()
-                } else {
-                    // Bazı komutlar anahtar istemez (PING, ECHO, KEYS *)
-                    // Ama aşağıdakiler ister.
-                    if [
-                        "GET",
+                // RESET: bağlantı durumunu sıfırla (oturum dahil)
+                if cmd_upper == "RESET" {
+                    self.reset();
+                    return ExecutionResult::Response(RespValue::SimpleString("RESET".to_string()));
+                }
+
+                // READONLY / READWRITE: bağlantıyı salt okunur moda al veya çıkar
+                if cmd_upper == "READONLY" || cmd_upper == "READWRITE" {
+                    if !args.is_empty() {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
+                    }
+                    self.read_only = cmd_upper == "READONLY";
+                    return ExecutionResult::Response(RespValue::SimpleString("OK".to_string()));
+                }
+
+                // AUTH [kullanıcı] şifre
+                if cmd_upper == "AUTH" {
+                    let (username, password) = match args.as_slice() {
+                        [password] => (DEFAULT_USER, password.as_str()),
+                        [username, password] => (username.as_str(), password.as_str()),
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "wrong number of arguments for 'AUTH' command".to_string(),
+                            ))
+                        }
+                    };
+                    if args.len() == 1 && !self.security.is_auth_required() {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".to_string(),
+                        ));
+                    }
+                    if !self.security.authenticate(username, password) {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "WRONGPASS invalid username-password pair or user is disabled.".to_string(),
+                        ));
+                    }
+                    self.user = Some(username.to_string());
+                    return ExecutionResult::Response(RespValue::SimpleString("OK".to_string()));
+                }
+
+                if cmd_upper == "ACL" {
+                    return ExecutionResult::Response(self.acl(&args));
+                }
+
+                if cmd_upper == "ECHO" {
+                    if let Some(arg) = args.get(0) {
+                        return ExecutionResult::Response(RespValue::BulkString(Some(arg.clone())));
+                    } else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'ECHO' command".to_string(),
+                        ));
+                    }
+                }
+
+                if cmd_upper == "INFO" {
+                    if args.len() > 1 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'INFO' command".to_string(),
+                        ));
+                    }
+                    let db_guard = self.db.read().await;
+                    let mut dataset = DatasetStats {
+                        keys: db_guard.items.len(),
+                        expires: db_guard.keys_with_expiry(),
+                        expired_keys: db_guard.expired_keys(),
+                        changes_since_save: db_guard.get_changes(),
+                        aof_size: 0,
+                    };
+                    drop(db_guard);
+                    dataset.aof_size = self.aof.read().await.size();
+
+                    let info_str = self
+                        .server_info
+                        .generate_info(args.first().map(|s| s.as_str()), &dataset);
+                    return ExecutionResult::Response(RespValue::BulkString(Some(info_str)));
+                }
+
+                // Anahtar gerektiren komutlar için kontrol
+                // Anahtar ödünç alınır; yalnızca sahiplik isteyen işlemler kopyalar
+                let no_key = String::new();
+                let key = if let Some(k) = args.first() {
+                    k
+                } else {
+                    // Bazı komutlar anahtar istemez (PING, ECHO, KEYS *)
+                    // Ama aşağıdakiler ister.
+                    if [
+                        "GET",
                         "SET",
                         "DEL",
+                        "UNDELETE",
                         "EXISTS",
                         "INCR",
                         "DECR",
+                        "INCRBY",
+                        "DECRBY",
+                        "INCRBYFLOAT",
+                        "GETSET",
+                        "APPEND",
                         "EXPIRE",
+                        "PEXPIRE",
+                        "EXPIREAT",
+                        "PEXPIREAT",
                         "TTL",
+                        "PTTL",
+                        "EXPIRETIME",
+                        "PEXPIRETIME",
                         "PERSIST",
+                        "DUMP",
+                        "RESTORE",
                         "LPUSH",
                         "RPUSH",
                         "LPOP",
                         "RPOP",
                         "LLEN",
                         "LRANGE",
+                        "LPOS",
                         "HSET",
+                        "HMSET",
                         "HGET",
                         "HGETALL",
                         "HDEL",
+                        "HMGET",
+                        "HSETNX",
+                        "HINCRBY",
+                        "HINCRBYFLOAT",
+                        "HRANDFIELD",
+                        "HSCAN",
+                        "HLEN",
+                        "HEXISTS",
+                        "HKEYS",
+                        "HVALS",
+                        "HSTRLEN",
+                        "HEXPIRE",
+                        "HPEXPIRE",
+                        "HEXPIREAT",
+                        "HPEXPIREAT",
+                        "HTTL",
+                        "HPTTL",
+                        "HPERSIST",
                         "SADD",
                         "SREM",
                         "SMEMBERS",
                         "SISMEMBER",
                         "SCARD",
+                        "SSCAN",
+                        "SORT",
+                        "SESSION.CREATE",
+                        "SESSION.GET",
+                        "SESSION.REFRESH",
+                        "PQ.PUSH",
+                        "PQ.POP",
+                        "PQ.ACK",
                     ]
                     .contains(&cmd_upper.as_str())
                     {
@@ -166,7 +1321,7 @@ impl Interpreter {
                             cmd_upper
                         )));
                     }
-                    "".to_string()
+                    &no_key
                 };
 
                 if cmd_upper == "GET" {
@@ -176,16 +1331,19 @@ impl Interpreter {
                             ExecutionResult::Response(RespValue::BulkString(Some(value)))
                         }
                         Ok(None) => ExecutionResult::Response(RespValue::BulkString(None)),
-                        Err(e) => ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => ExecutionResult::Response(e.into()),
                     };
+                } else if cmd_upper == "STRLEN" {
+                    let mut db = self.db.write().await;
+                    return ExecutionResult::Response(RespValue::Integer(db.strlen(key) as i64));
                 } else if cmd_upper == "SET" {
                     if let Some(value) = args.get(1) {
                         let mut db = self.db.write().await;
-                        db.set(key, value.clone());
+                        db.set(key.clone(), value.clone());
 
                         // AOF'a kaydet (Kalıcılık)
                         let mut aof = self.aof.write().await;
-                        if let Err(e) = aof.append(full_cmd_args) {
+                        if let Err(e) = aof.append_command(&cmd_string, &args) {
                             error!("AOF write error: {}", e);
                         }
 
@@ -199,22 +1357,58 @@ impl Interpreter {
                     }
                 } else if cmd_upper == "DEL" {
                     let mut db = self.db.write().await;
-                    db.del(&key);
+                    let removed = args.iter().filter(|k| db.del(k)).count();
 
-                    let mut aof = self.aof.write().await;
-                    if let Err(e) = aof.append(full_cmd_args) {
-                        error!("AOF write error: {}", e);
+                    if removed > 0 {
+                        let mut aof = self.aof.write().await;
+                        if let Err(e) = aof.append_command(&cmd_string, &args) {
+                            error!("AOF write error: {}", e);
+                        }
                     }
 
-                    return ExecutionResult::Response(RespValue::Integer(1));
-                } else if cmd_upper == "EXISTS" {
-                    let db = self.db.read().await;
-                    let exists = db.exists(&key);
-                    return ExecutionResult::Response(RespValue::Integer(if exists {
+                    return ExecutionResult::Response(RespValue::Integer(removed as i64));
+                } else if cmd_upper == "UNLINK" {
+                    let mut db = self.db.write().await;
+                    let removed = db.unlink(args.iter().map(String::as_str).collect());
+
+                    if removed > 0 {
+                        let mut aof = self.aof.write().await;
+                        if let Err(e) = aof.append_command(&cmd_string, &args) {
+                            error!("AOF write error: {}", e);
+                        }
+                    }
+
+                    return ExecutionResult::Response(RespValue::Integer(removed as i64));
+                } else if cmd_upper == "UNDELETE" {
+                    let mut db = self.db.write().await;
+                    let restored = db.undelete(key);
+
+                    // Log the restored value: on replay the recycle bin may be
+                    // empty, or a rewrite may have dropped the DEL it undoes
+                    if let Some(entry) = db.items.get(key).filter(|_| restored) {
+                        let mut aof = self.aof.write().await;
+                        if let Err(e) = aof.append(vec![
+                            "RESTORE".to_string(),
+                            key.clone(),
+                            entry.expires_at.map(unix_millis_from_instant).unwrap_or(0).to_string(),
+                            crate::persistence::dump::dump(entry),
+                            "REPLACE".to_string(),
+                            "ABSTTL".to_string(),
+                        ]) {
+                            error!("AOF write error: {}", e);
+                        }
+                    }
+
+                    return ExecutionResult::Response(RespValue::Integer(if restored {
                         1
                     } else {
                         0
                     }));
+                } else if cmd_upper == "EXISTS" {
+                    // A key given more than once is counted more than once
+                    let db = self.db.read().await;
+                    let count = args.iter().filter(|k| db.exists(k)).count();
+                    return ExecutionResult::Response(RespValue::Integer(count as i64));
                 } else if cmd_upper == "KEYS" {
                     if let Some(pattern) = args.get(0) {
                         let db = self.db.read().await;
@@ -229,29 +1423,165 @@ impl Interpreter {
                             "wrong number of arguments for 'KEYS' command".to_string(),
                         ));
                     }
+                } else if cmd_upper == "SCAN" {
+                    if args.is_empty() {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'SCAN' command".to_string(),
+                        ));
+                    }
+                    let (cursor, options) = match parse_scan_args(&cmd_upper, &args) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
+                    let db = self.db.read().await;
+                    let (next, keys) = db.scan(cursor, options.pattern.as_deref(), options.count);
+                    // TYPE sayfa alındıktan sonra filtreler
+                    let items = keys
+                        .into_iter()
+                        .filter(|k| db.exists(k))
+                        .filter(|k| {
+                            options.type_name.as_ref().is_none_or(|t| db.type_of(k).as_ref() == Some(t))
+                        })
+                        .map(|k| RespValue::BulkString(Some(k)))
+                        .collect();
+                    return ExecutionResult::Response(scan_reply(next, items));
                 } else if cmd_upper == "INCR" {
                     let mut db = self.db.write().await;
                     match db.incr(key) {
                         Ok(val) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
                             return ExecutionResult::Response(RespValue::Integer(val));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "DECR" {
                     let mut db = self.db.write().await;
                     match db.decr(key) {
                         Ok(val) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
                             return ExecutionResult::Response(RespValue::Integer(val));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "INCRBY" {
+                    if args.len() < 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'INCRBY' command".to_string(),
+                        ));
+                    }
+                    let delta = match args[1].parse::<i64>() {
+                        Ok(d) => d,
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                    };
+                    let bounds = match CounterBounds::parse(&args[2..]) {
+                        Ok(b) => b,
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    };
+                    let mut db = self.db.write().await;
+                    match db.incrby_bounded(key, delta, bounds) {
+                        Ok(val) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::Integer(val));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "DECRBY" {
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'DECRBY' command".to_string(),
+                        ));
+                    }
+                    // i64::MIN cannot be negated
+                    let delta = match args[1].parse::<i64>() {
+                        Ok(d) if d != i64::MIN => d,
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                    };
+                    let mut db = self.db.write().await;
+                    match db.decrby(key, delta) {
+                        Ok(val) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::Integer(val));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "INCRBYFLOAT" {
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'INCRBYFLOAT' command".to_string(),
+                        ));
+                    }
+                    let delta = match args[1].parse::<f64>() {
+                        Ok(d) if d.is_finite() => d,
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not a valid float".to_string(),
+                            ));
+                        }
+                    };
+                    let mut db = self.db.write().await;
+                    match db.incrbyfloat(key, delta) {
+                        Ok(val) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::BulkString(Some(val.to_string())));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "GETSET" {
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'GETSET' command".to_string(),
+                        ));
+                    }
+                    let mut db = self.db.write().await;
+                    match db.getset(key.clone(), args[1].clone()) {
+                        Ok(old) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::BulkString(old));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "APPEND" {
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'APPEND' command".to_string(),
+                        ));
+                    }
+                    let mut db = self.db.write().await;
+                    match db.append(key.clone(), args[1].clone()) {
+                        Ok(len) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::Integer(len as i64));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "LPUSH" || cmd_upper == "RPUSH" {
                     if args.len() < 2 {
@@ -261,50 +1591,217 @@ impl Interpreter {
                         )));
                     }
                     let values = args[1..].to_vec();
+                    let cap = self
+                        .config
+                        .read()
+                        .await
+                        .capped_list
+                        .iter()
+                        .find(|rule| rule.matches(key))
+                        .map(|rule| (rule.max_len, rule.policy))
+                        .filter(|(max_len, _)| *max_len > 0);
                     let mut db = self.db.write().await;
 
+                    if let Some((max_len, CapPolicy::Reject)) = cap {
+                        let current = match db.llen(key) {
+                            Ok(len) => len,
+                            Err(e) => return ExecutionResult::Response(e.into()),
+                        };
+                        if current + values.len() > max_len {
+                            return ExecutionResult::Response(RespValue::Error(format!(
+                                "list is capped at {} elements",
+                                max_len
+                            )));
+                        }
+                    }
+
                     let result = if cmd_upper == "LPUSH" {
-                        db.lpush(key, values)
+                        db.lpush(key.clone(), values)
                     } else {
-                        db.rpush(key, values)
+                        db.rpush(key.clone(), values)
                     };
 
                     match result {
-                        Ok(len) => {
+                        Ok(mut len) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
+                            // A full capped list drops elements from the
+                            // other end, in the same step as the push
+                            if let Some((max_len, CapPolicy::Trim)) = cap {
+                                if len > max_len {
+                                    let (start, stop) = if cmd_upper == "LPUSH" {
+                                        (0, max_len as i64 - 1)
+                                    } else {
+                                        (-(max_len as i64), -1)
+                                    };
+                                    db.ltrim(key.clone(), start, stop);
+                                    len = max_len;
+                                    if let Err(e) = aof.append(vec![
+                                        "LTRIM".to_string(),
+                                        key.clone(),
+                                        start.to_string(),
+                                        stop.to_string(),
+                                    ]) {
+                                        error!("AOF write error: {}", e);
+                                    }
+                                }
+                            }
+                            blocking::signal_ready();
                             return ExecutionResult::Response(RespValue::Integer(len as i64));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if (cmd_upper == "LPOP" || cmd_upper == "RPOP") && args.len() > 1 {
+                    // LPOP/RPOP key count: reply with an array
+                    if args.len() > 2 {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
+                    }
+                    let count = match args[1].parse::<usize>() {
+                        Ok(count) => count,
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is out of range, must be positive".to_string(),
+                            ))
+                        }
+                    };
+                    let mut db = self.db.write().await;
+                    let existed = db.exists(key);
+                    let result = if cmd_upper == "LPOP" {
+                        db.lpop_count(key.clone(), count)
+                    } else {
+                        db.rpop_count(key.clone(), count)
+                    };
+
+                    match result {
+                        Ok(values) => {
+                            if !values.is_empty() {
+                                let mut aof = self.aof.write().await;
+                                if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                    error!("AOF write error: {}", e);
+                                }
+                            }
+                            if !existed {
+                                return ExecutionResult::Response(RespValue::Array(None));
+                            }
+                            let values = values.into_iter().map(|v| RespValue::BulkString(Some(v))).collect();
+                            return ExecutionResult::Response(RespValue::Array(Some(values)));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "LPOP" || cmd_upper == "RPOP" {
                     let mut db = self.db.write().await;
                     let result = if cmd_upper == "LPOP" {
-                        db.lpop(key)
+                        db.lpop(key.clone())
                     } else {
-                        db.rpop(key)
+                        db.rpop(key.clone())
                     };
 
                     match result {
                         Ok(Some(val)) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
                             return ExecutionResult::Response(RespValue::BulkString(Some(val)));
                         }
                         Ok(None) => return ExecutionResult::Response(RespValue::BulkString(None)),
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "LPOS" {
+                    // LPOS key element [RANK rank] [COUNT num] [MAXLEN len]
+                    if args.len() < 2 || !args.len().is_multiple_of(2) {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'LPOS' command".to_string(),
+                        ));
+                    }
+                    let (mut rank, mut count, mut maxlen) = (1i64, None, 0usize);
+                    for option in args[2..].chunks(2) {
+                        let value = match option[1].parse::<i64>() {
+                            Ok(value) => value,
+                            Err(_) => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "value is not an integer or out of range".to_string(),
+                                ))
+                            }
+                        };
+                        match option[0].to_uppercase().as_str() {
+                            "RANK" if value == 0 || value == i64::MIN => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".to_string(),
+                                ))
+                            }
+                            "RANK" => rank = value,
+                            "COUNT" if value < 0 => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "COUNT can't be negative".to_string(),
+                                ))
+                            }
+                            "COUNT" => count = Some(value as usize),
+                            "MAXLEN" if value < 0 => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "MAXLEN can't be negative".to_string(),
+                                ))
+                            }
+                            "MAXLEN" => maxlen = value as usize,
+                            _ => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "syntax error".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    let mut db = self.db.write().await;
+                    return match db.lpos_matches(key.clone(), args[1].clone(), rank, count.unwrap_or(1), maxlen) {
+                        // Without COUNT the reply is the first match or nil
+                        Ok(positions) if count.is_none() => ExecutionResult::Response(
+                            positions
+                                .first()
+                                .map_or(RespValue::BulkString(None), |&pos| RespValue::Integer(pos as i64)),
+                        ),
+                        Ok(positions) => ExecutionResult::Response(RespValue::Array(Some(
+                            positions.into_iter().map(|pos| RespValue::Integer(pos as i64)).collect(),
+                        ))),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "LMPOP" || cmd_upper == "ZMPOP" {
+                    return ExecutionResult::Response(self.mpop(&cmd_upper, &args).await);
+                } else if cmd_upper == "BLMPOP" || cmd_upper == "BZMPOP" {
+                    // BLMPOP/BZMPOP timeout numkeys key [key ...] ...
+                    let timeout = match args.first().map(|t| blocking::parse_timeout(t)) {
+                        Some(Ok(timeout)) => timeout,
+                        Some(Err(e)) => return ExecutionResult::Response(RespValue::Error(e.to_string())),
+                        None => {
+                            return ExecutionResult::Response(RespValue::Error(format!(
+                                "wrong number of arguments for '{}' command",
+                                cmd_upper
+                            )))
+                        }
+                    };
+                    let pop = &cmd_upper[1..];
+                    let reply = self.mpop(pop, &args[1..]).await;
+                    if reply != RespValue::Array(None) {
+                        return ExecutionResult::Response(reply);
                     }
+                    let retry = std::iter::once(pop.to_string())
+                        .chain(args[1..].iter().cloned())
+                        .map(|arg| RespValue::BulkString(Some(arg)))
+                        .collect();
+                    return ExecutionResult::Block(Blocked {
+                        retry: RespValue::Array(Some(retry)),
+                        timeout,
+                    });
                 } else if cmd_upper == "LLEN" {
                     let mut db = self.db.write().await;
                     match db.llen(key) {
                         Ok(len) => {
                             return ExecutionResult::Response(RespValue::Integer(len as i64))
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "LRANGE" {
                     if args.len() != 3 {
@@ -328,7 +1825,7 @@ impl Interpreter {
                                         resp_values,
                                     )));
                                 }
-                                Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                                Err(e) => return ExecutionResult::Response(e.into()),
                             }
                         }
                         _ => {
@@ -337,25 +1834,32 @@ impl Interpreter {
                             ));
                         }
                     }
-                } else if cmd_upper == "HSET" {
-                    if args.len() != 3 {
-                        return ExecutionResult::Response(RespValue::Error(
-                            "wrong number of arguments for 'HSET' command".to_string(),
-                        ));
+                } else if cmd_upper == "HSET" || cmd_upper == "HMSET" {
+                    // HMSET is a deprecated alias that replies OK instead of the new field count
+                    if args.len() < 3 || args.len().is_multiple_of(2) {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
                     }
-                    let field = args[1].clone();
-                    let value = args[2].clone();
+                    let pairs: Vec<(String, String)> = args[1..]
+                        .chunks(2)
+                        .map(|pair| (pair[0].clone(), pair[1].clone()))
+                        .collect();
 
                     let mut db = self.db.write().await;
-                    match db.hset(key, field, value) {
-                        Ok(val) => {
+                    match db.hset_multi(key.clone(), pairs) {
+                        Ok(added) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
-                            return ExecutionResult::Response(RespValue::Integer(val as i64));
+                            if cmd_upper == "HMSET" {
+                                return ExecutionResult::Response(RespValue::ok());
+                            }
+                            return ExecutionResult::Response(RespValue::Integer(added as i64));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "HGET" {
                     if args.len() != 2 {
@@ -363,7 +1867,7 @@ impl Interpreter {
                             "wrong number of arguments for 'HGET' command".to_string(),
                         ));
                     }
-                    let field = args[1].clone();
+                    let field = &args[1];
 
                     let mut db = self.db.write().await;
                     match db.hget(key, field) {
@@ -371,7 +1875,7 @@ impl Interpreter {
                             return ExecutionResult::Response(RespValue::BulkString(Some(val)))
                         }
                         Ok(None) => return ExecutionResult::Response(RespValue::BulkString(None)),
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "HGETALL" {
                     let mut db = self.db.write().await;
@@ -383,7 +1887,7 @@ impl Interpreter {
                                 .collect();
                             return ExecutionResult::Response(RespValue::Array(Some(resp_values)));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "HDEL" {
                     if args.len() != 2 {
@@ -394,55 +1898,645 @@ impl Interpreter {
                     let field = args[1].clone();
 
                     let mut db = self.db.write().await;
-                    match db.hdel(key, field) {
+                    match db.hdel(key.clone(), field) {
                         Ok(val) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
                             return ExecutionResult::Response(RespValue::Integer(val as i64));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
-                } else if cmd_upper == "EXPIRE" {
-                    if let Some(seconds_str) = args.get(1) {
-                        if let Ok(seconds) = seconds_str.parse::<u64>() {
-                            let mut db = self.db.write().await;
-                            let result = db.expire(&key, seconds);
+                } else if cmd_upper == "HMGET" {
+                    if args.len() < 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HMGET' command".to_string(),
+                        ));
+                    }
+                    let fields = args[1..].to_vec();
 
-                            if result {
+                    let mut db = self.db.write().await;
+                    match db.hmget(key, fields) {
+                        Ok(values) => {
+                            let resp_values: Vec<RespValue> =
+                                values.into_iter().map(RespValue::BulkString).collect();
+                            return ExecutionResult::Response(RespValue::Array(Some(resp_values)));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "HSETNX" {
+                    if args.len() != 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HSETNX' command".to_string(),
+                        ));
+                    }
+                    let field = args[1].clone();
+                    let value = args[2].clone();
+
+                    let mut db = self.db.write().await;
+                    match db.hsetnx(key.clone(), field, value) {
+                        Ok(set) => {
+                            if set {
                                 let mut aof = self.aof.write().await;
-                                if let Err(e) = aof.append(full_cmd_args) {
+                                if let Err(e) = aof.append_command(&cmd_string, &args) {
                                     error!("AOF write error: {}", e);
                                 }
                             }
-
-                            return ExecutionResult::Response(RespValue::Integer(if result {
-                                1
-                            } else {
-                                0
-                            }));
+                            return ExecutionResult::Response(RespValue::Integer(set as i64));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "HINCRBY" {
+                    if args.len() != 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HINCRBY' command".to_string(),
+                        ));
+                    }
+                    let field = args[1].clone();
+                    let delta = match args[2].parse::<i64>() {
+                        Ok(d) => d,
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                    };
+
+                    let mut db = self.db.write().await;
+                    match db.hincrby(key.clone(), field, delta) {
+                        Ok(val) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::Integer(val));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "HINCRBYFLOAT" {
+                    if args.len() != 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HINCRBYFLOAT' command".to_string(),
+                        ));
+                    }
+                    let field = args[1].clone();
+                    let delta = match args[2].parse::<f64>() {
+                        Ok(d) if d.is_finite() => d,
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not a valid float".to_string(),
+                            ));
+                        }
+                    };
+
+                    let mut db = self.db.write().await;
+                    match db.hincrbyfloat(key.clone(), field, delta) {
+                        Ok(val) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            return ExecutionResult::Response(RespValue::BulkString(Some(
+                                val.to_string(),
+                            )));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "HRANDFIELD" {
+                    if args.is_empty() || args.len() > 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HRANDFIELD' command".to_string(),
+                        ));
+                    }
+                    let count = match args.get(1).map(|c| c.parse::<i64>()) {
+                        Some(Ok(c)) => Some(c),
+                        Some(Err(_)) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                        None => None,
+                    };
+                    let withvalues = match args.get(2) {
+                        Some(opt) if opt.to_uppercase() == "WITHVALUES" => true,
+                        Some(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "syntax error".to_string(),
+                            ));
+                        }
+                        None => false,
+                    };
+
+                    let mut db = self.db.write().await;
+                    return match db.hrandfield(key, count.unwrap_or(1)) {
+                        Ok(pairs) => match count {
+                            None => ExecutionResult::Response(RespValue::BulkString(
+                                pairs.into_iter().next().map(|(f, _)| f),
+                            )),
+                            Some(_) => {
+                                let resp: Vec<RespValue> = pairs
+                                    .into_iter()
+                                    .flat_map(|(field, value)| {
+                                        if withvalues {
+                                            vec![
+                                                RespValue::BulkString(Some(field)),
+                                                RespValue::BulkString(Some(value)),
+                                            ]
+                                        } else {
+                                            vec![RespValue::BulkString(Some(field))]
+                                        }
+                                    })
+                                    .collect();
+                                ExecutionResult::Response(RespValue::Array(Some(resp)))
+                            }
+                        },
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "HSCAN" {
+                    if args.len() < 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HSCAN' command".to_string(),
+                        ));
+                    }
+                    let (cursor, options) = match parse_scan_args(&cmd_upper, &args[1..]) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
+                    let mut db = self.db.write().await;
+                    return match db.hscan(key, cursor, options.pattern.as_deref(), options.count) {
+                        Ok((next, pairs)) => {
+                            let items = pairs
+                                .into_iter()
+                                .flat_map(|(field, value)| {
+                                    if options.novalues {
+                                        vec![RespValue::BulkString(Some(field))]
+                                    } else {
+                                        vec![
+                                            RespValue::BulkString(Some(field)),
+                                            RespValue::BulkString(Some(value)),
+                                        ]
+                                    }
+                                })
+                                .collect();
+                            ExecutionResult::Response(scan_reply(next, items))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "HLEN" {
+                    let mut db = self.db.write().await;
+                    let len = db.hlen(key);
+                    return ExecutionResult::Response(RespValue::Integer(len as i64));
+                } else if cmd_upper == "HEXISTS" {
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HEXISTS' command".to_string(),
+                        ));
+                    }
+                    let mut db = self.db.write().await;
+                    let exists = db.hexists(key, &args[1]);
+                    return ExecutionResult::Response(RespValue::Integer(exists as i64));
+                } else if cmd_upper == "HKEYS" || cmd_upper == "HVALS" {
+                    let mut db = self.db.write().await;
+                    let items = if cmd_upper == "HKEYS" {
+                        db.hkeys(key)
+                    } else {
+                        db.hvals(key)
+                    };
+                    let resp_items: Vec<RespValue> = items
+                        .into_iter()
+                        .map(|s| RespValue::BulkString(Some(s)))
+                        .collect();
+                    return ExecutionResult::Response(RespValue::Array(Some(resp_items)));
+                } else if cmd_upper == "HSTRLEN" {
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'HSTRLEN' command".to_string(),
+                        ));
+                    }
+                    let mut db = self.db.write().await;
+                    let len = db.hstrlen(key, &args[1]);
+                    return ExecutionResult::Response(RespValue::Integer(len as i64));
+                } else if ["HEXPIRE", "HPEXPIRE", "HEXPIREAT", "HPEXPIREAT"].contains(&cmd_upper.as_str()) {
+                    if args.len() < 5 {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
+                    }
+                    // Seconds or milliseconds, relative or absolute, as for EXPIRE
+                    let millis = match args[1].parse::<u64>() {
+                        Ok(n) if cmd_upper.starts_with("HEXPIRE") => n.checked_mul(1000),
+                        Ok(n) => Some(n),
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                    };
+                    let at = millis.and_then(|ms| {
+                        if cmd_upper.ends_with("AT") {
+                            instant_from_unix_millis(ms)
                         } else {
+                            std::time::Instant::now().checked_add(std::time::Duration::from_millis(ms))
+                        }
+                    });
+                    let Some(at) = at else {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "invalid expire time in '{}' command",
+                            cmd_upper.to_lowercase()
+                        )));
+                    };
+                    let parsed = match FieldTtlArgs::parse(&args[2..], true) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    };
+
+                    let mut db = self.db.write().await;
+                    return match db.hexpire(key.clone(), at, parsed.condition, parsed.fields.clone()) {
+                        Ok(codes) => {
+                            // Log the absolute time for the fields that changed, so
+                            // replay does not extend their TTLs
+                            let changed: Vec<String> = parsed
+                                .fields
+                                .into_iter()
+                                .zip(&codes)
+                                .filter(|(_, &code)| code > 0)
+                                .map(|(field, _)| field)
+                                .collect();
+                            if !changed.is_empty() {
+                                let mut cmd = vec![
+                                    "HPEXPIREAT".to_string(),
+                                    key.clone(),
+                                    unix_millis_from_instant(at).to_string(),
+                                    "FIELDS".to_string(),
+                                    changed.len().to_string(),
+                                ];
+                                cmd.extend(changed);
+                                let mut aof = self.aof.write().await;
+                                if let Err(e) = aof.append(cmd) {
+                                    error!("AOF write error: {}", e);
+                                }
+                            }
+                            ExecutionResult::Response(RespValue::Array(Some(
+                                codes.into_iter().map(RespValue::Integer).collect(),
+                            )))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "HTTL" || cmd_upper == "HPTTL" || cmd_upper == "HPERSIST" {
+                    let parsed = match FieldTtlArgs::parse(&args[1..], false) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    };
+
+                    let mut db = self.db.write().await;
+                    let result = match cmd_upper.as_str() {
+                        "HTTL" => db.httl(key, parsed.fields),
+                        "HPTTL" => db.hpttl(key, parsed.fields),
+                        _ => db.hpersist(key.clone(), parsed.fields),
+                    };
+                    return match result {
+                        Ok(codes) => {
+                            if cmd_upper == "HPERSIST" && codes.contains(&1) {
+                                let mut aof = self.aof.write().await;
+                                if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                    error!("AOF write error: {}", e);
+                                }
+                            }
+                            ExecutionResult::Response(RespValue::Array(Some(
+                                codes.into_iter().map(RespValue::Integer).collect(),
+                            )))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "SESSION.CREATE" || cmd_upper == "SESSION.REFRESH" {
+                    // SESSION.CREATE needs at least one field; SESSION.REFRESH may only touch the TTL
+                    let min_args = if cmd_upper == "SESSION.CREATE" { 4 } else { 2 };
+                    if args.len() < min_args || !args.len().is_multiple_of(2) {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
+                    }
+                    let at = match session_expiry(&cmd_upper, &args[1]) {
+                        Ok(at) => at,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
+                    let pairs: Vec<(String, String)> = args[2..]
+                        .chunks(2)
+                        .map(|pair| (pair[0].clone(), pair[1].clone()))
+                        .collect();
+
+                    let mut db = self.db.write().await;
+                    let result = if cmd_upper == "SESSION.CREATE" {
+                        db.session_create(key.clone(), pairs, at)
+                    } else {
+                        db.session_refresh(key.clone(), pairs, at)
+                    };
+                    return match result {
+                        Ok(applied) => {
+                            if applied {
+                                // Logged as HSET + PEXPIREAT so replay keeps the original deadline
+                                let mut aof = self.aof.write().await;
+                                for cmd in session_aof_records(key, &args[2..], at) {
+                                    if let Err(e) = aof.append(cmd) {
+                                        error!("AOF write error: {}", e);
+                                    }
+                                }
+                            }
+                            ExecutionResult::Response(RespValue::Integer(applied as i64))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "SESSION.GET" {
+                    if args.len() < 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'SESSION.GET' command".to_string(),
+                        ));
+                    }
+                    let at = match session_expiry(&cmd_upper, &args[1]) {
+                        Ok(at) => at,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
+
+                    let mut db = self.db.write().await;
+                    return match db.session_get(key, &args[2..], at) {
+                        Ok(Some(values)) => {
+                            let mut aof = self.aof.write().await;
+                            for cmd in session_aof_records(key, &[], at) {
+                                if let Err(e) = aof.append(cmd) {
+                                    error!("AOF write error: {}", e);
+                                }
+                            }
+                            // The whole session as field/value pairs, or just the requested values
+                            let items = if args.len() == 2 {
+                                values
+                                    .into_iter()
+                                    .flat_map(|(field, value)| {
+                                        [RespValue::BulkString(Some(field)), RespValue::BulkString(value)]
+                                    })
+                                    .collect()
+                            } else {
+                                values
+                                    .into_iter()
+                                    .map(|(_, value)| RespValue::BulkString(value))
+                                    .collect()
+                            };
+                            ExecutionResult::Response(RespValue::Array(Some(items)))
+                        }
+                        Ok(None) => ExecutionResult::Response(RespValue::Array(None)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "PQ.PUSH" {
+                    if args.len() < 3 || args.len().is_multiple_of(2) {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'PQ.PUSH' command".to_string(),
+                        ));
+                    }
+                    let mut messages = Vec::with_capacity(args.len() / 2);
+                    for pair in args[1..].chunks(2) {
+                        match pair[0].parse::<i64>() {
+                            Ok(priority) => messages.push((priority, pair[1].clone())),
+                            Err(_) => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "value is not an integer or out of range".to_string(),
+                                ));
+                            }
+                        }
+                    }
+
+                    let mut db = self.db.write().await;
+                    return match db.pq_push(key.clone(), messages) {
+                        Ok(ids) => {
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                error!("AOF write error: {}", e);
+                            }
+                            ExecutionResult::Response(RespValue::Array(Some(
+                                ids.into_iter().map(|id| RespValue::Integer(id as i64)).collect(),
+                            )))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "PQ.POP" {
+                    // PQ.POP key visibility-seconds [COUNT count]
+                    if args.len() != 2 && args.len() != 4 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'PQ.POP' command".to_string(),
+                        ));
+                    }
+                    let visibility = match args[1].parse::<u64>() {
+                        Ok(secs) if secs > 0 => secs,
+                        Ok(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "invalid visibility timeout in 'pq.pop' command".to_string(),
+                            ));
+                        }
+                        Err(_) => {
                             return ExecutionResult::Response(RespValue::Error(
                                 "value is not an integer or out of range".to_string(),
                             ));
                         }
+                    };
+                    let count = if args.len() == 4 {
+                        if !args[2].eq_ignore_ascii_case("COUNT") {
+                            return ExecutionResult::Response(RespValue::Error("syntax error".to_string()));
+                        }
+                        match args[3].parse::<usize>() {
+                            Ok(count) if count > 0 => Some(count),
+                            _ => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "value is out of range, must be positive".to_string(),
+                                ));
+                            }
+                        }
                     } else {
+                        None
+                    };
+                    let now_ms = unix_millis_now();
+                    let deadline = match visibility.checked_mul(1000).and_then(|ms| now_ms.checked_add(ms)) {
+                        Some(deadline) => deadline,
+                        None => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "invalid visibility timeout in 'pq.pop' command".to_string(),
+                            ));
+                        }
+                    };
+
+                    let mut db = self.db.write().await;
+                    let popped = match db.pq_pop(key, count.unwrap_or(1), deadline, now_ms) {
+                        Ok(popped) => popped,
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    };
+                    if !popped.is_empty() {
+                        // Logged with the chosen ids so replay leases the same messages
+                        let mut cmd = vec!["PQ.LEASE".to_string(), key.clone(), deadline.to_string()];
+                        cmd.extend(popped.iter().map(|(id, _)| id.to_string()));
+                        let mut aof = self.aof.write().await;
+                        if let Err(e) = aof.append(cmd) {
+                            error!("AOF write error: {}", e);
+                        }
+                    }
+
+                    let mut messages = popped.into_iter().map(|(id, payload)| {
+                        RespValue::Array(Some(vec![
+                            RespValue::Integer(id as i64),
+                            RespValue::BulkString(Some(payload)),
+                        ]))
+                    });
+                    // Like LPOP: a single message (or nil) without COUNT, an array with it
+                    return match count {
+                        Some(_) => ExecutionResult::Response(RespValue::Array(Some(messages.collect()))),
+                        None => ExecutionResult::Response(messages.next().unwrap_or(RespValue::Array(None))),
+                    };
+                } else if cmd_upper == "PQ.ACK" {
+                    if args.len() < 2 {
                         return ExecutionResult::Response(RespValue::Error(
-                            "wrong number of arguments for 'EXPIRE' command".to_string(),
+                            "wrong number of arguments for 'PQ.ACK' command".to_string(),
                         ));
                     }
-                } else if cmd_upper == "TTL" {
+                    let ids = match args[1..].iter().map(|id| id.parse::<u64>()).collect::<Result<Vec<_>, _>>() {
+                        Ok(ids) => ids,
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                    };
+
+                    let mut db = self.db.write().await;
+                    return match db.pq_ack(key, &ids) {
+                        Ok(acked) => {
+                            if acked > 0 {
+                                let mut aof = self.aof.write().await;
+                                if let Err(e) = aof.append_command(&cmd_string, &args) {
+                                    error!("AOF write error: {}", e);
+                                }
+                            }
+                            ExecutionResult::Response(RespValue::Integer(acked as i64))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if [
+                    "TS.CREATE",
+                    "TS.ADD",
+                    "TS.GET",
+                    "TS.RANGE",
+                    "TS.MRANGE",
+                    "TS.CREATERULE",
+                    "TS.DELETERULE",
+                ]
+                .contains(&cmd_upper.as_str())
+                {
+                    return ExecutionResult::Response(self.timeseries_command(&cmd_upper, &cmd_string, &args).await);
+                } else if [
+                    "BF.RESERVE",
+                    "BF.ADD",
+                    "BF.MADD",
+                    "BF.EXISTS",
+                    "BF.MEXISTS",
+                    "BF.INFO",
+                    "CF.RESERVE",
+                    "CF.ADD",
+                    "CF.ADDNX",
+                    "CF.EXISTS",
+                    "CF.MEXISTS",
+                    "CF.COUNT",
+                    "CF.DEL",
+                    "CF.INFO",
+                ]
+                .contains(&cmd_upper.as_str())
+                {
+                    return ExecutionResult::Response(self.filter_command(&cmd_upper, &cmd_string, &args).await);
+                } else if ["VECTOR.ADD", "VECTOR.GET", "VECTOR.DEL", "VECTOR.CARD", "VECTOR.SEARCH"]
+                    .contains(&cmd_upper.as_str())
+                {
+                    return ExecutionResult::Response(self.vector_command(&cmd_upper, &cmd_string, &args).await);
+                } else if ["EXPIRE", "PEXPIRE", "EXPIREAT", "PEXPIREAT"].contains(&cmd_upper.as_str()) {
+                    if args.len() < 2 || args.len() > 3 {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
+                    }
+                    let amount = match args[1].parse::<i64>() {
+                        Ok(n) => n,
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                    };
+                    let condition = match args.get(2) {
+                        Some(flag) => match ExpireCondition::parse(flag) {
+                            Some(c) => c,
+                            None => {
+                                return ExecutionResult::Response(RespValue::Error(format!(
+                                    "Unsupported option {}",
+                                    flag
+                                )));
+                            }
+                        },
+                        None => ExpireCondition::Always,
+                    };
+
+                    // Seconds or milliseconds, relative or absolute; negative values are in the past
+                    let millis = match cmd_upper.as_str() {
+                        "EXPIRE" | "EXPIREAT" => amount.checked_mul(1000),
+                        _ => Some(amount),
+                    };
+                    let at = millis.and_then(|ms| {
+                        let ms = ms.max(0) as u64;
+                        if cmd_upper.ends_with("AT") {
+                            instant_from_unix_millis(ms)
+                        } else {
+                            std::time::Instant::now().checked_add(std::time::Duration::from_millis(ms))
+                        }
+                    });
+                    let at = match at {
+                        Some(at) => at,
+                        None => {
+                            return ExecutionResult::Response(RespValue::Error(format!(
+                                "invalid expire time in '{}' command",
+                                cmd_upper.to_lowercase()
+                            )));
+                        }
+                    };
+
+                    let mut db = self.db.write().await;
+                    let result = db.expire_with(key, at, condition);
+
+                    if result {
+                        // Log the absolute time so replay does not extend the TTL
+                        let mut aof = self.aof.write().await;
+                        let cmd = vec![
+                            "PEXPIREAT".to_string(),
+                            key.clone(),
+                            unix_millis_from_instant(at).to_string(),
+                        ];
+                        if let Err(e) = aof.append(cmd) {
+                            error!("AOF write error: {}", e);
+                        }
+                    }
+
+                    return ExecutionResult::Response(RespValue::Integer(result as i64));
+                } else if ["TTL", "PTTL", "EXPIRETIME", "PEXPIRETIME"].contains(&cmd_upper.as_str()) {
                     let mut db = self.db.write().await;
-                    let ttl = db.ttl(&key);
-                    return ExecutionResult::Response(RespValue::Integer(ttl));
+                    let value = match cmd_upper.as_str() {
+                        "TTL" => db.ttl(key),
+                        "PTTL" => db.pttl(key),
+                        "EXPIRETIME" => db.expiretime(key),
+                        _ => db.pexpiretime(key),
+                    };
+                    return ExecutionResult::Response(RespValue::Integer(value));
                 } else if cmd_upper == "PERSIST" {
                     let mut db = self.db.write().await;
-                    let result = db.persist(&key);
+                    let result = db.persist(key);
 
                     if result {
                         let mut aof = self.aof.write().await;
-                        if let Err(e) = aof.append(full_cmd_args) {
+                        if let Err(e) = aof.append_command(&cmd_string, &args) {
                             error!("AOF write error: {}", e);
                         }
                     }
@@ -460,15 +2554,15 @@ impl Interpreter {
                     }
                     let members = args[1..].to_vec();
                     let mut db = self.db.write().await;
-                    match db.sadd(key, members) {
+                    match db.sadd(key.clone(), members) {
                         Ok(added) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
                             return ExecutionResult::Response(RespValue::Integer(added as i64));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "SREM" {
                     if args.len() != 2 {
@@ -478,15 +2572,15 @@ impl Interpreter {
                     }
                     let member = args[1].clone();
                     let mut db = self.db.write().await;
-                    match db.srem(key, member) {
+                    match db.srem(key.clone(), member) {
                         Ok(removed) => {
                             let mut aof = self.aof.write().await;
-                            if let Err(e) = aof.append(full_cmd_args) {
+                            if let Err(e) = aof.append_command(&cmd_string, &args) {
                                 error!("AOF write error: {}", e);
                             }
                             return ExecutionResult::Response(RespValue::Integer(removed as i64));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "SMEMBERS" {
                     let mut db = self.db.write().await;
@@ -498,15 +2592,36 @@ impl Interpreter {
                                 .collect();
                             return ExecutionResult::Response(RespValue::Array(Some(resp_members)));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                } else if cmd_upper == "SSCAN" {
+                    if args.len() < 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'SSCAN' command".to_string(),
+                        ));
                     }
+                    let (cursor, options) = match parse_scan_args(&cmd_upper, &args[1..]) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
+                    let mut db = self.db.write().await;
+                    return match db.sscan(key, cursor, options.pattern.as_deref(), options.count) {
+                        Ok((next, members)) => {
+                            let items = members
+                                .into_iter()
+                                .map(|m| RespValue::BulkString(Some(m)))
+                                .collect();
+                            ExecutionResult::Response(scan_reply(next, items))
+                        }
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
                 } else if cmd_upper == "SISMEMBER" {
                     if args.len() != 2 {
                         return ExecutionResult::Response(RespValue::Error(
                             "wrong number of arguments for 'SISMEMBER' command".to_string(),
                         ));
                     }
-                    let member = args[1].clone();
+                    let member = &args[1];
                     let mut db = self.db.write().await;
                     match db.sismember(key, member) {
                         Ok(exists) => {
@@ -516,7 +2631,7 @@ impl Interpreter {
                                 0
                             }))
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 } else if cmd_upper == "SCARD" {
                     let mut db = self.db.write().await;
@@ -524,60 +2639,152 @@ impl Interpreter {
                         Ok(count) => {
                             return ExecutionResult::Response(RespValue::Integer(count as i64))
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
-                } else if cmd_upper == "PUBLISH" {
-                    if tokens.len() != 3 {
-                        return ExecutionResult::Response(RespValue::Error(
-                            "ERR wrong number of arguments for 'publish' command".to_string(),
-                        ));
-                    }
-
-                    let channel = match &tokens[1] {
-                        RespValue::BulkString(Some(s)) => s.clone(),
-                        RespValue::SimpleString(s) => s.clone(),
-                        _ => {
+                } else if cmd_upper == "SINTERCARD" {
+                    // SINTERCARD numkeys key [key ...] [LIMIT limit]
+                    let numkeys = match args.first().map(|n| n.parse::<usize>()) {
+                        Some(Ok(n)) if n > 0 => n,
+                        Some(_) => {
                             return ExecutionResult::Response(RespValue::Error(
-                                "ERR channel name must be a string".to_string(),
-                            ));
+                                "numkeys should be greater than 0".to_string(),
+                            ))
                         }
-                    };
-
-                    let message = match &tokens[2] {
-                        RespValue::BulkString(Some(s)) => s.clone(),
-                        RespValue::SimpleString(s) => s.clone(),
-                        _ => {
+                        None => {
                             return ExecutionResult::Response(RespValue::Error(
-                                "ERR message must be a string".to_string(),
+                                "wrong number of arguments for 'SINTERCARD' command".to_string(),
                             ))
                         }
                     };
+                    let Some(keys) = args.get(1..1 + numkeys) else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "Number of keys can't be greater than number of args".to_string(),
+                        ));
+                    };
+                    let limit = match &args[1 + numkeys..] {
+                        [] => 0,
+                        [option, limit] if option.eq_ignore_ascii_case("LIMIT") => match limit.parse::<usize>() {
+                            Ok(limit) => limit,
+                            Err(_) => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "LIMIT can't be negative".to_string(),
+                                ))
+                            }
+                        },
+                        _ => return ExecutionResult::Response(RespValue::Error("syntax error".to_string())),
+                    };
+                    let mut db = self.db.write().await;
+                    return match db.sintercard(keys, limit) {
+                        Ok(count) => ExecutionResult::Response(RespValue::Integer(count as i64)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                } else if cmd_upper == "SORT" {
+                    let (opts, store) = match parse_sort_args(&args[1..]) {
+                        Ok(parsed) => parsed,
+                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                    };
+                    let mut db = self.db.write().await;
+                    let values = match db.sort(key, &opts) {
+                        Ok(values) => values,
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    };
+                    let Some(dst) = store else {
+                        return ExecutionResult::Response(RespValue::Array(Some(
+                            values.into_iter().map(RespValue::BulkString).collect(),
+                        )));
+                    };
+                    // Logged as the resulting list: without a BY pattern a
+                    // set sorts in its iteration order, which replay may not
+                    // reproduce
+                    let mut logged = vec![vec!["DEL".to_string(), dst.clone()]];
+                    if !values.is_empty() {
+                        logged.push(
+                            ["RPUSH".to_string(), dst.clone()]
+                                .into_iter()
+                                .chain(values.iter().map(|v| v.clone().unwrap_or_default()))
+                                .collect(),
+                        );
+                    }
+                    let len = db.sort_store(&dst, values);
+                    let mut aof = self.aof.write().await;
+                    for entry in logged {
+                        let _ = aof.append(entry);
+                    }
+                    if len > 0 {
+                        blocking::signal_ready();
+                    }
+                    return ExecutionResult::Response(RespValue::Integer(len as i64));
+                } else if cmd_upper == "PUBLISH" {
+                    let [channel, message] = args.as_slice() else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR wrong number of arguments for 'publish' command".to_string(),
+                        ));
+                    };
 
-                    let count = self.pubsub.publish(&channel, &message).await;
+                    let (retain_ttl, log_max_len) = {
+                        let config = self.config.read().await;
+                        (
+                            config.retain.iter().find(|rule| rule.matches(channel)).map(|rule| rule.ttl()),
+                            config
+                                .pubsub_log
+                                .iter()
+                                .find(|rule| rule.matches(channel))
+                                .map(|rule| if rule.max_len > 0 { rule.max_len } else { config.memory.stream_max_len }),
+                        )
+                    };
+                    // Logged before delivery, so a client that subscribes and
+                    // then reads the log never falls into a gap between them
+                    if let Some(max_len) = log_max_len {
+                        self.log_published(channel, message, max_len).await;
+                    }
+                    let count = self.pubsub.publish(channel, message).await;
+                    if let Some(ttl) = retain_ttl {
+                        self.pubsub.retain(channel, message, ttl).await;
+                    }
+                    return ExecutionResult::Response(RespValue::Integer(count as i64));
+                } else if cmd_upper == "SPUBLISH" {
+                    // Shard kanalı: yalnızca SSUBSCRIBE ile abone olanlara gider
+                    let [channel, message] = args.as_slice() else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR wrong number of arguments for 'spublish' command".to_string(),
+                        ));
+                    };
+                    let count = self.pubsub.spublish(channel, message).await;
                     return ExecutionResult::Response(RespValue::Integer(count as i64));
-                } else if cmd_upper == "SUBSCRIBE" {
+                } else if cmd_upper == "PUBSUB" {
+                    return ExecutionResult::Response(self.pubsub_command(&args).await);
+                } else if cmd_upper == "SUBSCRIBE" || cmd_upper == "PSUBSCRIBE" || cmd_upper == "SSUBSCRIBE" {
                     // SUBSCRIBE komutu özeldir: Bağlantıyı bloklar ve mesajları dinler.
                     // İstemci "abone" moduna geçer ve sadece pub/sub komutlarını gönderebilir.
                     // ExecutionResult::Subscribe döndürerek bağlantı yöneticisinin (connection handler)
                     // yayın akışı (streaming) moduna geçmesini sağlarız.
 
-                    let channel_name = match &tokens[1] {
-                        RespValue::BulkString(Some(s)) => s.clone(),
-                        RespValue::SimpleString(s) => s.clone(),
-                        _ => {
-                            return ExecutionResult::Response(RespValue::Error(
-                                "ERR channel name must be a string".to_string(),
-                            ))
-                        }
-                    };
+                    if args.is_empty() {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "ERR wrong number of arguments for '{}' command",
+                            cmd_upper.to_lowercase()
+                        )));
+                    }
 
-                    let receiver = self.pubsub.subscribe(&channel_name).await;
-                    return ExecutionResult::Subscribe(channel_name, receiver);
+                    let mut subscriber =
+                        Subscriber::new(Arc::clone(&self.pubsub), Arc::clone(&self.security), self.user.clone());
+                    let mut replies = Vec::new();
+                    match cmd_upper.as_str() {
+                        "SUBSCRIBE" => subscriber.subscribe(&args, &mut replies).await,
+                        "PSUBSCRIBE" => subscriber.psubscribe(&args, &mut replies).await,
+                        _ => subscriber.ssubscribe(&args, &mut replies).await,
+                    }
+                    return ExecutionResult::Subscribe(subscriber, replies);
                 } else if cmd_upper == "SAVE" {
                     // Synchronous snapshot save
-                    use crate::persistence::snapshot;
-                    match snapshot::save("dump.rdb", &self.db).await {
+                    if self.server_info.bgsave_in_progress() {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR Background save already in progress".to_string(),
+                        ));
+                    }
+                    match bgsave::save(&self.db).await {
                         Ok(_) => {
+                            self.server_info.record_save();
                             return ExecutionResult::Response(RespValue::SimpleString(
                                 "OK".to_string(),
                             ))
@@ -589,20 +2796,62 @@ impl Interpreter {
                             )))
                         }
                     }
-                } else if cmd_upper == "BGSAVE" {
-                    // Background snapshot save
-                    let db_clone = Arc::clone(&self.db);
-
-                    tokio::spawn(async move {
-                        use crate::persistence::snapshot;
-                        use tracing::{error, info};
-                        if let Err(e) = snapshot::save("dump.rdb", &db_clone).await {
-                            error!("Background save failed: {}", e);
-                        } else {
-                            info!("Background save completed successfully");
+                } else if cmd_upper == "PERSISTENCE" {
+                    let subcommand = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+                    if subcommand != "RELOCATE" {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR unknown subcommand for 'PERSISTENCE'. Try RELOCATE.".to_string(),
+                        ));
+                    }
+                    if args.len() != 2 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'PERSISTENCE RELOCATE' command".to_string(),
+                        ));
+                    }
+                    return match relocate::relocate(std::path::Path::new(&args[1]), &self.db, &self.aof).await {
+                        Ok(report) => ExecutionResult::Response(RespValue::BulkString(Some(format!(
+                            "relocated {} keys to {} in {}ms",
+                            report.keys,
+                            report.dir.display(),
+                            report.elapsed.as_millis()
+                        )))),
+                        Err(e) => ExecutionResult::Response(RespValue::Error(format!("ERR relocation failed: {}", e))),
+                    };
+                } else if cmd_upper == "BACKUP" {
+                    // BACKUP TO dir
+                    if args.len() != 2 || !args[0].eq_ignore_ascii_case("TO") {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR syntax error. Try BACKUP TO dir.".to_string(),
+                        ));
+                    }
+                    return match backup::backup(std::path::Path::new(&args[1]), &self.db, &self.aof).await {
+                        Ok(report) => ExecutionResult::Response(RespValue::BulkString(Some(
+                            report.dir.display().to_string(),
+                        ))),
+                        Err(e) => ExecutionResult::Response(RespValue::Error(format!("ERR backup failed: {}", e))),
+                    };
+                } else if cmd_upper == "DUMPALL" {
+                    // DUMPALL [RDB|AOF]: the whole dataset as one hex-encoded file
+                    let format = match args.first().map(|s| s.to_uppercase()).as_deref() {
+                        None | Some("RDB") if args.len() <= 1 => DumpFormat::Rdb,
+                        Some("AOF") if args.len() == 1 => DumpFormat::Aof,
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "ERR syntax error. Try DUMPALL [RDB|AOF].".to_string(),
+                            ))
                         }
-                    });
-
+                    };
+                    return match dump::dump_all(&self.db, format, dump::DUMPALL_MAX_BYTES).await {
+                        Ok(payload) => ExecutionResult::Response(RespValue::BulkString(Some(payload))),
+                        Err(e) => ExecutionResult::Response(RespValue::Error(format!("ERR dump failed: {}", e))),
+                    };
+                } else if cmd_upper == "BGSAVE" {
+                    // Background snapshot save, one at a time
+                    if !bgsave::start(&self.db, &self.server_info) {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR Background save already in progress".to_string(),
+                        ));
+                    }
                     return ExecutionResult::Response(RespValue::SimpleString(
                         "Background saving started".to_string(),
                     ));
@@ -613,6 +2862,413 @@ impl Interpreter {
                     let size = db.dbsize();
                     return ExecutionResult::Response(RespValue::Integer(size as i64));
                 }
+                // ===== EXPIREMANY =====
+                // EXPIREMANY START pattern seconds|PERSIST [BATCH n]
+                // EXPIREMANY STATUS [id]
+                else if cmd_upper == "EXPIREMANY" {
+                    let subcommand = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+                    if subcommand == "START" {
+                        if args.len() != 3 && args.len() != 5 {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "wrong number of arguments for 'EXPIREMANY START' command".to_string(),
+                            ));
+                        }
+                        let action = if args[2].eq_ignore_ascii_case("PERSIST") {
+                            BulkTtlAction::Persist
+                        } else {
+                            match args[2].parse::<u64>() {
+                                Ok(secs) => BulkTtlAction::Expire(secs),
+                                Err(_) => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "value is not an integer or out of range".to_string(),
+                                    ));
+                                }
+                            }
+                        };
+                        let batch_size = match args.get(3).map(|s| s.to_uppercase()).as_deref() {
+                            None => 1000,
+                            Some("BATCH") => match args[4].parse::<usize>() {
+                                Ok(n) if n > 0 => n,
+                                _ => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "value is not an integer or out of range".to_string(),
+                                    ));
+                                }
+                            },
+                            Some(_) => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "syntax error".to_string(),
+                                ));
+                            }
+                        };
+                        let job = self.bulk_ttl_jobs.start(
+                            args[1].clone(),
+                            action,
+                            batch_size,
+                            Arc::clone(&self.db),
+                            Arc::clone(&self.aof),
+                        );
+                        return ExecutionResult::Response(RespValue::Integer(job.id as i64));
+                    } else if subcommand == "STATUS" {
+                        let jobs = match args.get(1) {
+                            Some(id) => match id.parse::<u64>().ok().and_then(|id| self.bulk_ttl_jobs.get(id)) {
+                                Some(job) => vec![job],
+                                None => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "ERR no such EXPIREMANY job".to_string(),
+                                    ));
+                                }
+                            },
+                            None => self.bulk_ttl_jobs.list(),
+                        };
+                        let resp: Vec<RespValue> = jobs
+                            .into_iter()
+                            .map(|job| {
+                                let fields = job
+                                    .status()
+                                    .into_iter()
+                                    .flat_map(|(k, v)| {
+                                        vec![RespValue::BulkString(Some(k)), RespValue::BulkString(Some(v))]
+                                    })
+                                    .collect();
+                                RespValue::Array(Some(fields))
+                            })
+                            .collect();
+                        return ExecutionResult::Response(RespValue::Array(Some(resp)));
+                    } else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR unknown subcommand for 'EXPIREMANY'. Try START or STATUS.".to_string(),
+                        ));
+                    }
+                }
+                // MEMORY COMPACT key: parçalanmış koleksiyonu yeniden oluştur
+                else if cmd_upper == "MEMORY" {
+                    let subcommand = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+                    if subcommand == "COMPACT" {
+                        if args.len() != 2 {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "wrong number of arguments for 'MEMORY COMPACT' command".to_string(),
+                            ));
+                        }
+                        let freed = self.db.write().await.compact_key(&args[1]);
+                        return ExecutionResult::Response(RespValue::Integer(freed.unwrap_or(0) as i64));
+                    }
+                    // MEMORY USAGE key [SAMPLES count]: değer tamamen ölçülür, SAMPLES yok sayılır
+                    if subcommand == "USAGE" {
+                        let samples_ok = match &args[1..] {
+                            [_] => true,
+                            [_, opt, count] => opt.eq_ignore_ascii_case("SAMPLES") && count.parse::<u64>().is_ok(),
+                            _ => false,
+                        };
+                        if !samples_ok {
+                            return ExecutionResult::Response(RespValue::Error("syntax error".to_string()));
+                        }
+                        let mut db = self.db.write().await;
+                        if !db.check_expiration(&args[1]) {
+                            return ExecutionResult::Response(RespValue::BulkString(None));
+                        }
+                        return ExecutionResult::Response(match db.items.get(&args[1]) {
+                            Some(entry) => RespValue::Integer(
+                                (args[1].len() + introspect::serialized_length(&entry.value)) as i64,
+                            ),
+                            None => RespValue::BulkString(None),
+                        });
+                    }
+                    return ExecutionResult::Response(RespValue::Error(
+                        "ERR unknown subcommand for 'MEMORY'. Try COMPACT or USAGE.".to_string(),
+                    ));
+                }
+                // ANALYZE [pattern] | ANALYZE STATUS [id] | ANALYZE RESULT id
+                else if cmd_upper == "ANALYZE" {
+                    let subcommand = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+                    let job = |id: Option<&String>| id.and_then(|id| id.parse::<u64>().ok()).and_then(|id| self.analyze_jobs.get(id));
+                    if subcommand == "STATUS" && args.len() <= 2 {
+                        let jobs = match args.get(1) {
+                            Some(_) => match job(args.get(1)) {
+                                Some(job) => vec![job],
+                                None => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "ERR no such ANALYZE job".to_string(),
+                                    ));
+                                }
+                            },
+                            None => self.analyze_jobs.list(),
+                        };
+                        return ExecutionResult::Response(RespValue::Array(Some(
+                            jobs.into_iter().map(|job| field_pairs(job.status())).collect(),
+                        )));
+                    } else if subcommand == "RESULT" && args.len() == 2 {
+                        let report = match job(args.get(1)) {
+                            Some(job) => match job.report() {
+                                Some(report) => report,
+                                None => return ExecutionResult::Response(RespValue::BulkString(None)),
+                            },
+                            None => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "ERR no such ANALYZE job".to_string(),
+                                ));
+                            }
+                        };
+                        // grup adı → istatistik çiftleri
+                        let groups = |groups: std::collections::BTreeMap<String, GroupStats>| {
+                            RespValue::Array(Some(
+                                groups
+                                    .into_iter()
+                                    .flat_map(|(name, stats)| [RespValue::BulkString(Some(name)), field_pairs(stats.fields())])
+                                    .collect(),
+                            ))
+                        };
+                        return ExecutionResult::Response(RespValue::Array(Some(vec![
+                            RespValue::BulkString(Some("total".to_string())),
+                            field_pairs(report.total.fields()),
+                            RespValue::BulkString(Some("types".to_string())),
+                            groups(report.by_type),
+                            RespValue::BulkString(Some("prefixes".to_string())),
+                            groups(report.by_prefix),
+                            RespValue::BulkString(Some("biggest".to_string())),
+                            biggest_keys_reply(report.biggest),
+                        ])));
+                    } else if args.len() <= 1 {
+                        let pattern = args.first().cloned().unwrap_or_else(|| "*".to_string());
+                        let job = self.analyze_jobs.start(pattern, 1000, Arc::clone(&self.db));
+                        return ExecutionResult::Response(RespValue::Integer(job.id as i64));
+                    } else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'ANALYZE' command".to_string(),
+                        ));
+                    }
+                }
+                // DBSTATS [FULL]
+                else if cmd_upper == "DBSTATS" {
+                    return ExecutionResult::Response(self.dbstats(&args).await);
+                }
+                // ===== FCALL / FUNCTION =====
+                else if cmd_upper == "FCALL" || cmd_upper == "FCALL_RO" {
+                    return ExecutionResult::Response(self.fcall(&cmd_upper, &args).await);
+                } else if cmd_upper == "FUNCTION" {
+                    return ExecutionResult::Response(self.function(&args));
+                }
+                // ===== SCHEDULE =====
+                else if cmd_upper == "SCHEDULE" {
+                    return ExecutionResult::Response(self.schedule(&args).await);
+                }
+                // ===== SLOWLOG =====
+                // SLOWLOG GET [count] | LEN | RESET
+                else if cmd_upper == "SLOWLOG" {
+                    match args.first().map(|s| s.to_uppercase()).as_deref() {
+                        Some("GET") if args.len() <= 2 => {
+                            // A negative count returns the whole log
+                            let count = match args.get(1).map(|c| c.parse::<i64>()) {
+                                None => Some(10),
+                                Some(Ok(n)) if n < 0 => None,
+                                Some(Ok(n)) => Some(n as usize),
+                                Some(Err(_)) => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "value is not an integer or out of range".to_string(),
+                                    ))
+                                }
+                            };
+                            let entries = self.slowlog.get(Some(count.unwrap_or(usize::MAX)));
+                            let reply = entries
+                                .into_iter()
+                                .map(|entry| {
+                                    let command = entry
+                                        .command
+                                        .into_iter()
+                                        .map(|arg| RespValue::BulkString(Some(arg)))
+                                        .collect();
+                                    RespValue::Array(Some(vec![
+                                        RespValue::Integer(entry.id as i64),
+                                        RespValue::Integer(entry.timestamp as i64),
+                                        RespValue::Integer(entry.duration_us as i64),
+                                        RespValue::Array(Some(command)),
+                                        RespValue::BulkString(Some(entry.client_addr)),
+                                        RespValue::BulkString(Some(entry.client_name.unwrap_or_default())),
+                                    ]))
+                                })
+                                .collect();
+                            return ExecutionResult::Response(RespValue::Array(Some(reply)));
+                        }
+                        Some("LEN") if args.len() == 1 => {
+                            return ExecutionResult::Response(RespValue::Integer(self.slowlog.len() as i64));
+                        }
+                        Some("RESET") if args.len() == 1 => {
+                            self.slowlog.reset();
+                            return ExecutionResult::Response(RespValue::SimpleString("OK".to_string()));
+                        }
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "ERR unknown subcommand or wrong number of arguments for 'SLOWLOG'. Try GET, LEN or RESET.".to_string(),
+                            ));
+                        }
+                    }
+                }
+                // ===== CLIENT =====
+                // CLIENT LIST
+                else if cmd_upper == "CLIENT" {
+                    match args.first().map(|s| s.to_uppercase()).as_deref() {
+                        Some("LIST") if args.len() == 1 => {
+                            let list: String = self
+                                .server_info
+                                .clients()
+                                .list()
+                                .iter()
+                                .map(|client| client.describe() + "\n")
+                                .collect();
+                            return ExecutionResult::Response(RespValue::BulkString(Some(list)));
+                        }
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "ERR unknown subcommand or wrong number of arguments for 'CLIENT'. Try LIST.".to_string(),
+                            ));
+                        }
+                    }
+                }
+                // ===== LATENCY =====
+                // LATENCY LATEST | HISTORY event | RESET [event ...] | DOCTOR
+                else if cmd_upper == "LATENCY" {
+                    let monitor = latency::monitor();
+                    match args.first().map(|s| s.to_uppercase()).as_deref() {
+                        Some("LATEST") if args.len() == 1 => {
+                            let reply = monitor
+                                .latest()
+                                .into_iter()
+                                .map(|entry| {
+                                    RespValue::Array(Some(vec![
+                                        RespValue::BulkString(Some(entry.event)),
+                                        RespValue::Integer(entry.latest.timestamp as i64),
+                                        RespValue::Integer(entry.latest.latency_ms as i64),
+                                        RespValue::Integer(entry.max_ms as i64),
+                                    ]))
+                                })
+                                .collect();
+                            return ExecutionResult::Response(RespValue::Array(Some(reply)));
+                        }
+                        Some("HISTORY") if args.len() == 2 => {
+                            let reply = monitor
+                                .history(&args[1])
+                                .into_iter()
+                                .map(|sample| {
+                                    RespValue::Array(Some(vec![
+                                        RespValue::Integer(sample.timestamp as i64),
+                                        RespValue::Integer(sample.latency_ms as i64),
+                                    ]))
+                                })
+                                .collect();
+                            return ExecutionResult::Response(RespValue::Array(Some(reply)));
+                        }
+                        Some("RESET") => {
+                            let reset = monitor.reset(&args[1..]);
+                            return ExecutionResult::Response(RespValue::Integer(reset as i64));
+                        }
+                        Some("DOCTOR") if args.len() == 1 => {
+                            return ExecutionResult::Response(RespValue::BulkString(Some(monitor.doctor())));
+                        }
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "ERR unknown subcommand or wrong number of arguments for 'LATENCY'. Try LATEST, HISTORY, RESET or DOCTOR.".to_string(),
+                            ));
+                        }
+                    }
+                }
+                // ===== DEBUG =====
+                // DEBUG FAILPOINT [name OFF|DELAY ms|DROP|PANIC]
+                else if cmd_upper == "DEBUG" {
+                    let subcommand = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+                    match subcommand.as_str() {
+                        "FAILPOINT" => {}
+                        "SLEEP" => {
+                            if args.len() != 2 {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "wrong number of arguments for 'DEBUG SLEEP' command".to_string(),
+                                ));
+                            }
+                            let seconds = match args[1].parse::<f64>() {
+                                Ok(s) if s.is_finite() && s >= 0.0 => s,
+                                _ => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "ERR value is not a valid float".to_string(),
+                                    ))
+                                }
+                            };
+                            // Block every client, as a stalled event loop would
+                            let _db = self.db.write().await;
+                            tokio::time::sleep(std::time::Duration::from_secs_f64(seconds)).await;
+                            return ExecutionResult::Response(RespValue::ok());
+                        }
+                        "OBJECT" => {
+                            if args.len() != 2 {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "wrong number of arguments for 'DEBUG OBJECT' command".to_string(),
+                                ));
+                            }
+                            let mut db = self.db.write().await;
+                            if !db.check_expiration(&args[1]) {
+                                return ExecutionResult::Response(RespValue::Error("ERR no such key".to_string()));
+                            }
+                            return match db.items.get(&args[1]) {
+                                Some(entry) => ExecutionResult::Response(RespValue::SimpleString(
+                                    introspect::describe(entry, db.packed_threshold()),
+                                )),
+                                None => ExecutionResult::Response(RespValue::Error("ERR no such key".to_string())),
+                            };
+                        }
+                        "SET-ACTIVE-EXPIRE" => {
+                            let enabled = match args.get(1).map(|s| s.as_str()) {
+                                Some("0") if args.len() == 2 => false,
+                                Some("1") if args.len() == 2 => true,
+                                _ => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "ERR DEBUG SET-ACTIVE-EXPIRE expects 0 or 1".to_string(),
+                                    ))
+                                }
+                            };
+                            self.db.write().await.set_active_expire(enabled);
+                            return ExecutionResult::Response(RespValue::ok());
+                        }
+                        "QUICKLIST-PACKED-THRESHOLD" => {
+                            let bytes = match args.get(1).and_then(|s| parse_memory_size(s)) {
+                                Some(bytes) if args.len() == 2 && bytes > 0 => bytes,
+                                _ => {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "ERR argument must be a memory value".to_string(),
+                                    ))
+                                }
+                            };
+                            self.db.write().await.set_packed_threshold(bytes);
+                            return ExecutionResult::Response(RespValue::ok());
+                        }
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "ERR unknown subcommand for 'DEBUG'. Try FAILPOINT, SLEEP, OBJECT, SET-ACTIVE-EXPIRE or QUICKLIST-PACKED-THRESHOLD.".to_string(),
+                            ));
+                        }
+                    }
+                    if args.len() == 1 {
+                        let armed: Vec<RespValue> = failpoint::list()
+                            .into_iter()
+                            .map(|(name, action)| RespValue::BulkString(Some(format!("{} {}", name, action))))
+                            .collect();
+                        return ExecutionResult::Response(RespValue::Array(Some(armed)));
+                    }
+                    if args.len() < 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'DEBUG FAILPOINT' command".to_string(),
+                        ));
+                    }
+                    let action = if args.len() == 3 && args[2].eq_ignore_ascii_case("OFF") {
+                        None
+                    } else {
+                        match FailAction::parse(&args[2..]) {
+                            Ok(action) => Some(action),
+                            Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        }
+                    };
+                    return match failpoint::set(&args[1], action) {
+                        Ok(()) => ExecutionResult::Response(RespValue::ok()),
+                        Err(e) => ExecutionResult::Response(RespValue::Error(e)),
+                    };
+                }
                 // ===== ZADD =====
                 else if cmd_upper == "ZADD" {
                     if args.len() < 3 || args.len() % 2 == 0 {
@@ -631,10 +3287,11 @@ impl Interpreter {
                     match db.zadd(key.clone(), members) {
                         Ok(added) => {
                             let mut aof = self.aof.write().await;
-                            let _ = aof.append(full_cmd_args);
+                            let _ = aof.append_command(&cmd_string, &args);
+                            blocking::signal_ready();
                             return ExecutionResult::Response(RespValue::Integer(added as i64));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 }
                 // ===== ZRANGE =====
@@ -648,7 +3305,7 @@ impl Interpreter {
                     let stop: i64 = args[2].parse().unwrap_or(-1);
                     let withscores = args.get(3).map(|s| s.to_uppercase() == "WITHSCORES").unwrap_or(false);
                     let mut db = self.db.write().await;
-                    let items = db.zrange(key.clone(), start, stop, withscores);
+                    let items = db.zrange(key, start, stop, withscores);
                     let resp: Vec<RespValue> = items.into_iter()
                         .flat_map(|(member, score)| {
                             if withscores {
@@ -671,17 +3328,82 @@ impl Interpreter {
                         ));
                     }
                     let mut db = self.db.write().await;
-                    match db.zscore(key.clone(), args[1].clone()) {
+                    match db.zscore(key, &args[1]) {
                         Some(score) => {
                             return ExecutionResult::Response(RespValue::BulkString(Some(score.to_string())));
                         }
                         None => return ExecutionResult::Response(RespValue::BulkString(None)),
                     }
                 }
+                // ===== ZRANK / ZREVRANK =====
+                else if cmd_upper == "ZRANK" || cmd_upper == "ZREVRANK" {
+                    if args.len() < 2 {
+                        return ExecutionResult::Response(RespValue::Error(format!(
+                            "wrong number of arguments for '{}' command",
+                            cmd_upper
+                        )));
+                    }
+                    let withscore = match &args[2..] {
+                        [] => false,
+                        [opt] if opt.eq_ignore_ascii_case("WITHSCORE") => true,
+                        _ => return ExecutionResult::Response(RespValue::Error("ERR syntax error".to_string())),
+                    };
+                    let mut db = self.db.write().await;
+                    let rank = if cmd_upper == "ZRANK" {
+                        db.zrank(key, &args[1])
+                    } else {
+                        db.zrevrank(key, &args[1])
+                    };
+                    return ExecutionResult::Response(match rank {
+                        Some(rank) if withscore => {
+                            let score = db.zscore(key, &args[1]);
+                            RespValue::Array(Some(vec![
+                                RespValue::Integer(rank as i64),
+                                RespValue::BulkString(score.map(|s| s.to_string())),
+                            ]))
+                        }
+                        Some(rank) => RespValue::Integer(rank as i64),
+                        None => RespValue::BulkString(None),
+                    });
+                }
+                // ===== ZAROUND =====
+                else if cmd_upper == "ZAROUND" {
+                    if args.len() < 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'ZAROUND' command".to_string(),
+                        ));
+                    }
+                    let Ok(count) = args[2].parse::<usize>() else {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "ERR count must be a non-negative integer".to_string(),
+                        ));
+                    };
+                    let rev = match &args[3..] {
+                        [] => false,
+                        [opt] if opt.eq_ignore_ascii_case("REV") => true,
+                        _ => return ExecutionResult::Response(RespValue::Error("ERR syntax error".to_string())),
+                    };
+                    let mut db = self.db.write().await;
+                    return ExecutionResult::Response(match db.zaround(key, &args[1], count, rev) {
+                        Some(window) => RespValue::Array(Some(
+                            window
+                                .into_iter()
+                                .map(|(rank, member, score)| {
+                                    RespValue::Array(Some(vec![
+                                        RespValue::Integer(rank as i64),
+                                        RespValue::BulkString(Some(member)),
+                                        RespValue::BulkString(Some(score.to_string())),
+                                    ]))
+                                })
+                                .collect(),
+                        )),
+                        None => RespValue::BulkString(None),
+                    });
+                }
                 // ===== ZCARD =====
                 else if cmd_upper == "ZCARD" {
                     let mut db = self.db.write().await;
-                    let count = db.zcard(key.clone());
+                    let count = db.zcard(key);
                     return ExecutionResult::Response(RespValue::Integer(count as i64));
                 }
                 // ===== ZREM =====
@@ -696,10 +3418,89 @@ impl Interpreter {
                     match db.zrem(key.clone(), members) {
                         Ok(count) => {
                             let mut aof = self.aof.write().await;
-                            let _ = aof.append(full_cmd_args);
+                            let _ = aof.append_command(&cmd_string, &args);
                             return ExecutionResult::Response(RespValue::Integer(count as i64));
                         }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                }
+                // ===== ZRANDMEMBER =====
+                else if cmd_upper == "ZRANDMEMBER" {
+                    if args.is_empty() || args.len() > 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'ZRANDMEMBER' command".to_string(),
+                        ));
+                    }
+                    let count = match args.get(1).map(|c| c.parse::<i64>()) {
+                        Some(Ok(c)) => Some(c),
+                        Some(Err(_)) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ));
+                        }
+                        None => None,
+                    };
+                    let withscores = match args.get(2) {
+                        Some(opt) if opt.to_uppercase() == "WITHSCORES" => true,
+                        Some(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "syntax error".to_string(),
+                            ));
+                        }
+                        None => false,
+                    };
+                    let mut db = self.db.write().await;
+                    return match db.zrandmember(key, count.unwrap_or(1)) {
+                        Ok(members) => match count {
+                            None => ExecutionResult::Response(RespValue::BulkString(
+                                members.into_iter().next().map(|(m, _)| m),
+                            )),
+                            Some(_) => ExecutionResult::Response(zset_pairs_response(members, withscores)),
+                        },
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                }
+                // ===== ZUNION / ZINTER / ZDIFF =====
+                else if cmd_upper == "ZUNION" || cmd_upper == "ZINTER" || cmd_upper == "ZDIFF" {
+                    let opts = match parse_zset_combine_args(&args, cmd_upper != "ZDIFF") {
+                        Ok(opts) => opts,
+                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                    };
+                    let mut db = self.db.write().await;
+                    let result = match cmd_upper.as_str() {
+                        "ZUNION" => db.zunion(opts.keys, opts.weights, opts.aggregate),
+                        "ZINTER" => db.zinter(opts.keys, opts.weights, opts.aggregate),
+                        _ => db.zdiff(opts.keys),
+                    };
+                    return match result {
+                        Ok(members) => ExecutionResult::Response(zset_pairs_response(members, opts.withscores)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                }
+                // ===== ZDIFFSTORE =====
+                else if cmd_upper == "ZDIFFSTORE" {
+                    if args.len() < 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'ZDIFFSTORE' command".to_string(),
+                        ));
+                    }
+                    let opts = match parse_zset_combine_args(&args[1..], false) {
+                        Ok(opts) if !opts.withscores => opts,
+                        Ok(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "syntax error".to_string(),
+                            ));
+                        }
                         Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                    };
+                    let mut db = self.db.write().await;
+                    match db.zdiffstore(key.clone(), opts.keys) {
+                        Ok(count) => {
+                            let mut aof = self.aof.write().await;
+                            let _ = aof.append_command(&cmd_string, &args);
+                            return ExecutionResult::Response(RespValue::Integer(count as i64));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 }
                 // ===== PFADD =====
@@ -711,10 +3512,14 @@ impl Interpreter {
                     }
                     let mut db = self.db.write().await;
                     let elements: Vec<String> = args[1..].to_vec();
-                    let changed = db.pfadd(key.clone(), elements);
-                    let mut aof = self.aof.write().await;
-                    let _ = aof.append(full_cmd_args);
-                    return ExecutionResult::Response(RespValue::Integer(if changed { 1 } else { 0 }));
+                    match db.pfadd(key.clone(), elements) {
+                        Ok(changed) => {
+                            let mut aof = self.aof.write().await;
+                            let _ = aof.append_command(&cmd_string, &args);
+                            return ExecutionResult::Response(RespValue::Integer(if changed { 1 } else { 0 }));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
                 }
                 // ===== PFCOUNT =====
                 else if cmd_upper == "PFCOUNT" {
@@ -725,8 +3530,10 @@ impl Interpreter {
                     }
                     let mut db = self.db.write().await;
                     let keys: Vec<String> = args.clone();
-                    let count = db.pfcount(keys);
-                    return ExecutionResult::Response(RespValue::Integer(count as i64));
+                    return match db.pfcount(keys) {
+                        Ok(count) => ExecutionResult::Response(RespValue::Integer(count as i64)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
                 }
                 // ===== SETBIT =====
                 else if cmd_upper == "SETBIT" {
@@ -735,63 +3542,219 @@ impl Interpreter {
                             "wrong number of arguments for 'SETBIT' command".to_string(),
                         ));
                     }
-                    let offset: usize = args[1].parse().unwrap_or(0);
-                    let value: bool = args[2].parse::<u8>().unwrap_or(0) != 0;
-                    let mut db = self.db.write().await;
-                    let old = db.setbit(key.clone(), offset, value);
-                    let mut aof = self.aof.write().await;
-                    let _ = aof.append(full_cmd_args);
-                    return ExecutionResult::Response(RespValue::Integer(old));
-                }
-                // ===== GETBIT =====
+                    let offset = match self.bit_offset(&args[1]).await {
+                        Ok(offset) => offset,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
+                    let value = match args[2].as_str() {
+                        "0" => false,
+                        "1" => true,
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "bit is not an integer or out of range".to_string(),
+                            ))
+                        }
+                    };
+                    let mut db = self.db.write().await;
+                    match db.setbit(key.clone(), offset, value) {
+                        Ok(old) => {
+                            let mut aof = self.aof.write().await;
+                            let _ = aof.append_command(&cmd_string, &args);
+                            return ExecutionResult::Response(RespValue::Integer(old));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                }
+                // ===== GETBIT =====
                 else if cmd_upper == "GETBIT" {
                     if args.len() < 2 {
                         return ExecutionResult::Response(RespValue::Error(
                             "wrong number of arguments for 'GETBIT' command".to_string(),
                         ));
                     }
-                    let offset: usize = args[1].parse().unwrap_or(0);
+                    let offset = match self.bit_offset(&args[1]).await {
+                        Ok(offset) => offset,
+                        Err(e) => return ExecutionResult::Response(e),
+                    };
                     let mut db = self.db.write().await;
-                    let bit = db.getbit(key.clone(), offset);
-                    return ExecutionResult::Response(RespValue::Integer(bit));
+                    return match db.getbit(key, offset) {
+                        Ok(bit) => ExecutionResult::Response(RespValue::Integer(bit)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
                 }
                 // ===== BITCOUNT =====
                 else if cmd_upper == "BITCOUNT" {
                     let mut db = self.db.write().await;
                     let start = args.get(1).and_then(|s| s.parse().ok());
                     let end = args.get(2).and_then(|s| s.parse().ok());
-                    let count = db.bitcount(key.clone(), start, end);
-                    return ExecutionResult::Response(RespValue::Integer(count as i64));
+                    return match db.bitcount(key, start, end) {
+                        Ok(count) => ExecutionResult::Response(RespValue::Integer(count as i64)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
                 }
                 // ===== XADD =====
+                // XADD key [MAXLEN|MINID [=|~] threshold] id field value [field value ...]
                 else if cmd_upper == "XADD" {
-                    if args.len() < 4 {
+                    let mut trim = None;
+                    let mut i = 1;
+                    if args.get(1).is_some_and(|opt| opt.eq_ignore_ascii_case("MAXLEN") || opt.eq_ignore_ascii_case("MINID")) {
+                        match StreamTrim::parse(&args[1..]) {
+                            Ok((parsed, used)) => {
+                                trim = Some(parsed);
+                                i += used;
+                            }
+                            Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        }
+                    }
+                    if args.len() < i + 3 {
                         return ExecutionResult::Response(RespValue::Error(
                             "wrong number of arguments for 'XADD' command".to_string(),
                         ));
                     }
-                    let id = if args[1] == "*" { None } else { Some(args[1].clone()) };
-                    let mut fields = Vec::new();
-                    for i in (2..args.len()).step_by(2) {
-                        if let Some(value) = args.get(i + 1) {
-                            fields.push((args[i].clone(), value.clone()));
-                        }
-                    }
+                    // Without an explicit trim the configured default cap applies
+                    let trim = match trim {
+                        Some(trim) => Some(trim),
+                        None => match self.config.read().await.memory.stream_max_len {
+                            0 => None,
+                            max_len => Some(StreamTrim::MaxLen(max_len)),
+                        },
+                    };
+                    let id = if args[i] == "*" { None } else { Some(args[i].clone()) };
+                    let fields = args[i + 1..]
+                        .chunks_exact(2)
+                        .map(|pair| (pair[0].clone(), pair[1].clone()))
+                        .collect();
                     let mut db = self.db.write().await;
                     match db.xadd(key.clone(), id, fields) {
                         Ok(entry_id) => {
+                            // Log the generated id so replay rebuilds the same entry
+                            let mut logged = vec![key.clone(), entry_id.clone()];
+                            logged.extend_from_slice(&args[i + 1..]);
                             let mut aof = self.aof.write().await;
-                            let _ = aof.append(full_cmd_args);
+                            let _ = aof.append_command(&cmd_string, &logged);
+                            if let Some(trim) = trim {
+                                let _ = trim_stream(&mut db, &mut aof, key.clone(), trim);
+                            }
                             return ExecutionResult::Response(RespValue::BulkString(Some(entry_id)));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                }
+                // ===== XTRIM =====
+                // XTRIM key MAXLEN|MINID [=|~] threshold
+                else if cmd_upper == "XTRIM" {
+                    if args.len() < 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'XTRIM' command".to_string(),
+                        ));
                     }
+                    let trim = match StreamTrim::parse(&args[1..]) {
+                        Ok((trim, used)) if used + 1 == args.len() => trim,
+                        Ok(_) => return ExecutionResult::Response(RespValue::Error("syntax error".to_string())),
+                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                    };
+                    let mut db = self.db.write().await;
+                    let mut aof = self.aof.write().await;
+                    return match trim_stream(&mut db, &mut aof, key.clone(), trim) {
+                        Ok(removed) => ExecutionResult::Response(RespValue::Integer(removed as i64)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
                 }
                 // ===== XLEN =====
                 else if cmd_upper == "XLEN" {
                     let mut db = self.db.write().await;
-                    let len = db.xlen(key.clone());
-                    return ExecutionResult::Response(RespValue::Integer(len as i64));
+                    return match db.xlen(key) {
+                        Ok(len) => ExecutionResult::Response(RespValue::Integer(len as i64)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                }
+                // ===== XRANGE / XREVRANGE =====
+                // XRANGE key start end [COUNT count], XREVRANGE key end start [COUNT count]
+                else if cmd_upper == "XRANGE" || cmd_upper == "XREVRANGE" {
+                    let count = match &args[..] {
+                        [_, _, _] => None,
+                        [_, _, _, opt, n] if opt.eq_ignore_ascii_case("COUNT") => match n.parse::<usize>() {
+                            Ok(n) => Some(n),
+                            Err(_) => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "value is not an integer or out of range".to_string(),
+                                ))
+                            }
+                        },
+                        [_, _, _, ..] => {
+                            return ExecutionResult::Response(RespValue::Error("syntax error".to_string()))
+                        }
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(format!(
+                                "wrong number of arguments for '{}' command",
+                                cmd_upper
+                            )))
+                        }
+                    };
+                    let mut db = self.db.write().await;
+                    let result = if cmd_upper == "XRANGE" {
+                        db.xrange(key, &args[1], &args[2], count)
+                    } else {
+                        db.xrevrange(key, &args[1], &args[2], count)
+                    };
+                    return match result {
+                        Ok(entries) => ExecutionResult::Response(stream_entries(entries)),
+                        Err(e) => ExecutionResult::Response(e.into()),
+                    };
+                }
+                // ===== XREAD =====
+                // XREAD [COUNT count] [MAXENTRIES total] STREAMS key [key ...] id [id ...]
+                else if cmd_upper == "XREAD" {
+                    let mut count = None;
+                    let mut max_entries = None;
+                    let mut i = 0;
+                    while i < args.len() && !args[i].eq_ignore_ascii_case("STREAMS") {
+                        let opt = args[i].to_uppercase();
+                        let value = match args.get(i + 1) {
+                            Some(v) if opt == "COUNT" || opt == "MAXENTRIES" => v,
+                            _ => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "syntax error".to_string(),
+                                ))
+                            }
+                        };
+                        let n = match value.parse::<usize>() {
+                            Ok(n) => n,
+                            Err(_) => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "value is not an integer or out of range".to_string(),
+                                ))
+                            }
+                        };
+                        if opt == "COUNT" {
+                            count = Some(n);
+                        } else {
+                            max_entries = Some(n);
+                        }
+                        i += 2;
+                    }
+                    let streams = args.get(i + 1..).unwrap_or(&[]);
+                    if streams.is_empty() || streams.len() % 2 != 0 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "Unbalanced 'xread' list of streams: for each stream key an ID must be specified.".to_string(),
+                        ));
+                    }
+                    let (keys, ids) = streams.split_at(streams.len() / 2);
+                    let mut db = self.db.write().await;
+                    let results = match db.xread(keys.to_vec(), ids.to_vec(), count, max_entries) {
+                        Ok(results) => results,
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    };
+                    if results.is_empty() {
+                        return ExecutionResult::Response(RespValue::Array(None));
+                    }
+                    let reply = results
+                        .into_iter()
+                        .map(|(stream, entries)| {
+                            RespValue::Array(Some(vec![RespValue::BulkString(Some(stream)), stream_entries(entries)]))
+                        })
+                        .collect();
+                    return ExecutionResult::Response(RespValue::Array(Some(reply)));
                 }
                 // ===== GEOADD =====
                 else if cmd_upper == "GEOADD" {
@@ -808,10 +3771,14 @@ impl Interpreter {
                             locations.push((lon, lat, member));
                         }
                     }
-                    let added = db.geoadd(key.clone(), locations);
-                    let mut aof = self.aof.write().await;
-                    let _ = aof.append(full_cmd_args);
-                    return ExecutionResult::Response(RespValue::Integer(added as i64));
+                    match db.geoadd(key.clone(), locations) {
+                        Ok(added) => {
+                            let mut aof = self.aof.write().await;
+                            let _ = aof.append_command(&cmd_string, &args);
+                            return ExecutionResult::Response(RespValue::Integer(added as i64));
+                        }
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
                 }
                 // ===== GEODIST =====
                 else if cmd_upper == "GEODIST" {
@@ -828,17 +3795,228 @@ impl Interpreter {
                         _ => GeoUnit::Meters,
                     };
                     let mut db = self.db.write().await;
-                    match db.geodist(key.clone(), args[1].clone(), args[2].clone(), unit) {
-                        Some(dist) => {
+                    match db.geodist(key, &args[1], &args[2], unit) {
+                        Ok(Some(dist)) => {
                             return ExecutionResult::Response(RespValue::BulkString(Some(format!("{:.4}", dist))));
                         }
-                        None => return ExecutionResult::Response(RespValue::BulkString(None)),
+                        Ok(None) => return ExecutionResult::Response(RespValue::BulkString(None)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
+                    }
+                }
+                // ===== DUMP =====
+                else if cmd_upper == "DUMP" {
+                    let mut db = self.db.write().await;
+                    if !db.check_expiration(key) {
+                        return ExecutionResult::Response(RespValue::BulkString(None));
+                    }
+                    return match db.items.get(key) {
+                        Some(entry) => ExecutionResult::Response(RespValue::BulkString(Some(
+                            crate::persistence::dump::dump(entry),
+                        ))),
+                        None => ExecutionResult::Response(RespValue::BulkString(None)),
+                    };
+                }
+                // ===== RESTORE =====
+                // RESTORE key ttl payload [REPLACE] [ABSTTL]
+                else if cmd_upper == "RESTORE" {
+                    if args.len() < 3 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'RESTORE' command".to_string(),
+                        ));
+                    }
+                    let ttl = match args[1].parse::<i64>() {
+                        Ok(ttl) if ttl >= 0 => ttl as u64,
+                        Ok(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "Invalid TTL value, must be >= 0".to_string(),
+                            ))
+                        }
+                        Err(_) => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ))
+                        }
+                    };
+                    let mut replace = false;
+                    let mut absttl = false;
+                    for opt in &args[3..] {
+                        match opt.to_uppercase().as_str() {
+                            "REPLACE" => replace = true,
+                            "ABSTTL" => absttl = true,
+                            _ => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "syntax error".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    let mut entry = match crate::persistence::dump::restore(&args[2]) {
+                        Ok(entry) => entry,
+                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                    };
+                    let expires_at = match (ttl, absttl) {
+                        (0, _) => None,
+                        (ms, true) => instant_from_unix_millis(ms),
+                        (ms, false) => std::time::Instant::now().checked_add(std::time::Duration::from_millis(ms)),
+                    };
+                    if ttl > 0 && expires_at.is_none() {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "invalid expire time in 'restore' command".to_string(),
+                        ));
+                    }
+
+                    entry.expires_at = expires_at;
+                    let mut db = self.db.write().await;
+                    if !db.restore(key, entry, replace) {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "BUSYKEY Target key name already exists.".to_string(),
+                        ));
+                    }
+                    // Log an absolute expiry so replay does not extend the TTL
+                    let logged_ttl = expires_at.map(unix_millis_from_instant).unwrap_or(0);
+                    let mut aof = self.aof.write().await;
+                    if let Err(e) = aof.append(vec![
+                        "RESTORE".to_string(),
+                        key.clone(),
+                        logged_ttl.to_string(),
+                        args[2].clone(),
+                        "REPLACE".to_string(),
+                        "ABSTTL".to_string(),
+                    ]) {
+                        error!("AOF write error: {}", e);
+                    }
+                    blocking::signal_ready();
+                    return ExecutionResult::Response(RespValue::SimpleString("OK".to_string()));
+                }
+                // ===== MIGRATE =====
+                // MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [KEYS key [key ...]]
+                else if cmd_upper == "MIGRATE" {
+                    if args.len() < 5 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "wrong number of arguments for 'MIGRATE' command".to_string(),
+                        ));
+                    }
+                    let (port, dest_db, timeout_ms) = match (
+                        args[1].parse::<u16>(),
+                        args[3].parse::<i64>(),
+                        args[4].parse::<u64>(),
+                    ) {
+                        (Ok(port), Ok(dest_db), Ok(timeout_ms)) => (port, dest_db, timeout_ms),
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "value is not an integer or out of range".to_string(),
+                            ))
+                        }
+                    };
+                    // Only a single keyspace exists
+                    if dest_db != 0 {
+                        return ExecutionResult::Response(RespValue::Error(
+                            "DB index is out of range".to_string(),
+                        ));
+                    }
+                    let mut copy = false;
+                    let mut replace = false;
+                    let mut keys = vec![args[2].clone()];
+                    let mut i = 5;
+                    while i < args.len() {
+                        match args[i].to_uppercase().as_str() {
+                            "COPY" => copy = true,
+                            "REPLACE" => replace = true,
+                            "KEYS" => {
+                                if !args[2].is_empty() {
+                                    return ExecutionResult::Response(RespValue::Error(
+                                        "When using MIGRATE KEYS option, the key argument must be set to the empty string".to_string(),
+                                    ));
+                                }
+                                keys = args[i + 1..].to_vec();
+                                break;
+                            }
+                            _ => {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "syntax error".to_string(),
+                                ))
+                            }
+                        }
+                        i += 1;
+                    }
+
+                    // Values are serialized under a read lock that is released
+                    // before any network I/O
+                    let mut found = Vec::new();
+                    let mut restores = Vec::new();
+                    {
+                        let db = self.db.read().await;
+                        let now = std::time::Instant::now();
+                        for k in &keys {
+                            let Some(entry) = db.items.get(k).filter(|e| e.expires_at.is_none_or(|at| at > now)) else {
+                                continue;
+                            };
+                            let ttl_ms = entry
+                                .expires_at
+                                .map(|at| (at.saturating_duration_since(now).as_millis() as u64).max(1))
+                                .unwrap_or(0);
+                            let payload = crate::persistence::dump::dump(entry);
+                            let mut restore = vec!["RESTORE".to_string(), k.clone(), ttl_ms.to_string(), payload.clone()];
+                            if replace {
+                                restore.push("REPLACE".to_string());
+                            }
+                            found.push((k.clone(), payload));
+                            restores.push(restore);
+                        }
+                    }
+                    if restores.is_empty() {
+                        return ExecutionResult::Response(RespValue::SimpleString("NOKEY".to_string()));
+                    }
+
+                    let replies = match migrate::transfer(&args[0], port, &restores, timeout_ms).await {
+                        Ok(replies) => replies,
+                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                    };
+
+                    let mut first_error = None;
+                    let mut moved = Vec::new();
+                    for (sent, reply) in found.into_iter().zip(replies) {
+                        match reply {
+                            RespValue::Error(e) => {
+                                first_error.get_or_insert(e);
+                            }
+                            _ => moved.push(sent),
+                        }
+                    }
+                    if !copy && !moved.is_empty() {
+                        // A key written meanwhile keeps its new value here
+                        let mut db = self.db.write().await;
+                        let moved: Vec<String> = moved
+                            .into_iter()
+                            .filter(|(k, payload)| {
+                                db.items.get(k).is_some_and(|e| crate::persistence::dump::dump(e) == *payload)
+                            })
+                            .map(|(k, _)| k)
+                            .collect();
+                        for k in &moved {
+                            db.del(k);
+                        }
+                        if !moved.is_empty() {
+                            let mut del_cmd = vec!["DEL".to_string()];
+                            del_cmd.extend(moved);
+                            let mut aof = self.aof.write().await;
+                            if let Err(e) = aof.append(del_cmd) {
+                                error!("AOF write error: {}", e);
+                            }
+                        }
                     }
+                    return match first_error {
+                        Some(e) => ExecutionResult::Response(RespValue::Error(format!(
+                            "Target instance replied with error: {}",
+                            e
+                        ))),
+                        None => ExecutionResult::Response(RespValue::SimpleString("OK".to_string())),
+                    };
                 }
                 // ===== TYPE =====
                 else if cmd_upper == "TYPE" {
                     let db = self.db.read().await;
-                    let type_str = db.type_of(&key).unwrap_or_else(|| "none".to_string());
+                    let type_str = db.type_of(key).unwrap_or_else(|| "none".to_string());
                     return ExecutionResult::Response(RespValue::SimpleString(type_str));
                 }
                 // ===== RENAME =====
@@ -849,19 +4027,65 @@ impl Interpreter {
                         ));
                     }
                     let mut db = self.db.write().await;
-                    match db.rename(&key, &args[1]) {
+                    match db.rename(key, &args[1]) {
                         Ok(_) => {
                             let mut aof = self.aof.write().await;
-                            let _ = aof.append(full_cmd_args);
+                            let _ = aof.append_command(&cmd_string, &args);
+                            blocking::signal_ready();
                             return ExecutionResult::Response(RespValue::SimpleString("OK".to_string()));
                         }
-                        Err(e) => return ExecutionResult::Response(RespValue::Error(e)),
+                        Err(e) => return ExecutionResult::Response(e.into()),
                     }
                 }
-                // ===== FLUSHDB =====
-                else if cmd_upper == "FLUSHDB" {
+                // ===== FLUSHDB / FLUSHALL [ASYNC|SYNC] | SCHEDULE | CONFIRM token =====
+                else if cmd_upper == "FLUSHDB" || cmd_upper == "FLUSHALL" {
+                    let (confirmation, timeout) = {
+                        let config = self.config.read().await;
+                        (config.server.flush_confirmation, config.server.flush_confirmation_timeout_secs)
+                    };
+                    let mode = args.first().map(|a| a.to_uppercase());
+                    match mode.as_deref() {
+                        // Tek bir keyspace var; FLUSHDB ve FLUSHALL aynı şekilde çalışır
+                        None | Some("ASYNC") | Some("SYNC") if args.len() <= 1 => {
+                            if confirmation {
+                                return ExecutionResult::Response(RespValue::Error(format!(
+                                    "ERR flush confirmation required: run {} SCHEDULE, then {} CONFIRM <token>",
+                                    cmd_upper, cmd_upper
+                                )));
+                            }
+                        }
+                        Some("SCHEDULE") if args.len() == 1 => {
+                            let token = self
+                                .flush_confirmations
+                                .schedule(std::time::Duration::from_secs(timeout));
+                            return ExecutionResult::Response(RespValue::BulkString(Some(token)));
+                        }
+                        Some("CONFIRM") if args.len() == 2 => {
+                            if !self.flush_confirmations.confirm(&args[1]) {
+                                return ExecutionResult::Response(RespValue::Error(
+                                    "ERR invalid or expired flush token".to_string(),
+                                ));
+                            }
+                        }
+                        _ => {
+                            return ExecutionResult::Response(RespValue::Error(
+                                "syntax error".to_string(),
+                            ));
+                        }
+                    }
+
                     let mut db = self.db.write().await;
-                    db.flushdb();
+                    // ASYNC: eski keyspace arka planda serbest bırakılır
+                    if mode.as_deref() == Some("ASYNC") {
+                        db.flushdb_async();
+                    } else {
+                        db.flushdb();
+                    }
+                    // Replikalara ve AOF'a token'sız, açık bir komut olarak yaz
+                    let mut aof = self.aof.write().await;
+                    if let Err(e) = aof.append(vec![cmd_upper.clone()]) {
+                        error!("AOF write error: {}", e);
+                    }
                     return ExecutionResult::Response(RespValue::SimpleString("OK".to_string()));
                 }
                 else {
@@ -875,3 +4099,1949 @@ impl Interpreter {
         }
     }
 }
+
+/// Field/value pairs as a flat array of bulk strings
+fn field_pairs(pairs: Vec<(String, String)>) -> RespValue {
+    RespValue::Array(Some(
+        pairs
+            .into_iter()
+            .flat_map(|(k, v)| [RespValue::BulkString(Some(k)), RespValue::BulkString(Some(v))])
+            .collect(),
+    ))
+}
+
+/// Stream entries as `[id, [field, value, ...]]` pairs
+fn stream_entries(entries: StreamEntries) -> RespValue {
+    RespValue::Array(Some(
+        entries
+            .into_iter()
+            .map(|(id, fields)| RespValue::Array(Some(vec![RespValue::BulkString(Some(id)), field_pairs(fields)])))
+            .collect(),
+    ))
+}
+
+/// Trim a stream and log the trim when it removed entries
+fn trim_stream(db: &mut DB, aof: &mut Aof, key: String, trim: StreamTrim) -> Result<usize, crate::db::DbError> {
+    let removed = db.xtrim(key.clone(), trim.clone())?;
+    if removed > 0 {
+        let mut record = vec!["XTRIM".to_string(), key];
+        record.extend(trim.to_args());
+        if let Err(e) = aof.append(record) {
+            error!("AOF write error: {}", e);
+        }
+    }
+    Ok(removed)
+}
+
+/// Biggest keys of a report as `[key, type, memory]` triples
+fn biggest_keys_reply(keys: Vec<analyze::BigKey>) -> RespValue {
+    RespValue::Array(Some(
+        keys.into_iter()
+            .map(|k| {
+                RespValue::Array(Some(vec![
+                    RespValue::BulkString(Some(k.key)),
+                    RespValue::BulkString(Some(k.type_name)),
+                    RespValue::Integer(k.memory as i64),
+                ]))
+            })
+            .collect(),
+    ))
+}
+
+/// Deadline for a session touched now with an idle timeout of `seconds`
+fn session_expiry(cmd_upper: &str, seconds: &str) -> Result<std::time::Instant, RespValue> {
+    let seconds = seconds.parse::<u64>().map_err(|_| {
+        RespValue::Error("value is not an integer or out of range".to_string())
+    })?;
+    if seconds == 0 {
+        return Err(RespValue::Error(format!(
+            "invalid expire time in '{}' command",
+            cmd_upper.to_lowercase()
+        )));
+    }
+    std::time::Instant::now()
+        .checked_add(std::time::Duration::from_secs(seconds))
+        .ok_or_else(|| {
+            RespValue::Error(format!(
+                "invalid expire time in '{}' command",
+                cmd_upper.to_lowercase()
+            ))
+        })
+}
+
+/// AOF records for a session write: an HSET of `fields` (if any), then
+/// the absolute deadline
+fn session_aof_records(key: &str, fields: &[String], at: std::time::Instant) -> Vec<Vec<String>> {
+    let mut records = Vec::with_capacity(2);
+    if !fields.is_empty() {
+        let mut hset = vec!["HSET".to_string(), key.to_string()];
+        hset.extend_from_slice(fields);
+        records.push(hset);
+    }
+    records.push(vec![
+        "PEXPIREAT".to_string(),
+        key.to_string(),
+        unix_millis_from_instant(at).to_string(),
+    ]);
+    records
+}
+
+/// `[MATCH pattern] [COUNT count] [NOVALUES] [TYPE type]` options of SCAN,
+/// HSCAN and SSCAN
+struct ScanOptions {
+    pattern: Option<String>,
+    count: Option<usize>,
+    novalues: bool,
+    type_name: Option<String>,
+}
+
+/// Parse `cursor [MATCH pattern] [COUNT count]`, plus `NOVALUES` for HSCAN
+/// and `TYPE type` for SCAN. `args` must not be empty.
+fn parse_scan_args(cmd_upper: &str, args: &[String]) -> Result<(u64, ScanOptions), RespValue> {
+    let cursor = args[0]
+        .parse::<u64>()
+        .map_err(|_| RespValue::Error("ERR invalid cursor".to_string()))?;
+    let mut options = ScanOptions {
+        pattern: None,
+        count: None,
+        novalues: false,
+        type_name: None,
+    };
+    let mut rest = args[1..].iter();
+    while let Some(opt) = rest.next() {
+        match opt.to_uppercase().as_str() {
+            "MATCH" => match rest.next() {
+                Some(pattern) => options.pattern = Some(pattern.clone()),
+                None => return Err(RespValue::Error("syntax error".to_string())),
+            },
+            "COUNT" => match rest.next().map(|c| c.parse::<usize>()) {
+                Some(Ok(count)) if count > 0 => options.count = Some(count),
+                Some(Ok(_)) | None => return Err(RespValue::Error("syntax error".to_string())),
+                Some(Err(_)) => {
+                    return Err(RespValue::Error(
+                        "value is not an integer or out of range".to_string(),
+                    ))
+                }
+            },
+            "NOVALUES" if cmd_upper == "HSCAN" => options.novalues = true,
+            "TYPE" if cmd_upper == "SCAN" => match rest.next() {
+                Some(type_name) => options.type_name = Some(type_name.to_lowercase()),
+                None => return Err(RespValue::Error("syntax error".to_string())),
+            },
+            _ => return Err(RespValue::Error("syntax error".to_string())),
+        }
+    }
+    Ok((cursor, options))
+}
+
+/// `[next-cursor, [items...]]` reply of a scan command
+fn scan_reply(next: u64, items: Vec<RespValue>) -> RespValue {
+    RespValue::Array(Some(vec![
+        RespValue::BulkString(Some(next.to_string())),
+        RespValue::Array(Some(items)),
+    ]))
+}
+
+/// Single-key read commands counted in keyspace_hits/keyspace_misses
+const SINGLE_KEY_READS: &[&str] = &[
+    "GET", "GETRANGE", "STRLEN", "GETBIT", "BITCOUNT", "BITPOS", "DUMP", "LLEN", "LRANGE",
+    "LPOS", "LINDEX", "HGET", "HGETALL", "HMGET", "HLEN", "HEXISTS", "HKEYS", "HVALS", "HSTRLEN",
+    "SMEMBERS", "SISMEMBER", "SCARD", "ZRANGE", "ZSCORE", "ZCARD", "ZRANK", "ZREVRANK", "ZAROUND", "XLEN", "XRANGE",
+    "XREVRANGE", "PFCOUNT", "GEODIST", "GEOPOS", "HSCAN", "SSCAN", "SESSION.GET",
+    "TS.GET", "TS.RANGE", "BF.EXISTS", "BF.MEXISTS", "CF.EXISTS", "CF.MEXISTS", "CF.COUNT", "VECTOR.GET",
+    "VECTOR.CARD", "VECTOR.SEARCH",
+];
+
+/// Keys looked up by a read command, for the keyspace hit/miss counters
+fn keyspace_read_keys<'a>(cmd_upper: &str, args: &'a [String]) -> &'a [String] {
+    match cmd_upper {
+        "MGET" | "EXISTS" => args,
+        _ if SINGLE_KEY_READS.contains(&cmd_upper) => &args[..args.len().min(1)],
+        _ => &[],
+    }
+}
+
+/// Keys a command touches, for ACL key checks, the access log and AOF stats.
+/// `args` excludes the command name.
+pub(crate) fn command_keys<'a>(cmd_upper: &str, args: &'a [String]) -> Vec<&'a str> {
+    // ZUNION/ZINTER/ZDIFF/LMPOP/ZMPOP numkeys key [key ...]
+    let numkeys_at = |idx: usize| -> &'a [String] {
+        let n = args.get(idx).and_then(|n| n.parse::<usize>().ok()).unwrap_or(0);
+        &args[(idx + 1).min(args.len())..(idx + 1 + n).min(args.len())]
+    };
+    match cmd_upper {
+        "DEL" | "UNLINK" | "EXISTS" | "PFCOUNT" => args.iter().map(String::as_str).collect(),
+        "RENAME" | "TS.CREATERULE" | "TS.DELETERULE" => args.iter().take(2).map(String::as_str).collect(),
+        "MEMORY" => args.iter().skip(1).take(1).map(String::as_str).collect(),
+        "ZUNION" | "ZINTER" | "ZDIFF" | "LMPOP" | "ZMPOP" => numkeys_at(0).iter().map(String::as_str).collect(),
+        "BLMPOP" | "BZMPOP" => numkeys_at(1).iter().map(String::as_str).collect(),
+        "SINTERCARD" => numkeys_at(0).iter().map(String::as_str).collect(),
+        "FCALL" | "FCALL_RO" => numkeys_at(1).iter().map(String::as_str).collect(),
+        "SORT" => match args.iter().position(|a| a.eq_ignore_ascii_case("STORE")) {
+            Some(pos) => args.iter().take(1).chain(args.get(pos + 1)).map(String::as_str).collect(),
+            None => args.iter().take(1).map(String::as_str).collect(),
+        },
+        "ZDIFFSTORE" => args.iter().take(1).chain(numkeys_at(1)).map(String::as_str).collect(),
+        // MIGRATE host port key|"" db timeout [COPY] [REPLACE] [AUTH ...] [KEYS key ...]
+        "MIGRATE" => {
+            let keys = match args.iter().skip(5).position(|a| a.eq_ignore_ascii_case("KEYS")) {
+                Some(pos) => &args[pos + 6..],
+                None => &[],
+            };
+            let key = args.get(2).filter(|key| !key.is_empty());
+            key.into_iter().chain(keys).map(String::as_str).collect()
+        }
+        // XREAD [COUNT n] [BLOCK ms] STREAMS key [key ...] id [id ...]
+        "XREAD" => match args.iter().position(|a| a.eq_ignore_ascii_case("STREAMS")) {
+            Some(pos) => {
+                let rest = &args[pos + 1..];
+                rest[..rest.len() / 2].iter().map(String::as_str).collect()
+            }
+            None => Vec::new(),
+        },
+        // Commands whose first argument is not a key
+        "PING" | "ECHO" | "INFO" | "KEYS" | "PUBLISH" | "SUBSCRIBE" | "PSUBSCRIBE" | "SPUBLISH"
+        | "SSUBSCRIBE" | "PUBSUB" | "SAVE" | "BGSAVE" | "BACKUP" | "DUMPALL" | "DBSIZE" | "FLUSHDB"
+        | "FLUSHALL" | "EXPIREMANY" | "DEBUG" | "SLOWLOG" | "LATENCY" | "PERSISTENCE"
+        | "AUTH" | "ACL" | "ANALYZE" | "RESET" | "SELECT" | "CLIENT" | "FUNCTION" | "SCHEDULE"
+        | "READONLY" | "READWRITE" | "DBSTATS" | "SCAN" | "TS.MRANGE" => Vec::new(),
+        _ => args.iter().take(1).map(String::as_str).collect(),
+    }
+}
+
+/// Parse a memory size such as `512`, `4k`, `1mb` or `2gb`
+fn parse_memory_size(value: &str) -> Option<usize> {
+    let lower = value.to_ascii_lowercase();
+    let split = lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len());
+    let (digits, unit) = lower.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" | "kb" => 1 << 10,
+        "m" | "mb" => 1 << 20,
+        "g" | "gb" => 1 << 30,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Command name and arguments of a request, as strings
+fn request_args(request: &RespValue) -> Vec<String> {
+    match request {
+        RespValue::Array(Some(tokens)) => tokens
+            .iter()
+            .filter_map(|t| match t {
+                RespValue::BulkString(Some(s)) | RespValue::SimpleString(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Options shared by ZUNION, ZINTER and ZDIFF
+struct ZSetCombineArgs {
+    keys: Vec<String>,
+    weights: Option<Vec<f64>>,
+    aggregate: ZAggregate,
+    withscores: bool,
+}
+
+/// Parse `numkeys key [key ...] [WEIGHTS w ...] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]`.
+/// WEIGHTS and AGGREGATE are only accepted when `allow_aggregate` is set.
+fn parse_zset_combine_args(args: &[String], allow_aggregate: bool) -> Result<ZSetCombineArgs, String> {
+    let numkeys = match args.first().map(|n| n.parse::<usize>()) {
+        Some(Ok(n)) if n > 0 => n,
+        Some(Ok(_)) => return Err("ERR at least 1 input key is needed".to_string()),
+        Some(Err(_)) => return Err("value is not an integer or out of range".to_string()),
+        None => return Err("syntax error".to_string()),
+    };
+    if args.len() < 1 + numkeys {
+        return Err("syntax error".to_string());
+    }
+
+    let mut opts = ZSetCombineArgs {
+        keys: args[1..1 + numkeys].to_vec(),
+        weights: None,
+        aggregate: ZAggregate::Sum,
+        withscores: false,
+    };
+
+    let mut i = 1 + numkeys;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "WEIGHTS" if allow_aggregate => {
+                let weights = args
+                    .get(i + 1..i + 1 + numkeys)
+                    .ok_or_else(|| "syntax error".to_string())?
+                    .iter()
+                    .map(|w| w.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| "ERR weight value is not a float".to_string())?;
+                opts.weights = Some(weights);
+                i += 1 + numkeys;
+            }
+            "AGGREGATE" if allow_aggregate => {
+                opts.aggregate = args
+                    .get(i + 1)
+                    .and_then(|a| ZAggregate::parse(a))
+                    .ok_or_else(|| "syntax error".to_string())?;
+                i += 2;
+            }
+            "WITHSCORES" => {
+                opts.withscores = true;
+                i += 1;
+            }
+            _ => return Err("syntax error".to_string()),
+        }
+    }
+
+    Ok(opts)
+}
+
+/// Parse the options of `SORT key [BY pattern] [LIMIT offset count]
+/// [GET pattern ...] [ASC|DESC] [ALPHA] [STORE destination]`, returning
+/// them with the STORE destination
+fn parse_sort_args(args: &[String]) -> Result<(SortOptions, Option<String>), String> {
+    let mut opts = SortOptions::default();
+    let mut store = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = |n: usize| args.get(i + n).ok_or_else(|| "syntax error".to_string());
+        match args[i].to_uppercase().as_str() {
+            "BY" => {
+                opts.by = Some(value(1)?.clone());
+                i += 2;
+            }
+            "LIMIT" => {
+                let offset = value(1)?.parse::<i64>();
+                let count = value(2)?.parse::<i64>();
+                let (Ok(offset), Ok(count)) = (offset, count) else {
+                    return Err("value is not an integer or out of range".to_string());
+                };
+                // A negative count returns everything after the offset
+                let count = if count < 0 { usize::MAX } else { count as usize };
+                opts.limit = Some((offset.max(0) as usize, count));
+                i += 3;
+            }
+            "GET" => {
+                opts.get.push(value(1)?.clone());
+                i += 2;
+            }
+            "ASC" => {
+                opts.desc = false;
+                i += 1;
+            }
+            "DESC" => {
+                opts.desc = true;
+                i += 1;
+            }
+            "ALPHA" => {
+                opts.alpha = true;
+                i += 1;
+            }
+            "STORE" => {
+                store = Some(value(1)?.clone());
+                i += 2;
+            }
+            _ => return Err("syntax error".to_string()),
+        }
+    }
+    Ok((opts, store))
+}
+
+/// Render sorted set members, interleaving scores when requested
+fn zset_pairs_response(members: Vec<(String, f64)>, withscores: bool) -> RespValue {
+    let resp = members
+        .into_iter()
+        .flat_map(|(member, score)| {
+            if withscores {
+                vec![
+                    RespValue::BulkString(Some(member)),
+                    RespValue::BulkString(Some(score.to_string())),
+                ]
+            } else {
+                vec![RespValue::BulkString(Some(member))]
+            }
+        })
+        .collect();
+    RespValue::Array(Some(resp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build an interpreter backed by a throwaway AOF file
+    fn test_interpreter() -> Interpreter {
+        let path = std::env::temp_dir().join(format!("hexagondb-test-{}.aof", uuid::Uuid::new_v4()));
+        let aof = Aof::new(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        Interpreter::new(
+            Arc::new(RwLock::new(DB::new())),
+            Arc::new(RwLock::new(aof)),
+            Arc::new(ServerInfo::new()),
+            Arc::new(RwLock::new(Config::default())),
+            Arc::new(PubSub::new()),
+        )
+    }
+
+    /// Build an interpreter logging to an AOF at `path`
+    fn aof_interpreter(path: &std::path::Path) -> Interpreter {
+        Interpreter::new(
+            Arc::new(RwLock::new(DB::new())),
+            Arc::new(RwLock::new(Aof::new(path).unwrap())),
+            Arc::new(ServerInfo::new()),
+            Arc::new(RwLock::new(Config::default())),
+            Arc::new(PubSub::new()),
+        )
+    }
+
+    /// Replay the AOF at `path` into a fresh database, then delete its files
+    async fn replay_aof(path: &std::path::Path) -> Arc<RwLock<DB>> {
+        let replayed = Arc::new(RwLock::new(DB::new()));
+        let disabled = Arc::new(RwLock::new(Aof::disabled()));
+        crate::persistence::recovery::recover(
+            &crate::persistence::recovery::Source::Aof(path.to_path_buf()),
+            &replayed,
+            &disabled,
+            &crate::persistence::loading::LoadProgress::new(),
+        )
+        .await
+        .unwrap();
+        for (_, segment) in crate::persistence::aof::segment_paths(path).unwrap() {
+            let _ = std::fs::remove_file(segment);
+        }
+        let _ = std::fs::remove_file(path);
+        replayed
+    }
+
+    async fn run(interp: &mut Interpreter, cmd: &[&str]) -> RespValue {
+        let tokens = cmd
+            .iter()
+            .map(|s| RespValue::BulkString(Some(s.to_string())))
+            .collect();
+        match interp.execute(RespValue::Array(Some(tokens))).await {
+            ExecutionResult::Response(resp) => resp,
+            ExecutionResult::Subscribe(..) => panic!("unexpected subscribe"),
+            ExecutionResult::Block(_) => panic!("unexpected block"),
+        }
+    }
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::BulkString(Some(s.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_default_ttl_rules() {
+        let mut interp = test_interpreter();
+        interp.config.write().await.default_ttl = vec![
+            crate::config::DefaultTtlRule {
+                pattern: "session:*".to_string(),
+                ttl_seconds: 100,
+                key_type: Some("hash".to_string()),
+            },
+            crate::config::DefaultTtlRule {
+                pattern: "cache:*".to_string(),
+                ttl_seconds: 50,
+                key_type: None,
+            },
+        ];
+
+        run(&mut interp, &["HSET", "session:1", "user", "42"]).await;
+        run(&mut interp, &["SET", "session:2", "x"]).await;
+        run(&mut interp, &["HSET", "other", "user", "42"]).await;
+
+        match run(&mut interp, &["TTL", "session:1"]).await {
+            RespValue::Integer(ttl) => assert!(ttl > 0 && ttl <= 100),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(run(&mut interp, &["TTL", "session:2"]).await, RespValue::Integer(-1));
+        assert_eq!(run(&mut interp, &["TTL", "other"]).await, RespValue::Integer(-1));
+
+        // Keys other than the first argument are covered too
+        run(&mut interp, &["SET", "tmp", "v"]).await;
+        run(&mut interp, &["RENAME", "tmp", "cache:1"]).await;
+        match run(&mut interp, &["TTL", "cache:1"]).await {
+            RespValue::Integer(ttl) => assert!(ttl > 0 && ttl <= 50),
+            other => panic!("unexpected reply {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capped_lists() {
+        use crate::config::CappedListRule;
+
+        let mut interp = test_interpreter();
+        interp.config.write().await.capped_list = vec![
+            CappedListRule { pattern: "log:*".to_string(), max_len: 3, policy: CapPolicy::Trim },
+            CappedListRule { pattern: "jobs".to_string(), max_len: 2, policy: CapPolicy::Reject },
+        ];
+        let list = |items: &[&str]| RespValue::Array(Some(items.iter().map(|s| bulk(s)).collect()));
+
+        assert_eq!(run(&mut interp, &["RPUSH", "log:a", "1", "2", "3", "4"]).await, RespValue::Integer(3));
+        assert_eq!(run(&mut interp, &["RPUSH", "log:a", "5"]).await, RespValue::Integer(3));
+        assert_eq!(run(&mut interp, &["LRANGE", "log:a", "0", "-1"]).await, list(&["3", "4", "5"]));
+        assert_eq!(run(&mut interp, &["LPUSH", "log:a", "6"]).await, RespValue::Integer(3));
+        assert_eq!(run(&mut interp, &["LRANGE", "log:a", "0", "-1"]).await, list(&["6", "3", "4"]));
+
+        assert_eq!(run(&mut interp, &["RPUSH", "jobs", "a", "b"]).await, RespValue::Integer(2));
+        assert!(matches!(run(&mut interp, &["LPUSH", "jobs", "c"]).await, RespValue::Error(_)));
+        assert_eq!(run(&mut interp, &["LRANGE", "jobs", "0", "-1"]).await, list(&["a", "b"]));
+
+        assert_eq!(run(&mut interp, &["RPUSH", "other", "1", "2", "3", "4"]).await, RespValue::Integer(4));
+    }
+
+    #[tokio::test]
+    async fn test_incrby_bounds() {
+        let mut interp = test_interpreter();
+
+        assert_eq!(run(&mut interp, &["INCRBY", "quota", "7", "MAX", "10"]).await, RespValue::Integer(7));
+        assert!(matches!(
+            run(&mut interp, &["INCRBY", "quota", "7", "MAX", "10"]).await,
+            RespValue::Error(_)
+        ));
+        assert_eq!(
+            run(&mut interp, &["INCRBY", "quota", "7", "MAX", "10", "CLAMP"]).await,
+            RespValue::Integer(10)
+        );
+        assert_eq!(
+            run(&mut interp, &["INCRBY", "quota", "-20", "MIN", "0", "CLAMP"]).await,
+            RespValue::Integer(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_string_counters_and_append() {
+        let mut interp = test_interpreter();
+
+        assert_eq!(run(&mut interp, &["DECRBY", "n", "5"]).await, RespValue::Integer(-5));
+        assert_eq!(run(&mut interp, &["DECRBY", "n", "-7"]).await, RespValue::Integer(2));
+        assert!(matches!(run(&mut interp, &["DECRBY", "n", "x"]).await, RespValue::Error(_)));
+
+        assert_eq!(run(&mut interp, &["INCRBYFLOAT", "f", "10.5"]).await, bulk("10.5"));
+        assert_eq!(run(&mut interp, &["INCRBYFLOAT", "f", "-0.5"]).await, bulk("10"));
+        assert!(matches!(run(&mut interp, &["INCRBYFLOAT", "f", "inf"]).await, RespValue::Error(_)));
+
+        assert_eq!(run(&mut interp, &["GETSET", "g", "a"]).await, RespValue::BulkString(None));
+        run(&mut interp, &["EXPIRE", "g", "100"]).await;
+        assert_eq!(run(&mut interp, &["GETSET", "g", "b"]).await, bulk("a"));
+        assert_eq!(run(&mut interp, &["TTL", "g"]).await, RespValue::Integer(-1));
+
+        assert_eq!(run(&mut interp, &["APPEND", "s", "Hello"]).await, RespValue::Integer(5));
+        assert_eq!(run(&mut interp, &["APPEND", "s", " World"]).await, RespValue::Integer(11));
+        assert_eq!(run(&mut interp, &["GET", "s"]).await, bulk("Hello World"));
+
+        run(&mut interp, &["RPUSH", "list", "x"]).await;
+        for cmd in [&["APPEND", "list", "y"][..], &["GETSET", "list", "y"], &["INCRBYFLOAT", "list", "1"]] {
+            assert!(matches!(run(&mut interp, cmd).await, RespValue::Error(e) if e.starts_with("WRONGTYPE")));
+        }
+        assert_eq!(run(&mut interp, &["LLEN", "list"]).await, RespValue::Integer(1));
+    }
+
+    #[tokio::test]
+    async fn test_wrongtype_for_special_types() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["RPUSH", "list", "a"]).await;
+
+        for cmd in [
+            &["GEOADD", "list", "13.36", "38.11", "palermo"][..],
+            &["GEODIST", "list", "a", "b"],
+            &["SETBIT", "list", "0", "1"],
+            &["GETBIT", "list", "0"],
+            &["BITCOUNT", "list"],
+            &["PFADD", "list", "x"],
+            &["PFCOUNT", "list"],
+            &["XADD", "list", "*", "f", "v"],
+            &["XLEN", "list"],
+            &["XREAD", "STREAMS", "list", "0"],
+        ] {
+            assert!(
+                matches!(run(&mut interp, cmd).await, RespValue::Error(e) if e.starts_with("WRONGTYPE")),
+                "{:?}",
+                cmd
+            );
+        }
+        assert_eq!(run(&mut interp, &["LRANGE", "list", "0", "-1"]).await, RespValue::Array(Some(vec![bulk("a")])));
+    }
+
+    #[tokio::test]
+    async fn test_bit_offset_limits() {
+        let mut interp = test_interpreter();
+        interp.config.write().await.server.proto_max_bulk_len = 1024;
+        let offset_error = RespValue::Error("bit offset is not an integer or out of range".to_string());
+
+        assert_eq!(run(&mut interp, &["SETBIT", "bits", "8191", "1"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["GETBIT", "bits", "8191"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["SETBIT", "bits", "8192", "1"]).await, offset_error);
+        assert_eq!(run(&mut interp, &["GETBIT", "bits", "8192"]).await, offset_error);
+        assert_eq!(run(&mut interp, &["SETBIT", "bits", "-1", "1"]).await, offset_error);
+        assert_eq!(run(&mut interp, &["SETBIT", "bits", "abc", "1"]).await, offset_error);
+        assert_eq!(
+            run(&mut interp, &["SETBIT", "bits", "0", "2"]).await,
+            RespValue::Error("bit is not an integer or out of range".to_string())
+        );
+        assert_eq!(run(&mut interp, &["BITCOUNT", "bits"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["STRLEN", "bits"]).await, RespValue::Integer(1024));
+    }
+
+    #[tokio::test]
+    async fn test_hash_commands() {
+        let mut interp = test_interpreter();
+
+        assert_eq!(run(&mut interp, &["HSET", "m", "a", "1", "b", "2"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["HSET", "m", "b", "3", "c", "4"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["HMSET", "m", "d", "5"]).await, RespValue::ok());
+        assert!(matches!(run(&mut interp, &["HSET", "m", "a"]).await, RespValue::Error(_)));
+        assert_eq!(run(&mut interp, &["HLEN", "m"]).await, RespValue::Integer(4));
+
+        assert_eq!(run(&mut interp, &["HSETNX", "h", "a", "1"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["HSETNX", "h", "a", "2"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["HINCRBY", "h", "a", "5"]).await, RespValue::Integer(6));
+        assert_eq!(run(&mut interp, &["HINCRBYFLOAT", "h", "f", "1.5"]).await, bulk("1.5"));
+        assert_eq!(
+            run(&mut interp, &["HMGET", "h", "a", "missing"]).await,
+            RespValue::Array(Some(vec![bulk("6"), RespValue::BulkString(None)]))
+        );
+        assert_eq!(run(&mut interp, &["HLEN", "h"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["HEXISTS", "h", "f"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["HSTRLEN", "h", "f"]).await, RespValue::Integer(3));
+
+        match run(&mut interp, &["HKEYS", "h"]).await {
+            RespValue::Array(Some(keys)) => assert_eq!(keys.len(), 2),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        match run(&mut interp, &["HRANDFIELD", "h", "-3", "WITHVALUES"]).await {
+            RespValue::Array(Some(items)) => assert_eq!(items.len(), 6),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        let min = i64::MIN.to_string();
+        assert!(matches!(run(&mut interp, &["HRANDFIELD", "h", &min]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_hscan_sscan() {
+        let mut interp = test_interpreter();
+        for i in 0..25 {
+            let field = format!("f{}", i);
+            run(&mut interp, &["HSET", "h", &field, "v"]).await;
+            run(&mut interp, &["SADD", "s", &field]).await;
+        }
+
+        let mut fields = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            match run(&mut interp, &["HSCAN", "h", &cursor, "COUNT", "7", "NOVALUES"]).await {
+                RespValue::Array(Some(reply)) => {
+                    cursor = match &reply[0] {
+                        RespValue::BulkString(Some(c)) => c.clone(),
+                        other => panic!("unexpected cursor {:?}", other),
+                    };
+                    match &reply[1] {
+                        RespValue::Array(Some(items)) => fields.extend(items.iter().cloned()),
+                        other => panic!("unexpected items {:?}", other),
+                    }
+                }
+                other => panic!("unexpected reply {:?}", other),
+            }
+            if cursor == "0" {
+                break;
+            }
+        }
+        assert_eq!(fields.len(), 25);
+        assert!(!fields.contains(&bulk("v")));
+
+        match run(&mut interp, &["SSCAN", "s", "0", "MATCH", "f1*", "COUNT", "100"]).await {
+            RespValue::Array(Some(reply)) => {
+                assert_eq!(reply[0], bulk("0"));
+                match &reply[1] {
+                    RespValue::Array(Some(items)) => assert_eq!(items.len(), 11),
+                    other => panic!("unexpected items {:?}", other),
+                }
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert!(matches!(run(&mut interp, &["SSCAN", "s", "0", "NOVALUES"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["HSCAN", "h", "x"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["HSCAN", "s", "0"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_session_commands() {
+        let mut interp = test_interpreter();
+
+        assert_eq!(
+            run(&mut interp, &["SESSION.CREATE", "sess", "100", "user", "ada"]).await,
+            RespValue::Integer(1)
+        );
+        // An existing session is never overwritten
+        assert_eq!(
+            run(&mut interp, &["SESSION.CREATE", "sess", "100", "user", "eve"]).await,
+            RespValue::Integer(0)
+        );
+        assert!(matches!(run(&mut interp, &["TTL", "sess"]).await, RespValue::Integer(99..=100)));
+
+        // Reads slide the expiry to the given idle timeout
+        assert_eq!(
+            run(&mut interp, &["SESSION.GET", "sess", "500"]).await,
+            RespValue::Array(Some(vec![bulk("user"), bulk("ada")]))
+        );
+        assert!(matches!(run(&mut interp, &["TTL", "sess"]).await, RespValue::Integer(499..=500)));
+        assert_eq!(
+            run(&mut interp, &["SESSION.REFRESH", "sess", "900", "cart", "3"]).await,
+            RespValue::Integer(1)
+        );
+        assert!(matches!(run(&mut interp, &["TTL", "sess"]).await, RespValue::Integer(899..=900)));
+        assert_eq!(
+            run(&mut interp, &["SESSION.GET", "sess", "900", "cart", "missing"]).await,
+            RespValue::Array(Some(vec![bulk("3"), RespValue::BulkString(None)]))
+        );
+
+        assert_eq!(run(&mut interp, &["SESSION.GET", "none", "10"]).await, RespValue::Array(None));
+        assert_eq!(run(&mut interp, &["SESSION.REFRESH", "none", "10"]).await, RespValue::Integer(0));
+        assert!(matches!(run(&mut interp, &["SESSION.GET", "sess", "0"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["SESSION.CREATE", "s2", "10"]).await, RespValue::Error(_)));
+        run(&mut interp, &["SET", "str", "v"]).await;
+        assert!(matches!(run(&mut interp, &["SESSION.GET", "str", "10"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_priority_queue_commands() {
+        let mut interp = test_interpreter();
+        let message = |id: i64, payload: &str| RespValue::Array(Some(vec![RespValue::Integer(id), bulk(payload)]));
+
+        assert_eq!(
+            run(&mut interp, &["PQ.PUSH", "q", "5", "later", "1", "soon"]).await,
+            RespValue::Array(Some(vec![RespValue::Integer(1), RespValue::Integer(2)]))
+        );
+        run(&mut interp, &["PQ.PUSH", "q", "1", "next"]).await;
+        assert_eq!(run(&mut interp, &["TYPE", "q"]).await, RespValue::SimpleString("queue".to_string()));
+
+        assert_eq!(run(&mut interp, &["PQ.POP", "q", "30"]).await, message(2, "soon"));
+        assert_eq!(
+            run(&mut interp, &["PQ.POP", "q", "30", "COUNT", "5"]).await,
+            RespValue::Array(Some(vec![message(3, "next"), message(1, "later")]))
+        );
+        // Everything is leased
+        assert_eq!(run(&mut interp, &["PQ.POP", "q", "30"]).await, RespValue::Array(None));
+        assert_eq!(run(&mut interp, &["PQ.POP", "q", "30", "COUNT", "2"]).await, RespValue::Array(Some(vec![])));
+
+        assert_eq!(run(&mut interp, &["PQ.ACK", "q", "1", "2", "9"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["PQ.ACK", "q", "3"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["EXISTS", "q"]).await, RespValue::Integer(0));
+
+        assert!(matches!(run(&mut interp, &["PQ.PUSH", "q", "high", "x"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["PQ.POP", "q", "0"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["PQ.POP", "q", "1", "LIMIT", "2"]).await, RespValue::Error(_)));
+        run(&mut interp, &["SET", "s", "v"]).await;
+        assert!(matches!(run(&mut interp, &["PQ.POP", "s", "1"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_timeseries_commands() {
+        let mut interp = test_interpreter();
+        let sample = |ts: i64, value: &str| RespValue::Array(Some(vec![RespValue::Integer(ts), bulk(value)]));
+
+        assert_eq!(run(&mut interp, &["TS.CREATE", "temp:a", "LABELS", "room", "a"]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["TS.CREATE", "temp:a:hourly"]).await, RespValue::ok());
+        assert_eq!(
+            run(&mut interp, &["TS.CREATERULE", "temp:a", "temp:a:hourly", "AGGREGATION", "max", "100"]).await,
+            RespValue::ok()
+        );
+        for (ts, value) in [("10", "1"), ("20", "3.5"), ("110", "2")] {
+            run(&mut interp, &["TS.ADD", "temp:a", ts, value]).await;
+        }
+        run(&mut interp, &["TS.ADD", "temp:b", "15", "7", "LABELS", "room", "b"]).await;
+        assert_eq!(run(&mut interp, &["TYPE", "temp:a"]).await, RespValue::SimpleString("TSDB-TYPE".to_string()));
+        assert!(matches!(run(&mut interp, &["TS.ADD", "temp:a", "110", "0"]).await, RespValue::Error(_)));
+
+        assert_eq!(run(&mut interp, &["TS.GET", "temp:a"]).await, sample(110, "2"));
+        assert_eq!(run(&mut interp, &["TS.GET", "temp:a:hourly"]).await, RespValue::Array(Some(vec![])));
+        assert_eq!(
+            run(&mut interp, &["TS.RANGE", "temp:a", "-", "+", "COUNT", "2"]).await,
+            RespValue::Array(Some(vec![sample(10, "1"), sample(20, "3.5")]))
+        );
+        assert_eq!(
+            run(&mut interp, &["TS.RANGE", "temp:a", "0", "200", "AGGREGATION", "sum", "100"]).await,
+            RespValue::Array(Some(vec![sample(0, "4.5"), sample(100, "2")]))
+        );
+        assert_eq!(
+            run(&mut interp, &["TS.MRANGE", "-", "+", "WITHLABELS", "FILTER", "room!=a", "room!="]).await,
+            RespValue::Array(Some(vec![RespValue::Array(Some(vec![
+                bulk("temp:b"),
+                RespValue::Array(Some(vec![RespValue::Array(Some(vec![bulk("room"), bulk("b")]))])),
+                RespValue::Array(Some(vec![sample(15, "7")])),
+            ]))]))
+        );
+
+        // The background task folds closed buckets into the destination
+        assert_eq!(
+            interp.db.write().await.compact_timeseries(),
+            vec![("temp:a:hourly".to_string(), 0, 3.5)]
+        );
+        assert_eq!(run(&mut interp, &["TS.GET", "temp:a:hourly"]).await, sample(0, "3.5"));
+        assert_eq!(run(&mut interp, &["TS.DELETERULE", "temp:a", "temp:a:hourly"]).await, RespValue::ok());
+        assert!(interp.db.write().await.compact_timeseries().is_empty());
+        assert!(!interp.db.read().await.has_timeseries_rules());
+
+        assert!(matches!(run(&mut interp, &["TS.GET", "missing"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["TS.MRANGE", "-", "+"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["TS.RANGE", "temp:a", "-", "+", "LIMIT"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["TS.ADD", "temp:a", "200", "hot"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_filter_commands() {
+        let mut interp = test_interpreter();
+        let flags = |flags: &[i64]| RespValue::Array(Some(flags.iter().map(|&f| RespValue::Integer(f)).collect()));
+
+        assert_eq!(run(&mut interp, &["BF.RESERVE", "bf", "0.01", "2", "NONSCALING"]).await, RespValue::ok());
+        assert!(matches!(run(&mut interp, &["BF.RESERVE", "bf", "0.01", "2"]).await, RespValue::Error(_)));
+        assert_eq!(run(&mut interp, &["BF.ADD", "bf", "a"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["BF.ADD", "bf", "a"]).await, RespValue::Integer(0));
+        match run(&mut interp, &["BF.MADD", "bf", "b", "c"]).await {
+            RespValue::Array(Some(replies)) => {
+                assert_eq!(replies[0], RespValue::Integer(1));
+                assert!(matches!(replies[1], RespValue::Error(_)));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(run(&mut interp, &["BF.MEXISTS", "bf", "a", "b", "c"]).await, flags(&[1, 1, 0]));
+        assert_eq!(run(&mut interp, &["BF.EXISTS", "nope", "a"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["TYPE", "bf"]).await, RespValue::SimpleString("MBbloom--".to_string()));
+
+        assert_eq!(run(&mut interp, &["CF.ADD", "cf", "x"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["CF.ADD", "cf", "x"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["CF.ADDNX", "cf", "x"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["CF.COUNT", "cf", "x"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["CF.DEL", "cf", "x"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["CF.MEXISTS", "cf", "x", "y"]).await, flags(&[1, 0]));
+        match run(&mut interp, &["CF.INFO", "cf"]).await {
+            RespValue::Array(Some(fields)) => {
+                assert_eq!(fields[0], bulk("Size"));
+                assert_eq!(fields[7], RespValue::Integer(1));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+
+        assert!(matches!(run(&mut interp, &["CF.DEL", "missing", "x"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["BF.INFO", "missing"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["BF.ADD", "bf", "a", "b"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["CF.ADD", "bf", "a"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_vector_commands() {
+        let mut interp = test_interpreter();
+        let hit = |id: &str, distance: &str| RespValue::Array(Some(vec![bulk(id), bulk(distance)]));
+
+        assert_eq!(run(&mut interp, &["VECTOR.ADD", "docs", "a", "1,0", "lang", "en"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["VECTOR.ADD", "docs", "b", "0,2", "lang", "de"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["VECTOR.ADD", "docs", "c", "0.5, 0.5"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["VECTOR.ADD", "docs", "c", "3,4"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["VECTOR.CARD", "docs"]).await, RespValue::Integer(3));
+        assert_eq!(run(&mut interp, &["TYPE", "docs"]).await, RespValue::SimpleString("vectorset".to_string()));
+
+        assert_eq!(
+            run(&mut interp, &["VECTOR.SEARCH", "docs", "0,1", "2", "METRIC", "L2"]).await,
+            RespValue::Array(Some(vec![hit("b", "1"), hit("a", "1.4142135")]))
+        );
+        assert_eq!(
+            run(&mut interp, &["VECTOR.SEARCH", "docs", "1,0", "5", "FILTER", "lang", "en", "WITHMETA"]).await,
+            RespValue::Array(Some(vec![RespValue::Array(Some(vec![
+                bulk("a"),
+                bulk("0"),
+                RespValue::Array(Some(vec![bulk("lang"), bulk("en")])),
+            ]))]))
+        );
+        assert_eq!(
+            run(&mut interp, &["VECTOR.GET", "docs", "c"]).await,
+            RespValue::Array(Some(vec![bulk("3,4"), RespValue::Array(Some(vec![]))]))
+        );
+        assert_eq!(run(&mut interp, &["VECTOR.GET", "docs", "zz"]).await, RespValue::Array(None));
+        assert_eq!(run(&mut interp, &["VECTOR.SEARCH", "none", "1,0", "3"]).await, RespValue::Array(Some(vec![])));
+
+        assert!(matches!(run(&mut interp, &["VECTOR.ADD", "docs", "d", "1,2,3"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["VECTOR.ADD", "docs", "d", "1,abc"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["VECTOR.ADD", "docs", "d", "1,2", "lang"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["VECTOR.SEARCH", "docs", "1,0", "x"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["VECTOR.SEARCH", "docs", "1,0", "1", "METRIC", "dot"]).await, RespValue::Error(_)));
+
+        assert_eq!(run(&mut interp, &["VECTOR.DEL", "docs", "a", "b", "c", "d"]).await, RespValue::Integer(3));
+        assert_eq!(run(&mut interp, &["EXISTS", "docs"]).await, RespValue::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_hash_field_ttl_commands() {
+        let mut interp = test_interpreter();
+        let ints = |v: &[i64]| RespValue::Array(Some(v.iter().map(|&i| RespValue::Integer(i)).collect()));
+
+        run(&mut interp, &["HSET", "h", "a", "1", "b", "2"]).await;
+        assert_eq!(
+            run(&mut interp, &["HEXPIRE", "h", "100", "FIELDS", "2", "a", "nope"]).await,
+            ints(&[1, -2])
+        );
+        assert_eq!(run(&mut interp, &["HPEXPIRE", "h", "5000", "NX", "FIELDS", "1", "a"]).await, ints(&[0]));
+        assert_eq!(run(&mut interp, &["HTTL", "h", "FIELDS", "2", "a", "b"]).await, ints(&[99, -1]));
+        assert_eq!(run(&mut interp, &["HPERSIST", "h", "FIELDS", "1", "a"]).await, ints(&[1]));
+        assert_eq!(run(&mut interp, &["HPTTL", "h", "FIELDS", "1", "a"]).await, ints(&[-1]));
+        assert!(matches!(
+            run(&mut interp, &["HTTL", "h", "FIELDS", "2", "a"]).await,
+            RespValue::Error(_)
+        ));
+
+        let at = (unix_millis_now() + 50_000).to_string();
+        assert_eq!(run(&mut interp, &["HPEXPIREAT", "h", &at, "FIELDS", "1", "b"]).await, ints(&[1]));
+        assert_eq!(run(&mut interp, &["HTTL", "h", "FIELDS", "1", "b"]).await, ints(&[49]));
+        assert_eq!(run(&mut interp, &["HEXPIREAT", "h", "1", "FIELDS", "1", "b"]).await, ints(&[2]));
+        assert_eq!(run(&mut interp, &["HEXISTS", "h", "b"]).await, RespValue::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_hash_field_ttl_logged_as_unix_time() {
+        let aof = std::env::temp_dir().join(format!("hexagondb-hexpire-{}.aof", uuid::Uuid::new_v4()));
+        let mut interp = aof_interpreter(&aof);
+        run(&mut interp, &["HSET", "h", "a", "1", "b", "2"]).await;
+        run(&mut interp, &["HEXPIRE", "h", "100", "FIELDS", "2", "a", "nope"]).await;
+
+        let mut logged = String::new();
+        for (_, segment) in crate::persistence::aof::segment_paths(&aof).unwrap() {
+            logged.push_str(&std::fs::read_to_string(&segment).unwrap_or_default());
+        }
+        assert!(logged.contains("HPEXPIREAT") && !logged.contains("nope"), "{}", logged);
+
+        let replayed = replay_aof(&aof).await;
+        let ttls = replayed.write().await.httl("h", vec!["a".to_string(), "b".to_string()]).unwrap();
+        assert_eq!(ttls, vec![99, -1]);
+    }
+
+    #[tokio::test]
+    async fn test_undelete_logged_as_value() {
+        let aof = std::env::temp_dir().join(format!("hexagondb-undelete-{}.aof", uuid::Uuid::new_v4()));
+        let mut interp = aof_interpreter(&aof);
+        interp.db.write().await.recycle_bin.set_retention(Some(std::time::Duration::from_secs(60)));
+        run(&mut interp, &["SET", "k", "v"]).await;
+        run(&mut interp, &["DEL", "k"]).await;
+        assert_eq!(run(&mut interp, &["UNDELETE", "k"]).await, RespValue::Integer(1));
+
+        // The replayed database has no recycle bin to restore from
+        let replayed = replay_aof(&aof).await;
+        assert_eq!(replayed.write().await.get("k").unwrap(), Some("v".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_expire_family() {
+        let mut interp = test_interpreter();
+
+        run(&mut interp, &["SET", "k", "v"]).await;
+        assert_eq!(run(&mut interp, &["EXPIRE", "k", "100", "XX"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["PEXPIRE", "k", "100000", "NX"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["EXPIRE", "k", "50", "GT"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["EXPIRE", "k", "50", "LT"]).await, RespValue::Integer(1));
+        match run(&mut interp, &["PTTL", "k"]).await {
+            RespValue::Integer(ms) => assert!(ms > 45_000 && ms <= 50_000),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert!(matches!(run(&mut interp, &["EXPIRE", "k", "50", "BOGUS"]).await, RespValue::Error(_)));
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let at = (now + 1000).to_string();
+        assert_eq!(run(&mut interp, &["EXPIREAT", "k", &at]).await, RespValue::Integer(1));
+        match run(&mut interp, &["EXPIRETIME", "k"]).await {
+            RespValue::Integer(t) => assert!((t - (now + 1000)).abs() <= 1),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(run(&mut interp, &["PEXPIRETIME", "missing"]).await, RespValue::Integer(-2));
+
+        assert_eq!(run(&mut interp, &["PEXPIREAT", "k", "1"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["EXISTS", "k"]).await, RespValue::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_debug_failpoint() {
+        let mut interp = test_interpreter();
+
+        assert!(matches!(run(&mut interp, &["DEBUG", "NOPE"]).await, RespValue::Error(_)));
+        assert!(matches!(
+            run(&mut interp, &["DEBUG", "FAILPOINT", "aof-append", "explode"]).await,
+            RespValue::Error(_)
+        ));
+        let reply = run(&mut interp, &["DEBUG", "FAILPOINT", "aof-append", "delay", "1"]).await;
+        if failpoint::enabled() {
+            assert_eq!(reply, RespValue::ok());
+            assert_eq!(
+                run(&mut interp, &["DEBUG", "FAILPOINT"]).await,
+                RespValue::Array(Some(vec![bulk("aof-append delay 1")]))
+            );
+            assert_eq!(run(&mut interp, &["DEBUG", "FAILPOINT", "aof-append", "off"]).await, RespValue::ok());
+        } else {
+            assert!(matches!(reply, RespValue::Error(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_variadic_del_and_exists() {
+        let mut interp = test_interpreter();
+
+        run(&mut interp, &["SET", "a", "1"]).await;
+        run(&mut interp, &["SET", "b", "2"]).await;
+        assert_eq!(run(&mut interp, &["EXISTS", "a", "b", "a", "missing"]).await, RespValue::Integer(3));
+        assert_eq!(run(&mut interp, &["DEL", "a", "b", "missing", "a"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["DEL", "a"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["EXISTS", "a", "b"]).await, RespValue::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_variadic_del_and_exists_skip_expired_keys() {
+        let mut interp = test_interpreter();
+
+        run(&mut interp, &["SET", "live", "1"]).await;
+        run(&mut interp, &["SET", "stale", "1"]).await;
+        run(&mut interp, &["PEXPIRE", "stale", "1"]).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        assert_eq!(run(&mut interp, &["EXISTS", "live", "stale"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["DEL", "stale", "live"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["DEL"]).await, RespValue::Error("wrong number of arguments for 'DEL' command".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_dump_restore() {
+        let mut interp = test_interpreter();
+
+        run(&mut interp, &["HSET", "h", "f", "v"]).await;
+        let payload = match run(&mut interp, &["DUMP", "h"]).await {
+            RespValue::BulkString(Some(payload)) => payload,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(run(&mut interp, &["DUMP", "missing"]).await, RespValue::BulkString(None));
+
+        assert!(matches!(run(&mut interp, &["RESTORE", "h", "0", &payload]).await, RespValue::Error(e) if e.starts_with("BUSYKEY")));
+        assert_eq!(run(&mut interp, &["RESTORE", "copy", "5000", &payload]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["HGET", "copy", "f"]).await, bulk("v"));
+        assert!(matches!(run(&mut interp, &["PTTL", "copy"]).await, RespValue::Integer(ms) if ms > 0 && ms <= 5000));
+        assert_eq!(run(&mut interp, &["RESTORE", "copy", "0", &payload, "REPLACE"]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["TTL", "copy"]).await, RespValue::Integer(-1));
+
+        assert!(matches!(run(&mut interp, &["RESTORE", "bad", "0", "00ff"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["RESTORE", "bad", "-1", &payload]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_xread_max_entries() {
+        let mut interp = test_interpreter();
+
+        for i in 1..=4 {
+            run(&mut interp, &["XADD", "hot", &format!("{}-0", i), "n", "1"]).await;
+        }
+        run(&mut interp, &["XADD", "cold", "1-0", "n", "1"]).await;
+
+        let reply = run(&mut interp, &["XREAD", "MAXENTRIES", "2", "STREAMS", "hot", "cold", "0", "0"]).await;
+        let RespValue::Array(Some(streams)) = reply else { panic!("unexpected reply {:?}", reply) };
+        assert_eq!(streams.len(), 2);
+        for stream in &streams {
+            let RespValue::Array(Some(parts)) = stream else { panic!() };
+            assert!(matches!(&parts[1], RespValue::Array(Some(entries)) if entries.len() == 1));
+        }
+
+        assert_eq!(run(&mut interp, &["XREAD", "STREAMS", "cold", "1-0"]).await, RespValue::Array(None));
+        assert!(matches!(run(&mut interp, &["XREAD", "STREAMS", "hot"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["XREAD", "BLOCK", "0", "STREAMS", "hot", "0"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        use crate::network::connection::handle_client;
+        use crate::network::resp::ParseLimits;
+        use crate::network::scheduler::CommandScheduler;
+
+        let target = test_interpreter();
+        let target_db = target.db.clone();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        tokio::spawn(async move {
+            let mut target = target;
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_client(stream, &mut target, &CommandScheduler::default(), ParseLimits::default(), usize::MAX, Default::default(), None).await;
+        });
+
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "a", "1"]).await;
+        run(&mut interp, &["RPUSH", "b", "x", "y"]).await;
+        assert_eq!(
+            run(&mut interp, &["MIGRATE", "127.0.0.1", &port, "", "0", "1000", "KEYS", "a", "b", "missing"]).await,
+            RespValue::ok()
+        );
+        assert_eq!(run(&mut interp, &["EXISTS", "a", "b"]).await, RespValue::Integer(0));
+        {
+            let mut db = target_db.write().await;
+            assert_eq!(db.get("a").unwrap(), Some("1".to_string()));
+            assert_eq!(db.lrange("b", 0, -1).unwrap(), vec!["x", "y"]);
+        }
+
+        assert_eq!(
+            run(&mut interp, &["MIGRATE", "127.0.0.1", &port, "a", "0", "1000"]).await,
+            RespValue::SimpleString("NOKEY".to_string())
+        );
+        assert!(matches!(
+            run(&mut interp, &["MIGRATE", "127.0.0.1", &port, "a", "1", "1000"]).await,
+            RespValue::Error(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_does_not_hold_the_lock() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A target that replies once told to, or never
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            if reply_rx.await.is_ok() {
+                let _ = stream.write_all(b"+OK\r\n").await;
+            }
+            std::future::pending::<()>().await;
+        });
+
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "a", "1"]).await;
+        let db = Arc::clone(&interp.db);
+        let migrate = {
+            let port = port.clone();
+            tokio::spawn(async move {
+                let start = std::time::Instant::now();
+                let reply = run(&mut interp, &["MIGRATE", "127.0.0.1", &port, "a", "0", "5000"]).await;
+                (reply, start.elapsed(), interp)
+            })
+        };
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        // Writes go on during the transfer; a key changed meanwhile is kept
+        tokio::time::timeout(std::time::Duration::from_millis(500), db.write())
+            .await
+            .expect("MIGRATE held the lock across the transfer")
+            .set("a".to_string(), "2".to_string());
+        reply_tx.send(()).unwrap();
+        let (reply, _, mut interp) = migrate.await.unwrap();
+        assert_eq!(reply, RespValue::ok());
+        assert_eq!(db.write().await.get("a").unwrap(), Some("2".to_string()));
+
+        // The timeout bounds the whole exchange with a silent target
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port().to_string();
+        tokio::spawn(async move {
+            let _connection = listener.accept().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let start = std::time::Instant::now();
+        let reply = run(&mut interp, &["MIGRATE", "127.0.0.1", &port, "a", "0", "200"]).await;
+        assert!(matches!(reply, RespValue::Error(e) if e.contains("timeout")));
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert!(db.read().await.exists("a"));
+    }
+
+    #[tokio::test]
+    async fn test_publish_retains_last_message() {
+        let mut interp = test_interpreter();
+        interp.config.write().await.retain.push(crate::config::RetainRule {
+            pattern: "sensors:*".to_string(),
+            ttl_seconds: None,
+        });
+
+        run(&mut interp, &["PUBLISH", "sensors:temp", "20"]).await;
+        run(&mut interp, &["PUBLISH", "sensors:temp", "21"]).await;
+        run(&mut interp, &["PUBLISH", "chat", "hi"]).await;
+
+        let tokens = |cmd: &[&str]| RespValue::Array(Some(cmd.iter().map(|s| bulk(s)).collect()));
+        match interp.execute(tokens(&["SUBSCRIBE", "sensors:temp"])).await {
+            ExecutionResult::Subscribe(_, replies) => {
+                assert_eq!(replies[1], tokens(&["message", "sensors:temp", "21"]))
+            }
+            _ => panic!("unexpected reply"),
+        }
+        match interp.execute(tokens(&["SUBSCRIBE", "chat"])).await {
+            ExecutionResult::Subscribe(_, replies) => assert_eq!(replies.len(), 1),
+            _ => panic!("unexpected reply"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_logs_to_stream() {
+        let mut interp = test_interpreter();
+        interp.config.write().await.pubsub_log.push(crate::config::PubSubLogRule {
+            pattern: "orders:*".to_string(),
+            max_len: 2,
+        });
+
+        for message in ["1", "2", "3"] {
+            assert_eq!(run(&mut interp, &["PUBLISH", "orders:eu", message]).await, RespValue::Integer(0));
+        }
+        run(&mut interp, &["PUBLISH", "chat", "hi"]).await;
+
+        let message_of = |entry: &RespValue| match entry {
+            RespValue::Array(Some(pair)) => pair[1].clone(),
+            _ => panic!("unexpected entry {:?}", entry),
+        };
+        let RespValue::Array(Some(entries)) = run(&mut interp, &["XRANGE", "__pubsub_log__:orders:eu", "-", "+"]).await else {
+            panic!("unexpected reply")
+        };
+        let messages: Vec<_> = entries.iter().map(message_of).collect();
+        assert_eq!(
+            messages,
+            vec![
+                RespValue::Array(Some(vec![bulk("message"), bulk("2")])),
+                RespValue::Array(Some(vec![bulk("message"), bulk("3")])),
+            ]
+        );
+        let RespValue::Array(Some(entries)) =
+            run(&mut interp, &["XREVRANGE", "__pubsub_log__:orders:eu", "+", "-", "COUNT", "1"]).await
+        else {
+            panic!("unexpected reply")
+        };
+        assert_eq!(message_of(&entries[0]), RespValue::Array(Some(vec![bulk("message"), bulk("3")])));
+        assert_eq!(run(&mut interp, &["EXISTS", "__pubsub_log__:chat"]).await, RespValue::Integer(0));
+        assert!(matches!(
+            run(&mut interp, &["XRANGE", "__pubsub_log__:orders:eu", "-", "+", "LIMIT", "1"]).await,
+            RespValue::Error(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_trimming() {
+        let mut interp = test_interpreter();
+        for id in ["1-0", "2-0", "3-0"] {
+            run(&mut interp, &["XADD", "s", id, "n", id]).await;
+        }
+        assert_eq!(run(&mut interp, &["XADD", "s", "MAXLEN", "~", "2", "4-0", "n", "4"]).await, bulk("4-0"));
+        assert_eq!(run(&mut interp, &["XLEN", "s"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["XTRIM", "s", "MINID", "4"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["XTRIM", "s", "MAXLEN", "5"]).await, RespValue::Integer(0));
+        assert!(matches!(run(&mut interp, &["XTRIM", "s", "MAXLEN", "1", "extra"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["XADD", "s", "MAXLEN", "x", "*", "n", "5"]).await, RespValue::Error(_)));
+
+        interp.config.write().await.memory.stream_max_len = 2;
+        for n in ["1", "2", "3"] {
+            run(&mut interp, &["XADD", "capped", "*", "n", n]).await;
+        }
+        assert_eq!(run(&mut interp, &["XLEN", "capped"]).await, RespValue::Integer(2));
+        run(&mut interp, &["XADD", "capped", "MAXLEN", "3", "*", "n", "4"]).await;
+        assert_eq!(run(&mut interp, &["XLEN", "capped"]).await, RespValue::Integer(3));
+    }
+
+    #[tokio::test]
+    async fn test_shard_pubsub_commands() {
+        let mut interp = test_interpreter();
+        let tokens = |cmd: &[&str]| RespValue::Array(Some(cmd.iter().map(|s| bulk(s)).collect()));
+        let ExecutionResult::Subscribe(_subscriber, replies) =
+            interp.execute(tokens(&["SSUBSCRIBE", "orders"])).await
+        else {
+            panic!("unexpected reply")
+        };
+        assert_eq!(replies, vec![RespValue::Array(Some(vec![bulk("ssubscribe"), bulk("orders"), RespValue::Integer(1)]))]);
+
+        assert_eq!(run(&mut interp, &["SPUBLISH", "orders", "1"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["PUBLISH", "orders", "1"]).await, RespValue::Integer(0));
+        assert_eq!(
+            run(&mut interp, &["PUBSUB", "SHARDCHANNELS", "ord*"]).await,
+            RespValue::Array(Some(vec![bulk("orders")]))
+        );
+        assert_eq!(
+            run(&mut interp, &["PUBSUB", "SHARDNUMSUB", "orders", "users"]).await,
+            RespValue::Array(Some(vec![bulk("orders"), RespValue::Integer(1), bulk("users"), RespValue::Integer(0)]))
+        );
+        assert_eq!(run(&mut interp, &["PUBSUB", "CHANNELS"]).await, RespValue::Array(Some(vec![])));
+        assert_eq!(run(&mut interp, &["PUBSUB", "NUMPAT"]).await, RespValue::Integer(0));
+        assert!(matches!(run(&mut interp, &["PUBSUB", "NOPE"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_access_log_samples_successful_commands() {
+        let log = Arc::new(AccessLog::new(1, "__accesslog__".to_string(), 100));
+        let mut interp = test_interpreter().with_access_log(Some(log));
+
+        run(&mut interp, &["SET", "k", "v"]).await;
+        run(&mut interp, &["PING"]).await;
+        // Fails before touching the keyspace: not a hot key
+        assert!(matches!(run(&mut interp, &["INCR", "k"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["GET"]).await, RespValue::Error(_)));
+        run(&mut interp, &["ZUNION", "1", "z"]).await;
+        assert_eq!(run(&mut interp, &["XLEN", "__accesslog__"]).await, RespValue::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn test_slowlog_commands() {
+        let slowlog = Arc::new(SlowLog::new());
+        slowlog.set_threshold(0);
+        let mut interp = test_interpreter().with_slowlog(Arc::clone(&slowlog));
+
+        run(&mut interp, &["SET", "k", "v"]).await;
+        run(&mut interp, &["GET", "k"]).await;
+        assert_eq!(run(&mut interp, &["SLOWLOG", "LEN"]).await, RespValue::Integer(2));
+
+        let reply = run(&mut interp, &["SLOWLOG", "GET", "1"]).await;
+        let RespValue::Array(Some(entries)) = reply else { panic!("unexpected reply {:?}", reply) };
+        assert_eq!(entries.len(), 1);
+        let RespValue::Array(Some(fields)) = &entries[0] else { panic!() };
+        // Newest first; the SLOWLOG LEN above was logged too
+        assert_eq!(fields[3], RespValue::Array(Some(vec![bulk("SLOWLOG"), bulk("LEN")])));
+
+        assert_eq!(run(&mut interp, &["SLOWLOG", "RESET"]).await, RespValue::ok());
+        // The RESET itself is logged once it completes
+        assert_eq!(slowlog.len(), 1);
+        assert!(matches!(run(&mut interp, &["SLOWLOG", "NOPE"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_list_pop_count_and_lpos() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["RPUSH", "l", "a", "b", "c", "b", "d"]).await;
+
+        let int = RespValue::Integer;
+        assert_eq!(run(&mut interp, &["LPOS", "l", "b"]).await, int(1));
+        assert_eq!(run(&mut interp, &["LPOS", "l", "b", "RANK", "-1"]).await, int(3));
+        assert_eq!(
+            run(&mut interp, &["LPOS", "l", "b", "COUNT", "0"]).await,
+            RespValue::Array(Some(vec![int(1), int(3)]))
+        );
+        assert_eq!(run(&mut interp, &["LPOS", "l", "b", "MAXLEN", "1"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["LPOS", "l", "b", "RANK", "0"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["LPOS", "l", "b", "COUNT"]).await, RespValue::Error(_)));
+
+        assert_eq!(
+            run(&mut interp, &["LPOP", "l", "2"]).await,
+            RespValue::Array(Some(vec![bulk("a"), bulk("b")]))
+        );
+        assert_eq!(run(&mut interp, &["RPOP", "l", "1"]).await, RespValue::Array(Some(vec![bulk("d")])));
+        assert_eq!(run(&mut interp, &["RPOP", "l", "0"]).await, RespValue::Array(Some(vec![])));
+        assert_eq!(run(&mut interp, &["LPOP", "missing", "3"]).await, RespValue::Array(None));
+        assert!(matches!(run(&mut interp, &["LPOP", "l", "-1"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_lmpop_and_zmpop() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["RPUSH", "l2", "a", "b", "c"]).await;
+        run(&mut interp, &["ZADD", "z", "1", "one", "2", "two", "3", "three"]).await;
+
+        let pair = |key: &str, items: Vec<RespValue>| RespValue::Array(Some(vec![bulk(key), RespValue::Array(Some(items))]));
+        assert_eq!(
+            run(&mut interp, &["LMPOP", "2", "l1", "l2", "RIGHT", "COUNT", "2"]).await,
+            pair("l2", vec![bulk("c"), bulk("b")])
+        );
+        assert_eq!(run(&mut interp, &["LMPOP", "1", "l2", "LEFT"]).await, pair("l2", vec![bulk("a")]));
+        assert_eq!(run(&mut interp, &["LMPOP", "1", "l2", "LEFT"]).await, RespValue::Array(None));
+        let tokens = ["BLMPOP", "0.5", "1", "l2", "LEFT"].iter().map(|s| bulk(s)).collect();
+        match interp.execute(RespValue::Array(Some(tokens))).await {
+            ExecutionResult::Block(blocked) => assert_eq!(blocked.timeout, Some(std::time::Duration::from_millis(500))),
+            _ => panic!("expected BLMPOP to block"),
+        }
+        assert!(matches!(run(&mut interp, &["LMPOP", "0", "l2", "LEFT"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["LMPOP", "1", "l2", "UP"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["LMPOP", "1", "l2", "LEFT", "COUNT", "0"]).await, RespValue::Error(_)));
+
+        let member = |m: &str, s: &str| RespValue::Array(Some(vec![bulk(m), bulk(s)]));
+        assert_eq!(
+            run(&mut interp, &["ZMPOP", "1", "z", "MAX", "COUNT", "2"]).await,
+            pair("z", vec![member("three", "3"), member("two", "2")])
+        );
+        assert_eq!(run(&mut interp, &["BZMPOP", "1", "1", "z", "MIN"]).await, pair("z", vec![member("one", "1")]));
+        assert_eq!(run(&mut interp, &["ZMPOP", "1", "z", "MIN"]).await, RespValue::Array(None));
+        run(&mut interp, &["SET", "s", "v"]).await;
+        assert!(matches!(run(&mut interp, &["ZMPOP", "1", "s", "MIN"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["BZMPOP", "-1", "1", "z", "MIN"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_zrank_and_zrevrank() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["ZADD", "z", "1", "one", "2", "two", "3", "three"]).await;
+        run(&mut interp, &["ZADD", "z", "0", "three"]).await;
+
+        assert_eq!(run(&mut interp, &["ZRANK", "z", "three"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["ZRANK", "z", "two"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["ZREVRANK", "z", "two"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["ZRANK", "z", "four"]).await, RespValue::BulkString(None));
+        assert_eq!(run(&mut interp, &["ZREVRANK", "missing", "one"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["ZRANK", "z"]).await, RespValue::Error(_)));
+
+        assert_eq!(
+            run(&mut interp, &["ZREVRANK", "z", "two", "WITHSCORE"]).await,
+            RespValue::Array(Some(vec![RespValue::Integer(0), bulk("2")]))
+        );
+        assert_eq!(run(&mut interp, &["ZRANK", "z", "four", "WITHSCORE"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["ZRANK", "z", "two", "WITHSCORES"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_zaround() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["ZADD", "board", "10", "ann", "20", "bob", "30", "cat", "40", "dan"]).await;
+
+        let entry = |rank: i64, member: &str, score: &str| {
+            RespValue::Array(Some(vec![RespValue::Integer(rank), bulk(member), bulk(score)]))
+        };
+        assert_eq!(
+            run(&mut interp, &["ZAROUND", "board", "bob", "1"]).await,
+            RespValue::Array(Some(vec![entry(0, "ann", "10"), entry(1, "bob", "20"), entry(2, "cat", "30")]))
+        );
+        assert_eq!(
+            run(&mut interp, &["ZAROUND", "board", "cat", "1", "REV"]).await,
+            RespValue::Array(Some(vec![entry(0, "dan", "40"), entry(1, "cat", "30"), entry(2, "bob", "20")]))
+        );
+        assert_eq!(run(&mut interp, &["ZAROUND", "board", "eve", "1"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["ZAROUND", "board", "bob", "-1"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["ZAROUND", "board", "bob", "1", "UP"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_blmpop_waits_for_push() {
+        use crate::network::connection::handle_client;
+        use crate::network::resp::ParseLimits;
+        use crate::network::scheduler::CommandScheduler;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let server = test_interpreter();
+        let db = server.db.clone();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut server = server;
+            let (stream, _) = listener.accept().await.unwrap();
+            handle_client(stream, &mut server, &CommandScheduler::default(), ParseLimits::default(), usize::MAX, Default::default(), None).await;
+        });
+
+        let mut client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        client.write_all(b"*5\r\n$6\r\nBLMPOP\r\n$1\r\n0\r\n$1\r\n1\r\n$1\r\nq\r\n$4\r\nLEFT\r\n").await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        db.write().await.rpush("q".to_string(), vec!["job".to_string()]).unwrap();
+        blocking::signal_ready();
+
+        let expected = b"*2\r\n$1\r\nq\r\n*1\r\n$3\r\njob\r\n";
+        let mut reply = vec![0u8; expected.len()];
+        tokio::time::timeout(std::time::Duration::from_secs(5), client.read_exact(&mut reply)).await.unwrap().unwrap();
+        assert_eq!(&reply[..], &expected[..]);
+
+        client.write_all(b"*5\r\n$6\r\nBLMPOP\r\n$4\r\n0.05\r\n$1\r\n1\r\n$1\r\nq\r\n$4\r\nLEFT\r\n").await.unwrap();
+        let mut reply = [0u8; 5];
+        tokio::time::timeout(std::time::Duration::from_secs(5), client.read_exact(&mut reply)).await.unwrap().unwrap();
+        assert_eq!(&reply, b"*-1\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_sintercard_and_sort() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SADD", "s1", "a", "b", "c"]).await;
+        run(&mut interp, &["SADD", "s2", "b", "c", "d"]).await;
+        assert_eq!(run(&mut interp, &["SINTERCARD", "2", "s1", "s2"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["SINTERCARD", "2", "s1", "s2", "LIMIT", "1"]).await, RespValue::Integer(1));
+        assert!(matches!(run(&mut interp, &["SINTERCARD", "3", "s1", "s2"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["SINTERCARD", "0", "s1"]).await, RespValue::Error(_)));
+
+        run(&mut interp, &["RPUSH", "l", "3", "1", "2"]).await;
+        for (key, weight) in [("w_1", "30"), ("w_2", "10"), ("w_3", "20")] {
+            run(&mut interp, &["SET", key, weight]).await;
+        }
+        let list = |items: &[&str]| RespValue::Array(Some(items.iter().map(|s| bulk(s)).collect()));
+        assert_eq!(run(&mut interp, &["SORT", "l", "DESC"]).await, list(&["3", "2", "1"]));
+        assert_eq!(run(&mut interp, &["SORT", "l", "BY", "w_*", "LIMIT", "0", "2"]).await, list(&["2", "3"]));
+        assert_eq!(
+            run(&mut interp, &["SORT", "l", "BY", "w_*", "GET", "#", "GET", "w_*", "STORE", "out"]).await,
+            RespValue::Integer(6)
+        );
+        assert_eq!(run(&mut interp, &["LRANGE", "out", "0", "-1"]).await, list(&["2", "10", "3", "20", "1", "30"]));
+        assert!(matches!(run(&mut interp, &["SORT", "s1"]).await, RespValue::Error(_)));
+        assert_eq!(run(&mut interp, &["SORT", "s1", "ALPHA"]).await, list(&["a", "b", "c"]));
+        assert!(matches!(run(&mut interp, &["SORT", "l", "LIMIT", "0"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_fcall() {
+        use crate::functions::HexagonFunction;
+
+        /// Append its arguments to a list and reply with the new length
+        struct Journal;
+
+        impl HexagonFunction for Journal {
+            fn name(&self) -> &str {
+                "test_journal"
+            }
+
+            fn acl_categories(&self) -> &[&str] {
+                &["write", "list"]
+            }
+
+            fn call(&self, ctx: &mut FunctionContext<'_>) -> Result<RespValue, String> {
+                let key = ctx.keys().first().cloned().ok_or("a key is required")?;
+                let values = ctx.args().to_vec();
+                let len = ctx.db().rpush(key, values).map_err(|e| e.to_string())?;
+                Ok(RespValue::Integer(len as i64))
+            }
+        }
+
+        functions::registry().register(Arc::new(Journal));
+        let aof = std::env::temp_dir().join(format!("hexagondb-fcall-{}.aof", uuid::Uuid::new_v4()));
+        let mut interp = aof_interpreter(&aof);
+        assert_eq!(
+            run(&mut interp, &["FCALL", "TEST_JOURNAL", "1", "j", "a", "b"]).await,
+            RespValue::Integer(2)
+        );
+        assert_eq!(
+            run(&mut interp, &["LRANGE", "j", "0", "-1"]).await,
+            RespValue::Array(Some(vec![bulk("a"), bulk("b")]))
+        );
+        assert!(matches!(run(&mut interp, &["FCALL", "test_journal", "0"]).await, RespValue::Error(e) if e == "a key is required"));
+        assert!(matches!(run(&mut interp, &["FCALL_RO", "test_journal", "1", "j"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["FCALL", "test_journal", "2", "j"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["FCALL", "missing", "0"]).await, RespValue::Error(_)));
+
+        // The result was logged as the list itself
+        let replayed = replay_aof(&aof).await;
+        assert_eq!(replayed.write().await.lrange("j", 0, -1).unwrap(), vec!["a", "b"]);
+
+        let RespValue::Array(Some(stats)) = run(&mut interp, &["FUNCTION", "STATS"]).await else { panic!() };
+        let journal = stats
+            .iter()
+            .find(|s| matches!(s, RespValue::Array(Some(f)) if f[1] == bulk("TEST_JOURNAL")))
+            .unwrap();
+        let RespValue::Array(Some(fields)) = journal else { panic!() };
+        assert_eq!(fields[3], RespValue::Integer(2));
+        assert_eq!(fields[5], RespValue::Integer(1));
+        assert!(matches!(run(&mut interp, &["FUNCTION", "LIST"]).await, RespValue::Array(Some(list)) if !list.is_empty()));
+        assert!(matches!(run(&mut interp, &["FUNCTION", "DELETE"]).await, RespValue::Error(_)));
+        functions::registry().unregister("test_journal");
+    }
+
+    #[tokio::test]
+    async fn test_schedule() {
+        let mut interp = test_interpreter();
+        let ok = RespValue::SimpleString("OK".to_string());
+        assert_eq!(run(&mut interp, &["SCHEDULE", "ADD", "tick", "@every 60", "INCR ticks"]).await, ok);
+        assert_eq!(
+            run(&mut interp, &["SCHEDULE", "ADD", "reap", "@expire tmp:*", "RPUSH reaped {key}"]).await,
+            ok
+        );
+        assert!(matches!(run(&mut interp, &["SCHEDULE", "ADD", "bad", "* * *", "PING"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["SCHEDULE", "ADD", "bad"]).await, RespValue::Error(_)));
+        assert_eq!(
+            run(&mut interp, &["SCHEDULE", "LIST"]).await,
+            RespValue::Array(Some(vec![
+                RespValue::Array(Some(vec![bulk("reap"), bulk("@expire tmp:*"), bulk("RPUSH reaped {key}")])),
+                RespValue::Array(Some(vec![bulk("tick"), bulk("@every 60"), bulk("INCR ticks")])),
+            ]))
+        );
+
+        // An expired key runs the @expire job, which is logged
+        run(&mut interp, &["SET", "tmp:1", "x"]).await;
+        run(&mut interp, &["PEXPIRE", "tmp:1", "1"]).await;
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let db = Arc::clone(&interp.db);
+        let jobs = scheduler::jobs(&mut *db.write().await);
+        let expired = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = Arc::clone(&expired);
+        db.write().await.on_expire(move |key, _| seen.lock().unwrap().push(key.to_string()));
+        scheduler::expire_watched(&mut *db.write().await, &jobs);
+        assert_eq!(*expired.lock().unwrap(), vec!["tmp:1"]);
+        let config = crate::config::SchedulerConfig::default();
+        for job in scheduler::on_expire(&jobs, "tmp:1") {
+            scheduler::run_job(&mut interp, &db, &config, job, Some("tmp:1")).await;
+        }
+        assert_eq!(run(&mut interp, &["LRANGE", "reaped", "0", "-1"]).await, RespValue::Array(Some(vec![bulk("tmp:1")])));
+        let log = db.write().await.xrange(&config.log_stream_key, "-", "+", None).unwrap();
+        assert_eq!(log.len(), 1);
+        assert!(log[0].1.contains(&("reply".to_string(), "1".to_string())));
+        assert!(log[0].1.contains(&("key".to_string(), "tmp:1".to_string())));
+
+        assert_eq!(run(&mut interp, &["SCHEDULE", "DEL", "tick"]).await, RespValue::Integer(1));
+        assert_eq!(run(&mut interp, &["SCHEDULE", "DEL", "tick"]).await, RespValue::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_runs_as_owner() {
+        let security = Arc::new(Security::new());
+        let mut admin = test_interpreter().with_security(Arc::clone(&security));
+        assert_eq!(
+            run(&mut admin, &["ACL", "SETUSER", "app", "on", ">secret", "+@all", "~app:*", "~__schedule__"]).await,
+            RespValue::ok()
+        );
+        let mut app = test_interpreter().with_security(Arc::clone(&security));
+        assert_eq!(run(&mut app, &["AUTH", "app", "secret"]).await, RespValue::ok());
+
+        // The job store cannot be written around SCHEDULE, even with a key rule for it
+        let reserved = run(&mut app, &["HSET", scheduler::SCHEDULE_KEY, "x", "@every 1\nFLUSHALL"]).await;
+        assert!(matches!(reserved, RespValue::Error(e) if e.contains("reserved")));
+        assert!(matches!(run(&mut app, &["DEL", scheduler::SCHEDULE_KEY]).await, RespValue::Error(_)));
+
+        assert_eq!(run(&mut app, &["SCHEDULE", "ADD", "own", "@every 60", "SET app:1 v"]).await, RespValue::ok());
+        assert_eq!(run(&mut app, &["SCHEDULE", "ADD", "other", "@every 60", "SET other v"]).await, RespValue::ok());
+        let db = Arc::clone(&admin.db);
+        let jobs = scheduler::jobs(&mut *db.write().await);
+        assert!(jobs.iter().all(|job| job.owner.as_deref() == Some("app")));
+
+        // The scheduler's interpreter runs each job as its owner
+        let mut runner = test_interpreter().with_security(security);
+        let config = crate::config::SchedulerConfig::default();
+        for job in &jobs {
+            let reply = scheduler::run_job(&mut runner, &db, &config, job, None).await;
+            match job.name.as_str() {
+                "own" => assert_eq!(reply, RespValue::ok()),
+                _ => assert!(matches!(reply, RespValue::Error(e) if e.starts_with("NOPERM"))),
+            }
+        }
+        assert!(!db.read().await.exists("other"));
+    }
+
+    #[tokio::test]
+    async fn test_readonly() {
+        let mut interp = test_interpreter();
+        let ok = RespValue::SimpleString("OK".to_string());
+        let readonly = RespValue::Error(READONLY_ERROR.to_string());
+        run(&mut interp, &["SET", "k", "v"]).await;
+        assert_eq!(run(&mut interp, &["READONLY"]).await, ok);
+        assert_eq!(run(&mut interp, &["SET", "k", "w"]).await, readonly);
+        assert_eq!(run(&mut interp, &["DEL", "k"]).await, readonly);
+        assert_eq!(run(&mut interp, &["SCHEDULE", "ADD", "t", "@every 1", "PING"]).await, readonly);
+        assert_eq!(run(&mut interp, &["GET", "k"]).await, bulk("v"));
+        assert!(matches!(run(&mut interp, &["SCHEDULE", "LIST"]).await, RespValue::Array(_)));
+        assert_eq!(run(&mut interp, &["READWRITE"]).await, ok);
+        assert_eq!(run(&mut interp, &["SET", "k", "w"]).await, ok);
+
+        run(&mut interp, &["READONLY"]).await;
+        run(&mut interp, &["RESET"]).await;
+        assert_eq!(run(&mut interp, &["SET", "k", "x"]).await, ok);
+
+        interp.config.write().await.server.read_only = true;
+        assert_eq!(run(&mut interp, &["SET", "k", "y"]).await, readonly);
+        assert_eq!(run(&mut interp, &["GET", "k"]).await, bulk("x"));
+    }
+
+    #[tokio::test]
+    async fn test_client_list() {
+        let mut interp = test_interpreter();
+        let _registration = interp.server_info().clients().register("10.0.0.1:5000");
+
+        let reply = run(&mut interp, &["CLIENT", "LIST"]).await;
+        let RespValue::BulkString(Some(list)) = reply else { panic!("unexpected reply {:?}", reply) };
+        assert!(list.starts_with("id=1 addr=10.0.0.1:5000 age=0 idle=0 flags=N\n"));
+        assert!(matches!(run(&mut interp, &["CLIENT", "KILL"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_latency_commands() {
+        let mut interp = test_interpreter();
+
+        // The monitor is process-wide; only check reply shapes here
+        assert!(matches!(run(&mut interp, &["LATENCY", "LATEST"]).await, RespValue::Array(Some(_))));
+        assert!(matches!(run(&mut interp, &["LATENCY", "HISTORY", "nope"]).await, RespValue::Array(Some(v)) if v.is_empty()));
+        assert_eq!(run(&mut interp, &["LATENCY", "RESET", "nope"]).await, RespValue::Integer(0));
+        assert!(matches!(run(&mut interp, &["LATENCY", "DOCTOR"]).await, RespValue::BulkString(Some(_))));
+        assert!(matches!(run(&mut interp, &["LATENCY", "HISTORY"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_loading_rejects_commands() {
+        let mut interp = test_interpreter();
+        interp.server_info.loading().begin(10);
+
+        assert!(matches!(run(&mut interp, &["SET", "k", "v"]).await, RespValue::Error(e) if e.starts_with("LOADING")));
+        assert!(matches!(run(&mut interp, &["GET", "k"]).await, RespValue::Error(e) if e.starts_with("LOADING")));
+        assert_eq!(run(&mut interp, &["PING"]).await, RespValue::pong());
+
+        interp.config.write().await.server.serve_reads_while_loading = true;
+        assert_eq!(run(&mut interp, &["GET", "k"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["SET", "k", "v"]).await, RespValue::Error(_)));
+
+        interp.server_info.loading().finish();
+        assert_eq!(run(&mut interp, &["SET", "k", "v"]).await, RespValue::ok());
+    }
+
+    #[tokio::test]
+    async fn test_info_sections_and_stats() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "a", "1"]).await;
+        run(&mut interp, &["SET", "b", "2"]).await;
+        run(&mut interp, &["EXPIRE", "b", "100"]).await;
+        run(&mut interp, &["GET", "a"]).await;
+        run(&mut interp, &["GET", "missing"]).await;
+        run(&mut interp, &["MGET", "a", "b", "nope"]).await;
+
+        let stats = match run(&mut interp, &["INFO", "stats"]).await {
+            RespValue::BulkString(Some(s)) => s,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+        assert!(stats.starts_with("# Stats\r\n"));
+        assert!(stats.contains("keyspace_hits:3\r\n"));
+        assert!(stats.contains("keyspace_misses:2\r\n"));
+        assert!(!stats.contains("# Keyspace"));
+
+        let all = match run(&mut interp, &["INFO"]).await {
+            RespValue::BulkString(Some(s)) => s,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+        for header in ["# Server", "# Clients", "# Memory", "# Persistence", "# Replication"] {
+            assert!(all.contains(header), "missing {}", header);
+        }
+        assert!(all.contains("db0:keys=2,expires=1\r\n"));
+        assert!(all.contains("rdb_last_save_time:"));
+        assert!(all.contains("aof_pending_fsync_bytes:"));
+        assert!(all.contains("rdb_bgsave_in_progress:0\r\n"));
+        assert!(all.contains("rdb_last_bgsave_status:ok\r\n"));
+        assert!(all.contains("aof_size:"));
+
+        assert_eq!(run(&mut interp, &["INFO", "nosuchsection"]).await, bulk(""));
+    }
+
+    #[tokio::test]
+    async fn test_debug_introspection() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "n", "42"]).await;
+        run(&mut interp, &["RPUSH", "l", "a", "bbbbbbbb"]).await;
+
+        let object = |reply: RespValue| match reply {
+            RespValue::SimpleString(s) => s,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+        assert!(object(run(&mut interp, &["DEBUG", "OBJECT", "n"]).await).contains("encoding:int"));
+        assert!(matches!(run(&mut interp, &["DEBUG", "OBJECT", "missing"]).await, RespValue::Error(_)));
+
+        assert_eq!(run(&mut interp, &["DEBUG", "QUICKLIST-PACKED-THRESHOLD", "4"]).await, RespValue::ok());
+        assert!(object(run(&mut interp, &["DEBUG", "OBJECT", "l"]).await).ends_with("ql_nodes:2 ql_plain_nodes:1"));
+        assert!(matches!(
+            run(&mut interp, &["DEBUG", "QUICKLIST-PACKED-THRESHOLD", "1x"]).await,
+            RespValue::Error(_)
+        ));
+        assert_eq!(parse_memory_size("2kb"), Some(2048));
+
+        assert_eq!(run(&mut interp, &["DEBUG", "SET-ACTIVE-EXPIRE", "0"]).await, RespValue::ok());
+        assert!(!interp.db.read().await.active_expire);
+        assert!(matches!(run(&mut interp, &["DEBUG", "SET-ACTIVE-EXPIRE", "2"]).await, RespValue::Error(_)));
+
+        let start = std::time::Instant::now();
+        assert_eq!(run(&mut interp, &["DEBUG", "SLEEP", "0.05"]).await, RespValue::ok());
+        assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_auth_and_acl() {
+        let security = Arc::new(Security::new());
+        let mut interp = test_interpreter().with_security(Arc::clone(&security));
+        assert!(matches!(run(&mut interp, &["AUTH", "pw"]).await, RespValue::Error(e) if e.contains("without any password")));
+
+        security.set_password(Some("pw".to_string()));
+        assert!(matches!(run(&mut interp, &["PING"]).await, RespValue::Error(e) if e.starts_with("NOAUTH")));
+        assert!(matches!(run(&mut interp, &["GET", "k"]).await, RespValue::Error(e) if e.starts_with("NOAUTH")));
+        assert!(matches!(run(&mut interp, &["AUTH", "bad"]).await, RespValue::Error(e) if e.starts_with("WRONGPASS")));
+        assert_eq!(run(&mut interp, &["AUTH", "pw"]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["SET", "app:1", "v"]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["ACL", "WHOAMI"]).await, bulk("default"));
+        assert_eq!(
+            run(&mut interp, &["ACL", "SETUSER", "app", "on", ">secret", "+get", "~app:*"]).await,
+            RespValue::ok()
+        );
+
+        let mut other = test_interpreter().with_security(Arc::clone(&security));
+        assert_eq!(run(&mut other, &["AUTH", "app", "secret"]).await, RespValue::ok());
+        assert!(matches!(run(&mut other, &["GET", "other"]).await, RespValue::Error(e) if e.starts_with("NOPERM")));
+        assert!(matches!(run(&mut other, &["DEL", "app:1", "other"]).await, RespValue::Error(e) if e.starts_with("NOPERM")));
+
+        // MIGRATE's key, or its KEYS list, is checked like any other key
+        assert_eq!(
+            run(&mut interp, &["ACL", "SETUSER", "mover", "on", ">secret", "+migrate", "~app:*"]).await,
+            RespValue::ok()
+        );
+        let mut mover = test_interpreter().with_security(Arc::clone(&security));
+        assert_eq!(run(&mut mover, &["AUTH", "mover", "secret"]).await, RespValue::ok());
+        let noperm = |reply: RespValue| matches!(reply, RespValue::Error(e) if e.starts_with("NOPERM"));
+        assert!(noperm(run(&mut mover, &["MIGRATE", "127.0.0.1", "1", "other", "0", "100"]).await));
+        assert!(noperm(run(&mut mover, &["MIGRATE", "127.0.0.1", "1", "", "0", "100", "KEYS", "app:1", "other"]).await));
+        assert!(!noperm(run(&mut mover, &["MIGRATE", "127.0.0.1", "1", "", "0", "100", "KEYS", "app:1"]).await));
+
+        assert_eq!(run(&mut interp, &["ACL", "DELUSER", "app"]).await, RespValue::Integer(1));
+        assert!(matches!(run(&mut other, &["GET", "app:1"]).await, RespValue::Error(e) if e.starts_with("NOAUTH")));
+
+        // RESET logs the connection out
+        assert_eq!(run(&mut interp, &["RESET"]).await, RespValue::SimpleString("RESET".to_string()));
+        assert!(matches!(run(&mut interp, &["GET", "app:1"]).await, RespValue::Error(e) if e.starts_with("NOAUTH")));
+    }
+
+    #[tokio::test]
+    async fn test_memory_compact() {
+        let mut interp = test_interpreter();
+        let mut push = vec!["RPUSH", "l"];
+        push.extend(std::iter::repeat_n("x", 200));
+        run(&mut interp, &push).await;
+        for _ in 0..198 {
+            run(&mut interp, &["RPOP", "l"]).await;
+        }
+        assert!(matches!(run(&mut interp, &["MEMORY", "COMPACT", "l"]).await, RespValue::Integer(n) if n > 0));
+        assert_eq!(run(&mut interp, &["MEMORY", "COMPACT", "l"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["LLEN", "l"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["MEMORY", "COMPACT", "nope"]).await, RespValue::Integer(0));
+    }
+
+    #[tokio::test]
+    async fn test_acl_commands() {
+        let mut interp = test_interpreter();
+        assert_eq!(run(&mut interp, &["ACL", "SETUSER", "ro", "on", "nopass", "+@read", "~*"]).await, RespValue::ok());
+        assert!(matches!(run(&mut interp, &["ACL", "SETUSER", "ro", "+@nope"]).await, RespValue::Error(_)));
+        match run(&mut interp, &["ACL", "LIST"]).await {
+            RespValue::Array(Some(lines)) => {
+                assert_eq!(lines.len(), 1);
+                assert!(matches!(&lines[0], RespValue::BulkString(Some(l)) if l.starts_with("user ro on ") && l.contains("+get")));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        match run(&mut interp, &["ACL", "GETUSER", "ro"]).await {
+            RespValue::Array(Some(fields)) => assert_eq!(fields[7], bulk("~*")),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(run(&mut interp, &["ACL", "GETUSER", "nobody"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["ACL", "CAT", "list"]).await, RespValue::Array(Some(c)) if c.contains(&bulk("lpush"))));
+        assert!(matches!(run(&mut interp, &["ACL", "GENPASS", "32"]).await, RespValue::BulkString(Some(p)) if p.len() == 8));
+    }
+
+    #[tokio::test]
+    async fn test_scan_and_memory_usage() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "s", "hello"]).await;
+        run(&mut interp, &["RPUSH", "l", "a"]).await;
+        run(&mut interp, &["HSET", "h", "f", "v"]).await;
+
+        let mut keys = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            match run(&mut interp, &["SCAN", &cursor, "COUNT", "1"]).await {
+                RespValue::Array(Some(reply)) => {
+                    let RespValue::BulkString(Some(next)) = &reply[0] else { panic!("bad cursor") };
+                    let RespValue::Array(Some(page)) = &reply[1] else { panic!("bad page") };
+                    keys.extend(page.iter().cloned());
+                    cursor = next.clone();
+                }
+                other => panic!("unexpected reply {:?}", other),
+            }
+            if cursor == "0" {
+                break;
+            }
+        }
+        keys.sort_by_key(|k| format!("{:?}", k));
+        assert_eq!(keys, vec![bulk("h"), bulk("l"), bulk("s")]);
+        assert_eq!(
+            run(&mut interp, &["SCAN", "0", "COUNT", "10", "TYPE", "LIST"]).await,
+            RespValue::Array(Some(vec![bulk("0"), RespValue::Array(Some(vec![bulk("l")]))]))
+        );
+        assert!(matches!(run(&mut interp, &["SCAN", "x"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["SCAN", "0", "NOVALUES"]).await, RespValue::Error(_)));
+
+        assert!(matches!(run(&mut interp, &["MEMORY", "USAGE", "s"]).await, RespValue::Integer(n) if n > 6));
+        assert!(matches!(run(&mut interp, &["MEMORY", "USAGE", "h", "SAMPLES", "5"]).await, RespValue::Integer(_)));
+        assert_eq!(run(&mut interp, &["MEMORY", "USAGE", "missing"]).await, RespValue::BulkString(None));
+        assert!(matches!(run(&mut interp, &["MEMORY", "USAGE", "s", "BOGUS"]).await, RespValue::Error(_)));
+        assert_eq!(run(&mut interp, &["STRLEN", "s"]).await, RespValue::Integer(5));
+    }
+
+    #[tokio::test]
+    async fn test_dbstats() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "a", "1"]).await;
+        run(&mut interp, &["SET", "b", "x".repeat(100).as_str()]).await;
+        run(&mut interp, &["RPUSH", "l", "x", "y"]).await;
+        run(&mut interp, &["HSET", "h", "f", "v"]).await;
+        run(&mut interp, &["DEL", "h"]).await;
+        assert_eq!(
+            run(&mut interp, &["DBSTATS"]).await,
+            RespValue::Array(Some(vec![
+                bulk("keys"),
+                RespValue::Integer(3),
+                bulk("types"),
+                RespValue::Array(Some(vec![bulk("list"), RespValue::Integer(1), bulk("string"), RespValue::Integer(2)])),
+            ]))
+        );
+
+        run(&mut interp, &["EXPIRE", "a", "30"]).await;
+        let reply = match run(&mut interp, &["DBSTATS", "FULL"]).await {
+            RespValue::Array(Some(reply)) => reply,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(reply[0], bulk("total"));
+        assert!(matches!(&reply[1], RespValue::Array(Some(f)) if f[..2] == [bulk("keys"), bulk("3")] && f[7] == bulk("1")));
+        match &reply[5] {
+            RespValue::Array(Some(biggest)) => {
+                assert_eq!(biggest.len(), 3);
+                assert!(matches!(&biggest[0], RespValue::Array(Some(k)) if k[0] == bulk("b") && k[1] == bulk("string")));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert!(matches!(run(&mut interp, &["DBSTATS", "BOGUS"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_dumpall() {
+        use crate::persistence::snapshot::RdbReader;
+
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "a", "1"]).await;
+        run(&mut interp, &["RPUSH", "l", "x", "y"]).await;
+
+        let payload = match run(&mut interp, &["DUMPALL"]).await {
+            RespValue::BulkString(Some(payload)) => payload,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        let bytes = dump::decode_hex(&payload).unwrap();
+        let mut reader = RdbReader::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = reader.next_entry().unwrap() {
+            keys.push(entry.key);
+        }
+        keys.sort();
+        assert_eq!(keys, vec!["a", "l"]);
+
+        assert!(matches!(run(&mut interp, &["DUMPALL", "aof"]).await, RespValue::BulkString(Some(p)) if !p.is_empty()));
+        assert!(matches!(run(&mut interp, &["DUMPALL", "RDB", "x"]).await, RespValue::Error(_)));
+        assert!(matches!(run(&mut interp, &["DUMPALL", "JSON"]).await, RespValue::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_lazy_free() {
+        let mut interp = test_interpreter();
+        let members: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+        let mut sadd = vec!["SADD", "big"];
+        sadd.extend(members.iter().map(String::as_str));
+        run(&mut interp, &sadd).await;
+        run(&mut interp, &["SET", "small", "x"]).await;
+        assert_eq!(run(&mut interp, &["UNLINK", "big", "small", "missing"]).await, RespValue::Integer(2));
+        assert_eq!(run(&mut interp, &["EXISTS", "big", "small"]).await, RespValue::Integer(0));
+
+        run(&mut interp, &sadd).await;
+        run(&mut interp, &["SET", "small", "x"]).await;
+        assert_eq!(run(&mut interp, &["FLUSHDB", "ASYNC"]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["DBSIZE"]).await, RespValue::Integer(0));
+        assert_eq!(run(&mut interp, &["SADD", "big", "1"]).await, RespValue::Integer(1));
+    }
+
+    #[tokio::test]
+    async fn test_flush_confirmation() {
+        let mut interp = test_interpreter();
+        run(&mut interp, &["SET", "a", "1"]).await;
+        assert_eq!(run(&mut interp, &["FLUSHALL", "ASYNC"]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["DBSIZE"]).await, RespValue::Integer(0));
+
+        interp.config.write().await.server.flush_confirmation = true;
+        run(&mut interp, &["SET", "a", "1"]).await;
+        assert!(matches!(run(&mut interp, &["FLUSHDB"]).await, RespValue::Error(e) if e.contains("SCHEDULE")));
+        assert!(matches!(run(&mut interp, &["FLUSHALL", "CONFIRM", "bogus"]).await, RespValue::Error(_)));
+        assert_eq!(run(&mut interp, &["DBSIZE"]).await, RespValue::Integer(1));
+
+        let token = match run(&mut interp, &["FLUSHALL", "SCHEDULE"]).await {
+            RespValue::BulkString(Some(token)) => token,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        assert_eq!(run(&mut interp, &["FLUSHALL", "CONFIRM", &token]).await, RespValue::ok());
+        assert_eq!(run(&mut interp, &["DBSIZE"]).await, RespValue::Integer(0));
+        assert!(matches!(run(&mut interp, &["FLUSHALL", "CONFIRM", &token]).await, RespValue::Error(_)));
+    }
+}
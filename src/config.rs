@@ -2,6 +2,8 @@
 //!
 //! Supports TOML configuration files and hot-reload via SIGHUP.
 
+use crate::network::output_limit::OutputLimits;
+use crate::network::resp::ParseLimits;
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
@@ -19,40 +21,171 @@ pub struct Config {
     pub memory: MemoryConfig,
     #[serde(default)]
     pub security: SecurityConfig,
+    #[serde(default)]
+    pub recycle_bin: RecycleBinConfig,
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+    #[serde(default)]
+    pub slowlog: SlowLogConfig,
+    #[serde(default)]
+    pub replication: ReplicationConfig,
+    #[serde(default)]
+    pub client_output_buffer_limit: ClientOutputBufferLimitConfig,
+    #[serde(default)]
+    pub default_ttl: Vec<DefaultTtlRule>,
+    #[serde(default)]
+    pub retain: Vec<RetainRule>,
+    #[serde(default)]
+    pub pubsub_log: Vec<PubSubLogRule>,
+    #[serde(default)]
+    pub capped_list: Vec<CappedListRule>,
+    #[serde(default)]
+    pub functions: FunctionsConfig,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
 }
 
 /// Server configuration
 #[derive(Debug, Clone, Deserialize)]
 pub struct ServerConfig {
+    /// Addresses to listen on, separated by spaces (e.g. "0.0.0.0 ::1")
     #[serde(default = "default_bind_address")]
     pub bind_address: String,
     #[serde(default = "default_port")]
     pub port: u16,
     #[serde(default = "default_max_connections")]
     pub max_connections: usize,
+    /// Close clients idle for this many seconds (0 = never)
     #[serde(default = "default_timeout")]
     pub timeout_seconds: u64,
+    /// Close clients that do not read a reply within this many seconds (0 = never)
+    #[serde(default)]
+    pub client_write_timeout_secs: u64,
     #[serde(default)]
     pub tcp_keepalive: bool,
+    /// Idle seconds before the first keepalive probe
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: u64,
+    /// Disable Nagle's algorithm on client connections
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    /// Set SO_REUSEPORT on the listeners so several processes can share a port (Unix only)
+    #[serde(default)]
+    pub reuse_port: bool,
+    /// Runtime worker threads (0 = one per CPU core)
+    #[serde(default)]
+    pub worker_threads: usize,
+    /// Most threads in the runtime's pool for blocking work
+    #[serde(default = "default_max_blocking_threads")]
+    pub max_blocking_threads: usize,
+    /// Commands a connection may run per turn when connections take turns
+    /// executing, one at a time (0 = no turns, the default)
+    #[serde(default)]
+    pub command_quantum: usize,
+    /// Name of this node, attached to metrics, logs and INFO
+    #[serde(default = "default_instance_name")]
+    pub instance_name: String,
+    /// Shard this node serves, attached alongside the instance name
+    #[serde(default)]
+    pub shard_id: u32,
+    /// Events at least this slow are tracked by LATENCY (0 = disabled)
+    #[serde(default)]
+    pub latency_monitor_threshold_ms: u64,
+    /// Serve read-only commands from the partially loaded dataset at startup
+    #[serde(default)]
+    pub serve_reads_while_loading: bool,
+    /// Reject write commands with -READONLY on every connection, e.g. for
+    /// an instance serving reads behind a load balancer
+    #[serde(default)]
+    pub read_only: bool,
+    /// Expect a PROXY protocol (v1 or v2) header on every connection
+    #[serde(default)]
+    pub proxy_protocol: bool,
+    /// Largest bulk string a client may send, in bytes
+    #[serde(default = "default_proto_max_bulk_len")]
+    pub proto_max_bulk_len: usize,
+    /// Most arguments in a single request
+    #[serde(default = "default_max_multibulk_len")]
+    pub max_multibulk_len: usize,
+    /// Most unparsed bytes buffered for a client before it is disconnected
+    #[serde(default = "default_client_query_buffer_limit")]
+    pub client_query_buffer_limit: usize,
+    /// Require FLUSHALL SCHEDULE + FLUSHALL CONFIRM instead of a plain flush
+    #[serde(default)]
+    pub flush_confirmation: bool,
+    /// Seconds a scheduled flush waits for confirmation
+    #[serde(default = "default_flush_confirmation_timeout")]
+    pub flush_confirmation_timeout_secs: u64,
 }
 
 /// Persistence configuration
 #[derive(Debug, Clone, Deserialize)]
 pub struct PersistenceConfig {
-    #[serde(default = "default_aof_enabled")]
+    /// Log writes to the AOF and restore from it on startup (`appendonly`)
+    #[serde(default = "default_aof_enabled", alias = "appendonly")]
     pub aof_enabled: bool,
+    /// Start rewritten AOF files with an RDB snapshot of the dataset
+    #[serde(default = "default_aof_use_rdb_preamble")]
+    pub aof_use_rdb_preamble: bool,
+    /// Start a new AOF segment once the current one reaches this many bytes (0 = never)
+    #[serde(default = "default_aof_segment_size")]
+    pub aof_segment_size: u64,
+    /// Compact the AOF into a new base once more segments than this are sealed (0 = never)
+    #[serde(default = "default_aof_max_segments")]
+    pub aof_max_segments: u64,
+    /// Where segments replaced by a compaction are moved (deleted if unset)
+    #[serde(default)]
+    pub aof_archive_dir: Option<String>,
     #[serde(default = "default_aof_fsync")]
     pub aof_fsync: String, // "always", "everysec", "no"
     #[serde(default)]
     pub aof_path: Option<String>,
+    /// Directory holding the AOF and RDB files (default: working directory)
+    #[serde(default)]
+    pub data_dir: Option<String>,
     #[serde(default = "default_rdb_enabled")]
     pub rdb_enabled: bool,
     #[serde(default = "default_rdb_save_interval")]
     pub rdb_save_interval: u64,
     #[serde(default = "default_rdb_min_changes")]
     pub rdb_min_changes: u64,
+    /// Snapshot when any of these is reached; empty means the single
+    /// point `rdb_save_interval` / `rdb_min_changes`
+    #[serde(default)]
+    pub save_points: Vec<SavePoint>,
+    /// LZ4-compress snapshot values of at least
+    /// `rdb_compression_threshold` serialized bytes
     #[serde(default)]
     pub rdb_compression: bool,
+    #[serde(default = "default_rdb_compression_threshold")]
+    pub rdb_compression_threshold: usize,
+}
+
+/// Take a background snapshot once `changes` writes have happened and
+/// `seconds` have passed since the last one (`save 900 1`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct SavePoint {
+    pub seconds: u64,
+    pub changes: u64,
+}
+
+impl PersistenceConfig {
+    /// Size from which snapshot values are compressed, None when disabled
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.rdb_compression.then_some(self.rdb_compression_threshold)
+    }
+
+    /// Save points in effect
+    pub fn save_points(&self) -> Vec<SavePoint> {
+        if self.save_points.is_empty() {
+            vec![SavePoint {
+                seconds: self.rdb_save_interval,
+                changes: self.rdb_min_changes,
+            }]
+        } else {
+            self.save_points.clone()
+        }
+    }
 }
 
 /// Logging configuration
@@ -73,6 +206,12 @@ pub struct MemoryConfig {
     pub maxmemory: Option<usize>, // bytes
     #[serde(default = "default_eviction_policy")]
     pub eviction_policy: String, // "noeviction", "allkeys-lru", "volatile-lru", etc.
+    /// Seconds between background compactions of fragmented collections (0 = off)
+    #[serde(default = "default_compaction_interval")]
+    pub compaction_interval_secs: u64,
+    /// Entries kept per stream by XADD without MAXLEN or MINID (0 = unlimited)
+    #[serde(default)]
+    pub stream_max_len: usize,
 }
 
 /// Security configuration
@@ -86,6 +225,202 @@ pub struct SecurityConfig {
     pub tls_cert_file: Option<String>,
     #[serde(default)]
     pub tls_key_file: Option<String>,
+    /// Only these client IPs may connect (empty = any)
+    #[serde(default)]
+    pub ip_whitelist: Vec<String>,
+    /// Client IPs rejected at accept time
+    #[serde(default)]
+    pub ip_blacklist: Vec<String>,
+    /// Commands per second allowed to each connection (0 = unlimited)
+    #[serde(default)]
+    pub max_commands_per_second: f64,
+    /// Open connections allowed from a single IP (0 = unlimited)
+    #[serde(default)]
+    pub max_connections_per_ip: usize,
+}
+
+/// Soft-delete configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecycleBinConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_recycle_retention")]
+    pub retention_seconds: u64,
+    /// Most deleted keys kept, oldest dropped first (0 = unlimited)
+    #[serde(default)]
+    pub max_entries: usize,
+    /// Most bytes of deleted keys kept, oldest dropped first (0 = unlimited)
+    #[serde(default)]
+    pub max_memory: usize,
+}
+
+/// Sampled access log configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccessLogConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_access_log_sample_rate")]
+    pub sample_rate: u64, // log 1 in N commands
+    #[serde(default = "default_access_log_stream_key")]
+    pub stream_key: String,
+    #[serde(default = "default_access_log_max_len")]
+    pub max_len: usize,
+}
+
+/// Writes kept out of the replication stream, e.g. edge-local scratch keys
+/// in a hub-and-spoke topology
+///
+/// ```toml
+/// [replication]
+/// exclude_keys = ["local:*", "scratch:*"]
+/// exclude_commands = ["DEBUG"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReplicationConfig {
+    /// Glob patterns of keys whose writes are not propagated
+    #[serde(default)]
+    pub exclude_keys: Vec<String>,
+    /// Commands (case-insensitive) that are never propagated
+    #[serde(default)]
+    pub exclude_commands: Vec<String>,
+}
+
+/// Output buffer limits per client class
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientOutputBufferLimitConfig {
+    #[serde(default)]
+    pub normal: OutputBufferLimitConfig,
+    #[serde(default = "default_pubsub_output_limit")]
+    pub pubsub: OutputBufferLimitConfig,
+    #[serde(default = "default_replica_output_limit")]
+    pub replica: OutputBufferLimitConfig,
+}
+
+/// Output buffer limit of one client class; 0 disables a limit
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OutputBufferLimitConfig {
+    /// Disconnect as soon as pending output exceeds this many bytes
+    #[serde(default)]
+    pub hard_limit: usize,
+    /// Disconnect when pending output stays above this many bytes ...
+    #[serde(default)]
+    pub soft_limit: usize,
+    /// ... for this many seconds
+    #[serde(default)]
+    pub soft_seconds: u64,
+}
+
+/// Server-side functions (FCALL)
+#[derive(Debug, Clone, Deserialize)]
+pub struct FunctionsConfig {
+    /// Plugin libraries loaded at startup to register functions
+    #[serde(default)]
+    pub plugins: Vec<String>,
+    /// Fuel (roughly, WASM instructions) a WASM function may use per call
+    #[serde(default = "default_wasm_fuel")]
+    pub wasm_fuel: u64,
+    /// Linear memory a WASM function may grow to, in megabytes
+    #[serde(default = "default_wasm_memory_mb")]
+    pub wasm_memory_mb: usize,
+}
+
+/// Scheduled commands (SCHEDULE)
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchedulerConfig {
+    /// Run the scheduled jobs; when off they are kept but not run
+    #[serde(default = "default_scheduler_enabled")]
+    pub enabled: bool,
+    /// Stream that receives a log entry for every run
+    #[serde(default = "default_scheduler_log_stream_key")]
+    pub log_stream_key: String,
+    /// Maximum log length (oldest entries are trimmed)
+    #[serde(default = "default_scheduler_log_max_len")]
+    pub log_max_len: usize,
+}
+
+/// Slow log configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlowLogConfig {
+    /// Log commands slower than this many microseconds; negative disables
+    #[serde(default = "default_slowlog_log_slower_than")]
+    pub log_slower_than: i64,
+    #[serde(default = "default_slowlog_max_len")]
+    pub max_len: usize,
+}
+
+/// TTL applied automatically to new keys matching a pattern
+///
+/// ```toml
+/// [[default_ttl]]
+/// pattern = "session:*"
+/// ttl_seconds = 3600
+/// type = "hash" # optional
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct DefaultTtlRule {
+    pub pattern: String,
+    pub ttl_seconds: u64,
+    /// Only apply to keys of this type (as reported by TYPE)
+    #[serde(default, rename = "type")]
+    pub key_type: Option<String>,
+}
+
+/// Pub/Sub channels whose last message is retained and delivered to new
+/// subscribers on SUBSCRIBE
+///
+/// ```toml
+/// [[retain]]
+/// pattern = "sensors:*"
+/// ttl_seconds = 300 # optional, retained forever when unset
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetainRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+}
+
+/// Pub/Sub channels whose messages are also appended to a stream key, so
+/// late subscribers can replay what they missed with XRANGE
+///
+/// ```toml
+/// [[pubsub_log]]
+/// pattern = "orders:*"
+/// max_len = 10000 # optional, defaults to memory.stream_max_len
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct PubSubLogRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub max_len: usize,
+}
+
+/// Lists matching a pattern that are kept at a maximum length by LPUSH and
+/// RPUSH, like a ring buffer
+///
+/// ```toml
+/// [[capped_list]]
+/// pattern = "log:*"
+/// max_len = 1000 # 0 disables the rule
+/// policy = "trim" # or "reject"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct CappedListRule {
+    pub pattern: String,
+    pub max_len: usize,
+    #[serde(default)]
+    pub policy: CapPolicy,
+}
+
+/// What a push into a full capped list does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapPolicy {
+    /// Drop elements from the end opposite the push
+    #[default]
+    Trim,
+    /// Refuse the push with an error
+    Reject,
 }
 
 // Default value functions
@@ -101,10 +436,42 @@ fn default_max_connections() -> usize {
     10000
 }
 
+fn default_proto_max_bulk_len() -> usize {
+    ParseLimits::default().max_bulk_len
+}
+
+fn default_max_multibulk_len() -> usize {
+    ParseLimits::default().max_multibulk_len
+}
+
+fn default_client_query_buffer_limit() -> usize {
+    1024 * 1024 * 1024
+}
+
+fn default_flush_confirmation_timeout() -> u64 {
+    60
+}
+
 fn default_timeout() -> u64 {
     0 // No timeout
 }
 
+fn default_instance_name() -> String {
+    "hexagondb".to_string()
+}
+
+fn default_tcp_keepalive_secs() -> u64 {
+    300
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_max_blocking_threads() -> usize {
+    512
+}
+
 fn default_aof_enabled() -> bool {
     true
 }
@@ -125,6 +492,22 @@ fn default_rdb_min_changes() -> u64 {
     1
 }
 
+fn default_aof_use_rdb_preamble() -> bool {
+    true
+}
+
+fn default_aof_segment_size() -> u64 {
+    crate::persistence::aof::DEFAULT_SEGMENT_SIZE
+}
+
+fn default_aof_max_segments() -> u64 {
+    8
+}
+
+fn default_rdb_compression_threshold() -> usize {
+    256
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -133,6 +516,70 @@ fn default_eviction_policy() -> String {
     "noeviction".to_string()
 }
 
+fn default_compaction_interval() -> u64 {
+    60
+}
+
+fn default_recycle_retention() -> u64 {
+    300 // 5 minutes
+}
+
+fn default_access_log_sample_rate() -> u64 {
+    100
+}
+
+fn default_access_log_stream_key() -> String {
+    "__accesslog__".to_string()
+}
+
+fn default_access_log_max_len() -> usize {
+    10000
+}
+
+fn default_pubsub_output_limit() -> OutputBufferLimitConfig {
+    OutputBufferLimitConfig {
+        hard_limit: 32 * 1024 * 1024,
+        soft_limit: 8 * 1024 * 1024,
+        soft_seconds: 60,
+    }
+}
+
+fn default_replica_output_limit() -> OutputBufferLimitConfig {
+    OutputBufferLimitConfig {
+        hard_limit: 256 * 1024 * 1024,
+        soft_limit: 64 * 1024 * 1024,
+        soft_seconds: 60,
+    }
+}
+
+fn default_slowlog_log_slower_than() -> i64 {
+    10000
+}
+
+fn default_slowlog_max_len() -> usize {
+    128
+}
+
+fn default_scheduler_enabled() -> bool {
+    true
+}
+
+fn default_scheduler_log_stream_key() -> String {
+    "__schedule_log__".to_string()
+}
+
+fn default_scheduler_log_max_len() -> usize {
+    1000
+}
+
+fn default_wasm_fuel() -> u64 {
+    100_000_000
+}
+
+fn default_wasm_memory_mb() -> usize {
+    64
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -141,6 +588,27 @@ impl Default for Config {
             logging: LoggingConfig::default(),
             memory: MemoryConfig::default(),
             security: SecurityConfig::default(),
+            recycle_bin: RecycleBinConfig::default(),
+            access_log: AccessLogConfig::default(),
+            slowlog: SlowLogConfig::default(),
+            replication: ReplicationConfig::default(),
+            client_output_buffer_limit: ClientOutputBufferLimitConfig::default(),
+            default_ttl: Vec::new(),
+            retain: Vec::new(),
+            pubsub_log: Vec::new(),
+            capped_list: Vec::new(),
+            functions: FunctionsConfig::default(),
+            scheduler: SchedulerConfig::default(),
+        }
+    }
+}
+
+impl Default for ClientOutputBufferLimitConfig {
+    fn default() -> Self {
+        ClientOutputBufferLimitConfig {
+            normal: OutputBufferLimitConfig::default(),
+            pubsub: default_pubsub_output_limit(),
+            replica: default_replica_output_limit(),
         }
     }
 }
@@ -152,7 +620,25 @@ impl Default for ServerConfig {
             port: default_port(),
             max_connections: default_max_connections(),
             timeout_seconds: default_timeout(),
+            client_write_timeout_secs: 0,
             tcp_keepalive: false,
+            tcp_keepalive_secs: default_tcp_keepalive_secs(),
+            tcp_nodelay: default_tcp_nodelay(),
+            reuse_port: false,
+            worker_threads: 0,
+            max_blocking_threads: default_max_blocking_threads(),
+            command_quantum: 0,
+            instance_name: default_instance_name(),
+            shard_id: 0,
+            latency_monitor_threshold_ms: 0,
+            serve_reads_while_loading: false,
+            read_only: false,
+            proxy_protocol: false,
+            proto_max_bulk_len: default_proto_max_bulk_len(),
+            max_multibulk_len: default_max_multibulk_len(),
+            client_query_buffer_limit: default_client_query_buffer_limit(),
+            flush_confirmation: false,
+            flush_confirmation_timeout_secs: default_flush_confirmation_timeout(),
         }
     }
 }
@@ -161,12 +647,19 @@ impl Default for PersistenceConfig {
     fn default() -> Self {
         PersistenceConfig {
             aof_enabled: default_aof_enabled(),
+            aof_use_rdb_preamble: default_aof_use_rdb_preamble(),
+            aof_segment_size: default_aof_segment_size(),
+            aof_max_segments: default_aof_max_segments(),
+            aof_archive_dir: None,
             aof_fsync: default_aof_fsync(),
             aof_path: None,
+            data_dir: None,
             rdb_enabled: default_rdb_enabled(),
             rdb_save_interval: default_rdb_save_interval(),
             rdb_min_changes: default_rdb_min_changes(),
+            save_points: Vec::new(),
             rdb_compression: false,
+            rdb_compression_threshold: default_rdb_compression_threshold(),
         }
     }
 }
@@ -186,6 +679,8 @@ impl Default for MemoryConfig {
         MemoryConfig {
             maxmemory: None,
             eviction_policy: default_eviction_policy(),
+            compaction_interval_secs: default_compaction_interval(),
+            stream_max_len: 0,
         }
     }
 }
@@ -197,6 +692,117 @@ impl Default for SecurityConfig {
             tls_enabled: false,
             tls_cert_file: None,
             tls_key_file: None,
+            ip_whitelist: Vec::new(),
+            ip_blacklist: Vec::new(),
+            max_commands_per_second: 0.0,
+            max_connections_per_ip: 0,
+        }
+    }
+}
+
+impl Default for RecycleBinConfig {
+    fn default() -> Self {
+        RecycleBinConfig {
+            enabled: false,
+            retention_seconds: default_recycle_retention(),
+            max_entries: 0,
+            max_memory: 0,
+        }
+    }
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        AccessLogConfig {
+            enabled: false,
+            sample_rate: default_access_log_sample_rate(),
+            stream_key: default_access_log_stream_key(),
+            max_len: default_access_log_max_len(),
+        }
+    }
+}
+
+impl DefaultTtlRule {
+    /// Check if the rule applies to a key of the given type
+    pub fn matches(&self, key: &str, key_type: &str) -> bool {
+        let type_ok = self
+            .key_type
+            .as_deref()
+            .map(|t| t.eq_ignore_ascii_case(key_type))
+            .unwrap_or(true);
+        type_ok && crate::db::ops::generic::glob_match(&self.pattern, key)
+    }
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            enabled: default_scheduler_enabled(),
+            log_stream_key: default_scheduler_log_stream_key(),
+            log_max_len: default_scheduler_log_max_len(),
+        }
+    }
+}
+
+impl Default for FunctionsConfig {
+    fn default() -> Self {
+        FunctionsConfig {
+            plugins: Vec::new(),
+            wasm_fuel: default_wasm_fuel(),
+            wasm_memory_mb: default_wasm_memory_mb(),
+        }
+    }
+}
+
+impl Default for SlowLogConfig {
+    fn default() -> Self {
+        SlowLogConfig {
+            log_slower_than: default_slowlog_log_slower_than(),
+            max_len: default_slowlog_max_len(),
+        }
+    }
+}
+
+impl SlowLogConfig {
+    /// Threshold in microseconds, or None when the slow log is disabled
+    pub fn threshold_us(&self) -> Option<u64> {
+        u64::try_from(self.log_slower_than).ok()
+    }
+}
+
+impl CappedListRule {
+    /// Check if the rule applies to a key
+    pub fn matches(&self, key: &str) -> bool {
+        crate::db::ops::generic::glob_match(&self.pattern, key)
+    }
+}
+
+impl RetainRule {
+    /// Check if the rule applies to a channel
+    pub fn matches(&self, channel: &str) -> bool {
+        crate::db::ops::generic::glob_match(&self.pattern, channel)
+    }
+
+    /// How long a retained message on a matching channel is kept
+    pub fn ttl(&self) -> Option<std::time::Duration> {
+        self.ttl_seconds.map(std::time::Duration::from_secs)
+    }
+}
+
+impl PubSubLogRule {
+    /// Check if the rule applies to a channel
+    pub fn matches(&self, channel: &str) -> bool {
+        crate::db::ops::generic::glob_match(&self.pattern, channel)
+    }
+}
+
+impl RecycleBinConfig {
+    /// Retention window to apply to the database, or None when disabled
+    pub fn retention(&self) -> Option<std::time::Duration> {
+        if self.enabled {
+            Some(std::time::Duration::from_secs(self.retention_seconds))
+        } else {
+            None
         }
     }
 }
@@ -211,9 +817,44 @@ impl Config {
             .map_err(|e| ConfigError::ParseError(e.to_string()))
     }
 
-    /// Get the server address as a string
-    pub fn server_address(&self) -> String {
-        format!("{}:{}", self.server.bind_address, self.server.port)
+    /// Addresses to listen on as `host:port`, IPv6 hosts in brackets
+    pub fn server_addresses(&self) -> Vec<String> {
+        self.server
+            .bind_address
+            .split_whitespace()
+            .map(|host| {
+                if host.contains(':') && !host.starts_with('[') {
+                    format!("[{}]:{}", host, self.server.port)
+                } else {
+                    format!("{}:{}", host, self.server.port)
+                }
+            })
+            .collect()
+    }
+
+    /// Time a client has to read a reply, None when unlimited
+    pub fn client_write_timeout(&self) -> Option<std::time::Duration> {
+        (self.server.client_write_timeout_secs > 0)
+            .then(|| std::time::Duration::from_secs(self.server.client_write_timeout_secs))
+    }
+
+    /// Request parser limits from the `[server]` section
+    pub fn parse_limits(&self) -> ParseLimits {
+        ParseLimits {
+            max_bulk_len: self.server.proto_max_bulk_len,
+            max_multibulk_len: self.server.max_multibulk_len,
+            ..ParseLimits::default()
+        }
+    }
+
+    /// Output buffer limits from the `[client_output_buffer_limit]` section
+    pub fn output_limits(&self) -> OutputLimits {
+        let limits = &self.client_output_buffer_limit;
+        OutputLimits {
+            normal: (&limits.normal).into(),
+            pubsub: (&limits.pubsub).into(),
+            replica: (&limits.replica).into(),
+        }
     }
 }
 
@@ -234,3 +875,123 @@ impl std::fmt::Display for ConfigError {
 }
 
 impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_ttl_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[default_ttl]]
+            pattern = "session:*"
+            ttl_seconds = 3600
+
+            [[default_ttl]]
+            pattern = "cache:*"
+            ttl_seconds = 60
+            type = "string"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.default_ttl.len(), 2);
+        assert!(config.default_ttl[0].matches("session:42", "hash"));
+        assert!(!config.default_ttl[0].matches("user:42", "hash"));
+        assert!(config.default_ttl[1].matches("cache:a", "string"));
+        assert!(!config.default_ttl[1].matches("cache:a", "list"));
+    }
+
+    #[test]
+    fn test_retain_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[retain]]
+            pattern = "sensors:*"
+            ttl_seconds = 300
+
+            [[retain]]
+            pattern = "status"
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.retain[0].matches("sensors:temp"));
+        assert_eq!(config.retain[0].ttl(), Some(std::time::Duration::from_secs(300)));
+        assert!(!config.retain[1].matches("sensors:temp"));
+        assert_eq!(config.retain[1].ttl(), None);
+    }
+
+    #[test]
+    fn test_pubsub_log_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[pubsub_log]]
+            pattern = "orders:*"
+            max_len = 100
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.pubsub_log[0].matches("orders:eu"));
+        assert!(!config.pubsub_log[0].matches("chat"));
+        assert_eq!(config.pubsub_log[0].max_len, 100);
+        assert!(Config::default().pubsub_log.is_empty());
+    }
+
+    #[test]
+    fn test_capped_list_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            [[capped_list]]
+            pattern = "log:*"
+            max_len = 1000
+
+            [[capped_list]]
+            pattern = "jobs"
+            max_len = 10
+            policy = "reject"
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.capped_list[0].matches("log:api"));
+        assert_eq!(config.capped_list[0].policy, CapPolicy::Trim);
+        assert!(!config.capped_list[1].matches("jobs:1"));
+        assert_eq!(config.capped_list[1].policy, CapPolicy::Reject);
+    }
+
+    #[test]
+    fn test_instance_identity() {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            instance_name = "cache-eu-1"
+            shard_id = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.instance_name, "cache-eu-1");
+        assert_eq!(config.server.shard_id, 3);
+        assert_eq!(Config::default().server.instance_name, "hexagondb");
+    }
+
+    #[test]
+    fn test_server_addresses() {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            bind_address = "0.0.0.0  ::1 [::]"
+            port = 7000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.server_addresses(),
+            vec!["0.0.0.0:7000", "[::1]:7000", "[::]:7000"]
+        );
+        assert!(config.server.tcp_nodelay);
+        assert_eq!(config.server.worker_threads, 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Memory compaction for fragmented collections.
+//!
+//! Vectors and hash tables never give memory back when elements are removed,
+//! so a hash, list, set or sorted set that once held a million entries keeps
+//! that allocation after being trimmed to ten. Compaction rebuilds such
+//! values into freshly allocated structures sized to their contents. It runs
+//! from a background task for values whose capacity far exceeds their
+//! length, and on demand through `MEMORY COMPACT key`.
+
+use crate::db::core::DB;
+use crate::db::types::{DataType, Entry};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A collection is fragmented when its capacity exceeds this many times its length
+pub const FRAGMENTATION_RATIO: usize = 4;
+/// ... and at least this many slots are unused (small values are not worth it)
+pub const MIN_SLACK: usize = 64;
+
+fn fragmented(capacity: usize, len: usize) -> bool {
+    capacity.saturating_sub(len) >= MIN_SLACK && capacity > len.saturating_mul(FRAGMENTATION_RATIO)
+}
+
+/// Whether an entry holds a collection worth compacting
+pub fn is_fragmented(entry: &Entry) -> bool {
+    let value = match &entry.value {
+        DataType::List(list) => fragmented(list.capacity(), list.len()),
+        DataType::Hash(hash) => fragmented(hash.capacity(), hash.len()),
+        DataType::Set(set) => fragmented(set.capacity(), set.len()),
+        DataType::ZSet(zset) => {
+            fragmented(zset.members.capacity(), zset.members.len())
+                || fragmented(zset.scores.capacity(), zset.scores.len())
+        }
+        _ => false,
+    };
+    value
+        || entry
+            .field_expires
+            .as_ref()
+            .is_some_and(|expires| fragmented(expires.capacity(), expires.len()))
+}
+
+fn rebuild_map<K: Eq + Hash, V>(map: &mut HashMap<K, V>) -> usize {
+    let before = map.capacity();
+    *map = std::mem::take(map).into_iter().collect();
+    before.saturating_sub(map.capacity())
+}
+
+/// Rebuild the collections of an entry. Returns the number of unused slots
+/// released.
+pub fn compact_entry(entry: &mut Entry) -> usize {
+    let mut freed = match &mut entry.value {
+        DataType::List(list) => {
+            let before = list.capacity();
+            list.shrink_to_fit();
+            before - list.capacity()
+        }
+        DataType::Hash(hash) => rebuild_map(hash),
+        DataType::Set(set) => {
+            let before = set.capacity();
+            *set = std::mem::take(set).into_iter().collect();
+            before.saturating_sub(set.capacity())
+        }
+        DataType::ZSet(zset) => rebuild_map(&mut zset.members) + zset.scores.compact(),
+        _ => 0,
+    };
+    if let Some(expires) = entry.field_expires.as_mut() {
+        freed += rebuild_map(expires);
+    }
+    freed
+}
+
+impl DB {
+    /// Compact a single key, fragmented or not. Returns None if the key does
+    /// not exist, otherwise the number of unused slots released.
+    pub fn compact_key(&mut self, key: &str) -> Option<usize> {
+        self.items.get_mut(key).map(compact_entry)
+    }
+
+    /// Keys whose collections are fragmented
+    pub fn fragmented_keys(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .filter(|(_, entry)| is_fragmented(entry))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Compact the given keys if they are still fragmented. Returns how many
+    /// keys were compacted and the unused slots released.
+    pub fn compact_fragmented(&mut self, keys: &[String]) -> (usize, usize) {
+        let mut compacted = 0;
+        let mut freed = 0;
+        for key in keys {
+            if let Some(entry) = self.items.get_mut(key) {
+                if is_fragmented(entry) {
+                    compacted += 1;
+                    freed += compact_entry(entry);
+                }
+            }
+        }
+        (compacted, freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{DataType, HashOps, ListOps, DB};
+
+    #[test]
+    fn test_compaction() {
+        let mut db = DB::new();
+        let items: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        db.rpush("list".to_string(), items.clone()).unwrap();
+        for item in &items {
+            db.hset("hash".to_string(), item.clone(), "v".to_string()).unwrap();
+        }
+        db.rpush("small".to_string(), vec!["a".to_string()]).unwrap();
+        assert!(db.fragmented_keys().is_empty());
+
+        if let DataType::List(list) = &mut db.items.get_mut("list").unwrap().value {
+            list.truncate(5);
+        }
+        db.hdel_multi("hash".to_string(), items[5..].to_vec()).unwrap();
+        let mut fragmented = db.fragmented_keys();
+        fragmented.sort();
+        assert_eq!(fragmented, vec!["hash", "list"]);
+
+        let (compacted, freed) = db.compact_fragmented(&fragmented);
+        assert_eq!(compacted, 2);
+        assert!(freed > 1000);
+        assert!(db.fragmented_keys().is_empty());
+        match &db.items["list"].value {
+            DataType::List(list) => assert_eq!(list.capacity(), 5),
+            _ => unreachable!(),
+        }
+        assert_eq!(db.hlen("hash"), 5);
+        assert_eq!(db.compact_key("missing"), None);
+    }
+}
@@ -2,34 +2,81 @@
 //!
 //! The heart of HexagonDB - an in-memory HashMap storing all data.
 
-use crate::db::types::Entry;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::db::error::{DbError, DbResult};
+use crate::db::hooks::KeyHooks;
+use crate::db::introspect::DEFAULT_PACKED_THRESHOLD;
+use crate::db::keyspace::Keyspace;
+use crate::db::ops::generic::GenericOps;
+use crate::db::recycle::RecycleBin;
+use crate::db::types::{DataType, Entry};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// The core database structure.
-/// All data is stored in memory in this HashMap.
+/// All data is stored in memory in this map.
 pub struct DB {
     /// Main data store
-    pub items: HashMap<String, Entry>,
+    pub items: Keyspace,
     /// Changes since last save (for persistence triggers)
     pub(crate) changes_since_save: Arc<AtomicUsize>,
+    /// Soft-deleted keys (disabled unless a retention window is set)
+    pub recycle_bin: RecycleBin,
+    /// Hashes that have (or had) per-field TTLs, swept by active expiry
+    pub(crate) hash_field_ttl_keys: HashSet<String>,
+    /// Time series that have (or had) compaction rules, visited by downsampling
+    pub(crate) timeseries_rule_keys: HashSet<String>,
+    /// Keys removed because their TTL ran out
+    pub(crate) expired_keys: AtomicU64,
+    /// Callbacks registered by embedders
+    pub(crate) hooks: KeyHooks,
+    /// Whether the background expiry pass runs (DEBUG SET-ACTIVE-EXPIRE)
+    pub(crate) active_expire: bool,
+    /// List elements this large count as plain nodes (DEBUG QUICKLIST-PACKED-THRESHOLD)
+    pub(crate) packed_threshold: usize,
 }
 
 impl DB {
     /// Create a new empty database
     pub fn new() -> Self {
         DB {
-            items: HashMap::new(),
+            items: Keyspace::new(),
             changes_since_save: Arc::new(AtomicUsize::new(0)),
+            recycle_bin: RecycleBin::new(),
+            hash_field_ttl_keys: HashSet::new(),
+            timeseries_rule_keys: HashSet::new(),
+            expired_keys: AtomicU64::new(0),
+            hooks: KeyHooks::new(),
+            active_expire: true,
+            packed_threshold: DEFAULT_PACKED_THRESHOLD,
         }
     }
 
     /// Create a database with initial capacity
     pub fn with_capacity(capacity: usize) -> Self {
         DB {
-            items: HashMap::with_capacity(capacity),
+            items: Keyspace::with_capacity(capacity),
             changes_since_save: Arc::new(AtomicUsize::new(0)),
+            recycle_bin: RecycleBin::new(),
+            hash_field_ttl_keys: HashSet::new(),
+            timeseries_rule_keys: HashSet::new(),
+            expired_keys: AtomicU64::new(0),
+            hooks: KeyHooks::new(),
+            active_expire: true,
+            packed_threshold: DEFAULT_PACKED_THRESHOLD,
+        }
+    }
+
+    /// Add `key` to the sets of hashes with field TTLs and series with
+    /// compaction rules if its entry belongs there. Call it after an entry
+    /// is moved in whole (RENAME, COPY, RESTORE, UNDELETE, loading).
+    pub(crate) fn track_entry(&mut self, key: &str) {
+        let Some(entry) = self.items.get(key) else { return };
+        if entry.field_expires.is_some() {
+            self.hash_field_ttl_keys.insert(key.to_string());
+        }
+        if matches!(&entry.value, DataType::TimeSeries(series) if !series.rules.is_empty()) {
+            self.timeseries_rule_keys.insert(key.to_string());
         }
     }
 
@@ -43,11 +90,126 @@ impl DB {
         self.changes_since_save.store(0, Ordering::Relaxed);
     }
 
+    /// Take `count` changes off the counter, keeping those made since they
+    /// were read (after a save that did not block writes)
+    pub fn discount_changes(&self, count: usize) {
+        let _ = self
+            .changes_since_save
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| {
+                Some(c.saturating_sub(count))
+            });
+    }
+
     /// Get the number of changes since last save
     pub fn get_changes(&self) -> usize {
         self.changes_since_save.load(Ordering::Relaxed)
     }
 
+    /// Number of keys removed because their TTL ran out
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys.load(Ordering::Relaxed)
+    }
+
+    /// Number of keys with a TTL
+    pub fn keys_with_expiry(&self) -> usize {
+        self.items.values().filter(|e| e.expires_at.is_some()).count()
+    }
+
+    /// Enable or disable the background expiry pass. Lazy expiry on access
+    /// still applies.
+    pub fn set_active_expire(&mut self, enabled: bool) {
+        self.active_expire = enabled;
+    }
+
+    /// Set the size at which list elements count as plain nodes
+    pub fn set_packed_threshold(&mut self, bytes: usize) {
+        self.packed_threshold = bytes;
+    }
+
+    /// Size at which list elements count as plain nodes
+    pub fn packed_threshold(&self) -> usize {
+        self.packed_threshold
+    }
+
+    /// Entry of `key` for a write of one type: created from `init` when the
+    /// key is missing or expired, `WrongType` when `is_type` rejects its value
+    pub(crate) fn typed_entry_mut(
+        &mut self,
+        key: &str,
+        is_type: fn(&DataType) -> bool,
+        init: impl FnOnce() -> DataType,
+    ) -> DbResult<&mut Entry> {
+        self.check_expiration(key);
+        if self.items.get(key).is_some_and(|entry| !is_type(&entry.value)) {
+            return Err(DbError::WrongType);
+        }
+        Ok(self.items.get_or_insert_with(key.to_string(), || Entry {
+            value: init(),
+            expires_at: None,
+            field_expires: None,
+        }))
+    }
+
+    /// Value of `key` for a read of one type: None when the key is missing
+    /// or expired, `WrongType` when `is_type` rejects it
+    pub(crate) fn typed_value(
+        &mut self,
+        key: &str,
+        is_type: fn(&DataType) -> bool,
+    ) -> DbResult<Option<&DataType>> {
+        if !self.check_expiration(key) {
+            return Ok(None);
+        }
+        match self.items.get(key) {
+            Some(entry) if !is_type(&entry.value) => Err(DbError::WrongType),
+            entry => Ok(entry.map(|entry| &entry.value)),
+        }
+    }
+
+    /// Register a callback run after a key is written, with its new entry
+    pub fn on_set<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &Entry) + Send + Sync + 'static,
+    {
+        self.hooks.add_on_set(Box::new(hook));
+    }
+
+    /// Register a callback run after a key is deleted, with its old entry
+    pub fn on_delete<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &Entry) + Send + Sync + 'static,
+    {
+        self.hooks.add_on_delete(Box::new(hook));
+    }
+
+    /// Register a callback run after a key expires, with its old entry
+    pub fn on_expire<F>(&mut self, hook: F)
+    where
+        F: Fn(&str, &Entry) + Send + Sync + 'static,
+    {
+        self.hooks.add_on_expire(Box::new(hook));
+    }
+
+    /// Run the on_set hooks for a key that was just written
+    pub(crate) fn notify_set(&self, key: &str) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        if let Some(entry) = self.items.get(key) {
+            self.hooks.set(key, entry);
+        }
+    }
+
+    /// Run the on_delete hooks for a removed entry
+    pub(crate) fn notify_delete(&self, key: &str, entry: &Entry) {
+        self.hooks.delete(key, entry);
+    }
+
+    /// Run the on_expire hooks for an expired entry
+    pub(crate) fn notify_expire(&self, key: &str, entry: &Entry) {
+        self.hooks.expire(key, entry);
+    }
+
     /// Get a clone of the changes counter (for background tasks)
     pub fn get_changes_counter(&self) -> Arc<AtomicUsize> {
         Arc::clone(&self.changes_since_save)
@@ -0,0 +1,86 @@
+//! Errors returned by database operations.
+//!
+//! Each variant maps to the RESP error a client sees, so the interpreter can
+//! forward it unchanged while embedders can match on the kind.
+
+use crate::network::resp::RespValue;
+use std::fmt;
+
+/// Result of a database operation
+pub type DbResult<T> = Result<T, DbError>;
+
+/// Database operation error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbError {
+    /// The key holds a value of another type
+    WrongType,
+    /// The key does not exist
+    NoSuchKey,
+    /// An index is outside the value
+    OutOfRange,
+    /// Malformed arguments
+    Syntax(String),
+    /// A stored or given value cannot be used, e.g. not a number or an overflow
+    InvalidValue(String),
+    /// Unexpected failure inside the database
+    Internal(String),
+}
+
+impl DbError {
+    /// RESP error code (the first word of the error reply)
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbError::WrongType => "WRONGTYPE",
+            _ => "ERR",
+        }
+    }
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::WrongType => write!(f, "WRONGTYPE Operation against a key holding the wrong kind of value"),
+            DbError::NoSuchKey => write!(f, "ERR no such key"),
+            DbError::OutOfRange => write!(f, "ERR index out of range"),
+            DbError::Syntax(msg) | DbError::InvalidValue(msg) | DbError::Internal(msg) => {
+                write!(f, "{} {}", self.code(), msg)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DbError {}
+
+impl From<DbError> for String {
+    fn from(e: DbError) -> Self {
+        e.to_string()
+    }
+}
+
+impl From<DbError> for RespValue {
+    fn from(e: DbError) -> Self {
+        RespValue::Error(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{ListOps, StringOps, DB};
+
+    #[test]
+    fn test_db_error_kinds() {
+        let mut db = DB::new();
+        db.rpush("list".to_string(), vec!["a".to_string()]).unwrap();
+        db.set("text".to_string(), "abc".to_string());
+
+        assert_eq!(db.get("list"), Err(DbError::WrongType));
+        assert!(matches!(db.incr("text"), Err(DbError::InvalidValue(_))));
+        assert_eq!(db.lset("nope".to_string(), 0, "x".to_string()), Err(DbError::NoSuchKey));
+        assert_eq!(db.lset("list".to_string(), 5, "x".to_string()), Err(DbError::OutOfRange));
+
+        let err = db.incr("text").unwrap_err();
+        assert_eq!(err.code(), "ERR");
+        assert_eq!(RespValue::from(err), RespValue::Error("ERR value is not an integer or out of range".to_string()));
+    }
+}
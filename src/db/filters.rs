@@ -0,0 +1,396 @@
+//! Bloom and cuckoo filters.
+//!
+//! Both answer "was this item added?" with no false negatives and a small
+//! rate of false positives, in a fraction of the memory a set would take.
+//! A bloom filter cannot forget an item; a cuckoo filter stores a 16-bit
+//! fingerprint per item, so items can also be counted and deleted.
+//!
+//! Each filter is a chain of sub-filters sized for a capacity. When the
+//! newest one is full, a larger one is added (`expansion` times the size of
+//! the last), unless the expansion is 0, in which case adds fail instead.
+//! Hashing and eviction are deterministic, so replaying the same adds
+//! rebuilds the same filter.
+
+use siphasher::sip::SipHasher;
+use std::f64::consts::LN_2;
+use std::hash::Hasher;
+
+pub const BLOOM_DEFAULT_ERROR_RATE: f64 = 0.01;
+pub const BLOOM_DEFAULT_CAPACITY: u64 = 100;
+pub const BLOOM_DEFAULT_EXPANSION: u32 = 2;
+/// Error rate of each bloom sub-filter relative to the one before, so the
+/// chain as a whole stays under the filter's error rate
+const BLOOM_TIGHTENING: f64 = 0.5;
+
+pub const CUCKOO_DEFAULT_CAPACITY: u64 = 1024;
+pub const CUCKOO_DEFAULT_BUCKET_SIZE: usize = 2;
+pub const CUCKOO_DEFAULT_MAX_ITERATIONS: u32 = 20;
+pub const CUCKOO_DEFAULT_EXPANSION: u32 = 1;
+
+/// An add to a full filter that cannot grow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterFull;
+
+/// Two independent 64-bit hashes of an item
+fn hash_pair(item: &str) -> (u64, u64) {
+    let hash = |k0, k1| {
+        let mut hasher = SipHasher::new_with_keys(k0, k1);
+        hasher.write(item.as_bytes());
+        hasher.finish()
+    };
+    (hash(0x6865_7861, 0x676f_6e01), hash(0x6865_7861, 0x676f_6e02))
+}
+
+/// One fixed-size bloom filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomLayer {
+    pub bits: Vec<u8>,
+    pub hashes: u32,
+    pub capacity: u64,
+    /// Items added to this layer
+    pub items: u64,
+}
+
+impl BloomLayer {
+    fn new(capacity: u64, error_rate: f64) -> Self {
+        let bits = (-(capacity as f64) * error_rate.ln() / (LN_2 * LN_2)).ceil().max(64.0) as usize;
+        BloomLayer {
+            bits: vec![0; bits.div_ceil(8)],
+            hashes: (-error_rate.log2()).ceil().max(1.0) as u32,
+            capacity,
+            items: 0,
+        }
+    }
+
+    fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = usize> {
+        let nbits = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % nbits) as usize)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn insert(&mut self, hash: (u64, u64)) {
+        let positions: Vec<usize> = self.positions(hash).collect();
+        for bit in positions {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+        self.items += 1;
+    }
+}
+
+/// Scalable bloom filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct BloomFilter {
+    pub error_rate: f64,
+    /// Growth factor of new sub-filters, 0 if the filter does not scale
+    pub expansion: u32,
+    pub layers: Vec<BloomLayer>,
+}
+
+impl BloomFilter {
+    pub fn new(error_rate: f64, capacity: u64, expansion: u32) -> Self {
+        BloomFilter {
+            error_rate,
+            expansion,
+            layers: vec![BloomLayer::new(capacity, error_rate * BLOOM_TIGHTENING)],
+        }
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        let hash = hash_pair(item);
+        self.layers.iter().any(|layer| layer.contains(hash))
+    }
+
+    /// Add an item. Returns false if it may have been added already.
+    pub fn add(&mut self, item: &str) -> Result<bool, FilterFull> {
+        let hash = hash_pair(item);
+        if self.layers.iter().any(|layer| layer.contains(hash)) {
+            return Ok(false);
+        }
+        let last = self.layers.last().expect("a bloom filter has a layer");
+        if last.items >= last.capacity {
+            if self.expansion == 0 {
+                return Err(FilterFull);
+            }
+            let capacity = last.capacity.saturating_mul(self.expansion as u64);
+            let error_rate = self.error_rate * BLOOM_TIGHTENING.powi(self.layers.len() as i32 + 1);
+            self.layers.push(BloomLayer::new(capacity, error_rate));
+        }
+        self.layers.last_mut().expect("a bloom filter has a layer").insert(hash);
+        Ok(true)
+    }
+
+    /// Items the filter holds before it grows or fills up
+    pub fn capacity(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.capacity).sum()
+    }
+
+    /// Items added
+    pub fn items(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.items).sum()
+    }
+
+    /// Bytes of bit arrays
+    pub fn size(&self) -> usize {
+        self.layers.iter().map(|layer| layer.bits.len()).sum()
+    }
+}
+
+/// One fixed-size cuckoo filter. Slots hold fingerprints, 0 when empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuckooLayer {
+    pub slots: Vec<u16>,
+}
+
+impl CuckooLayer {
+    /// A layer of `buckets` buckets, rounded up to a power of two so an
+    /// item's two buckets can be derived from each other
+    fn new(buckets: u64, bucket_size: usize) -> Self {
+        CuckooLayer {
+            slots: vec![0; buckets.max(1).next_power_of_two() as usize * bucket_size],
+        }
+    }
+
+    fn buckets(&self, bucket_size: usize) -> u64 {
+        (self.slots.len() / bucket_size) as u64
+    }
+
+    /// First bucket of an item
+    fn index(&self, h1: u64, bucket_size: usize) -> u64 {
+        h1 & (self.buckets(bucket_size) - 1)
+    }
+
+    /// The other bucket a fingerprint may sit in
+    fn alt_index(&self, index: u64, fp: u16, bucket_size: usize) -> u64 {
+        (index ^ (fp as u64).wrapping_mul(0x5bd1_e995)) & (self.buckets(bucket_size) - 1)
+    }
+
+    fn bucket(&self, index: u64, bucket_size: usize) -> &[u16] {
+        let start = index as usize * bucket_size;
+        &self.slots[start..start + bucket_size]
+    }
+
+    fn bucket_mut(&mut self, index: u64, bucket_size: usize) -> &mut [u16] {
+        let start = index as usize * bucket_size;
+        &mut self.slots[start..start + bucket_size]
+    }
+
+    /// Count the fingerprint in both of its buckets
+    fn count(&self, h1: u64, fp: u16, bucket_size: usize) -> usize {
+        let i1 = self.index(h1, bucket_size);
+        let i2 = self.alt_index(i1, fp, bucket_size);
+        let mut count = self.bucket(i1, bucket_size).iter().filter(|&&slot| slot == fp).count();
+        if i2 != i1 {
+            count += self.bucket(i2, bucket_size).iter().filter(|&&slot| slot == fp).count();
+        }
+        count
+    }
+
+    fn remove(&mut self, h1: u64, fp: u16, bucket_size: usize) -> bool {
+        let i1 = self.index(h1, bucket_size);
+        let i2 = self.alt_index(i1, fp, bucket_size);
+        for index in [i1, i2] {
+            if let Some(slot) = self.bucket_mut(index, bucket_size).iter_mut().find(|slot| **slot == fp) {
+                *slot = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn insert_free(&mut self, index: u64, fp: u16, bucket_size: usize) -> bool {
+        match self.bucket_mut(index, bucket_size).iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fp;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Insert, evicting fingerprints to their other bucket when both of
+    /// this one's are full. After `max_iterations` evictions the moves are
+    /// undone and false is returned.
+    fn insert(&mut self, h1: u64, fp: u16, bucket_size: usize, max_iterations: u32) -> bool {
+        let i1 = self.index(h1, bucket_size);
+        let i2 = self.alt_index(i1, fp, bucket_size);
+        if self.insert_free(i1, fp, bucket_size) || self.insert_free(i2, fp, bucket_size) {
+            return true;
+        }
+        let mut path = Vec::new();
+        let (mut index, mut fp) = (i2, fp);
+        for n in 0..max_iterations as usize {
+            let slot = index as usize * bucket_size + n % bucket_size;
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+            path.push(slot);
+            index = self.alt_index(index, fp, bucket_size);
+            if self.insert_free(index, fp, bucket_size) {
+                return true;
+            }
+        }
+        for slot in path.into_iter().rev() {
+            std::mem::swap(&mut fp, &mut self.slots[slot]);
+        }
+        false
+    }
+}
+
+/// Scalable cuckoo filter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuckooFilter {
+    pub bucket_size: usize,
+    pub max_iterations: u32,
+    /// Growth factor of new sub-filters, 0 if the filter does not scale
+    pub expansion: u32,
+    pub layers: Vec<CuckooLayer>,
+    pub items: u64,
+    pub deleted: u64,
+}
+
+impl CuckooFilter {
+    pub fn new(capacity: u64, bucket_size: usize, max_iterations: u32, expansion: u32) -> Self {
+        CuckooFilter {
+            bucket_size,
+            max_iterations,
+            expansion,
+            layers: vec![CuckooLayer::new(capacity.div_ceil(bucket_size as u64), bucket_size)],
+            items: 0,
+            deleted: 0,
+        }
+    }
+
+    /// First-bucket hash and non-zero fingerprint of an item
+    fn fingerprint(item: &str) -> (u64, u16) {
+        let (h1, h2) = hash_pair(item);
+        (h1, (h2 % u16::MAX as u64) as u16 + 1)
+    }
+
+    /// Add an item, even if it is already in the filter
+    pub fn add(&mut self, item: &str) -> Result<(), FilterFull> {
+        let (h1, fp) = Self::fingerprint(item);
+        let bucket_size = self.bucket_size;
+        let placed = self.layers.iter_mut().any(|layer| {
+            let i1 = layer.index(h1, bucket_size);
+            let i2 = layer.alt_index(i1, fp, bucket_size);
+            layer.insert_free(i1, fp, bucket_size) || layer.insert_free(i2, fp, bucket_size)
+        });
+        let last = self.layers.last_mut().expect("a cuckoo filter has a layer");
+        if !placed && !last.insert(h1, fp, bucket_size, self.max_iterations) {
+            if self.expansion == 0 {
+                return Err(FilterFull);
+            }
+            let buckets = last.buckets(bucket_size).saturating_mul(self.expansion as u64);
+            let mut layer = CuckooLayer::new(buckets, bucket_size);
+            layer.insert(h1, fp, bucket_size, self.max_iterations);
+            self.layers.push(layer);
+        }
+        self.items += 1;
+        Ok(())
+    }
+
+    /// Add an item unless it may be in the filter already. Returns whether
+    /// it was added.
+    pub fn add_nx(&mut self, item: &str) -> Result<bool, FilterFull> {
+        if self.contains(item) {
+            return Ok(false);
+        }
+        self.add(item).map(|_| true)
+    }
+
+    pub fn contains(&self, item: &str) -> bool {
+        self.count(item) > 0
+    }
+
+    /// Times the item may have been added, and not deleted
+    pub fn count(&self, item: &str) -> usize {
+        let (h1, fp) = Self::fingerprint(item);
+        let bucket_size = self.bucket_size;
+        self.layers.iter().map(|layer| layer.count(h1, fp, bucket_size)).sum()
+    }
+
+    /// Delete one copy of an item. Deleting an item that was never added
+    /// can remove another item with the same fingerprint.
+    pub fn delete(&mut self, item: &str) -> bool {
+        let (h1, fp) = Self::fingerprint(item);
+        let bucket_size = self.bucket_size;
+        let removed = self.layers.iter_mut().rev().any(|layer| layer.remove(h1, fp, bucket_size));
+        if removed {
+            self.items -= 1;
+            self.deleted += 1;
+        }
+        removed
+    }
+
+    /// Buckets across all sub-filters
+    pub fn buckets(&self) -> u64 {
+        self.layers.iter().map(|layer| layer.buckets(self.bucket_size)).sum()
+    }
+
+    /// Bytes of fingerprint slots
+    pub fn size(&self) -> usize {
+        self.layers.iter().map(|layer| layer.slots.len() * 2).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_scales_and_keeps_error_rate() {
+        let mut bloom = BloomFilter::new(0.01, 100, BLOOM_DEFAULT_EXPANSION);
+        // A new item can be a false positive, so not every add is counted
+        let added = (0..1000).filter(|i| bloom.add(&format!("item:{}", i)) == Ok(true)).count();
+        assert!(added > 980, "{} added", added);
+        assert_eq!(bloom.add("item:7"), Ok(false));
+        assert!((0..1000).all(|i| bloom.contains(&format!("item:{}", i))));
+        assert!(bloom.layers.len() > 1);
+        assert_eq!(bloom.items(), added as u64);
+
+        // 1% of 10000, with room for sampling noise
+        let false_positives = (0..10_000).filter(|i| bloom.contains(&format!("other:{}", i))).count();
+        assert!(false_positives < 150, "{} false positives", false_positives);
+
+        let mut fixed = BloomFilter::new(0.01, 10, 0);
+        for i in 0..10 {
+            fixed.add(&i.to_string()).unwrap();
+        }
+        assert_eq!(fixed.add("10"), Err(FilterFull));
+        assert_eq!(fixed.add("3"), Ok(false));
+    }
+
+    #[test]
+    fn test_cuckoo_add_count_delete() {
+        let mut cuckoo = CuckooFilter::new(64, 2, CUCKOO_DEFAULT_MAX_ITERATIONS, 1);
+        for i in 0..500 {
+            cuckoo.add(&format!("item:{}", i)).unwrap();
+        }
+        assert!(cuckoo.layers.len() > 1);
+        assert!((0..500).all(|i| cuckoo.contains(&format!("item:{}", i))));
+
+        cuckoo.add("item:1").unwrap();
+        assert_eq!(cuckoo.count("item:1"), 2);
+        assert_eq!(cuckoo.add_nx("item:1"), Ok(false));
+        assert!(cuckoo.delete("item:1"));
+        assert!(cuckoo.delete("item:1"));
+        assert!(!cuckoo.contains("item:1"));
+        assert_eq!((cuckoo.items, cuckoo.deleted), (499, 2));
+        assert!((2..500).all(|i| cuckoo.contains(&format!("item:{}", i))));
+
+        // Replaying the same adds builds the same filter
+        let mut replayed = CuckooFilter::new(64, 2, CUCKOO_DEFAULT_MAX_ITERATIONS, 1);
+        let mut again = CuckooFilter::new(64, 2, CUCKOO_DEFAULT_MAX_ITERATIONS, 1);
+        for i in 0..300 {
+            replayed.add(&i.to_string()).unwrap();
+            again.add(&i.to_string()).unwrap();
+        }
+        assert_eq!(replayed, again);
+
+        let mut fixed = CuckooFilter::new(64, 2, 5, 0);
+        let added = (0..100).take_while(|i| fixed.add(&i.to_string()).is_ok()).count();
+        assert!((32..=64).contains(&added), "{} added", added);
+        assert!((0..added).all(|i| fixed.contains(&i.to_string())));
+    }
+}
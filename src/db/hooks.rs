@@ -0,0 +1,109 @@
+//! Per-key write hooks for embedders.
+//!
+//! Host applications that use `DB` as a library can register callbacks that
+//! run synchronously, under the caller's lock, whenever a key is written,
+//! deleted or expires. Hooks receive the key and the entry (the new value for
+//! `on_set`, the removed value for `on_delete` and `on_expire`), so derived
+//! state can be kept in sync without polling.
+//!
+//! Hooks must be cheap and must not call back into the same `DB`.
+
+use crate::db::types::Entry;
+
+/// Callback invoked with a key and its entry
+pub type EntryHook = Box<dyn Fn(&str, &Entry) + Send + Sync>;
+
+/// Registered key hooks
+#[derive(Default)]
+pub struct KeyHooks {
+    on_set: Vec<EntryHook>,
+    on_delete: Vec<EntryHook>,
+    on_expire: Vec<EntryHook>,
+}
+
+impl KeyHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether any hook is registered
+    pub fn is_empty(&self) -> bool {
+        self.on_set.is_empty() && self.on_delete.is_empty() && self.on_expire.is_empty()
+    }
+
+    pub(crate) fn add_on_set(&mut self, hook: EntryHook) {
+        self.on_set.push(hook);
+    }
+
+    pub(crate) fn add_on_delete(&mut self, hook: EntryHook) {
+        self.on_delete.push(hook);
+    }
+
+    pub(crate) fn add_on_expire(&mut self, hook: EntryHook) {
+        self.on_expire.push(hook);
+    }
+
+    pub(crate) fn set(&self, key: &str, entry: &Entry) {
+        self.on_set.iter().for_each(|hook| hook(key, entry));
+    }
+
+    pub(crate) fn delete(&self, key: &str, entry: &Entry) {
+        self.on_delete.iter().for_each(|hook| hook(key, entry));
+    }
+
+    pub(crate) fn expire(&self, key: &str, entry: &Entry) {
+        self.on_expire.iter().for_each(|hook| hook(key, entry));
+    }
+}
+
+impl std::fmt::Debug for KeyHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyHooks")
+            .field("on_set", &self.on_set.len())
+            .field("on_delete", &self.on_delete.len())
+            .field("on_expire", &self.on_expire.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{DataType, GenericOps, HashOps, StringOps, DB};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_key_hooks() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut db = DB::new();
+        let log = Arc::clone(&events);
+        db.on_set(move |key, entry| {
+            let value = match &entry.value {
+                DataType::String(s) => s.clone(),
+                DataType::Hash(h) => format!("{} fields", h.len()),
+                _ => String::new(),
+            };
+            log.lock().unwrap().push(format!("set {} {}", key, value));
+        });
+        let log = Arc::clone(&events);
+        db.on_delete(move |key, _| log.lock().unwrap().push(format!("del {}", key)));
+        let log = Arc::clone(&events);
+        db.on_expire(move |key, _| log.lock().unwrap().push(format!("expire {}", key)));
+
+        db.set("a".to_string(), "1".to_string());
+        db.append("a".to_string(), "2".to_string()).unwrap();
+        db.hset("h".to_string(), "f".to_string(), "v".to_string()).unwrap();
+        db.rename("a", "b").unwrap();
+        db.del("b");
+        db.psetex("t".to_string(), 1, "x".to_string());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(db.get("t").unwrap(), None);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "set a 1", "set a 12", "set h 1 fields", "del a", "set b 12", "del b", "set t x",
+                "expire t",
+            ]
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Object introspection for DEBUG OBJECT.
+//!
+//! Every type has a single native representation here, so the encodings
+//! reported are the ones Redis would pick for a value of the same shape.
+//! That keeps test suites that assert on encodings working.
+
+use crate::db::types::{DataType, Entry};
+use crate::persistence::codec;
+
+/// Collections up to this many elements report a packed encoding
+pub const LISTPACK_MAX_ENTRIES: usize = 128;
+/// Elements up to this many bytes keep a collection packed
+pub const LISTPACK_MAX_VALUE: usize = 64;
+/// Integer-only sets up to this many members report `intset`
+pub const INTSET_MAX_ENTRIES: usize = 512;
+/// Default size above which a list element counts as a plain node
+pub const DEFAULT_PACKED_THRESHOLD: usize = 1 << 30;
+/// Strings up to this many bytes report `embstr`
+const EMBSTR_MAX: usize = 44;
+
+fn packable<'a>(len: usize, mut items: impl Iterator<Item = &'a String>) -> bool {
+    len <= LISTPACK_MAX_ENTRIES && items.all(|s| s.len() <= LISTPACK_MAX_VALUE)
+}
+
+/// Encoding name of a value, as reported by DEBUG OBJECT
+pub fn encoding_of(value: &DataType) -> &'static str {
+    match value {
+        DataType::String(s) if s.len() <= 20 && s.parse::<i64>().is_ok() => "int",
+        DataType::String(s) if s.len() <= EMBSTR_MAX => "embstr",
+        DataType::String(_) | DataType::Bitmap(_) | DataType::HyperLogLog(_) => "raw",
+        DataType::List(list) if packable(list.len(), list.iter()) => "listpack",
+        DataType::List(_) => "quicklist",
+        DataType::Hash(hash) if packable(hash.len(), hash.keys().chain(hash.values())) => "listpack",
+        DataType::Hash(_) => "hashtable",
+        DataType::Set(set)
+            if set.len() <= INTSET_MAX_ENTRIES && set.iter().all(|m| m.parse::<i64>().is_ok()) =>
+        {
+            "intset"
+        }
+        DataType::Set(set) if packable(set.len(), set.iter()) => "listpack",
+        DataType::Set(_) => "hashtable",
+        DataType::ZSet(zset) if packable(zset.len(), zset.members.keys()) => "listpack",
+        DataType::ZSet(_) | DataType::Geo(_) => "skiplist",
+        DataType::Stream(_) => "stream",
+        DataType::Queue(_) => "queue",
+        DataType::TimeSeries(_) => "timeseries",
+        DataType::Bloom(_) => "bloom",
+        DataType::Cuckoo(_) => "cuckoo",
+        DataType::Vector(_) => "flat",
+    }
+}
+
+/// Size of the value in the snapshot format
+pub fn serialized_length(value: &DataType) -> usize {
+    let mut buf = Vec::new();
+    codec::write_value(&mut buf, value).map(|_| buf.len()).unwrap_or(0)
+}
+
+/// DEBUG OBJECT line for an entry. List elements of at least
+/// `packed_threshold` bytes are counted as plain nodes.
+pub fn describe(entry: &Entry, packed_threshold: usize) -> String {
+    let mut line = format!(
+        "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru:0 lru_seconds_idle:0",
+        entry,
+        encoding_of(&entry.value),
+        serialized_length(&entry.value)
+    );
+    if let DataType::List(list) = &entry.value {
+        let plain = list.iter().filter(|e| e.len() >= packed_threshold).count();
+        line.push_str(&format!(" ql_nodes:{} ql_plain_nodes:{}", list.len(), plain));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(encoding_of(&DataType::String("12345".to_string())), "int");
+        assert_eq!(encoding_of(&DataType::String("hello".to_string())), "embstr");
+        assert_eq!(encoding_of(&DataType::String("x".repeat(45))), "raw");
+        assert_eq!(encoding_of(&DataType::List(vec!["a".to_string()].into())), "listpack");
+        assert_eq!(encoding_of(&DataType::List(vec!["a".to_string(); 129].into())), "quicklist");
+        let ints = ["1", "2"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(encoding_of(&DataType::Set(ints)), "intset");
+
+        let entry = Entry {
+            value: DataType::List(vec!["small".to_string(), "x".repeat(100)].into()),
+            expires_at: None,
+            field_expires: None,
+        };
+        let line = describe(&entry, 64);
+        assert!(line.contains("encoding:quicklist"));
+        assert!(line.ends_with("ql_nodes:2 ql_plain_nodes:1"));
+    }
+}
@@ -0,0 +1,144 @@
+//! The keyspace map.
+//!
+//! Reads go straight to the underlying map; every change to the set of keys
+//! goes through `Keyspace` so it can keep a count of keys per type for
+//! DBSTATS.
+//!
+//! Keys are plain `String`s moved in from the parsed request. `Arc<str>`
+//! keys were tried: a new key then costs an extra allocation and copy, and
+//! the bulk key copies (snapshots, DUMPALL) already avoid a per-key
+//! allocation, so hexagondb-benchmark showed no gain. Read-only operations
+//! take the key as `&str` so a lookup never copies it.
+
+use crate::db::types::Entry;
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::ops::Deref;
+
+/// Main data store, keyed by key name
+#[derive(Default)]
+pub struct Keyspace {
+    map: HashMap<String, Entry>,
+    /// Keys per type name, maintained on every insert and remove
+    type_counts: HashMap<&'static str, usize>,
+}
+
+impl Keyspace {
+    /// Create an empty keyspace
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty keyspace with room for `capacity` keys
+    pub fn with_capacity(capacity: usize) -> Self {
+        Keyspace {
+            map: HashMap::with_capacity(capacity),
+            type_counts: HashMap::new(),
+        }
+    }
+
+    /// Insert an entry, returning the one it replaced
+    pub fn insert(&mut self, key: String, entry: Entry) -> Option<Entry> {
+        self.count(&entry, 1);
+        let old = self.map.insert(key, entry);
+        if let Some(old) = &old {
+            self.count(old, -1);
+        }
+        old
+    }
+
+    /// Remove a key, returning its entry
+    pub fn remove(&mut self, key: &str) -> Option<Entry> {
+        let old = self.map.remove(key);
+        if let Some(old) = &old {
+            self.count(old, -1);
+        }
+        old
+    }
+
+    /// The entry for `key`, inserting the one built by `default` if missing
+    pub fn get_or_insert_with(&mut self, key: String, default: impl FnOnce() -> Entry) -> &mut Entry {
+        match self.map.entry(key) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(vacant) => {
+                let entry = default();
+                *self.type_counts.entry(entry.value.type_name()).or_default() += 1;
+                vacant.insert(entry)
+            }
+        }
+    }
+
+    /// Mutable access to a key's entry
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.map.get_mut(key)
+    }
+
+    /// Remove every key, returning the old entries
+    pub fn take(&mut self) -> HashMap<String, Entry> {
+        self.type_counts.clear();
+        std::mem::take(&mut self.map)
+    }
+
+    /// Number of keys per type name, sorted by name. Expired keys not yet
+    /// removed are still counted, as in DBSIZE.
+    pub fn type_counts(&self) -> Vec<(&'static str, usize)> {
+        let mut counts: Vec<_> = self
+            .type_counts
+            .iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(&name, &count)| (name, count))
+            .collect();
+        counts.sort_unstable();
+        counts
+    }
+
+    fn count(&mut self, entry: &Entry, delta: isize) {
+        let count = self.type_counts.entry(entry.value.type_name()).or_default();
+        *count = count.saturating_add_signed(delta);
+    }
+}
+
+impl Deref for Keyspace {
+    type Target = HashMap<String, Entry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::types::DataType;
+
+    fn entry(value: &str) -> Entry {
+        Entry {
+            value: DataType::String(value.to_string()),
+            expires_at: None,
+            field_expires: None,
+        }
+    }
+
+    #[test]
+    fn test_type_counts() {
+        let mut keys = Keyspace::new();
+        keys.insert("a".to_string(), entry("1"));
+        keys.insert("b".to_string(), entry("2"));
+        keys.get_or_insert_with("l".to_string(), || Entry {
+            value: DataType::List(Default::default()),
+            expires_at: None,
+            field_expires: None,
+        });
+        keys.get_or_insert_with("l".to_string(), || entry("unused"));
+        assert_eq!(keys.type_counts(), vec![("list", 1), ("string", 2)]);
+
+        // Overwriting with another type moves the key between counts
+        keys.insert("l".to_string(), entry("3"));
+        keys.remove("a");
+        keys.remove("missing");
+        assert_eq!(keys.type_counts(), vec![("string", 2)]);
+
+        keys.take();
+        assert!(keys.type_counts().is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+//! Freeing large values off the command path.
+//!
+//! Dropping a hash with millions of fields, or a whole keyspace, walks every
+//! allocation and can stall the server for seconds while the write lock is
+//! held. FLUSHDB ASYNC, UNLINK and DEL instead detach the value and hand it
+//! to a blocking background task, so the lock is released right away.
+//! Small values are still dropped inline, where that is cheaper than
+//! scheduling a task.
+
+use crate::db::types::{DataType, Entry};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Values with more elements than this are freed in the background
+pub const LAZYFREE_THRESHOLD: usize = 64;
+
+/// Objects handed to a background task that have not been dropped yet
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// Objects waiting to be freed in the background (INFO lazyfree_pending_objects)
+pub fn pending() -> usize {
+    PENDING.load(Ordering::Relaxed)
+}
+
+/// Rough number of allocations behind a value
+pub fn effort(value: &DataType) -> usize {
+    match value {
+        DataType::String(_) | DataType::Bitmap(_) | DataType::HyperLogLog(_) => 1,
+        DataType::List(list) => list.len(),
+        DataType::Hash(hash) => hash.len(),
+        DataType::Set(set) => set.len(),
+        DataType::ZSet(zset) => zset.len(),
+        DataType::Stream(stream) => stream.entries.len(),
+        DataType::Geo(geo) => geo.locations.len(),
+        DataType::Queue(queue) => queue.messages.len(),
+        DataType::TimeSeries(series) => series.samples.len(),
+        DataType::Bloom(bloom) => bloom.layers.len(),
+        DataType::Cuckoo(cuckoo) => cuckoo.layers.len(),
+        DataType::Vector(index) => index.len(),
+    }
+}
+
+/// Drop `value` on a background task. Without a tokio runtime (tools,
+/// embedders) it is dropped on the calling thread.
+pub fn free<T: Send + 'static>(value: T) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            PENDING.fetch_add(1, Ordering::Relaxed);
+            handle.spawn_blocking(move || {
+                drop(value);
+                PENDING.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+        Err(_) => drop(value),
+    }
+}
+
+/// Drop a removed entry, in the background when it is large
+pub fn free_entry(entry: Entry) {
+    if effort(&entry.value) > LAZYFREE_THRESHOLD {
+        free(entry);
+    }
+}
@@ -2,13 +2,23 @@
 //!
 //! Contains the core database structure, data types, and all operations.
 
+pub mod compact;
 pub mod core;
+pub mod error;
+pub mod filters;
+pub mod hooks;
+pub mod introspect;
+pub mod keyspace;
+pub mod lazyfree;
 pub mod ops;
 pub mod pubsub;
+pub mod recycle;
+pub mod skiplist;
 pub mod types;
 
 // Re-export main types and traits
 pub use core::DB;
+pub use recycle::RecycleBin;
 pub use ops::generic::GenericOps;
 pub use ops::hash::HashOps;
 pub use ops::list::ListOps;
@@ -16,7 +26,14 @@ pub use ops::set::SetOps;
 pub use ops::string::StringOps;
 pub use ops::zset::ZSetOps;
 pub use ops::bitmap::BitmapOps;
-pub use ops::stream::StreamOps;
+pub use ops::stream::{StreamEntries, StreamOps, StreamTrim};
 pub use ops::geo::GeoOps;
 pub use ops::hyperloglog::HyperLogLogOps;
+pub use ops::queue::QueueOps;
+pub use ops::timeseries::TimeSeriesOps;
+pub use ops::bloom::BloomOps;
+pub use ops::cuckoo::CuckooOps;
+pub use ops::vector::VectorOps;
+pub use ops::sort::{SortOps, SortOptions};
+pub use error::{DbError, DbResult};
 pub use types::{DataType, Entry};
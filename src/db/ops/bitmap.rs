@@ -3,26 +3,26 @@
 //! Redis-compatible bitmap (string-based) operations.
 
 use crate::db::core::DB;
-use crate::db::ops::generic::GenericOps;
+use crate::db::error::{DbError, DbResult};
 use crate::db::types::{DataType, Entry};
 use std::sync::atomic::Ordering;
 
 /// Bitmap operations trait
 pub trait BitmapOps {
     /// Set or clear the bit at offset
-    fn setbit(&mut self, key: String, offset: usize, value: bool) -> i64;
+    fn setbit(&mut self, key: String, offset: usize, value: bool) -> DbResult<i64>;
     
     /// Get the bit value at offset
-    fn getbit(&mut self, key: String, offset: usize) -> i64;
+    fn getbit(&mut self, key: &str, offset: usize) -> DbResult<i64>;
     
     /// Count the number of set bits
-    fn bitcount(&mut self, key: String, start: Option<i64>, end: Option<i64>) -> usize;
+    fn bitcount(&mut self, key: &str, start: Option<i64>, end: Option<i64>) -> DbResult<usize>;
     
     /// Perform bitwise operations between keys
-    fn bitop(&mut self, op: BitOperation, destkey: String, keys: Vec<String>) -> usize;
+    fn bitop(&mut self, op: BitOperation, destkey: String, keys: Vec<String>) -> DbResult<usize>;
     
     /// Find first bit set to 0 or 1
-    fn bitpos(&mut self, key: String, bit: bool, start: Option<i64>, end: Option<i64>) -> i64;
+    fn bitpos(&mut self, key: &str, bit: bool, start: Option<i64>, end: Option<i64>) -> DbResult<i64>;
 }
 
 /// Bitwise operation types
@@ -34,135 +34,100 @@ pub enum BitOperation {
     Not,
 }
 
-impl BitmapOps for DB {
-    fn setbit(&mut self, key: String, offset: usize, value: bool) -> i64 {
-        self.check_expiration(&key);
-
-        let byte_index = offset / 8;
-        let bit_index = 7 - (offset % 8); // MSB first (Redis compatible)
+/// Bitmaps are strings: both hold bits
+fn is_bitmap(value: &DataType) -> bool {
+    matches!(value, DataType::Bitmap(_) | DataType::String(_))
+}
 
-        // Get or create bitmap
-        let old_bit = if let Some(entry) = self.items.get_mut(&key) {
-            match &mut entry.value {
-                DataType::Bitmap(data) => {
-                    // Expand if needed
-                    if byte_index >= data.len() {
-                        data.resize(byte_index + 1, 0);
-                    }
-                    let old = (data[byte_index] >> bit_index) & 1;
-                    if value {
-                        data[byte_index] |= 1 << bit_index;
-                    } else {
-                        data[byte_index] &= !(1 << bit_index);
-                    }
-                    old as i64
-                }
-                DataType::String(s) => {
-                    // Convert string to bitmap
-                    let mut data: Vec<u8> = s.as_bytes().to_vec();
-                    if byte_index >= data.len() {
-                        data.resize(byte_index + 1, 0);
-                    }
-                    let old = (data[byte_index] >> bit_index) & 1;
-                    if value {
-                        data[byte_index] |= 1 << bit_index;
-                    } else {
-                        data[byte_index] &= !(1 << bit_index);
-                    }
-                    entry.value = DataType::Bitmap(data);
-                    old as i64
-                }
-                _ => return 0, // Wrong type
-            }
-        } else {
-            // Create new bitmap
-            let mut data = vec![0u8; byte_index + 1];
-            if value {
-                data[byte_index] |= 1 << bit_index;
-            }
-            self.items.insert(key, Entry {
-                value: DataType::Bitmap(data),
-                expires_at: None,
-            });
-            0
-        };
+/// Set bits in a byte slice, counted eight bytes at a time
+fn popcount(bytes: &[u8]) -> usize {
+    let mut words = bytes.chunks_exact(8);
+    let mut count: usize = words
+        .by_ref()
+        .map(|word| u64::from_ne_bytes(word.try_into().unwrap()).count_ones() as usize)
+        .sum();
+    count += words.remainder().iter().map(|b| b.count_ones() as usize).sum::<usize>();
+    count
+}
 
-        self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-        old_bit
+/// Bytes of a bitmap or string value, empty when the key is missing
+fn bytes_of(value: Option<&DataType>) -> &[u8] {
+    match value {
+        Some(DataType::Bitmap(data)) => data,
+        Some(DataType::String(s)) => s.as_bytes(),
+        _ => &[],
     }
+}
+
+impl BitmapOps for DB {
+    fn setbit(&mut self, key: String, offset: usize, value: bool) -> DbResult<i64> {
+        let byte_index = offset / 8;
+        let bit_index = 7 - (offset % 8); // MSB first (Redis compatible)
 
-    fn getbit(&mut self, key: String, offset: usize) -> i64 {
-        if !self.check_expiration(&key) {
-            return 0;
+        // Bits past the end already read as 0: clearing one neither creates
+        // the key nor grows the value
+        if !value && byte_index >= bytes_of(self.typed_value(&key, is_bitmap)?).len() {
+            return Ok(0);
         }
 
-        let byte_index = offset / 8;
-        let bit_index = 7 - (offset % 8);
-
-        if let Some(entry) = self.items.get(&key) {
-            match &entry.value {
-                DataType::Bitmap(data) => {
-                    if byte_index < data.len() {
-                        ((data[byte_index] >> bit_index) & 1) as i64
-                    } else {
-                        0
-                    }
-                }
-                DataType::String(s) => {
-                    let bytes = s.as_bytes();
-                    if byte_index < bytes.len() {
-                        ((bytes[byte_index] >> bit_index) & 1) as i64
-                    } else {
-                        0
-                    }
-                }
-                _ => 0,
-            }
-        } else {
-            0
+        let entry = self.typed_entry_mut(&key, is_bitmap, || DataType::Bitmap(Vec::new()))?;
+        // A string becomes a bitmap on its first bit write
+        if let DataType::String(s) = &mut entry.value {
+            let data = std::mem::take(s).into_bytes();
+            entry.value = DataType::Bitmap(data);
         }
-    }
+        let data = match &mut entry.value {
+            DataType::Bitmap(data) => data,
+            _ => return Err(DbError::WrongType),
+        };
 
-    fn bitcount(&mut self, key: String, start: Option<i64>, end: Option<i64>) -> usize {
-        if !self.check_expiration(&key) {
-            return 0;
+        // Expand if needed
+        if byte_index >= data.len() {
+            data.resize(byte_index + 1, 0);
+        }
+        let old = (data[byte_index] >> bit_index) & 1;
+        if value {
+            data[byte_index] |= 1 << bit_index;
+        } else {
+            data[byte_index] &= !(1 << bit_index);
         }
 
-        if let Some(entry) = self.items.get(&key) {
-            let data = match &entry.value {
-                DataType::Bitmap(d) => d.as_slice(),
-                DataType::String(s) => s.as_bytes(),
-                _ => return 0,
-            };
+        self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(&key);
+        Ok(old as i64)
+    }
 
-            if data.is_empty() {
-                return 0;
-            }
+    fn getbit(&mut self, key: &str, offset: usize) -> DbResult<i64> {
+        let data = bytes_of(self.typed_value(key, is_bitmap)?);
+        Ok(data
+            .get(offset / 8)
+            .map_or(0, |byte| ((byte >> (7 - (offset % 8))) & 1) as i64))
+    }
 
-            let len = data.len() as i64;
-            let start = start.map(|s| {
-                if s < 0 { (len + s).max(0) } else { s.min(len) }
-            }).unwrap_or(0) as usize;
-            let end = end.map(|e| {
-                if e < 0 { (len + e).max(0) } else { e.min(len - 1) }
-            }).unwrap_or(len - 1) as usize;
+    fn bitcount(&mut self, key: &str, start: Option<i64>, end: Option<i64>) -> DbResult<usize> {
+        let data = bytes_of(self.typed_value(key, is_bitmap)?);
+        if data.is_empty() {
+            return Ok(0);
+        }
 
-            if start > end || start >= data.len() {
-                return 0;
-            }
+        let len = data.len() as i64;
+        let start = start.map(|s| {
+            if s < 0 { (len + s).max(0) } else { s.min(len) }
+        }).unwrap_or(0) as usize;
+        let end = end.map(|e| {
+            if e < 0 { (len + e).max(0) } else { e.min(len - 1) }
+        }).unwrap_or(len - 1) as usize;
 
-            data[start..=end.min(data.len() - 1)]
-                .iter()
-                .map(|b| b.count_ones() as usize)
-                .sum()
-        } else {
-            0
+        if start > end || start >= data.len() {
+            return Ok(0);
         }
+
+        Ok(popcount(&data[start..=end.min(data.len() - 1)]))
     }
 
-    fn bitop(&mut self, op: BitOperation, destkey: String, keys: Vec<String>) -> usize {
+    fn bitop(&mut self, op: BitOperation, destkey: String, keys: Vec<String>) -> DbResult<usize> {
         if keys.is_empty() {
-            return 0;
+            return Ok(0);
         }
 
         // Collect all bitmaps
@@ -170,22 +135,16 @@ impl BitmapOps for DB {
         let mut max_len = 0;
 
         for key in &keys {
-            if let Some(entry) = self.items.get(key) {
-                let data = match &entry.value {
-                    DataType::Bitmap(d) => d.clone(),
-                    DataType::String(s) => s.as_bytes().to_vec(),
-                    _ => vec![],
-                };
-                max_len = max_len.max(data.len());
-                bitmaps.push(data);
-            } else {
-                bitmaps.push(vec![]);
-            }
+            let data = bytes_of(self.typed_value(key, is_bitmap)?).to_vec();
+            max_len = max_len.max(data.len());
+            bitmaps.push(data);
         }
 
         if max_len == 0 {
-            self.items.remove(&destkey);
-            return 0;
+            if let Some(old) = self.items.remove(&destkey) {
+                self.notify_delete(&destkey, &old);
+            }
+            return Ok(0);
         }
 
         // Perform operation
@@ -233,68 +192,58 @@ impl BitmapOps for DB {
             }
         }
 
-        self.items.insert(destkey, Entry {
-            value: DataType::Bitmap(result.clone()),
+        let len = result.len();
+        self.items.insert(destkey.clone(), Entry {
+            value: DataType::Bitmap(result),
             expires_at: None,
+            field_expires: None,
         });
         self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(&destkey);
 
-        result.len()
+        Ok(len)
     }
 
-    fn bitpos(&mut self, key: String, bit: bool, start: Option<i64>, end: Option<i64>) -> i64 {
-        if !self.check_expiration(&key) {
-            return if bit { -1 } else { 0 };
+    fn bitpos(&mut self, key: &str, bit: bool, start: Option<i64>, end: Option<i64>) -> DbResult<i64> {
+        let data = bytes_of(self.typed_value(key, is_bitmap)?);
+        if data.is_empty() {
+            return Ok(if bit { -1 } else { 0 });
         }
 
-        if let Some(entry) = self.items.get(&key) {
-            let data = match &entry.value {
-                DataType::Bitmap(d) => d.as_slice(),
-                DataType::String(s) => s.as_bytes(),
-                _ => return -1,
-            };
+        let len = data.len() as i64;
+        let start_byte = start.map(|s| {
+            if s < 0 { (len + s).max(0) } else { s.min(len) }
+        }).unwrap_or(0) as usize;
+        let end_byte = end.map(|e| {
+            if e < 0 { (len + e).max(0) } else { e.min(len - 1) }
+        }).unwrap_or(len - 1) as usize;
 
-            if data.is_empty() {
-                return if bit { -1 } else { 0 };
-            }
-
-            let len = data.len() as i64;
-            let start_byte = start.map(|s| {
-                if s < 0 { (len + s).max(0) } else { s.min(len) }
-            }).unwrap_or(0) as usize;
-            let end_byte = end.map(|e| {
-                if e < 0 { (len + e).max(0) } else { e.min(len - 1) }
-            }).unwrap_or(len - 1) as usize;
-
-            if start_byte > end_byte || start_byte >= data.len() {
-                return -1;
-            }
+        if start_byte > end_byte || start_byte >= data.len() {
+            return Ok(-1);
+        }
 
-            for (byte_idx, &byte) in data[start_byte..=end_byte.min(data.len() - 1)].iter().enumerate() {
-                let target = if bit { byte } else { !byte };
-                if target != 0 {
-                    // Find the first set bit
-                    for bit_idx in 0..8 {
-                        if (target >> (7 - bit_idx)) & 1 == 1 {
-                            return ((start_byte + byte_idx) * 8 + bit_idx) as i64;
-                        }
+        for (byte_idx, &byte) in data[start_byte..=end_byte.min(data.len() - 1)].iter().enumerate() {
+            let target = if bit { byte } else { !byte };
+            if target != 0 {
+                // Find the first set bit
+                for bit_idx in 0..8 {
+                    if (target >> (7 - bit_idx)) & 1 == 1 {
+                        return Ok(((start_byte + byte_idx) * 8 + bit_idx) as i64);
                     }
                 }
             }
+        }
 
-            if bit {
-                -1
+        if bit {
+            Ok(-1)
+        } else {
+            // If searching for 0 and end was specified, return -1
+            // If searching for 0 and end was not specified, return first bit after end
+            if end.is_some() {
+                Ok(-1)
             } else {
-                // If searching for 0 and end was specified, return -1
-                // If searching for 0 and end was not specified, return first bit after end
-                if end.is_some() {
-                    -1
-                } else {
-                    (data.len() * 8) as i64
-                }
+                Ok((data.len() * 8) as i64)
             }
-        } else {
-            if bit { -1 } else { 0 }
         }
     }
 }
@@ -307,22 +256,60 @@ mod tests {
     fn test_setbit_getbit() {
         let mut db = DB::new();
         
-        assert_eq!(db.setbit("mykey".to_string(), 7, true), 0);
-        assert_eq!(db.getbit("mykey".to_string(), 0), 0);
-        assert_eq!(db.getbit("mykey".to_string(), 7), 1);
+        assert_eq!(db.setbit("mykey".to_string(), 7, true), Ok(0));
+        assert_eq!(db.getbit("mykey", 0), Ok(0));
+        assert_eq!(db.getbit("mykey", 7), Ok(1));
         
-        assert_eq!(db.setbit("mykey".to_string(), 7, false), 1);
-        assert_eq!(db.getbit("mykey".to_string(), 7), 0);
+        assert_eq!(db.setbit("mykey".to_string(), 7, false), Ok(1));
+        assert_eq!(db.getbit("mykey", 7), Ok(0));
     }
 
     #[test]
     fn test_bitcount() {
         let mut db = DB::new();
         
-        db.setbit("mykey".to_string(), 0, true);
-        db.setbit("mykey".to_string(), 1, true);
-        db.setbit("mykey".to_string(), 2, true);
+        db.setbit("mykey".to_string(), 0, true).unwrap();
+        db.setbit("mykey".to_string(), 1, true).unwrap();
+        db.setbit("mykey".to_string(), 2, true).unwrap();
         
-        assert_eq!(db.bitcount("mykey".to_string(), None, None), 3);
+        assert_eq!(db.bitcount("mykey", None, None), Ok(3));
+
+        // Spans whole words and a tail
+        db.setbit("mykey".to_string(), 100, true).unwrap();
+        db.setbit("mykey".to_string(), 150, true).unwrap();
+        assert_eq!(db.bitcount("mykey", None, None), Ok(5));
+        assert_eq!(db.bitcount("mykey", Some(1), None), Ok(2));
+    }
+
+    #[test]
+    fn test_clearing_past_end_does_not_grow() {
+        use crate::db::{GenericOps, StringOps};
+        let mut db = DB::new();
+
+        assert_eq!(db.setbit("missing".to_string(), 1 << 30, false), Ok(0));
+        assert!(!db.exists("missing"));
+
+        db.setbit("mykey".to_string(), 3, true).unwrap();
+        assert_eq!(db.setbit("mykey".to_string(), 1 << 30, false), Ok(0));
+        assert_eq!(db.bitcount("mykey", None, None), Ok(1));
+        assert_eq!(db.getbit("mykey", 1 << 30), Ok(0));
+        assert_eq!(db.strlen("mykey"), 1);
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        use crate::db::ListOps;
+        let mut db = DB::new();
+        db.lpush("list".to_string(), vec!["a".to_string()]).unwrap();
+
+        assert_eq!(db.setbit("list".to_string(), 0, true), Err(DbError::WrongType));
+        assert_eq!(db.getbit("list", 0), Err(DbError::WrongType));
+        assert_eq!(db.bitcount("list", None, None), Err(DbError::WrongType));
+        assert_eq!(db.bitpos("list", true, None, None), Err(DbError::WrongType));
+        assert_eq!(
+            db.bitop(BitOperation::Or, "dest".to_string(), vec!["list".to_string()]),
+            Err(DbError::WrongType)
+        );
+        assert_eq!(db.llen("list"), Ok(1));
     }
 }
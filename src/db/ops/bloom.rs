@@ -0,0 +1,174 @@
+//! Bloom filter operations.
+//!
+//! A key created by BF.ADD or BF.MADD gets the default error rate and
+//! capacity; BF.RESERVE creates one with its own.
+
+use crate::db::core::DB;
+use crate::db::error::{DbError, DbResult};
+use crate::db::filters::{BloomFilter, BLOOM_DEFAULT_CAPACITY, BLOOM_DEFAULT_ERROR_RATE, BLOOM_DEFAULT_EXPANSION};
+use crate::db::ops::generic::GenericOps;
+use crate::db::types::DataType;
+
+/// Options of a new filter: `error_rate capacity [EXPANSION n] [NONSCALING]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomOptions {
+    pub error_rate: f64,
+    pub capacity: u64,
+    /// 0 for a filter that does not scale
+    pub expansion: u32,
+}
+
+impl Default for BloomOptions {
+    fn default() -> Self {
+        BloomOptions {
+            error_rate: BLOOM_DEFAULT_ERROR_RATE,
+            capacity: BLOOM_DEFAULT_CAPACITY,
+            expansion: BLOOM_DEFAULT_EXPANSION,
+        }
+    }
+}
+
+impl BloomOptions {
+    pub fn parse(args: &[String]) -> DbResult<Self> {
+        let [error_rate, capacity, rest @ ..] = args else {
+            return Err(DbError::Syntax("syntax error".to_string()));
+        };
+        let mut options = BloomOptions {
+            error_rate: error_rate
+                .parse()
+                .ok()
+                .filter(|rate| *rate > 0.0 && *rate < 1.0)
+                .ok_or_else(|| DbError::InvalidValue("(0 < error rate range < 1)".to_string()))?,
+            capacity: capacity
+                .parse()
+                .ok()
+                .filter(|&capacity| capacity > 0)
+                .ok_or_else(|| DbError::InvalidValue("(capacity should be larger than 0)".to_string()))?,
+            ..Default::default()
+        };
+        let mut rest = rest;
+        loop {
+            match rest {
+                [] => return Ok(options),
+                [opt, tail @ ..] if opt.eq_ignore_ascii_case("NONSCALING") => {
+                    options.expansion = 0;
+                    rest = tail;
+                }
+                [opt, n, tail @ ..] if opt.eq_ignore_ascii_case("EXPANSION") => {
+                    options.expansion = n
+                        .parse()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| DbError::InvalidValue("bad expansion".to_string()))?;
+                    rest = tail;
+                }
+                _ => return Err(DbError::Syntax("syntax error".to_string())),
+            }
+        }
+    }
+}
+
+/// Bloom filter operations trait
+pub trait BloomOps {
+    /// Create an empty filter (BF.RESERVE). Fails if the key exists.
+    fn bf_reserve(&mut self, key: String, options: BloomOptions) -> DbResult<()>;
+
+    /// Add items (BF.ADD / BF.MADD). Each result is whether the item was
+    /// new, or an error if the filter is full.
+    fn bf_madd(&mut self, key: String, items: &[String]) -> DbResult<Vec<DbResult<bool>>>;
+
+    /// Whether each item may have been added (BF.EXISTS / BF.MEXISTS)
+    fn bf_mexists(&mut self, key: &str, items: &[String]) -> DbResult<Vec<bool>>;
+
+    /// Fields reported by BF.INFO
+    fn bf_info(&mut self, key: &str) -> DbResult<Vec<(&'static str, i64)>>;
+}
+
+fn is_bloom(value: &DataType) -> bool {
+    matches!(value, DataType::Bloom(_))
+}
+
+impl BloomOps for DB {
+    fn bf_reserve(&mut self, key: String, options: BloomOptions) -> DbResult<()> {
+        if self.check_expiration(&key) && self.items.get(&key).is_some() {
+            return Err(DbError::InvalidValue("item exists".to_string()));
+        }
+        self.typed_entry_mut(&key, is_bloom, || {
+            DataType::Bloom(BloomFilter::new(options.error_rate, options.capacity, options.expansion))
+        })?;
+        self.increment_changes();
+        self.notify_set(&key);
+        Ok(())
+    }
+
+    fn bf_madd(&mut self, key: String, items: &[String]) -> DbResult<Vec<DbResult<bool>>> {
+        let entry = self.typed_entry_mut(&key, is_bloom, || {
+            let options = BloomOptions::default();
+            DataType::Bloom(BloomFilter::new(options.error_rate, options.capacity, options.expansion))
+        })?;
+        let DataType::Bloom(bloom) = &mut entry.value else { return Err(DbError::WrongType) };
+        let added: Vec<DbResult<bool>> = items
+            .iter()
+            .map(|item| {
+                bloom
+                    .add(item)
+                    .map_err(|_| DbError::InvalidValue("non scaling filter is full".to_string()))
+            })
+            .collect();
+        if added.contains(&Ok(true)) {
+            self.increment_changes();
+            self.notify_set(&key);
+        }
+        Ok(added)
+    }
+
+    fn bf_mexists(&mut self, key: &str, items: &[String]) -> DbResult<Vec<bool>> {
+        Ok(match self.typed_value(key, is_bloom)? {
+            Some(DataType::Bloom(bloom)) => items.iter().map(|item| bloom.contains(item)).collect(),
+            _ => vec![false; items.len()],
+        })
+    }
+
+    fn bf_info(&mut self, key: &str) -> DbResult<Vec<(&'static str, i64)>> {
+        match self.typed_value(key, is_bloom)? {
+            Some(DataType::Bloom(bloom)) => Ok(vec![
+                ("Capacity", bloom.capacity() as i64),
+                ("Size", bloom.size() as i64),
+                ("Number of filters", bloom.layers.len() as i64),
+                ("Number of items inserted", bloom.items() as i64),
+                ("Expansion rate", bloom.expansion as i64),
+            ]),
+            _ => Err(DbError::NoSuchKey),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn test_reserve_add_exists() {
+        let mut db = DB::new();
+        let options = BloomOptions::parse(&items(&["0.001", "2", "NONSCALING"])).unwrap();
+        assert_eq!(options, BloomOptions { error_rate: 0.001, capacity: 2, expansion: 0 });
+        db.bf_reserve("seen".to_string(), options).unwrap();
+        assert!(db.bf_reserve("seen".to_string(), options).is_err());
+
+        let added = db.bf_madd("seen".to_string(), &items(&["a", "b", "a", "c"])).unwrap();
+        assert_eq!(added[..3], [Ok(true), Ok(true), Ok(false)]);
+        assert!(added[3].is_err());
+        assert_eq!(db.bf_mexists("seen", &items(&["a", "b", "c"])).unwrap(), vec![true, true, false]);
+        assert_eq!(db.bf_mexists("missing", &items(&["a"])).unwrap(), vec![false]);
+        assert_eq!(db.bf_info("seen").unwrap()[3], ("Number of items inserted", 2));
+        assert_eq!(db.bf_info("missing"), Err(DbError::NoSuchKey));
+
+        assert!(BloomOptions::parse(&items(&["1.5", "10"])).is_err());
+        assert!(BloomOptions::parse(&items(&["0.01", "0"])).is_err());
+        assert!(BloomOptions::parse(&items(&["0.01", "10", "EXPANSION"])).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Cuckoo filter operations.
+//!
+//! Unlike a bloom filter, an item can be added more than once and deleted.
+//! A key created by CF.ADD or CF.ADDNX gets the default capacity; CF.RESERVE
+//! creates one with its own.
+
+use crate::db::core::DB;
+use crate::db::error::{DbError, DbResult};
+use crate::db::filters::{
+    CuckooFilter, CUCKOO_DEFAULT_BUCKET_SIZE, CUCKOO_DEFAULT_CAPACITY, CUCKOO_DEFAULT_EXPANSION,
+    CUCKOO_DEFAULT_MAX_ITERATIONS,
+};
+use crate::db::ops::generic::GenericOps;
+use crate::db::types::DataType;
+
+/// Options of a new filter:
+/// `capacity [BUCKETSIZE n] [MAXITERATIONS n] [EXPANSION n]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CuckooOptions {
+    pub capacity: u64,
+    pub bucket_size: usize,
+    pub max_iterations: u32,
+    /// 0 for a filter that does not scale
+    pub expansion: u32,
+}
+
+impl Default for CuckooOptions {
+    fn default() -> Self {
+        CuckooOptions {
+            capacity: CUCKOO_DEFAULT_CAPACITY,
+            bucket_size: CUCKOO_DEFAULT_BUCKET_SIZE,
+            max_iterations: CUCKOO_DEFAULT_MAX_ITERATIONS,
+            expansion: CUCKOO_DEFAULT_EXPANSION,
+        }
+    }
+}
+
+impl CuckooOptions {
+    pub fn parse(args: &[String]) -> DbResult<Self> {
+        let [capacity, rest @ ..] = args else {
+            return Err(DbError::Syntax("syntax error".to_string()));
+        };
+        let mut options = CuckooOptions {
+            capacity: capacity
+                .parse()
+                .ok()
+                .filter(|&capacity| capacity > 0)
+                .ok_or_else(|| DbError::InvalidValue("Bad capacity".to_string()))?,
+            ..Default::default()
+        };
+        for pair in rest.chunks(2) {
+            let [opt, value] = pair else {
+                return Err(DbError::Syntax("syntax error".to_string()));
+            };
+            let value = value.parse::<u32>().ok();
+            match opt.to_ascii_uppercase().as_str() {
+                "BUCKETSIZE" => {
+                    options.bucket_size = value
+                        .filter(|n| (1..=255).contains(n))
+                        .ok_or_else(|| DbError::InvalidValue("Bad bucket size".to_string()))?
+                        as usize;
+                }
+                "MAXITERATIONS" => {
+                    options.max_iterations = value
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| DbError::InvalidValue("Bad max iterations".to_string()))?;
+                }
+                "EXPANSION" => {
+                    options.expansion =
+                        value.ok_or_else(|| DbError::InvalidValue("Bad expansion".to_string()))?;
+                }
+                _ => return Err(DbError::Syntax("syntax error".to_string())),
+            }
+        }
+        Ok(options)
+    }
+
+    fn filter(&self) -> CuckooFilter {
+        CuckooFilter::new(self.capacity, self.bucket_size, self.max_iterations, self.expansion)
+    }
+}
+
+/// Cuckoo filter operations trait
+pub trait CuckooOps {
+    /// Create an empty filter (CF.RESERVE). Fails if the key exists.
+    fn cf_reserve(&mut self, key: String, options: CuckooOptions) -> DbResult<()>;
+
+    /// Add an item (CF.ADD), or with `nx` only if it may not be in the
+    /// filter yet (CF.ADDNX). Returns whether it was added.
+    fn cf_add(&mut self, key: String, item: &str, nx: bool) -> DbResult<bool>;
+
+    /// Whether each item may have been added (CF.EXISTS / CF.MEXISTS)
+    fn cf_mexists(&mut self, key: &str, items: &[String]) -> DbResult<Vec<bool>>;
+
+    /// Times an item may have been added (CF.COUNT)
+    fn cf_count(&mut self, key: &str, item: &str) -> DbResult<usize>;
+
+    /// Delete one copy of an item, returning whether one was found (CF.DEL)
+    fn cf_del(&mut self, key: &str, item: &str) -> DbResult<bool>;
+
+    /// Fields reported by CF.INFO
+    fn cf_info(&mut self, key: &str) -> DbResult<Vec<(&'static str, i64)>>;
+}
+
+fn is_cuckoo(value: &DataType) -> bool {
+    matches!(value, DataType::Cuckoo(_))
+}
+
+impl DB {
+    /// The cuckoo filter stored at `key`, if any
+    fn cuckoo_mut(&mut self, key: &str) -> DbResult<Option<&mut CuckooFilter>> {
+        if !self.check_expiration(key) {
+            return Ok(None);
+        }
+        match self.items.get_mut(key).map(|entry| &mut entry.value) {
+            Some(DataType::Cuckoo(cuckoo)) => Ok(Some(cuckoo)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+impl CuckooOps for DB {
+    fn cf_reserve(&mut self, key: String, options: CuckooOptions) -> DbResult<()> {
+        if self.check_expiration(&key) && self.items.get(&key).is_some() {
+            return Err(DbError::InvalidValue("item exists".to_string()));
+        }
+        self.typed_entry_mut(&key, is_cuckoo, || DataType::Cuckoo(options.filter()))?;
+        self.increment_changes();
+        self.notify_set(&key);
+        Ok(())
+    }
+
+    fn cf_add(&mut self, key: String, item: &str, nx: bool) -> DbResult<bool> {
+        let entry = self.typed_entry_mut(&key, is_cuckoo, || DataType::Cuckoo(CuckooOptions::default().filter()))?;
+        let DataType::Cuckoo(cuckoo) = &mut entry.value else { return Err(DbError::WrongType) };
+        let added = if nx { cuckoo.add_nx(item) } else { cuckoo.add(item).map(|_| true) }
+            .map_err(|_| DbError::InvalidValue("Filter is full".to_string()))?;
+        if added {
+            self.increment_changes();
+            self.notify_set(&key);
+        }
+        Ok(added)
+    }
+
+    fn cf_mexists(&mut self, key: &str, items: &[String]) -> DbResult<Vec<bool>> {
+        Ok(match self.typed_value(key, is_cuckoo)? {
+            Some(DataType::Cuckoo(cuckoo)) => items.iter().map(|item| cuckoo.contains(item)).collect(),
+            _ => vec![false; items.len()],
+        })
+    }
+
+    fn cf_count(&mut self, key: &str, item: &str) -> DbResult<usize> {
+        Ok(match self.typed_value(key, is_cuckoo)? {
+            Some(DataType::Cuckoo(cuckoo)) => cuckoo.count(item),
+            _ => 0,
+        })
+    }
+
+    fn cf_del(&mut self, key: &str, item: &str) -> DbResult<bool> {
+        let deleted = match self.cuckoo_mut(key)? {
+            Some(cuckoo) => cuckoo.delete(item),
+            None => return Err(DbError::NoSuchKey),
+        };
+        if deleted {
+            self.increment_changes();
+            self.notify_set(key);
+        }
+        Ok(deleted)
+    }
+
+    fn cf_info(&mut self, key: &str) -> DbResult<Vec<(&'static str, i64)>> {
+        match self.typed_value(key, is_cuckoo)? {
+            Some(DataType::Cuckoo(cuckoo)) => Ok(vec![
+                ("Size", cuckoo.size() as i64),
+                ("Number of buckets", cuckoo.buckets() as i64),
+                ("Number of filters", cuckoo.layers.len() as i64),
+                ("Number of items inserted", cuckoo.items as i64),
+                ("Number of items deleted", cuckoo.deleted as i64),
+                ("Bucket size", cuckoo.bucket_size as i64),
+                ("Expansion rate", cuckoo.expansion as i64),
+                ("Max iterations", cuckoo.max_iterations as i64),
+            ]),
+            _ => Err(DbError::NoSuchKey),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn test_add_count_delete() {
+        let mut db = DB::new();
+        let options = CuckooOptions::parse(&items(&["100", "BUCKETSIZE", "4", "EXPANSION", "2"])).unwrap();
+        assert_eq!((options.capacity, options.bucket_size, options.expansion), (100, 4, 2));
+        db.cf_reserve("seen".to_string(), options).unwrap();
+        assert!(db.cf_reserve("seen".to_string(), options).is_err());
+
+        assert_eq!(db.cf_add("seen".to_string(), "a", false), Ok(true));
+        assert_eq!(db.cf_add("seen".to_string(), "a", false), Ok(true));
+        assert_eq!(db.cf_add("seen".to_string(), "a", true), Ok(false));
+        assert_eq!(db.cf_add("seen".to_string(), "b", true), Ok(true));
+        assert_eq!(db.cf_count("seen", "a"), Ok(2));
+        assert_eq!(db.cf_mexists("seen", &items(&["a", "b", "c"])).unwrap(), vec![true, true, false]);
+
+        assert_eq!(db.cf_del("seen", "a"), Ok(true));
+        assert_eq!(db.cf_del("seen", "c"), Ok(false));
+        assert_eq!(db.cf_del("missing", "a"), Err(DbError::NoSuchKey));
+        assert_eq!(db.cf_count("seen", "a"), Ok(1));
+        assert_eq!(db.cf_info("seen").unwrap()[3..5], [("Number of items inserted", 2), ("Number of items deleted", 1)]);
+
+        assert!(CuckooOptions::parse(&items(&["0"])).is_err());
+        assert!(CuckooOptions::parse(&items(&["10", "BUCKETSIZE"])).is_err());
+        assert!(CuckooOptions::parse(&items(&["10", "MAXITERATIONS", "0"])).is_err());
+    }
+}
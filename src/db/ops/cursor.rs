@@ -0,0 +1,76 @@
+//! Resize-proof cursors for HSCAN and SSCAN.
+//!
+//! Elements are visited in the order of a fixed hash of the element rather
+//! than their position in the map, and the cursor is the hash to resume
+//! from. Growing, shrinking or rehashing the map never moves an element
+//! across the cursor, so an element present for the whole scan is returned
+//! exactly once; elements added or removed meanwhile may or may not be.
+
+use siphasher::sip::SipHasher;
+use std::hash::{Hash, Hasher};
+
+/// Position of an element in scan order
+pub fn scan_hash(element: &str) -> u64 {
+    let mut hasher = SipHasher::new();
+    element.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// One page of a scan: up to `count` items whose element hashes at or after
+/// `cursor`, in scan order, and the cursor of the next page (0 when done).
+/// Elements sharing a hash always land on the same page.
+pub fn scan_page<'a, T>(
+    items: impl Iterator<Item = (&'a str, T)>,
+    cursor: u64,
+    count: usize,
+) -> (u64, Vec<T>) {
+    let mut pending: Vec<(u64, T)> = items
+        .map(|(element, item)| (scan_hash(element), item))
+        .filter(|(hash, _)| *hash >= cursor)
+        .collect();
+    pending.sort_unstable_by_key(|(hash, _)| *hash);
+
+    let mut end = count.max(1).min(pending.len());
+    while end < pending.len() && pending[end].0 == pending[end - 1].0 {
+        end += 1;
+    }
+    // The next element hashes strictly higher, so this cannot wrap to 0
+    let next = match pending.get(end) {
+        Some(_) => pending[end - 1].0 + 1,
+        None => 0,
+    };
+    pending.truncate(end);
+    (next, pending.into_iter().map(|(_, item)| item).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_scan_survives_resize() {
+        let mut set: HashSet<String> = (0..100).map(|i| format!("m{}", i)).collect();
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        let mut grown = false;
+        loop {
+            let (next, page) = scan_page(set.iter().map(|m| (m.as_str(), m.clone())), cursor, 10);
+            seen.extend(page);
+            if !grown {
+                // Force a rehash halfway through the first pass
+                set.extend((100..5000).map(|i| format!("m{}", i)));
+                set.shrink_to_fit();
+                grown = true;
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        for i in 0..100 {
+            let member = format!("m{}", i);
+            assert_eq!(seen.iter().filter(|m| **m == member).count(), 1, "{}", member);
+        }
+    }
+}
@@ -2,11 +2,54 @@
 //!
 //! Operations that work on any key regardless of data type.
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
+use crate::db::lazyfree;
 use crate::db::types::{DataType, Entry};
 use rand::seq::IteratorRandom;
+use std::sync::atomic::Ordering;
 use std::time::{Duration, Instant};
 
+/// Condition for replacing an existing TTL (NX, XX, GT, LT)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpireCondition {
+    /// Always set the TTL
+    #[default]
+    Always,
+    /// Only when there is no TTL yet
+    Nx,
+    /// Only when there already is a TTL
+    Xx,
+    /// Only when the new TTL is greater than the current one
+    Gt,
+    /// Only when the new TTL is less than the current one
+    Lt,
+}
+
+impl ExpireCondition {
+    /// Parse an NX/XX/GT/LT flag (case-insensitive)
+    pub fn parse(flag: &str) -> Option<Self> {
+        match flag.to_uppercase().as_str() {
+            "NX" => Some(ExpireCondition::Nx),
+            "XX" => Some(ExpireCondition::Xx),
+            "GT" => Some(ExpireCondition::Gt),
+            "LT" => Some(ExpireCondition::Lt),
+            _ => None,
+        }
+    }
+
+    /// Check whether a TTL may be replaced. No TTL counts as infinite for GT/LT.
+    pub fn allows(self, current: Option<Instant>, new: Instant) -> bool {
+        match self {
+            ExpireCondition::Always => true,
+            ExpireCondition::Nx => current.is_none(),
+            ExpireCondition::Xx => current.is_some(),
+            ExpireCondition::Gt => current.is_some_and(|at| new > at),
+            ExpireCondition::Lt => current.is_none_or(|at| new < at),
+        }
+    }
+}
+
 /// Generic operations trait
 pub trait GenericOps {
     /// Check and handle key expiration. Returns false if key was expired.
@@ -15,8 +58,12 @@ pub trait GenericOps {
     /// Check if a key exists
     fn exists(&self, key: &str) -> bool;
     
-    /// Delete a key
+    /// Delete a key (moved to the recycle bin when soft-delete is enabled)
     fn del(&mut self, key: &str) -> bool;
+
+    /// Restore a soft-deleted key from the recycle bin.
+    /// Returns false if the key is not in the bin or a live key already uses the name.
+    fn undelete(&mut self, key: &str) -> bool;
     
     /// Get the type of a key
     fn type_of(&self, key: &str) -> Option<String>;
@@ -27,12 +74,29 @@ pub trait GenericOps {
     /// Set expiration at a specific timestamp
     fn expireat(&mut self, key: &str, timestamp: u64) -> bool;
     
+    /// Set expiration in milliseconds
+    fn pexpire(&mut self, key: &str, millis: u64) -> bool;
+
+    /// Set expiration at a Unix timestamp in milliseconds
+    fn pexpireat(&mut self, key: &str, timestamp_ms: u64) -> bool;
+
+    /// Set expiration at an instant if the condition allows it. An instant in
+    /// the past deletes the key. Returns false if the key is missing or the
+    /// condition is not met.
+    fn expire_with(&mut self, key: &str, at: Instant, condition: ExpireCondition) -> bool;
+
     /// Get TTL in seconds
     fn ttl(&mut self, key: &str) -> i64;
     
     /// Get TTL in milliseconds
     fn pttl(&mut self, key: &str) -> i64;
     
+    /// Get the absolute expiry as a Unix timestamp in seconds (-1 no TTL, -2 no key)
+    fn expiretime(&mut self, key: &str) -> i64;
+
+    /// Get the absolute expiry as a Unix timestamp in milliseconds (-1 no TTL, -2 no key)
+    fn pexpiretime(&mut self, key: &str) -> i64;
+
     /// Remove expiration from a key
     fn persist(&mut self, key: &str) -> bool;
     
@@ -43,7 +107,7 @@ pub trait GenericOps {
     fn scan(&self, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> (u64, Vec<String>);
     
     /// Rename a key
-    fn rename(&mut self, key: &str, newkey: &str) -> Result<(), String>;
+    fn rename(&mut self, key: &str, newkey: &str) -> DbResult<()>;
     
     /// Rename key if newkey doesn't exist
     fn renamenx(&mut self, key: &str, newkey: &str) -> bool;
@@ -53,6 +117,10 @@ pub trait GenericOps {
     
     /// Flush all keys
     fn flushdb(&mut self);
+
+    /// Flush all keys, freeing the old keyspace on a background task
+    /// (FLUSHDB ASYNC). Soft-deleted keys still go to the recycle bin.
+    fn flushdb_async(&mut self);
     
     /// Get a random key
     fn randomkey(&self) -> Option<String>;
@@ -60,10 +128,12 @@ pub trait GenericOps {
     /// Copy a key to another
     fn copy(&mut self, src: &str, dst: &str, replace: bool) -> bool;
     
-    /// Delete keys asynchronously (UNLINK).
-    /// Note: In Redis, UNLINK performs deletion in a background thread.
-    /// In HexagonDB single-threaded mode, this behaves identically to DEL.
-    /// For true async deletion, use the async runtime variant.
+    /// Store a deserialized entry under a key (RESTORE).
+    /// Returns false if the key exists and `replace` is not set.
+    fn restore(&mut self, key: &str, entry: Entry, replace: bool) -> bool;
+    
+    /// Delete keys, freeing large values on a background task (UNLINK).
+    /// Unlike DEL, unlinked keys skip the recycle bin.
     fn unlink(&mut self, keys: Vec<&str>) -> usize;
     
     /// Touch keys (update access time)
@@ -72,10 +142,25 @@ pub trait GenericOps {
 
 impl GenericOps for DB {
     fn check_expiration(&mut self, key: &str) -> bool {
-        if let Some(entry) = self.items.get(key) {
+        if let Some(entry) = self.items.get_mut(key) {
+            let now = Instant::now();
             if let Some(expires_at) = entry.expires_at {
-                if Instant::now() >= expires_at {
-                    self.items.remove(key);
+                if now >= expires_at {
+                    if let Some(old) = self.items.remove(key) {
+                        self.notify_expire(key, &old);
+                    }
+                    self.expired_keys.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+            }
+            // Lazily drop expired hash fields; a hash left empty goes away
+            if entry.purge_expired_fields(now) > 0 {
+                let emptied = matches!(&entry.value, DataType::Hash(hash) if hash.is_empty());
+                self.increment_changes();
+                if emptied {
+                    if let Some(old) = self.items.remove(key) {
+                        self.notify_expire(key, &old);
+                    }
                     return false;
                 }
             }
@@ -94,7 +179,17 @@ impl GenericOps for DB {
     }
 
     fn del(&mut self, key: &str) -> bool {
-        if self.items.remove(key).is_some() {
+        // An expired key is already gone and does not count as deleted
+        if !self.check_expiration(key) {
+            return false;
+        }
+        if let Some(entry) = self.items.remove(key) {
+            self.notify_delete(key, &entry);
+            if self.recycle_bin.is_enabled() {
+                self.recycle_bin.bury(key.to_string(), entry);
+            } else {
+                lazyfree::free_entry(entry);
+            }
             self.increment_changes();
             true
         } else {
@@ -102,20 +197,29 @@ impl GenericOps for DB {
         }
     }
 
-    fn type_of(&self, key: &str) -> Option<String> {
-        self.items.get(key).map(|entry| {
-            match &entry.value {
-                DataType::String(_) => "string".to_string(),
-                DataType::List(_) => "list".to_string(),
-                DataType::Hash(_) => "hash".to_string(),
-                DataType::Set(_) => "set".to_string(),
-                DataType::ZSet(_) => "zset".to_string(),
-                DataType::Stream(_) => "stream".to_string(),
-                DataType::Bitmap(_) => "string".to_string(), // Bitmap is stored as string in Redis
-                DataType::Geo(_) => "zset".to_string(), // Geo uses zset internally
-                DataType::HyperLogLog(_) => "string".to_string(),
+    fn undelete(&mut self, key: &str) -> bool {
+        if !self.check_expiration(key) || self.items.contains_key(key) {
+            return false;
+        }
+
+        match self.recycle_bin.restore(key) {
+            Some(entry) => {
+                self.items.insert(key.to_string(), entry);
+                self.track_entry(key);
+                // Restored key may have been deleted with a TTL that has since passed
+                if !self.check_expiration(key) {
+                    return false;
+                }
+                self.increment_changes();
+                self.notify_set(key);
+                true
             }
-        })
+            None => false,
+        }
+    }
+
+    fn type_of(&self, key: &str) -> Option<String> {
+        self.items.get(key).map(|entry| entry.value.type_name().to_string())
     }
 
     fn expire(&mut self, key: &str, seconds: u64) -> bool {
@@ -139,12 +243,53 @@ impl GenericOps for DB {
             let secs = timestamp - now;
             self.expire(key, secs)
         } else {
-            // Timestamp in the past - delete the key
-            self.del(key);
+            // Timestamp in the past - delete the key (expiry bypasses the recycle bin)
+            if let Some(old) = self.items.remove(key) {
+                self.notify_delete(key, &old);
+                self.increment_changes();
+            }
             true
         }
     }
 
+    fn pexpire(&mut self, key: &str, millis: u64) -> bool {
+        match Instant::now().checked_add(Duration::from_millis(millis)) {
+            Some(at) => self.expire_with(key, at, ExpireCondition::Always),
+            None => false,
+        }
+    }
+
+    fn pexpireat(&mut self, key: &str, timestamp_ms: u64) -> bool {
+        match instant_from_unix_millis(timestamp_ms) {
+            Some(at) => self.expire_with(key, at, ExpireCondition::Always),
+            None => false,
+        }
+    }
+
+    fn expire_with(&mut self, key: &str, at: Instant, condition: ExpireCondition) -> bool {
+        if !self.check_expiration(key) {
+            return false;
+        }
+        let entry = match self.items.get_mut(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if !condition.allows(entry.expires_at, at) {
+            return false;
+        }
+
+        if at <= Instant::now() {
+            // Already expired - delete the key (expiry bypasses the recycle bin)
+            if let Some(old) = self.items.remove(key) {
+                self.notify_delete(key, &old);
+            }
+        } else {
+            entry.expires_at = Some(at);
+        }
+        self.increment_changes();
+        true
+    }
+
     fn ttl(&mut self, key: &str) -> i64 {
         if !self.check_expiration(key) {
             return -2; // Key doesn't exist
@@ -179,6 +324,27 @@ impl GenericOps for DB {
         -2
     }
 
+    fn expiretime(&mut self, key: &str) -> i64 {
+        match self.pexpiretime(key) {
+            ms if ms >= 0 => ms / 1000,
+            code => code,
+        }
+    }
+
+    fn pexpiretime(&mut self, key: &str) -> i64 {
+        if !self.check_expiration(key) {
+            return -2;
+        }
+
+        match self.items.get(key) {
+            Some(entry) => match entry.expires_at {
+                Some(at) => unix_millis_from_instant(at) as i64,
+                None => -1,
+            },
+            None => -2,
+        }
+    }
+
     fn persist(&mut self, key: &str) -> bool {
         if let Some(entry) = self.items.get_mut(key) {
             if entry.expires_at.is_some() {
@@ -195,21 +361,13 @@ impl GenericOps for DB {
             return self.items.keys().cloned().collect();
         }
 
-        let _regex_pattern = pattern
-            .replace("*", ".*")
-            .replace("?", ".")
-            .replace("[", "\\[")
-            .replace("]", "\\]");
+        if !pattern.contains('*') && !pattern.contains('?') {
+            return self.items.get_key_value(pattern).map(|(k, _)| k.clone()).into_iter().collect();
+        }
 
         self.items
             .keys()
-            .filter(|key| {
-                if pattern.contains('*') || pattern.contains('?') {
-                    glob_match(pattern, key)
-                } else {
-                    key.as_str() == pattern
-                }
-            })
+            .filter(|key| glob_match(pattern, key))
             .cloned()
             .collect()
     }
@@ -250,13 +408,16 @@ impl GenericOps for DB {
         (next_cursor, result)
     }
 
-    fn rename(&mut self, key: &str, newkey: &str) -> Result<(), String> {
+    fn rename(&mut self, key: &str, newkey: &str) -> DbResult<()> {
         if let Some(entry) = self.items.remove(key) {
+            self.notify_delete(key, &entry);
             self.items.insert(newkey.to_string(), entry);
+            self.track_entry(newkey);
             self.increment_changes();
+            self.notify_set(newkey);
             Ok(())
         } else {
-            Err("ERR no such key".to_string())
+            Err(DbError::NoSuchKey)
         }
     }
 
@@ -272,7 +433,30 @@ impl GenericOps for DB {
     }
 
     fn flushdb(&mut self) {
-        self.items.clear();
+        let items = self.items.take();
+        if !self.hooks.is_empty() {
+            for (key, entry) in &items {
+                self.notify_delete(key, entry);
+            }
+        }
+        if self.recycle_bin.is_enabled() {
+            self.recycle_bin.bury_many(items);
+        }
+        self.increment_changes();
+    }
+
+    fn flushdb_async(&mut self) {
+        if self.recycle_bin.is_enabled() {
+            self.flushdb();
+            return;
+        }
+        let items = self.items.take();
+        if !self.hooks.is_empty() {
+            for (key, entry) in &items {
+                self.notify_delete(key, entry);
+            }
+        }
+        lazyfree::free(items);
         self.increment_changes();
     }
 
@@ -290,19 +474,40 @@ impl GenericOps for DB {
             let new_entry = Entry {
                 value: entry.value.clone(),
                 expires_at: entry.expires_at,
+                field_expires: entry.field_expires.clone(),
             };
             self.items.insert(dst.to_string(), new_entry);
+            self.track_entry(dst);
             self.increment_changes();
+            self.notify_set(dst);
             true
         } else {
             false
         }
     }
 
+    fn restore(&mut self, key: &str, entry: Entry, replace: bool) -> bool {
+        if !replace && self.check_expiration(key) && self.items.contains_key(key) {
+            return false;
+        }
+
+        self.items.insert(key.to_string(), entry);
+        self.track_entry(key);
+        self.increment_changes();
+        self.notify_set(key);
+        true
+    }
+
     fn unlink(&mut self, keys: Vec<&str>) -> usize {
         let mut count = 0;
         for key in keys {
-            if self.items.remove(key).is_some() {
+            // An expired key is already gone and does not count as unlinked
+            if !self.check_expiration(key) {
+                continue;
+            }
+            if let Some(old) = self.items.remove(key) {
+                self.notify_delete(key, &old);
+                lazyfree::free_entry(old);
                 count += 1;
             }
         }
@@ -317,8 +522,34 @@ impl GenericOps for DB {
     }
 }
 
+/// Convert a Unix timestamp in milliseconds to an `Instant`.
+/// Timestamps in the past map to now. Returns None if out of range.
+pub fn instant_from_unix_millis(timestamp_ms: u64) -> Option<Instant> {
+    let now_ms = unix_millis_now();
+    Instant::now().checked_add(Duration::from_millis(timestamp_ms.saturating_sub(now_ms)))
+}
+
+/// Convert an `Instant` to a Unix timestamp in milliseconds
+pub fn unix_millis_from_instant(at: Instant) -> u64 {
+    let now = Instant::now();
+    let now_ms = unix_millis_now();
+    if at >= now {
+        now_ms + (at - now).as_millis() as u64
+    } else {
+        now_ms.saturating_sub((now - at).as_millis() as u64)
+    }
+}
+
+/// Current Unix time in milliseconds
+pub(crate) fn unix_millis_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
 /// Simple glob pattern matching
-fn glob_match(pattern: &str, text: &str) -> bool {
+pub fn glob_match(pattern: &str, text: &str) -> bool {
     let mut pattern_chars = pattern.chars().peekable();
     let mut text_chars = text.chars().peekable();
 
@@ -372,4 +603,55 @@ mod tests {
         assert!(glob_match("user:*", "user:123"));
         assert!(!glob_match("foo", "bar"));
     }
+
+    #[test]
+    fn test_undelete() {
+        use crate::db::ops::string::StringOps;
+
+        let mut db = DB::new();
+        db.set("key".to_string(), "value".to_string());
+        db.del("key");
+        // Soft-delete is off by default
+        assert!(!db.undelete("key"));
+
+        db.recycle_bin.set_retention(Some(Duration::from_secs(60)));
+        db.set("key".to_string(), "value".to_string());
+        db.del("key");
+        assert!(!db.exists("key"));
+        assert!(db.undelete("key"));
+        assert_eq!(db.get("key").unwrap(), Some("value".to_string()));
+
+        db.flushdb();
+        assert_eq!(db.dbsize(), 0);
+        assert!(db.undelete("key"));
+        assert!(db.exists("key"));
+    }
+
+    #[test]
+    fn test_expire_conditions_and_times() {
+        use crate::db::ops::string::StringOps;
+
+        let mut db = DB::new();
+        db.set("key".to_string(), "value".to_string());
+        assert_eq!(db.pexpiretime("key"), -1);
+        assert_eq!(db.pexpiretime("missing"), -2);
+
+        let later = Instant::now() + Duration::from_secs(100);
+        assert!(!db.expire_with("key", later, ExpireCondition::Xx));
+        assert!(!db.expire_with("key", later, ExpireCondition::Gt));
+        assert!(db.expire_with("key", later, ExpireCondition::Nx));
+        assert!(!db.expire_with("key", later, ExpireCondition::Nx));
+        assert!(!db.expire_with("key", later + Duration::from_secs(1), ExpireCondition::Lt));
+        assert!(db.expire_with("key", later - Duration::from_secs(50), ExpireCondition::Lt));
+        assert!((45_000..=50_000).contains(&db.pttl("key")));
+
+        assert!(db.pexpireat("key", unix_millis_now() + 10_000));
+        let at = db.expiretime("key");
+        assert!((at - (unix_millis_now() / 1000 + 10) as i64).abs() <= 1);
+
+        // A timestamp in the past deletes the key
+        assert!(db.pexpireat("key", 1));
+        assert!(!db.exists("key"));
+        assert!(!db.pexpire("key", 1000));
+    }
 }
@@ -3,32 +3,32 @@
 //! Redis-compatible geo operations using geohash encoding.
 
 use crate::db::core::DB;
-use crate::db::ops::generic::GenericOps;
-use crate::db::types::{DataType, Entry, GeoData};
+use crate::db::error::{DbError, DbResult};
+use crate::db::types::{DataType, GeoData};
 use std::sync::atomic::Ordering;
 
 /// Geo operations trait
 pub trait GeoOps {
     /// Add geo locations (GEOADD)
-    fn geoadd(&mut self, key: String, locations: Vec<(f64, f64, String)>) -> usize;
+    fn geoadd(&mut self, key: String, locations: Vec<(f64, f64, String)>) -> DbResult<usize>;
     
     /// Get position of members (GEOPOS)
-    fn geopos(&mut self, key: String, members: Vec<String>) -> Vec<Option<(f64, f64)>>;
+    fn geopos(&mut self, key: &str, members: Vec<String>) -> DbResult<Vec<Option<(f64, f64)>>>;
     
     /// Get distance between two members (GEODIST)
-    fn geodist(&mut self, key: String, member1: String, member2: String, unit: GeoUnit) -> Option<f64>;
+    fn geodist(&mut self, key: &str, member1: &str, member2: &str, unit: GeoUnit) -> DbResult<Option<f64>>;
     
     /// Get geohash of members (GEOHASH)
-    fn geohash(&mut self, key: String, members: Vec<String>) -> Vec<Option<String>>;
+    fn geohash(&mut self, key: &str, members: Vec<String>) -> DbResult<Vec<Option<String>>>;
     
     /// Search by radius from member (GEORADIUSBYMEMBER)
-    fn georadiusbymember(&mut self, key: String, member: String, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult>;
+    fn georadiusbymember(&mut self, key: &str, member: &str, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> DbResult<Vec<GeoResult>>;
     
     /// Search by radius from coordinates (GEORADIUS)
-    fn georadius(&mut self, key: String, lon: f64, lat: f64, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult>;
+    fn georadius(&mut self, key: &str, lon: f64, lat: f64, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> DbResult<Vec<GeoResult>>;
     
     /// Search within box (GEOSEARCH)
-    fn geosearch(&mut self, key: String, from: GeoFrom, by: GeoBy, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult>;
+    fn geosearch(&mut self, key: &str, from: GeoFrom, by: GeoBy, count: Option<usize>, sort: Option<GeoSort>) -> DbResult<Vec<GeoResult>>;
 }
 
 /// Distance unit
@@ -90,228 +90,187 @@ pub struct GeoResult {
     pub hash: Option<String>,
 }
 
+/// Geo sets are kept apart from sorted sets
+fn is_geo(value: &DataType) -> bool {
+    matches!(value, DataType::Geo(_))
+}
+
 impl GeoOps for DB {
-    fn geoadd(&mut self, key: String, locations: Vec<(f64, f64, String)>) -> usize {
-        self.check_expiration(&key);
-
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
-            value: DataType::Geo(GeoData::new()),
-            expires_at: None,
-        });
-
-        match &mut entry.value {
-            DataType::Geo(geo) => {
-                let mut added = 0;
-                for (lon, lat, member) in locations {
-                    if geo.add(member, lon, lat) {
-                        added += 1;
-                    }
-                }
-                if added > 0 {
-                    self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-                }
-                added
+    fn geoadd(&mut self, key: String, locations: Vec<(f64, f64, String)>) -> DbResult<usize> {
+        let entry = self.typed_entry_mut(&key, is_geo, || DataType::Geo(GeoData::new()))?;
+        let geo = match &mut entry.value {
+            DataType::Geo(geo) => geo,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let mut added = 0;
+        for (lon, lat, member) in locations {
+            if geo.add(member, lon, lat) {
+                added += 1;
             }
-            _ => 0,
-        }
-    }
-
-    fn geopos(&mut self, key: String, members: Vec<String>) -> Vec<Option<(f64, f64)>> {
-        if !self.check_expiration(&key) {
-            return vec![None; members.len()];
         }
-
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Geo(geo) = &entry.value {
-                return members.iter().map(|m| {
-                    geo.locations.get(m).map(|loc| (loc.longitude, loc.latitude))
-                }).collect();
-            }
+        if added > 0 {
+            self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+            self.notify_set(&key);
         }
-        vec![None; members.len()]
+        Ok(added)
     }
 
-    fn geodist(&mut self, key: String, member1: String, member2: String, unit: GeoUnit) -> Option<f64> {
-        if !self.check_expiration(&key) {
-            return None;
-        }
-
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Geo(geo) = &entry.value {
-                if let Some(distance_m) = geo.distance(&member1, &member2) {
-                    return Some(unit.from_meters(distance_m));
-                }
-            }
-        }
-        None
+    fn geopos(&mut self, key: &str, members: Vec<String>) -> DbResult<Vec<Option<(f64, f64)>>> {
+        Ok(match self.typed_value(key, is_geo)? {
+            Some(DataType::Geo(geo)) => members.iter().map(|m| {
+                geo.locations.get(m).map(|loc| (loc.longitude, loc.latitude))
+            }).collect(),
+            _ => vec![None; members.len()],
+        })
     }
 
-    fn geohash(&mut self, key: String, members: Vec<String>) -> Vec<Option<String>> {
-        if !self.check_expiration(&key) {
-            return vec![None; members.len()];
-        }
+    fn geodist(&mut self, key: &str, member1: &str, member2: &str, unit: GeoUnit) -> DbResult<Option<f64>> {
+        Ok(match self.typed_value(key, is_geo)? {
+            Some(DataType::Geo(geo)) => geo
+                .distance(member1, member2)
+                .map(|distance_m| unit.from_meters(distance_m)),
+            _ => None,
+        })
+    }
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Geo(geo) = &entry.value {
-                return members.iter().map(|m| {
-                    geo.locations.get(m).map(|loc| {
-                        geohash::encode(
-                            geohash::Coord { x: loc.longitude, y: loc.latitude },
-                            11
-                        ).unwrap_or_default()
-                    })
-                }).collect();
-            }
-        }
-        vec![None; members.len()]
+    fn geohash(&mut self, key: &str, members: Vec<String>) -> DbResult<Vec<Option<String>>> {
+        Ok(match self.typed_value(key, is_geo)? {
+            Some(DataType::Geo(geo)) => members.iter().map(|m| {
+                geo.locations.get(m).map(|loc| {
+                    geohash::encode(
+                        geohash::Coord { x: loc.longitude, y: loc.latitude },
+                        11
+                    ).unwrap_or_default()
+                })
+            }).collect(),
+            _ => vec![None; members.len()],
+        })
     }
 
-    fn georadiusbymember(&mut self, key: String, member: String, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
-        if !self.check_expiration(&key) {
-            return vec![];
-        }
+    fn georadiusbymember(&mut self, key: &str, member: &str, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> DbResult<Vec<GeoResult>> {
+        Ok(match self.typed_value(key, is_geo)? {
+            Some(DataType::Geo(geo)) => match geo.locations.get(member) {
+                Some(center) => search_radius(geo, center.longitude, center.latitude, radius, unit, count, sort),
+                None => vec![],
+            },
+            _ => vec![],
+        })
+    }
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Geo(geo) = &entry.value {
-                if let Some(center) = geo.locations.get(&member) {
-                    return self.search_radius(geo, center.longitude, center.latitude, radius, unit, count, sort);
-                }
-            }
-        }
-        vec![]
+    fn georadius(&mut self, key: &str, lon: f64, lat: f64, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> DbResult<Vec<GeoResult>> {
+        Ok(match self.typed_value(key, is_geo)? {
+            Some(DataType::Geo(geo)) => search_radius(geo, lon, lat, radius, unit, count, sort),
+            _ => vec![],
+        })
     }
 
-    fn georadius(&mut self, key: String, lon: f64, lat: f64, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
-        if !self.check_expiration(&key) {
-            return vec![];
-        }
+    fn geosearch(&mut self, key: &str, from: GeoFrom, by: GeoBy, count: Option<usize>, sort: Option<GeoSort>) -> DbResult<Vec<GeoResult>> {
+        let geo = match self.typed_value(key, is_geo)? {
+            Some(DataType::Geo(geo)) => geo,
+            _ => return Ok(vec![]),
+        };
+        let (lon, lat) = match &from {
+            GeoFrom::LonLat(lon, lat) => (*lon, *lat),
+            GeoFrom::Member(m) => match geo.locations.get(m) {
+                Some(loc) => (loc.longitude, loc.latitude),
+                None => return Ok(vec![]),
+            },
+        };
+
+        Ok(match by {
+            GeoBy::Radius(radius, unit) => search_radius(geo, lon, lat, radius, unit, count, sort),
+            GeoBy::Box(width, height, unit) => search_box(geo, lon, lat, width, height, unit, count, sort),
+        })
+    }
+}
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Geo(geo) = &entry.value {
-                return self.search_radius(geo, lon, lat, radius, unit, count, sort);
+/// Members within `radius` of a point
+fn search_radius(geo: &GeoData, lon: f64, lat: f64, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
+    let radius_m = unit.to_meters(radius);
+    
+    let mut results: Vec<GeoResult> = geo.locations.iter()
+        .filter_map(|(name, loc)| {
+            let dist = haversine_distance(lat, lon, loc.latitude, loc.longitude);
+            if dist <= radius_m {
+                Some(GeoResult {
+                    member: name.clone(),
+                    distance: Some(unit.from_meters(dist)),
+                    coordinates: Some((loc.longitude, loc.latitude)),
+                    hash: Some(geohash::encode(
+                        geohash::Coord { x: loc.longitude, y: loc.latitude },
+                        11
+                    ).unwrap_or_default()),
+                })
+            } else {
+                None
             }
+        })
+        .collect();
+
+    // Sort
+    if let Some(order) = sort {
+        match order {
+            GeoSort::Asc => results.sort_by(|a, b| {
+                a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            GeoSort::Desc => results.sort_by(|a, b| {
+                b.distance.partial_cmp(&a.distance).unwrap_or(std::cmp::Ordering::Equal)
+            }),
         }
-        vec![]
     }
 
-    fn geosearch(&mut self, key: String, from: GeoFrom, by: GeoBy, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
-        if !self.check_expiration(&key) {
-            return vec![];
-        }
-
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Geo(geo) = &entry.value {
-                let (lon, lat) = match &from {
-                    GeoFrom::LonLat(lon, lat) => (*lon, *lat),
-                    GeoFrom::Member(m) => {
-                        if let Some(loc) = geo.locations.get(m) {
-                            (loc.longitude, loc.latitude)
-                        } else {
-                            return vec![];
-                        }
-                    }
-                };
-
-                match by {
-                    GeoBy::Radius(radius, unit) => {
-                        return self.search_radius(geo, lon, lat, radius, unit, count, sort);
-                    }
-                    GeoBy::Box(width, height, unit) => {
-                        return self.search_box(geo, lon, lat, width, height, unit, count, sort);
-                    }
-                }
-            }
-        }
-        vec![]
+    if let Some(n) = count {
+        results.truncate(n);
     }
+
+    results
 }
 
-impl DB {
-    fn search_radius(&self, geo: &GeoData, lon: f64, lat: f64, radius: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
-        let radius_m = unit.to_meters(radius);
-        
-        let mut results: Vec<GeoResult> = geo.locations.iter()
-            .filter_map(|(name, loc)| {
+/// Members within a `width` x `height` box centred on a point
+fn search_box(geo: &GeoData, lon: f64, lat: f64, width: f64, height: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
+    let half_width_m = unit.to_meters(width) / 2.0;
+    let half_height_m = unit.to_meters(height) / 2.0;
+    
+    // Approximate lat/lon deltas (not perfectly accurate but good enough)
+    let lat_delta = half_height_m / 111320.0;
+    let lon_delta = half_width_m / (111320.0 * lat.to_radians().cos());
+
+    let mut results: Vec<GeoResult> = geo.locations.iter()
+        .filter_map(|(name, loc)| {
+            if (loc.latitude - lat).abs() <= lat_delta && (loc.longitude - lon).abs() <= lon_delta {
                 let dist = haversine_distance(lat, lon, loc.latitude, loc.longitude);
-                if dist <= radius_m {
-                    Some(GeoResult {
-                        member: name.clone(),
-                        distance: Some(unit.from_meters(dist)),
-                        coordinates: Some((loc.longitude, loc.latitude)),
-                        hash: Some(geohash::encode(
-                            geohash::Coord { x: loc.longitude, y: loc.latitude },
-                            11
-                        ).unwrap_or_default()),
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        // Sort
-        if let Some(order) = sort {
-            match order {
-                GeoSort::Asc => results.sort_by(|a, b| {
-                    a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal)
-                }),
-                GeoSort::Desc => results.sort_by(|a, b| {
-                    b.distance.partial_cmp(&a.distance).unwrap_or(std::cmp::Ordering::Equal)
-                }),
+                Some(GeoResult {
+                    member: name.clone(),
+                    distance: Some(unit.from_meters(dist)),
+                    coordinates: Some((loc.longitude, loc.latitude)),
+                    hash: Some(geohash::encode(
+                        geohash::Coord { x: loc.longitude, y: loc.latitude },
+                        11
+                    ).unwrap_or_default()),
+                })
+            } else {
+                None
             }
+        })
+        .collect();
+
+    if let Some(order) = sort {
+        match order {
+            GeoSort::Asc => results.sort_by(|a, b| {
+                a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal)
+            }),
+            GeoSort::Desc => results.sort_by(|a, b| {
+                b.distance.partial_cmp(&a.distance).unwrap_or(std::cmp::Ordering::Equal)
+            }),
         }
-
-        if let Some(n) = count {
-            results.truncate(n);
-        }
-
-        results
     }
 
-    fn search_box(&self, geo: &GeoData, lon: f64, lat: f64, width: f64, height: f64, unit: GeoUnit, count: Option<usize>, sort: Option<GeoSort>) -> Vec<GeoResult> {
-        let half_width_m = unit.to_meters(width) / 2.0;
-        let half_height_m = unit.to_meters(height) / 2.0;
-        
-        // Approximate lat/lon deltas (not perfectly accurate but good enough)
-        let lat_delta = half_height_m / 111320.0;
-        let lon_delta = half_width_m / (111320.0 * lat.to_radians().cos());
-
-        let mut results: Vec<GeoResult> = geo.locations.iter()
-            .filter_map(|(name, loc)| {
-                if (loc.latitude - lat).abs() <= lat_delta && (loc.longitude - lon).abs() <= lon_delta {
-                    let dist = haversine_distance(lat, lon, loc.latitude, loc.longitude);
-                    Some(GeoResult {
-                        member: name.clone(),
-                        distance: Some(unit.from_meters(dist)),
-                        coordinates: Some((loc.longitude, loc.latitude)),
-                        hash: Some(geohash::encode(
-                            geohash::Coord { x: loc.longitude, y: loc.latitude },
-                            11
-                        ).unwrap_or_default()),
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        if let Some(order) = sort {
-            match order {
-                GeoSort::Asc => results.sort_by(|a, b| {
-                    a.distance.partial_cmp(&b.distance).unwrap_or(std::cmp::Ordering::Equal)
-                }),
-                GeoSort::Desc => results.sort_by(|a, b| {
-                    b.distance.partial_cmp(&a.distance).unwrap_or(std::cmp::Ordering::Equal)
-                }),
-            }
-        }
-
-        if let Some(n) = count {
-            results.truncate(n);
-        }
-
-        results
+    if let Some(n) = count {
+        results.truncate(n);
     }
+
+    results
 }
 
 /// Haversine distance calculation
@@ -342,9 +301,9 @@ mod tests {
             (13.361389, 38.115556, "Palermo".to_string()),
             (15.087269, 37.502669, "Catania".to_string()),
         ]);
-        assert_eq!(added, 2);
+        assert_eq!(added, Ok(2));
 
-        let positions = db.geopos("sicily".to_string(), vec!["Palermo".to_string()]);
+        let positions = db.geopos("sicily", vec!["Palermo".to_string()]).unwrap();
         assert!(positions[0].is_some());
         let (lon, lat) = positions[0].unwrap();
         assert!((lon - 13.361389).abs() < 0.0001);
@@ -358,15 +317,29 @@ mod tests {
         db.geoadd("sicily".to_string(), vec![
             (13.361389, 38.115556, "Palermo".to_string()),
             (15.087269, 37.502669, "Catania".to_string()),
-        ]);
+        ]).unwrap();
 
-        let dist = db.geodist("sicily".to_string(), 
-            "Palermo".to_string(), 
-            "Catania".to_string(), 
-            GeoUnit::Kilometers);
+        let dist = db.geodist("sicily", 
+            "Palermo", 
+            "Catania", 
+            GeoUnit::Kilometers).unwrap();
         
         assert!(dist.is_some());
         let d = dist.unwrap();
         assert!(d > 160.0 && d < 170.0); // ~166km
     }
+
+    #[test]
+    fn test_wrong_type() {
+        use crate::db::ZSetOps;
+        let mut db = DB::new();
+        db.zadd("scores".to_string(), vec![(1.0, "a".to_string())]).unwrap();
+
+        let palermo = vec![(13.361389, 38.115556, "Palermo".to_string())];
+        assert_eq!(db.geoadd("scores".to_string(), palermo), Err(DbError::WrongType));
+        assert!(db.geopos("scores", vec!["a".to_string()]).is_err());
+        assert!(db.geodist("scores", "a", "b", GeoUnit::Meters).is_err());
+        assert!(db.georadius("scores", 13.0, 38.0, 1.0, GeoUnit::Meters, None, None).is_err());
+        assert_eq!(db.zcard("scores"), 1);
+    }
 }
@@ -2,141 +2,237 @@
 //!
 //! Operations for the hash data type (field->value mapping).
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
-use crate::db::ops::generic::GenericOps;
+use crate::db::ops::check_random_count;
+use crate::db::ops::cursor::scan_page;
+use crate::db::ops::generic::{glob_match, ExpireCondition, GenericOps};
 use crate::db::types::{DataType, Entry};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use std::collections::HashMap;
+use std::time::Instant;
+
+/// Parsed `[NX|XX|GT|LT] FIELDS numfields field [field ...]` arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldTtlArgs {
+    pub condition: ExpireCondition,
+    pub fields: Vec<String>,
+}
+
+impl FieldTtlArgs {
+    /// Parse the field list of HEXPIRE-style commands. The condition flag is
+    /// only accepted when `allow_condition` is set (HEXPIRE/HPEXPIRE).
+    pub fn parse(args: &[String], allow_condition: bool) -> DbResult<Self> {
+        let mut condition = ExpireCondition::Always;
+        let mut rest = args;
+        if allow_condition {
+            if let Some(c) = rest.first().and_then(|flag| ExpireCondition::parse(flag)) {
+                condition = c;
+                rest = &rest[1..];
+            }
+        }
+
+        match rest.first() {
+            Some(kw) if kw.eq_ignore_ascii_case("FIELDS") => {}
+            _ => return Err(DbError::Syntax("mandatory argument FIELDS is missing or not at the right position".to_string())),
+        }
+        let numfields = rest
+            .get(1)
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(|| DbError::Syntax("Parameter `numFields` should be greater than 0".to_string()))?;
+        let fields = &rest[2..];
+        if fields.len() != numfields {
+            return Err(DbError::Syntax("The `numfields` parameter must match the number of arguments".to_string()));
+        }
+
+        Ok(FieldTtlArgs {
+            condition,
+            fields: fields.to_vec(),
+        })
+    }
+}
+
+/// Fields read by SESSION.GET, with None for fields that are not set
+pub type SessionFields = Vec<(String, Option<String>)>;
 
 /// Hash operations trait
 pub trait HashOps {
     /// Set hash field value
-    fn hset(&mut self, key: String, field: String, value: String) -> Result<usize, String>;
+    fn hset(&mut self, key: String, field: String, value: String) -> DbResult<usize>;
     
+    /// Set multiple hash fields, returning how many fields were newly created
+    fn hset_multi(&mut self, key: String, pairs: Vec<(String, String)>) -> DbResult<usize>;
+
     /// Set multiple hash fields
-    fn hmset(&mut self, key: String, pairs: Vec<(String, String)>) -> Result<(), String>;
+    fn hmset(&mut self, key: String, pairs: Vec<(String, String)>) -> DbResult<()>;
     
     /// Set field only if it doesn't exist
-    fn hsetnx(&mut self, key: String, field: String, value: String) -> bool;
+    fn hsetnx(&mut self, key: String, field: String, value: String) -> DbResult<bool>;
     
     /// Get hash field value
-    fn hget(&mut self, key: String, field: String) -> Result<Option<String>, String>;
+    fn hget(&mut self, key: &str, field: &str) -> DbResult<Option<String>>;
     
     /// Get multiple hash field values
-    fn hmget(&mut self, key: String, fields: Vec<String>) -> Result<Vec<Option<String>>, String>;
+    fn hmget(&mut self, key: &str, fields: Vec<String>) -> DbResult<Vec<Option<String>>>;
     
     /// Get all fields and values
-    fn hgetall(&mut self, key: String) -> Result<Vec<String>, String>;
+    fn hgetall(&mut self, key: &str) -> DbResult<Vec<String>>;
     
     /// Delete hash field
-    fn hdel(&mut self, key: String, field: String) -> Result<usize, String>;
+    fn hdel(&mut self, key: String, field: String) -> DbResult<usize>;
     
     /// Delete multiple hash fields
-    fn hdel_multi(&mut self, key: String, fields: Vec<String>) -> Result<usize, String>;
+    fn hdel_multi(&mut self, key: String, fields: Vec<String>) -> DbResult<usize>;
     
     /// Check if field exists
-    fn hexists(&mut self, key: String, field: String) -> bool;
+    fn hexists(&mut self, key: &str, field: &str) -> bool;
     
     /// Get number of fields
-    fn hlen(&mut self, key: String) -> usize;
+    fn hlen(&mut self, key: &str) -> usize;
     
     /// Get all field names
-    fn hkeys(&mut self, key: String) -> Vec<String>;
+    fn hkeys(&mut self, key: &str) -> Vec<String>;
     
     /// Get all values
-    fn hvals(&mut self, key: String) -> Vec<String>;
+    fn hvals(&mut self, key: &str) -> Vec<String>;
     
     /// Increment field by integer
-    fn hincrby(&mut self, key: String, field: String, delta: i64) -> Result<i64, String>;
+    fn hincrby(&mut self, key: String, field: String, delta: i64) -> DbResult<i64>;
     
     /// Increment field by float
-    fn hincrbyfloat(&mut self, key: String, field: String, delta: f64) -> Result<f64, String>;
+    fn hincrbyfloat(&mut self, key: String, field: String, delta: f64) -> DbResult<f64>;
     
     /// Get field string length
-    fn hstrlen(&mut self, key: String, field: String) -> usize;
+    fn hstrlen(&mut self, key: &str, field: &str) -> usize;
     
-    /// Scan hash fields
-    fn hscan(&self, key: &str, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> (u64, Vec<(String, String)>);
+    /// Get random fields with their values. A negative count may repeat fields
+    /// and must be at least `-MAX_RANDOM_COUNT`.
+    fn hrandfield(&mut self, key: &str, count: i64) -> DbResult<Vec<(String, String)>>;
+
+    /// Expire hash fields at an instant. Per field: -2 no such field, 0 condition
+    /// not met, 1 TTL set, 2 field deleted because the instant is not in the future.
+    fn hexpire(&mut self, key: String, at: Instant, condition: ExpireCondition, fields: Vec<String>) -> DbResult<Vec<i64>>;
+
+    /// Get hash field TTLs in seconds. Per field: -2 no such field, -1 no TTL.
+    fn httl(&mut self, key: &str, fields: Vec<String>) -> DbResult<Vec<i64>>;
+
+    /// Get hash field TTLs in milliseconds. Per field: -2 no such field, -1 no TTL.
+    fn hpttl(&mut self, key: &str, fields: Vec<String>) -> DbResult<Vec<i64>>;
+
+    /// Remove hash field TTLs. Per field: -2 no such field, -1 no TTL, 1 TTL removed.
+    fn hpersist(&mut self, key: String, fields: Vec<String>) -> DbResult<Vec<i64>>;
+
+    /// Scan hash fields in cursor order (see `ops::cursor`). MATCH is
+    /// applied after a page of `count` fields is taken, so pages may be
+    /// short or empty before the scan ends.
+    fn hscan(&mut self, key: &str, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> DbResult<(u64, Vec<(String, String)>)>;
+
+    /// Create a hash from `pairs` that expires at `at`, unless the key
+    /// already exists. Returns true if it was created.
+    fn session_create(&mut self, key: String, pairs: Vec<(String, String)>, at: Instant) -> DbResult<bool>;
+
+    /// Read a hash and move its expiry to `at`. With no `fields`, every
+    /// field is returned. None if the key does not exist.
+    fn session_get(&mut self, key: &str, fields: &[String], at: Instant) -> DbResult<Option<SessionFields>>;
+
+    /// Set `pairs` on an existing hash and move its expiry to `at`.
+    /// Returns false if the key does not exist.
+    fn session_refresh(&mut self, key: String, pairs: Vec<(String, String)>, at: Instant) -> DbResult<bool>;
 }
 
 impl HashOps for DB {
-    fn hset(&mut self, key: String, field: String, value: String) -> Result<usize, String> {
+    fn hset(&mut self, key: String, field: String, value: String) -> DbResult<usize> {
         self.check_expiration(&key);
 
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
+        let entry = self.items.get_or_insert_with(key.clone(), || Entry {
             value: DataType::Hash(HashMap::new()),
             expires_at: None,
+            field_expires: None,
         });
 
         match &mut entry.value {
             DataType::Hash(hash) => {
                 let is_new = !hash.contains_key(&field);
-                hash.insert(field, value);
+                hash.insert(field.clone(), value);
+                // Overwriting a field also drops its TTL
+                entry.clear_field_expire(&field);
                 self.increment_changes();
+                self.notify_set(&key);
                 Ok(if is_new { 1 } else { 0 })
             }
-            _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+            _ => Err(DbError::WrongType),
         }
     }
 
-    fn hmset(&mut self, key: String, pairs: Vec<(String, String)>) -> Result<(), String> {
+    fn hset_multi(&mut self, key: String, pairs: Vec<(String, String)>) -> DbResult<usize> {
+        let mut added = 0;
         for (field, value) in pairs {
-            self.hset(key.clone(), field, value)?;
+            added += self.hset(key.clone(), field, value)?;
         }
-        Ok(())
+        Ok(added)
     }
 
-    fn hsetnx(&mut self, key: String, field: String, value: String) -> bool {
+    fn hmset(&mut self, key: String, pairs: Vec<(String, String)>) -> DbResult<()> {
+        self.hset_multi(key, pairs).map(|_| ())
+    }
+
+    fn hsetnx(&mut self, key: String, field: String, value: String) -> DbResult<bool> {
         self.check_expiration(&key);
 
         if let Some(entry) = self.items.get(&key) {
-            if let DataType::Hash(hash) = &entry.value {
-                if hash.contains_key(&field) {
-                    return false;
+            match &entry.value {
+                DataType::Hash(hash) => {
+                    if hash.contains_key(&field) {
+                        return Ok(false);
+                    }
                 }
+                _ => return Err(DbError::WrongType),
             }
         }
-        
-        self.hset(key, field, value).is_ok()
+
+        self.hset(key, field, value).map(|_| true)
     }
 
-    fn hget(&mut self, key: String, field: String) -> Result<Option<String>, String> {
-        if !self.check_expiration(&key) {
+    fn hget(&mut self, key: &str, field: &str) -> DbResult<Option<String>> {
+        if !self.check_expiration(key) {
             return Ok(None);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
-                DataType::Hash(hash) => Ok(hash.get(&field).cloned()),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                DataType::Hash(hash) => Ok(hash.get(field).cloned()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(None)
         }
     }
 
-    fn hmget(&mut self, key: String, fields: Vec<String>) -> Result<Vec<Option<String>>, String> {
-        if !self.check_expiration(&key) {
+    fn hmget(&mut self, key: &str, fields: Vec<String>) -> DbResult<Vec<Option<String>>> {
+        if !self.check_expiration(key) {
             return Ok(vec![None; fields.len()]);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::Hash(hash) => {
                     Ok(fields.iter().map(|f| hash.get(f).cloned()).collect())
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(vec![None; fields.len()])
         }
     }
 
-    fn hgetall(&mut self, key: String) -> Result<Vec<String>, String> {
-        if !self.check_expiration(&key) {
+    fn hgetall(&mut self, key: &str) -> DbResult<Vec<String>> {
+        if !self.check_expiration(key) {
             return Ok(vec![]);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::Hash(hash) => {
                     let mut result = Vec::with_capacity(hash.len() * 2);
@@ -146,14 +242,14 @@ impl HashOps for DB {
                     }
                     Ok(result)
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(vec![])
         }
     }
 
-    fn hdel(&mut self, key: String, field: String) -> Result<usize, String> {
+    fn hdel(&mut self, key: String, field: String) -> DbResult<usize> {
         if !self.check_expiration(&key) {
             return Ok(0);
         }
@@ -162,20 +258,22 @@ impl HashOps for DB {
             match &mut entry.value {
                 DataType::Hash(hash) => {
                     if hash.remove(&field).is_some() {
+                        entry.clear_field_expire(&field);
                         self.increment_changes();
+                        self.notify_set(&key);
                         Ok(1)
                     } else {
                         Ok(0)
                     }
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(0)
         }
     }
 
-    fn hdel_multi(&mut self, key: String, fields: Vec<String>) -> Result<usize, String> {
+    fn hdel_multi(&mut self, key: String, fields: Vec<String>) -> DbResult<usize> {
         let mut count = 0;
         for field in fields {
             count += self.hdel(key.clone(), field)?;
@@ -183,25 +281,25 @@ impl HashOps for DB {
         Ok(count)
     }
 
-    fn hexists(&mut self, key: String, field: String) -> bool {
-        if !self.check_expiration(&key) {
+    fn hexists(&mut self, key: &str, field: &str) -> bool {
+        if !self.check_expiration(key) {
             return false;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::Hash(hash) = &entry.value {
-                return hash.contains_key(&field);
+                return hash.contains_key(field);
             }
         }
         false
     }
 
-    fn hlen(&mut self, key: String) -> usize {
-        if !self.check_expiration(&key) {
+    fn hlen(&mut self, key: &str) -> usize {
+        if !self.check_expiration(key) {
             return 0;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::Hash(hash) = &entry.value {
                 return hash.len();
             }
@@ -209,12 +307,12 @@ impl HashOps for DB {
         0
     }
 
-    fn hkeys(&mut self, key: String) -> Vec<String> {
-        if !self.check_expiration(&key) {
+    fn hkeys(&mut self, key: &str) -> Vec<String> {
+        if !self.check_expiration(key) {
             return vec![];
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::Hash(hash) = &entry.value {
                 return hash.keys().cloned().collect();
             }
@@ -222,12 +320,12 @@ impl HashOps for DB {
         vec![]
     }
 
-    fn hvals(&mut self, key: String) -> Vec<String> {
-        if !self.check_expiration(&key) {
+    fn hvals(&mut self, key: &str) -> Vec<String> {
+        if !self.check_expiration(key) {
             return vec![];
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::Hash(hash) = &entry.value {
                 return hash.values().cloned().collect();
             }
@@ -235,90 +333,319 @@ impl HashOps for DB {
         vec![]
     }
 
-    fn hincrby(&mut self, key: String, field: String, delta: i64) -> Result<i64, String> {
+    fn hincrby(&mut self, key: String, field: String, delta: i64) -> DbResult<i64> {
         self.check_expiration(&key);
 
-        let current = self.hget(key.clone(), field.clone())?.unwrap_or_else(|| "0".to_string());
+        let current = self.hget(&key, &field)?.unwrap_or_else(|| "0".to_string());
         
         match current.parse::<i64>() {
             Ok(num) => {
                 let new_val = num.checked_add(delta)
-                    .ok_or_else(|| "ERR increment would overflow".to_string())?;
+                    .ok_or_else(|| DbError::InvalidValue("increment would overflow".to_string()))?;
                 self.hset(key, field, new_val.to_string())?;
                 Ok(new_val)
             }
-            Err(_) => Err("ERR hash value is not an integer".to_string()),
+            Err(_) => Err(DbError::InvalidValue("hash value is not an integer".to_string())),
         }
     }
 
-    fn hincrbyfloat(&mut self, key: String, field: String, delta: f64) -> Result<f64, String> {
+    fn hincrbyfloat(&mut self, key: String, field: String, delta: f64) -> DbResult<f64> {
         self.check_expiration(&key);
 
-        let current = self.hget(key.clone(), field.clone())?.unwrap_or_else(|| "0".to_string());
+        let current = self.hget(&key, &field)?.unwrap_or_else(|| "0".to_string());
         
         match current.parse::<f64>() {
             Ok(num) => {
                 let new_val = num + delta;
                 if new_val.is_nan() || new_val.is_infinite() {
-                    return Err("ERR increment would produce NaN or Infinity".to_string());
+                    return Err(DbError::InvalidValue("increment would produce NaN or Infinity".to_string()));
                 }
                 self.hset(key, field, format!("{}", new_val))?;
                 Ok(new_val)
             }
-            Err(_) => Err("ERR hash value is not a float".to_string()),
+            Err(_) => Err(DbError::InvalidValue("hash value is not a float".to_string())),
         }
     }
 
-    fn hstrlen(&mut self, key: String, field: String) -> usize {
+    fn hstrlen(&mut self, key: &str, field: &str) -> usize {
         self.hget(key, field).ok().flatten().map(|s| s.len()).unwrap_or(0)
     }
 
-    fn hscan(&self, key: &str, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> (u64, Vec<(String, String)>) {
-        let count = count.unwrap_or(10);
+    fn hrandfield(&mut self, key: &str, count: i64) -> DbResult<Vec<(String, String)>> {
+        check_random_count(count)?;
+        if !self.check_expiration(key) {
+            return Ok(vec![]);
+        }
+
+        let pairs: Vec<(String, String)> = match self.items.get(key) {
+            Some(entry) => match &entry.value {
+                DataType::Hash(hash) => hash.iter().map(|(f, v)| (f.clone(), v.clone())).collect(),
+                _ => return Err(DbError::WrongType),
+            },
+            None => return Ok(vec![]),
+        };
 
-        if let Some(entry) = self.items.get(key) {
-            if let DataType::Hash(hash) = &entry.value {
-                let pairs: Vec<(&String, &String)> = hash.iter().collect();
-                let total = pairs.len();
+        if pairs.is_empty() || count == 0 {
+            return Ok(vec![]);
+        }
 
-                if total == 0 {
-                    return (0, vec![]);
-                }
+        let mut rng = rand::thread_rng();
+        if count > 0 {
+            Ok(pairs.choose_multiple(&mut rng, count as usize).cloned().collect())
+        } else {
+            Ok((0..count.unsigned_abs())
+                .map(|_| pairs[rng.gen_range(0..pairs.len())].clone())
+                .collect())
+        }
+    }
+
+    fn hexpire(&mut self, key: String, at: Instant, condition: ExpireCondition, fields: Vec<String>) -> DbResult<Vec<i64>> {
+        if !self.check_expiration(&key) {
+            return Ok(vec![-2; fields.len()]);
+        }
 
-                let start = cursor as usize;
-                if start >= total {
-                    return (0, vec![]);
+        let entry = match self.items.get_mut(&key) {
+            Some(entry) => entry,
+            None => return Ok(vec![-2; fields.len()]),
+        };
+        if !matches!(entry.value, DataType::Hash(_)) {
+            return Err(DbError::WrongType);
+        }
+
+        let expired = at <= Instant::now();
+        let mut result = Vec::with_capacity(fields.len());
+        for field in &fields {
+            let exists = matches!(&entry.value, DataType::Hash(hash) if hash.contains_key(field));
+            if !exists {
+                result.push(-2);
+                continue;
+            }
+            let current = entry.field_expires.as_ref().and_then(|e| e.get(field).copied());
+            if !condition.allows(current, at) {
+                result.push(0);
+            } else if expired {
+                if let DataType::Hash(hash) = &mut entry.value {
+                    hash.remove(field);
                 }
+                entry.clear_field_expire(field);
+                result.push(2);
+            } else {
+                entry
+                    .field_expires
+                    .get_or_insert_with(Default::default)
+                    .insert(field.clone(), at);
+                result.push(1);
+            }
+        }
 
-                let mut result = Vec::new();
-                let mut end = start;
+        let emptied = matches!(&entry.value, DataType::Hash(hash) if hash.is_empty());
+        let has_ttls = entry.field_expires.is_some();
+        if emptied {
+            if let Some(old) = self.items.remove(&key) {
+                self.notify_delete(&key, &old);
+            }
+        } else if has_ttls {
+            self.hash_field_ttl_keys.insert(key.clone());
+        }
+        if result.iter().any(|&r| r > 0) {
+            self.increment_changes();
+            self.notify_set(&key);
+        }
+        Ok(result)
+    }
 
-                for (i, (field, value)) in pairs.iter().enumerate().skip(start) {
-                    if result.len() >= count {
-                        break;
-                    }
+    fn httl(&mut self, key: &str, fields: Vec<String>) -> DbResult<Vec<i64>> {
+        Ok(self
+            .hpttl(key, fields)?
+            .into_iter()
+            .map(|ms| if ms > 0 { ms / 1000 } else { ms })
+            .collect())
+    }
 
-                    let matches = pattern
-                        .map(|p| field.contains(p) || p == "*")
-                        .unwrap_or(true);
+    fn hpttl(&mut self, key: &str, fields: Vec<String>) -> DbResult<Vec<i64>> {
+        if !self.check_expiration(key) {
+            return Ok(vec![-2; fields.len()]);
+        }
 
-                    if matches {
-                        result.push(((*field).clone(), (*value).clone()));
-                    }
-                    end = i + 1;
+        match self.items.get(key) {
+            Some(entry) => match &entry.value {
+                DataType::Hash(hash) => {
+                    let now = Instant::now();
+                    Ok(fields
+                        .iter()
+                        .map(|field| {
+                            if !hash.contains_key(field) {
+                                return -2;
+                            }
+                            match entry.field_expires.as_ref().and_then(|e| e.get(field)) {
+                                Some(at) => at.saturating_duration_since(now).as_millis() as i64,
+                                None => -1,
+                            }
+                        })
+                        .collect())
                 }
+                _ => Err(DbError::WrongType),
+            },
+            None => Ok(vec![-2; fields.len()]),
+        }
+    }
+
+    fn hpersist(&mut self, key: String, fields: Vec<String>) -> DbResult<Vec<i64>> {
+        if !self.check_expiration(&key) {
+            return Ok(vec![-2; fields.len()]);
+        }
+
+        let entry = match self.items.get_mut(&key) {
+            Some(entry) => entry,
+            None => return Ok(vec![-2; fields.len()]),
+        };
+        if !matches!(entry.value, DataType::Hash(_)) {
+            return Err(DbError::WrongType);
+        }
+
+        let mut result = Vec::with_capacity(fields.len());
+        for field in &fields {
+            let exists = matches!(&entry.value, DataType::Hash(hash) if hash.contains_key(field));
+            if !exists {
+                result.push(-2);
+            } else if entry.clear_field_expire(field) {
+                result.push(1);
+            } else {
+                result.push(-1);
+            }
+        }
+        if result.contains(&1) {
+            self.increment_changes();
+            self.notify_set(&key);
+        }
+        Ok(result)
+    }
+
+    fn hscan(&mut self, key: &str, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> DbResult<(u64, Vec<(String, String)>)> {
+        if !self.check_expiration(key) {
+            return Ok((0, vec![]));
+        }
+        let entry = match self.items.get(key) {
+            Some(entry) => entry,
+            None => return Ok((0, vec![])),
+        };
+        let hash = match &entry.value {
+            DataType::Hash(hash) => hash,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let (next, page) = scan_page(
+            hash.iter().map(|(field, value)| (field.as_str(), (field, value))),
+            cursor,
+            count.unwrap_or(10),
+        );
+        let now = Instant::now();
+        let fields = page
+            .into_iter()
+            .filter(|(field, _)| pattern.is_none_or(|p| glob_match(p, field)))
+            .filter(|(field, _)| {
+                entry
+                    .field_expires
+                    .as_ref()
+                    .and_then(|e| e.get(*field))
+                    .is_none_or(|at| now < *at)
+            })
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+        Ok((next, fields))
+    }
+
+    fn session_create(&mut self, key: String, pairs: Vec<(String, String)>, at: Instant) -> DbResult<bool> {
+        if self.check_expiration(&key) && self.items.contains_key(&key) {
+            return Ok(false);
+        }
+        self.items.insert(
+            key.clone(),
+            Entry {
+                value: DataType::Hash(pairs.into_iter().collect()),
+                expires_at: Some(at),
+                field_expires: None,
+            },
+        );
+        self.increment_changes();
+        self.notify_set(&key);
+        Ok(true)
+    }
+
+    fn session_get(&mut self, key: &str, fields: &[String], at: Instant) -> DbResult<Option<SessionFields>> {
+        if !self.check_expiration(key) {
+            return Ok(None);
+        }
+        let entry = match self.items.get_mut(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let hash = match &entry.value {
+            DataType::Hash(hash) => hash,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let values = if fields.is_empty() {
+            hash.iter()
+                .map(|(field, value)| (field.clone(), Some(value.clone())))
+                .collect()
+        } else {
+            fields
+                .iter()
+                .map(|field| (field.clone(), hash.get(field).cloned()))
+                .collect()
+        };
+        entry.expires_at = Some(at);
+        self.increment_changes();
+        Ok(Some(values))
+    }
+
+    fn session_refresh(&mut self, key: String, pairs: Vec<(String, String)>, at: Instant) -> DbResult<bool> {
+        if !self.check_expiration(&key) {
+            return Ok(false);
+        }
+        match self.items.get(&key).map(|entry| &entry.value) {
+            Some(DataType::Hash(_)) => {}
+            Some(_) => return Err(DbError::WrongType),
+            None => return Ok(false),
+        }
 
-                let next_cursor = if end >= total { 0 } else { end as u64 };
-                return (next_cursor, result);
+        self.hset_multi(key.clone(), pairs)?;
+        if let Some(entry) = self.items.get_mut(&key) {
+            entry.expires_at = Some(at);
+        }
+        self.increment_changes();
+        Ok(true)
+    }
+}
+
+impl DB {
+    /// Drop expired fields from every hash that has field TTLs, so fields
+    /// that are never read again still get reclaimed. Returns how many
+    /// hashes were checked.
+    pub fn active_expire_hash_fields(&mut self) -> usize {
+        if !self.active_expire {
+            return 0;
+        }
+        let keys: Vec<String> = self.hash_field_ttl_keys.iter().cloned().collect();
+        for key in &keys {
+            self.check_expiration(key);
+            let has_ttls = self
+                .items
+                .get(key)
+                .is_some_and(|entry| entry.field_expires.is_some());
+            if !has_ttls {
+                self.hash_field_ttl_keys.remove(key);
             }
         }
-        (0, vec![])
+        keys.len()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_hash_ops() {
@@ -326,8 +653,8 @@ mod tests {
         
         assert_eq!(db.hset("myhash".to_string(), "field1".to_string(), "value1".to_string()).unwrap(), 1);
         assert_eq!(db.hset("myhash".to_string(), "field1".to_string(), "value2".to_string()).unwrap(), 0);
-        assert_eq!(db.hget("myhash".to_string(), "field1".to_string()).unwrap(), Some("value2".to_string()));
-        assert_eq!(db.hlen("myhash".to_string()), 1);
+        assert_eq!(db.hget("myhash", "field1").unwrap(), Some("value2".to_string()));
+        assert_eq!(db.hlen("myhash"), 1);
     }
 
     #[test]
@@ -338,4 +665,84 @@ mod tests {
         assert_eq!(db.hincrby("myhash".to_string(), "counter".to_string(), 5).unwrap(), 6);
         assert_eq!(db.hincrby("myhash".to_string(), "counter".to_string(), -3).unwrap(), 3);
     }
+
+    #[test]
+    fn test_hsetnx_and_hrandfield() {
+        use crate::db::ops::string::StringOps;
+        use crate::db::ops::MAX_RANDOM_COUNT;
+
+        let mut db = DB::new();
+
+        assert!(db.hsetnx("myhash".to_string(), "a".to_string(), "1".to_string()).unwrap());
+        assert!(!db.hsetnx("myhash".to_string(), "a".to_string(), "2".to_string()).unwrap());
+        db.hset("myhash".to_string(), "b".to_string(), "2".to_string()).unwrap();
+
+        let fields = db.hrandfield("myhash", 5).unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(db.hrandfield("myhash", -4).unwrap().len(), 4);
+        assert!(db.hrandfield("missing", 1).unwrap().is_empty());
+        assert!(matches!(db.hrandfield("myhash", i64::MIN), Err(DbError::InvalidValue(_))));
+        assert!(db.hrandfield("myhash", -(MAX_RANDOM_COUNT as i64) - 1).is_err());
+
+        db.set("str".to_string(), "value".to_string());
+        assert!(db.hsetnx("str".to_string(), "a".to_string(), "1".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_field_expiration() {
+        let mut db = DB::new();
+        db.hset("h".to_string(), "a".to_string(), "1".to_string()).unwrap();
+        db.hset("h".to_string(), "b".to_string(), "2".to_string()).unwrap();
+        let fields = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let res = db.hexpire("h".to_string(), Instant::now() + Duration::from_secs(100), ExpireCondition::Always, fields(&["a", "missing"])).unwrap();
+        assert_eq!(res, vec![1, -2]);
+        assert_eq!(db.hexpire("h".to_string(), Instant::now() + Duration::from_secs(50), ExpireCondition::Gt, fields(&["a"])).unwrap(), vec![0]);
+        assert_eq!(db.hexpire("h".to_string(), Instant::now() + Duration::from_secs(50), ExpireCondition::Nx, fields(&["b"])).unwrap(), vec![1]);
+        let ttls = db.httl("h", fields(&["a", "b", "missing"])).unwrap();
+        assert!(ttls[0] > 90 && ttls[1] > 40);
+        assert_eq!(ttls[2], -2);
+
+        assert_eq!(db.hpersist("h".to_string(), fields(&["b"])).unwrap(), vec![1]);
+        assert_eq!(db.hpttl("h", fields(&["b"])).unwrap(), vec![-1]);
+
+        // Overwriting a field drops its TTL
+        db.hset("h".to_string(), "a".to_string(), "3".to_string()).unwrap();
+        assert_eq!(db.httl("h", fields(&["a"])).unwrap(), vec![-1]);
+
+        // An instant that has passed deletes the field right away
+        assert_eq!(db.hexpire("h".to_string(), Instant::now(), ExpireCondition::Always, fields(&["a"])).unwrap(), vec![2]);
+        assert!(!db.hexists("h", "a"));
+    }
+
+    #[test]
+    fn test_field_expiry_lazy_and_active() {
+        let mut db = DB::new();
+        db.hset("lazy".to_string(), "f".to_string(), "v".to_string()).unwrap();
+        db.hset("lazy".to_string(), "g".to_string(), "v".to_string()).unwrap();
+        db.hset("active".to_string(), "f".to_string(), "v".to_string()).unwrap();
+        for key in ["lazy", "active"] {
+            db.hexpire(key.to_string(), Instant::now() + Duration::from_millis(1), ExpireCondition::Always, vec!["f".to_string()]).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert_eq!(db.hkeys("lazy"), vec!["g".to_string()]);
+
+        db.active_expire_hash_fields();
+        assert!(!db.items.contains_key("active"));
+        assert!(db.hash_field_ttl_keys.is_empty());
+    }
+
+    #[test]
+    fn test_parse_field_ttl_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let parsed = FieldTtlArgs::parse(&args(&["NX", "FIELDS", "2", "a", "b"]), true).unwrap();
+        assert_eq!(parsed.condition, ExpireCondition::Nx);
+        assert_eq!(parsed.fields, args(&["a", "b"]));
+
+        assert!(FieldTtlArgs::parse(&args(&["NX", "FIELDS", "1", "a"]), false).is_err());
+        assert!(FieldTtlArgs::parse(&args(&["FIELDS", "2", "a"]), false).is_err());
+        assert!(FieldTtlArgs::parse(&args(&["FIELDS", "0"]), false).is_err());
+    }
 }
@@ -3,110 +3,85 @@
 //! Redis-compatible probabilistic cardinality estimation.
 
 use crate::db::core::DB;
-use crate::db::ops::generic::GenericOps;
-use crate::db::types::{DataType, Entry, HyperLogLogData};
+use crate::db::error::{DbError, DbResult};
+use crate::db::types::{DataType, HyperLogLogData};
 use std::sync::atomic::Ordering;
 
 /// HyperLogLog operations trait
 pub trait HyperLogLogOps {
     /// Add elements to HyperLogLog (PFADD)
-    fn pfadd(&mut self, key: String, elements: Vec<String>) -> bool;
+    fn pfadd(&mut self, key: String, elements: Vec<String>) -> DbResult<bool>;
     
     /// Count unique elements (PFCOUNT)
-    fn pfcount(&mut self, keys: Vec<String>) -> usize;
+    fn pfcount(&mut self, keys: Vec<String>) -> DbResult<usize>;
     
-    /// Merge multiple HyperLogLogs (PFMERGE)
-    fn pfmerge(&mut self, destkey: String, sourcekeys: Vec<String>) -> bool;
+    /// Merge HyperLogLogs into `destkey`, including its own elements (PFMERGE)
+    fn pfmerge(&mut self, destkey: String, sourcekeys: Vec<String>) -> DbResult<()>;
+}
+
+fn is_hll(value: &DataType) -> bool {
+    matches!(value, DataType::HyperLogLog(_))
 }
 
 impl HyperLogLogOps for DB {
-    fn pfadd(&mut self, key: String, elements: Vec<String>) -> bool {
-        self.check_expiration(&key);
-
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
-            value: DataType::HyperLogLog(HyperLogLogData::new()),
-            expires_at: None,
-        });
-
-        match &mut entry.value {
-            DataType::HyperLogLog(hll) => {
-                let mut modified = false;
-                for element in elements {
-                    if hll.add(&element) {
-                        modified = true;
-                    }
-                }
-                if modified {
-                    self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-                }
-                modified
+    fn pfadd(&mut self, key: String, elements: Vec<String>) -> DbResult<bool> {
+        let entry = self.typed_entry_mut(&key, is_hll, || DataType::HyperLogLog(HyperLogLogData::new()))?;
+        let hll = match &mut entry.value {
+            DataType::HyperLogLog(hll) => hll,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let mut modified = false;
+        for element in elements {
+            if hll.add(&element) {
+                modified = true;
             }
-            _ => false,
         }
+        if modified {
+            self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+            self.notify_set(&key);
+        }
+        Ok(modified)
     }
 
-    fn pfcount(&mut self, keys: Vec<String>) -> usize {
+    fn pfcount(&mut self, keys: Vec<String>) -> DbResult<usize> {
         if keys.is_empty() {
-            return 0;
+            return Ok(0);
         }
 
         if keys.len() == 1 {
             // Single key - direct count
-            let key = &keys[0];
-            if !self.check_expiration(key) {
-                return 0;
-            }
-
-            if let Some(entry) = self.items.get(key) {
-                if let DataType::HyperLogLog(hll) = &entry.value {
-                    return hll.count();
-                }
-            }
-            return 0;
+            return Ok(match self.typed_value(&keys[0], is_hll)? {
+                Some(DataType::HyperLogLog(hll)) => hll.count(),
+                _ => 0,
+            });
         }
 
         // Multiple keys - merge and count
         let mut merged = HyperLogLogData::new();
-        
         for key in &keys {
-            if !self.check_expiration(key) {
-                continue;
-            }
-
-            if let Some(entry) = self.items.get(key) {
-                if let DataType::HyperLogLog(hll) = &entry.value {
-                    merged.merge(hll);
-                }
+            if let Some(DataType::HyperLogLog(hll)) = self.typed_value(key, is_hll)? {
+                merged.merge(hll);
             }
         }
-
-        merged.count()
+        Ok(merged.count())
     }
 
-    fn pfmerge(&mut self, destkey: String, sourcekeys: Vec<String>) -> bool {
+    fn pfmerge(&mut self, destkey: String, sourcekeys: Vec<String>) -> DbResult<()> {
         let mut merged = HyperLogLogData::new();
-
-        // First, collect data from all source keys
         for key in &sourcekeys {
-            if !self.check_expiration(key) {
-                continue;
-            }
-
-            if let Some(entry) = self.items.get(key) {
-                if let DataType::HyperLogLog(hll) = &entry.value {
-                    merged.merge(hll);
-                }
+            if let Some(DataType::HyperLogLog(hll)) = self.typed_value(key, is_hll)? {
+                merged.merge(hll);
             }
         }
 
-        // Store the merged result
-        self.items.insert(destkey, Entry {
-            value: DataType::HyperLogLog(merged),
-            expires_at: None,
-        });
+        let entry = self.typed_entry_mut(&destkey, is_hll, || DataType::HyperLogLog(HyperLogLogData::new()))?;
+        if let DataType::HyperLogLog(hll) = &mut entry.value {
+            hll.merge(&merged);
+        }
         self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-
-        true
+        self.notify_set(&destkey);
+        Ok(())
     }
 }
 
@@ -119,41 +94,56 @@ mod tests {
         let mut db = DB::new();
         
         // Add some elements
-        assert!(db.pfadd("hll".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]));
+        assert_eq!(db.pfadd("hll".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]), Ok(true));
         
         // Count should be 3
         let count = db.pfcount(vec!["hll".to_string()]);
-        assert_eq!(count, 3);
+        assert_eq!(count, Ok(3));
         
         // Adding same elements should not modify
-        assert!(!db.pfadd("hll".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]));
+        assert_eq!(db.pfadd("hll".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]), Ok(false));
         
         // Adding new element should modify
-        assert!(db.pfadd("hll".to_string(), vec!["d".to_string()]));
-        assert_eq!(db.pfcount(vec!["hll".to_string()]), 4);
+        assert_eq!(db.pfadd("hll".to_string(), vec!["d".to_string()]), Ok(true));
+        assert_eq!(db.pfcount(vec!["hll".to_string()]), Ok(4));
     }
 
     #[test]
     fn test_pfmerge() {
         let mut db = DB::new();
         
-        db.pfadd("hll1".to_string(), vec!["a".to_string(), "b".to_string()]);
-        db.pfadd("hll2".to_string(), vec!["c".to_string(), "d".to_string()]);
-        
-        db.pfmerge("hll3".to_string(), vec!["hll1".to_string(), "hll2".to_string()]);
+        db.pfadd("hll1".to_string(), vec!["a".to_string(), "b".to_string()]).unwrap();
+        db.pfadd("hll2".to_string(), vec!["c".to_string(), "d".to_string()]).unwrap();
         
-        assert_eq!(db.pfcount(vec!["hll3".to_string()]), 4);
+        db.pfmerge("hll3".to_string(), vec!["hll1".to_string(), "hll2".to_string()]).unwrap();
+        assert_eq!(db.pfcount(vec!["hll3".to_string()]), Ok(4));
+
+        // The destination's own elements are kept
+        db.pfmerge("hll1".to_string(), vec!["hll2".to_string()]).unwrap();
+        assert_eq!(db.pfcount(vec!["hll1".to_string()]), Ok(4));
     }
 
     #[test]
     fn test_pfcount_multiple_keys() {
         let mut db = DB::new();
         
-        db.pfadd("hll1".to_string(), vec!["a".to_string(), "b".to_string()]);
-        db.pfadd("hll2".to_string(), vec!["b".to_string(), "c".to_string()]); // 'b' is duplicate
+        db.pfadd("hll1".to_string(), vec!["a".to_string(), "b".to_string()]).unwrap();
+        db.pfadd("hll2".to_string(), vec!["b".to_string(), "c".to_string()]).unwrap(); // 'b' is duplicate
         
         // Union count should be 3 (a, b, c)
         let count = db.pfcount(vec!["hll1".to_string(), "hll2".to_string()]);
-        assert_eq!(count, 3);
+        assert_eq!(count, Ok(3));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        use crate::db::StringOps;
+        let mut db = DB::new();
+        db.set("plain".to_string(), "value".to_string());
+
+        assert_eq!(db.pfadd("plain".to_string(), vec!["a".to_string()]), Err(DbError::WrongType));
+        assert_eq!(db.pfcount(vec!["plain".to_string()]), Err(DbError::WrongType));
+        assert_eq!(db.pfmerge("plain".to_string(), vec![]), Err(DbError::WrongType));
+        assert_eq!(db.get("plain").unwrap(), Some("value".to_string()));
     }
 }
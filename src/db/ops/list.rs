@@ -1,18 +1,20 @@
 //! List operations.
 //!
-//! Operations for the list data type (linked list of strings).
+//! Operations for the list data type (double-ended queue of strings).
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
 use crate::db::ops::generic::GenericOps;
 use crate::db::types::{DataType, Entry};
+use std::collections::VecDeque;
 
 /// List operations trait
 pub trait ListOps {
     /// Push values to the left of a list
-    fn lpush(&mut self, key: String, values: Vec<String>) -> Result<usize, String>;
+    fn lpush(&mut self, key: String, values: Vec<String>) -> DbResult<usize>;
     
     /// Push values to the right of a list
-    fn rpush(&mut self, key: String, values: Vec<String>) -> Result<usize, String>;
+    fn rpush(&mut self, key: String, values: Vec<String>) -> DbResult<usize>;
     
     /// Push to left only if list exists
     fn lpushx(&mut self, key: String, values: Vec<String>) -> usize;
@@ -21,31 +23,31 @@ pub trait ListOps {
     fn rpushx(&mut self, key: String, values: Vec<String>) -> usize;
     
     /// Pop from left
-    fn lpop(&mut self, key: String) -> Result<Option<String>, String>;
+    fn lpop(&mut self, key: String) -> DbResult<Option<String>>;
     
     /// Pop from right
-    fn rpop(&mut self, key: String) -> Result<Option<String>, String>;
+    fn rpop(&mut self, key: String) -> DbResult<Option<String>>;
     
     /// Pop multiple from left
-    fn lpop_count(&mut self, key: String, count: usize) -> Result<Vec<String>, String>;
+    fn lpop_count(&mut self, key: String, count: usize) -> DbResult<Vec<String>>;
     
     /// Pop multiple from right
-    fn rpop_count(&mut self, key: String, count: usize) -> Result<Vec<String>, String>;
+    fn rpop_count(&mut self, key: String, count: usize) -> DbResult<Vec<String>>;
     
     /// Get list length
-    fn llen(&mut self, key: String) -> Result<usize, String>;
+    fn llen(&mut self, key: &str) -> DbResult<usize>;
     
     /// Get range of elements
-    fn lrange(&mut self, key: String, start: i64, stop: i64) -> Result<Vec<String>, String>;
+    fn lrange(&mut self, key: &str, start: i64, stop: i64) -> DbResult<Vec<String>>;
     
     /// Get element at index
-    fn lindex(&mut self, key: String, index: i64) -> Result<Option<String>, String>;
+    fn lindex(&mut self, key: &str, index: i64) -> DbResult<Option<String>>;
     
     /// Set element at index
-    fn lset(&mut self, key: String, index: i64, value: String) -> Result<(), String>;
+    fn lset(&mut self, key: String, index: i64, value: String) -> DbResult<()>;
     
     /// Insert before or after pivot
-    fn linsert(&mut self, key: String, before: bool, pivot: String, value: String) -> Result<i64, String>;
+    fn linsert(&mut self, key: String, before: bool, pivot: String, value: String) -> DbResult<i64>;
     
     /// Remove count occurrences of element
     fn lrem(&mut self, key: String, count: i64, element: String) -> usize;
@@ -54,7 +56,20 @@ pub trait ListOps {
     fn ltrim(&mut self, key: String, start: i64, stop: i64);
     
     /// Find position of element
-    fn lpos(&mut self, key: String, element: String) -> Option<usize>;
+    fn lpos(&mut self, key: &str, element: &str) -> Option<usize>;
+
+    /// Positions of up to `count` matches of element (0 = all), skipping
+    /// the first `rank - 1` matches. A negative rank searches from the
+    /// tail. Only the first `maxlen` elements searched are compared
+    /// (0 = no limit).
+    fn lpos_matches(
+        &mut self,
+        key: String,
+        element: String,
+        rank: i64,
+        count: usize,
+        maxlen: usize,
+    ) -> DbResult<Vec<usize>>;
     
     /// Move element from one list to another
     fn lmove(&mut self, src: String, dst: String, src_left: bool, dst_left: bool) -> Option<String>;
@@ -64,24 +79,25 @@ pub trait ListOps {
 }
 
 impl ListOps for DB {
-    fn lpush(&mut self, key: String, values: Vec<String>) -> Result<usize, String> {
+    fn lpush(&mut self, key: String, values: Vec<String>) -> DbResult<usize> {
         self.check_expiration(&key);
 
         // Check existing entry type first
         if let Some(entry) = self.items.get(&key) {
             if !matches!(&entry.value, DataType::List(_)) {
-                return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+                return Err(DbError::WrongType);
             }
         }
 
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
-            value: DataType::List(Vec::new()),
+        let entry = self.items.get_or_insert_with(key, || Entry {
+            value: DataType::List(VecDeque::new()),
             expires_at: None,
+            field_expires: None,
         });
 
         if let DataType::List(list) = &mut entry.value {
             for value in values.into_iter().rev() {
-                list.insert(0, value);
+                list.push_front(value);
             }
             let len = list.len();
             // Increment after we're done with borrowing list
@@ -89,23 +105,24 @@ impl ListOps for DB {
             self.changes_since_save.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             Ok(len)
         } else {
-            Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+            Err(DbError::WrongType)
         }
     }
 
-    fn rpush(&mut self, key: String, values: Vec<String>) -> Result<usize, String> {
+    fn rpush(&mut self, key: String, values: Vec<String>) -> DbResult<usize> {
         self.check_expiration(&key);
 
         // Check existing entry type first
         if let Some(entry) = self.items.get(&key) {
             if !matches!(&entry.value, DataType::List(_)) {
-                return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+                return Err(DbError::WrongType);
             }
         }
 
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
-            value: DataType::List(Vec::new()),
+        let entry = self.items.get_or_insert_with(key, || Entry {
+            value: DataType::List(VecDeque::new()),
             expires_at: None,
+            field_expires: None,
         });
 
         if let DataType::List(list) = &mut entry.value {
@@ -115,7 +132,7 @@ impl ListOps for DB {
             self.changes_since_save.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
             Ok(len)
         } else {
-            Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+            Err(DbError::WrongType)
         }
     }
 
@@ -133,7 +150,7 @@ impl ListOps for DB {
         self.rpush(key, values).unwrap_or(0)
     }
 
-    fn lpop(&mut self, key: String) -> Result<Option<String>, String> {
+    fn lpop(&mut self, key: String) -> DbResult<Option<String>> {
         if !self.check_expiration(&key) {
             return Ok(None);
         }
@@ -141,13 +158,9 @@ impl ListOps for DB {
         let result = if let Some(entry) = self.items.get_mut(&key) {
             match &mut entry.value {
                 DataType::List(list) => {
-                    if list.is_empty() {
-                        Ok(None)
-                    } else {
-                        Ok(Some(list.remove(0)))
-                    }
+                    Ok(list.pop_front())
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(None)
@@ -159,15 +172,15 @@ impl ListOps for DB {
         result
     }
 
-    fn rpop(&mut self, key: String) -> Result<Option<String>, String> {
+    fn rpop(&mut self, key: String) -> DbResult<Option<String>> {
         if !self.check_expiration(&key) {
             return Ok(None);
         }
 
         let result = if let Some(entry) = self.items.get_mut(&key) {
             match &mut entry.value {
-                DataType::List(list) => Ok(list.pop()),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                DataType::List(list) => Ok(list.pop_back()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(None)
@@ -179,7 +192,7 @@ impl ListOps for DB {
         result
     }
 
-    fn lpop_count(&mut self, key: String, count: usize) -> Result<Vec<String>, String> {
+    fn lpop_count(&mut self, key: String, count: usize) -> DbResult<Vec<String>> {
         let mut result = Vec::new();
         for _ in 0..count {
             match self.lpop(key.clone())? {
@@ -190,7 +203,7 @@ impl ListOps for DB {
         Ok(result)
     }
 
-    fn rpop_count(&mut self, key: String, count: usize) -> Result<Vec<String>, String> {
+    fn rpop_count(&mut self, key: String, count: usize) -> DbResult<Vec<String>> {
         let mut result = Vec::new();
         for _ in 0..count {
             match self.rpop(key.clone())? {
@@ -201,27 +214,27 @@ impl ListOps for DB {
         Ok(result)
     }
 
-    fn llen(&mut self, key: String) -> Result<usize, String> {
-        if !self.check_expiration(&key) {
+    fn llen(&mut self, key: &str) -> DbResult<usize> {
+        if !self.check_expiration(key) {
             return Ok(0);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::List(list) => Ok(list.len()),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(0)
         }
     }
 
-    fn lrange(&mut self, key: String, start: i64, stop: i64) -> Result<Vec<String>, String> {
-        if !self.check_expiration(&key) {
+    fn lrange(&mut self, key: &str, start: i64, stop: i64) -> DbResult<Vec<String>> {
+        if !self.check_expiration(key) {
             return Ok(vec![]);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::List(list) => {
                     let len = list.len() as i64;
@@ -232,21 +245,21 @@ impl ListOps for DB {
                         return Ok(vec![]);
                     }
 
-                    Ok(list[start..=stop.min(list.len() - 1)].to_vec())
+                    Ok(list.range(start..=stop.min(list.len() - 1)).cloned().collect())
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(vec![])
         }
     }
 
-    fn lindex(&mut self, key: String, index: i64) -> Result<Option<String>, String> {
-        if !self.check_expiration(&key) {
+    fn lindex(&mut self, key: &str, index: i64) -> DbResult<Option<String>> {
+        if !self.check_expiration(key) {
             return Ok(None);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::List(list) => {
                     let len = list.len() as i64;
@@ -258,16 +271,16 @@ impl ListOps for DB {
                         Ok(Some(list[idx as usize].clone()))
                     }
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(None)
         }
     }
 
-    fn lset(&mut self, key: String, index: i64, value: String) -> Result<(), String> {
+    fn lset(&mut self, key: String, index: i64, value: String) -> DbResult<()> {
         if !self.check_expiration(&key) {
-            return Err("ERR no such key".to_string());
+            return Err(DbError::NoSuchKey);
         }
 
         if let Some(entry) = self.items.get_mut(&key) {
@@ -277,21 +290,21 @@ impl ListOps for DB {
                     let idx = if index < 0 { len + index } else { index };
                     
                     if idx < 0 || idx >= len {
-                        Err("ERR index out of range".to_string())
+                        Err(DbError::OutOfRange)
                     } else {
                         list[idx as usize] = value;
                         self.changes_since_save.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
                         Ok(())
                     }
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
-            Err("ERR no such key".to_string())
+            Err(DbError::NoSuchKey)
         }
     }
 
-    fn linsert(&mut self, key: String, before: bool, pivot: String, value: String) -> Result<i64, String> {
+    fn linsert(&mut self, key: String, before: bool, pivot: String, value: String) -> DbResult<i64> {
         if !self.check_expiration(&key) {
             return Ok(-1);
         }
@@ -307,7 +320,7 @@ impl ListOps for DB {
                         Ok(-1)
                     }
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(0)
@@ -381,26 +394,61 @@ impl ListOps for DB {
                 if start > stop || start >= list.len() {
                     list.clear();
                 } else {
-                    *list = list[start..=stop.min(list.len() - 1)].to_vec();
+                    list.truncate(stop + 1);
+                    list.drain(..start);
                 }
             }
         }
         self.changes_since_save.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
     }
 
-    fn lpos(&mut self, key: String, element: String) -> Option<usize> {
-        if !self.check_expiration(&key) {
+    fn lpos(&mut self, key: &str, element: &str) -> Option<usize> {
+        if !self.check_expiration(key) {
             return None;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::List(list) = &entry.value {
-                return list.iter().position(|x| x == &element);
+                return list.iter().position(|x| x == element);
             }
         }
         None
     }
 
+    fn lpos_matches(
+        &mut self,
+        key: String,
+        element: String,
+        rank: i64,
+        count: usize,
+        maxlen: usize,
+    ) -> DbResult<Vec<usize>> {
+        if !self.check_expiration(&key) {
+            return Ok(Vec::new());
+        }
+
+        let list = match self.items.get(&key).map(|entry| &entry.value) {
+            Some(DataType::List(list)) => list,
+            Some(_) => return Err(DbError::WrongType),
+            None => return Ok(Vec::new()),
+        };
+        let limit = if maxlen == 0 { list.len() } else { maxlen };
+        let count = if count == 0 { usize::MAX } else { count };
+        let skip = (rank.unsigned_abs() as usize).saturating_sub(1);
+        let searched: Box<dyn Iterator<Item = (usize, &String)>> = if rank >= 0 {
+            Box::new(list.iter().enumerate())
+        } else {
+            Box::new(list.iter().enumerate().rev())
+        };
+        Ok(searched
+            .take(limit)
+            .filter(|(_, value)| **value == element)
+            .skip(skip)
+            .take(count)
+            .map(|(i, _)| i)
+            .collect())
+    }
+
     fn lmove(&mut self, src: String, dst: String, src_left: bool, dst_left: bool) -> Option<String> {
         let value = if src_left {
             self.lpop(src.clone()).ok().flatten()?
@@ -425,16 +473,38 @@ impl ListOps for DB {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::StringOps;
 
     #[test]
     fn test_list_ops() {
         let mut db = DB::new();
         
         assert_eq!(db.rpush("mylist".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]).unwrap(), 3);
-        assert_eq!(db.lrange("mylist".to_string(), 0, -1).unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(db.lrange("mylist", 0, -1).unwrap(), vec!["a", "b", "c"]);
         assert_eq!(db.lpop("mylist".to_string()).unwrap(), Some("a".to_string()));
         assert_eq!(db.rpop("mylist".to_string()).unwrap(), Some("c".to_string()));
-        assert_eq!(db.llen("mylist".to_string()).unwrap(), 1);
+        assert_eq!(db.llen("mylist").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_push_pop_both_ends_and_trim() {
+        let mut db = DB::new();
+        let items = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        db.rpush("mylist".to_string(), items(&["c", "d"])).unwrap();
+        assert_eq!(db.lpush("mylist".to_string(), items(&["a", "b"])).unwrap(), 4);
+        assert_eq!(db.rpush("mylist".to_string(), items(&["e", "f"])).unwrap(), 6);
+        assert_eq!(db.lrange("mylist", 0, -1).unwrap(), vec!["a", "b", "c", "d", "e", "f"]);
+        assert_eq!(db.lrange("mylist", -3, -2).unwrap(), vec!["d", "e"]);
+
+        db.ltrim("mylist".to_string(), 1, -2);
+        assert_eq!(db.lrange("mylist", 0, -1).unwrap(), vec!["b", "c", "d", "e"]);
+        assert_eq!(db.lpop_count("mylist".to_string(), 2).unwrap(), vec!["b", "c"]);
+        assert_eq!(db.rpop("mylist".to_string()).unwrap(), Some("e".to_string()));
+        assert_eq!(db.lindex("mylist", 0).unwrap(), Some("d".to_string()));
+
+        db.ltrim("mylist".to_string(), 5, 10);
+        assert_eq!(db.llen("mylist").unwrap(), 0);
     }
 
     #[test]
@@ -442,10 +512,31 @@ mod tests {
         let mut db = DB::new();
         db.rpush("mylist".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]).unwrap();
         
-        assert_eq!(db.lindex("mylist".to_string(), 0).unwrap(), Some("a".to_string()));
-        assert_eq!(db.lindex("mylist".to_string(), -1).unwrap(), Some("c".to_string()));
+        assert_eq!(db.lindex("mylist", 0).unwrap(), Some("a".to_string()));
+        assert_eq!(db.lindex("mylist", -1).unwrap(), Some("c".to_string()));
         
         db.lset("mylist".to_string(), 1, "B".to_string()).unwrap();
-        assert_eq!(db.lindex("mylist".to_string(), 1).unwrap(), Some("B".to_string()));
+        assert_eq!(db.lindex("mylist", 1).unwrap(), Some("B".to_string()));
+    }
+
+    #[test]
+    fn test_lpos_matches() {
+        let mut db = DB::new();
+        let items = ["a", "b", "c", "1", "2", "3", "c", "c"].map(String::from).to_vec();
+        db.rpush("mylist".to_string(), items).unwrap();
+        let lpos = |db: &mut DB, rank, count, maxlen| {
+            db.lpos_matches("mylist".to_string(), "c".to_string(), rank, count, maxlen).unwrap()
+        };
+
+        assert_eq!(lpos(&mut db, 1, 1, 0), vec![2]);
+        assert_eq!(lpos(&mut db, 2, 1, 0), vec![6]);
+        assert_eq!(lpos(&mut db, -1, 1, 0), vec![7]);
+        assert_eq!(lpos(&mut db, 1, 0, 0), vec![2, 6, 7]);
+        assert_eq!(lpos(&mut db, -2, 0, 0), vec![6, 2]);
+        assert_eq!(lpos(&mut db, 1, 0, 3), vec![2]);
+        assert!(lpos(&mut db, 4, 0, 0).is_empty());
+
+        db.set("str".to_string(), "x".to_string());
+        assert!(db.lpos_matches("str".to_string(), "x".to_string(), 1, 1, 0).is_err());
     }
 }
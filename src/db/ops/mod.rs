@@ -11,7 +11,14 @@
 //! - StreamOps: Stream (Kafka-like) operations
 //! - GeoOps: Geospatial operations
 //! - HyperLogLogOps: Probabilistic cardinality estimation
+//! - QueueOps: Priority queues with acknowledgements
+//! - TimeSeriesOps: Time series with retention, label queries and downsampling
+//! - BloomOps: Scalable bloom filters
+//! - CuckooOps: Scalable cuckoo filters, which also count and delete items
+//! - VectorOps: Vector indexes with nearest neighbour search
+//! - SortOps: SORT over lists, sets and sorted sets
 
+pub mod cursor;
 pub mod generic;
 pub mod hash;
 pub mod list;
@@ -22,3 +29,26 @@ pub mod bitmap;
 pub mod stream;
 pub mod geo;
 pub mod hyperloglog;
+pub mod queue;
+pub mod timeseries;
+pub mod bloom;
+pub mod cuckoo;
+pub mod vector;
+pub mod sort;
+
+use crate::db::error::{DbError, DbResult};
+
+/// Largest reply ZRANDMEMBER and HRANDFIELD give for a negative count.
+/// Members may repeat then, so the collection's size does not bound it.
+pub const MAX_RANDOM_COUNT: u64 = 1_000_000;
+
+/// Reject a negative random member count over `MAX_RANDOM_COUNT`
+pub(crate) fn check_random_count(count: i64) -> DbResult<()> {
+    if count < 0 && count.unsigned_abs() > MAX_RANDOM_COUNT {
+        return Err(DbError::InvalidValue(format!(
+            "value is out of range, count must be at least -{}",
+            MAX_RANDOM_COUNT
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! Priority queue operations.
+//!
+//! A queue hands out messages lowest priority first, FIFO within a
+//! priority. A popped message is leased rather than removed: it stays
+//! hidden until its visibility deadline and then becomes deliverable again
+//! unless it was acknowledged first. Deadlines are Unix milliseconds so
+//! they survive snapshots and AOF replay unchanged.
+
+use crate::db::core::DB;
+use crate::db::error::{DbError, DbResult};
+use crate::db::ops::generic::GenericOps;
+use crate::db::types::{DataType, Entry, QueueData};
+
+/// Priority queue operations trait
+pub trait QueueOps {
+    /// Add messages as (priority, payload), returning their ids (PQ.PUSH)
+    fn pq_push(&mut self, key: String, messages: Vec<(i64, String)>) -> DbResult<Vec<u64>>;
+
+    /// Deliver up to `count` messages, hidden until `deadline` (PQ.POP)
+    fn pq_pop(&mut self, key: &str, count: usize, deadline: u64, now_ms: u64) -> DbResult<Vec<(u64, String)>>;
+
+    /// Hide the given messages until `deadline`, as a logged PQ.POP does on
+    /// replay. Returns how many exist.
+    fn pq_lease(&mut self, key: &str, ids: &[u64], deadline: u64) -> DbResult<usize>;
+
+    /// Remove messages, returning how many existed (PQ.ACK). The key is
+    /// deleted once the queue is empty.
+    fn pq_ack(&mut self, key: &str, ids: &[u64]) -> DbResult<usize>;
+}
+
+impl DB {
+    /// The queue stored at `key`, if any
+    fn queue_mut(&mut self, key: &str) -> DbResult<Option<&mut QueueData>> {
+        if !self.check_expiration(key) {
+            return Ok(None);
+        }
+        match self.items.get_mut(key).map(|entry| &mut entry.value) {
+            Some(DataType::Queue(queue)) => Ok(Some(queue)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+}
+
+impl QueueOps for DB {
+    fn pq_push(&mut self, key: String, messages: Vec<(i64, String)>) -> DbResult<Vec<u64>> {
+        self.check_expiration(&key);
+
+        let entry = self.items.get_or_insert_with(key.clone(), || Entry {
+            value: DataType::Queue(QueueData::new()),
+            expires_at: None,
+            field_expires: None,
+        });
+        let queue = match &mut entry.value {
+            DataType::Queue(queue) => queue,
+            _ => return Err(DbError::WrongType),
+        };
+
+        let ids = messages
+            .into_iter()
+            .map(|(priority, payload)| queue.push(priority, payload))
+            .collect();
+        self.increment_changes();
+        self.notify_set(&key);
+        Ok(ids)
+    }
+
+    fn pq_pop(&mut self, key: &str, count: usize, deadline: u64, now_ms: u64) -> DbResult<Vec<(u64, String)>> {
+        let popped = match self.queue_mut(key)? {
+            Some(queue) => queue.pop(count, deadline, now_ms),
+            None => return Ok(vec![]),
+        };
+        if !popped.is_empty() {
+            self.increment_changes();
+        }
+        Ok(popped)
+    }
+
+    fn pq_lease(&mut self, key: &str, ids: &[u64], deadline: u64) -> DbResult<usize> {
+        let leased = match self.queue_mut(key)? {
+            Some(queue) => ids.iter().filter(|&&id| queue.lease(id, deadline)).count(),
+            None => return Ok(0),
+        };
+        if leased > 0 {
+            self.increment_changes();
+        }
+        Ok(leased)
+    }
+
+    fn pq_ack(&mut self, key: &str, ids: &[u64]) -> DbResult<usize> {
+        let (acked, emptied) = match self.queue_mut(key)? {
+            Some(queue) => (
+                ids.iter().filter(|&&id| queue.ack(id)).count(),
+                queue.is_empty(),
+            ),
+            None => return Ok(0),
+        };
+        if acked > 0 {
+            self.increment_changes();
+        }
+        if emptied {
+            if let Some(old) = self.items.remove(key) {
+                self.notify_delete(key, &old);
+            }
+        }
+        Ok(acked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_priority_order_and_redelivery() {
+        let mut db = DB::new();
+        let ids = db
+            .pq_push(
+                "q".to_string(),
+                vec![(5, "low".to_string()), (1, "first".to_string()), (1, "second".to_string())],
+            )
+            .unwrap();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        // Lowest priority first, push order within a priority
+        let popped = db.pq_pop("q", 2, 1_000, 0).unwrap();
+        assert_eq!(popped, vec![(2, "first".to_string()), (3, "second".to_string())]);
+        assert_eq!(db.pq_ack("q", &[2, 42]).unwrap(), 1);
+
+        // Message 3 stays hidden until its deadline passes
+        assert_eq!(db.pq_pop("q", 5, 2_000, 500).unwrap(), vec![(1, "low".to_string())]);
+        assert_eq!(db.pq_pop("q", 5, 3_000, 1_000).unwrap(), vec![(3, "second".to_string())]);
+
+        assert_eq!(db.pq_ack("q", &[1, 3]).unwrap(), 2);
+        assert!(!db.items.contains_key("q"));
+    }
+
+    #[test]
+    fn test_lease_replays_a_pop() {
+        use crate::db::ops::string::StringOps;
+
+        let mut db = DB::new();
+        db.pq_push("q".to_string(), vec![(0, "a".to_string()), (0, "b".to_string())]).unwrap();
+
+        assert_eq!(db.pq_lease("q", &[2, 9], 5_000).unwrap(), 1);
+        assert_eq!(db.pq_pop("q", 5, 6_000, 0).unwrap(), vec![(1, "a".to_string())]);
+
+        db.set("s".to_string(), "v".to_string());
+        assert!(db.pq_pop("s", 1, 0, 0).is_err());
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! Operations for the set data type (unordered unique strings).
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
+use crate::db::ops::cursor::scan_page;
 use crate::db::ops::generic::GenericOps;
 use crate::db::types::{DataType, Entry};
 use rand::seq::IteratorRandom;
@@ -11,28 +13,28 @@ use std::collections::HashSet;
 /// Set operations trait
 pub trait SetOps {
     /// Add members to set
-    fn sadd(&mut self, key: String, members: Vec<String>) -> Result<usize, String>;
+    fn sadd(&mut self, key: String, members: Vec<String>) -> DbResult<usize>;
     
     /// Remove a member from set
-    fn srem(&mut self, key: String, member: String) -> Result<usize, String>;
+    fn srem(&mut self, key: String, member: String) -> DbResult<usize>;
     
     /// Remove multiple members from set
-    fn srem_multi(&mut self, key: String, members: Vec<String>) -> Result<usize, String>;
+    fn srem_multi(&mut self, key: String, members: Vec<String>) -> DbResult<usize>;
     
     /// Get all members
-    fn smembers(&mut self, key: String) -> Result<Vec<String>, String>;
+    fn smembers(&mut self, key: &str) -> DbResult<Vec<String>>;
     
     /// Check if member exists
-    fn sismember(&mut self, key: String, member: String) -> Result<bool, String>;
+    fn sismember(&mut self, key: &str, member: &str) -> DbResult<bool>;
     
     /// Check multiple members
-    fn smismember(&mut self, key: String, members: Vec<String>) -> Result<Vec<bool>, String>;
+    fn smismember(&mut self, key: &str, members: Vec<String>) -> DbResult<Vec<bool>>;
     
     /// Get set cardinality
-    fn scard(&mut self, key: String) -> Result<usize, String>;
+    fn scard(&mut self, key: &str) -> DbResult<usize>;
     
     /// Get random members
-    fn srandmember(&mut self, key: String, count: Option<i64>) -> Vec<String>;
+    fn srandmember(&mut self, key: &str, count: Option<i64>) -> Vec<String>;
     
     /// Remove and return random members
     fn spop(&mut self, key: String, count: Option<usize>) -> Vec<String>;
@@ -51,6 +53,9 @@ pub trait SetOps {
     
     /// Store intersection result
     fn sinterstore(&mut self, dst: String, keys: Vec<String>) -> usize;
+
+    /// Size of the intersection, counting at most `limit` members (0 = all)
+    fn sintercard(&mut self, keys: &[String], limit: usize) -> DbResult<usize>;
     
     /// Difference of sets
     fn sdiff(&mut self, keys: Vec<String>) -> HashSet<String>;
@@ -58,17 +63,19 @@ pub trait SetOps {
     /// Store difference result
     fn sdiffstore(&mut self, dst: String, keys: Vec<String>) -> usize;
     
-    /// Scan set members with cursor
-    fn sscan(&mut self, key: String, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> (u64, Vec<String>);
+    /// Scan set members in cursor order (see `ops::cursor`). MATCH is
+    /// applied after a page of `count` members is taken.
+    fn sscan(&mut self, key: &str, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> DbResult<(u64, Vec<String>)>;
 }
 
 impl SetOps for DB {
-    fn sadd(&mut self, key: String, members: Vec<String>) -> Result<usize, String> {
+    fn sadd(&mut self, key: String, members: Vec<String>) -> DbResult<usize> {
         self.check_expiration(&key);
 
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
+        let entry = self.items.get_or_insert_with(key.clone(), || Entry {
             value: DataType::Set(HashSet::new()),
             expires_at: None,
+            field_expires: None,
         });
 
         match &mut entry.value {
@@ -81,14 +88,15 @@ impl SetOps for DB {
                 }
                 if added > 0 {
                     self.increment_changes();
+                    self.notify_set(&key);
                 }
                 Ok(added)
             }
-            _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+            _ => Err(DbError::WrongType),
         }
     }
 
-    fn srem(&mut self, key: String, member: String) -> Result<usize, String> {
+    fn srem(&mut self, key: String, member: String) -> DbResult<usize> {
         if !self.check_expiration(&key) {
             return Ok(0);
         }
@@ -98,19 +106,20 @@ impl SetOps for DB {
                 DataType::Set(set) => {
                     if set.remove(&member) {
                         self.increment_changes();
+                        self.notify_set(&key);
                         Ok(1)
                     } else {
                         Ok(0)
                     }
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(0)
         }
     }
 
-    fn srem_multi(&mut self, key: String, members: Vec<String>) -> Result<usize, String> {
+    fn srem_multi(&mut self, key: String, members: Vec<String>) -> DbResult<usize> {
         let mut count = 0;
         for member in members {
             count += self.srem(key.clone(), member)?;
@@ -118,74 +127,74 @@ impl SetOps for DB {
         Ok(count)
     }
 
-    fn smembers(&mut self, key: String) -> Result<Vec<String>, String> {
-        if !self.check_expiration(&key) {
+    fn smembers(&mut self, key: &str) -> DbResult<Vec<String>> {
+        if !self.check_expiration(key) {
             return Ok(vec![]);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::Set(set) => Ok(set.iter().cloned().collect()),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(vec![])
         }
     }
 
-    fn sismember(&mut self, key: String, member: String) -> Result<bool, String> {
-        if !self.check_expiration(&key) {
+    fn sismember(&mut self, key: &str, member: &str) -> DbResult<bool> {
+        if !self.check_expiration(key) {
             return Ok(false);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
-                DataType::Set(set) => Ok(set.contains(&member)),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                DataType::Set(set) => Ok(set.contains(member)),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(false)
         }
     }
 
-    fn smismember(&mut self, key: String, members: Vec<String>) -> Result<Vec<bool>, String> {
-        if !self.check_expiration(&key) {
+    fn smismember(&mut self, key: &str, members: Vec<String>) -> DbResult<Vec<bool>> {
+        if !self.check_expiration(key) {
             return Ok(vec![false; members.len()]);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::Set(set) => {
                     Ok(members.iter().map(|m| set.contains(m)).collect())
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(vec![false; members.len()])
         }
     }
 
-    fn scard(&mut self, key: String) -> Result<usize, String> {
-        if !self.check_expiration(&key) {
+    fn scard(&mut self, key: &str) -> DbResult<usize> {
+        if !self.check_expiration(key) {
             return Ok(0);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::Set(set) => Ok(set.len()),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(0)
         }
     }
 
-    fn srandmember(&mut self, key: String, count: Option<i64>) -> Vec<String> {
-        if !self.check_expiration(&key) {
+    fn srandmember(&mut self, key: &str, count: Option<i64>) -> Vec<String> {
+        if !self.check_expiration(key) {
             return vec![];
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::Set(set) = &entry.value {
                 let mut rng = rand::thread_rng();
                 
@@ -244,6 +253,7 @@ impl SetOps for DB {
 
                 if !result.is_empty() {
                     self.increment_changes();
+                    self.notify_set(&key);
                 }
                 return result;
             }
@@ -278,11 +288,13 @@ impl SetOps for DB {
         let result = self.sunion(keys);
         let len = result.len();
         
-        self.items.insert(dst, Entry {
+        self.items.insert(dst.clone(), Entry {
             value: DataType::Set(result),
             expires_at: None,
+            field_expires: None,
         });
         self.increment_changes();
+        self.notify_set(&dst);
         
         len
     }
@@ -330,15 +342,45 @@ impl SetOps for DB {
         let result = self.sinter(keys);
         let len = result.len();
         
-        self.items.insert(dst, Entry {
+        self.items.insert(dst.clone(), Entry {
             value: DataType::Set(result),
             expires_at: None,
+            field_expires: None,
         });
         self.increment_changes();
+        self.notify_set(&dst);
         
         len
     }
 
+    fn sintercard(&mut self, keys: &[String], limit: usize) -> DbResult<usize> {
+        if !keys.iter().all(|key| self.check_expiration(key)) {
+            return Ok(0);
+        }
+        let mut sets = Vec::with_capacity(keys.len());
+        for key in keys {
+            match self.items.get(key).map(|e| &e.value) {
+                Some(DataType::Set(set)) => sets.push(set),
+                Some(_) => return Err(DbError::WrongType),
+                None => {
+                    sets.clear();
+                    break;
+                }
+            }
+        }
+        // Walk the smallest set and look its members up in the others
+        sets.sort_by_key(|set| set.len());
+        let Some((smallest, others)) = sets.split_first() else {
+            return Ok(0);
+        };
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        Ok(smallest
+            .iter()
+            .filter(|member| others.iter().all(|set| set.contains(*member)))
+            .take(limit)
+            .count())
+    }
+
     fn sdiff(&mut self, keys: Vec<String>) -> HashSet<String> {
         if keys.is_empty() {
             return HashSet::new();
@@ -376,58 +418,39 @@ impl SetOps for DB {
         let result = self.sdiff(keys);
         let len = result.len();
         
-        self.items.insert(dst, Entry {
+        self.items.insert(dst.clone(), Entry {
             value: DataType::Set(result),
             expires_at: None,
+            field_expires: None,
         });
         self.increment_changes();
+        self.notify_set(&dst);
         
         len
     }
 
-    fn sscan(&mut self, key: String, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> (u64, Vec<String>) {
-        if !self.check_expiration(&key) {
-            return (0, vec![]);
+    fn sscan(&mut self, key: &str, cursor: u64, pattern: Option<&str>, count: Option<usize>) -> DbResult<(u64, Vec<String>)> {
+        if !self.check_expiration(key) {
+            return Ok((0, vec![]));
         }
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Set(set) = &entry.value {
-                let count = count.unwrap_or(10);
-                let members: Vec<String> = set.iter().cloned().collect();
-                let total = members.len();
-
-                if total == 0 {
-                    return (0, vec![]);
-                }
-
-                let start = cursor as usize;
-                if start >= total {
-                    return (0, vec![]);
-                }
-
-                let mut result = Vec::new();
-                let mut end = start;
-
-                for (i, member) in members.iter().enumerate().skip(start) {
-                    if result.len() >= count {
-                        break;
-                    }
-
-                    let matches = pattern
-                        .map(|p| glob_match(p, member))
-                        .unwrap_or(true);
-
-                    if matches {
-                        result.push(member.clone());
-                    }
-                    end = i + 1;
-                }
-
-                let next_cursor = if end >= total { 0 } else { end as u64 };
-                return (next_cursor, result);
+        match self.items.get(key).map(|entry| &entry.value) {
+            Some(DataType::Set(set)) => {
+                let (next, page) = scan_page(
+                    set.iter().map(|member| (member.as_str(), member)),
+                    cursor,
+                    count.unwrap_or(10),
+                );
+                let members = page
+                    .into_iter()
+                    .filter(|member| pattern.is_none_or(|p| glob_match(p, member)))
+                    .cloned()
+                    .collect();
+                Ok((next, members))
             }
+            Some(_) => Err(DbError::WrongType),
+            None => Ok((0, vec![])),
         }
-        (0, vec![])
     }
 }
 
@@ -480,6 +503,7 @@ fn glob_match(pattern: &str, text: &str) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::ops::hash::HashOps;
 
     #[test]
     fn test_set_ops() {
@@ -487,9 +511,9 @@ mod tests {
         
         assert_eq!(db.sadd("myset".to_string(), vec!["a".to_string(), "b".to_string(), "c".to_string()]).unwrap(), 3);
         assert_eq!(db.sadd("myset".to_string(), vec!["a".to_string()]).unwrap(), 0);
-        assert_eq!(db.scard("myset".to_string()).unwrap(), 3);
-        assert!(db.sismember("myset".to_string(), "a".to_string()).unwrap());
-        assert!(!db.sismember("myset".to_string(), "d".to_string()).unwrap());
+        assert_eq!(db.scard("myset").unwrap(), 3);
+        assert!(db.sismember("myset", "a").unwrap());
+        assert!(!db.sismember("myset", "d").unwrap());
     }
 
     #[test]
@@ -510,5 +534,12 @@ mod tests {
         let diff = db.sdiff(vec!["set1".to_string(), "set2".to_string()]);
         assert_eq!(diff.len(), 1);
         assert!(diff.contains("a"));
+
+        let keys = vec!["set1".to_string(), "set2".to_string()];
+        assert_eq!(db.sintercard(&keys, 0).unwrap(), 2);
+        assert_eq!(db.sintercard(&keys, 1).unwrap(), 1);
+        assert_eq!(db.sintercard(&["set1".to_string(), "missing".to_string()], 0).unwrap(), 0);
+        db.hset("h".to_string(), "f".to_string(), "v".to_string()).unwrap();
+        assert!(db.sintercard(&["set1".to_string(), "h".to_string()], 0).is_err());
     }
 }
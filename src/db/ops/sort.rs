@@ -0,0 +1,188 @@
+//! SORT.
+//!
+//! Sorts the elements of a list, set or sorted set, optionally by weights
+//! read from other keys (`BY weight_*`, or `BY weight_*->field` for a hash
+//! field) and returning values looked up the same way (`GET`). Elements are
+//! compared as numbers unless `ALPHA` is given.
+
+use crate::db::core::DB;
+use crate::db::error::{DbError, DbResult};
+use crate::db::ops::generic::GenericOps;
+use crate::db::ops::hash::HashOps;
+use crate::db::ops::string::StringOps;
+use crate::db::types::{DataType, Entry};
+use std::cmp::Ordering;
+
+/// Options of a SORT
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortOptions {
+    /// Pattern for the weights; one without `*` keeps the original order
+    pub by: Option<String>,
+    /// Offset and count of the elements to return
+    pub limit: Option<(usize, usize)>,
+    /// Patterns to look up for each element, `#` for the element itself
+    pub get: Vec<String>,
+    pub desc: bool,
+    pub alpha: bool,
+}
+
+/// SORT operations trait
+pub trait SortOps {
+    /// Sort the elements of `key`. Without GET patterns each element is
+    /// returned once; with them, one value per pattern and element, None
+    /// where the looked up key or field does not exist.
+    fn sort(&mut self, key: &str, opts: &SortOptions) -> DbResult<Vec<Option<String>>>;
+
+    /// Replace `dst` with a list of `values` (missing ones stored as empty
+    /// strings), deleting it when there are none. Returns the list length.
+    fn sort_store(&mut self, dst: &str, values: Vec<Option<String>>) -> usize;
+}
+
+impl DB {
+    /// Value of a SORT pattern for `element`: `*` is replaced by the element
+    /// and a `->field` suffix reads a hash field instead of a string
+    fn sort_lookup(&mut self, pattern: &str, element: &str) -> Option<String> {
+        if pattern == "#" {
+            return Some(element.to_string());
+        }
+        let (key_pattern, field) = match pattern.split_once("->") {
+            Some((key, field)) if !field.is_empty() => (key, Some(field)),
+            _ => (pattern, None),
+        };
+        let key = key_pattern.replacen('*', element, 1);
+        match field {
+            Some(field) => self.hget(&key, field).ok().flatten(),
+            None => self.get(&key).ok().flatten(),
+        }
+    }
+}
+
+impl SortOps for DB {
+    fn sort(&mut self, key: &str, opts: &SortOptions) -> DbResult<Vec<Option<String>>> {
+        let mut elements: Vec<String> = if !self.check_expiration(key) {
+            Vec::new()
+        } else {
+            match self.items.get(key).map(|e| &e.value) {
+                None => Vec::new(),
+                Some(DataType::List(list)) => list.iter().cloned().collect(),
+                Some(DataType::Set(set)) => set.iter().cloned().collect(),
+                Some(DataType::ZSet(zset)) => zset.scores.iter().map(|(member, _)| member.to_string()).collect(),
+                Some(_) => return Err(DbError::WrongType),
+            }
+        };
+
+        let sorting = opts.by.as_ref().is_none_or(|by| by.contains('*'));
+        if sorting {
+            let weights: Vec<Option<String>> = match &opts.by {
+                Some(by) => elements.iter().map(|e| self.sort_lookup(by, e)).collect(),
+                None => elements.iter().cloned().map(Some).collect(),
+            };
+            let keyed = weights.into_iter().zip(elements);
+            elements = if opts.alpha {
+                let mut keyed: Vec<(Option<String>, String)> = keyed.collect();
+                keyed.sort();
+                keyed.into_iter().map(|(_, element)| element).collect()
+            } else {
+                // Missing weights count as 0
+                let mut scored = Vec::new();
+                for (weight, element) in keyed {
+                    let score = match weight {
+                        Some(w) => w.trim().parse::<f64>().ok().filter(|s| !s.is_nan()).ok_or_else(|| {
+                            DbError::InvalidValue("One or more scores can't be converted into double".to_string())
+                        })?,
+                        None => 0.0,
+                    };
+                    scored.push((score, element));
+                }
+                scored.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal).then_with(|| a.1.cmp(&b.1)));
+                scored.into_iter().map(|(_, element)| element).collect()
+            };
+            if opts.desc {
+                elements.reverse();
+            }
+        }
+
+        if let Some((offset, count)) = opts.limit {
+            elements = elements.into_iter().skip(offset).take(count).collect();
+        }
+
+        if opts.get.is_empty() {
+            return Ok(elements.into_iter().map(Some).collect());
+        }
+        let mut values = Vec::with_capacity(elements.len() * opts.get.len());
+        for element in &elements {
+            for pattern in &opts.get {
+                values.push(self.sort_lookup(pattern, element));
+            }
+        }
+        Ok(values)
+    }
+
+    fn sort_store(&mut self, dst: &str, values: Vec<Option<String>>) -> usize {
+        let len = values.len();
+        if len == 0 {
+            self.del(dst);
+            return 0;
+        }
+        self.items.insert(dst.to_string(), Entry {
+            value: DataType::List(values.into_iter().map(Option::unwrap_or_default).collect()),
+            expires_at: None,
+            field_expires: None,
+        });
+        self.increment_changes();
+        self.notify_set(dst);
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::ops::hash::HashOps;
+    use crate::db::ops::list::ListOps;
+    use crate::db::ops::set::SetOps;
+
+    fn strings(values: &[&str]) -> Vec<Option<String>> {
+        values.iter().map(|v| Some(v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_sort() {
+        let mut db = DB::new();
+        db.rpush("l".to_string(), vec!["3".into(), "10".into(), "1".into()]).unwrap();
+        assert_eq!(db.sort("l", &SortOptions::default()).unwrap(), strings(&["1", "3", "10"]));
+
+        let alpha = SortOptions { alpha: true, desc: true, ..Default::default() };
+        assert_eq!(db.sort("l", &alpha).unwrap(), strings(&["3", "10", "1"]));
+
+        let limited = SortOptions { limit: Some((1, 5)), ..Default::default() };
+        assert_eq!(db.sort("l", &limited).unwrap(), strings(&["3", "10"]));
+
+        db.sadd("s".to_string(), vec!["a".into(), "b".into(), "c".into()]).unwrap();
+        db.set("w_a".to_string(), "3".to_string());
+        db.set("w_b".to_string(), "1".to_string());
+        db.hset("h_a".to_string(), "name".to_string(), "Ann".to_string()).unwrap();
+        let by = SortOptions {
+            by: Some("w_*".to_string()),
+            get: vec!["#".to_string(), "h_*->name".to_string()],
+            ..Default::default()
+        };
+        // c has no weight and sorts as 0
+        assert_eq!(
+            db.sort("s", &by).unwrap(),
+            vec![Some("c".into()), None, Some("b".into()), None, Some("a".into()), Some("Ann".into())]
+        );
+
+        let nosort = SortOptions { by: Some("nosort".to_string()), ..Default::default() };
+        assert_eq!(db.sort("l", &nosort).unwrap(), strings(&["3", "10", "1"]));
+
+        assert!(db.sort("s", &SortOptions::default()).is_err());
+        db.set("str".to_string(), "x".to_string());
+        assert!(matches!(db.sort("str", &SortOptions::default()), Err(DbError::WrongType)));
+
+        assert_eq!(db.sort_store("dst", vec![Some("1".into()), None]), 2);
+        assert_eq!(db.lrange("dst", 0, -1).unwrap(), vec!["1", ""]);
+        assert_eq!(db.sort_store("dst", vec![]), 0);
+        assert!(!db.exists("dst"));
+    }
+}
@@ -2,37 +2,51 @@
 //!
 //! Redis-compatible stream (Kafka-like) operations.
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
-use crate::db::ops::generic::GenericOps;
-use crate::db::types::{DataType, Entry, StreamData};
+use crate::db::types::{DataType, StreamData, StreamEntry, StreamId, INVALID_STREAM_ID};
 use std::collections::HashMap;
 use std::sync::atomic::Ordering;
 
+/// Stream entries as (ID, field-value pairs), in ID order
+pub type StreamEntries = Vec<(String, Vec<(String, String)>)>;
+
 /// Stream operations trait
 pub trait StreamOps {
     /// Add entry to stream (XADD)
-    fn xadd(&mut self, key: String, id: Option<String>, fields: Vec<(String, String)>) -> Result<String, String>;
+    fn xadd(&mut self, key: String, id: Option<String>, fields: Vec<(String, String)>) -> DbResult<String>;
     
     /// Get stream length (XLEN)
-    fn xlen(&mut self, key: String) -> usize;
+    fn xlen(&mut self, key: &str) -> DbResult<usize>;
     
     /// Get range of entries (XRANGE)
-    fn xrange(&mut self, key: String, start: String, end: String, count: Option<usize>) -> Vec<(String, Vec<(String, String)>)>;
+    fn xrange(&mut self, key: &str, start: &str, end: &str, count: Option<usize>) -> DbResult<StreamEntries>;
     
     /// Get reverse range (XREVRANGE)
-    fn xrevrange(&mut self, key: String, end: String, start: String, count: Option<usize>) -> Vec<(String, Vec<(String, String)>)>;
+    fn xrevrange(&mut self, key: &str, end: &str, start: &str, count: Option<usize>) -> DbResult<StreamEntries>;
     
-    /// Read from streams (XREAD) - simplified version
-    fn xread(&mut self, keys: Vec<String>, ids: Vec<String>, count: Option<usize>) -> Vec<(String, Vec<(String, Vec<(String, String)>)>)>;
+    /// Read from streams (XREAD) - simplified version.
+    ///
+    /// `count` caps the entries per stream and `max_entries` caps the whole
+    /// reply. Under the total cap entries are handed out round-robin, one per
+    /// stream per round in argument order, so a busy stream cannot crowd out
+    /// the others. Streams are returned in argument order, entries in ID order.
+    fn xread(
+        &mut self,
+        keys: Vec<String>,
+        ids: Vec<String>,
+        count: Option<usize>,
+        max_entries: Option<usize>,
+    ) -> DbResult<Vec<(String, StreamEntries)>>;
     
-    /// Trim stream (XTRIM)
-    fn xtrim(&mut self, key: String, maxlen: usize, approximate: bool) -> usize;
+    /// Trim stream (XTRIM), returning the number of entries removed
+    fn xtrim(&mut self, key: String, trim: StreamTrim) -> DbResult<usize>;
     
     /// Delete entries (XDEL)
-    fn xdel(&mut self, key: String, ids: Vec<String>) -> usize;
+    fn xdel(&mut self, key: String, ids: Vec<String>) -> DbResult<usize>;
     
     /// Get stream info (XINFO STREAM)
-    fn xinfo_stream(&mut self, key: String) -> Option<StreamInfo>;
+    fn xinfo_stream(&mut self, key: &str) -> DbResult<Option<StreamInfo>>;
 }
 
 /// Stream information
@@ -44,166 +58,182 @@ pub struct StreamInfo {
     pub last_generated_id: String,
 }
 
-impl StreamOps for DB {
-    fn xadd(&mut self, key: String, id: Option<String>, fields: Vec<(String, String)>) -> Result<String, String> {
-        self.check_expiration(&key);
-
-        let fields_map: HashMap<String, String> = fields.into_iter().collect();
-
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
-            value: DataType::Stream(StreamData::new()),
-            expires_at: None,
-        });
+/// How a stream is trimmed by XTRIM and XADD
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamTrim {
+    /// Keep at most this many of the newest entries
+    MaxLen(usize),
+    /// Drop entries with an ID lower than this one
+    MinId(StreamId),
+}
 
-        match &mut entry.value {
-            DataType::Stream(stream) => {
-                let entry_id = stream.add(id, fields_map);
-                self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-                Ok(entry_id)
-            }
-            _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+impl StreamTrim {
+    /// Parse `MAXLEN|MINID [=|~] threshold` from the start of `args`,
+    /// returning the strategy and how many arguments it used. `~` is
+    /// accepted but trimming is always exact.
+    pub fn parse(args: &[String]) -> Result<(StreamTrim, usize), String> {
+        let strategy = args.first().map(|s| s.to_uppercase()).unwrap_or_default();
+        let mut used = 1;
+        if matches!(args.get(used).map(String::as_str), Some("=") | Some("~")) {
+            used += 1;
         }
+        let threshold = args.get(used).ok_or_else(|| "syntax error".to_string())?;
+        let trim = match strategy.as_str() {
+            "MAXLEN" => StreamTrim::MaxLen(
+                threshold
+                    .parse()
+                    .map_err(|_| "value is not an integer or out of range".to_string())?,
+            ),
+            "MINID" => StreamTrim::MinId(StreamId::parse(threshold, 0).ok_or_else(|| INVALID_STREAM_ID.to_string())?),
+            _ => return Err("syntax error".to_string()),
+        };
+        Ok((trim, used + 1))
     }
 
-    fn xlen(&mut self, key: String) -> usize {
-        if !self.check_expiration(&key) {
-            return 0;
-        }
-
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Stream(stream) = &entry.value {
-                return stream.entries.len();
-            }
+    /// Arguments that replay this trim through XTRIM
+    pub fn to_args(&self) -> Vec<String> {
+        match self {
+            StreamTrim::MaxLen(max_len) => vec!["MAXLEN".to_string(), max_len.to_string()],
+            StreamTrim::MinId(id) => vec!["MINID".to_string(), id.to_string()],
         }
-        0
     }
+}
 
-    fn xrange(&mut self, key: String, start: String, end: String, count: Option<usize>) -> Vec<(String, Vec<(String, String)>)> {
-        if !self.check_expiration(&key) {
-            return vec![];
-        }
+fn is_stream(value: &DataType) -> bool {
+    matches!(value, DataType::Stream(_))
+}
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Stream(stream) = &entry.value {
-                let start_id = if start == "-" { "" } else { &start };
-                let end_id = if end == "+" { "\u{FFFF}" } else { &end };
-
-                let mut results: Vec<_> = stream.entries
-                    .iter()
-                    .filter(|e| e.id.as_str() >= start_id && e.id.as_str() <= end_id)
-                    .map(|e| {
-                        let fields: Vec<(String, String)> = e.fields.iter()
-                            .map(|(k, v)| (k.clone(), v.clone()))
-                            .collect();
-                        (e.id.clone(), fields)
-                    })
-                    .collect();
-
-                if let Some(n) = count {
-                    results.truncate(n);
-                }
+/// Entries of a stream as (id, fields) pairs
+fn entry_pairs<'a>(entries: impl Iterator<Item = &'a StreamEntry>) -> StreamEntries {
+    entries
+        .map(|e| {
+            let fields: Vec<(String, String)> = e.fields.iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            (e.id.to_string(), fields)
+        })
+        .collect()
+}
 
-                return results;
-            }
+impl StreamOps for DB {
+    fn xadd(&mut self, key: String, id: Option<String>, fields: Vec<(String, String)>) -> DbResult<String> {
+        let fields_map: HashMap<String, String> = fields.into_iter().collect();
+
+        // Checked before the stream is created, so a rejected ID leaves no empty key
+        let entry_id = match self.typed_value(&key, is_stream)? {
+            Some(DataType::Stream(stream)) => stream.next_id(id.as_deref()),
+            _ => StreamData::new().next_id(id.as_deref()),
         }
-        vec![]
-    }
+        .map_err(DbError::InvalidValue)?;
 
-    fn xrevrange(&mut self, key: String, end: String, start: String, count: Option<usize>) -> Vec<(String, Vec<(String, String)>)> {
-        if !self.check_expiration(&key) {
-            return vec![];
+        let entry = self.typed_entry_mut(&key, is_stream, || DataType::Stream(StreamData::new()))?;
+        match &mut entry.value {
+            DataType::Stream(stream) => stream.add(entry_id, fields_map),
+            _ => return Err(DbError::WrongType),
         }
+        self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(&key);
+        Ok(entry_id.to_string())
+    }
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Stream(stream) = &entry.value {
-                let start_id = if start == "-" { "" } else { &start };
-                let end_id = if end == "+" { "\u{FFFF}" } else { &end };
-
-                let mut results: Vec<_> = stream.entries
-                    .iter()
-                    .rev()
-                    .filter(|e| e.id.as_str() >= start_id && e.id.as_str() <= end_id)
-                    .map(|e| {
-                        let fields: Vec<(String, String)> = e.fields.iter()
-                            .map(|(k, v)| (k.clone(), v.clone()))
-                            .collect();
-                        (e.id.clone(), fields)
-                    })
-                    .collect();
-
-                if let Some(n) = count {
-                    results.truncate(n);
-                }
+    fn xlen(&mut self, key: &str) -> DbResult<usize> {
+        Ok(match self.typed_value(key, is_stream)? {
+            Some(DataType::Stream(stream)) => stream.entries.len(),
+            _ => 0,
+        })
+    }
 
-                return results;
+    fn xrange(&mut self, key: &str, start: &str, end: &str, count: Option<usize>) -> DbResult<StreamEntries> {
+        let (start, end) = range_bounds(start, end)?;
+        Ok(match self.typed_value(key, is_stream)? {
+            Some(DataType::Stream(stream)) => {
+                entry_pairs(stream.range(start, end).iter().take(count.unwrap_or(usize::MAX)))
             }
-        }
-        vec![]
+            _ => vec![],
+        })
     }
 
-    fn xread(&mut self, keys: Vec<String>, ids: Vec<String>, count: Option<usize>) -> Vec<(String, Vec<(String, Vec<(String, String)>)>)> {
+    fn xrevrange(&mut self, key: &str, end: &str, start: &str, count: Option<usize>) -> DbResult<StreamEntries> {
+        let (start, end) = range_bounds(start, end)?;
+        Ok(match self.typed_value(key, is_stream)? {
+            Some(DataType::Stream(stream)) => {
+                entry_pairs(stream.range(start, end).iter().rev().take(count.unwrap_or(usize::MAX)))
+            }
+            _ => vec![],
+        })
+    }
+
+    fn xread(
+        &mut self,
+        keys: Vec<String>,
+        ids: Vec<String>,
+        count: Option<usize>,
+        max_entries: Option<usize>,
+    ) -> DbResult<Vec<(String, StreamEntries)>> {
         let mut results = Vec::new();
 
         for (key, last_id) in keys.iter().zip(ids.iter()) {
-            if !self.check_expiration(key) {
-                continue;
+            let stream = match self.typed_value(key, is_stream)? {
+                Some(DataType::Stream(stream)) => stream,
+                _ => continue,
+            };
+            // `$` reads only what arrives after the stream's current top
+            let after = match last_id.as_str() {
+                "$" => stream.last_id,
+                id => StreamId::parse(id, 0).ok_or_else(|| DbError::InvalidValue(INVALID_STREAM_ID.to_string()))?,
+            };
+            let from = stream.entries.partition_point(|e| e.id <= after);
+            let entries = entry_pairs(stream.entries[from..].iter().take(count.unwrap_or(usize::MAX)));
+
+            if !entries.is_empty() {
+                results.push((key.clone(), entries));
             }
+        }
 
-            if let Some(entry) = self.items.get(key) {
-                if let DataType::Stream(stream) = &entry.value {
-                    let start_id = if last_id == "0" || last_id == "0-0" {
-                        ""
-                    } else {
-                        last_id.as_str()
-                    };
-
-                    let mut entries: Vec<_> = stream.entries
-                        .iter()
-                        .filter(|e| e.id.as_str() > start_id)
-                        .map(|e| {
-                            let fields: Vec<(String, String)> = e.fields.iter()
-                                .map(|(k, v)| (k.clone(), v.clone()))
-                                .collect();
-                            (e.id.clone(), fields)
-                        })
-                        .collect();
-
-                    if let Some(n) = count {
-                        entries.truncate(n);
-                    }
-
-                    if !entries.is_empty() {
-                        results.push((key.clone(), entries));
-                    }
-                }
+        if let Some(cap) = max_entries {
+            let quotas = round_robin_quotas(results.iter().map(|(_, e)| e.len()), cap);
+            for ((_, entries), quota) in results.iter_mut().zip(quotas) {
+                entries.truncate(quota);
             }
+            results.retain(|(_, entries)| !entries.is_empty());
         }
 
-        results
+        Ok(results)
     }
 
-    fn xtrim(&mut self, key: String, maxlen: usize, _approximate: bool) -> usize {
-        if !self.check_expiration(&key) {
-            return 0;
+    fn xtrim(&mut self, key: String, trim: StreamTrim) -> DbResult<usize> {
+        if self.typed_value(&key, is_stream)?.is_none() {
+            return Ok(0);
         }
 
         if let Some(entry) = self.items.get_mut(&key) {
             if let DataType::Stream(stream) = &mut entry.value {
-                let current_len = stream.entries.len();
-                if current_len > maxlen {
-                    let to_remove = current_len - maxlen;
+                // Entries are in ID order, so trimming drops a prefix
+                let to_remove = match &trim {
+                    StreamTrim::MaxLen(max_len) => stream.entries.len().saturating_sub(*max_len),
+                    StreamTrim::MinId(min_id) => {
+                        stream.entries.partition_point(|e| e.id < *min_id)
+                    }
+                };
+                if to_remove > 0 {
                     stream.entries.drain(0..to_remove);
                     self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-                    return to_remove;
+                    self.notify_set(&key);
                 }
+                return Ok(to_remove);
             }
         }
-        0
+        Ok(0)
     }
 
-    fn xdel(&mut self, key: String, ids: Vec<String>) -> usize {
-        if !self.check_expiration(&key) {
-            return 0;
+    fn xdel(&mut self, key: String, ids: Vec<String>) -> DbResult<usize> {
+        let ids = ids
+            .iter()
+            .map(|id| StreamId::parse(id, 0))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| DbError::InvalidValue(INVALID_STREAM_ID.to_string()))?;
+        if self.typed_value(&key, is_stream)?.is_none() {
+            return Ok(0);
         }
 
         if let Some(entry) = self.items.get_mut(&key) {
@@ -213,30 +243,58 @@ impl StreamOps for DB {
                 let deleted = original_len - stream.entries.len();
                 if deleted > 0 {
                     self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+                    self.notify_set(&key);
                 }
-                return deleted;
+                return Ok(deleted);
             }
         }
-        0
+        Ok(0)
     }
 
-    fn xinfo_stream(&mut self, key: String) -> Option<StreamInfo> {
-        if !self.check_expiration(&key) {
-            return None;
-        }
+    fn xinfo_stream(&mut self, key: &str) -> DbResult<Option<StreamInfo>> {
+        Ok(match self.typed_value(key, is_stream)? {
+            Some(DataType::Stream(stream)) => Some(StreamInfo {
+                length: stream.entries.len(),
+                first_entry: stream.entries.first().map(|e| e.id.to_string()),
+                last_entry: stream.entries.last().map(|e| e.id.to_string()),
+                last_generated_id: stream.last_id.to_string(),
+            }),
+            _ => None,
+        })
+    }
+}
+
+/// Parse the start and end of an XRANGE or XREVRANGE
+fn range_bounds(start: &str, end: &str) -> DbResult<(StreamId, StreamId)> {
+    match (StreamId::parse_bound(start, false), StreamId::parse_bound(end, true)) {
+        (Some(start), Some(end)) => Ok((start, end)),
+        _ => Err(DbError::InvalidValue(INVALID_STREAM_ID.to_string())),
+    }
+}
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::Stream(stream) = &entry.value {
-                return Some(StreamInfo {
-                    length: stream.entries.len(),
-                    first_entry: stream.entries.first().map(|e| e.id.clone()),
-                    last_entry: stream.entries.last().map(|e| e.id.clone()),
-                    last_generated_id: format!("{}", stream.last_id),
-                });
+/// Split `cap` entries over streams with the given number of available
+/// entries, one per stream per round in order.
+fn round_robin_quotas(available: impl Iterator<Item = usize>, cap: usize) -> Vec<usize> {
+    let available: Vec<usize> = available.collect();
+    let mut quotas = vec![0; available.len()];
+    let mut remaining = cap;
+    while remaining > 0 {
+        let mut granted = false;
+        for (quota, &avail) in quotas.iter_mut().zip(&available) {
+            if remaining == 0 {
+                break;
             }
+            if *quota < avail {
+                *quota += 1;
+                remaining -= 1;
+                granted = true;
+            }
+        }
+        if !granted {
+            break;
         }
-        None
     }
+    quotas
 }
 
 #[cfg(test)]
@@ -255,7 +313,7 @@ mod tests {
             ("field2".to_string(), "value2".to_string()),
         ]).unwrap();
         
-        assert_eq!(db.xlen("mystream".to_string()), 2);
+        assert_eq!(db.xlen("mystream"), Ok(2));
         assert!(id2 > id1);
     }
 
@@ -271,7 +329,139 @@ mod tests {
             ("b".to_string(), "2".to_string()),
         ]).unwrap();
         
-        let range = db.xrange("mystream".to_string(), "-".to_string(), "+".to_string(), None);
+        let range = db.xrange("mystream", "-", "+", None).unwrap();
         assert_eq!(range.len(), 2);
     }
+
+    #[test]
+    fn test_xread_max_entries_round_robin() {
+        let mut db = DB::new();
+        for i in 1..=5 {
+            db.xadd("hot".to_string(), Some(format!("{}-0", i)), vec![("n".to_string(), i.to_string())]).unwrap();
+        }
+        db.xadd("cold".to_string(), Some("1-0".to_string()), vec![("n".to_string(), "1".to_string())]).unwrap();
+
+        let keys = vec!["hot".to_string(), "cold".to_string()];
+        let ids = vec!["0".to_string(), "0".to_string()];
+        let reply = db.xread(keys.clone(), ids.clone(), None, Some(3)).unwrap();
+        assert_eq!(reply.len(), 2);
+        assert_eq!(reply[0].0, "hot");
+        assert_eq!(reply[0].1.len(), 2);
+        assert_eq!(reply[1].1.len(), 1);
+
+        // The per-stream COUNT still applies under the total cap
+        let reply = db.xread(keys, ids, Some(1), Some(10)).unwrap();
+        assert_eq!(reply.iter().map(|(_, e)| e.len()).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn test_xadd_id_rules() {
+        let mut db = DB::new();
+        let add = |db: &mut DB, id: &str| db.xadd("s".to_string(), Some(id.to_string()), vec![("f".to_string(), "v".to_string())]);
+
+        assert!(matches!(add(&mut db, "0-0"), Err(DbError::InvalidValue(_))));
+        assert!(matches!(add(&mut db, "abc"), Err(DbError::InvalidValue(_))));
+        assert!(!crate::db::GenericOps::exists(&db, "s"));
+
+        assert_eq!(add(&mut db, "5"), Ok("5-0".to_string()));
+        assert_eq!(add(&mut db, "5-*"), Ok("5-1".to_string()));
+        assert_eq!(add(&mut db, "1691234567-3"), Ok("1691234567-3".to_string()));
+        assert!(matches!(add(&mut db, "1691234567-3"), Err(DbError::InvalidValue(_))));
+        assert!(matches!(add(&mut db, "10-0"), Err(DbError::InvalidValue(_))));
+        assert!(matches!(add(&mut db, "5-*"), Err(DbError::InvalidValue(_))));
+        assert_eq!(add(&mut db, "1691234567-*"), Ok("1691234567-4".to_string()));
+
+        // Deleting the top entry does not let its ID be reused
+        db.xdel("s".to_string(), vec!["1691234567-4".to_string()]).unwrap();
+        assert!(matches!(add(&mut db, "1691234567-4"), Err(DbError::InvalidValue(_))));
+        let auto = db.xadd("s".to_string(), None, vec![]).unwrap();
+        assert!(StreamId::parse(&auto, 0).unwrap() > StreamId { ms: 1691234567, seq: 4 });
+    }
+
+    #[test]
+    fn test_xadd_exhausted_id() {
+        let mut db = DB::new();
+        let max = format!("{}-{}", u64::MAX, u64::MAX);
+        assert_eq!(db.xadd("s".to_string(), Some(max.clone()), vec![]), Ok(max));
+        match db.xadd("s".to_string(), None, vec![]) {
+            Err(DbError::InvalidValue(msg)) => assert!(msg.contains("exhausted"), "{}", msg),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(db.xlen("s"), Ok(1));
+    }
+
+    #[test]
+    fn test_xread_dollar_and_range_ends() {
+        let mut db = DB::new();
+        for id in ["1-0", "2-0"] {
+            db.xadd("s".to_string(), Some(id.to_string()), vec![]).unwrap();
+        }
+        // `$` is the current top, so nothing is new yet
+        assert!(db.xread(vec!["s".to_string()], vec!["$".to_string()], None, None).unwrap().is_empty());
+        db.xdel("s".to_string(), vec!["2-0".to_string()]).unwrap();
+        db.xadd("s".to_string(), Some("3-0".to_string()), vec![]).unwrap();
+        let read = db.xread(vec!["s".to_string()], vec!["0".to_string()], None, None).unwrap();
+        assert_eq!(read[0].1.len(), 2);
+        assert!(db.xread(vec!["s".to_string()], vec!["$".to_string()], None, None).unwrap().is_empty());
+        assert!(db.xread(vec!["s".to_string()], vec!["+".to_string()], None, None).is_err());
+
+        assert_eq!(db.xrange("s", "-", "+", None).unwrap().len(), 2);
+        assert!(db.xdel("s".to_string(), vec!["$".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_ids_compare_numerically() {
+        let mut db = DB::new();
+        for id in ["9-0", "10-0", "10-2", "100-0"] {
+            db.xadd("s".to_string(), Some(id.to_string()), vec![]).unwrap();
+        }
+        let ids = |entries: StreamEntries| entries.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+
+        assert_eq!(ids(db.xrange("s", "10", "10", None).unwrap()), vec!["10-0", "10-2"]);
+        assert_eq!(ids(db.xrevrange("s", "+", "10-1", Some(2)).unwrap()), vec!["100-0", "10-2"]);
+        let read = db.xread(vec!["s".to_string()], vec!["10-0".to_string()], None, None).unwrap();
+        assert_eq!(ids(read[0].1.clone()), vec!["10-2", "100-0"]);
+        assert!(db.xrange("s", "x", "+", None).is_err());
+    }
+
+    #[test]
+    fn test_xtrim_maxlen_and_minid() {
+        let mut db = DB::new();
+        for id in ["5-0", "9-1", "10-0", "12-3"] {
+            db.xadd("s".to_string(), Some(id.to_string()), vec![("n".to_string(), id.to_string())]).unwrap();
+        }
+
+        // IDs compare numerically, so 9-1 sorts before 10-0
+        assert_eq!(db.xtrim("s".to_string(), StreamTrim::MinId(StreamId { ms: 10, seq: 0 })), Ok(2));
+        assert_eq!(db.xtrim("s".to_string(), StreamTrim::MinId(StreamId { ms: 10, seq: 0 })), Ok(0));
+        assert_eq!(db.xtrim("s".to_string(), StreamTrim::MaxLen(1)), Ok(1));
+        let ids: Vec<_> = db.xrange("s", "-", "+", None).unwrap();
+        assert_eq!(ids[0].0, "12-3");
+        assert_eq!(db.xtrim("missing".to_string(), StreamTrim::MaxLen(0)), Ok(0));
+    }
+
+    #[test]
+    fn test_stream_trim_parse() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(StreamTrim::parse(&args(&["MAXLEN", "10", "*"])), Ok((StreamTrim::MaxLen(10), 2)));
+        assert_eq!(StreamTrim::parse(&args(&["minid", "~", "5-0"])), Ok((StreamTrim::MinId(StreamId { ms: 5, seq: 0 }), 3)));
+        assert!(StreamTrim::parse(&args(&["MAXLEN", "ten"])).is_err());
+        assert!(StreamTrim::parse(&args(&["MINID", "x-1"])).is_err());
+        assert!(StreamTrim::parse(&args(&["MAXLEN"])).is_err());
+        assert_eq!(StreamTrim::MinId(StreamId { ms: 5, seq: 0 }).to_args(), args(&["MINID", "5-0"]));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        use crate::db::HashOps;
+        let mut db = DB::new();
+        db.hset("h".to_string(), "f".to_string(), "v".to_string()).unwrap();
+
+        assert_eq!(db.xadd("h".to_string(), None, vec![("a".to_string(), "1".to_string())]), Err(DbError::WrongType));
+        assert_eq!(db.xlen("h"), Err(DbError::WrongType));
+        assert!(db.xrange("h", "-", "+", None).is_err());
+        assert!(db.xread(vec!["h".to_string()], vec!["0".to_string()], None, None).is_err());
+        assert_eq!(db.xtrim("h".to_string(), StreamTrim::MaxLen(0)), Err(DbError::WrongType));
+        assert_eq!(db.hlen("h"), 1);
+    }
 }
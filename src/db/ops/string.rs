@@ -2,15 +2,83 @@
 //!
 //! Basic key-value operations for string data type.
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
 use crate::db::ops::generic::GenericOps;
 use crate::db::types::{DataType, Entry};
 use std::sync::atomic::Ordering;
 
+/// Optional bounds for counter increments (`INCRBY key delta [MIN n] [MAX n] [CLAMP]`)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CounterBounds {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    /// Clamp to the bound instead of rejecting the increment
+    pub clamp: bool,
+}
+
+impl CounterBounds {
+    /// Parse `[MIN n] [MAX n] [CLAMP]` options
+    pub fn parse(args: &[String]) -> DbResult<Self> {
+        let mut bounds = CounterBounds::default();
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].to_uppercase().as_str() {
+                "MIN" | "MAX" => {
+                    let value = args
+                        .get(i + 1)
+                        .and_then(|v| v.parse::<i64>().ok())
+                        .ok_or_else(|| DbError::Syntax("value is not an integer or out of range".to_string()))?;
+                    if args[i].eq_ignore_ascii_case("MIN") {
+                        bounds.min = Some(value);
+                    } else {
+                        bounds.max = Some(value);
+                    }
+                    i += 2;
+                }
+                "CLAMP" => {
+                    bounds.clamp = true;
+                    i += 1;
+                }
+                _ => return Err(DbError::Syntax("syntax error".to_string())),
+            }
+        }
+        if let (Some(min), Some(max)) = (bounds.min, bounds.max) {
+            if min > max {
+                return Err(DbError::Syntax("MIN is greater than MAX".to_string()));
+            }
+        }
+        Ok(bounds)
+    }
+
+    /// Apply the bounds to a candidate value
+    fn apply(&self, value: i64) -> DbResult<i64> {
+        if let Some(max) = self.max {
+            if value > max {
+                return if self.clamp {
+                    Ok(max)
+                } else {
+                    Err(DbError::InvalidValue("increment would exceed the counter maximum".to_string()))
+                };
+            }
+        }
+        if let Some(min) = self.min {
+            if value < min {
+                return if self.clamp {
+                    Ok(min)
+                } else {
+                    Err(DbError::InvalidValue("decrement would go below the counter minimum".to_string()))
+                };
+            }
+        }
+        Ok(value)
+    }
+}
+
 /// String operations trait
 pub trait StringOps {
     /// Get the value of a key
-    fn get(&mut self, key: String) -> Result<Option<String>, String>;
+    fn get(&mut self, key: &str) -> DbResult<Option<String>>;
     
     /// Set the value of a key
     fn set(&mut self, key: String, value: String);
@@ -25,7 +93,7 @@ pub trait StringOps {
     fn setnx(&mut self, key: String, value: String) -> bool;
     
     /// Get old value and set new value
-    fn getset(&mut self, key: String, value: String) -> Result<Option<String>, String>;
+    fn getset(&mut self, key: String, value: String) -> DbResult<Option<String>>;
     
     /// Get multiple values
     fn mget(&mut self, keys: Vec<String>) -> Vec<Option<String>>;
@@ -36,44 +104,48 @@ pub trait StringOps {
     /// Set multiple only if none exist
     fn msetnx(&mut self, pairs: Vec<(String, String)>) -> bool;
     
-    /// Append to a string
-    fn append(&mut self, key: String, value: String) -> usize;
+    /// Append to a string, creating it if missing. Returns the new length.
+    fn append(&mut self, key: String, value: String) -> DbResult<usize>;
     
     /// Get string length
-    fn strlen(&mut self, key: String) -> usize;
+    fn strlen(&mut self, key: &str) -> usize;
     
     /// Get substring
-    fn getrange(&mut self, key: String, start: i64, end: i64) -> String;
+    fn getrange(&mut self, key: &str, start: i64, end: i64) -> String;
     
     /// Set substring
     fn setrange(&mut self, key: String, offset: usize, value: String) -> usize;
     
     /// Increment integer value
-    fn incr(&mut self, key: String) -> Result<i64, String>;
+    fn incr(&mut self, key: &str) -> DbResult<i64>;
     
     /// Decrement integer value
-    fn decr(&mut self, key: String) -> Result<i64, String>;
+    fn decr(&mut self, key: &str) -> DbResult<i64>;
     
     /// Increment by amount
-    fn incrby(&mut self, key: String, delta: i64) -> Result<i64, String>;
+    fn incrby(&mut self, key: &str, delta: i64) -> DbResult<i64>;
     
+    /// Increment by amount, clamping or rejecting values outside the bounds
+    fn incrby_bounded(&mut self, key: &str, delta: i64, bounds: CounterBounds) -> DbResult<i64>;
+
     /// Decrement by amount
-    fn decrby(&mut self, key: String, delta: i64) -> Result<i64, String>;
+    fn decrby(&mut self, key: &str, delta: i64) -> DbResult<i64>;
     
     /// Increment by float
-    fn incrbyfloat(&mut self, key: String, delta: f64) -> Result<f64, String>;
+    fn incrbyfloat(&mut self, key: &str, delta: f64) -> DbResult<f64>;
 }
 
 impl StringOps for DB {
-    fn get(&mut self, key: String) -> Result<Option<String>, String> {
-        if !self.check_expiration(&key) {
+    fn get(&mut self, key: &str) -> DbResult<Option<String>> {
+        if !self.check_expiration(key) {
             return Ok(None);
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             match &entry.value {
                 DataType::String(s) => Ok(Some(s.clone())),
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                DataType::Bitmap(data) => Ok(Some(String::from_utf8_lossy(data).into_owned())),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(None)
@@ -82,37 +154,43 @@ impl StringOps for DB {
 
     fn set(&mut self, key: String, value: String) {
         self.items.insert(
-            key,
+            key.clone(),
             Entry {
                 value: DataType::String(value),
                 expires_at: None,
+                field_expires: None,
             },
         );
         self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(&key);
     }
 
     fn setex(&mut self, key: String, seconds: u64, value: String) {
         let expires_at = Some(std::time::Instant::now() + std::time::Duration::from_secs(seconds));
         self.items.insert(
-            key,
+            key.clone(),
             Entry {
                 value: DataType::String(value),
                 expires_at,
+                field_expires: None,
             },
         );
         self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(&key);
     }
 
     fn psetex(&mut self, key: String, milliseconds: u64, value: String) {
         let expires_at = Some(std::time::Instant::now() + std::time::Duration::from_millis(milliseconds));
         self.items.insert(
-            key,
+            key.clone(),
             Entry {
                 value: DataType::String(value),
                 expires_at,
+                field_expires: None,
             },
         );
         self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(&key);
     }
 
     fn setnx(&mut self, key: String, value: String) -> bool {
@@ -124,15 +202,15 @@ impl StringOps for DB {
         }
     }
 
-    fn getset(&mut self, key: String, value: String) -> Result<Option<String>, String> {
-        let old = self.get(key.clone())?;
+    fn getset(&mut self, key: String, value: String) -> DbResult<Option<String>> {
+        let old = self.get(&key)?;
         self.set(key, value);
         Ok(old)
     }
 
     fn mget(&mut self, keys: Vec<String>) -> Vec<Option<String>> {
         keys.into_iter()
-            .map(|key| self.get(key).ok().flatten())
+            .map(|key| self.get(&key).ok().flatten())
             .collect()
     }
 
@@ -154,13 +232,19 @@ impl StringOps for DB {
         true
     }
 
-    fn append(&mut self, key: String, value: String) -> usize {
+    fn append(&mut self, key: String, value: String) -> DbResult<usize> {
+        self.check_expiration(&key);
         let result = if let Some(entry) = self.items.get_mut(&key) {
-            if let DataType::String(ref mut s) = entry.value {
-                s.push_str(&value);
-                Some(s.len())
-            } else {
-                None
+            match entry.value {
+                DataType::String(ref mut s) => {
+                    s.push_str(&value);
+                    Some(s.len())
+                }
+                DataType::Bitmap(ref mut data) => {
+                    data.extend_from_slice(value.as_bytes());
+                    Some(data.len())
+                }
+                _ => return Err(DbError::WrongType),
             }
         } else {
             None
@@ -168,34 +252,37 @@ impl StringOps for DB {
         
         if let Some(len) = result {
             self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-            return len;
+            self.notify_set(&key);
+            return Ok(len);
         }
         
         // Key doesn't exist, create it
         let len = value.len();
         self.set(key, value);
-        len
+        Ok(len)
     }
 
-    fn strlen(&mut self, key: String) -> usize {
-        if !self.check_expiration(&key) {
+    fn strlen(&mut self, key: &str) -> usize {
+        if !self.check_expiration(key) {
             return 0;
         }
 
-        if let Some(entry) = self.items.get(&key) {
-            if let DataType::String(s) = &entry.value {
-                return s.len();
+        if let Some(entry) = self.items.get(key) {
+            match &entry.value {
+                DataType::String(s) => return s.len(),
+                DataType::Bitmap(data) => return data.len(),
+                _ => {}
             }
         }
         0
     }
 
-    fn getrange(&mut self, key: String, start: i64, end: i64) -> String {
-        if !self.check_expiration(&key) {
+    fn getrange(&mut self, key: &str, start: i64, end: i64) -> String {
+        if !self.check_expiration(key) {
             return String::new();
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::String(s) = &entry.value {
                 let len = s.len() as i64;
                 let start = if start < 0 { (len + start).max(0) } else { start.min(len) } as usize;
@@ -207,6 +294,20 @@ impl StringOps for DB {
 
                 return s.chars().skip(start).take(end - start + 1).collect();
             }
+            if let DataType::Bitmap(data) = &entry.value {
+                if data.is_empty() {
+                    return String::new();
+                }
+                let len = data.len() as i64;
+                let start = if start < 0 { (len + start).max(0) } else { start.min(len) } as usize;
+                let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) } as usize;
+
+                if start > end || start >= data.len() {
+                    return String::new();
+                }
+
+                return String::from_utf8_lossy(&data[start..=end]).into_owned();
+            }
         }
         String::new()
     }
@@ -234,6 +335,14 @@ impl StringOps for DB {
                 }
                 *s = String::from_utf8_lossy(&bytes).to_string();
                 Some(s.len())
+            } else if let DataType::Bitmap(ref mut data) = entry.value {
+                // Bitmaps are strings too; overwrite the raw bytes in place
+                let new_len = offset + value.len();
+                if data.len() < new_len {
+                    data.resize(new_len, 0);
+                }
+                data[offset..new_len].copy_from_slice(value.as_bytes());
+                Some(data.len())
             } else {
                 None
             }
@@ -243,6 +352,7 @@ impl StringOps for DB {
         
         if let Some(len) = result {
             self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+            self.notify_set(&key);
             return len;
         }
 
@@ -257,82 +367,87 @@ impl StringOps for DB {
         len
     }
 
-    fn incr(&mut self, key: String) -> Result<i64, String> {
+    fn incr(&mut self, key: &str) -> DbResult<i64> {
         self.incrby(key, 1)
     }
 
-    fn decr(&mut self, key: String) -> Result<i64, String> {
+    fn decr(&mut self, key: &str) -> DbResult<i64> {
         self.incrby(key, -1)
     }
 
-    fn incrby(&mut self, key: String, delta: i64) -> Result<i64, String> {
-        let _ = self.check_expiration(&key);
+    fn incrby(&mut self, key: &str, delta: i64) -> DbResult<i64> {
+        self.incrby_bounded(key, delta, CounterBounds::default())
+    }
 
-        let current_val = if let Some(entry) = self.items.get(&key) {
-            match &entry.value {
-                DataType::String(s) => s.clone(),
-                _ => return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
-            }
-        } else {
-            "0".to_string()
+    fn incrby_bounded(&mut self, key: &str, delta: i64, bounds: CounterBounds) -> DbResult<i64> {
+        let _ = self.check_expiration(key);
+
+        let current = match self.items.get(key).map(|entry| &entry.value) {
+            Some(DataType::String(s)) => s.parse::<i64>(),
+            Some(DataType::Bitmap(data)) => String::from_utf8_lossy(data).parse::<i64>(),
+            Some(_) => return Err(DbError::WrongType),
+            None => Ok(0),
         };
 
-        match current_val.parse::<i64>() {
+        match current {
             Ok(num) => {
                 let new_val = num.checked_add(delta)
-                    .ok_or_else(|| "ERR increment or decrement would overflow".to_string())?;
-                
-                let expires_at = self.items.get(&key).and_then(|e| e.expires_at);
-                self.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::String(new_val.to_string()),
-                        expires_at,
-                    },
-                );
-                self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+                    .ok_or_else(|| DbError::InvalidValue("increment or decrement would overflow".to_string()))?;
+                let new_val = bounds.apply(new_val)?;
+                self.store_counter(key, new_val.to_string());
                 Ok(new_val)
             }
-            Err(_) => Err("ERR value is not an integer or out of range".to_string()),
+            Err(_) => Err(DbError::InvalidValue("value is not an integer or out of range".to_string())),
         }
     }
 
-    fn decrby(&mut self, key: String, delta: i64) -> Result<i64, String> {
+    fn decrby(&mut self, key: &str, delta: i64) -> DbResult<i64> {
         self.incrby(key, -delta)
     }
 
-    fn incrbyfloat(&mut self, key: String, delta: f64) -> Result<f64, String> {
-        let _ = self.check_expiration(&key);
+    fn incrbyfloat(&mut self, key: &str, delta: f64) -> DbResult<f64> {
+        let _ = self.check_expiration(key);
 
-        let current_val = if let Some(entry) = self.items.get(&key) {
-            match &entry.value {
-                DataType::String(s) => s.clone(),
-                _ => return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
-            }
-        } else {
-            "0".to_string()
+        let current = match self.items.get(key).map(|entry| &entry.value) {
+            Some(DataType::String(s)) => s.parse::<f64>(),
+            Some(DataType::Bitmap(data)) => String::from_utf8_lossy(data).parse::<f64>(),
+            Some(_) => return Err(DbError::WrongType),
+            None => Ok(0.0),
         };
 
-        match current_val.parse::<f64>() {
+        match current {
             Ok(num) => {
                 let new_val = num + delta;
                 if new_val.is_nan() || new_val.is_infinite() {
-                    return Err("ERR increment would produce NaN or Infinity".to_string());
+                    return Err(DbError::InvalidValue("increment would produce NaN or Infinity".to_string()));
                 }
+                self.store_counter(key, format!("{}", new_val));
+                Ok(new_val)
+            }
+            Err(_) => Err(DbError::InvalidValue("value is not a valid float".to_string())),
+        }
+    }
+}
 
-                let expires_at = self.items.get(&key).and_then(|e| e.expires_at);
+impl DB {
+    /// Store a counter's new value. An existing key is updated in place,
+    /// keeping its TTL, so only a new key needs an owned copy of its name.
+    fn store_counter(&mut self, key: &str, value: String) {
+        match self.items.get_mut(key) {
+            Some(entry) => entry.value = DataType::String(value),
+            None => {
                 self.items.insert(
-                    key,
+                    key.to_string(),
                     Entry {
-                        value: DataType::String(format!("{}", new_val)),
-                        expires_at,
+                        value: DataType::String(value),
+                        expires_at: None,
+                        field_expires: None,
                     },
                 );
-                self.changes_since_save.fetch_add(1, Ordering::Relaxed);
-                Ok(new_val)
             }
-            Err(_) => Err("ERR value is not a valid float".to_string()),
         }
+        self.changes_since_save.fetch_add(1, Ordering::Relaxed);
+        self.notify_set(key);
     }
 }
 
@@ -345,28 +460,89 @@ mod tests {
         let mut db = DB::new();
         
         db.set("foo".to_string(), "bar".to_string());
-        assert_eq!(db.get("foo".to_string()).unwrap(), Some("bar".to_string()));
+        assert_eq!(db.get("foo").unwrap(), Some("bar".to_string()));
         
-        assert!(db.setnx("foo".to_string(), "baz".to_string()) == false);
-        assert!(db.setnx("new".to_string(), "value".to_string()) == true);
+        assert!(!db.setnx("foo".to_string(), "baz".to_string()));
+        assert!(db.setnx("new".to_string(), "value".to_string()));
     }
 
     #[test]
     fn test_incr_decr() {
         let mut db = DB::new();
         
-        assert_eq!(db.incr("counter".to_string()).unwrap(), 1);
-        assert_eq!(db.incr("counter".to_string()).unwrap(), 2);
-        assert_eq!(db.decr("counter".to_string()).unwrap(), 1);
-        assert_eq!(db.incrby("counter".to_string(), 10).unwrap(), 11);
+        assert_eq!(db.incr("counter").unwrap(), 1);
+        assert_eq!(db.incr("counter").unwrap(), 2);
+        assert_eq!(db.decr("counter").unwrap(), 1);
+        assert_eq!(db.incrby("counter", 10).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_incr_updates_in_place() {
+        use crate::db::GenericOps;
+        let mut db = DB::new();
+
+        db.set("counter".to_string(), "41".to_string());
+        db.expire("counter", 100);
+        assert_eq!(db.incr("counter").unwrap(), 42);
+        assert!(db.ttl("counter") > 0);
+        assert_eq!(db.incrbyfloat("counter", 0.5).unwrap(), 42.5);
+        assert!(db.ttl("counter") > 0);
+        assert_eq!(db.items.type_counts(), vec![("string", 1)]);
+    }
+
+    #[test]
+    fn test_incrby_bounded() {
+        let mut db = DB::new();
+        let reject = CounterBounds { min: Some(0), max: Some(10), clamp: false };
+        let clamp = CounterBounds { clamp: true, ..reject };
+
+        assert_eq!(db.incrby_bounded("stock", 8, reject).unwrap(), 8);
+        assert!(db.incrby_bounded("stock", 5, reject).is_err());
+        assert_eq!(db.get("stock").unwrap(), Some("8".to_string()));
+        assert_eq!(db.incrby_bounded("stock", 5, clamp).unwrap(), 10);
+        assert_eq!(db.incrby_bounded("stock", -50, clamp).unwrap(), 0);
+        assert!(db.incrby_bounded("stock", -1, reject).is_err());
+
+        let args: Vec<String> = ["MAX", "5", "clamp"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(CounterBounds::parse(&args).unwrap(), CounterBounds { min: None, max: Some(5), clamp: true });
+        assert!(CounterBounds::parse(&["MIN".to_string()]).is_err());
     }
 
     #[test]
     fn test_append() {
+        use crate::db::ListOps;
         let mut db = DB::new();
         
-        assert_eq!(db.append("key".to_string(), "Hello".to_string()), 5);
-        assert_eq!(db.append("key".to_string(), " World".to_string()), 11);
-        assert_eq!(db.get("key".to_string()).unwrap(), Some("Hello World".to_string()));
+        assert_eq!(db.append("key".to_string(), "Hello".to_string()), Ok(5));
+        assert_eq!(db.append("key".to_string(), " World".to_string()), Ok(11));
+        assert_eq!(db.get("key").unwrap(), Some("Hello World".to_string()));
+
+        // Other types are left alone
+        db.lpush("list".to_string(), vec!["a".to_string()]).unwrap();
+        assert_eq!(db.append("list".to_string(), "b".to_string()), Err(DbError::WrongType));
+    }
+
+    #[test]
+    fn test_bitmap_is_a_string() {
+        use crate::db::BitmapOps;
+        let mut db = DB::new();
+
+        // 'a' is 0x61; setting bit 6 turns it into 'c' (0x63)
+        db.set("k".to_string(), "a".to_string());
+        assert_eq!(db.setbit("k".to_string(), 6, true), Ok(0));
+        assert_eq!(db.get("k").unwrap(), Some("c".to_string()));
+        assert_eq!(db.strlen("k"), 1);
+
+        assert_eq!(db.append("k".to_string(), "de".to_string()), Ok(3));
+        assert_eq!(db.getrange("k", 1, -1), "de");
+        assert_eq!(db.setrange("k".to_string(), 1, "xyz".to_string()), 4);
+        assert_eq!(db.get("k").unwrap(), Some("cxyz".to_string()));
+        assert_eq!(db.getbit("k", 6), Ok(1));
+
+        db.setbit("n".to_string(), 2, true).unwrap();
+        db.setbit("n".to_string(), 3, true).unwrap();
+        db.setbit("n".to_string(), 7, true).unwrap();
+        assert_eq!(db.get("n").unwrap(), Some("1".to_string()));
+        assert_eq!(db.incr("n").unwrap(), 2);
     }
 }
@@ -0,0 +1,426 @@
+//! Time series operations.
+//!
+//! A series holds samples in timestamp order and only accepts samples newer
+//! than its last one. Labels make series selectable by TS.MRANGE filters.
+//! Compaction rules downsample a series into another: a background task
+//! folds every closed bucket (one that ends before the bucket of the newest
+//! source sample) into the destination, resuming after the destination's
+//! last sample, so the work picks up where it left off after a restart or
+//! AOF replay. Series with rules are tracked in a set so the task does not
+//! scan the keyspace.
+
+use crate::db::core::DB;
+use crate::db::error::{DbError, DbResult};
+use crate::db::ops::generic::GenericOps;
+use crate::db::types::{aggregate, Aggregation, CompactionRule, DataType, TimeSeriesData};
+
+/// Options for a new series: `[RETENTION ms] [LABELS label value ...]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeriesOptions {
+    pub retention_ms: u64,
+    pub labels: Vec<(String, String)>,
+}
+
+impl SeriesOptions {
+    /// Parse the options. LABELS takes the rest of the arguments.
+    pub fn parse(args: &[String]) -> DbResult<Self> {
+        let mut options = SeriesOptions::default();
+        let mut i = 0;
+        while i < args.len() {
+            if args[i].eq_ignore_ascii_case("RETENTION") {
+                options.retention_ms = args
+                    .get(i + 1)
+                    .and_then(|ms| ms.parse().ok())
+                    .ok_or_else(|| DbError::Syntax("TSDB: invalid RETENTION".to_string()))?;
+                i += 2;
+            } else if args[i].eq_ignore_ascii_case("LABELS") {
+                let pairs = &args[i + 1..];
+                if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+                    return Err(DbError::Syntax("TSDB: invalid LABELS".to_string()));
+                }
+                options.labels = pairs.chunks_exact(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+                break;
+            } else {
+                return Err(DbError::Syntax("syntax error".to_string()));
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Options of a range query: `[COUNT count] [AGGREGATION aggregator bucket-ms]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RangeQuery {
+    pub count: Option<usize>,
+    pub aggregation: Option<(Aggregation, u64)>,
+}
+
+impl RangeQuery {
+    /// Parse the options up to the first argument that is not one, and
+    /// return the arguments left over
+    pub fn parse(args: &[String]) -> DbResult<(Self, &[String])> {
+        let mut query = RangeQuery::default();
+        let mut rest = args;
+        loop {
+            match rest {
+                [opt, count, tail @ ..] if opt.eq_ignore_ascii_case("COUNT") => {
+                    let count = count
+                        .parse()
+                        .map_err(|_| DbError::InvalidValue("TSDB: invalid COUNT".to_string()))?;
+                    query.count = Some(count);
+                    rest = tail;
+                }
+                [opt, name, bucket, tail @ ..] if opt.eq_ignore_ascii_case("AGGREGATION") => {
+                    let aggregation = Aggregation::parse(name)
+                        .ok_or_else(|| DbError::Syntax("TSDB: unknown aggregation type".to_string()))?;
+                    let bucket_ms = bucket
+                        .parse()
+                        .ok()
+                        .filter(|&ms| ms > 0)
+                        .ok_or_else(|| DbError::InvalidValue("TSDB: invalid time bucket".to_string()))?;
+                    query.aggregation = Some((aggregation, bucket_ms));
+                    rest = tail;
+                }
+                _ => return Ok((query, rest)),
+            }
+        }
+    }
+
+    /// Run the query over samples in timestamp order
+    fn apply<'a>(&self, samples: impl Iterator<Item = &'a (u64, f64)>) -> Vec<(u64, f64)> {
+        let mut result = match self.aggregation {
+            Some((aggregation, bucket_ms)) => aggregate(samples, aggregation, bucket_ms),
+            None => samples.copied().collect(),
+        };
+        if let Some(count) = self.count {
+            result.truncate(count);
+        }
+        result
+    }
+}
+
+/// A TS.MRANGE filter, `label=value` or `label!=value`. An empty value
+/// matches series without the label (or, negated, with it).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelFilter {
+    pub label: String,
+    pub value: String,
+    pub negated: bool,
+}
+
+impl LabelFilter {
+    pub fn parse(expr: &str) -> Option<Self> {
+        let (label, value, negated) = match expr.split_once("!=") {
+            Some((label, value)) => (label, value, true),
+            None => {
+                let (label, value) = expr.split_once('=')?;
+                (label, value, false)
+            }
+        };
+        if label.is_empty() {
+            return None;
+        }
+        Some(LabelFilter {
+            label: label.to_string(),
+            value: value.to_string(),
+            negated,
+        })
+    }
+
+    pub fn matches(&self, series: &TimeSeriesData) -> bool {
+        let equal = match series.label(&self.label) {
+            Some(value) => value == self.value,
+            None => self.value.is_empty(),
+        };
+        equal != self.negated
+    }
+}
+
+/// Series matched by TS.MRANGE, as (key, labels, samples)
+pub type SeriesRange = (String, Vec<(String, String)>, Vec<(u64, f64)>);
+
+/// Time series operations trait
+pub trait TimeSeriesOps {
+    /// Create an empty series (TS.CREATE). Fails if the key exists.
+    fn ts_create(&mut self, key: String, options: SeriesOptions) -> DbResult<()>;
+
+    /// Append a sample, creating the series from `options` when the key is
+    /// missing (TS.ADD)
+    fn ts_add(&mut self, key: String, timestamp: u64, value: f64, options: SeriesOptions) -> DbResult<()>;
+
+    /// Newest sample, None while the series is empty (TS.GET)
+    fn ts_get(&mut self, key: &str) -> DbResult<Option<(u64, f64)>>;
+
+    /// Samples with timestamps from `from` to `to` (TS.RANGE)
+    fn ts_range(&mut self, key: &str, from: u64, to: u64, query: &RangeQuery) -> DbResult<Vec<(u64, f64)>>;
+
+    /// Samples of every series matching all `filters`, in key order (TS.MRANGE)
+    fn ts_mrange(&mut self, from: u64, to: u64, query: &RangeQuery, filters: &[LabelFilter]) -> Vec<SeriesRange>;
+
+    /// Downsample `source` into another existing series (TS.CREATERULE)
+    fn ts_createrule(&mut self, source: &str, rule: CompactionRule) -> DbResult<()>;
+
+    /// Remove the rule from `source` into `dest` (TS.DELETERULE)
+    fn ts_deleterule(&mut self, source: &str, dest: &str) -> DbResult<()>;
+}
+
+fn is_timeseries(value: &DataType) -> bool {
+    matches!(value, DataType::TimeSeries(_))
+}
+
+fn no_such_series() -> DbError {
+    DbError::InvalidValue("TSDB: the key does not exist".to_string())
+}
+
+impl DB {
+    /// The series stored at `key`, if any
+    fn series_mut(&mut self, key: &str) -> DbResult<Option<&mut TimeSeriesData>> {
+        if !self.check_expiration(key) {
+            return Ok(None);
+        }
+        match self.items.get_mut(key).map(|entry| &mut entry.value) {
+            Some(DataType::TimeSeries(series)) => Ok(Some(series)),
+            Some(_) => Err(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether any series has compaction rules
+    pub fn has_timeseries_rules(&self) -> bool {
+        !self.timeseries_rule_keys.is_empty()
+    }
+
+    /// Fold the closed buckets of every series' rules into their
+    /// destinations. Returns the samples added, as (dest, timestamp,
+    /// value), so they can be logged.
+    pub fn compact_timeseries(&mut self) -> Vec<(String, u64, f64)> {
+        let mut added = Vec::new();
+        let sources: Vec<String> = self.timeseries_rule_keys.iter().cloned().collect();
+        for source in &sources {
+            let live = self.check_expiration(source);
+            let series = match self.items.get(source).map(|entry| &entry.value) {
+                Some(DataType::TimeSeries(series)) if live && !series.rules.is_empty() => series,
+                _ => {
+                    self.timeseries_rule_keys.remove(source);
+                    continue;
+                }
+            };
+            let Some((newest, _)) = series.last() else { continue };
+            let pending: Vec<(String, Vec<(u64, f64)>)> = series
+                .rules
+                .iter()
+                .filter_map(|rule| {
+                    let resume = match self.items.get(&rule.dest).map(|entry| &entry.value) {
+                        Some(DataType::TimeSeries(dest)) => dest.last().map_or(0, |(ts, _)| ts + rule.bucket_ms),
+                        _ => return None,
+                    };
+                    let open = newest - newest % rule.bucket_ms;
+                    (resume < open).then(|| {
+                        let buckets = aggregate(series.range(resume, open - 1), rule.aggregation, rule.bucket_ms);
+                        (rule.dest.clone(), buckets)
+                    })
+                })
+                .collect();
+
+            for (dest, buckets) in pending {
+                let Some(DataType::TimeSeries(series)) = self.items.get_mut(&dest).map(|entry| &mut entry.value) else {
+                    continue;
+                };
+                let before = added.len();
+                for (timestamp, value) in buckets {
+                    if series.add(timestamp, value).is_ok() {
+                        added.push((dest.clone(), timestamp, value));
+                    }
+                }
+                if added.len() > before {
+                    self.increment_changes();
+                    self.notify_set(&dest);
+                }
+            }
+        }
+        added
+    }
+}
+
+impl TimeSeriesOps for DB {
+    fn ts_create(&mut self, key: String, options: SeriesOptions) -> DbResult<()> {
+        if self.check_expiration(&key) && self.items.get(&key).is_some() {
+            return Err(DbError::InvalidValue("TSDB: key already exists".to_string()));
+        }
+        self.typed_entry_mut(&key, is_timeseries, || {
+            DataType::TimeSeries(TimeSeriesData::new(options.retention_ms, options.labels))
+        })?;
+        self.increment_changes();
+        self.notify_set(&key);
+        Ok(())
+    }
+
+    fn ts_add(&mut self, key: String, timestamp: u64, value: f64, options: SeriesOptions) -> DbResult<()> {
+        let entry = self.typed_entry_mut(&key, is_timeseries, || {
+            DataType::TimeSeries(TimeSeriesData::new(options.retention_ms, options.labels))
+        })?;
+        match &mut entry.value {
+            DataType::TimeSeries(series) => series.add(timestamp, value).map_err(DbError::InvalidValue)?,
+            _ => return Err(DbError::WrongType),
+        }
+        self.increment_changes();
+        self.notify_set(&key);
+        Ok(())
+    }
+
+    fn ts_get(&mut self, key: &str) -> DbResult<Option<(u64, f64)>> {
+        match self.typed_value(key, is_timeseries)? {
+            Some(DataType::TimeSeries(series)) => Ok(series.last()),
+            _ => Err(no_such_series()),
+        }
+    }
+
+    fn ts_range(&mut self, key: &str, from: u64, to: u64, query: &RangeQuery) -> DbResult<Vec<(u64, f64)>> {
+        match self.typed_value(key, is_timeseries)? {
+            Some(DataType::TimeSeries(series)) => Ok(query.apply(series.range(from, to))),
+            _ => Err(no_such_series()),
+        }
+    }
+
+    fn ts_mrange(&mut self, from: u64, to: u64, query: &RangeQuery, filters: &[LabelFilter]) -> Vec<SeriesRange> {
+        let mut keys: Vec<String> = self
+            .items
+            .iter()
+            .filter(|(_, entry)| {
+                matches!(&entry.value, DataType::TimeSeries(series) if filters.iter().all(|f| f.matches(series)))
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.sort();
+
+        keys.into_iter()
+            .filter_map(|key| match self.typed_value(&key, is_timeseries) {
+                Ok(Some(DataType::TimeSeries(series))) => {
+                    let samples = query.apply(series.range(from, to));
+                    let labels = series.labels.clone();
+                    Some((key, labels, samples))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn ts_createrule(&mut self, source: &str, rule: CompactionRule) -> DbResult<()> {
+        if source == rule.dest {
+            return Err(DbError::InvalidValue(
+                "TSDB: the source key and destination key should be different".to_string(),
+            ));
+        }
+        if self.series_mut(&rule.dest)?.is_none() {
+            return Err(no_such_series());
+        }
+        let series = self.series_mut(source)?.ok_or_else(no_such_series)?;
+        if series.rules.iter().any(|r| r.dest == rule.dest) {
+            return Err(DbError::InvalidValue("TSDB: the destination key already has a rule".to_string()));
+        }
+        series.rules.push(rule);
+        self.timeseries_rule_keys.insert(source.to_string());
+        self.increment_changes();
+        self.notify_set(source);
+        Ok(())
+    }
+
+    fn ts_deleterule(&mut self, source: &str, dest: &str) -> DbResult<()> {
+        let series = self.series_mut(source)?.ok_or_else(no_such_series)?;
+        let before = series.rules.len();
+        series.rules.retain(|rule| rule.dest != dest);
+        if series.rules.len() == before {
+            return Err(DbError::InvalidValue("TSDB: compaction rule does not exist".to_string()));
+        }
+        self.increment_changes();
+        self.notify_set(source);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> SeriesOptions {
+        SeriesOptions {
+            retention_ms: 0,
+            labels: pairs.iter().map(|(l, v)| (l.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_add_range_and_retention() {
+        let mut db = DB::new();
+        let options = SeriesOptions { retention_ms: 100, labels: vec![] };
+        for ts in [10, 20, 30, 150] {
+            db.ts_add("t".to_string(), ts, ts as f64, options.clone()).unwrap();
+        }
+        assert!(db.ts_add("t".to_string(), 150, 1.0, options.clone()).is_err());
+
+        // 150 - 100 drops everything before 50
+        let all = db.ts_range("t", 0, u64::MAX, &RangeQuery::default()).unwrap();
+        assert_eq!(all, vec![(150, 150.0)]);
+        assert_eq!(db.ts_get("t").unwrap(), Some((150, 150.0)));
+
+        db.ts_create("u".to_string(), SeriesOptions::default()).unwrap();
+        assert!(db.ts_create("u".to_string(), SeriesOptions::default()).is_err());
+        for ts in [0, 5, 10, 12, 25] {
+            db.ts_add("u".to_string(), ts, ts as f64, SeriesOptions::default()).unwrap();
+        }
+        let query = RangeQuery { count: Some(2), aggregation: Some((Aggregation::Avg, 10)) };
+        assert_eq!(db.ts_range("u", 0, 20, &query).unwrap(), vec![(0, 2.5), (10, 11.0)]);
+        assert!(db.ts_range("missing", 0, 20, &query).is_err());
+    }
+
+    #[test]
+    fn test_mrange_filters() {
+        let mut db = DB::new();
+        db.ts_add("cpu:b".to_string(), 1, 2.0, labels(&[("metric", "cpu"), ("host", "b")])).unwrap();
+        db.ts_add("cpu:a".to_string(), 1, 1.0, labels(&[("metric", "cpu"), ("host", "a")])).unwrap();
+        db.ts_add("mem:a".to_string(), 1, 9.0, labels(&[("metric", "mem"), ("host", "a")])).unwrap();
+
+        let mut keys = |exprs: &[&str]| {
+            let filters: Vec<_> = exprs.iter().map(|e| LabelFilter::parse(e).unwrap()).collect();
+            db.ts_mrange(0, 10, &RangeQuery::default(), &filters).into_iter().map(|(k, _, _)| k).collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&["metric=cpu"]), vec!["cpu:a", "cpu:b"]);
+        assert_eq!(keys(&["host=a", "metric!=cpu"]), vec!["mem:a"]);
+        assert_eq!(keys(&["rack="]).len(), 3);
+        assert_eq!(keys(&["host!="]).len(), 3);
+        assert!(LabelFilter::parse("=x").is_none());
+        assert!(LabelFilter::parse("metric").is_none());
+    }
+
+    #[test]
+    fn test_compaction_rules() {
+        let mut db = DB::new();
+        db.ts_create("raw".to_string(), SeriesOptions::default()).unwrap();
+        db.ts_create("max".to_string(), SeriesOptions::default()).unwrap();
+        let rule = CompactionRule { dest: "max".to_string(), aggregation: Aggregation::Max, bucket_ms: 10 };
+        db.ts_createrule("raw", rule.clone()).unwrap();
+        assert!(db.ts_createrule("raw", rule).is_err());
+        assert!(db.has_timeseries_rules());
+
+        for (ts, value) in [(1, 5.0), (4, 7.0), (12, 1.0), (15, 3.0)] {
+            db.ts_add("raw".to_string(), ts, value, SeriesOptions::default()).unwrap();
+        }
+        // The bucket at 10 is still open
+        assert_eq!(db.compact_timeseries(), vec![("max".to_string(), 0, 7.0)]);
+
+        db.ts_add("raw".to_string(), 31, 0.0, SeriesOptions::default()).unwrap();
+        assert_eq!(db.compact_timeseries(), vec![("max".to_string(), 10, 3.0)]);
+        assert!(db.compact_timeseries().is_empty());
+
+        // A renamed source keeps being downsampled
+        db.rename("raw", "renamed").unwrap();
+        db.ts_add("renamed".to_string(), 45, 9.0, SeriesOptions::default()).unwrap();
+        assert_eq!(db.compact_timeseries(), vec![("max".to_string(), 30, 0.0)]);
+
+        db.ts_deleterule("renamed", "max").unwrap();
+        assert!(db.ts_deleterule("renamed", "max").is_err());
+        // The source is forgotten on the next pass
+        assert!(db.compact_timeseries().is_empty());
+        assert!(!db.has_timeseries_rules());
+    }
+}
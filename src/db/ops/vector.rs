@@ -0,0 +1,215 @@
+//! Vector index operations.
+//!
+//! An index holds float vectors of one dimension under string ids, each
+//! with optional metadata fields. VECTOR.SEARCH ranks every vector in the
+//! index by cosine or L2 distance to the query and returns the nearest,
+//! optionally only those whose metadata has a given field value.
+
+use crate::db::core::DB;
+use crate::db::error::{DbError, DbResult};
+use crate::db::ops::generic::GenericOps;
+use crate::db::types::{DataType, VectorData, VectorEntry, VectorMetric};
+
+/// Parse a vector given as comma-separated numbers, e.g. `0.1,0.5,-2`
+pub fn parse_vector(arg: &str) -> DbResult<Vec<f32>> {
+    arg.split(',')
+        .map(|value| value.trim().parse::<f32>().ok().filter(|v| v.is_finite()))
+        .collect::<Option<Vec<f32>>>()
+        .filter(|values| !values.is_empty())
+        .ok_or_else(|| DbError::InvalidValue("invalid vector, expected comma-separated numbers".to_string()))
+}
+
+/// Options of a search: `[METRIC COSINE|L2] [FILTER field value] [WITHMETA]`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorQuery {
+    pub metric: VectorMetric,
+    /// Only vectors with this metadata field value
+    pub filter: Option<(String, String)>,
+    pub with_meta: bool,
+}
+
+impl VectorQuery {
+    pub fn parse(args: &[String]) -> DbResult<Self> {
+        let mut query = VectorQuery::default();
+        let mut rest = args;
+        loop {
+            match rest {
+                [] => return Ok(query),
+                [opt, metric, tail @ ..] if opt.eq_ignore_ascii_case("METRIC") => {
+                    query.metric = VectorMetric::parse(metric)
+                        .ok_or_else(|| DbError::Syntax("unknown metric, expected COSINE or L2".to_string()))?;
+                    rest = tail;
+                }
+                [opt, field, value, tail @ ..] if opt.eq_ignore_ascii_case("FILTER") => {
+                    query.filter = Some((field.clone(), value.clone()));
+                    rest = tail;
+                }
+                [opt, tail @ ..] if opt.eq_ignore_ascii_case("WITHMETA") => {
+                    query.with_meta = true;
+                    rest = tail;
+                }
+                _ => return Err(DbError::Syntax("syntax error".to_string())),
+            }
+        }
+    }
+}
+
+/// A search hit as (id, distance, metadata)
+pub type VectorHit = (String, f32, Vec<(String, String)>);
+
+/// Vector index operations trait
+pub trait VectorOps {
+    /// Add or replace a vector and its metadata (VECTOR.ADD). Returns true
+    /// if the id is new.
+    fn vector_add(&mut self, key: String, id: String, values: Vec<f32>, meta: Vec<(String, String)>) -> DbResult<bool>;
+
+    /// A vector and its metadata (VECTOR.GET)
+    fn vector_get(&mut self, key: &str, id: &str) -> DbResult<Option<VectorEntry>>;
+
+    /// Remove vectors, returning how many existed (VECTOR.DEL). The key is
+    /// deleted once the index is empty.
+    fn vector_del(&mut self, key: &str, ids: &[String]) -> DbResult<usize>;
+
+    /// Number of vectors (VECTOR.CARD)
+    fn vector_card(&mut self, key: &str) -> DbResult<usize>;
+
+    /// The `k` vectors nearest to `query` (VECTOR.SEARCH)
+    fn vector_search(&mut self, key: &str, query: &[f32], k: usize, options: &VectorQuery) -> DbResult<Vec<VectorHit>>;
+}
+
+fn is_vector(value: &DataType) -> bool {
+    matches!(value, DataType::Vector(_))
+}
+
+impl VectorOps for DB {
+    fn vector_add(&mut self, key: String, id: String, values: Vec<f32>, meta: Vec<(String, String)>) -> DbResult<bool> {
+        let entry = self.typed_entry_mut(&key, is_vector, || DataType::Vector(VectorData::default()))?;
+        let DataType::Vector(index) = &mut entry.value else { return Err(DbError::WrongType) };
+        let added = index.upsert(id, values, meta).map_err(DbError::InvalidValue);
+        if index.is_empty() {
+            // A rejected first vector leaves no index behind
+            self.items.remove(&key);
+        } else if added.is_ok() {
+            self.increment_changes();
+            self.notify_set(&key);
+        }
+        added
+    }
+
+    fn vector_get(&mut self, key: &str, id: &str) -> DbResult<Option<VectorEntry>> {
+        Ok(match self.typed_value(key, is_vector)? {
+            Some(DataType::Vector(index)) => index.vectors.get(id).cloned(),
+            _ => None,
+        })
+    }
+
+    fn vector_del(&mut self, key: &str, ids: &[String]) -> DbResult<usize> {
+        if !self.check_expiration(key) {
+            return Ok(0);
+        }
+        let (removed, emptied) = match self.items.get_mut(key).map(|entry| &mut entry.value) {
+            Some(DataType::Vector(index)) => {
+                let removed = ids.iter().filter(|id| index.vectors.remove(id.as_str()).is_some()).count();
+                (removed, index.is_empty())
+            }
+            Some(_) => return Err(DbError::WrongType),
+            None => return Ok(0),
+        };
+        if removed > 0 {
+            self.increment_changes();
+            self.notify_set(key);
+        }
+        if emptied {
+            if let Some(old) = self.items.remove(key) {
+                self.notify_delete(key, &old);
+            }
+        }
+        Ok(removed)
+    }
+
+    fn vector_card(&mut self, key: &str) -> DbResult<usize> {
+        Ok(match self.typed_value(key, is_vector)? {
+            Some(DataType::Vector(index)) => index.len(),
+            _ => 0,
+        })
+    }
+
+    fn vector_search(&mut self, key: &str, query: &[f32], k: usize, options: &VectorQuery) -> DbResult<Vec<VectorHit>> {
+        let index = match self.typed_value(key, is_vector)? {
+            Some(DataType::Vector(index)) => index,
+            _ => return Ok(vec![]),
+        };
+        if query.len() != index.dim {
+            return Err(DbError::InvalidValue(format!(
+                "query dimension {} does not match the index dimension {}",
+                query.len(),
+                index.dim
+            )));
+        }
+        let accept = |entry: &VectorEntry| match &options.filter {
+            Some((field, value)) => entry.meta.iter().any(|(f, v)| f == field && v == value),
+            None => true,
+        };
+        Ok(index
+            .nearest(query, k, options.metric, accept)
+            .into_iter()
+            .map(|(id, distance, entry)| {
+                let meta = if options.with_meta { entry.meta.clone() } else { Vec::new() };
+                (id.to_string(), distance, meta)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(f, v)| (f.to_string(), v.to_string())).collect()
+    }
+
+    fn ids(hits: Vec<VectorHit>) -> Vec<String> {
+        hits.into_iter().map(|(id, _, _)| id).collect()
+    }
+
+    #[test]
+    fn test_add_search_delete() {
+        let mut db = DB::new();
+        let docs = [
+            ("a", "1,0", "en"),
+            ("b", "0.9,0.1", "de"),
+            ("c", "0,1", "en"),
+            ("d", "-1,0", "en"),
+            ("e", "3,0", "de"),
+        ];
+        for (id, vector, lang) in docs {
+            let added = db.vector_add("idx".to_string(), id.to_string(), parse_vector(vector).unwrap(), meta(&[("lang", lang)]));
+            assert_eq!(added, Ok(true));
+        }
+        assert_eq!(db.vector_add("idx".to_string(), "a".to_string(), vec![1.0, 0.0], vec![]), Ok(false));
+        assert!(db.vector_add("idx".to_string(), "f".to_string(), vec![1.0], vec![]).is_err());
+        assert_eq!(db.vector_card("idx"), Ok(5));
+
+        // Cosine ignores length, L2 does not
+        let cosine = VectorQuery::default();
+        assert_eq!(ids(db.vector_search("idx", &[1.0, 0.0], 3, &cosine).unwrap()), vec!["a", "e", "b"]);
+        let l2 = VectorQuery::parse(&["METRIC".to_string(), "l2".to_string()]).unwrap();
+        assert_eq!(ids(db.vector_search("idx", &[1.0, 0.0], 2, &l2).unwrap()), vec!["a", "b"]);
+
+        let filtered = VectorQuery::parse(&["FILTER", "lang", "en", "WITHMETA"].map(String::from)).unwrap();
+        let hits = db.vector_search("idx", &[1.0, 0.0], 10, &filtered).unwrap();
+        assert_eq!(hits.iter().map(|(id, _, _)| id.as_str()).collect::<Vec<_>>(), vec!["c", "d"]);
+        assert_eq!(hits[1].1, 2.0);
+        assert_eq!(hits[0].2, meta(&[("lang", "en")]));
+        assert!(db.vector_search("idx", &[1.0], 1, &cosine).is_err());
+
+        assert_eq!(db.vector_get("idx", "a").unwrap().map(|e| e.meta), Some(vec![]));
+        assert_eq!(db.vector_del("idx", &["a".to_string(), "z".to_string()]), Ok(1));
+        assert_eq!(db.vector_del("idx", &["b", "c", "d", "e"].map(String::from)), Ok(4));
+        assert!(!db.exists("idx"));
+
+        assert!(parse_vector("1,x").is_err());
+        assert!(parse_vector("").is_err());
+    }
+}
@@ -2,53 +2,97 @@
 //!
 //! Operations for the sorted set data type (score-ordered unique strings).
 
+use crate::db::error::{DbError, DbResult};
 use crate::db::core::DB;
+use crate::db::ops::check_random_count;
 use crate::db::ops::generic::GenericOps;
+use crate::db::skiplist::cmp_elements;
 use crate::db::types::{DataType, Entry, ZSetData};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+
+/// How scores are combined by ZUNION/ZINTER
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ZAggregate {
+    #[default]
+    Sum,
+    Min,
+    Max,
+}
+
+impl ZAggregate {
+    /// Parse an AGGREGATE argument (SUM, MIN or MAX)
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_uppercase().as_str() {
+            "SUM" => Some(ZAggregate::Sum),
+            "MIN" => Some(ZAggregate::Min),
+            "MAX" => Some(ZAggregate::Max),
+            _ => None,
+        }
+    }
+
+    fn combine(self, a: f64, b: f64) -> f64 {
+        match self {
+            ZAggregate::Sum => {
+                let sum = a + b;
+                // inf + -inf is defined as 0 by Redis
+                if sum.is_nan() { 0.0 } else { sum }
+            }
+            ZAggregate::Min => a.min(b),
+            ZAggregate::Max => a.max(b),
+        }
+    }
+}
 
 /// Sorted Set operations trait
 pub trait ZSetOps {
     /// Add members with scores
-    fn zadd(&mut self, key: String, members: Vec<(f64, String)>) -> Result<usize, String>;
+    fn zadd(&mut self, key: String, members: Vec<(f64, String)>) -> DbResult<usize>;
     
     /// Add members with NX option (only if not exists)
-    fn zadd_nx(&mut self, key: String, members: Vec<(f64, String)>) -> Result<usize, String>;
+    fn zadd_nx(&mut self, key: String, members: Vec<(f64, String)>) -> DbResult<usize>;
     
     /// Add members with XX option (only if exists)
-    fn zadd_xx(&mut self, key: String, members: Vec<(f64, String)>) -> Result<usize, String>;
+    fn zadd_xx(&mut self, key: String, members: Vec<(f64, String)>) -> DbResult<usize>;
     
     /// Remove members
-    fn zrem(&mut self, key: String, members: Vec<String>) -> Result<usize, String>;
+    fn zrem(&mut self, key: String, members: Vec<String>) -> DbResult<usize>;
     
     /// Get score of member
-    fn zscore(&mut self, key: String, member: String) -> Option<f64>;
+    fn zscore(&mut self, key: &str, member: &str) -> Option<f64>;
     
     /// Get rank of member (0-indexed, ascending)
-    fn zrank(&mut self, key: String, member: String) -> Option<usize>;
+    fn zrank(&mut self, key: &str, member: &str) -> Option<usize>;
     
     /// Get reverse rank of member (0-indexed, descending)
-    fn zrevrank(&mut self, key: String, member: String) -> Option<usize>;
+    fn zrevrank(&mut self, key: &str, member: &str) -> Option<usize>;
+
+    /// A member and up to `count` neighbours on each side as (rank,
+    /// member, score) in rank order, ranks counting from the highest score
+    /// with `rev` (ZAROUND). None if the member is missing.
+    fn zaround(&mut self, key: &str, member: &str, count: usize, rev: bool) -> Option<Vec<(usize, String, f64)>>;
     
     /// Get range by rank (ascending)
-    fn zrange(&mut self, key: String, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)>;
+    fn zrange(&mut self, key: &str, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)>;
     
     /// Get range by rank (descending)
-    fn zrevrange(&mut self, key: String, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)>;
+    fn zrevrange(&mut self, key: &str, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)>;
     
     /// Get range by score
-    fn zrangebyscore(&mut self, key: String, min: f64, max: f64, withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)>;
+    fn zrangebyscore(&mut self, key: &str, min: f64, max: f64, withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)>;
     
     /// Get reverse range by score
-    fn zrevrangebyscore(&mut self, key: String, max: f64, min: f64, withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)>;
+    fn zrevrangebyscore(&mut self, key: &str, max: f64, min: f64, withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)>;
     
     /// Get cardinality
-    fn zcard(&mut self, key: String) -> usize;
+    fn zcard(&mut self, key: &str) -> usize;
     
     /// Count members in score range
-    fn zcount(&mut self, key: String, min: f64, max: f64) -> usize;
+    fn zcount(&mut self, key: &str, min: f64, max: f64) -> usize;
     
     /// Increment score of member
-    fn zincrby(&mut self, key: String, increment: f64, member: String) -> Result<f64, String>;
+    fn zincrby(&mut self, key: String, increment: f64, member: String) -> DbResult<f64>;
     
     /// Remove members by rank range
     fn zremrangebyrank(&mut self, key: String, start: i64, stop: i64) -> usize;
@@ -63,22 +107,62 @@ pub trait ZSetOps {
     fn zinterstore(&mut self, dst: String, keys: Vec<String>, weights: Option<Vec<f64>>) -> usize;
     
     /// Get multiple scores
-    fn zmscore(&mut self, key: String, members: Vec<String>) -> Vec<Option<f64>>;
+    fn zmscore(&mut self, key: &str, members: Vec<String>) -> Vec<Option<f64>>;
     
     /// Pop member with minimum score
     fn zpopmin(&mut self, key: String, count: Option<usize>) -> Vec<(String, f64)>;
     
     /// Pop member with maximum score
     fn zpopmax(&mut self, key: String, count: Option<usize>) -> Vec<(String, f64)>;
+    
+    /// Get random members. A negative count may return the same member multiple times
+    /// and must be at least `-MAX_RANDOM_COUNT`.
+    fn zrandmember(&mut self, key: &str, count: i64) -> DbResult<Vec<(String, f64)>>;
+
+    /// Union of sorted sets without storing the result, ordered by score
+    fn zunion(&mut self, keys: Vec<String>, weights: Option<Vec<f64>>, aggregate: ZAggregate) -> DbResult<Vec<(String, f64)>>;
+
+    /// Intersection of sorted sets without storing the result, ordered by score
+    fn zinter(&mut self, keys: Vec<String>, weights: Option<Vec<f64>>, aggregate: ZAggregate) -> DbResult<Vec<(String, f64)>>;
+
+    /// Members of the first sorted set that are in none of the others, ordered by score
+    fn zdiff(&mut self, keys: Vec<String>) -> DbResult<Vec<(String, f64)>>;
+
+    /// Store the difference of sorted sets in dst
+    fn zdiffstore(&mut self, dst: String, keys: Vec<String>) -> DbResult<usize>;
+}
+
+impl DB {
+    /// Read the members of a sorted set for multi-key operations.
+    /// Missing keys are treated as empty sets; plain sets count as members with score 1.
+    fn zset_members_for(&mut self, key: &str) -> DbResult<HashMap<String, f64>> {
+        if !self.check_expiration(key) {
+            return Ok(HashMap::new());
+        }
+        match self.items.get(key).map(|e| &e.value) {
+            None => Ok(HashMap::new()),
+            Some(DataType::ZSet(zset)) => Ok(zset.members.clone()),
+            Some(DataType::Set(set)) => Ok(set.iter().map(|m| (m.clone(), 1.0)).collect()),
+            Some(_) => Err(DbError::WrongType),
+        }
+    }
+}
+
+/// Order members the way a sorted set would (by score, then lexicographically)
+fn sorted_by_score(members: HashMap<String, f64>) -> Vec<(String, f64)> {
+    let mut result: Vec<_> = members.into_iter().collect();
+    result.sort_unstable_by(|a, b| cmp_elements(a.1, &a.0, b.1, &b.0));
+    result
 }
 
 impl ZSetOps for DB {
-    fn zadd(&mut self, key: String, members: Vec<(f64, String)>) -> Result<usize, String> {
+    fn zadd(&mut self, key: String, members: Vec<(f64, String)>) -> DbResult<usize> {
         self.check_expiration(&key);
 
-        let entry = self.items.entry(key).or_insert_with(|| Entry {
+        let entry = self.items.get_or_insert_with(key.clone(), || Entry {
             value: DataType::ZSet(ZSetData::new()),
             expires_at: None,
+            field_expires: None,
         });
 
         match &mut entry.value {
@@ -90,13 +174,14 @@ impl ZSetOps for DB {
                     }
                 }
                 self.increment_changes();
+                self.notify_set(&key);
                 Ok(added)
             }
-            _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+            _ => Err(DbError::WrongType),
         }
     }
 
-    fn zadd_nx(&mut self, key: String, members: Vec<(f64, String)>) -> Result<usize, String> {
+    fn zadd_nx(&mut self, key: String, members: Vec<(f64, String)>) -> DbResult<usize> {
         self.check_expiration(&key);
 
         // Filter out members that already exist
@@ -104,7 +189,7 @@ impl ZSetOps for DB {
             if let DataType::ZSet(zset) = &entry.value {
                 zset.members.keys().cloned().collect()
             } else {
-                return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+                return Err(DbError::WrongType);
             }
         } else {
             std::collections::HashSet::new()
@@ -118,7 +203,7 @@ impl ZSetOps for DB {
         self.zadd(key, filtered)
     }
 
-    fn zadd_xx(&mut self, key: String, members: Vec<(f64, String)>) -> Result<usize, String> {
+    fn zadd_xx(&mut self, key: String, members: Vec<(f64, String)>) -> DbResult<usize> {
         self.check_expiration(&key);
 
         // Filter to only include members that already exist
@@ -131,14 +216,14 @@ impl ZSetOps for DB {
                     .collect();
                 return self.zadd(key, filtered);
             } else {
-                return Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string());
+                return Err(DbError::WrongType);
             }
         }
         
         Ok(0)
     }
 
-    fn zrem(&mut self, key: String, members: Vec<String>) -> Result<usize, String> {
+    fn zrem(&mut self, key: String, members: Vec<String>) -> DbResult<usize> {
         if !self.check_expiration(&key) {
             return Ok(0);
         }
@@ -154,61 +239,81 @@ impl ZSetOps for DB {
                     }
                     if removed > 0 {
                         self.increment_changes();
+                        self.notify_set(&key);
                     }
                     Ok(removed)
                 }
-                _ => Err("WRONGTYPE Operation against a key holding the wrong kind of value".to_string()),
+                _ => Err(DbError::WrongType),
             }
         } else {
             Ok(0)
         }
     }
 
-    fn zscore(&mut self, key: String, member: String) -> Option<f64> {
-        if !self.check_expiration(&key) {
+    fn zscore(&mut self, key: &str, member: &str) -> Option<f64> {
+        if !self.check_expiration(key) {
             return None;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
-                return zset.score(&member);
+                return zset.score(member);
             }
         }
         None
     }
 
-    fn zrank(&mut self, key: String, member: String) -> Option<usize> {
-        if !self.check_expiration(&key) {
+    fn zrank(&mut self, key: &str, member: &str) -> Option<usize> {
+        if !self.check_expiration(key) {
             return None;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
-                return zset.rank(&member);
+                return zset.rank(member);
             }
         }
         None
     }
 
-    fn zrevrank(&mut self, key: String, member: String) -> Option<usize> {
-        if !self.check_expiration(&key) {
+    fn zrevrank(&mut self, key: &str, member: &str) -> Option<usize> {
+        if !self.check_expiration(key) {
             return None;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
-                return zset.revrank(&member);
+                return zset.revrank(member);
             }
         }
         None
     }
 
-    fn zrange(&mut self, key: String, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)> {
-        if !self.check_expiration(&key) {
+    fn zaround(&mut self, key: &str, member: &str, count: usize, rev: bool) -> Option<Vec<(usize, String, f64)>> {
+        if !self.check_expiration(key) {
+            return None;
+        }
+
+        let Some(DataType::ZSet(zset)) = self.items.get(key).map(|entry| &entry.value) else {
+            return None;
+        };
+        let rank = if rev { zset.revrank(member)? } else { zset.rank(member)? };
+        let len = zset.len();
+        let ranks = rank.saturating_sub(count)..rank.saturating_add(count).min(len - 1) + 1;
+        let window: Vec<(&str, f64)> = if rev {
+            zset.scores.range(len - ranks.end..len - ranks.start).rev().collect()
+        } else {
+            zset.scores.range(ranks.clone()).collect()
+        };
+        Some(ranks.zip(window).map(|(rank, (member, score))| (rank, member.to_string(), score)).collect())
+    }
+
+    fn zrange(&mut self, key: &str, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)> {
+        if !self.check_expiration(key) {
             return vec![];
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
                 return zset.range(start, stop)
                     .into_iter()
@@ -219,63 +324,70 @@ impl ZSetOps for DB {
         vec![]
     }
 
-    fn zrevrange(&mut self, key: String, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)> {
-        if !self.check_expiration(&key) {
+    fn zrevrange(&mut self, key: &str, start: i64, stop: i64, withscores: bool) -> Vec<(String, Option<f64>)> {
+        if !self.check_expiration(key) {
             return vec![];
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
-                let mut result: Vec<_> = zset.range(start, stop)
-                    .into_iter()
-                    .map(|(m, s)| (m, if withscores { Some(s) } else { None }))
+                // Indexes count from the highest score
+                let ranks = zset.ranks(start, stop);
+                let len = zset.len();
+                return zset.scores.range(len - ranks.end..len - ranks.start)
+                    .rev()
+                    .map(|(m, s)| (m.to_string(), if withscores { Some(s) } else { None }))
                     .collect();
-                result.reverse();
-                return result;
             }
         }
         vec![]
     }
 
-    fn zrangebyscore(&mut self, key: String, min: f64, max: f64, _withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)> {
-        if !self.check_expiration(&key) {
+    fn zrangebyscore(&mut self, key: &str, min: f64, max: f64, _withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)> {
+        if !self.check_expiration(key) {
             return vec![];
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
-                let mut result = zset.range_by_score(min, max);
-                
-                if let Some(off) = offset {
-                    if off < result.len() {
-                        result = result[off..].to_vec();
-                    } else {
-                        result.clear();
-                    }
-                }
-                
-                if let Some(cnt) = count {
-                    result.truncate(cnt);
-                }
-                
-                return result;
+                // Apply the limit to ranks so skipped members are never visited
+                let ranks = zset.score_ranks(min, max);
+                let start = ranks.start.saturating_add(offset.unwrap_or(0)).min(ranks.end);
+                let end = count.map_or(ranks.end, |cnt| start.saturating_add(cnt).min(ranks.end));
+                return zset.scores.range(start..end)
+                    .map(|(m, s)| (m.to_string(), s))
+                    .collect();
             }
         }
         vec![]
     }
 
-    fn zrevrangebyscore(&mut self, key: String, max: f64, min: f64, withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)> {
-        let mut result = self.zrangebyscore(key, min, max, withscores, offset, count);
-        result.reverse();
-        result
+    fn zrevrangebyscore(&mut self, key: &str, max: f64, min: f64, _withscores: bool, offset: Option<usize>, count: Option<usize>) -> Vec<(String, f64)> {
+        if !self.check_expiration(key) {
+            return vec![];
+        }
+
+        if let Some(entry) = self.items.get(key) {
+            if let DataType::ZSet(zset) = &entry.value {
+                // The offset counts from the highest score
+                let ranks = zset.score_ranks(min, max);
+                let end = ranks.end.saturating_sub(offset.unwrap_or(0)).max(ranks.start);
+                let start = count.map_or(ranks.start, |cnt| end.saturating_sub(cnt).max(ranks.start));
+                return zset.scores.range(start..end)
+                    .rev()
+                    .map(|(m, s)| (m.to_string(), s))
+                    .collect();
+            }
+        }
+        vec![]
     }
 
-    fn zcard(&mut self, key: String) -> usize {
-        if !self.check_expiration(&key) {
+    fn zcard(&mut self, key: &str) -> usize {
+        if !self.check_expiration(key) {
             return 0;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
                 return zset.len();
             }
@@ -283,12 +395,12 @@ impl ZSetOps for DB {
         0
     }
 
-    fn zcount(&mut self, key: String, min: f64, max: f64) -> usize {
-        if !self.check_expiration(&key) {
+    fn zcount(&mut self, key: &str, min: f64, max: f64) -> usize {
+        if !self.check_expiration(key) {
             return 0;
         }
 
-        if let Some(entry) = self.items.get(&key) {
+        if let Some(entry) = self.items.get(key) {
             if let DataType::ZSet(zset) = &entry.value {
                 return zset.count(min, max);
             }
@@ -296,14 +408,14 @@ impl ZSetOps for DB {
         0
     }
 
-    fn zincrby(&mut self, key: String, increment: f64, member: String) -> Result<f64, String> {
+    fn zincrby(&mut self, key: String, increment: f64, member: String) -> DbResult<f64> {
         self.check_expiration(&key);
 
-        let current_score = self.zscore(key.clone(), member.clone()).unwrap_or(0.0);
+        let current_score = self.zscore(&key, &member).unwrap_or(0.0);
         let new_score = current_score + increment;
 
         if new_score.is_nan() || new_score.is_infinite() {
-            return Err("ERR resulting score is not a number (nan) or infinity".to_string());
+            return Err(DbError::InvalidValue("resulting score is not a number (nan) or infinity".to_string()));
         }
 
         self.zadd(key, vec![(new_score, member)])?;
@@ -315,7 +427,7 @@ impl ZSetOps for DB {
             return 0;
         }
 
-        let members_to_remove: Vec<String> = self.zrange(key.clone(), start, stop, false)
+        let members_to_remove: Vec<String> = self.zrange(&key, start, stop, false)
             .into_iter()
             .map(|(m, _)| m)
             .collect();
@@ -328,7 +440,7 @@ impl ZSetOps for DB {
             return 0;
         }
 
-        let members_to_remove: Vec<String> = self.zrangebyscore(key.clone(), min, max, false, None, None)
+        let members_to_remove: Vec<String> = self.zrangebyscore(&key, min, max, false, None, None)
             .into_iter()
             .map(|(m, _)| m)
             .collect();
@@ -357,11 +469,13 @@ impl ZSetOps for DB {
         }
 
         let len = result.len();
-        self.items.insert(dst, Entry {
+        self.items.insert(dst.clone(), Entry {
             value: DataType::ZSet(result),
             expires_at: None,
+            field_expires: None,
         });
         self.increment_changes();
+        self.notify_set(&dst);
         len
     }
 
@@ -431,17 +545,19 @@ impl ZSetOps for DB {
         }
 
         let len = final_zset.len();
-        self.items.insert(dst, Entry {
+        self.items.insert(dst.clone(), Entry {
             value: DataType::ZSet(final_zset),
             expires_at: None,
+            field_expires: None,
         });
         self.increment_changes();
+        self.notify_set(&dst);
         len
     }
 
-    fn zmscore(&mut self, key: String, members: Vec<String>) -> Vec<Option<f64>> {
+    fn zmscore(&mut self, key: &str, members: Vec<String>) -> Vec<Option<f64>> {
         members.into_iter()
-            .map(|m| self.zscore(key.clone(), m))
+            .map(|m| self.zscore(key, &m))
             .collect()
     }
 
@@ -456,15 +572,16 @@ impl ZSetOps for DB {
         if let Some(entry) = self.items.get_mut(&key) {
             if let DataType::ZSet(zset) = &mut entry.value {
                 for _ in 0..count {
-                    if let Some(entry) = zset.scores.iter().next().cloned() {
-                        result.push((entry.member.clone(), entry.score));
-                        zset.remove(&entry.member);
+                    if let Some((member, score)) = zset.scores.first().map(|(m, s)| (m.to_string(), s)) {
+                        zset.remove(&member);
+                        result.push((member, score));
                     } else {
                         break;
                     }
                 }
                 if !result.is_empty() {
                     self.increment_changes();
+                    self.notify_set(&key);
                 }
             }
         }
@@ -483,35 +600,147 @@ impl ZSetOps for DB {
         if let Some(entry) = self.items.get_mut(&key) {
             if let DataType::ZSet(zset) = &mut entry.value {
                 for _ in 0..count {
-                    if let Some(entry) = zset.scores.iter().next_back().cloned() {
-                        result.push((entry.member.clone(), entry.score));
-                        zset.remove(&entry.member);
+                    if let Some((member, score)) = zset.scores.last().map(|(m, s)| (m.to_string(), s)) {
+                        zset.remove(&member);
+                        result.push((member, score));
                     } else {
                         break;
                     }
                 }
                 if !result.is_empty() {
                     self.increment_changes();
+                    self.notify_set(&key);
                 }
             }
         }
 
         result
     }
+
+    fn zrandmember(&mut self, key: &str, count: i64) -> DbResult<Vec<(String, f64)>> {
+        check_random_count(count)?;
+        if !self.check_expiration(key) {
+            return Ok(vec![]);
+        }
+        let members: Vec<(String, f64)> = match self.items.get(key).map(|e| &e.value) {
+            Some(DataType::ZSet(zset)) => zset.members.iter().map(|(m, s)| (m.clone(), *s)).collect(),
+            Some(_) => return Err(DbError::WrongType),
+            None => return Ok(vec![]),
+        };
+        if members.is_empty() || count == 0 {
+            return Ok(vec![]);
+        }
+
+        let mut rng = rand::thread_rng();
+        if count > 0 {
+            Ok(members
+                .choose_multiple(&mut rng, count as usize)
+                .cloned()
+                .collect())
+        } else {
+            Ok((0..count.unsigned_abs())
+                .map(|_| members[rng.gen_range(0..members.len())].clone())
+                .collect())
+        }
+    }
+
+    fn zunion(&mut self, keys: Vec<String>, weights: Option<Vec<f64>>, aggregate: ZAggregate) -> DbResult<Vec<(String, f64)>> {
+        let weights = weights.unwrap_or_else(|| vec![1.0; keys.len()]);
+        let mut result: HashMap<String, f64> = HashMap::new();
+
+        for (i, key) in keys.iter().enumerate() {
+            let weight = weights.get(i).copied().unwrap_or(1.0);
+            for (member, score) in self.zset_members_for(key)? {
+                let weighted = score * weight;
+                result
+                    .entry(member)
+                    .and_modify(|s| *s = aggregate.combine(*s, weighted))
+                    .or_insert(weighted);
+            }
+        }
+
+        Ok(sorted_by_score(result))
+    }
+
+    fn zinter(&mut self, keys: Vec<String>, weights: Option<Vec<f64>>, aggregate: ZAggregate) -> DbResult<Vec<(String, f64)>> {
+        let weights = weights.unwrap_or_else(|| vec![1.0; keys.len()]);
+        let mut result: Option<HashMap<String, f64>> = None;
+
+        for (i, key) in keys.iter().enumerate() {
+            let weight = weights.get(i).copied().unwrap_or(1.0);
+            let members = self.zset_members_for(key)?;
+            result = Some(match result {
+                None => members.into_iter().map(|(m, s)| (m, s * weight)).collect(),
+                Some(mut acc) => {
+                    acc.retain(|member, score| match members.get(member) {
+                        Some(other) => {
+                            *score = aggregate.combine(*score, other * weight);
+                            true
+                        }
+                        None => false,
+                    });
+                    acc
+                }
+            });
+        }
+
+        Ok(sorted_by_score(result.unwrap_or_default()))
+    }
+
+    fn zdiff(&mut self, keys: Vec<String>) -> DbResult<Vec<(String, f64)>> {
+        let mut iter = keys.iter();
+        let mut result = match iter.next() {
+            Some(first) => self.zset_members_for(first)?,
+            None => return Ok(vec![]),
+        };
+
+        for key in iter {
+            let other = self.zset_members_for(key)?;
+            result.retain(|member, _| !other.contains_key(member));
+        }
+
+        Ok(sorted_by_score(result))
+    }
+
+    fn zdiffstore(&mut self, dst: String, keys: Vec<String>) -> DbResult<usize> {
+        let members = self.zdiff(keys)?;
+        let len = members.len();
+
+        if members.is_empty() {
+            if let Some(old) = self.items.remove(&dst) {
+                self.notify_delete(&dst, &old);
+            }
+        } else {
+            let mut zset = ZSetData::new();
+            for (member, score) in members {
+                zset.insert(member, score);
+            }
+            self.items.insert(dst.clone(), Entry {
+                value: DataType::ZSet(zset),
+                expires_at: None,
+                field_expires: None,
+            });
+        }
+        self.increment_changes();
+        self.notify_set(&dst);
+        Ok(len)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::ops::set::SetOps;
+    use crate::db::ops::MAX_RANDOM_COUNT;
 
     #[test]
     fn test_zset_basic() {
         let mut db = DB::new();
         
         assert_eq!(db.zadd("myzset".to_string(), vec![(1.0, "one".to_string()), (2.0, "two".to_string()), (3.0, "three".to_string())]).unwrap(), 3);
-        assert_eq!(db.zcard("myzset".to_string()), 3);
-        assert_eq!(db.zscore("myzset".to_string(), "two".to_string()), Some(2.0));
-        assert_eq!(db.zrank("myzset".to_string(), "two".to_string()), Some(1));
+        assert_eq!(db.zcard("myzset"), 3);
+        assert_eq!(db.zscore("myzset", "two"), Some(2.0));
+        assert_eq!(db.zrank("myzset", "two"), Some(1));
     }
 
     #[test]
@@ -519,19 +748,108 @@ mod tests {
         let mut db = DB::new();
         db.zadd("myzset".to_string(), vec![(1.0, "a".to_string()), (2.0, "b".to_string()), (3.0, "c".to_string())]).unwrap();
         
-        let range = db.zrange("myzset".to_string(), 0, -1, false);
+        let range = db.zrange("myzset", 0, -1, false);
         assert_eq!(range.len(), 3);
         assert_eq!(range[0].0, "a");
         assert_eq!(range[1].0, "b");
         assert_eq!(range[2].0, "c");
     }
 
+    #[test]
+    fn test_ranks_and_score_ranges() {
+        let mut db = DB::new();
+        let members = (0..100).map(|i| ((i / 2) as f64, format!("m{:02}", i))).collect();
+        db.zadd("z".to_string(), members).unwrap();
+        db.zadd("z".to_string(), vec![(100.0, "m00".to_string())]).unwrap();
+
+        assert_eq!(db.zrank("z", "m01"), Some(0));
+        assert_eq!(db.zrank("z", "m00"), Some(99));
+        assert_eq!(db.zrevrank("z", "m00"), Some(0));
+        assert_eq!(db.zrank("z", "missing"), None);
+
+        let names = |items: Vec<(String, Option<f64>)>| items.into_iter().map(|(m, _)| m).collect::<Vec<_>>();
+        assert_eq!(names(db.zrange("z", -2, -1, false)), vec!["m99", "m00"]);
+        assert_eq!(names(db.zrevrange("z", 0, 1, false)), vec!["m00", "m99"]);
+        assert!(db.zrange("z", 0, -200, false).is_empty());
+
+        assert_eq!(db.zcount("z", 10.0, 19.0), 20);
+        let by_score = db.zrangebyscore("z", 10.0, 19.0, false, Some(3), Some(2));
+        assert_eq!(by_score, vec![("m23".to_string(), 11.0), ("m24".to_string(), 12.0)]);
+        let by_score = db.zrevrangebyscore("z", 19.0, 10.0, false, Some(1), Some(2));
+        assert_eq!(by_score, vec![("m38".to_string(), 19.0), ("m37".to_string(), 18.0)]);
+        assert!(db.zrangebyscore("z", 10.0, 19.0, false, Some(50), None).is_empty());
+
+        assert_eq!(db.zpopmin("z".to_string(), Some(2)), vec![("m01".to_string(), 0.0), ("m02".to_string(), 1.0)]);
+        assert_eq!(db.zpopmax("z".to_string(), None), vec![("m00".to_string(), 100.0)]);
+        assert_eq!(db.zrank("z", "m03"), Some(0));
+    }
+
+    #[test]
+    fn test_zaround() {
+        let mut db = DB::new();
+        let members = (0..10).map(|i| (i as f64, format!("p{}", i))).collect();
+        db.zadd("board".to_string(), members).unwrap();
+
+        let window = |items: Option<Vec<(usize, String, f64)>>| {
+            items.unwrap().into_iter().map(|(rank, member, _)| (rank, member)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            window(db.zaround("board", "p5", 1, false)),
+            vec![(4, "p4".to_string()), (5, "p5".to_string()), (6, "p6".to_string())]
+        );
+        // Highest score first, clamped at the top of the board
+        assert_eq!(
+            window(db.zaround("board", "p8", 2, true)),
+            vec![(0, "p9".to_string()), (1, "p8".to_string()), (2, "p7".to_string()), (3, "p6".to_string())]
+        );
+        assert_eq!(window(db.zaround("board", "p0", 0, false)), vec![(0, "p0".to_string())]);
+        assert_eq!(db.zaround("board", "p0", 100, true).unwrap().len(), 10);
+        assert_eq!(db.zaround("board", "nobody", 1, false), None);
+        assert_eq!(db.zaround("missing", "p0", 1, false), None);
+    }
+
     #[test]
     fn test_zincrby() {
         let mut db = DB::new();
         db.zadd("myzset".to_string(), vec![(1.0, "one".to_string())]).unwrap();
         
         assert_eq!(db.zincrby("myzset".to_string(), 2.5, "one".to_string()).unwrap(), 3.5);
-        assert_eq!(db.zscore("myzset".to_string(), "one".to_string()), Some(3.5));
+        assert_eq!(db.zscore("myzset", "one"), Some(3.5));
+    }
+
+    #[test]
+    fn test_zunion_zinter_aggregate() {
+        let mut db = DB::new();
+        db.zadd("a".to_string(), vec![(1.0, "x".to_string()), (2.0, "y".to_string())]).unwrap();
+        db.zadd("b".to_string(), vec![(5.0, "y".to_string()), (3.0, "z".to_string())]).unwrap();
+
+        let union = db.zunion(vec!["a".to_string(), "b".to_string()], None, ZAggregate::Sum).unwrap();
+        assert_eq!(union, vec![("x".to_string(), 1.0), ("z".to_string(), 3.0), ("y".to_string(), 7.0)]);
+
+        let inter = db.zinter(vec!["a".to_string(), "b".to_string()], Some(vec![2.0, 1.0]), ZAggregate::Min).unwrap();
+        assert_eq!(inter, vec![("y".to_string(), 4.0)]);
+
+        let inter = db.zinter(vec!["a".to_string(), "b".to_string()], None, ZAggregate::Max).unwrap();
+        assert_eq!(inter, vec![("y".to_string(), 5.0)]);
+    }
+
+    #[test]
+    fn test_zdiff_and_randmember() {
+        let mut db = DB::new();
+        db.zadd("a".to_string(), vec![(1.0, "x".to_string()), (2.0, "y".to_string()), (3.0, "z".to_string())]).unwrap();
+        db.zadd("b".to_string(), vec![(1.0, "y".to_string())]).unwrap();
+
+        let diff = db.zdiff(vec!["a".to_string(), "b".to_string()]).unwrap();
+        assert_eq!(diff, vec![("x".to_string(), 1.0), ("z".to_string(), 3.0)]);
+        assert_eq!(db.zdiffstore("c".to_string(), vec!["a".to_string(), "b".to_string()]).unwrap(), 2);
+        assert_eq!(db.zcard("c"), 2);
+
+        assert_eq!(db.zrandmember("a", 10).unwrap().len(), 3);
+        assert_eq!(db.zrandmember("a", -5).unwrap().len(), 5);
+        assert!(db.zrandmember("missing", 1).unwrap().is_empty());
+        assert!(matches!(db.zrandmember("a", i64::MIN), Err(DbError::InvalidValue(_))));
+        assert_eq!(db.zrandmember("a", -(MAX_RANDOM_COUNT as i64)).unwrap().len(), MAX_RANDOM_COUNT as usize);
+        db.sadd("set".to_string(), vec!["m".to_string()]).unwrap();
+        assert_eq!(db.zrandmember("set", 1), Err(DbError::WrongType));
     }
 }
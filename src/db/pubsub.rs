@@ -2,16 +2,34 @@
 //!
 //! Provides publish/subscribe messaging between clients.
 //! Supports both channel subscriptions and pattern-based subscriptions.
+//!
+//! Shard channels (SSUBSCRIBE/SPUBLISH) are a separate namespace: a message
+//! published to one reaches only that shard channel's subscribers, never
+//! channel or pattern subscribers. Without cluster mode the whole server
+//! is one shard, so every shard channel lives here.
 
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tokio::sync::{broadcast, RwLock};
 
+/// Prefix of the stream keys that `[[pubsub_log]]` channels are logged to
+pub const LOG_KEY_PREFIX: &str = "__pubsub_log__:";
+
+/// Stream key holding the logged messages of a channel
+pub fn log_key(channel: &str) -> String {
+    format!("{}{}", LOG_KEY_PREFIX, channel)
+}
+
 /// Pub/Sub manager
 pub struct PubSub {
     /// Channel subscribers
     channels: RwLock<HashMap<String, broadcast::Sender<String>>>,
     /// Pattern subscribers (glob patterns)
     patterns: RwLock<HashMap<String, broadcast::Sender<(String, String)>>>,
+    /// Last retained message per channel and when it expires
+    retained: RwLock<HashMap<String, (String, Option<Instant>)>>,
+    /// Shard channel subscribers
+    shard_channels: RwLock<HashMap<String, broadcast::Sender<String>>>,
 }
 
 impl PubSub {
@@ -20,6 +38,8 @@ impl PubSub {
         PubSub {
             channels: RwLock::new(HashMap::new()),
             patterns: RwLock::new(HashMap::new()),
+            retained: RwLock::new(HashMap::new()),
+            shard_channels: RwLock::new(HashMap::new()),
         }
     }
 
@@ -73,6 +93,46 @@ impl PubSub {
         count
     }
 
+    /// Subscribe to a shard channel
+    pub async fn ssubscribe(&self, channel: &str) -> broadcast::Receiver<String> {
+        let mut channels = self.shard_channels.write().await;
+        let sender = channels.entry(channel.to_string()).or_insert_with(|| {
+            let (tx, _) = broadcast::channel(1000);
+            tx
+        });
+        sender.subscribe()
+    }
+
+    /// Publish a message to a shard channel
+    /// Returns the number of its subscribers that received the message
+    pub async fn spublish(&self, channel: &str, message: &str) -> usize {
+        let channels = self.shard_channels.read().await;
+        channels
+            .get(channel)
+            .map_or(0, |sender| sender.send(message.to_string()).unwrap_or(0))
+    }
+
+    /// Keep `message` as the channel's retained message, replacing the
+    /// previous one. It is dropped after `ttl` if given.
+    pub async fn retain(&self, channel: &str, message: &str, ttl: Option<Duration>) {
+        let expires_at = ttl.and_then(|ttl| Instant::now().checked_add(ttl));
+        let mut retained = self.retained.write().await;
+        retained.insert(channel.to_string(), (message.to_string(), expires_at));
+    }
+
+    /// Get the retained message of a channel, if any and not expired
+    pub async fn retained(&self, channel: &str) -> Option<String> {
+        let mut retained = self.retained.write().await;
+        match retained.get(channel) {
+            Some((_, Some(expires_at))) if Instant::now() >= *expires_at => {
+                retained.remove(channel);
+                None
+            }
+            Some((message, _)) => Some(message.clone()),
+            None => None,
+        }
+    }
+
     /// Unsubscribe from a channel (removes the channel if no subscribers remain)
     pub async fn unsubscribe(&self, channel: &str) {
         let mut channels = self.channels.write().await;
@@ -94,6 +154,16 @@ impl PubSub {
         }
     }
 
+    /// Unsubscribe from a shard channel (removes it if no subscribers remain)
+    pub async fn sunsubscribe(&self, channel: &str) {
+        let mut channels = self.shard_channels.write().await;
+        if let Some(sender) = channels.get(channel) {
+            if sender.receiver_count() == 0 {
+                channels.remove(channel);
+            }
+        }
+    }
+
     /// Get number of subscribers for a channel
     pub async fn numsub(&self, channel: &str) -> usize {
         let channels = self.channels.read().await;
@@ -105,19 +175,31 @@ impl PubSub {
         }
     }
 
-    /// Get list of active channels
+    /// Get list of channels with at least one subscriber
     pub async fn channels(&self, pattern: Option<&str>) -> Vec<String> {
         let channels = self.channels.read().await;
         
-        channels.keys()
-            .filter(|ch| {
-                if let Some(p) = pattern {
-                    glob_match(p, ch)
-                } else {
-                    true
-                }
+        channels.iter()
+            .filter(|(ch, sender)| {
+                sender.receiver_count() > 0 && pattern.is_none_or(|p| glob_match(p, ch))
             })
-            .cloned()
+            .map(|(ch, _)| ch.clone())
+            .collect()
+    }
+
+    /// Get number of subscribers for a shard channel
+    pub async fn shard_numsub(&self, channel: &str) -> usize {
+        let channels = self.shard_channels.read().await;
+        channels.get(channel).map_or(0, |sender| sender.receiver_count())
+    }
+
+    /// Get list of shard channels with at least one subscriber
+    pub async fn shard_channels(&self, pattern: Option<&str>) -> Vec<String> {
+        let channels = self.shard_channels.read().await;
+        channels
+            .iter()
+            .filter(|(ch, sender)| sender.receiver_count() > 0 && pattern.is_none_or(|p| glob_match(p, ch)))
+            .map(|(ch, _)| ch.clone())
             .collect()
     }
 
@@ -227,4 +309,39 @@ mod tests {
         assert!(glob_match("h[ae]llo", "hallo"));
         assert!(!glob_match("h[ae]llo", "hillo"));
     }
+
+    #[tokio::test]
+    async fn test_shard_channels_are_separate() {
+        let pubsub = PubSub::new();
+        let mut shard = pubsub.ssubscribe("orders").await;
+        let mut plain = pubsub.subscribe("orders").await;
+        let _pattern = pubsub.psubscribe("*").await;
+
+        assert_eq!(pubsub.spublish("orders", "1").await, 1);
+        assert_eq!(shard.recv().await.unwrap(), "1");
+        assert!(plain.try_recv().is_err());
+
+        assert_eq!(pubsub.publish("orders", "2").await, 2);
+        assert!(shard.try_recv().is_err());
+
+        assert_eq!(pubsub.shard_channels(Some("ord*")).await, vec!["orders".to_string()]);
+        assert_eq!(pubsub.shard_numsub("orders").await, 1);
+        drop(shard);
+        assert!(pubsub.shard_channels(None).await.is_empty());
+        pubsub.sunsubscribe("orders").await;
+        assert_eq!(pubsub.shard_numsub("orders").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_retained_message() {
+        let pubsub = PubSub::new();
+        assert_eq!(pubsub.retained("status").await, None);
+
+        pubsub.retain("status", "up", None).await;
+        pubsub.retain("status", "down", None).await;
+        assert_eq!(pubsub.retained("status").await, Some("down".to_string()));
+
+        pubsub.retain("temp", "21", Some(Duration::ZERO)).await;
+        assert_eq!(pubsub.retained("temp").await, None);
+    }
 }
@@ -0,0 +1,267 @@
+//! Recycle bin for soft-deleted keys.
+//!
+//! When enabled, DEL and FLUSHDB move entries here instead of dropping them,
+//! so a fat-fingered delete can be reverted with UNDELETE until the
+//! retention window runs out. The bin can be capped by key count and by
+//! size; past a cap the oldest tombstones are dropped first.
+
+use crate::db::introspect::serialized_length;
+use crate::db::types::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// A deleted entry waiting in the recycle bin
+#[derive(Debug, Clone)]
+pub struct Tombstone {
+    pub entry: Entry,
+    pub deleted_at: Instant,
+    /// Position in burial order
+    seq: u64,
+    /// Bytes counted against `max_memory` (0 when it is not set)
+    size: usize,
+}
+
+/// Holds soft-deleted keys for a configurable retention window
+#[derive(Debug, Default)]
+pub struct RecycleBin {
+    /// How long tombstones are kept (None = recycle bin disabled)
+    retention: Option<Duration>,
+    /// Most tombstones kept (0 = unlimited)
+    max_entries: usize,
+    /// Most bytes kept, as keys plus serialized values (0 = unlimited)
+    max_memory: usize,
+    /// Deleted key -> tombstone
+    entries: HashMap<String, Tombstone>,
+    /// Deleted keys by burial sequence, oldest first
+    order: BTreeMap<u64, String>,
+    next_seq: u64,
+    /// Sum of the tombstone sizes
+    memory: usize,
+}
+
+impl RecycleBin {
+    /// Create a disabled recycle bin
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the retention window. `None` disables the bin and drops its contents.
+    pub fn set_retention(&mut self, retention: Option<Duration>) {
+        self.retention = retention;
+        if retention.is_none() {
+            self.clear();
+        } else {
+            self.purge_expired();
+        }
+    }
+
+    /// Get the retention window
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention
+    }
+
+    /// Cap the bin at `max_entries` keys and `max_memory` bytes (0 = no cap),
+    /// dropping the oldest tombstones to fit
+    pub fn set_limits(&mut self, max_entries: usize, max_memory: usize) {
+        self.max_entries = max_entries;
+        self.max_memory = max_memory;
+        self.memory = 0;
+        for (key, tombstone) in self.entries.iter_mut() {
+            tombstone.size = if max_memory > 0 { key.len() + serialized_length(&tombstone.entry.value) } else { 0 };
+            self.memory += tombstone.size;
+        }
+        self.evict();
+    }
+
+    /// Check if soft-delete is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.retention.is_some()
+    }
+
+    /// Move a deleted entry into the bin. Does nothing when disabled.
+    pub fn bury(&mut self, key: String, entry: Entry) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.purge_expired();
+        self.insert(key, entry, Instant::now());
+        self.evict();
+    }
+
+    /// Move a batch of deleted entries into the bin (FLUSHDB). Expired
+    /// tombstones are purged once up front rather than once per key.
+    pub fn bury_many<I>(&mut self, entries: I)
+    where
+        I: IntoIterator<Item = (String, Entry)>,
+    {
+        if !self.is_enabled() {
+            return;
+        }
+        self.purge_expired();
+        let deleted_at = Instant::now();
+        for (key, entry) in entries {
+            self.insert(key, entry, deleted_at);
+        }
+        self.evict();
+    }
+
+    /// Take an entry back out of the bin if it is still within retention
+    pub fn restore(&mut self, key: &str) -> Option<Entry> {
+        self.purge_expired();
+        self.remove(key).map(|t| t.entry)
+    }
+
+    /// Drop tombstones older than the retention window. Returns how many were dropped.
+    pub fn purge_expired(&mut self) -> usize {
+        let retention = match self.retention {
+            Some(r) => r,
+            None => return 0,
+        };
+        let mut purged = 0;
+        while let Some(key) = self.order.values().next() {
+            if self.entries[key].deleted_at.elapsed() < retention {
+                break;
+            }
+            let key = key.clone();
+            self.remove(&key);
+            purged += 1;
+        }
+        purged
+    }
+
+    /// Number of keys currently in the bin
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the bin is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bytes counted against the size cap
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Empty the bin
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.memory = 0;
+    }
+
+    fn insert(&mut self, key: String, entry: Entry, deleted_at: Instant) {
+        self.remove(&key);
+        let size = if self.max_memory > 0 { key.len() + serialized_length(&entry.value) } else { 0 };
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.memory += size;
+        self.order.insert(seq, key.clone());
+        self.entries.insert(key, Tombstone { entry, deleted_at, seq, size });
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Tombstone> {
+        let tombstone = self.entries.remove(key)?;
+        self.order.remove(&tombstone.seq);
+        self.memory -= tombstone.size;
+        Some(tombstone)
+    }
+
+    /// Drop the oldest tombstones until the bin is within its caps
+    fn evict(&mut self) {
+        while (self.max_entries > 0 && self.entries.len() > self.max_entries)
+            || (self.max_memory > 0 && self.memory > self.max_memory)
+        {
+            let Some((_, key)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some(tombstone) = self.entries.remove(&key) {
+                self.memory -= tombstone.size;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::types::DataType;
+
+    fn string_entry(value: &str) -> Entry {
+        Entry {
+            value: DataType::String(value.to_string()),
+            expires_at: None,
+            field_expires: None,
+        }
+    }
+
+    #[test]
+    fn test_disabled_bin_drops_entries() {
+        let mut bin = RecycleBin::new();
+        bin.bury("key".to_string(), string_entry("value"));
+        assert!(bin.is_empty());
+        assert!(bin.restore("key").is_none());
+    }
+
+    #[test]
+    fn test_bury_and_restore() {
+        let mut bin = RecycleBin::new();
+        bin.set_retention(Some(Duration::from_secs(60)));
+        bin.bury("key".to_string(), string_entry("value"));
+        assert_eq!(bin.len(), 1);
+
+        let entry = bin.restore("key").unwrap();
+        assert!(matches!(entry.value, DataType::String(ref s) if s == "value"));
+        assert!(bin.is_empty());
+    }
+
+    #[test]
+    fn test_bury_many() {
+        let mut bin = RecycleBin::new();
+        bin.bury_many(vec![("a".to_string(), string_entry("1"))]);
+        assert!(bin.is_empty());
+
+        bin.set_retention(Some(Duration::from_secs(60)));
+        bin.bury_many((0..100).map(|i| (format!("key{}", i), string_entry("value"))));
+        assert_eq!(bin.len(), 100);
+        assert!(bin.restore("key42").is_some());
+    }
+
+    #[test]
+    fn test_limits_evict_oldest() {
+        let mut bin = RecycleBin::new();
+        bin.set_retention(Some(Duration::from_secs(60)));
+        bin.set_limits(2, 0);
+        for key in ["a", "b", "c"] {
+            bin.bury(key.to_string(), string_entry("value"));
+        }
+        assert_eq!(bin.len(), 2);
+        assert!(bin.restore("a").is_none());
+
+        // Burying a key again makes it the newest
+        bin.bury("b".to_string(), string_entry("value"));
+        bin.bury("d".to_string(), string_entry("value"));
+        assert!(bin.restore("c").is_none());
+        assert!(bin.restore("b").is_some());
+
+        // A size cap counts keys and serialized values
+        bin.set_limits(0, 30);
+        assert!(bin.memory() > 0 && bin.memory() <= 30);
+        bin.bury("big".to_string(), string_entry(&"x".repeat(100)));
+        assert!(bin.restore("big").is_none());
+        bin.bury_many((0..10).map(|i| (format!("k{}", i), string_entry("v"))));
+        assert!(bin.memory() <= 30);
+        assert!(bin.restore("k9").is_some());
+        assert!(bin.restore("k0").is_none());
+    }
+
+    #[test]
+    fn test_retention_expiry() {
+        let mut bin = RecycleBin::new();
+        bin.set_retention(Some(Duration::from_millis(1)));
+        bin.bury("key".to_string(), string_entry("value"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(bin.restore("key").is_none());
+    }
+}
@@ -0,0 +1,415 @@
+//! Indexable skiplist ordering the members of a sorted set.
+//!
+//! Elements are kept by score, then member, the order of `ZRANGE`. As in
+//! Redis, every forward link records how many elements it skips (its span),
+//! so the rank of an element and the element at a rank are both found in
+//! O(log n), and range queries walk only the elements they return. Nodes
+//! live in one vector and link to each other by index; the slots of removed
+//! nodes are reused by later inserts.
+
+use rand::Rng;
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// Levels a node can have, enough for 4^32 elements
+const MAX_LEVEL: usize = 32;
+/// Chance of a node reaching the next level
+const LEVEL_P: f64 = 0.25;
+/// Index of the header node, which holds no element
+const HEAD: usize = 0;
+
+/// Order of sorted set elements: by score, then by member
+pub fn cmp_elements(score: f64, member: &str, other_score: f64, other_member: &str) -> Ordering {
+    match score.partial_cmp(&other_score) {
+        Some(Ordering::Equal) | None => member.cmp(other_member),
+        Some(ord) => ord,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    next: Option<usize>,
+    /// Elements between this node and `next`, counting `next`
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: String,
+    score: f64,
+    prev: Option<usize>,
+    links: Vec<Link>,
+}
+
+/// Elements of a sorted set in (score, member) order
+#[derive(Debug, Clone)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    /// Slots of removed nodes
+    free: Vec<usize>,
+    tail: Option<usize>,
+    level: usize,
+    len: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SkipList {
+    pub fn new() -> Self {
+        let head = Node {
+            member: String::new(),
+            score: 0.0,
+            prev: None,
+            links: vec![Link { next: None, span: 0 }; MAX_LEVEL],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            level: 1,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Node slots allocated, used or not
+    pub fn capacity(&self) -> usize {
+        self.nodes.capacity() - 1
+    }
+
+    fn random_level() -> usize {
+        let mut rng = rand::thread_rng();
+        let mut level = 1;
+        while level < MAX_LEVEL && rng.gen_bool(LEVEL_P) {
+            level += 1;
+        }
+        level
+    }
+
+    /// Last node on each level ordered before (score, member), with the
+    /// rank of each
+    fn predecessors(&self, score: f64, member: &str) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].links[i].next {
+                let node = &self.nodes[next];
+                if cmp_elements(node.score, &node.member, score, member) != Ordering::Less {
+                    break;
+                }
+                rank[i] += self.nodes[x].links[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Insert an element. The member must not be in the list already.
+    pub fn insert(&mut self, member: String, score: f64) {
+        let (mut update, mut rank) = self.predecessors(score, &member);
+        let level = Self::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].links[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            prev: (update[0] != HEAD).then_some(update[0]),
+            links: Vec::with_capacity(level),
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let before = self.nodes[update[i]].links[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[id].links.push(Link {
+                next: before.next,
+                span: before.span - skipped,
+            });
+            self.nodes[update[i]].links[i] = Link {
+                next: Some(id),
+                span: skipped + 1,
+            };
+        }
+        // Links above the new node now pass over one more element
+        for (i, &node) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[node].links[i].span += 1;
+        }
+
+        match self.nodes[id].links[0].next {
+            Some(next) => self.nodes[next].prev = Some(id),
+            None => self.tail = Some(id),
+        }
+        self.len += 1;
+    }
+
+    /// Remove an element. Returns false if it is not in the list.
+    pub fn remove(&mut self, member: &str, score: f64) -> bool {
+        let (update, _) = self.predecessors(score, member);
+        let id = match self.nodes[update[0]].links[0].next {
+            Some(id) if cmp_elements(self.nodes[id].score, &self.nodes[id].member, score, member) == Ordering::Equal => id,
+            _ => return false,
+        };
+
+        for (i, &x) in update.iter().enumerate().take(self.level) {
+            let link = self.nodes[x].links[i];
+            if link.next == Some(id) {
+                let removed = self.nodes[id].links[i];
+                self.nodes[x].links[i] = Link {
+                    next: removed.next,
+                    span: link.span + removed.span - 1,
+                };
+            } else {
+                self.nodes[x].links[i].span -= 1;
+            }
+        }
+
+        let prev = self.nodes[id].prev;
+        match self.nodes[id].links[0].next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+        while self.level > 1 && self.nodes[HEAD].links[self.level - 1].next.is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        let node = &mut self.nodes[id];
+        node.member = String::new();
+        node.links = Vec::new();
+        self.free.push(id);
+        true
+    }
+
+    /// Number of leading elements for which `before(score, member)` holds.
+    /// The predicate must hold for a prefix of the list and fail after it.
+    pub fn count_before(&self, before: impl Fn(f64, &str) -> bool) -> usize {
+        let mut x = HEAD;
+        let mut rank = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[i].next {
+                let node = &self.nodes[next];
+                if !before(node.score, &node.member) {
+                    break;
+                }
+                rank += self.nodes[x].links[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+
+    /// Node holding the element at a 0-based rank
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len {
+            return None;
+        }
+        let target = rank + 1;
+        let mut x = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].links[i].next {
+                let span = self.nodes[x].links[i].span;
+                if traversed + span > target {
+                    break;
+                }
+                traversed += span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// Elements whose ranks fall in `ranks`, clamped to the list
+    pub fn range(&self, ranks: Range<usize>) -> Iter<'_> {
+        let end = ranks.end.min(self.len);
+        let start = ranks.start.min(end);
+        let remaining = end - start;
+        Iter {
+            list: self,
+            front: if remaining > 0 { self.node_at(start) } else { None },
+            back: if remaining > 0 { self.node_at(end - 1) } else { None },
+            remaining,
+        }
+    }
+
+    /// All elements in order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            list: self,
+            front: self.nodes[HEAD].links[0].next,
+            back: self.tail,
+            remaining: self.len,
+        }
+    }
+
+    /// Lowest element
+    pub fn first(&self) -> Option<(&str, f64)> {
+        self.iter().next()
+    }
+
+    /// Highest element
+    pub fn last(&self) -> Option<(&str, f64)> {
+        self.iter().next_back()
+    }
+
+    /// Rebuild the list without free slots. Returns the number released.
+    pub fn compact(&mut self) -> usize {
+        let before = self.capacity();
+        let mut order = Vec::with_capacity(self.len);
+        let mut next = self.nodes[HEAD].links[0].next;
+        while let Some(id) = next {
+            order.push(id);
+            next = self.nodes[id].links[0].next;
+        }
+        let mut list = SkipList::new();
+        list.nodes.reserve_exact(self.len);
+        for id in order {
+            let node = &mut self.nodes[id];
+            list.insert(std::mem::take(&mut node.member), node.score);
+        }
+        *self = list;
+        before.saturating_sub(self.capacity())
+    }
+}
+
+/// Iterator over a run of consecutive elements, from either end
+pub struct Iter<'a> {
+    list: &'a SkipList,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let list = self.list;
+        let node = &list.nodes[self.front?];
+        self.front = node.links[0].next;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let list = self.list;
+        let node = &list.nodes[self.back?];
+        self.back = node.prev;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members<'a>(iter: impl Iterator<Item = (&'a str, f64)>) -> Vec<String> {
+        iter.map(|(member, _)| member.to_string()).collect()
+    }
+
+    #[test]
+    fn test_order_rank_and_ranges() {
+        let mut list = SkipList::new();
+        for i in (0..1000).rev() {
+            list.insert(format!("m{:04}", i), (i / 10) as f64);
+        }
+        assert_eq!(list.len(), 1000);
+        assert_eq!(list.first(), Some(("m0000", 0.0)));
+        assert_eq!(list.last(), Some(("m0999", 99.0)));
+
+        for rank in [0, 1, 499, 998, 999] {
+            let member = format!("m{:04}", rank);
+            let score = (rank / 10) as f64;
+            assert_eq!(list.count_before(|s, m| cmp_elements(s, m, score, &member) == Ordering::Less), rank);
+            assert_eq!(members(list.range(rank..rank + 1)), vec![member]);
+        }
+        assert_eq!(list.count_before(|s, _| s < 50.0), 500);
+        assert_eq!(members(list.range(10..13)), vec!["m0010", "m0011", "m0012"]);
+        assert_eq!(members(list.range(10..13).rev()), vec!["m0012", "m0011", "m0010"]);
+        assert_eq!(list.range(995..2000).len(), 5);
+        assert_eq!(list.range(2000..3000).len(), 0);
+    }
+
+    #[test]
+    fn test_remove_and_reuse() {
+        let mut list = SkipList::new();
+        for i in 0..200 {
+            list.insert(format!("m{:03}", i), i as f64);
+        }
+        for i in (0..200).filter(|i| i % 2 == 0) {
+            assert!(list.remove(&format!("m{:03}", i), i as f64));
+        }
+        assert!(!list.remove("m000", 0.0));
+        assert!(!list.remove("m001", 2.0));
+        assert_eq!(list.len(), 100);
+        assert_eq!(members(list.range(0..3)), vec!["m001", "m003", "m005"]);
+        assert_eq!(list.last(), Some(("m199", 199.0)));
+        assert_eq!(list.count_before(|s, _| s < 100.0), 50);
+
+        let slots = list.nodes.len();
+        list.insert("again".to_string(), 0.5);
+        assert_eq!(list.nodes.len(), slots);
+        assert_eq!(list.first(), Some(("again", 0.5)));
+
+        list.compact();
+        assert_eq!(list.nodes.len(), 102);
+        assert_eq!(list.len(), 101);
+        assert_eq!(members(list.range(0..3)), vec!["again", "m001", "m003"]);
+        assert_eq!(list.count_before(|s, _| s < 100.0), 51);
+
+        while let Some((member, score)) = list.first().map(|(m, s)| (m.to_string(), s)) {
+            assert!(list.remove(&member, score));
+        }
+        assert!(list.is_empty());
+        assert_eq!(list.iter().count(), 0);
+        assert_eq!(list.last(), None);
+    }
+}
@@ -2,7 +2,11 @@
 //!
 //! Supports String, List, Hash, Set, Sorted Set, and more.
 
-use std::collections::{BTreeSet, HashMap, HashSet};
+use crate::db::filters::{BloomFilter, CuckooFilter};
+use crate::db::skiplist::{cmp_elements, SkipList};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::Range;
 use std::time::Instant;
 
 /// All supported data types in HexagonDB
@@ -10,8 +14,8 @@ use std::time::Instant;
 pub enum DataType {
     /// Simple string value
     String(String),
-    /// Ordered list of strings
-    List(Vec<String>),
+    /// Ordered list of strings, a ring buffer so both ends push and pop in O(1)
+    List(VecDeque<String>),
     /// Hash map of field -> value
     Hash(HashMap<String, String>),
     /// Unordered set of unique strings
@@ -26,6 +30,35 @@ pub enum DataType {
     Geo(GeoData),
     /// HyperLogLog data
     HyperLogLog(HyperLogLogData),
+    /// Priority queue with acknowledgements
+    Queue(QueueData),
+    /// Time series of timestamped samples
+    TimeSeries(TimeSeriesData),
+    /// Scalable bloom filter
+    Bloom(BloomFilter),
+    /// Scalable cuckoo filter
+    Cuckoo(CuckooFilter),
+    /// Vector index for nearest neighbour search
+    Vector(VectorData),
+}
+
+impl DataType {
+    /// Type name reported by TYPE (bitmaps and HyperLogLogs are strings, geo sets are zsets)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            DataType::String(_) | DataType::Bitmap(_) | DataType::HyperLogLog(_) => "string",
+            DataType::List(_) => "list",
+            DataType::Hash(_) => "hash",
+            DataType::Set(_) => "set",
+            DataType::ZSet(_) | DataType::Geo(_) => "zset",
+            DataType::Stream(_) => "stream",
+            DataType::Queue(_) => "queue",
+            DataType::TimeSeries(_) => "TSDB-TYPE",
+            DataType::Bloom(_) => "MBbloom--",
+            DataType::Cuckoo(_) => "MBbloomCF",
+            DataType::Vector(_) => "vectorset",
+        }
+    }
 }
 
 /// Database entry with value and optional expiration
@@ -33,80 +66,84 @@ pub enum DataType {
 pub struct Entry {
     pub value: DataType,
     pub expires_at: Option<Instant>,
+    /// Per-field expirations (hashes only, None when no field has a TTL)
+    pub field_expires: Option<Box<HashMap<String, Instant>>>,
 }
 
-/// Sorted Set data structure
-#[derive(Debug, Clone, Default)]
-pub struct ZSetData {
-    /// Member to score mapping
-    pub members: HashMap<String, f64>,
-    /// Score to members mapping (for range queries)
-    pub scores: BTreeSet<ZSetEntry>,
-}
-
-/// Entry in sorted set for ordering
-#[derive(Debug, Clone)]
-pub struct ZSetEntry {
-    pub score: f64,
-    pub member: String,
-}
-
-impl PartialEq for ZSetEntry {
-    fn eq(&self, other: &Self) -> bool {
-        self.score == other.score && self.member == other.member
+impl Entry {
+    /// Drop hash fields whose TTL has passed. Returns how many were removed.
+    pub fn purge_expired_fields(&mut self, now: Instant) -> usize {
+        let expires = match self.field_expires.as_mut() {
+            Some(expires) => expires,
+            None => return 0,
+        };
+        let expired: Vec<String> = expires
+            .iter()
+            .filter(|(_, at)| now >= **at)
+            .map(|(field, _)| field.clone())
+            .collect();
+        for field in &expired {
+            expires.remove(field);
+            if let DataType::Hash(hash) = &mut self.value {
+                hash.remove(field);
+            }
+        }
+        if expires.is_empty() {
+            self.field_expires = None;
+        }
+        expired.len()
     }
-}
-
-impl Eq for ZSetEntry {}
 
-impl PartialOrd for ZSetEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    /// Forget the TTL of a hash field. Returns true if it had one.
+    pub fn clear_field_expire(&mut self, field: &str) -> bool {
+        let expires = match self.field_expires.as_mut() {
+            Some(expires) => expires,
+            None => return false,
+        };
+        let removed = expires.remove(field).is_some();
+        if expires.is_empty() {
+            self.field_expires = None;
+        }
+        removed
     }
 }
 
-impl Ord for ZSetEntry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.score.partial_cmp(&other.score) {
-            Some(std::cmp::Ordering::Equal) | None => self.member.cmp(&other.member),
-            Some(ord) => ord,
-        }
-    }
+/// Sorted Set data structure
+#[derive(Debug, Clone, Default)]
+pub struct ZSetData {
+    /// Member to score mapping
+    pub members: HashMap<String, f64>,
+    /// Members ordered by score (for ranks and range queries)
+    pub scores: SkipList,
 }
 
 impl ZSetData {
     pub fn new() -> Self {
         ZSetData {
             members: HashMap::new(),
-            scores: BTreeSet::new(),
+            scores: SkipList::new(),
         }
     }
 
     /// Insert or update a member with score
     pub fn insert(&mut self, member: String, score: f64) -> bool {
-        let is_new = if let Some(&old_score) = self.members.get(&member) {
-            // Remove old entry from scores
-            self.scores.remove(&ZSetEntry {
-                score: old_score,
-                member: member.clone(),
-            });
-            false
-        } else {
-            true
-        };
+        match self.members.get(&member) {
+            Some(&old_score) if old_score == score => return false,
+            Some(&old_score) => {
+                self.scores.remove(&member, old_score);
+            }
+            None => {}
+        }
 
-        self.members.insert(member.clone(), score);
-        self.scores.insert(ZSetEntry { score, member });
+        let is_new = self.members.insert(member.clone(), score).is_none();
+        self.scores.insert(member, score);
         is_new
     }
 
     /// Remove a member
     pub fn remove(&mut self, member: &str) -> bool {
         if let Some(score) = self.members.remove(member) {
-            self.scores.remove(&ZSetEntry {
-                score,
-                member: member.to_string(),
-            });
+            self.scores.remove(member, score);
             true
         } else {
             false
@@ -120,50 +157,54 @@ impl ZSetData {
 
     /// Get rank of a member (0-indexed)
     pub fn rank(&self, member: &str) -> Option<usize> {
-        let score = self.members.get(member)?;
-        let entry = ZSetEntry {
-            score: *score,
-            member: member.to_string(),
-        };
-        Some(self.scores.iter().position(|e| e == &entry)?)
+        let score = *self.members.get(member)?;
+        Some(self.scores.count_before(|s, m| cmp_elements(s, m, score, member) == Ordering::Less))
     }
 
     /// Get reverse rank of a member
     pub fn revrank(&self, member: &str) -> Option<usize> {
         let rank = self.rank(member)?;
-        Some(self.scores.len() - 1 - rank)
+        Some(self.len() - 1 - rank)
     }
 
-    /// Get members in range by rank
-    pub fn range(&self, start: i64, stop: i64) -> Vec<(String, f64)> {
-        let len = self.scores.len() as i64;
-        let start = if start < 0 { (len + start).max(0) } else { start.min(len) } as usize;
-        let stop = if stop < 0 { (len + stop).max(0) } else { stop.min(len - 1) } as usize;
-
-        if start > stop {
-            return vec![];
+    /// Ranks selected by ZRANGE-style start/stop indexes (negative ones
+    /// count from the end)
+    pub fn ranks(&self, start: i64, stop: i64) -> Range<usize> {
+        let len = self.len() as i64;
+        let start = if start < 0 { (len + start).max(0) } else { start };
+        let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+        if start > stop || start >= len {
+            return 0..0;
         }
+        start as usize..stop as usize + 1
+    }
+
+    /// Ranks of the members with scores between min and max
+    pub fn score_ranks(&self, min: f64, max: f64) -> Range<usize> {
+        let start = self.scores.count_before(|s, _| s < min);
+        let end = self.scores.count_before(|s, _| s <= max);
+        start..end.max(start)
+    }
 
+    /// Get members in range by rank
+    pub fn range(&self, start: i64, stop: i64) -> Vec<(String, f64)> {
         self.scores
-            .iter()
-            .skip(start)
-            .take(stop - start + 1)
-            .map(|e| (e.member.clone(), e.score))
+            .range(self.ranks(start, stop))
+            .map(|(member, score)| (member.to_string(), score))
             .collect()
     }
 
     /// Get members in range by score
     pub fn range_by_score(&self, min: f64, max: f64) -> Vec<(String, f64)> {
         self.scores
-            .iter()
-            .filter(|e| e.score >= min && e.score <= max)
-            .map(|e| (e.member.clone(), e.score))
+            .range(self.score_ranks(min, max))
+            .map(|(member, score)| (member.to_string(), score))
             .collect()
     }
 
     /// Count members in score range
     pub fn count(&self, min: f64, max: f64) -> usize {
-        self.scores.iter().filter(|e| e.score >= min && e.score <= max).count()
+        self.score_ranks(min, max).len()
     }
 
     /// Get length
@@ -177,18 +218,57 @@ impl ZSetData {
     }
 }
 
-/// Stream data structure
+/// Stream data structure. Entries are kept in ascending ID order.
 #[derive(Debug, Clone, Default)]
 pub struct StreamData {
     pub entries: Vec<StreamEntry>,
     pub groups: HashMap<String, ConsumerGroup>,
-    pub last_id: u64,
+    /// Highest ID the stream has produced, even if that entry was deleted
+    pub last_id: StreamId,
+}
+
+/// Stream entry ID: a millisecond timestamp and a sequence number within
+/// that millisecond, ordered by both
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId { ms: u64::MAX, seq: u64::MAX };
+
+    /// Parse `ms-seq`, or a bare `ms` with sequence `default_seq`
+    pub fn parse(id: &str, default_seq: u64) -> Option<StreamId> {
+        let (ms, seq) = match id.split_once('-') {
+            Some((ms, seq)) => (ms, seq.parse().ok()?),
+            None => (id, default_seq),
+        };
+        Some(StreamId { ms: ms.parse().ok()?, seq })
+    }
+
+    /// Parse a range bound: `-` and `+` are the ends of the stream, and a
+    /// bare `ms` covers every sequence in that millisecond
+    pub fn parse_bound(id: &str, is_end: bool) -> Option<StreamId> {
+        match id {
+            "-" => Some(StreamId::MIN),
+            "+" => Some(StreamId::MAX),
+            _ => StreamId::parse(id, if is_end { u64::MAX } else { 0 }),
+        }
+    }
+}
+
+impl std::fmt::Display for StreamId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
 }
 
 /// Stream entry
 #[derive(Debug, Clone)]
 pub struct StreamEntry {
-    pub id: String,
+    pub id: StreamId,
     pub fields: HashMap<String, String>,
     pub timestamp: u64,
 }
@@ -346,25 +426,56 @@ impl StreamData {
         StreamData {
             entries: Vec::new(),
             groups: HashMap::new(),
-            last_id: 0,
+            last_id: StreamId::MIN,
         }
     }
 
-    /// Generate next stream ID
-    pub fn next_id(&mut self) -> String {
-        self.last_id += 1;
-        let timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_millis();
-        format!("{}-{}", timestamp, self.last_id)
+    /// ID for a new entry from an XADD argument. `*` (or None) takes the
+    /// current time, `ms-*` the next sequence within `ms`, and any ID must be
+    /// greater than every ID the stream has produced.
+    pub fn next_id(&self, spec: Option<&str>) -> Result<StreamId, String> {
+        let last = self.last_id;
+        let id = match spec {
+            None | Some("*") => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                // A clock that stands still or goes back keeps counting in the last millisecond
+                match last.seq.checked_add(1) {
+                    _ if now > last.ms => StreamId { ms: now, seq: 0 },
+                    Some(seq) => StreamId { ms: last.ms, seq },
+                    None => StreamId {
+                        ms: last.ms.checked_add(1).ok_or_else(|| STREAM_EXHAUSTED.to_string())?,
+                        seq: 0,
+                    },
+                }
+            }
+            Some(spec) => match spec.strip_suffix("-*") {
+                Some(ms) => {
+                    let ms = ms.parse::<u64>().map_err(|_| INVALID_STREAM_ID.to_string())?;
+                    match last.seq.checked_add(1) {
+                        _ if ms > last.ms => StreamId { ms, seq: 0 },
+                        Some(seq) if ms == last.ms => StreamId { ms, seq },
+                        _ => return Err(STREAM_ID_TOO_SMALL.to_string()),
+                    }
+                }
+                None => StreamId::parse(spec, 0).ok_or_else(|| INVALID_STREAM_ID.to_string())?,
+            },
+        };
+        if id == StreamId::MIN {
+            return Err("The ID specified in XADD must be greater than 0-0".to_string());
+        }
+        if id <= last {
+            return Err(STREAM_ID_TOO_SMALL.to_string());
+        }
+        Ok(id)
     }
 
-    /// Add an entry to the stream
-    pub fn add(&mut self, id: Option<String>, fields: HashMap<String, String>) -> String {
-        let id = id.unwrap_or_else(|| self.next_id());
+    /// Append an entry whose ID came from `next_id`
+    pub fn add(&mut self, id: StreamId, fields: HashMap<String, String>) {
         let entry = StreamEntry {
-            id: id.clone(),
+            id,
             fields,
             timestamp: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -372,20 +483,369 @@ impl StreamData {
                 .as_millis() as u64,
         };
         self.entries.push(entry);
+        self.last_id = id;
+    }
+
+    /// Entries with IDs from `start` to `end` inclusive
+    pub fn range(&self, start: StreamId, end: StreamId) -> &[StreamEntry] {
+        let from = self.entries.partition_point(|e| e.id < start);
+        let to = self.entries.partition_point(|e| e.id <= end).max(from);
+        &self.entries[from..to]
+    }
+}
+
+/// Error for a malformed stream ID argument
+pub const INVALID_STREAM_ID: &str = "Invalid stream ID specified as stream command argument";
+
+/// Error for an auto-generated XADD ID once the stream has used `MAX`
+const STREAM_EXHAUSTED: &str = "The stream has exhausted the last possible ID, unable to add more items";
+
+/// Error for an XADD ID that does not follow the stream's last ID
+const STREAM_ID_TOO_SMALL: &str = "The ID specified in XADD is equal or smaller than the target stream top item";
+
+/// Priority queue. Messages are delivered lowest priority first and in
+/// push order within a priority; a delivered message stays hidden until it
+/// is acknowledged or its visibility deadline passes.
+#[derive(Debug, Clone, Default)]
+pub struct QueueData {
+    /// Messages by id
+    pub messages: HashMap<u64, QueueMessage>,
+    /// Messages waiting for delivery, as (priority, id)
+    pub ready: BTreeSet<(i64, u64)>,
+    /// Delivered messages waiting for an ack, as (deadline, id)
+    pub pending: BTreeSet<(u64, u64)>,
+    /// Id of the last pushed message
+    pub last_id: u64,
+}
+
+/// Message in a priority queue
+#[derive(Debug, Clone)]
+pub struct QueueMessage {
+    pub priority: i64,
+    pub payload: String,
+    /// Unix time in milliseconds at which an unacknowledged delivery
+    /// becomes visible again (None while waiting for delivery)
+    pub deadline: Option<u64>,
+}
+
+impl QueueData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message, returning its id
+    pub fn push(&mut self, priority: i64, payload: String) -> u64 {
+        self.last_id += 1;
+        let id = self.last_id;
+        self.insert(id, QueueMessage { priority, payload, deadline: None });
         id
     }
 
-    /// Get entries in range
-    pub fn range(&self, start: &str, end: &str, count: Option<usize>) -> Vec<&StreamEntry> {
-        let mut result: Vec<_> = self
-            .entries
+    /// Add a message under a known id (when loading)
+    pub fn insert(&mut self, id: u64, message: QueueMessage) {
+        match message.deadline {
+            Some(deadline) => self.pending.insert((deadline, id)),
+            None => self.ready.insert((message.priority, id)),
+        };
+        self.messages.insert(id, message);
+    }
+
+    /// Make deliveries whose deadline is at or before `now_ms` visible again
+    pub fn reclaim(&mut self, now_ms: u64) {
+        while let Some(&(deadline, id)) = self.pending.first() {
+            if deadline > now_ms {
+                break;
+            }
+            self.pending.pop_first();
+            if let Some(message) = self.messages.get_mut(&id) {
+                message.deadline = None;
+                self.ready.insert((message.priority, id));
+            }
+        }
+    }
+
+    /// Hide a message until `deadline`, whether it was waiting or already
+    /// delivered. Returns false if there is no such message.
+    pub fn lease(&mut self, id: u64, deadline: u64) -> bool {
+        let message = match self.messages.get_mut(&id) {
+            Some(message) => message,
+            None => return false,
+        };
+        match message.deadline {
+            Some(old) => self.pending.remove(&(old, id)),
+            None => self.ready.remove(&(message.priority, id)),
+        };
+        message.deadline = Some(deadline);
+        self.pending.insert((deadline, id));
+        true
+    }
+
+    /// Deliver up to `count` messages, hiding them until `deadline`
+    pub fn pop(&mut self, count: usize, deadline: u64, now_ms: u64) -> Vec<(u64, String)> {
+        self.reclaim(now_ms);
+        let ids: Vec<u64> = self.ready.iter().take(count).map(|&(_, id)| id).collect();
+        ids.into_iter()
+            .map(|id| {
+                self.lease(id, deadline);
+                (id, self.messages[&id].payload.clone())
+            })
+            .collect()
+    }
+
+    /// Remove a message. Returns false if there is no such message.
+    pub fn ack(&mut self, id: u64) -> bool {
+        match self.messages.remove(&id) {
+            Some(message) => {
+                match message.deadline {
+                    Some(deadline) => self.pending.remove(&(deadline, id)),
+                    None => self.ready.remove(&(message.priority, id)),
+                };
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of messages, delivered or not
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Time series: samples in ascending timestamp order, appended only at the
+/// end, with a retention window, labels for TS.MRANGE filters and rules
+/// that downsample it into other series
+#[derive(Debug, Clone, Default)]
+pub struct TimeSeriesData {
+    /// (Unix time in milliseconds, value)
+    pub samples: VecDeque<(u64, f64)>,
+    /// Samples older than this many milliseconds before the newest one are
+    /// dropped (0 keeps every sample)
+    pub retention_ms: u64,
+    pub labels: Vec<(String, String)>,
+    pub rules: Vec<CompactionRule>,
+}
+
+/// Downsampling of a series into `dest`, one sample per bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactionRule {
+    pub dest: String,
+    pub aggregation: Aggregation,
+    pub bucket_ms: u64,
+}
+
+/// How the samples in a bucket are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl Aggregation {
+    pub const ALL: [Aggregation; 5] =
+        [Aggregation::Avg, Aggregation::Min, Aggregation::Max, Aggregation::Sum, Aggregation::Count];
+
+    /// Name used by commands, case-insensitively
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Sum => "sum",
+            Aggregation::Count => "count",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Aggregation> {
+        Aggregation::ALL.into_iter().find(|a| a.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Combine a non-empty run of values
+    fn combine(&self, values: impl Iterator<Item = f64>) -> f64 {
+        let (mut count, mut sum, mut min, mut max) = (0u64, 0.0, f64::INFINITY, f64::NEG_INFINITY);
+        for value in values {
+            count += 1;
+            sum += value;
+            min = min.min(value);
+            max = max.max(value);
+        }
+        match self {
+            Aggregation::Avg => sum / count as f64,
+            Aggregation::Min => min,
+            Aggregation::Max => max,
+            Aggregation::Sum => sum,
+            Aggregation::Count => count as f64,
+        }
+    }
+}
+
+/// Combine samples into buckets of `bucket_ms` aligned to multiples of it,
+/// as (bucket start, value). Samples must be in timestamp order.
+pub fn aggregate<'a>(
+    samples: impl Iterator<Item = &'a (u64, f64)>,
+    aggregation: Aggregation,
+    bucket_ms: u64,
+) -> Vec<(u64, f64)> {
+    let mut buckets: Vec<(u64, Vec<f64>)> = Vec::new();
+    for &(ts, value) in samples {
+        let start = ts - ts % bucket_ms;
+        match buckets.last_mut() {
+            Some((last, values)) if *last == start => values.push(value),
+            _ => buckets.push((start, vec![value])),
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(start, values)| (start, aggregation.combine(values.into_iter())))
+        .collect()
+}
+
+impl TimeSeriesData {
+    pub fn new(retention_ms: u64, labels: Vec<(String, String)>) -> Self {
+        TimeSeriesData {
+            retention_ms,
+            labels,
+            ..Default::default()
+        }
+    }
+
+    /// Append a sample newer than the last one, then drop samples that
+    /// fell out of the retention window
+    pub fn add(&mut self, timestamp: u64, value: f64) -> Result<(), String> {
+        if self.samples.back().is_some_and(|&(last, _)| timestamp <= last) {
+            return Err("TSDB: timestamp must be greater than the last sample's".to_string());
+        }
+        self.samples.push_back((timestamp, value));
+        if self.retention_ms > 0 {
+            let cutoff = timestamp.saturating_sub(self.retention_ms);
+            while self.samples.front().is_some_and(|&(ts, _)| ts < cutoff) {
+                self.samples.pop_front();
+            }
+        }
+        Ok(())
+    }
+
+    /// Newest sample
+    pub fn last(&self) -> Option<(u64, f64)> {
+        self.samples.back().copied()
+    }
+
+    /// Samples with timestamps from `from` to `to` inclusive
+    pub fn range(&self, from: u64, to: u64) -> std::collections::vec_deque::Iter<'_, (u64, f64)> {
+        let start = self.samples.partition_point(|&(ts, _)| ts < from);
+        let end = self.samples.partition_point(|&(ts, _)| ts <= to).max(start);
+        self.samples.range(start..end)
+    }
+
+    /// Value of a label
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels.iter().find(|(label, _)| label == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Vectors of one dimension keyed by id, each with metadata fields.
+/// Searches compare the query with every vector (a flat index), so results
+/// are exact.
+#[derive(Debug, Clone, Default)]
+pub struct VectorData {
+    /// Dimension of every vector, set by the first one added
+    pub dim: usize,
+    pub vectors: HashMap<String, VectorEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorEntry {
+    pub values: Vec<f32>,
+    pub meta: Vec<(String, String)>,
+}
+
+/// Distance used to rank vectors, smaller is nearer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VectorMetric {
+    /// 1 - cosine similarity, from 0 to 2
+    #[default]
+    Cosine,
+    /// Euclidean distance
+    L2,
+}
+
+impl VectorMetric {
+    pub fn parse(name: &str) -> Option<VectorMetric> {
+        match name.to_ascii_uppercase().as_str() {
+            "COSINE" => Some(VectorMetric::Cosine),
+            "L2" => Some(VectorMetric::L2),
+            _ => None,
+        }
+    }
+
+    pub fn distance(&self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            VectorMetric::Cosine => {
+                let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+                for (x, y) in a.iter().zip(b) {
+                    dot += x * y;
+                    norm_a += x * x;
+                    norm_b += y * y;
+                }
+                if norm_a == 0.0 || norm_b == 0.0 {
+                    return 1.0;
+                }
+                1.0 - dot / (norm_a.sqrt() * norm_b.sqrt())
+            }
+            VectorMetric::L2 => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt(),
+        }
+    }
+}
+
+impl VectorData {
+    /// Add or replace a vector. Returns true if the id is new.
+    pub fn upsert(&mut self, id: String, values: Vec<f32>, meta: Vec<(String, String)>) -> Result<bool, String> {
+        if self.vectors.is_empty() {
+            self.dim = values.len();
+        } else if values.len() != self.dim {
+            return Err(format!("vector dimension {} does not match the index dimension {}", values.len(), self.dim));
+        }
+        Ok(self.vectors.insert(id, VectorEntry { values, meta }).is_none())
+    }
+
+    /// The `k` vectors nearest to `query` accepted by `accept`, nearest
+    /// first, ties broken by id
+    pub fn nearest(
+        &self,
+        query: &[f32],
+        k: usize,
+        metric: VectorMetric,
+        accept: impl Fn(&VectorEntry) -> bool,
+    ) -> Vec<(&str, f32, &VectorEntry)> {
+        let mut hits: Vec<(&str, f32, &VectorEntry)> = self
+            .vectors
             .iter()
-            .filter(|e| e.id.as_str() >= start && e.id.as_str() <= end)
+            .filter(|(_, entry)| accept(entry))
+            .map(|(id, entry)| (id.as_str(), metric.distance(query, &entry.values), entry))
             .collect();
-        
-        if let Some(n) = count {
-            result.truncate(n);
+        let order = |a: &(&str, f32, &VectorEntry), b: &(&str, f32, &VectorEntry)| {
+            a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0))
+        };
+        if k < hits.len() {
+            hits.select_nth_unstable_by(k, order);
+            hits.truncate(k);
         }
-        result
+        hits.sort_unstable_by(order);
+        hits
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
     }
 }
@@ -0,0 +1,443 @@
+//! Embedded Mode
+//!
+//! HexagonDB as an in-process store, without a listener: [`HexagonDb`] owns
+//! the keyspace, the AOF and the background tasks a server would run
+//! (hash field expiry, RDB save points, AOF compaction and scheduled jobs).
+//! Commands go through the same interpreter as network clients, so they
+//! are logged to the AOF and behave exactly as over RESP.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), hexagondb::embedded::EmbeddedError> {
+//! use hexagondb::config::Config;
+//! use hexagondb::embedded::HexagonDb;
+//!
+//! let db = HexagonDb::open(Config::default()).await?;
+//! db.set("greeting", "hello").await?;
+//! assert_eq!(db.get("greeting").await?.as_deref(), Some("hello"));
+//! db.execute(&["LPUSH", "queue", "job-1"]).await;
+//! db.close().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The data directory is process-wide, so one process should open at most
+//! one persistent instance; [`HexagonDb::in_memory`] instances are free.
+
+use crate::commands::{ExecutionResult, Interpreter};
+use crate::config::Config;
+use crate::db::pubsub::PubSub;
+use crate::db::DB;
+use crate::network::resp::RespValue;
+use crate::persistence::aof::{self, Aof};
+use crate::persistence::recovery::{self, Source};
+use crate::persistence::{bgsave, paths, snapshot};
+use crate::server_info::ServerInfo;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tracing::error;
+
+/// Error of a typed [`HexagonDb`] call
+#[derive(Debug)]
+pub enum EmbeddedError {
+    /// Opening, saving or syncing the data files failed
+    Io(io::Error),
+    /// The command replied with an error, e.g. WRONGTYPE
+    Command(String),
+    /// The reply had a shape the typed method does not expect
+    UnexpectedReply(RespValue),
+}
+
+impl fmt::Display for EmbeddedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddedError::Io(err) => write!(f, "I/O error: {}", err),
+            EmbeddedError::Command(msg) => write!(f, "{}", msg),
+            EmbeddedError::UnexpectedReply(reply) => write!(f, "Unexpected reply: {:?}", reply),
+        }
+    }
+}
+
+impl std::error::Error for EmbeddedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmbeddedError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EmbeddedError {
+    fn from(err: io::Error) -> Self {
+        EmbeddedError::Io(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, EmbeddedError>;
+
+/// An embedded HexagonDB instance. Background tasks stop when it is closed
+/// or dropped.
+pub struct HexagonDb {
+    interp: Mutex<Interpreter>,
+    db: Arc<RwLock<DB>>,
+    aof: Arc<RwLock<Aof>>,
+    server_info: Arc<ServerInfo>,
+    config: Arc<RwLock<Config>>,
+    pubsub: Arc<PubSub>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl HexagonDb {
+    /// Open an instance with the persistence settings of `config`, loading
+    /// the AOF or RDB file of its data directory before returning
+    pub async fn open(config: Config) -> Result<Self> {
+        let persistence = config.persistence.clone();
+        if let Some(dir) = &persistence.data_dir {
+            std::fs::create_dir_all(dir)?;
+            paths::set_data_dir(dir);
+        }
+        snapshot::set_compression(persistence.compression_threshold());
+        aof::set_rdb_preamble(persistence.aof_use_rdb_preamble);
+        aof::set_segment_size(persistence.aof_segment_size);
+
+        let source = Source::detect(persistence.aof_enabled, &paths::aof_path(), &paths::rdb_path());
+        let aof = if persistence.aof_enabled { Aof::new(paths::aof_path())? } else { Aof::disabled() };
+        let instance = Self::start(config, aof);
+
+        let progress = instance.server_info.loading();
+        progress.begin(0);
+        let recovered = recovery::recover(&source, &instance.db, &instance.aof, progress).await;
+        progress.finish();
+        recovered?;
+        Ok(instance)
+    }
+
+    /// Open an instance that never touches the disk
+    pub async fn in_memory() -> Result<Self> {
+        let mut config = Config::default();
+        config.persistence.aof_enabled = false;
+        config.persistence.rdb_enabled = false;
+        Ok(Self::start(config, Aof::disabled()))
+    }
+
+    /// Build the shared state and spawn the background tasks
+    fn start(config: Config, aof: Aof) -> Self {
+        let mut db = DB::new();
+        db.recycle_bin.set_retention(config.recycle_bin.retention());
+        db.recycle_bin.set_limits(config.recycle_bin.max_entries, config.recycle_bin.max_memory);
+        let (expired_tx, expired_rx) = tokio::sync::mpsc::channel(crate::scheduler::EXPIRED_QUEUE_LEN);
+        db.on_expire(move |key, _| {
+            let _ = expired_tx.try_send(key.to_string());
+        });
+
+        let db = Arc::new(RwLock::new(db));
+        let aof = Arc::new(RwLock::new(aof));
+        let server_info = Arc::new(ServerInfo::new());
+        let config = Arc::new(RwLock::new(config));
+        let pubsub = Arc::new(PubSub::new());
+        let interpreter = |name: &str| {
+            Interpreter::new(
+                Arc::clone(&db),
+                Arc::clone(&aof),
+                Arc::clone(&server_info),
+                Arc::clone(&config),
+                Arc::clone(&pubsub),
+            )
+            .with_client_addr(name)
+        };
+
+        let tasks = vec![
+            tokio::spawn(crate::scheduler::run(
+                interpreter("scheduler"),
+                Arc::clone(&db),
+                Arc::clone(&config),
+                Arc::clone(server_info.loading()),
+                expired_rx,
+            )),
+            tokio::spawn(expire_hash_fields(Arc::clone(&db))),
+            tokio::spawn(save_points(Arc::clone(&db), Arc::clone(&config), Arc::clone(&server_info))),
+            tokio::spawn(compact_aof(Arc::clone(&db), Arc::clone(&aof), Arc::clone(&config), Arc::clone(&server_info))),
+        ];
+
+        HexagonDb {
+            interp: Mutex::new(interpreter("embedded")),
+            db,
+            aof,
+            server_info,
+            config,
+            pubsub,
+            tasks,
+        }
+    }
+
+    /// Run any command, e.g. `db.execute(&["HSET", "user:1", "name", "ada"])`.
+    /// Error replies are returned as [`RespValue::Error`]. Blocking pops
+    /// that find nothing reply nil instead of waiting, and SUBSCRIBE is
+    /// not available; use [`HexagonDb::pubsub`] to subscribe.
+    pub async fn execute(&self, args: &[&str]) -> RespValue {
+        let request = RespValue::Array(Some(
+            args.iter().map(|arg| RespValue::BulkString(Some(arg.to_string()))).collect(),
+        ));
+        match self.interp.lock().await.execute(request).await {
+            ExecutionResult::Response(reply) => reply,
+            ExecutionResult::Block(_) => RespValue::Array(None),
+            ExecutionResult::Subscribe(..) => {
+                RespValue::Error("ERR SUBSCRIBE is not supported in embedded mode".to_string())
+            }
+        }
+    }
+
+    /// The keyspace, for reads and writes that bypass the interpreter.
+    /// Such writes are not logged to the AOF.
+    pub fn db(&self) -> &Arc<RwLock<DB>> {
+        &self.db
+    }
+
+    /// The live configuration; changes apply as CONFIG SET's would
+    pub fn config(&self) -> &Arc<RwLock<Config>> {
+        &self.config
+    }
+
+    /// Channels and patterns shared with PUBLISH, for in-process subscribers
+    pub fn pubsub(&self) -> &Arc<PubSub> {
+        &self.pubsub
+    }
+
+    /// Write an RDB snapshot now
+    pub async fn save(&self) -> Result<()> {
+        self.call(&["SAVE"]).await.map(|_| ())
+    }
+
+    /// Stop the background tasks and sync the AOF to disk
+    pub async fn close(self) -> Result<()> {
+        for task in &self.tasks {
+            task.abort();
+        }
+        self.aof.write().await.fsync()?;
+        Ok(())
+    }
+
+    /// Run a command and fail on an error reply
+    async fn call(&self, args: &[&str]) -> Result<RespValue> {
+        match self.execute(args).await {
+            RespValue::Error(msg) => Err(EmbeddedError::Command(msg)),
+            reply => Ok(reply),
+        }
+    }
+
+    async fn call_int(&self, args: &[&str]) -> Result<i64> {
+        match self.call(args).await? {
+            RespValue::Integer(n) => Ok(n),
+            reply => Err(EmbeddedError::UnexpectedReply(reply)),
+        }
+    }
+
+    async fn call_bulk(&self, args: &[&str]) -> Result<Option<String>> {
+        match self.call(args).await? {
+            RespValue::BulkString(value) => Ok(value),
+            reply => Err(EmbeddedError::UnexpectedReply(reply)),
+        }
+    }
+
+    async fn call_list(&self, args: &[&str]) -> Result<Vec<String>> {
+        match self.call(args).await? {
+            RespValue::Array(items) => items
+                .unwrap_or_default()
+                .into_iter()
+                .map(|item| match item {
+                    RespValue::BulkString(Some(s)) | RespValue::SimpleString(s) => Ok(s),
+                    other => Err(EmbeddedError::UnexpectedReply(other)),
+                })
+                .collect(),
+            reply => Err(EmbeddedError::UnexpectedReply(reply)),
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<String>> {
+        self.call_bulk(&["GET", key]).await
+    }
+
+    pub async fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.call(&["SET", key, value]).await.map(|_| ())
+    }
+
+    /// Number of keys removed
+    pub async fn del(&self, keys: &[&str]) -> Result<i64> {
+        let args: Vec<&str> = std::iter::once("DEL").chain(keys.iter().copied()).collect();
+        self.call_int(&args).await
+    }
+
+    pub async fn exists(&self, key: &str) -> Result<bool> {
+        Ok(self.call_int(&["EXISTS", key]).await? > 0)
+    }
+
+    /// Set a TTL in seconds; false if the key does not exist
+    pub async fn expire(&self, key: &str, seconds: u64) -> Result<bool> {
+        Ok(self.call_int(&["EXPIRE", key, &seconds.to_string()]).await? == 1)
+    }
+
+    /// Remaining TTL in seconds: -1 without a TTL, -2 for a missing key
+    pub async fn ttl(&self, key: &str) -> Result<i64> {
+        self.call_int(&["TTL", key]).await
+    }
+
+    pub async fn incr(&self, key: &str) -> Result<i64> {
+        self.call_int(&["INCR", key]).await
+    }
+
+    /// True if the field is new
+    pub async fn hset(&self, key: &str, field: &str, value: &str) -> Result<bool> {
+        Ok(self.call_int(&["HSET", key, field, value]).await? == 1)
+    }
+
+    pub async fn hget(&self, key: &str, field: &str) -> Result<Option<String>> {
+        self.call_bulk(&["HGET", key, field]).await
+    }
+
+    /// Field/value pairs of a hash
+    pub async fn hgetall(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let flat = self.call_list(&["HGETALL", key]).await?;
+        let mut items = flat.into_iter();
+        let mut pairs = Vec::new();
+        while let (Some(field), Some(value)) = (items.next(), items.next()) {
+            pairs.push((field, value));
+        }
+        Ok(pairs)
+    }
+
+    /// Length of the list after the push
+    pub async fn lpush(&self, key: &str, value: &str) -> Result<i64> {
+        self.call_int(&["LPUSH", key, value]).await
+    }
+
+    /// Length of the list after the push
+    pub async fn rpush(&self, key: &str, value: &str) -> Result<i64> {
+        self.call_int(&["RPUSH", key, value]).await
+    }
+
+    pub async fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<String>> {
+        self.call_list(&["LRANGE", key, &start.to_string(), &stop.to_string()]).await
+    }
+
+    /// True if the member is new
+    pub async fn sadd(&self, key: &str, member: &str) -> Result<bool> {
+        Ok(self.call_int(&["SADD", key, member]).await? == 1)
+    }
+
+    pub async fn smembers(&self, key: &str) -> Result<Vec<String>> {
+        self.call_list(&["SMEMBERS", key]).await
+    }
+
+    pub async fn keys(&self, pattern: &str) -> Result<Vec<String>> {
+        self.call_list(&["KEYS", pattern]).await
+    }
+
+    pub async fn dbsize(&self) -> Result<i64> {
+        self.call_int(&["DBSIZE"]).await
+    }
+}
+
+impl Drop for HexagonDb {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Active expiry of hash fields with a TTL
+async fn expire_hash_fields(db: Arc<RwLock<DB>>) {
+    loop {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        db.write().await.active_expire_hash_fields();
+    }
+}
+
+/// Snapshot whenever a configured save point is reached
+async fn save_points(db: Arc<RwLock<DB>>, config: Arc<RwLock<Config>>, server_info: Arc<ServerInfo>) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let save_points = {
+            let cfg = config.read().await;
+            if !cfg.persistence.rdb_enabled || server_info.loading().is_loading() {
+                continue;
+            }
+            cfg.persistence.save_points()
+        };
+        // Back off after a failed snapshot
+        if !server_info.last_bgsave_ok()
+            && server_info.last_save_age_secs() < bgsave::RETRY_DELAY.as_secs()
+        {
+            continue;
+        }
+        let changes = db.read().await.get_changes() as u64;
+        let elapsed = Duration::from_secs(server_info.last_save_age_secs());
+        if bgsave::due(&save_points, changes, elapsed).is_some() {
+            bgsave::start(&db, &server_info);
+        }
+    }
+}
+
+/// Fold sealed AOF segments into a new base once there are too many
+async fn compact_aof(
+    db: Arc<RwLock<DB>>,
+    aof: Arc<RwLock<Aof>>,
+    config: Arc<RwLock<Config>>,
+    server_info: Arc<ServerInfo>,
+) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let (max_segments, archive_dir) = {
+            let cfg = config.read().await;
+            (cfg.persistence.aof_max_segments, cfg.persistence.aof_archive_dir.clone())
+        };
+        if max_segments == 0
+            || server_info.loading().is_loading()
+            || aof.read().await.sealed_segments() <= max_segments
+        {
+            continue;
+        }
+        let result = {
+            let db_guard = db.read().await;
+            aof.write().await.compact(&db_guard, archive_dir.as_deref().map(std::path::Path::new))
+        };
+        if let Err(e) = result {
+            error!("AOF compaction failed: {}", e);
+            tokio::time::sleep(bgsave::RETRY_DELAY).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_typed_commands() {
+        let db = HexagonDb::in_memory().await.unwrap();
+        db.set("name", "ada").await.unwrap();
+        assert_eq!(db.get("name").await.unwrap().as_deref(), Some("ada"));
+        assert_eq!(db.get("missing").await.unwrap(), None);
+        assert_eq!(db.incr("n").await.unwrap(), 1);
+        assert!(db.hset("user", "name", "ada").await.unwrap());
+        assert_eq!(db.hgetall("user").await.unwrap(), vec![("name".to_string(), "ada".to_string())]);
+        assert_eq!(db.rpush("list", "a").await.unwrap(), 1);
+        assert_eq!(db.rpush("list", "b").await.unwrap(), 2);
+        assert_eq!(db.lrange("list", 0, -1).await.unwrap(), vec!["a", "b"]);
+        assert!(db.expire("name", 100).await.unwrap());
+        assert!(db.ttl("name").await.unwrap() > 0);
+        assert_eq!(db.dbsize().await.unwrap(), 4);
+        assert_eq!(db.del(&["name", "missing"]).await.unwrap(), 1);
+
+        assert!(matches!(db.incr("list").await, Err(EmbeddedError::Command(msg)) if msg.starts_with("WRONGTYPE")));
+        assert_eq!(db.execute(&["BLMPOP", "0", "1", "empty", "LEFT"]).await, RespValue::Array(None));
+
+        let mut messages = db.pubsub().subscribe("news").await;
+        assert_eq!(db.execute(&["PUBLISH", "news", "hi"]).await, RespValue::Integer(1));
+        assert_eq!(messages.recv().await.unwrap(), "hi");
+        db.close().await.unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+//! Failpoints for resilience testing.
+//!
+//! Named hooks in the AOF, replication and snapshot code paths that can be
+//! told to sleep, drop work or panic via `DEBUG FAILPOINT`. They are only
+//! compiled in with the `failpoints` cargo feature; without it every hook is
+//! an inlined no-op.
+//!
+//! Known failpoints:
+//! - `aof-append`: before an AOF record is written
+//! - `repl-broadcast`: before a write is sent to replicas
+//! - `snapshot-save`: before an RDB snapshot is written
+
+/// Failpoint names understood by the server
+pub const FAILPOINTS: &[&str] = &["aof-append", "repl-broadcast", "snapshot-save"];
+
+/// What a failpoint does when it is hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailAction {
+    /// Sleep for the given number of milliseconds, then continue
+    Delay(u64),
+    /// Skip the guarded operation
+    Drop,
+    /// Panic the current task
+    Panic,
+}
+
+impl FailAction {
+    /// Parse `delay <ms>`, `drop` or `panic`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        match args.first().map(|a| a.to_uppercase()).as_deref() {
+            Some("DELAY") if args.len() == 2 => args[1]
+                .parse::<u64>()
+                .map(FailAction::Delay)
+                .map_err(|_| "value is not an integer or out of range".to_string()),
+            Some("DROP") if args.len() == 1 => Ok(FailAction::Drop),
+            Some("PANIC") if args.len() == 1 => Ok(FailAction::Panic),
+            _ => Err("syntax error".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for FailAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailAction::Delay(ms) => write!(f, "delay {}", ms),
+            FailAction::Drop => write!(f, "drop"),
+            FailAction::Panic => write!(f, "panic"),
+        }
+    }
+}
+
+#[cfg(feature = "failpoints")]
+mod registry {
+    use super::FailAction;
+    use parking_lot::RwLock;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static ANY_ACTIVE: AtomicBool = AtomicBool::new(false);
+    static ACTIVE: RwLock<BTreeMap<String, FailAction>> = RwLock::new(BTreeMap::new());
+
+    pub fn set(name: &str, action: Option<FailAction>) {
+        let mut active = ACTIVE.write();
+        match action {
+            Some(action) => {
+                active.insert(name.to_string(), action);
+            }
+            None => {
+                active.remove(name);
+            }
+        }
+        ANY_ACTIVE.store(!active.is_empty(), Ordering::Release);
+    }
+
+    pub fn list() -> Vec<(String, FailAction)> {
+        ACTIVE.read().iter().map(|(k, v)| (k.clone(), *v)).collect()
+    }
+
+    pub fn get(name: &str) -> Option<FailAction> {
+        if !ANY_ACTIVE.load(Ordering::Acquire) {
+            return None;
+        }
+        ACTIVE.read().get(name).copied()
+    }
+}
+
+/// Whether failpoints were compiled in
+pub const fn enabled() -> bool {
+    cfg!(feature = "failpoints")
+}
+
+/// Arm (`Some`) or disarm (`None`) a failpoint
+#[cfg(feature = "failpoints")]
+pub fn set(name: &str, action: Option<FailAction>) -> Result<(), String> {
+    if !FAILPOINTS.contains(&name) {
+        return Err(format!("ERR unknown failpoint '{}'", name));
+    }
+    registry::set(name, action);
+    Ok(())
+}
+
+/// Arm (`Some`) or disarm (`None`) a failpoint
+#[cfg(not(feature = "failpoints"))]
+pub fn set(_name: &str, _action: Option<FailAction>) -> Result<(), String> {
+    Err("ERR failpoints are not compiled in (build with --features failpoints)".to_string())
+}
+
+/// Armed failpoints and their actions
+#[cfg(feature = "failpoints")]
+pub fn list() -> Vec<(String, FailAction)> {
+    registry::list()
+}
+
+/// Armed failpoints and their actions
+#[cfg(not(feature = "failpoints"))]
+pub fn list() -> Vec<(String, FailAction)> {
+    Vec::new()
+}
+
+/// Evaluate a failpoint. Delays and panics happen here; returns true when
+/// the caller should drop the guarded operation.
+#[cfg(feature = "failpoints")]
+pub fn hit(name: &str) -> bool {
+    match registry::get(name) {
+        Some(FailAction::Delay(ms)) => {
+            tracing::warn!("failpoint {}: delaying {}ms", name, ms);
+            std::thread::sleep(std::time::Duration::from_millis(ms));
+            false
+        }
+        Some(FailAction::Drop) => {
+            tracing::warn!("failpoint {}: dropping operation", name);
+            true
+        }
+        Some(FailAction::Panic) => panic!("failpoint {} triggered", name),
+        None => false,
+    }
+}
+
+/// Evaluate a failpoint (compiled out)
+#[cfg(not(feature = "failpoints"))]
+#[inline(always)]
+pub fn hit(_name: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_action() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(FailAction::parse(&args(&["delay", "10"])), Ok(FailAction::Delay(10)));
+        assert_eq!(FailAction::parse(&args(&["DROP"])), Ok(FailAction::Drop));
+        assert_eq!(FailAction::parse(&args(&["panic"])), Ok(FailAction::Panic));
+        assert!(FailAction::parse(&args(&["delay"])).is_err());
+        assert!(FailAction::parse(&args(&["explode"])).is_err());
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_arm_and_disarm() {
+        set("repl-broadcast", Some(FailAction::Drop)).unwrap();
+        assert!(hit("repl-broadcast"));
+        set("repl-broadcast", None).unwrap();
+        assert!(!hit("repl-broadcast"));
+        assert!(set("nope", Some(FailAction::Drop)).is_err());
+    }
+
+    #[cfg(not(feature = "failpoints"))]
+    #[test]
+    fn test_compiled_out() {
+        assert!(!enabled());
+        assert!(set("aof-append", Some(FailAction::Drop)).is_err());
+        assert!(!hit("aof-append"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Two-step flush confirmation.
+//!
+//! A single mistyped FLUSHALL wipes the dataset and, once propagated, every
+//! replica and the AOF with it. With `server.flush_confirmation` enabled,
+//! plain FLUSHDB/FLUSHALL are refused: `FLUSHALL SCHEDULE` hands out a
+//! token, and the flush only runs on `FLUSHALL CONFIRM <token>` before the
+//! token expires. Tokens are shared by all connections, so a second
+//! operator can confirm a flush scheduled by the first.
+
+use parking_lot::Mutex;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// A scheduled flush waiting for confirmation
+#[derive(Debug, Clone)]
+struct PendingFlush {
+    token: String,
+    expires_at: Instant,
+}
+
+/// Pending flush confirmation shared by all connections
+#[derive(Debug, Default)]
+pub struct FlushConfirmations {
+    pending: Mutex<Option<PendingFlush>>,
+}
+
+impl FlushConfirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedule a flush and return its token. Replaces any earlier one.
+    pub fn schedule(&self, timeout: Duration) -> String {
+        let token = format!("{:016x}", rand::thread_rng().gen::<u64>());
+        *self.pending.lock() = Some(PendingFlush {
+            token: token.clone(),
+            expires_at: Instant::now() + timeout,
+        });
+        token
+    }
+
+    /// Consume the pending flush if `token` matches and has not expired
+    pub fn confirm(&self, token: &str) -> bool {
+        let mut pending = self.pending.lock();
+        match pending.as_ref() {
+            Some(p) if p.expires_at <= Instant::now() => {
+                *pending = None;
+                false
+            }
+            Some(p) if p.token == token => {
+                *pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flush_confirmations() {
+        let flushes = FlushConfirmations::new();
+        assert!(!flushes.confirm("anything"));
+
+        let token = flushes.schedule(Duration::from_secs(60));
+        assert!(!flushes.confirm("wrong"));
+        assert!(flushes.confirm(&token));
+        // Tokens are single use
+        assert!(!flushes.confirm(&token));
+
+        let first = flushes.schedule(Duration::from_secs(60));
+        let second = flushes.schedule(Duration::from_secs(60));
+        assert!(!flushes.confirm(&first));
+        assert!(flushes.confirm(&second));
+
+        let expired = flushes.schedule(Duration::ZERO);
+        assert!(!flushes.confirm(&expired));
+    }
+}
@@ -0,0 +1,275 @@
+//! Server-side functions (FUNCTION, FCALL).
+//!
+//! A function is Rust code implementing `HexagonFunction`, registered with
+//! the process-wide `FunctionRegistry` either by code compiled into the
+//! server or by a plugin library listed under `[functions] plugins`. A
+//! plugin exports `hexagondb_register_functions`, which receives the
+//! registry; since trait objects cross the library boundary, a plugin has
+//! to be built with the same compiler and hexagondb version as the server.
+//!
+//! `FCALL name numkeys key [key ...] arg [arg ...]` runs a function with
+//! the keyspace locked. A function may only touch the keys it is given:
+//! after a call that is not read-only, those keys are written to the AOF as
+//! their resulting values (or deleted), so replay does not need the
+//! function. Each function counts its calls, errors and run time, and may
+//! name ACL categories that `+@category` / `-@category` grant or revoke it
+//! by. Functions can also be loaded at runtime from WASM modules; see
+//! `wasm`.
+
+pub mod wasm;
+
+use crate::db::DB;
+use crate::network::resp::RespValue;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Symbol a plugin library exports to register its functions
+pub const PLUGIN_ENTRY_POINT: &str = "hexagondb_register_functions";
+
+/// Signature of `PLUGIN_ENTRY_POINT`
+pub type PluginInit = unsafe extern "C" fn(registry: *const FunctionRegistry);
+
+/// What a function is called with
+pub struct FunctionContext<'a> {
+    db: &'a mut DB,
+    keys: &'a [String],
+    args: &'a [String],
+}
+
+impl<'a> FunctionContext<'a> {
+    pub fn new(db: &'a mut DB, keys: &'a [String], args: &'a [String]) -> Self {
+        FunctionContext { db, keys, args }
+    }
+
+    /// The keyspace, locked for the duration of the call
+    pub fn db(&mut self) -> &mut DB {
+        self.db
+    }
+
+    /// Keys the function was given; the only ones it may touch
+    pub fn keys(&self) -> &[String] {
+        self.keys
+    }
+
+    /// Arguments after the keys
+    pub fn args(&self) -> &[String] {
+        self.args
+    }
+}
+
+/// A server-side function
+pub trait HexagonFunction: Send + Sync {
+    /// Name used by FCALL (case-insensitive)
+    fn name(&self) -> &str;
+
+    /// Whether the function never writes; only these run under FCALL_RO
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// ACL categories the function belongs to, e.g. `["read", "hash"]`
+    fn acl_categories(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Short description shown by FUNCTION LIST
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// Run the function; an `Err` is sent to the client as an error reply
+    fn call(&self, ctx: &mut FunctionContext<'_>) -> Result<RespValue, String>;
+}
+
+/// Counters of one function
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub total_us: u64,
+}
+
+struct Registered {
+    function: Arc<dyn HexagonFunction>,
+    calls: AtomicU64,
+    errors: AtomicU64,
+    total_us: AtomicU64,
+}
+
+/// Registered functions by upper-cased name
+#[derive(Default)]
+pub struct FunctionRegistry {
+    functions: RwLock<BTreeMap<String, Arc<Registered>>>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a function, replacing one with the same name. Returns whether
+    /// one was replaced.
+    pub fn register(&self, function: Arc<dyn HexagonFunction>) -> bool {
+        let name = function.name().to_uppercase();
+        let registered = Arc::new(Registered {
+            function,
+            calls: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            total_us: AtomicU64::new(0),
+        });
+        self.functions.write().insert(name, registered).is_some()
+    }
+
+    /// Remove a function, returning whether it existed
+    pub fn unregister(&self, name: &str) -> bool {
+        self.functions.write().remove(&name.to_uppercase()).is_some()
+    }
+
+    /// Look up a function by name
+    pub fn get(&self, name: &str) -> Option<Arc<dyn HexagonFunction>> {
+        self.functions
+            .read()
+            .get(&name.to_uppercase())
+            .map(|r| Arc::clone(&r.function))
+    }
+
+    /// Registered functions, by name
+    pub fn list(&self) -> Vec<Arc<dyn HexagonFunction>> {
+        self.functions.read().values().map(|r| Arc::clone(&r.function)).collect()
+    }
+
+    /// Counters of every function, by name
+    pub fn stats(&self) -> Vec<(String, FunctionStats)> {
+        self.functions
+            .read()
+            .iter()
+            .map(|(name, r)| {
+                let stats = FunctionStats {
+                    calls: r.calls.load(Ordering::Relaxed),
+                    errors: r.errors.load(Ordering::Relaxed),
+                    total_us: r.total_us.load(Ordering::Relaxed),
+                };
+                (name.clone(), stats)
+            })
+            .collect()
+    }
+
+    /// Count a call of `name` that took `elapsed`
+    pub fn record(&self, name: &str, elapsed: Duration, failed: bool) {
+        let name = name.to_uppercase();
+        if let Some(r) = self.functions.read().get(&name) {
+            r.calls.fetch_add(1, Ordering::Relaxed);
+            r.total_us.fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+            if failed {
+                r.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let labels = [("function", name)];
+        metrics::counter!(crate::observability::metrics::METRIC_FUNCTION_CALLS, &labels).increment(1);
+        if failed {
+            metrics::counter!(crate::observability::metrics::METRIC_FUNCTION_ERRORS, &labels).increment(1);
+        }
+        metrics::histogram!(crate::observability::metrics::METRIC_FUNCTION_DURATION, &labels)
+            .record(elapsed.as_secs_f64());
+    }
+}
+
+/// The process-wide registry used by FCALL
+pub fn registry() -> &'static FunctionRegistry {
+    static REGISTRY: OnceLock<FunctionRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(FunctionRegistry::new)
+}
+
+/// Load a plugin library and let it register its functions
+#[cfg(unix)]
+pub fn load_plugin(path: &std::path::Path) -> Result<(), String> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| e.to_string())?;
+    let symbol = CString::new(PLUGIN_ENTRY_POINT).map_err(|e| e.to_string())?;
+    let dl_error = || {
+        // SAFETY: dlerror returns null or a valid C string
+        let message = unsafe { libc::dlerror() };
+        if message.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+        }
+    };
+
+    // The library stays loaded for the life of the process, since its
+    // functions are called until then
+    // SAFETY: both strings are valid and NUL-terminated
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return Err(dl_error());
+    }
+    let init = unsafe { libc::dlsym(handle, symbol.as_ptr()) };
+    if init.is_null() {
+        return Err(format!("{} does not export {}", path.display(), PLUGIN_ENTRY_POINT));
+    }
+    // SAFETY: plugins export the entry point with the PluginInit signature
+    let init: PluginInit = unsafe { std::mem::transmute::<*mut libc::c_void, PluginInit>(init) };
+    unsafe { init(registry()) };
+    Ok(())
+}
+
+/// Load a plugin library and let it register its functions
+#[cfg(not(unix))]
+pub fn load_plugin(path: &std::path::Path) -> Result<(), String> {
+    Err(format!("cannot load {}: plugins are only supported on Unix", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::StringOps;
+
+    struct Echo;
+
+    impl HexagonFunction for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn read_only(&self) -> bool {
+            true
+        }
+
+        fn call(&self, ctx: &mut FunctionContext<'_>) -> Result<RespValue, String> {
+            let first = ctx.keys().first().cloned().ok_or("no key")?;
+            let value = ctx.db().get(&first).map_err(|e| e.to_string())?;
+            Ok(RespValue::BulkString(value))
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = FunctionRegistry::new();
+        assert!(!registry.register(Arc::new(Echo)));
+        assert!(registry.register(Arc::new(Echo)));
+
+        let function = registry.get("ECHO").unwrap();
+        let mut db = DB::new();
+        db.set("k".to_string(), "v".to_string());
+        let keys = vec!["k".to_string()];
+        let mut ctx = FunctionContext::new(&mut db, &keys, &[]);
+        assert_eq!(function.call(&mut ctx), Ok(RespValue::BulkString(Some("v".to_string()))));
+        let mut ctx = FunctionContext::new(&mut db, &[], &[]);
+        assert!(function.call(&mut ctx).is_err());
+
+        registry.record("echo", Duration::from_micros(5), false);
+        registry.record("echo", Duration::from_micros(7), true);
+        assert_eq!(
+            registry.stats(),
+            vec![("ECHO".to_string(), FunctionStats { calls: 2, errors: 1, total_us: 12 })]
+        );
+
+        assert!(registry.unregister("Echo"));
+        assert!(registry.get("echo").is_none());
+    }
+}
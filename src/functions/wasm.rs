@@ -0,0 +1,576 @@
+//! WASM function libraries (FUNCTION LOAD WASM).
+//!
+//! A library is a WebAssembly module loaded with `FUNCTION LOAD WASM
+//! library code`, where `code` is the module hex-encoded or as WAT text.
+//! Every export taking and returning nothing becomes a function called
+//! `library.export`. Modules only see the host API imported from the
+//! `hexagon` module; they cannot reach the keyspace, files or network any
+//! other way:
+//!
+//! - `arg_count() -> i32`, `arg_len(i) -> i32`, `arg_read(i, ptr) -> i32`
+//! - `key_count() -> i32`
+//! - `get(key, ptr, cap) -> i32`: copies up to `cap` bytes of the string
+//!   at key index `key` and returns its length, -1 if it does not exist
+//! - `set(key, ptr, len)`, `del(key) -> i32`
+//! - `reply_int(i64)`, `reply_bulk(ptr, len)`, `reply_nil()`,
+//!   `reply_error(ptr, len)`
+//!
+//! Pointers refer to the module's exported `memory`. Functions work on a
+//! copy of their keys, written back only when the call neither traps nor
+//! replies with an error, so a failed call leaves the keyspace unchanged.
+//! Each call gets a fresh instance. A library that imports neither `set`
+//! nor `del` is read-only. A call runs out of fuel after
+//! `functions.wasm_fuel` units (roughly, instructions) and cannot grow its
+//! memory past `functions.wasm_memory_mb`, so a runaway module fails
+//! instead of stalling the server.
+//!
+//! Loaded libraries are saved as `functions/<library>.wasm` in the data
+//! directory and loaded again at startup. The runtime is compiled in with
+//! the `wasm` feature.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Import module of the host API
+pub const HOST_MODULE: &str = "hexagon";
+
+/// Directory of the saved libraries
+pub fn library_dir() -> PathBuf {
+    crate::persistence::paths::data_dir().join("functions")
+}
+
+static FUEL: AtomicU64 = AtomicU64::new(100_000_000);
+static MEMORY_BYTES: AtomicUsize = AtomicUsize::new(64 << 20);
+
+/// Set the fuel and linear memory (in megabytes) each call may use
+pub fn set_limits(fuel: u64, memory_mb: usize) {
+    FUEL.store(fuel, Ordering::Relaxed);
+    MEMORY_BYTES.store(memory_mb.saturating_mul(1 << 20), Ordering::Relaxed);
+}
+
+/// Fuel and memory bytes each call may use
+#[cfg(feature = "wasm")]
+fn limits() -> (u64, usize) {
+    (FUEL.load(Ordering::Relaxed), MEMORY_BYTES.load(Ordering::Relaxed))
+}
+
+/// Whether the WASM runtime was compiled in
+pub const fn enabled() -> bool {
+    cfg!(feature = "wasm")
+}
+
+#[cfg(feature = "wasm")]
+pub use runtime::{delete, load};
+
+/// Load a library, returning the names of its functions
+#[cfg(not(feature = "wasm"))]
+pub fn load(_library: &str, _code: &str, _replace: bool) -> Result<Vec<String>, String> {
+    Err("WASM functions are not compiled in (build with --features wasm)".to_string())
+}
+
+/// Unload a library, returning whether it existed
+#[cfg(not(feature = "wasm"))]
+pub fn delete(_library: &str) -> bool {
+    false
+}
+
+/// Save a loaded library to `dir`
+#[cfg(feature = "wasm")]
+pub fn save(library: &str, dir: &Path) -> std::io::Result<()> {
+    let Some(wasm) = runtime::binary(library) else {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "library not loaded"));
+    };
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.wasm", library));
+    let tmp = path.with_extension("wasm.tmp");
+    std::fs::write(&tmp, wasm)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// Save a loaded library to `dir`
+#[cfg(not(feature = "wasm"))]
+pub fn save(_library: &str, _dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Remove the saved copy of a library from `dir`
+pub fn forget(library: &str, dir: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(dir.join(format!("{}.wasm", library))) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Load every library saved in `dir`, returning each library name with the
+/// outcome
+pub fn load_saved(dir: &Path) -> Vec<(String, Result<Vec<String>, String>)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut loaded = Vec::new();
+    for path in entries.flatten().map(|e| e.path()) {
+        if path.extension().and_then(|e| e.to_str()) != Some("wasm") {
+            continue;
+        }
+        let Some(library) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+            continue;
+        };
+        let result = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|wasm| load_binary(&library, &wasm));
+        loaded.push((library, result));
+    }
+    loaded.sort_by(|a, b| a.0.cmp(&b.0));
+    loaded
+}
+
+#[cfg(feature = "wasm")]
+fn load_binary(library: &str, wasm: &[u8]) -> Result<Vec<String>, String> {
+    runtime::install(library, wasm.to_vec(), true)
+}
+
+#[cfg(not(feature = "wasm"))]
+fn load_binary(library: &str, _wasm: &[u8]) -> Result<Vec<String>, String> {
+    load(library, "", true)
+}
+
+#[cfg(feature = "wasm")]
+mod runtime {
+    use super::{limits, HOST_MODULE};
+    use crate::db::{GenericOps, StringOps, DB};
+    use crate::functions::{registry, FunctionContext, HexagonFunction};
+    use crate::network::resp::RespValue;
+    use parking_lot::Mutex;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, OnceLock};
+    use wasmtime::{Caller, Engine, Extern, ExternType, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+    /// Host functions a module may import
+    const HOST_FUNCTIONS: &[&str] = &[
+        "arg_count", "arg_len", "arg_read", "key_count", "get", "set", "del", "reply_int", "reply_bulk",
+        "reply_nil", "reply_error",
+    ];
+
+    struct Library {
+        functions: Vec<String>,
+        wasm: Vec<u8>,
+    }
+
+    fn libraries() -> &'static Mutex<BTreeMap<String, Library>> {
+        static LIBRARIES: OnceLock<Mutex<BTreeMap<String, Library>>> = OnceLock::new();
+        LIBRARIES.get_or_init(Default::default)
+    }
+
+    fn engine() -> &'static Engine {
+        static ENGINE: OnceLock<Engine> = OnceLock::new();
+        ENGINE.get_or_init(|| {
+            let mut config = wasmtime::Config::new();
+            config.consume_fuel(true).wasm_backtrace(false);
+            Engine::new(&config).expect("valid WASM engine configuration")
+        })
+    }
+
+    /// Load a library, returning the names of its functions
+    pub fn load(library: &str, code: &str, replace: bool) -> Result<Vec<String>, String> {
+        install(library, decode(code)?, replace)
+    }
+
+    /// Unload a library, returning whether it existed
+    pub fn delete(library: &str) -> bool {
+        let Some(old) = libraries().lock().remove(library) else {
+            return false;
+        };
+        for name in &old.functions {
+            registry().unregister(name);
+        }
+        true
+    }
+
+    /// The module binary of a loaded library
+    pub fn binary(library: &str) -> Option<Vec<u8>> {
+        libraries().lock().get(library).map(|l| l.wasm.clone())
+    }
+
+    /// Module bytes from hex-encoded WASM or WAT text
+    fn decode(code: &str) -> Result<Vec<u8>, String> {
+        let code = code.trim();
+        let hex = !code.is_empty() && code.len().is_multiple_of(2) && code.bytes().all(|b| b.is_ascii_hexdigit());
+        if hex {
+            return Ok((0..code.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&code[i..i + 2], 16).unwrap_or_default())
+                .collect());
+        }
+        wat::parse_str(code).map_err(|e| format!("invalid module: {}", e))
+    }
+
+    /// Compile a module and register its functions
+    pub fn install(library: &str, wasm: Vec<u8>, replace: bool) -> Result<Vec<String>, String> {
+        if library.is_empty() || !library.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+            return Err("Library names can only contain letters, numbers, or underscores(_)".to_string());
+        }
+        let module = Module::new(engine(), &wasm).map_err(|e| format!("invalid module: {}", e))?;
+
+        let mut read_only = true;
+        for import in module.imports() {
+            if import.module() != HOST_MODULE || !HOST_FUNCTIONS.contains(&import.name()) {
+                return Err(format!("module imports unknown {}.{}", import.module(), import.name()));
+            }
+            if matches!(import.name(), "set" | "del") {
+                read_only = false;
+            }
+        }
+        let exports: Vec<String> = module
+            .exports()
+            .filter(|export| match export.ty() {
+                ExternType::Func(ty) => ty.params().len() == 0 && ty.results().len() == 0,
+                _ => false,
+            })
+            .map(|export| export.name().to_string())
+            .collect();
+        if exports.is_empty() {
+            return Err("library exports no functions".to_string());
+        }
+
+        let mut libraries = libraries().lock();
+        if libraries.contains_key(library) && !replace {
+            return Err(format!("Library '{}' already exists", library));
+        }
+        let names: Vec<String> = exports.iter().map(|export| format!("{}.{}", library, export)).collect();
+        let previous = libraries.get(library).map(|l| l.functions.as_slice()).unwrap_or_default();
+        if let Some(taken) = names
+            .iter()
+            .find(|name| registry().get(name).is_some() && !previous.iter().any(|p| p.eq_ignore_ascii_case(name)))
+        {
+            return Err(format!("Function {} already exists", taken));
+        }
+        for name in previous {
+            registry().unregister(name);
+        }
+        for (name, export) in names.iter().zip(exports) {
+            registry().register(Arc::new(WasmFunction {
+                name: name.clone(),
+                export,
+                module: module.clone(),
+                read_only,
+            }));
+        }
+        libraries.insert(library.to_string(), Library { functions: names.clone(), wasm });
+        Ok(names)
+    }
+
+    struct WasmFunction {
+        name: String,
+        export: String,
+        module: Module,
+        read_only: bool,
+    }
+
+    /// A key as the function sees it
+    #[derive(Clone)]
+    enum Staged {
+        Missing,
+        Value(String),
+        /// Holds a type other than a string
+        Other,
+    }
+
+    /// Store data of one call
+    struct CallState {
+        args: Vec<String>,
+        keys: Vec<Staged>,
+        dirty: Vec<bool>,
+        reply: Option<Result<RespValue, String>>,
+        limits: StoreLimits,
+    }
+
+    impl CallState {
+        fn arg(&self, i: i32) -> wasmtime::Result<&str> {
+            usize::try_from(i)
+                .ok()
+                .and_then(|i| self.args.get(i))
+                .map(String::as_str)
+                .ok_or_else(|| wasmtime::Error::msg(format!("argument index {} out of range", i)))
+        }
+
+        fn key_index(&self, key: i32) -> wasmtime::Result<usize> {
+            usize::try_from(key)
+                .ok()
+                .filter(|&i| i < self.keys.len())
+                .ok_or_else(|| wasmtime::Error::msg(format!("key index {} out of range", key)))
+        }
+    }
+
+    fn memory(caller: &mut Caller<'_, CallState>) -> wasmtime::Result<Memory> {
+        caller
+            .get_export("memory")
+            .and_then(Extern::into_memory)
+            .ok_or_else(|| wasmtime::Error::msg("module does not export memory"))
+    }
+
+    fn read(caller: &mut Caller<'_, CallState>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+        let memory = memory(caller)?;
+        let len = usize::try_from(len).unwrap_or_default();
+        if len > memory.data_size(&*caller) {
+            return Err(wasmtime::Error::msg("out of bounds memory access"));
+        }
+        let mut buffer = vec![0; len];
+        memory.read(&*caller, ptr as u32 as usize, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn write(caller: &mut Caller<'_, CallState>, ptr: i32, bytes: &[u8]) -> wasmtime::Result<()> {
+        let memory = memory(caller)?;
+        memory.write(caller, ptr as u32 as usize, bytes)?;
+        Ok(())
+    }
+
+    /// The host API, shared by every call
+    fn linker() -> &'static Linker<CallState> {
+        static LINKER: OnceLock<Linker<CallState>> = OnceLock::new();
+        LINKER.get_or_init(|| {
+            let mut linker = Linker::new(engine());
+            define_host_api(&mut linker).expect("host functions are defined once");
+            linker
+        })
+    }
+
+    fn define_host_api(linker: &mut Linker<CallState>) -> wasmtime::Result<()> {
+        linker.func_wrap(HOST_MODULE, "arg_count", |caller: Caller<'_, CallState>| {
+            caller.data().args.len() as i32
+        })?;
+        linker.func_wrap(HOST_MODULE, "arg_len", |caller: Caller<'_, CallState>, i: i32| {
+            caller.data().arg(i).map(|arg| arg.len() as i32)
+        })?;
+        linker.func_wrap(HOST_MODULE, "arg_read", |mut caller: Caller<'_, CallState>, i: i32, ptr: i32| {
+            let arg = caller.data().arg(i)?.to_string();
+            write(&mut caller, ptr, arg.as_bytes())?;
+            Ok(arg.len() as i32)
+        })?;
+        linker.func_wrap(HOST_MODULE, "key_count", |caller: Caller<'_, CallState>| {
+            caller.data().keys.len() as i32
+        })?;
+        linker.func_wrap(
+            HOST_MODULE,
+            "get",
+            |mut caller: Caller<'_, CallState>, key: i32, ptr: i32, cap: i32| -> wasmtime::Result<i32> {
+                let index = caller.data().key_index(key)?;
+                match caller.data().keys[index].clone() {
+                    Staged::Missing => Ok(-1),
+                    Staged::Other => Err(wasmtime::Error::msg(
+                        "WRONGTYPE Operation against a key holding the wrong kind of value",
+                    )),
+                    Staged::Value(value) => {
+                        let bytes = value.as_bytes();
+                        let copied = bytes.len().min(usize::try_from(cap).unwrap_or_default());
+                        write(&mut caller, ptr, &bytes[..copied])?;
+                        Ok(bytes.len() as i32)
+                    }
+                }
+            },
+        )?;
+        linker.func_wrap(
+            HOST_MODULE,
+            "set",
+            |mut caller: Caller<'_, CallState>, key: i32, ptr: i32, len: i32| -> wasmtime::Result<()> {
+                let index = caller.data().key_index(key)?;
+                let value = String::from_utf8(read(&mut caller, ptr, len)?)
+                    .map_err(|_| wasmtime::Error::msg("value is not valid UTF-8"))?;
+                let state = caller.data_mut();
+                state.keys[index] = Staged::Value(value);
+                state.dirty[index] = true;
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(HOST_MODULE, "del", |mut caller: Caller<'_, CallState>, key: i32| {
+            let state = caller.data_mut();
+            let index = state.key_index(key)?;
+            let existed = !matches!(state.keys[index], Staged::Missing);
+            state.keys[index] = Staged::Missing;
+            state.dirty[index] = true;
+            wasmtime::Result::<i32>::Ok(existed as i32)
+        })?;
+        linker.func_wrap(HOST_MODULE, "reply_int", |mut caller: Caller<'_, CallState>, value: i64| {
+            caller.data_mut().reply = Some(Ok(RespValue::Integer(value)));
+        })?;
+        linker.func_wrap(
+            HOST_MODULE,
+            "reply_bulk",
+            |mut caller: Caller<'_, CallState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+                let value = String::from_utf8_lossy(&read(&mut caller, ptr, len)?).into_owned();
+                caller.data_mut().reply = Some(Ok(RespValue::BulkString(Some(value))));
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(HOST_MODULE, "reply_nil", |mut caller: Caller<'_, CallState>| {
+            caller.data_mut().reply = Some(Ok(RespValue::BulkString(None)));
+        })?;
+        linker.func_wrap(
+            HOST_MODULE,
+            "reply_error",
+            |mut caller: Caller<'_, CallState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+                let message = String::from_utf8_lossy(&read(&mut caller, ptr, len)?).into_owned();
+                caller.data_mut().reply = Some(Err(message));
+                Ok(())
+            },
+        )?;
+        Ok(())
+    }
+
+    /// A key's value before the call
+    fn stage(db: &mut DB, key: &str) -> Staged {
+        match db.get(key) {
+            Ok(Some(value)) => Staged::Value(value),
+            Ok(None) => Staged::Missing,
+            Err(_) => Staged::Other,
+        }
+    }
+
+    impl HexagonFunction for WasmFunction {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn read_only(&self) -> bool {
+            self.read_only
+        }
+
+        fn acl_categories(&self) -> &[&str] {
+            if self.read_only {
+                &["read", "wasm"]
+            } else {
+                &["write", "wasm"]
+            }
+        }
+
+        fn description(&self) -> &str {
+            "WASM"
+        }
+
+        fn call(&self, ctx: &mut FunctionContext<'_>) -> Result<RespValue, String> {
+            let keys = ctx.keys().to_vec();
+            let staged: Vec<Staged> = keys.iter().map(|key| stage(ctx.db(), key)).collect();
+            let (fuel, memory_bytes) = limits();
+            let mut store = Store::new(engine(), CallState {
+                args: ctx.args().to_vec(),
+                dirty: vec![false; staged.len()],
+                keys: staged,
+                reply: None,
+                limits: StoreLimitsBuilder::new().memory_size(memory_bytes).instances(1).build(),
+            });
+            store.limiter(|state| &mut state.limits);
+            store.set_fuel(fuel).map_err(|e| e.to_string())?;
+
+            let run = linker()
+                .instantiate(&mut store, &self.module)
+                .and_then(|instance| instance.get_typed_func::<(), ()>(&mut store, &self.export))
+                .and_then(|entry| entry.call(&mut store, ()));
+            if let Err(e) = run {
+                return Err(match e.downcast_ref::<Trap>() {
+                    Some(Trap::OutOfFuel) => "function exceeded its fuel limit".to_string(),
+                    _ => e.root_cause().to_string(),
+                });
+            }
+
+            let state = store.into_data();
+            let reply = state.reply.unwrap_or(Ok(RespValue::BulkString(None)))?;
+            for ((key, staged), dirty) in keys.iter().zip(state.keys).zip(state.dirty) {
+                if !dirty {
+                    continue;
+                }
+                match staged {
+                    Staged::Value(value) => ctx.db().set(key.clone(), value),
+                    _ => {
+                        ctx.db().del(key);
+                    }
+                }
+            }
+            Ok(reply)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn test_wasm_library() {
+        use crate::db::{StringOps, DB};
+        use crate::functions::{registry, FunctionContext};
+        use crate::network::resp::RespValue;
+
+        // incrby(key, amount): adds a single digit to a single digit value
+        let code = r#"(module
+            (import "hexagon" "get" (func $get (param i32 i32 i32) (result i32)))
+            (import "hexagon" "set" (func $set (param i32 i32 i32)))
+            (import "hexagon" "arg_read" (func $arg_read (param i32 i32) (result i32)))
+            (import "hexagon" "reply_bulk" (func $reply_bulk (param i32 i32)))
+            (import "hexagon" "reply_error" (func $reply_error (param i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 100) "not a digit")
+            (func (export "add")
+                (local $sum i32)
+                (if (i32.lt_s (call $get (i32.const 0) (i32.const 0) (i32.const 1)) (i32.const 0))
+                    (then (i32.store8 (i32.const 0) (i32.const 48))))
+                (drop (call $arg_read (i32.const 0) (i32.const 1)))
+                (local.set $sum (i32.sub (i32.add (i32.load8_u (i32.const 0)) (i32.load8_u (i32.const 1))) (i32.const 48)))
+                (if (i32.gt_u (local.get $sum) (i32.const 57))
+                    (then (call $reply_error (i32.const 100) (i32.const 11)) (return)))
+                (i32.store8 (i32.const 0) (local.get $sum))
+                (call $set (i32.const 0) (i32.const 0) (i32.const 1))
+                (call $reply_bulk (i32.const 0) (i32.const 1)))
+            (func (export "spin") (loop $l (br $l)))
+            (func (export "helper") (param i32)))"#;
+
+        assert_eq!(load("wasmtest", code, false), Ok(vec!["wasmtest.add".to_string(), "wasmtest.spin".to_string()]));
+        assert!(load("wasmtest", code, false).is_err());
+        assert!(load("bad name", code, false).is_err());
+        assert!(load("wasmtest2", "(module (import \"env\" \"f\" (func)) (func (export \"f\")))", false).is_err());
+
+        let add = registry().get("WASMTEST.ADD").unwrap();
+        assert!(!add.read_only());
+        let mut db = DB::new();
+        let keys = vec!["counter".to_string()];
+        let args = vec!["4".to_string()];
+        let mut ctx = FunctionContext::new(&mut db, &keys, &args);
+        assert_eq!(add.call(&mut ctx), Ok(RespValue::BulkString(Some("4".to_string()))));
+        let args = vec!["3".to_string()];
+        let mut ctx = FunctionContext::new(&mut db, &keys, &args);
+        assert_eq!(add.call(&mut ctx), Ok(RespValue::BulkString(Some("7".to_string()))));
+        // A failed call leaves the key alone
+        let mut ctx = FunctionContext::new(&mut db, &keys, &args);
+        assert_eq!(add.call(&mut ctx), Err("not a digit".to_string()));
+        assert_eq!(db.get("counter").unwrap(), Some("7".to_string()));
+        // Touching a key that was not given traps
+        let mut ctx = FunctionContext::new(&mut db, &[], &args);
+        assert!(add.call(&mut ctx).is_err());
+        // A runaway call fails once it runs out of fuel
+        let spin = registry().get("wasmtest.spin").unwrap();
+        let mut ctx = FunctionContext::new(&mut db, &[], &[]);
+        assert_eq!(spin.call(&mut ctx), Err("function exceeded its fuel limit".to_string()));
+
+        let dir = std::env::temp_dir().join(format!("hexagondb-wasm-{}", std::process::id()));
+        save("wasmtest", &dir).unwrap();
+        assert!(delete("wasmtest"));
+        assert!(!delete("wasmtest"));
+        assert!(registry().get("wasmtest.add").is_none());
+        let loaded = load_saved(&dir);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].0, "wasmtest");
+        assert!(registry().get("wasmtest.add").is_some());
+        forget("wasmtest", &dir).unwrap();
+        assert!(load_saved(&dir).is_empty());
+        delete("wasmtest");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn test_compiled_out() {
+        assert!(!enabled());
+        assert!(load("lib", "(module)", false).is_err());
+        assert!(!delete("lib"));
+    }
+}
@@ -0,0 +1,235 @@
+//! Latency monitor.
+//!
+//! Records latency spikes per event class so stalls can be correlated with
+//! internal activity. Only events at or above `latency_monitor_threshold_ms`
+//! are kept; a threshold of 0 disables the monitor. Each event keeps its
+//! last `HISTORY_LEN` spikes, one sample per second (the worst one).
+//!
+//! Event classes:
+//! - `command`: a client command
+//! - `aof-fsync`: fsync of the AOF file
+//! - `expire-cycle`: an active expiry pass
+//! - `save`: an RDB snapshot
+
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub const EVENT_COMMAND: &str = "command";
+pub const EVENT_AOF_FSYNC: &str = "aof-fsync";
+pub const EVENT_EXPIRE_CYCLE: &str = "expire-cycle";
+pub const EVENT_SAVE: &str = "save";
+
+/// Samples kept per event
+const HISTORY_LEN: usize = 160;
+
+/// One latency spike
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    /// Unix time in seconds
+    pub timestamp: u64,
+    pub latency_ms: u64,
+}
+
+/// Latest spike of an event and the worst one seen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyLatest {
+    pub event: String,
+    pub latest: LatencySample,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct EventHistory {
+    samples: VecDeque<LatencySample>,
+    max_ms: u64,
+}
+
+/// Latency event tracker
+pub struct LatencyMonitor {
+    threshold_ms: AtomicU64,
+    events: RwLock<BTreeMap<String, EventHistory>>,
+}
+
+impl LatencyMonitor {
+    /// Create a monitor (disabled when `threshold_ms` is 0)
+    pub const fn new(threshold_ms: u64) -> Self {
+        LatencyMonitor {
+            threshold_ms: AtomicU64::new(threshold_ms),
+            events: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Set the threshold in milliseconds (0 disables)
+    pub fn set_threshold(&self, threshold_ms: u64) {
+        self.threshold_ms.store(threshold_ms, Ordering::Relaxed);
+    }
+
+    /// Get the threshold in milliseconds
+    pub fn threshold(&self) -> u64 {
+        self.threshold_ms.load(Ordering::Relaxed)
+    }
+
+    /// Record how long an event took; kept only if it is a spike
+    pub fn record(&self, event: &str, elapsed: Duration) {
+        let threshold = self.threshold();
+        let latency_ms = elapsed.as_millis() as u64;
+        if threshold == 0 || latency_ms < threshold {
+            return;
+        }
+        self.add_sample(event, unix_secs(), latency_ms);
+    }
+
+    fn add_sample(&self, event: &str, timestamp: u64, latency_ms: u64) {
+        let mut events = self.events.write();
+        let history = events.entry(event.to_string()).or_default();
+        history.max_ms = history.max_ms.max(latency_ms);
+        match history.samples.back_mut() {
+            // Several spikes in the same second keep the worst one
+            Some(last) if last.timestamp == timestamp => {
+                last.latency_ms = last.latency_ms.max(latency_ms);
+            }
+            _ => {
+                history.samples.push_back(LatencySample { timestamp, latency_ms });
+                if history.samples.len() > HISTORY_LEN {
+                    history.samples.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Latest spike of every event (LATENCY LATEST)
+    pub fn latest(&self) -> Vec<LatencyLatest> {
+        self.events
+            .read()
+            .iter()
+            .filter_map(|(event, history)| {
+                history.samples.back().map(|latest| LatencyLatest {
+                    event: event.clone(),
+                    latest: *latest,
+                    max_ms: history.max_ms,
+                })
+            })
+            .collect()
+    }
+
+    /// All recorded spikes of an event, oldest first (LATENCY HISTORY)
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        self.events
+            .read()
+            .get(event)
+            .map(|h| h.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget the given events, or all of them if none are given.
+    /// Returns the number of events reset (LATENCY RESET).
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut recorded = self.events.write();
+        if events.is_empty() {
+            let count = recorded.len();
+            recorded.clear();
+            return count;
+        }
+        events.iter().filter(|e| recorded.remove(e.as_str()).is_some()).count()
+    }
+
+    /// Human readable summary with hints (LATENCY DOCTOR)
+    pub fn doctor(&self) -> String {
+        let threshold = self.threshold();
+        if threshold == 0 {
+            return "Latency monitoring is disabled. Set latency_monitor_threshold_ms in the [server] section to enable it.".to_string();
+        }
+        let latest = self.latest();
+        if latest.is_empty() {
+            return format!("No latency spikes at or above {}ms were observed.", threshold);
+        }
+
+        let mut report = format!("Latency spikes at or above {}ms:\n", threshold);
+        for entry in &latest {
+            let samples = self.history(&entry.event);
+            let avg = samples.iter().map(|s| s.latency_ms).sum::<u64>() / samples.len().max(1) as u64;
+            report.push_str(&format!(
+                "- {}: {} spikes, average {}ms, worst {}ms, latest {}ms\n",
+                entry.event,
+                samples.len(),
+                avg,
+                entry.max_ms,
+                entry.latest.latency_ms
+            ));
+        }
+        let advice: Vec<&str> = latest
+            .iter()
+            .filter_map(|e| match e.event.as_str() {
+                EVENT_COMMAND => Some("Slow commands: check SLOWLOG GET for expensive commands on large keys."),
+                EVENT_AOF_FSYNC => Some("AOF fsync stalls: consider a faster disk or aof_fsync = \"everysec\"."),
+                EVENT_EXPIRE_CYCLE => Some("Expiry cycles are slow: many keys or hash fields expire at the same time."),
+                EVENT_SAVE => Some("Snapshots are slow: the keyspace is locked while dump.rdb is written."),
+                _ => None,
+            })
+            .collect();
+        if !advice.is_empty() {
+            report.push_str("\nAdvice:\n");
+            for line in advice {
+                report.push_str(&format!("- {}\n", line));
+            }
+        }
+        report
+    }
+}
+
+static MONITOR: LatencyMonitor = LatencyMonitor::new(0);
+
+/// The process-wide latency monitor
+pub fn monitor() -> &'static LatencyMonitor {
+    &MONITOR
+}
+
+/// Record an event on the process-wide monitor
+pub fn record(event: &str, elapsed: Duration) {
+    MONITOR.record(event, elapsed);
+}
+
+fn unix_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_monitor() {
+        let monitor = LatencyMonitor::new(0);
+        monitor.record(EVENT_COMMAND, Duration::from_millis(500));
+        assert!(monitor.latest().is_empty());
+
+        monitor.set_threshold(10);
+        monitor.record(EVENT_COMMAND, Duration::from_millis(5));
+        monitor.add_sample(EVENT_COMMAND, 100, 20);
+        monitor.add_sample(EVENT_COMMAND, 100, 50);
+        monitor.add_sample(EVENT_COMMAND, 101, 30);
+        monitor.add_sample(EVENT_SAVE, 100, 15);
+
+        assert_eq!(
+            monitor.history(EVENT_COMMAND),
+            vec![
+                LatencySample { timestamp: 100, latency_ms: 50 },
+                LatencySample { timestamp: 101, latency_ms: 30 },
+            ]
+        );
+        let latest = monitor.latest();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].event, EVENT_COMMAND);
+        assert_eq!(latest[0].max_ms, 50);
+        assert!(monitor.doctor().contains("worst 50ms"));
+
+        assert_eq!(monitor.reset(&[EVENT_SAVE.to_string(), "nope".to_string()]), 1);
+        assert_eq!(monitor.reset(&[]), 1);
+        assert!(monitor.latest().is_empty());
+    }
+}
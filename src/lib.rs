@@ -1,15 +1,30 @@
+pub mod access_log;
+pub mod analyze;
+pub mod benchmark;
+pub mod blocking;
+pub mod bulk_ttl;
 pub mod commands;
 pub mod config;
+pub mod failpoint;
+pub mod flush;
+pub mod functions;
+pub mod latency;
 pub mod db;
 pub mod network;
 pub mod observability;
 pub mod persistence;
+pub mod scheduler;
 pub mod security;
+pub mod selftest;
 pub mod server_info;
 pub mod transaction;
 pub mod slowlog;
+pub mod subscription;
 pub mod client;
 pub mod backup;
 pub mod replication;
 pub mod pipeline;
 pub mod cli;
+pub mod embedded;
+#[cfg(windows)]
+pub mod service;
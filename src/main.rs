@@ -1,15 +1,24 @@
 use clap::Parser;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::net::TcpListener;
 use tokio::sync::RwLock;
-use tracing::{error, info};
-use tracing_subscriber;
+use tracing::{error, info, warn, Instrument};
 
 use hexagondb::{
-    commands, config::Config, db::DB, network::connection, persistence::aof::Aof,
-    server_info::ServerInfo,
+    access_log::AccessLog, analyze::AnalyzeJobs, bulk_ttl::BulkTtlJobs, commands,
+    config::{Config, ServerConfig}, db::DB, flush::FlushConfirmations, latency,
+    network::connection, network::listener, network::proxy,
+    network::scheduler::CommandScheduler, persistence::aof::{self, Aof}, persistence::backup,
+    persistence::bgsave, persistence::durability::DURABILITY, persistence::migrate,
+    persistence::paths, persistence::recovery::{self, Source}, persistence::snapshot,
+    security::Security, selftest,
+    selftest::SelfTestOptions, server_info::ServerInfo, slowlog::SlowLog,
 };
 
+#[cfg(windows)]
+use hexagondb::service;
+
 /// HexagonDB - in-memory database written in Rust
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -25,12 +34,123 @@ struct Args {
     /// Override port
     #[arg(short, long)]
     port: Option<u16>,
+
+    /// Run a quick environment self-test, print a report and exit
+    #[arg(long)]
+    selftest: bool,
+
+    /// Restore the data directory from a BACKUP directory before loading
+    #[arg(long, value_name = "DIR")]
+    restore_from: Option<PathBuf>,
+
+    /// Register HexagonDB as a Windows service using this config file
+    #[cfg(windows)]
+    #[arg(long)]
+    install_service: bool,
+
+    /// Remove the HexagonDB Windows service
+    #[cfg(windows)]
+    #[arg(long)]
+    uninstall_service: bool,
+
+    /// Run under the Service Control Manager (set by --install-service)
+    #[cfg(windows)]
+    #[arg(long, hide = true)]
+    service: bool,
 }
 
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
+fn main() -> std::io::Result<()> {
     let args = Args::parse();
 
+    // Self-test mode: benchmark the environment instead of starting the server.
+    // Data files live in the working directory, so that is where fsync is measured.
+    if args.selftest {
+        let report = runtime(&Default::default())?.block_on(selftest::run(
+            std::path::Path::new("."),
+            SelfTestOptions::default(),
+        ));
+        println!("{}", report);
+        std::process::exit(if report.passed() { 0 } else { 1 });
+    }
+
+    #[cfg(windows)]
+    {
+        if args.install_service {
+            return service::install(&args.config);
+        }
+        if args.uninstall_service {
+            return service::uninstall();
+        }
+        if args.service {
+            return service::run(run_service);
+        }
+    }
+
+    // Run until the process is killed
+    let server = runtime_settings(&args.config);
+    runtime(&server)?.block_on(serve(args, std::future::pending()))
+}
+
+/// Service entry point: serve until the Service Control Manager stops us
+#[cfg(windows)]
+fn run_service(stop: tokio::sync::oneshot::Receiver<()>) -> std::io::Result<()> {
+    let args = Args::parse();
+    runtime(&runtime_settings(&args.config))?.block_on(serve(args, async {
+        let _ = stop.await;
+    }))
+}
+
+/// The `[server]` section, read before the runtime exists. A config file
+/// that fails to load is reported once the server starts.
+fn runtime_settings(config_path: &str) -> ServerConfig {
+    Config::from_file(config_path)
+        .map(|config| config.server)
+        .unwrap_or_default()
+}
+
+fn runtime(server: &ServerConfig) -> std::io::Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if server.worker_threads > 0 {
+        builder.worker_threads(server.worker_threads);
+    }
+    builder
+        .max_blocking_threads(server.max_blocking_threads.max(1))
+        .enable_all()
+        .build()
+}
+
+/// Log to stdout, or to the Windows event log when running as a service
+fn init_logging(as_service: bool) {
+    // Note: Dynamic log level change requires tracing-subscriber reload layer.
+    // Currently, config reload does not update the log level.
+    let builder = tracing_subscriber::fmt()
+        .with_target(false)
+        .with_thread_ids(true)
+        .with_file(true)
+        .with_line_number(true)
+        .compact();
+
+    #[cfg(windows)]
+    if as_service {
+        match service::EventLogWriter::open() {
+            Ok(writer) => return builder.with_ansi(false).with_writer(writer).init(),
+            // A service has no console, but stdout is still better than nothing
+            Err(e) => eprintln!("Failed to open the event log: {}", e),
+        }
+    }
+    #[cfg(not(windows))]
+    let _ = as_service;
+
+    builder.init();
+}
+
+/// Start the server and accept clients until `shutdown` completes
+async fn serve(args: Args, shutdown: impl Future<Output = ()>) -> std::io::Result<()> {
+    #[cfg(windows)]
+    let as_service = args.service;
+    #[cfg(not(windows))]
+    let as_service = false;
+
     // Load configuration
     let mut config = Config::from_file(&args.config).unwrap_or_else(|e| {
         eprintln!("Warning: Failed to load config file: {}", e);
@@ -49,45 +169,120 @@ async fn main() -> std::io::Result<()> {
     // Wrap config in Arc<RwLock> for hot reload
     let config = Arc::new(RwLock::new(config));
 
-    // Initialize logging with configured level.
-    // Note: Dynamic log level change requires tracing-subscriber reload layer.
-    // Currently, config reload does not update the log level.
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .compact()
-        .init();
+    // Initialize logging
+    init_logging(as_service);
+
+    // Node identity, attached to metrics, logs and INFO
+    let (instance_name, shard_id) = {
+        let cfg = config.read().await;
+        (cfg.server.instance_name.clone(), cfg.server.shard_id)
+    };
+    let node_span = tracing::info_span!("node", instance = %instance_name, shard = shard_id);
 
     // Initialize metrics
-    hexagondb::observability::metrics::init_metrics();
+    hexagondb::observability::metrics::init_metrics(&instance_name, shard_id);
+    latency::monitor().set_threshold(config.read().await.server.latency_monitor_threshold_ms);
 
     {
         let cfg = config.read().await;
-        info!("Starting HexagonDB server...");
+        info!(
+            "Starting HexagonDB server (instance {}, shard {})...",
+            instance_name, shard_id
+        );
         info!("Configuration loaded from: {}", args.config);
-        info!("HexagonDB server listening on {} 🚀", cfg.server_address());
+        info!("HexagonDB server listening on {} 🚀", cfg.server_addresses().join(", "));
         info!("Max connections: {}", cfg.server.max_connections);
     }
 
     // Create database
-    let db: DB = DB::new();
+    let mut db: DB = DB::new();
+    {
+        let recycle = &config.read().await.recycle_bin;
+        db.recycle_bin.set_retention(recycle.retention());
+        db.recycle_bin.set_limits(recycle.max_entries, recycle.max_memory);
+    }
+    // Expired keys are handed to the scheduler for its @expire jobs
+    let (expired_tx, expired_rx) = tokio::sync::mpsc::channel(hexagondb::scheduler::EXPIRED_QUEUE_LEN);
+    db.on_expire(move |key, _| {
+        let _ = expired_tx.try_send(key.to_string());
+    });
     let db = Arc::new(RwLock::new(db));
 
-    // Initialize AOF
-    let aof = Aof::new("database.aof")?;
-    if let Err(e) = Aof::load("database.aof", &db).await {
-        error!("Error loading AOF: {}", e);
+    // A service starts in the system directory, so it keeps its files in
+    // the platform data directory unless one is configured
+    let data_dir = config
+        .read()
+        .await
+        .persistence
+        .data_dir
+        .clone()
+        .map(PathBuf::from)
+        .or_else(|| as_service.then(paths::platform_data_dir).flatten());
+    if let Some(dir) = data_dir {
+        std::fs::create_dir_all(&dir)?;
+        paths::set_data_dir(dir);
+    }
+
+    let persistence = config.read().await.persistence.clone();
+    snapshot::set_compression(persistence.compression_threshold());
+    aof::set_rdb_preamble(persistence.aof_use_rdb_preamble);
+    aof::set_segment_size(persistence.aof_segment_size);
+
+    // A restore replaces the data files before anything reads them
+    if let Some(backup_dir) = &args.restore_from {
+        let report = backup::restore(backup_dir, &paths::data_dir(), persistence.aof_enabled)?;
+        info!(
+            "Restored backup {} ({} keys{})",
+            backup_dir.display(),
+            report.keys,
+            if report.aof_restored { ", with its AOF" } else { "" }
+        );
+        if let Some(suffix) = report.moved_aside {
+            warn!("Previous data files were kept with the suffix .{}", suffix);
+        }
     }
+
+    // Initialize AOF, migrating a legacy file first if there is one.
+    // A failed migration aborts startup rather than risk replaying twice.
+    let migrated = persistence.aof_enabled
+        && migrate::migrate_legacy_aof(paths::aof_path(), &db)
+            .await?
+            .is_some();
+    let source = Source::detect(persistence.aof_enabled, &paths::aof_path(), &paths::rdb_path());
+    let aof = if persistence.aof_enabled {
+        Aof::new(paths::aof_path())?
+    } else {
+        info!("AOF disabled; writes are only persisted by RDB snapshots");
+        Aof::disabled()
+    };
     let aof = Arc::new(RwLock::new(aof));
 
     // Initialize server info
-    let server_info = Arc::new(ServerInfo::new());
+    let server_info = Arc::new(ServerInfo::new().with_identity(instance_name, shard_id));
+
+    // Restore the dataset in the background; clients get LOADING until it is done
+    if !migrated {
+        let db_clone = Arc::clone(&db);
+        let aof_clone = Arc::clone(&aof);
+        let progress = Arc::clone(server_info.loading());
+        progress.begin(0);
+        tokio::spawn(
+            async move {
+                if let Err(e) = recovery::recover(&source, &db_clone, &aof_clone, &progress).await {
+                    error!("Error restoring the dataset: {}", e);
+                }
+                progress.finish();
+            }
+            .instrument(node_span.clone()),
+        );
+    }
 
     // Start TCP server
-    let addr = config.read().await.server_address();
-    let listener = TcpListener::bind(&addr).await?;
+    let (addrs, server_settings) = {
+        let cfg = config.read().await;
+        (cfg.server_addresses(), cfg.server.clone())
+    };
+    let mut incoming = listener::accept_all(listener::bind_all(&addrs, &server_settings).await?);
 
     // Limit max concurrent connections
     let max_conn = config.read().await.server.max_connections;
@@ -96,71 +291,320 @@ async fn main() -> std::io::Result<()> {
     // Initialize PubSub
     let pubsub = Arc::new(hexagondb::db::pubsub::PubSub::new());
 
-    // Spawn signal handler for SIGHUP
-    let config_clone = Arc::clone(&config);
-    let config_path = args.config.clone();
-    tokio::spawn(async move {
-        use tokio::signal::unix::{signal, SignalKind};
-        let mut stream = signal(SignalKind::hangup()).unwrap();
-        loop {
-            stream.recv().await;
-            info!("Received SIGHUP. Reloading configuration...");
-            match Config::from_file(&config_path) {
-                Ok(new_config) => {
-                    let mut cfg = config_clone.write().await;
-                    cfg.persistence = new_config.persistence;
-                    cfg.logging = new_config.logging;
-                    cfg.memory = new_config.memory;
-                    info!("Configuration reloaded successfully");
+    // Initialize sampled access log
+    let access_log = AccessLog::from_config(&config.read().await.access_log).map(Arc::new);
+
+    // Server-side functions from plugin libraries
+    for plugin in &config.read().await.functions.plugins {
+        match hexagondb::functions::load_plugin(std::path::Path::new(plugin)) {
+            Ok(()) => info!("Loaded function plugin {}", plugin),
+            Err(e) => error!("Error loading function plugin {}: {}", plugin, e),
+        }
+    }
+    // WASM function libraries loaded by FUNCTION LOAD
+    {
+        let functions = &config.read().await.functions;
+        hexagondb::functions::wasm::set_limits(functions.wasm_fuel, functions.wasm_memory_mb);
+    }
+    for (library, result) in hexagondb::functions::wasm::load_saved(&hexagondb::functions::wasm::library_dir()) {
+        match result {
+            Ok(functions) => info!("Loaded function library {} ({} functions)", library, functions.len()),
+            Err(e) => error!("Error loading function library {}: {}", library, e),
+        }
+    }
+
+    // Slow log shared by all connections
+    let slowlog = Arc::new(SlowLog::from_config(&config.read().await.slowlog));
+
+    // Password, ACL users and IP filters shared by all connections
+    let security = Arc::new(Security::from_config(&config.read().await.security));
+    if paths::acl_path().exists() {
+        if let Err(e) = security.load_acl(paths::acl_path()) {
+            error!("Error loading ACL file: {}", e);
+        }
+    }
+
+    // Registry for background EXPIREMANY jobs
+    let bulk_ttl_jobs = Arc::new(BulkTtlJobs::new());
+
+    // Registry for background ANALYZE jobs
+    let analyze_jobs = Arc::new(AnalyzeJobs::new());
+
+    // Flushes scheduled with FLUSHALL SCHEDULE
+    let flush_confirmations = Arc::new(FlushConfirmations::new());
+
+    // Scheduled commands (SCHEDULE), each run as the user who added it
+    let interp = commands::Interpreter::new(
+        Arc::clone(&db),
+        Arc::clone(&aof),
+        Arc::clone(&server_info),
+        Arc::clone(&config),
+        Arc::clone(&pubsub),
+    )
+    .with_client_addr("scheduler")
+    .with_security(Arc::clone(&security))
+    .with_bulk_ttl_jobs(Arc::clone(&bulk_ttl_jobs))
+    .with_analyze_jobs(Arc::clone(&analyze_jobs))
+    .with_slowlog(Arc::clone(&slowlog));
+    tokio::spawn(
+        hexagondb::scheduler::run(
+            interp,
+            Arc::clone(&db),
+            Arc::clone(&config),
+            Arc::clone(server_info.loading()),
+            expired_rx,
+        )
+        .instrument(node_span.clone()),
+    );
+
+    // Round-robin command scheduling across connections
+    let scheduler = Arc::new(CommandScheduler::new(
+        config.read().await.server.command_quantum,
+    ));
+
+    // Spawn signal handler for SIGHUP (there is no equivalent on Windows)
+    #[cfg(unix)]
+    {
+        let config_clone = Arc::clone(&config);
+        let db_clone = Arc::clone(&db);
+        let slowlog_clone = Arc::clone(&slowlog);
+        let security_clone = Arc::clone(&security);
+        let config_path = args.config.clone();
+        tokio::spawn(
+            async move {
+                use tokio::signal::unix::{signal, SignalKind};
+                let mut stream = signal(SignalKind::hangup()).unwrap();
+                loop {
+                    stream.recv().await;
+                    info!("Received SIGHUP. Reloading configuration...");
+                    match Config::from_file(&config_path) {
+                        Ok(new_config) => {
+                            let mut cfg = config_clone.write().await;
+                            snapshot::set_compression(
+                                new_config.persistence.compression_threshold(),
+                            );
+                            aof::set_rdb_preamble(new_config.persistence.aof_use_rdb_preamble);
+                            aof::set_segment_size(new_config.persistence.aof_segment_size);
+                            cfg.persistence = new_config.persistence;
+                            cfg.logging = new_config.logging;
+                            cfg.memory = new_config.memory;
+                            {
+                                let mut db = db_clone.write().await;
+                                db.recycle_bin.set_retention(new_config.recycle_bin.retention());
+                                db.recycle_bin.set_limits(
+                                    new_config.recycle_bin.max_entries,
+                                    new_config.recycle_bin.max_memory,
+                                );
+                            }
+                            cfg.recycle_bin = new_config.recycle_bin;
+                            cfg.default_ttl = new_config.default_ttl;
+                            cfg.retain = new_config.retain;
+                            cfg.pubsub_log = new_config.pubsub_log;
+                            cfg.capped_list = new_config.capped_list;
+                            cfg.scheduler = new_config.scheduler;
+                            cfg.server.read_only = new_config.server.read_only;
+                            hexagondb::functions::wasm::set_limits(
+                                new_config.functions.wasm_fuel,
+                                new_config.functions.wasm_memory_mb,
+                            );
+                            slowlog_clone.apply_config(&new_config.slowlog);
+                            cfg.slowlog = new_config.slowlog;
+                            security_clone.apply_config(&new_config.security);
+                            cfg.security = new_config.security;
+                            info!("Configuration reloaded successfully");
+                        }
+                        Err(e) => error!("Failed to reload configuration: {}", e),
+                    }
                 }
-                Err(e) => error!("Failed to reload configuration: {}", e),
+            }
+            .instrument(node_span.clone()),
+        );
+    }
+
+    // Publish durability lag gauges once a second
+    let info_clone = Arc::clone(&server_info);
+    tokio::spawn(
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                DURABILITY.publish_metrics(info_clone.last_save_age_secs());
             }
         }
-    });
+        .instrument(node_span.clone()),
+    );
 
-    // Spawn automatic RDB save task
+    // Spawn AOF compaction once too many segments are sealed
     let db_clone = Arc::clone(&db);
+    let aof_clone = Arc::clone(&aof);
     let config_clone = Arc::clone(&config);
-    tokio::spawn(async move {
-        let mut last_save_time = std::time::Instant::now();
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
-
-            let cfg = config_clone.read().await;
-            if !cfg.persistence.rdb_enabled {
-                continue;
+    let loading = Arc::clone(server_info.loading());
+    tokio::spawn(
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                let (max_segments, archive_dir) = {
+                    let cfg = config_clone.read().await;
+                    (cfg.persistence.aof_max_segments, cfg.persistence.aof_archive_dir.clone())
+                };
+                // The dataset must be complete before it replaces the base
+                if max_segments == 0
+                    || loading.is_loading()
+                    || aof_clone.read().await.sealed_segments() <= max_segments
+                {
+                    continue;
+                }
+                let result = {
+                    let db_guard = db_clone.read().await;
+                    let mut aof_guard = aof_clone.write().await;
+                    aof_guard.compact(&db_guard, archive_dir.as_deref().map(std::path::Path::new))
+                };
+                if let Err(e) = result {
+                    error!("AOF compaction failed: {}", e);
+                    tokio::time::sleep(bgsave::RETRY_DELAY).await;
+                }
             }
+        }
+        .instrument(node_span.clone()),
+    );
 
-            let save_interval = cfg.persistence.rdb_save_interval;
-            drop(cfg);
+    // Spawn the sweep closing clients idle for longer than the timeout
+    let config_clone = Arc::clone(&config);
+    let clients = Arc::clone(server_info.clients());
+    tokio::spawn(
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                let timeout = config_clone.read().await.server.timeout_seconds;
+                if timeout == 0 {
+                    continue;
+                }
+                let evicted = clients.evict_idle(std::time::Duration::from_secs(timeout));
+                if evicted > 0 {
+                    info!("Closing {} clients idle for over {} seconds", evicted, timeout);
+                }
+            }
+        }
+        .instrument(node_span.clone()),
+    );
 
-            if last_save_time.elapsed().as_secs() < save_interval {
-                continue;
+    // Spawn active expiry of hash fields with a TTL
+    let db_clone = Arc::clone(&db);
+    tokio::spawn(
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                let start = std::time::Instant::now();
+                db_clone.write().await.active_expire_hash_fields();
+                latency::record(latency::EVENT_EXPIRE_CYCLE, start.elapsed());
             }
+        }
+        .instrument(node_span.clone()),
+    );
 
-            let changes = {
-                let db_guard = db_clone.read().await;
-                db_guard.get_changes()
-            };
-
-            if changes > 0 {
-                info!("Auto-save triggered: {} changes since last save", changes);
-
-                match hexagondb::persistence::snapshot::save("dump.rdb", &db_clone).await {
-                    Ok(_) => {
-                        let db_guard = db_clone.read().await;
-                        db_guard.reset_changes();
-                        drop(db_guard);
-                        last_save_time = std::time::Instant::now();
-                        info!("Auto-save completed successfully");
+    // Spawn compaction of fragmented collections
+    let db_clone = Arc::clone(&db);
+    let config_clone = Arc::clone(&config);
+    tokio::spawn(
+        async move {
+            loop {
+                let interval = config_clone.read().await.memory.compaction_interval_secs;
+                tokio::time::sleep(tokio::time::Duration::from_secs(interval.max(1))).await;
+                if interval == 0 {
+                    continue;
+                }
+                let keys = db_clone.read().await.fragmented_keys();
+                let (mut compacted, mut freed) = (0, 0);
+                for batch in keys.chunks(100) {
+                    let (c, f) = db_clone.write().await.compact_fragmented(batch);
+                    compacted += c;
+                    freed += f;
+                    tokio::task::yield_now().await;
+                }
+                if compacted > 0 {
+                    info!(
+                        "Compacted {} fragmented keys ({} unused slots released)",
+                        compacted, freed
+                    );
+                }
+            }
+        }
+        .instrument(node_span.clone()),
+    );
+
+    // Spawn downsampling of time series into the destinations of their rules
+    let db_clone = Arc::clone(&db);
+    let aof_clone = Arc::clone(&aof);
+    tokio::spawn(
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+                if !db_clone.read().await.has_timeseries_rules() {
+                    continue;
+                }
+                // The samples are logged before the DB lock is released, so
+                // they keep their place among other writes in the AOF
+                let mut db_guard = db_clone.write().await;
+                let added = db_guard.compact_timeseries();
+                let mut aof_guard = aof_clone.write().await;
+                for (dest, timestamp, value) in added {
+                    let record = vec!["TS.ADD".to_string(), dest, timestamp.to_string(), value.to_string()];
+                    if let Err(e) = aof_guard.append(record) {
+                        error!("AOF write error: {}", e);
                     }
-                    Err(e) => {
-                        error!("Auto-save failed: {}", e);
+                }
+            }
+        }
+        .instrument(node_span.clone()),
+    );
+
+    // Spawn automatic RDB save task: BGSAVE whenever a save point is reached
+    let db_clone = Arc::clone(&db);
+    let config_clone = Arc::clone(&config);
+    let loading = Arc::clone(server_info.loading());
+    let info_clone = Arc::clone(&server_info);
+    tokio::spawn(
+        async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+
+                let cfg = config_clone.read().await;
+                // Never snapshot a partially loaded dataset
+                if !cfg.persistence.rdb_enabled || loading.is_loading() {
+                    continue;
+                }
+                let save_points = cfg.persistence.save_points();
+                drop(cfg);
+
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                // Back off after a failed snapshot
+                if !info_clone.last_bgsave_ok()
+                    && now.saturating_sub(info_clone.last_bgsave_time())
+                        < bgsave::RETRY_DELAY.as_secs()
+                {
+                    continue;
+                }
+
+                let changes = db_clone.read().await.get_changes() as u64;
+                let elapsed =
+                    std::time::Duration::from_secs(now.saturating_sub(info_clone.last_save_time()));
+                if let Some(point) = bgsave::due(&save_points, changes, elapsed) {
+                    if bgsave::start(&db_clone, &info_clone) {
+                        info!(
+                            "{} changes in {} seconds. Saving...",
+                            point.changes, point.seconds
+                        );
                     }
                 }
             }
         }
-    });
+        .instrument(node_span.clone()),
+    );
+
+    let proxy_protocol = config.read().await.server.proxy_protocol;
+    tokio::pin!(shutdown);
 
     // Accept incoming connections
     loop {
@@ -172,42 +616,136 @@ async fn main() -> std::io::Result<()> {
         // Let's accept first, then try to acquire. If full, drop connection or wait?
         // Standard pattern: Acquire permit, then accept? No, accept then acquire.
 
-        match listener.accept().await {
-            Ok((stream, addr)) => {
+        let accepted = tokio::select! {
+            Some(accepted) = incoming.recv() => accepted,
+            _ = &mut shutdown => break,
+        };
+        match accepted {
+            Ok((mut stream, peer)) => {
+                if let Err(e) = listener::configure_stream(&stream, &server_settings) {
+                    warn!("Failed to set socket options for {}: {}", peer, e);
+                }
                 let db_clone = Arc::clone(&db);
                 let aof_clone = Arc::clone(&aof);
                 let info_clone = Arc::clone(&server_info);
                 let config_clone = Arc::clone(&config);
+                let client_config = Arc::clone(&config);
                 let pubsub_clone = Arc::clone(&pubsub);
+                let access_log_clone = access_log.clone();
+                let bulk_ttl_jobs_clone = Arc::clone(&bulk_ttl_jobs);
+                let analyze_jobs_clone = Arc::clone(&analyze_jobs);
+                let flush_confirmations_clone = Arc::clone(&flush_confirmations);
+                let slowlog_clone = Arc::clone(&slowlog);
+                let security_clone = Arc::clone(&security);
+                let scheduler_clone = Arc::clone(&scheduler);
                 let limit_clone = Arc::clone(&connection_limit);
 
-                // Try to acquire permit
-                // We use acquire_owned so the permit moves into the task and is dropped when task finishes
-                match limit_clone.clone().try_acquire_owned() {
-                    Ok(permit) => {
-                        tokio::spawn(async move {
-                            // permit is held until this block exits
-                            let _permit = permit;
-                            info!("New client connected: {}", addr);
-                            let mut client = commands::Interpreter::new(
-                                db_clone,
-                                aof_clone,
-                                info_clone,
-                                config_clone,
-                                pubsub_clone,
-                            );
-                            connection::handle_client(stream, &mut client).await;
-                            info!("Client disconnected: {}", addr);
-                        });
-                    }
-                    Err(_) => {
-                        error!("Max connections reached. Rejecting client: {}", addr);
-                        // Optional: Send error message to client before closing?
-                        // stream.write_all(b"-ERR max number of clients reached\r\n").await.ok();
+                tokio::spawn(
+                    async move {
+                        // Behind a load balancer the client address comes from the PROXY header
+                        let addr = if proxy_protocol {
+                            let header = tokio::time::timeout(
+                                std::time::Duration::from_secs(5),
+                                proxy::read_header(&mut stream),
+                            )
+                            .await;
+                            match header {
+                                Ok(Ok(addr)) => addr.unwrap_or(peer),
+                                Ok(Err(e)) => {
+                                    info_clone.increment_rejected();
+                                    warn!("Rejecting client {}: {}", peer, e);
+                                    return;
+                                }
+                                Err(_) => {
+                                    info_clone.increment_rejected();
+                                    warn!("Rejecting client {}: no PROXY header in time", peer);
+                                    return;
+                                }
+                            }
+                        } else {
+                            peer
+                        };
+
+                        if !security_clone.is_ip_allowed(addr.ip()) {
+                            info_clone.increment_rejected();
+                            warn!("Rejecting client from disallowed IP: {}", addr);
+                            return;
+                        }
+                        let _ip_guard = match security_clone.register_connection(addr.ip()) {
+                            Some(guard) => guard,
+                            None => {
+                                info_clone.increment_rejected();
+                                warn!("Too many connections from {}. Rejecting client", addr.ip());
+                                reject(stream, "ERR max number of clients from this IP reached")
+                                    .await;
+                                return;
+                            }
+                        };
+                        // The permit is held until this block exits
+                        let _permit = match limit_clone.try_acquire_owned() {
+                            Ok(permit) => permit,
+                            Err(_) => {
+                                info_clone.increment_rejected();
+                                error!("Max connections reached. Rejecting client: {}", addr);
+                                reject(stream, "ERR max number of clients reached").await;
+                                return;
+                            }
+                        };
+
+                        info!("New client connected: {}", addr);
+                        let mut client = commands::Interpreter::new(
+                            db_clone,
+                            aof_clone,
+                            Arc::clone(&info_clone),
+                            config_clone,
+                            pubsub_clone,
+                        )
+                        .with_client_addr(addr.to_string())
+                        .with_access_log(access_log_clone)
+                        .with_bulk_ttl_jobs(bulk_ttl_jobs_clone)
+                        .with_analyze_jobs(analyze_jobs_clone)
+                        .with_flush_confirmations(flush_confirmations_clone)
+                        .with_slowlog(slowlog_clone)
+                        .with_security(security_clone);
+                        let (limits, max_query_buffer, output_limits, write_timeout) = {
+                            let cfg = client_config.read().await;
+                            (
+                                cfg.parse_limits(),
+                                cfg.server.client_query_buffer_limit,
+                                cfg.output_limits(),
+                                cfg.client_write_timeout(),
+                            )
+                        };
+                        connection::handle_client(
+                            stream,
+                            &mut client,
+                            &scheduler_clone,
+                            limits,
+                            max_query_buffer,
+                            output_limits,
+                            write_timeout,
+                        )
+                        .await;
+                        info!("Client disconnected: {}", addr);
                     }
-                }
+                    .instrument(node_span.clone()),
+                );
             }
             Err(e) => error!("Connection error: {}", e),
         }
     }
+
+    info!("Shutting down");
+    if let Err(e) = aof.write().await.fsync() {
+        error!("Failed to sync AOF on shutdown: {}", e);
+    }
+    Ok(())
+}
+
+/// Send an error reply to a connection that is turned away, then close it
+async fn reject(mut stream: tokio::net::TcpStream, message: &str) {
+    use tokio::io::AsyncWriteExt;
+    let _ = stream
+        .write_all(format!("-{}\r\n", message).as_bytes())
+        .await;
 }
@@ -0,0 +1,209 @@
+//! Connected client registry.
+//!
+//! Every connection registers itself here for as long as it is open and
+//! records when it last sent a command. CLIENT LIST reads the registry, and
+//! a periodic sweep closes clients idle for longer than
+//! `server.timeout_seconds`, so connections whose peer vanished without a
+//! FIN do not pile up. Subscribed clients only receive and blocked clients
+//! wait on purpose, so neither is considered idle.
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// A connected client
+pub struct ClientHandle {
+    id: u64,
+    addr: String,
+    connected_at: Instant,
+    /// Milliseconds after `connected_at` of the last command
+    last_active_ms: AtomicU64,
+    subscribed: AtomicBool,
+    blocked: AtomicBool,
+    evicted: Notify,
+}
+
+impl ClientHandle {
+    /// Unique id of the connection
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Client address
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Time since the client connected
+    pub fn age(&self) -> Duration {
+        self.connected_at.elapsed()
+    }
+
+    /// Time since the client last sent a command
+    pub fn idle(&self) -> Duration {
+        let active = Duration::from_millis(self.last_active_ms.load(Ordering::Relaxed));
+        self.age().saturating_sub(active)
+    }
+
+    /// The client sent a command
+    pub fn touch(&self) {
+        let ms = self.connected_at.elapsed().as_millis() as u64;
+        self.last_active_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Mark the client as in (or out of) subscribe mode
+    pub fn set_subscribed(&self, subscribed: bool) {
+        self.subscribed.store(subscribed, Ordering::Relaxed);
+    }
+
+    /// Whether the client is in subscribe mode
+    pub fn is_subscribed(&self) -> bool {
+        self.subscribed.load(Ordering::Relaxed)
+    }
+
+    /// Mark the client as waiting in (or done with) a blocking command
+    pub fn set_blocked(&self, blocked: bool) {
+        self.blocked.store(blocked, Ordering::Relaxed);
+    }
+
+    /// Whether the client is waiting in a blocking command
+    pub fn is_blocked(&self) -> bool {
+        self.blocked.load(Ordering::Relaxed)
+    }
+
+    /// Ask the connection to close
+    pub fn evict(&self) {
+        self.evicted.notify_one();
+    }
+
+    /// Completes once the connection has been asked to close
+    pub async fn evicted(&self) {
+        self.evicted.notified().await
+    }
+
+    /// One CLIENT LIST line, without the newline
+    pub fn describe(&self) -> String {
+        format!(
+            "id={} addr={} age={} idle={} flags={}",
+            self.id,
+            self.addr,
+            self.age().as_secs(),
+            self.idle().as_secs(),
+            match (self.is_subscribed(), self.is_blocked()) {
+                (true, _) => "P",
+                (_, true) => "b",
+                _ => "N",
+            }
+        )
+    }
+}
+
+/// All open connections
+#[derive(Default)]
+pub struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Arc<ClientHandle>>>,
+}
+
+/// Removes the client from the registry when the connection ends
+pub struct Registration {
+    registry: Arc<ClientRegistry>,
+    handle: Arc<ClientHandle>,
+}
+
+impl Registration {
+    pub fn handle(&self) -> &Arc<ClientHandle> {
+        &self.handle
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.registry.clients.lock().remove(&self.handle.id);
+    }
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a client connected from `addr`
+    pub fn register(self: &Arc<Self>, addr: impl Into<String>) -> Registration {
+        let handle = Arc::new(ClientHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            addr: addr.into(),
+            connected_at: Instant::now(),
+            last_active_ms: AtomicU64::new(0),
+            subscribed: AtomicBool::new(false),
+            blocked: AtomicBool::new(false),
+            evicted: Notify::new(),
+        });
+        self.clients.lock().insert(handle.id, Arc::clone(&handle));
+        Registration {
+            registry: Arc::clone(self),
+            handle,
+        }
+    }
+
+    /// Connected clients, oldest first
+    pub fn list(&self) -> Vec<Arc<ClientHandle>> {
+        self.clients.lock().values().cloned().collect()
+    }
+
+    /// Number of connected clients
+    pub fn len(&self) -> usize {
+        self.clients.lock().len()
+    }
+
+    /// Whether no client is connected
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ask every client idle for longer than `timeout` to close, returning
+    /// how many were
+    pub fn evict_idle(&self, timeout: Duration) -> usize {
+        let idle: Vec<_> = self
+            .clients
+            .lock()
+            .values()
+            .filter(|client| !client.is_subscribed() && !client.is_blocked() && client.idle() > timeout)
+            .cloned()
+            .collect();
+        for client in &idle {
+            client.evict();
+        }
+        idle.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_register_and_evict_idle() {
+        let registry = Arc::new(ClientRegistry::new());
+        let first = registry.register("127.0.0.1:1000");
+        let second = registry.register("127.0.0.1:1001");
+        assert_eq!(registry.len(), 2);
+        assert!(registry.list()[0].describe().starts_with("id=1 addr=127.0.0.1:1000 age=0 idle=0"));
+
+        second.handle().set_subscribed(true);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(registry.evict_idle(Duration::from_millis(10)), 1);
+        // The notification is kept until the connection waits for it
+        first.handle().evicted().await;
+
+        first.handle().touch();
+        assert_eq!(registry.evict_idle(Duration::from_millis(10)), 0);
+
+        drop(first);
+        assert_eq!(registry.list().len(), 1);
+        assert_eq!(registry.list()[0].id(), 2);
+    }
+}
@@ -1,179 +1,463 @@
+use crate::blocking::{Blocked, KEYS_READY};
 use crate::commands::{ExecutionResult, Interpreter};
-use crate::network::resp::{RespHandler, RespValue};
-use crate::observability::metrics::{METRIC_ACTIVE_CONNECTIONS, METRIC_CONNECTIONS_TOTAL};
+use crate::network::output_limit::{LimitExceeded, OutputBufferTracker, OutputLimit, OutputLimits};
+use crate::network::resp::{ParseLimits, RespDecoder, RespValue};
+use crate::network::scheduler::CommandScheduler;
+use crate::observability::metrics::{
+    METRIC_ACTIVE_CONNECTIONS, METRIC_CONNECTIONS_TOTAL, METRIC_OUTPUT_LIMIT_DISCONNECTS,
+    METRIC_PUBSUB_DROPPED_MESSAGES,
+};
+use crate::security::Security;
+use crate::server_info::ServerInfo;
+use crate::subscription::{Subscriber, SubscriptionEnd};
+use bytes::{Buf, BytesMut};
 use metrics::{counter, gauge};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tracing::{debug, error, info, instrument, Instrument};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, error, info, instrument, warn, Instrument};
 use uuid::Uuid;
 
-struct ConnectionGuard;
+struct ConnectionGuard(Arc<ServerInfo>);
 
 impl Drop for ConnectionGuard {
     fn drop(&mut self) {
         gauge!(METRIC_ACTIVE_CONNECTIONS).decrement(1.0);
+        self.0.client_disconnected();
+    }
+}
+
+/// Bağlantı kapanınca hız sınırı durumunu temizler
+struct RateLimitGuard(Arc<Security>, String);
+
+impl Drop for RateLimitGuard {
+    fn drop(&mut self) {
+        self.0.clear_rate_limit(&self.1);
+    }
+}
+
+/// Hata cevabını gönderip bağlantıyı kapatır
+async fn close_with_error(stream: &mut TcpStream, message: &str) {
+    let reply = RespValue::Error(message.to_string()).serialize();
+    let _ = stream.write_all(reply.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Cevabı en fazla `limit` süre içinde yazar (None: süre sınırı yok).
+/// Süre dolarsa istemci okumuyor demektir; hata olarak döner.
+async fn write_with_timeout(
+    stream: &mut TcpStream,
+    bytes: &[u8],
+    limit: Option<Duration>,
+) -> std::io::Result<()> {
+    match limit {
+        Some(limit) => tokio::time::timeout(limit, stream.write_all(bytes))
+            .await
+            .unwrap_or_else(|_| {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "client did not read the reply in time",
+                ))
+            }),
+        None => stream.write_all(bytes).await,
+    }
+}
+
+/// Bu boyutu aşan cevap tamponu yazıldıktan sonra bırakılır
+const OUTPUT_BUFFER_KEEP: usize = 64 * 1024;
+
+/// Hız sınırını aşan komutlara verilen cevap
+pub const RATE_LIMIT_ERROR: &str = "ERR rate limit exceeded";
+
+/// Çıkış tamponu sınırını aşan istemciyi kaydeder ve bağlantıyı kapatır
+async fn close_slow_client(
+    stream: &mut TcpStream,
+    class: &str,
+    pending: usize,
+    reason: LimitExceeded,
+) {
+    warn!(
+        "Closing {} client: {:?} output buffer limit exceeded ({} bytes pending)",
+        class, reason, pending
+    );
+    counter!(METRIC_OUTPUT_LIMIT_DISCONNECTS, "class" => class.to_string()).increment(1);
+    let _ = stream.shutdown().await;
+}
+
+/// Abonelik modunda tampondaki tüm tam komutları `subscriber` ile işler,
+/// cevapları `pending` tamponuna ekler. Abonelik modu bitmeliyse nasıl
+/// bittiğini döner; kalan komutlar normal modda işlenir. Normal moddaki
+/// gibi her komut saniye başına komut sınırına tabidir.
+async fn handle_subscribed_commands(
+    subscriber: &mut Subscriber,
+    decoder: &mut RespDecoder,
+    pending: &mut BytesMut,
+    rate_limit: &RateLimitGuard,
+) -> Option<SubscriptionEnd> {
+    let mut replies = Vec::new();
+    loop {
+        let request = match decoder.decode() {
+            Ok(Some(request)) => request,
+            // Veri eksik, devam et
+            Ok(None) => return None,
+            Err(e) => {
+                error!("Failed to parse request in subscribe mode: {}", e);
+                return Some(SubscriptionEnd::Close);
+            }
+        };
+
+        if !rate_limit.0.allow_command(&rate_limit.1) {
+            RespValue::Error(RATE_LIMIT_ERROR.to_string()).serialize_into(pending);
+            continue;
+        }
+        let end = subscriber.execute(request, &mut replies).await;
+        for reply in replies.drain(..) {
+            reply.serialize_into(pending);
+        }
+        if end.is_some() {
+            return end;
+        }
+    }
+}
+
+/// Abonelik modu: abone olunan kanal ve desenlere gelen mesajları istemciye
+/// iletir ve istemciden gelen komutları `Subscriber` ile işler.
+/// Mesajlar önce bir çıkış tamponunda birikir ve soket yazılabildikçe
+/// gönderilir; yavaş okuyan istemci `limit` aşılınca bağlantıdan atılır.
+async fn subscribe_loop(
+    stream: &mut TcpStream,
+    subscriber: &mut Subscriber,
+    replies: Vec<RespValue>,
+    decoder: &mut RespDecoder,
+    limit: OutputLimit,
+    server_info: &ServerInfo,
+    rate_limit: &RateLimitGuard,
+) -> SubscriptionEnd {
+    // İlk olarak abonelik onaylarını (ve saklanan mesajları) gönder
+    let mut pending = BytesMut::new();
+    for reply in replies {
+        reply.serialize_into(&mut pending);
+    }
+
+    // SUBSCRIBE ile aynı pakette gelmiş komutlar
+    if let Some(end) = handle_subscribed_commands(subscriber, decoder, &mut pending, rate_limit).await {
+        let _ = stream.write_all(&pending).await;
+        return end;
+    }
+
+    let mut tracker = OutputBufferTracker::new(limit);
+    let end = {
+        let (mut reader, mut writer) = stream.split();
+        // Hem kanallardan gelen mesajları hem de istemciden gelen komutları dinliyoruz.
+        loop {
+            tokio::select! {
+                // 1. Kanallardan gelen mesajlar: soket yazılamasa bile tampona al
+                msg = subscriber.recv() => {
+                    match msg {
+                        Ok(message) => message.serialize_into(&mut pending),
+                        Err(RecvError::Lagged(skipped)) => {
+                            // Kanal kapasitesi aşıldı, eski mesajlar kaybedildi
+                            warn!("Subscriber lagged behind, {} messages dropped", skipped);
+                            counter!(METRIC_PUBSUB_DROPPED_MESSAGES).increment(skipped);
+                        }
+                        Err(RecvError::Closed) => {
+                            debug!("Channel closed");
+                            break Ok(SubscriptionEnd::Close);
+                        }
+                    }
+                }
+
+                // 2. Tampondaki verinin yazılabilen kısmını gönder
+                written = writer.write(&pending), if !pending.is_empty() => {
+                    match written {
+                        Ok(n) => {
+                            server_info.add_bytes_sent(n as u64);
+                            pending.advance(n);
+                        }
+                        Err(e) => {
+                            error!("Failed to send push message: {}", e);
+                            break Ok(SubscriptionEnd::Close);
+                        }
+                    }
+                }
+
+                // 3. İstemciden gelen veriler ((P)UNSUBSCRIBE, QUIT vb.)
+                read_result = reader.read_buf(decoder.read_buf()) => {
+                    let n = match read_result {
+                        Ok(0) => {
+                            debug!("Client closed connection during subscribe");
+                            break Ok(SubscriptionEnd::Close);
+                        }
+                        Ok(n) => n,
+                        Err(e) => {
+                            error!("Failed to read from socket in subscribe mode: {}", e);
+                            break Ok(SubscriptionEnd::Close);
+                        }
+                    };
+                    server_info.add_bytes_received(n as u64);
+
+                    if let Some(end) = handle_subscribed_commands(subscriber, decoder, &mut pending, rate_limit).await {
+                        break Ok(end);
+                    }
+                }
+            }
+
+            if let Err(reason) = tracker.check(pending.len(), Instant::now()) {
+                break Err(reason);
+            }
+        }
+    };
+
+    match end {
+        Err(reason) => {
+            close_slow_client(stream, "pubsub", pending.len(), reason).await;
+            SubscriptionEnd::Close
+        }
+        // Bekleyen cevapları (ör. PONG) gönder
+        Ok(end) => match stream.write_all(&pending).await {
+            Ok(()) => end,
+            Err(_) => SubscriptionEnd::Close,
+        },
+    }
+}
+
+/// Engelleyen pop (BLMPOP, BZMPOP): sırayı bırakıp beklerken, bir listeye
+/// ya da sıralı kümeye eleman eklendikçe komutun engellemeyen hâlini yeniden
+/// dener. Süre dolarsa nil döner; istemci bağlantıyı kapatırsa None döner.
+/// Bu sırada gelen veriler `decoder`'a eklenir.
+async fn wait_blocked(
+    stream: &mut TcpStream,
+    client: &mut Interpreter,
+    scheduler: &CommandScheduler,
+    blocked: Blocked,
+    decoder: &mut RespDecoder,
+) -> Option<RespValue> {
+    let deadline = blocked.timeout.map(|timeout| tokio::time::Instant::now() + timeout);
+    loop {
+        // Denemeden önce kaydol ki arada gelen eklemeler kaçmasın
+        let notified = KEYS_READY.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        let reply = {
+            let _turn = scheduler.turn().await;
+            client.execute(blocked.retry.clone()).await
+        };
+        if let ExecutionResult::Response(reply) = reply {
+            if reply != RespValue::Array(None) {
+                return Some(reply);
+            }
+        }
+
+        let expired = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = notified => {}
+            _ = expired => return Some(RespValue::Array(None)),
+            read = stream.read_buf(decoder.read_buf()) => match read {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            },
+        }
     }
 }
 
 /// Her bir istemci bağlantısını işler.
 /// Gelen veriyi buffer'a alır, RESP formatında parse eder, komutu işler ve cevap gönderir.
-#[instrument(skip(stream, client), fields(connection_id = %Uuid::new_v4()))]
-pub async fn handle_client(mut stream: TcpStream, client: &mut Interpreter) {
+/// Komutlar `scheduler` üzerinden sırayla (round-robin) çalıştırılır.
+/// `limits` aşan istekler ve `max_query_buffer` baytı aşan bekleyen veri
+/// hata cevabıyla bağlantının kapanmasına yol açar. `output_limits`
+/// aşan istemcilerin bağlantısı cevap beklemeden kapatılır.
+/// Boşta kalma süresi dolan istemciler (bkz. `ClientRegistry::evict_idle`)
+/// kapatılır; `write_timeout` içinde yazılamayan cevaplar da bağlantıyı
+/// kapatır.
+#[instrument(
+    skip(stream, client, scheduler, limits, output_limits),
+    fields(connection_id = %Uuid::new_v4())
+)]
+pub async fn handle_client(
+    mut stream: TcpStream,
+    client: &mut Interpreter,
+    scheduler: &CommandScheduler,
+    limits: ParseLimits,
+    max_query_buffer: usize,
+    output_limits: OutputLimits,
+    write_timeout: Option<Duration>,
+) {
     counter!(METRIC_CONNECTIONS_TOTAL).increment(1);
     gauge!(METRIC_ACTIVE_CONNECTIONS).increment(1.0);
-    let _guard = ConnectionGuard;
+    let server_info = Arc::clone(client.server_info());
+    server_info.increment_connections();
+    server_info.client_connected();
+    let _guard = ConnectionGuard(Arc::clone(&server_info));
+    let security = Arc::clone(client.security());
+    let rate_limit = RateLimitGuard(Arc::clone(&security), Uuid::new_v4().to_string());
+    let mut output_tracker = OutputBufferTracker::new(output_limits.normal);
+    // CLIENT LIST ve boşta kalma takibi için kayıt
+    let registration = server_info
+        .clients()
+        .register(client.client_addr().unwrap_or_default());
+    let handle = Arc::clone(registration.handle());
 
     info!("New connection established");
 
-    // Sabit buffer yerine dinamik bir buffer kullanıyoruz.
-    // Bu sayede parça parça gelen verileri birleştirebiliriz.
-    let mut buffer = Vec::new();
-    let mut temp_buf = [0u8; 1024]; // Ağdan okuma yapmak için geçici buffer
+    // Soketten okunan veri doğrudan çözücünün tamponuna yazılır; parça
+    // parça gelen istekler kaldığı yerden çözülmeye devam eder.
+    let mut decoder = RespDecoder::new(limits);
+    // Cevap tamponu; kapasitesi okumalar arasında yeniden kullanılır
+    let mut output = BytesMut::new();
 
     loop {
-        match stream.read(&mut temp_buf).await {
+        let read = tokio::select! {
+            read = stream.read_buf(decoder.read_buf()) => read,
+            _ = handle.evicted() => {
+                info!("Closing idle client after {:?}", handle.idle());
+                let _ = stream.shutdown().await;
+                return;
+            }
+        };
+        match read {
             Ok(bytes_read) => {
                 if bytes_read == 0 {
                     debug!("Client closed the connection");
                     return;
                 }
+                handle.touch();
+
+                server_info.add_bytes_received(bytes_read as u64);
+
+                // Sınırsız büyüyen sorgu tamponu bellek tüketir: bağlantıyı kapat
+                if decoder.buffered() > max_query_buffer {
+                    warn!(
+                        "Closing client: query buffer over {} bytes",
+                        max_query_buffer
+                    );
+                    close_with_error(
+                        &mut stream,
+                        "ERR Protocol error: query buffer limit exceeded",
+                    )
+                    .await;
+                    return;
+                }
 
-                // Okunan veriyi ana buffer'a ekle
-                buffer.extend_from_slice(&temp_buf[..bytes_read]);
+                // Pipelining desteği: Tüm mevcut komutları işle, cevaplar
+                // doğrudan `output` tamponuna yazılır
 
-                // Pipelining desteği: Tüm mevcut komutları işle
-                let mut responses = Vec::new();
+                // Sıramızı bekle; diğer istemciler de komut çalıştırabilsin
+                let mut turn = scheduler.turn().await;
 
                 loop {
                     // Buffer boşsa döngüden çık, yeni veri bekle
-                    if buffer.is_empty() {
+                    if decoder.is_empty() {
                         break;
                     }
 
+                    // Kota dolduysa sırayı bırak ve kuyruğun sonuna geç
+                    if !turn.consume() {
+                        drop(turn);
+                        turn = scheduler.turn().await;
+                        turn.consume();
+                    }
+
                     // Gelen veriyi RESP formatında parse etmeye çalış
-                    match RespHandler::parse_request(&buffer) {
-                        Ok(Some((request, len))) => {
+                    match decoder.decode() {
+                        Ok(Some(request)) => {
                             // Başarılı bir şekilde tam bir komut parse edildi
 
                             // Komutu çalıştır
                             let request_id = Uuid::new_v4();
                             let span = tracing::info_span!("request", %request_id);
 
+                            // Saniye başına komut sınırı aşıldıysa çalıştırmadan reddet
+                            if !security.allow_command(&rate_limit.1) {
+                                RespValue::Error(RATE_LIMIT_ERROR.to_string()).serialize_into(&mut output);
+                                continue;
+                            }
+
                             match client.execute(request).instrument(span).await {
                                 ExecutionResult::Response(response) => {
-                                    // Cevabı topla (pipelining için)
-                                    responses.push(response);
+                                    // Cevabı tampona yaz (pipelining için)
+                                    response.serialize_into(&mut output);
                                 }
-                                ExecutionResult::Subscribe(channel, mut receiver) => {
-                                    // Abonelik moduna geç
-                                    // İlk olarak abonelik onayını gönder
-                                    let success_resp = RespValue::Array(Some(vec![
-                                        RespValue::BulkString(Some("subscribe".to_string())),
-                                        RespValue::BulkString(Some(channel.clone())),
-                                        RespValue::Integer(1),
-                                    ]));
-
-                                    let response_bytes = success_resp.serialize();
-                                    if let Err(e) =
-                                        stream.write_all(response_bytes.as_bytes()).await
+                                ExecutionResult::Block(blocked) => {
+                                    // Beklerken sırayı tutma
+                                    drop(turn);
+
+                                    // Önceki cevaplar bekleme süresince gecikmesin
+                                    server_info.add_bytes_sent(output.len() as u64);
+                                    if write_with_timeout(&mut stream, &output, write_timeout)
+                                        .await
+                                        .is_err()
                                     {
-                                        error!("Failed to send subscribe response: {}", e);
                                         return;
                                     }
+                                    output.clear();
 
-                                    // Abonelik döngüsü
-                                    // Hem kanaldan gelen mesajları hem de istemciden gelen komutları dinliyoruz.
-                                    loop {
-                                        tokio::select! {
-                                            // 1. Kanaldan gelen mesajlar
-                                            msg = receiver.recv() => {
-                                                match msg {
-                                                    Ok(msg_content) => {
-                                                        let push_msg = RespValue::Array(Some(vec![
-                                                            RespValue::BulkString(Some("message".to_string())),
-                                                            RespValue::BulkString(Some(channel.clone())),
-                                                            RespValue::BulkString(Some(msg_content)),
-                                                        ]));
-
-                                                        let push_bytes = push_msg.serialize();
-                                                        if let Err(e) = stream.write_all(push_bytes.as_bytes()).await {
-                                                            error!("Failed to send push message: {}", e);
-                                                            break;
-                                                        }
-                                                    }
-                                                    Err(e) => {
-                                                        // Kanal kapandı veya hata oluştu
-                                                        error!("Broadcast receive error: {}", e);
-                                                        break;
-                                                    }
-                                                }
-                                            }
-
-                                            // 2. İstemciden gelen veriler (UNSUBSCRIBE, QUIT vb.)
-                                            read_result = stream.read(&mut temp_buf) => {
-                                                match read_result {
-                                                    Ok(0) => {
-                                                        // Bağlantı koptu
-                                                        debug!("Client closed connection during subscribe");
-                                                        break;
-                                                    }
-                                                    Ok(n) => {
-                                                        // Veriyi buffer'a ekle
-                                                        buffer.extend_from_slice(&temp_buf[..n]);
-
-                                                        // Buffer'daki komutları işle
-                                                        // Not: Basitlik için burada sadece buffer'ın başındaki komuta bakıyoruz.
-                                                        // Gerçek bir implementasyonda döngü içinde tüm komutları işlemeliyiz.
-                                                        match RespHandler::parse_request(&buffer) {
-                                                            Ok(Some((request, len))) => {
-                                                                // Buffer'dan işlenen kısmı sil
-                                                                buffer.drain(0..len);
-
-                                                                if let RespValue::Array(Some(tokens)) = &request {
-                                                                    if !tokens.is_empty() {
-                                                                        if let RespValue::BulkString(Some(cmd)) = &tokens[0] {
-                                                                            let cmd_upper = cmd.to_uppercase();
-                                                                            if cmd_upper == "UNSUBSCRIBE" || cmd_upper == "QUIT" {
-                                                                                // Döngüden çık, normal moda dön veya bağlantıyı kapat
-                                                                                // UNSUBSCRIBE durumunda normal moda dönmek gerekebilir ama şimdilik çıkıyoruz.
-                                                                                break;
-                                                                            } else if cmd_upper == "PING" {
-                                                                                // PONG gönder
-                                                                                let pong = RespValue::SimpleString("PONG".to_string());
-                                                                                if let Err(e) = stream.write_all(pong.serialize().as_bytes()).await {
-                                                                                     error!("Failed to send PONG: {}", e);
-                                                                                     break;
-                                                                                }
-                                                                            }
-                                                                        }
-                                                                    }
-                                                                }
-                                                            }
-                                                            Ok(None) => {
-                                                                // Veri eksik, devam et
-                                                            }
-                                                            Err(e) => {
-                                                                error!("Failed to parse request in subscribe mode: {}", e);
-                                                                break;
-                                                            }
-                                                        }
-                                                    }
-                                                    Err(e) => {
-                                                        error!("Failed to read from socket in subscribe mode: {}", e);
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                        }
+                                    handle.set_blocked(true);
+                                    let reply = wait_blocked(
+                                        &mut stream,
+                                        client,
+                                        scheduler,
+                                        blocked,
+                                        &mut decoder,
+                                    )
+                                    .await;
+                                    handle.set_blocked(false);
+                                    handle.touch();
+                                    match reply {
+                                        Some(reply) => reply.serialize_into(&mut output),
+                                        None => return,
                                     }
-                                    // Döngüden çıkınca fonksiyon bitiyor ve bağlantı kapanıyor.
-                                    // Normalde UNSUBSCRIBE sonrası normal moda dönmek gerekir (recursive call veya loop yapısı değişikliği ile).
-                                    return;
+                                    turn = scheduler.turn().await;
+                                    continue;
                                 }
-                            }
+                                ExecutionResult::Subscribe(mut subscriber, replies) => {
+                                    // Abonelik süresince sırayı tutma
+                                    drop(turn);
+
+                                    // Abonelikten önceki cevapları gönder
+                                    server_info.add_bytes_sent(output.len() as u64);
+                                    if write_with_timeout(&mut stream, &output, write_timeout)
+                                        .await
+                                        .is_err()
+                                    {
+                                        return;
+                                    }
+                                    output.clear();
+
+                                    handle.set_subscribed(true);
+                                    let end = subscribe_loop(
+                                        &mut stream,
+                                        &mut subscriber,
+                                        replies,
+                                        &mut decoder,
+                                        output_limits.pubsub,
+                                        &server_info,
+                                        &rate_limit,
+                                    )
+                                    .await;
+                                    // Bağlantı kapansa da kanallar serbest kalsın
+                                    subscriber.clear().await;
+                                    handle.set_subscribed(false);
+                                    handle.touch();
 
-                            // İşlenen kısmı buffer'dan sil (drain)
-                            buffer.drain(0..len);
+                                    match end {
+                                        // RESET: bağlantı durumunu sıfırla ve normal moda dön
+                                        SubscriptionEnd::Reset => client.reset(),
+                                        // Son abonelik de bırakıldı: normal moda dön
+                                        SubscriptionEnd::Unsubscribed => {}
+                                        SubscriptionEnd::Close => return,
+                                    }
+                                    turn = scheduler.turn().await;
+                                    continue;
+                                }
+                            }
                         }
                         Ok(None) => {
                             // Veri eksik, daha fazla veri bekle
@@ -181,20 +465,34 @@ pub async fn handle_client(mut stream: TcpStream, client: &mut Interpreter) {
                         }
                         Err(e) => {
                             error!("Failed to parse request: {}", e);
-                            // Hatalı veriyi temizle veya bağlantıyı kapat
+                            // Hata cevabını (ve öncekileri) gönder, bağlantıyı kapat
+                            drop(turn);
+                            let _ = stream.write_all(&output).await;
+                            close_with_error(&mut stream, &format!("ERR {}", e)).await;
                             return;
                         }
                     }
                 }
 
+                // Cevapları yazarken sırayı tutma
+                drop(turn);
+
                 // Pipelining: Tüm cevapları birlikte gönder
-                if !responses.is_empty() {
-                    for response in responses {
-                        let response_bytes = response.serialize();
-                        if let Err(e) = stream.write_all(response_bytes.as_bytes()).await {
-                            error!("Failed to send pipelined response: {}", e);
-                            return;
-                        }
+                if !output.is_empty() {
+                    // Çok büyük cevap yığınları sınırı aşarsa bağlantıyı kapat
+                    if let Err(reason) = output_tracker.check(output.len(), Instant::now()) {
+                        close_slow_client(&mut stream, "normal", output.len(), reason).await;
+                        return;
+                    }
+                    server_info.add_bytes_sent(output.len() as u64);
+                    if let Err(e) = write_with_timeout(&mut stream, &output, write_timeout).await {
+                        error!("Failed to send pipelined response: {}", e);
+                        return;
+                    }
+                    output.clear();
+                    // Büyük bir cevabın belleğini bağlantı boyunca tutma
+                    if output.capacity() > OUTPUT_BUFFER_KEEP {
+                        output = BytesMut::new();
                     }
                 }
             }
@@ -0,0 +1,117 @@
+//! Client listeners.
+//!
+//! The server listens on every address in `server.bind_address`, so one
+//! process can accept on e.g. `0.0.0.0` and `::1` at once. IPv6 listeners
+//! are IPv6-only, which lets `0.0.0.0` and `::` share a port. With
+//! `server.reuse_port` the listeners set SO_REUSEPORT so several server
+//! processes can split the connections to one port. Accepted connections
+//! get the configured TCP_NODELAY and keepalive options.
+
+use crate::config::ServerConfig;
+use socket2::{SockRef, TcpKeepalive};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpListener, TcpSocket, TcpStream};
+use tokio::sync::mpsc;
+
+/// Pending connections queued by the kernel per listener
+const BACKLOG: u32 = 1024;
+
+/// Bind a listener on every address in `addrs` (`host:port`). Hosts that
+/// resolve to several addresses get a listener on each.
+pub async fn bind_all(addrs: &[String], server: &ServerConfig) -> io::Result<Vec<TcpListener>> {
+    let mut listeners = Vec::new();
+    for addr in addrs {
+        for resolved in lookup_host(addr.as_str()).await? {
+            let listener = bind(resolved, server.reuse_port).map_err(|e| {
+                io::Error::new(e.kind(), format!("cannot listen on {}: {}", resolved, e))
+            })?;
+            listeners.push(listener);
+        }
+    }
+    if listeners.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "no address to listen on",
+        ));
+    }
+    Ok(listeners)
+}
+
+fn bind(addr: SocketAddr, reuse_port: bool) -> io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        let socket = TcpSocket::new_v6()?;
+        SockRef::from(&socket).set_only_v6(true)?;
+        socket
+    };
+    // Same as TcpListener::bind, so a restart can reuse the port at once
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    #[cfg(unix)]
+    if reuse_port {
+        socket.set_reuseport(true)?;
+    }
+    #[cfg(not(unix))]
+    if reuse_port {
+        tracing::warn!("reuse_port is only supported on Unix; ignoring it for {}", addr);
+    }
+    socket.bind(addr)?;
+    socket.listen(BACKLOG)
+}
+
+/// Accept from every listener, passing connections into one queue
+pub fn accept_all(listeners: Vec<TcpListener>) -> mpsc::Receiver<io::Result<(TcpStream, SocketAddr)>> {
+    let (tx, rx) = mpsc::channel(BACKLOG as usize);
+    for listener in listeners {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let accepted = listener.accept().await;
+                if tx.send(accepted).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    rx
+}
+
+/// Apply the TCP options from `[server]` to an accepted connection
+pub fn configure_stream(stream: &TcpStream, server: &ServerConfig) -> io::Result<()> {
+    stream.set_nodelay(server.tcp_nodelay)?;
+    if server.tcp_keepalive {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(server.tcp_keepalive_secs.max(1)));
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_all_and_accept() {
+        let server = ServerConfig {
+            tcp_keepalive: true,
+            reuse_port: true,
+            ..ServerConfig::default()
+        };
+        let addrs = vec!["127.0.0.1:0".to_string(), "127.0.0.1:0".to_string()];
+        let listeners = bind_all(&addrs, &server).await.unwrap();
+        assert_eq!(listeners.len(), 2);
+        let ports: Vec<SocketAddr> = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+
+        let mut incoming = accept_all(listeners);
+        for addr in &ports {
+            let _client = TcpStream::connect(addr).await.unwrap();
+            let (stream, _) = incoming.recv().await.unwrap().unwrap();
+            configure_stream(&stream, &server).unwrap();
+            assert!(stream.nodelay().unwrap());
+            assert!(SockRef::from(&stream).keepalive().unwrap());
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Outbound connection used by MIGRATE.
+//!
+//! Sends RESTORE commands to another HexagonDB instance over RESP and
+//! collects the replies. The whole exchange, from connecting to the last
+//! reply, is bounded by the caller's timeout.
+
+use crate::network::resp::{RespHandler, RespValue};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Connect to `host:port`, send `commands` and return one reply per
+/// command, all within `timeout_ms`
+pub async fn transfer(host: &str, port: u16, commands: &[Vec<String>], timeout_ms: u64) -> Result<Vec<RespValue>, String> {
+    let limit = Duration::from_millis(timeout_ms.max(1));
+    let exchange = async { MigrateTarget::connect(host, port).await?.send_all(commands).await };
+    match timeout(limit, exchange).await {
+        Ok(result) => result,
+        Err(_) => Err("IOERR timeout exchanging data with target instance".to_string()),
+    }
+}
+
+/// Connection to a MIGRATE target
+pub struct MigrateTarget {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl MigrateTarget {
+    /// Connect to `host:port`
+    pub async fn connect(host: &str, port: u16) -> Result<Self, String> {
+        let stream = TcpStream::connect((host, port))
+            .await
+            .map_err(|e| format!("IOERR error connecting to target instance: {}", e))?;
+        Ok(MigrateTarget {
+            stream,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Send all commands in one pipeline and return one reply per command
+    pub async fn send_all(&mut self, commands: &[Vec<String>]) -> Result<Vec<RespValue>, String> {
+        let mut payload = String::new();
+        for cmd in commands {
+            let parts = cmd.iter().map(|s| RespValue::BulkString(Some(s.clone()))).collect();
+            payload.push_str(&RespValue::Array(Some(parts)).serialize());
+        }
+        self.stream
+            .write_all(payload.as_bytes())
+            .await
+            .map_err(|e| format!("IOERR error writing to target instance: {}", e))?;
+
+        let mut replies = Vec::with_capacity(commands.len());
+        while replies.len() < commands.len() {
+            replies.push(self.read_reply().await?);
+        }
+        Ok(replies)
+    }
+
+    async fn read_reply(&mut self) -> Result<RespValue, String> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some((value, used)) = RespHandler::parse_request(&self.buffer)? {
+                self.buffer.drain(..used);
+                return Ok(value);
+            }
+            let n = match self.stream.read(&mut chunk).await {
+                Ok(0) => return Err("IOERR target instance closed the connection".to_string()),
+                Ok(n) => n,
+                Err(e) => return Err(format!("IOERR error reading from target instance: {}", e)),
+            };
+            self.buffer.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
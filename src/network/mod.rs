@@ -2,5 +2,11 @@
 //!
 //! Handles client connections, RESP protocol parsing, and communication.
 
+pub mod clients;
 pub mod connection;
+pub mod listener;
+pub mod migrate;
+pub mod output_limit;
+pub mod proxy;
 pub mod resp;
+pub mod scheduler;
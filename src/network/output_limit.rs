@@ -0,0 +1,108 @@
+//! Client output buffer limits.
+//!
+//! A client that does not read its replies fast enough makes the server
+//! queue them in memory. Like Redis' `client-output-buffer-limit`, each
+//! client class has a hard limit, which disconnects the client as soon as
+//! it is exceeded, and a soft limit, which disconnects it once it has been
+//! exceeded continuously for a number of seconds. A limit of 0 disables it.
+
+use crate::config::OutputBufferLimitConfig;
+use std::time::{Duration, Instant};
+
+/// Limits of one client class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputLimit {
+    pub hard: usize,
+    pub soft: usize,
+    pub soft_duration: Duration,
+}
+
+impl From<&OutputBufferLimitConfig> for OutputLimit {
+    fn from(config: &OutputBufferLimitConfig) -> Self {
+        OutputLimit {
+            hard: config.hard_limit,
+            soft: config.soft_limit,
+            soft_duration: Duration::from_secs(config.soft_seconds),
+        }
+    }
+}
+
+/// Limits for every client class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputLimits {
+    pub normal: OutputLimit,
+    pub pubsub: OutputLimit,
+    pub replica: OutputLimit,
+}
+
+/// Why a client went over its limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Hard,
+    Soft,
+}
+
+/// Tracks one client's pending output against a limit
+#[derive(Debug)]
+pub struct OutputBufferTracker {
+    limit: OutputLimit,
+    over_soft_since: Option<Instant>,
+}
+
+impl OutputBufferTracker {
+    pub fn new(limit: OutputLimit) -> Self {
+        OutputBufferTracker {
+            limit,
+            over_soft_since: None,
+        }
+    }
+
+    /// Record the bytes currently waiting to be written. Returns an error
+    /// when the client should be disconnected.
+    pub fn check(&mut self, pending: usize, now: Instant) -> Result<(), LimitExceeded> {
+        if self.limit.hard > 0 && pending > self.limit.hard {
+            return Err(LimitExceeded::Hard);
+        }
+        if self.limit.soft == 0 || pending <= self.limit.soft {
+            self.over_soft_since = None;
+            return Ok(());
+        }
+        let since = *self.over_soft_since.get_or_insert(now);
+        if now.duration_since(since) >= self.limit.soft_duration {
+            return Err(LimitExceeded::Soft);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_buffer_tracker() {
+        let limit = OutputLimit {
+            hard: 100,
+            soft: 50,
+            soft_duration: Duration::from_secs(10),
+        };
+        let mut tracker = OutputBufferTracker::new(limit);
+        let start = Instant::now();
+        assert_eq!(tracker.check(40, start), Ok(()));
+        assert_eq!(tracker.check(101, start), Err(LimitExceeded::Hard));
+
+        // Over the soft limit, but not for long enough
+        assert_eq!(tracker.check(60, start), Ok(()));
+        assert_eq!(tracker.check(60, start + Duration::from_secs(5)), Ok(()));
+        // Dropping below resets the timer
+        assert_eq!(tracker.check(10, start + Duration::from_secs(6)), Ok(()));
+        assert_eq!(tracker.check(60, start + Duration::from_secs(11)), Ok(()));
+        assert_eq!(
+            tracker.check(60, start + Duration::from_secs(21)),
+            Err(LimitExceeded::Soft)
+        );
+
+        let mut unlimited = OutputBufferTracker::new(OutputLimit::default());
+        assert_eq!(unlimited.check(usize::MAX, start), Ok(()));
+    }
+}
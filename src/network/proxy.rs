@@ -0,0 +1,130 @@
+//! HAProxy PROXY protocol (v1 and v2).
+//!
+//! Behind a load balancer every connection comes from the balancer's
+//! address. With `server.proxy_protocol` enabled, each connection must start
+//! with a PROXY header carrying the original client address, which is then
+//! used for IP rules, rate limiting and the client address in logs.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// v2 header signature
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+/// Longest valid v1 header, CRLF included
+const V1_MAX_LEN: usize = 107;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid PROXY header: {}", msg))
+}
+
+/// Read a PROXY header from the start of a connection, consuming exactly
+/// the header bytes. Returns the client address, or None when the header
+/// carries none (v1 `UNKNOWN`, v2 `LOCAL` or a non-IP family) and the
+/// peer address should be used.
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut start = [0u8; 6];
+    stream.read_exact(&mut start).await?;
+    if &start == b"PROXY " {
+        read_v1(stream).await
+    } else if start == V2_SIGNATURE[..6] {
+        read_v2(stream).await
+    } else {
+        Err(invalid("missing signature"))
+    }
+}
+
+/// `PROXY TCP4|TCP6|UNKNOWN src dst sport dport\r\n`, after "PROXY "
+async fn read_v1<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut line = Vec::new();
+    loop {
+        let byte = stream.read_u8().await?;
+        line.push(byte);
+        if line.ends_with(b"\r\n") {
+            break;
+        }
+        if line.len() + 6 >= V1_MAX_LEN {
+            return Err(invalid("v1 line too long"));
+        }
+    }
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("not ASCII"))?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["UNKNOWN", ..] => Ok(None),
+        [proto @ ("TCP4" | "TCP6"), src, _dst, sport, _dport] => {
+            let ip: IpAddr = src.parse().map_err(|_| invalid("bad source address"))?;
+            if ip.is_ipv4() != (*proto == "TCP4") {
+                return Err(invalid("address does not match protocol"));
+            }
+            let port: u16 = sport.parse().map_err(|_| invalid("bad source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("malformed v1 line")),
+    }
+}
+
+/// Binary header, after the first six signature bytes
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut head = [0u8; 10];
+    stream.read_exact(&mut head).await?;
+    if head[..6] != V2_SIGNATURE[6..] {
+        return Err(invalid("bad v2 signature"));
+    }
+    let (version_command, family) = (head[6], head[7]);
+    let len = u16::from_be_bytes([head[8], head[9]]) as usize;
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body).await?;
+
+    // LOCAL: health checks from the proxy itself
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+    match family >> 4 {
+        // AF_INET: src(4) dst(4) sport(2) dport(2)
+        1 if len >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            Ok(Some(SocketAddr::new(ip.into(), u16::from_be_bytes([body[8], body[9]]))))
+        }
+        // AF_INET6: src(16) dst(16) sport(2) dport(2)
+        2 if len >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&body[..16]);
+            let ip = Ipv6Addr::from(octets);
+            Ok(Some(SocketAddr::new(ip.into(), u16::from_be_bytes([body[32], body[33]]))))
+        }
+        1 | 2 => Err(invalid("address block too short")),
+        // AF_UNSPEC / AF_UNIX: no usable client address
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_proxy_headers() {
+        let mut v1: &[u8] = b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 6379\r\n*1\r\n";
+        assert_eq!(read_header(&mut v1).await.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(v1, b"*1\r\n");
+
+        let mut unknown: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_header(&mut unknown).await.unwrap(), None);
+
+        let mut v2 = V2_SIGNATURE.to_vec();
+        v2.extend_from_slice(&[0x21, 0x11, 0, 12, 192, 0, 2, 9, 10, 0, 0, 1, 0x1f, 0x90, 0x18, 0xeb, b'+']);
+        let mut reader: &[u8] = &v2;
+        assert_eq!(read_header(&mut reader).await.unwrap(), Some("192.0.2.9:8080".parse().unwrap()));
+        assert_eq!(reader, b"+");
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(read_header(&mut &local[..]).await.unwrap(), None);
+
+        assert!(read_header(&mut &b"*1\r\n$4\r\nPING\r\n"[..]).await.is_err());
+        assert!(read_header(&mut &b"PROXY TCP6 1.2.3.4 1.2.3.4 1 2\r\n"[..]).await.is_err());
+    }
+}
@@ -2,6 +2,9 @@
 //!
 //! Supports RESP2 and RESP3 protocols for Redis client compatibility.
 
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt::Write;
+
 /// RESP value types
 #[derive(Debug, Clone, PartialEq)]
 pub enum RespValue {
@@ -41,6 +44,30 @@ impl RespValue {
         }
     }
 
+    /// Append the RESP encoding to `out`, the way `serialize` would write
+    /// it but without building intermediate strings. Replies are written
+    /// this way straight into a connection's output buffer.
+    pub fn serialize_into(&self, out: &mut BytesMut) {
+        match self {
+            RespValue::SimpleString(s) => put_line(out, b'+', s),
+            RespValue::Error(msg) => put_line(out, b'-', msg),
+            RespValue::Integer(i) => put_header(out, b':', *i),
+            RespValue::BulkString(Some(s)) => {
+                put_header(out, b'$', s.len() as i64);
+                out.extend_from_slice(s.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            RespValue::BulkString(None) => out.extend_from_slice(b"$-1\r\n"),
+            RespValue::Array(Some(arr)) => {
+                put_header(out, b'*', arr.len() as i64);
+                for v in arr {
+                    v.serialize_into(out);
+                }
+            }
+            RespValue::Array(None) => out.extend_from_slice(b"*-1\r\n"),
+        }
+    }
+
     /// Create an OK response
     pub fn ok() -> Self {
         RespValue::SimpleString("OK".to_string())
@@ -77,6 +104,50 @@ impl RespValue {
     }
 }
 
+/// Write a type byte, a line of text and CRLF
+fn put_line(out: &mut BytesMut, kind: u8, line: &str) {
+    out.reserve(line.len() + 3);
+    out.put_u8(kind);
+    out.extend_from_slice(line.as_bytes());
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Write a type byte, a decimal number and CRLF
+fn put_header(out: &mut BytesMut, kind: u8, n: i64) {
+    out.put_u8(kind);
+    // Formatting into BytesMut cannot fail
+    let _ = write!(out, "{}", n);
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Size limits enforced while parsing requests, so a client cannot make the
+/// server allocate or buffer arbitrary amounts from a length prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Largest bulk string (`proto-max-bulk-len`)
+    pub max_bulk_len: usize,
+    /// Most elements in one array (multibulk)
+    pub max_multibulk_len: usize,
+    /// Longest inline command line
+    pub max_inline_len: usize,
+    /// Deepest array nesting
+    pub max_depth: usize,
+}
+
+/// Longest `$`/`*`/`:` header line (a sign, 19 digits and CRLF fit comfortably)
+const MAX_HEADER_LEN: usize = 32;
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: 1024 * 1024,
+            max_inline_len: 64 * 1024,
+            max_depth: 8,
+        }
+    }
+}
+
 /// RESP protocol handler for parsing requests
 pub struct RespHandler;
 
@@ -86,109 +157,207 @@ impl RespHandler {
         RespHandler
     }
 
-    /// Helper to read a line ending with CRLF
-    fn read_line(buffer: &[u8]) -> Option<(String, usize)> {
-        let mut i = 0;
-        while i < buffer.len().saturating_sub(1) {
-            if buffer[i] == b'\r' && buffer[i + 1] == b'\n' {
-                let line = String::from_utf8_lossy(&buffer[0..i]).to_string();
-                return Some((line, i + 2));
-            }
-            i += 1;
+    /// Helper to read a line ending with CRLF. Fails if no CRLF shows up
+    /// within `max_len` bytes.
+    fn read_line(buffer: &[u8], max_len: usize) -> Result<Option<(String, usize)>, String> {
+        let window = &buffer[..buffer.len().min(max_len + 2)];
+        match window.windows(2).position(|w| w == b"\r\n") {
+            Some(i) => Ok(Some((String::from_utf8_lossy(&buffer[..i]).to_string(), i + 2))),
+            None if buffer.len() > max_len + 1 => Err("line too long".to_string()),
+            None => Ok(None),
         }
-        None
     }
 
-    /// Helper to parse an integer from a line
-    fn parse_int(buffer: &[u8]) -> Option<(i64, usize)> {
-        if let Some((line, len)) = Self::read_line(buffer) {
-            if let Ok(val) = line.parse::<i64>() {
-                return Some((val, len));
-            }
+    /// Helper to parse an integer header line
+    fn parse_int(buffer: &[u8], what: &str) -> Result<Option<(i64, usize)>, String> {
+        let invalid = || format!("Protocol error: invalid {}", what);
+        match Self::read_line(buffer, MAX_HEADER_LEN).map_err(|_| invalid())? {
+            Some((line, len)) => line.parse::<i64>().map(|val| Some((val, len))).map_err(|_| invalid()),
+            None => Ok(None),
         }
-        None
     }
 
-    /// Parse a RESP request from buffer
+    /// Parse a RESP request from buffer with the default limits
     pub fn parse_request(buffer: &[u8]) -> Result<Option<(RespValue, usize)>, String> {
+        Self::parse_request_with_limits(buffer, &ParseLimits::default())
+    }
+
+    /// Parse a RESP request from buffer. `Ok(None)` means more data is
+    /// needed; an error means the stream cannot be parsed and the
+    /// connection should be closed.
+    pub fn parse_request_with_limits(
+        buffer: &[u8],
+        limits: &ParseLimits,
+    ) -> Result<Option<(RespValue, usize)>, String> {
+        match ParseState::default().resume(buffer, limits)? {
+            (Some(value), used) => Ok(Some((value, used))),
+            (None, _) => Ok(None),
+        }
+    }
+
+    /// Parse the element at the front of buffer. Arrays only have their
+    /// header parsed; their elements follow as separate elements.
+    fn parse_element(buffer: &[u8], limits: &ParseLimits, depth: usize) -> Result<Element, String> {
+        // Anything cut short needs at least one more byte
+        let more = Element::Incomplete(buffer.len() + 1);
         if buffer.is_empty() {
-            return Ok(None);
+            return Ok(more);
         }
 
         match buffer[0] {
-            b'+' => {
-                if let Some((line, len)) = Self::read_line(&buffer[1..]) {
-                    Ok(Some((RespValue::SimpleString(line), len + 1)))
-                } else {
-                    Ok(None) // Incomplete
+            b'+' | b'-' => {
+                let line = Self::read_line(&buffer[1..], limits.max_inline_len)
+                    .map_err(|_| "Protocol error: too big inline request".to_string())?;
+                Ok(match line {
+                    Some((line, len)) if buffer[0] == b'+' => Element::Value(RespValue::SimpleString(line), len + 1),
+                    Some((line, len)) => Element::Value(RespValue::Error(line), len + 1),
+                    None => more,
+                })
+            }
+            b':' => Ok(match Self::parse_int(&buffer[1..], "integer")? {
+                Some((val, len)) => Element::Value(RespValue::Integer(val), len + 1),
+                None => more,
+            }),
+            b'$' => {
+                let (len_val, len_bytes) = match Self::parse_int(&buffer[1..], "bulk length")? {
+                    Some(header) => header,
+                    None => return Ok(more),
+                };
+                let start = 1 + len_bytes;
+                if len_val == -1 {
+                    return Ok(Element::Value(RespValue::BulkString(None), start));
+                }
+                if len_val < 0 || len_val as u64 > limits.max_bulk_len as u64 {
+                    return Err("Protocol error: invalid bulk length".to_string());
                 }
+                let str_len = len_val as usize;
+                if buffer.len() < start + str_len + 2 {
+                    return Ok(Element::Incomplete(start + str_len + 2));
+                }
+                if &buffer[start + str_len..start + str_len + 2] != b"\r\n" {
+                    return Err("Protocol error: bulk string not terminated by CRLF".to_string());
+                }
+                let str_val = String::from_utf8_lossy(&buffer[start..start + str_len]).to_string();
+                Ok(Element::Value(RespValue::BulkString(Some(str_val)), start + str_len + 2))
             }
-            b'-' => {
-                if let Some((line, len)) = Self::read_line(&buffer[1..]) {
-                    Ok(Some((RespValue::Error(line), len + 1)))
-                } else {
-                    Ok(None)
+            b'*' => {
+                if depth >= limits.max_depth {
+                    return Err("Protocol error: too deeply nested request".to_string());
+                }
+                let (count, len_bytes) = match Self::parse_int(&buffer[1..], "multibulk length")? {
+                    Some(header) => header,
+                    None => return Ok(more),
+                };
+                if count == -1 {
+                    return Ok(Element::Value(RespValue::Array(None), 1 + len_bytes));
                 }
+                if count < 0 || count as u64 > limits.max_multibulk_len as u64 {
+                    return Err("Protocol error: invalid multibulk length".to_string());
+                }
+                Ok(Element::Array(count as usize, 1 + len_bytes))
             }
-            b':' => {
-                if let Some((val, len)) = Self::parse_int(&buffer[1..]) {
-                    Ok(Some((RespValue::Integer(val), len + 1)))
-                } else {
-                    Ok(None)
+            _ if depth == 0 => Ok(match Self::parse_inline(buffer, limits)? {
+                Some((value, len)) => Element::Value(value, len),
+                None => more,
+            }),
+            _ => Err(format!("Protocol error: expected '$', got '{}'", buffer[0] as char)),
+        }
+    }
+
+    /// Inline command, as sent by telnet, `nc` or redis-cli in inline mode:
+    /// space-separated arguments ending in LF or CRLF, with optional quoting
+    /// (`SET k "hello world"`). Blank lines are skipped.
+    fn parse_inline(buffer: &[u8], limits: &ParseLimits) -> Result<Option<(RespValue, usize)>, String> {
+        let mut start = 0;
+        loop {
+            let rest = &buffer[start..];
+            let window = &rest[..rest.len().min(limits.max_inline_len + 1)];
+            let end = match window.iter().position(|&b| b == b'\n') {
+                Some(end) => end,
+                None if rest.len() > limits.max_inline_len => {
+                    return Err("Protocol error: too big inline request".to_string())
                 }
+                None => return Ok(None),
+            };
+            let line = String::from_utf8_lossy(&rest[..end]);
+            let args = split_inline_args(line.trim_end_matches('\r'))
+                .ok_or_else(|| "Protocol error: unbalanced quotes in request".to_string())?;
+            start += end + 1;
+            if args.is_empty() {
+                continue;
             }
-            b'$' => {
-                if let Some((len_val, len_bytes)) = Self::parse_int(&buffer[1..]) {
-                    let start = 1 + len_bytes;
-                    if len_val == -1 {
-                        return Ok(Some((RespValue::BulkString(None), start)));
-                    }
-                    let str_len = len_val as usize;
-                    if buffer.len() >= start + str_len + 2 {
-                        let str_val = String::from_utf8_lossy(&buffer[start..start + str_len]).to_string();
-                        Ok(Some((RespValue::BulkString(Some(str_val)), start + str_len + 2)))
-                    } else {
-                        Ok(None) // Incomplete
+            let args = args.into_iter().map(|a| RespValue::BulkString(Some(a))).collect();
+            return Ok(Some((RespValue::Array(Some(args)), start)));
+        }
+    }
+}
+
+/// Split an inline command into arguments. Double-quoted arguments support
+/// `\n`, `\r`, `\t`, `\b`, `\a`, `\xHH` and backslash escapes;
+/// single-quoted ones only `\'`. Returns None on unbalanced quotes or a
+/// closing quote not followed by a space.
+pub fn split_inline_args(line: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let first = match chars.peek() {
+            Some(&c) => c,
+            None => return Some(args),
+        };
+        let mut arg = String::new();
+        match first {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            'n' => arg.push('\n'),
+                            'r' => arg.push('\r'),
+                            't' => arg.push('\t'),
+                            'b' => arg.push('\u{8}'),
+                            'a' => arg.push('\u{7}'),
+                            'x' => {
+                                let hex: String = [chars.next()?, chars.next()?].iter().collect();
+                                match u8::from_str_radix(&hex, 16) {
+                                    Ok(byte) => arg.push(byte as char),
+                                    Err(_) => {
+                                        arg.push('x');
+                                        arg.push_str(&hex);
+                                    }
+                                }
+                            }
+                            other => arg.push(other),
+                        },
+                        c => arg.push(c),
                     }
-                } else {
-                    Ok(None)
                 }
             }
-            b'*' => {
-                if let Some((count, len_bytes)) = Self::parse_int(&buffer[1..]) {
-                    let mut current_pos = 1 + len_bytes;
-                    if count == -1 {
-                        return Ok(Some((RespValue::Array(None), current_pos)));
-                    }
-                    
-                    let mut items = Vec::new();
-                    for _ in 0..count {
-                        if let Ok(Some((item, len))) = Self::parse_request(&buffer[current_pos..]) {
-                            items.push(item);
-                            current_pos += len;
-                        } else {
-                            return Ok(None); // Incomplete
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        '\\' if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            arg.push('\'');
                         }
+                        c => arg.push(c),
                     }
-                    Ok(Some((RespValue::Array(Some(items)), current_pos)))
-                } else {
-                    Ok(None)
                 }
             }
             _ => {
-                // Inline command (simple space-separated like "GET key")
-                // This is for backward compatibility and simple telnet usage
-                if let Some((line, len)) = Self::read_line(buffer) {
-                    let parts: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
-                    let args: Vec<RespValue> = parts.into_iter()
-                        .map(|s| RespValue::BulkString(Some(s)))
-                        .collect();
-                    Ok(Some((RespValue::Array(Some(args)), len)))
-                } else {
-                    Ok(None)
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.push(c);
                 }
             }
         }
+        // A closing quote must end the argument
+        if matches!(first, '"' | '\'') && chars.peek().is_some_and(|c| !c.is_whitespace()) {
+            return None;
+        }
+        args.push(arg);
     }
 }
 
@@ -198,6 +367,148 @@ impl Default for RespHandler {
     }
 }
 
+/// One element parsed from the front of a buffer
+enum Element {
+    /// A complete non-array value and the bytes it took
+    Value(RespValue, usize),
+    /// An array header: the element count and the bytes of the header
+    Array(usize, usize),
+    /// Nothing can be parsed before the buffer holds this many bytes
+    Incomplete(usize),
+}
+
+/// An array whose elements are still arriving
+#[derive(Debug)]
+struct PartialArray {
+    remaining: usize,
+    items: Vec<RespValue>,
+}
+
+/// Where parsing of a request stopped, so it can resume when more data
+/// arrives instead of starting over
+#[derive(Debug, Default)]
+struct ParseState {
+    /// Arrays being filled, outermost first
+    stack: Vec<PartialArray>,
+    /// Buffer length needed before the next element can be parsed
+    needed: usize,
+    /// Bytes of the request already consumed into `stack`
+    consumed: usize,
+}
+
+impl ParseState {
+    /// Parse elements from the front of buffer until a request is
+    /// complete or the buffer runs out. Returns the request, if complete,
+    /// and the bytes consumed from buffer either way.
+    fn resume(&mut self, buffer: &[u8], limits: &ParseLimits) -> Result<(Option<RespValue>, usize), String> {
+        let mut pos = 0;
+        loop {
+            if buffer.len() - pos < self.needed {
+                self.consumed += pos;
+                return Ok((None, pos));
+            }
+            let mut value = match RespHandler::parse_element(&buffer[pos..], limits, self.stack.len())? {
+                Element::Incomplete(needed) => {
+                    self.needed = needed;
+                    self.consumed += pos;
+                    return Ok((None, pos));
+                }
+                Element::Value(value, len) => {
+                    pos += len;
+                    value
+                }
+                Element::Array(count, len) => {
+                    pos += len;
+                    if count == 0 {
+                        RespValue::Array(Some(Vec::new()))
+                    } else {
+                        // Elements are only allocated as they arrive
+                        self.stack.push(PartialArray { remaining: count, items: Vec::new() });
+                        self.needed = 0;
+                        continue;
+                    }
+                }
+            };
+            self.needed = 0;
+
+            // A complete value fills the innermost array, which may complete it in turn
+            loop {
+                match self.stack.last_mut() {
+                    None => {
+                        self.consumed = 0;
+                        return Ok((Some(value), pos));
+                    }
+                    Some(array) => {
+                        array.items.push(value);
+                        array.remaining -= 1;
+                        if array.remaining > 0 {
+                            break;
+                        }
+                    }
+                }
+                let array = self.stack.pop().expect("checked above");
+                value = RespValue::Array(Some(array.items));
+            }
+        }
+    }
+}
+
+/// Socket reads are made with room for at least this many bytes
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Incremental RESP decoder for one connection. Socket data is read straight
+/// into its buffer, and each decoded request is split off the front in O(1)
+/// rather than by moving the rest of the buffer. The elements of a
+/// partially received array are kept between reads, so a large request or
+/// pipeline is parsed once instead of again from the start on every read.
+#[derive(Debug)]
+pub struct RespDecoder {
+    buffer: BytesMut,
+    limits: ParseLimits,
+    state: ParseState,
+}
+
+impl RespDecoder {
+    pub fn new(limits: ParseLimits) -> Self {
+        RespDecoder {
+            buffer: BytesMut::new(),
+            limits,
+            state: ParseState::default(),
+        }
+    }
+
+    /// Buffer to read socket data into, with room for at least `READ_CHUNK` bytes
+    pub fn read_buf(&mut self) -> &mut BytesMut {
+        self.buffer.reserve(READ_CHUNK);
+        &mut self.buffer
+    }
+
+    /// Append received bytes
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Whether no received bytes are waiting to be parsed
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Bytes received for requests not yet decoded, including those of
+    /// array elements already parsed
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() + self.state.consumed
+    }
+
+    /// Decode the next request. `Ok(None)` means more data is needed; an
+    /// error means the stream cannot be parsed and the connection should
+    /// be closed.
+    pub fn decode(&mut self) -> Result<Option<RespValue>, String> {
+        let (value, used) = self.state.resume(&self.buffer, &self.limits)?;
+        self.buffer.advance(used);
+        Ok(value)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -241,6 +552,31 @@ mod tests {
         assert_eq!(null_arr.serialize(), "*-1\r\n");
     }
 
+    #[test]
+    fn test_serialize_into_matches_serialize() {
+        let values = [
+            RespValue::SimpleString("OK".to_string()),
+            RespValue::Error("ERR boom".to_string()),
+            RespValue::Integer(i64::MIN),
+            RespValue::Integer(0),
+            RespValue::BulkString(Some("çok güzel".to_string())),
+            RespValue::BulkString(Some(String::new())),
+            RespValue::BulkString(None),
+            RespValue::Array(None),
+            RespValue::Array(Some(vec![
+                RespValue::Integer(42),
+                RespValue::Array(Some(vec![RespValue::BulkString(None)])),
+                RespValue::Array(Some(Vec::new())),
+            ])),
+        ];
+        let mut out = BytesMut::new();
+        for value in &values {
+            let start = out.len();
+            value.serialize_into(&mut out);
+            assert_eq!(&out[start..], value.serialize().as_bytes());
+        }
+    }
+
     #[test]
     fn test_parse_array() {
         let data = b"*2\r\n$5\r\nhello\r\n$5\r\nworld\r\n";
@@ -273,4 +609,139 @@ mod tests {
             _ => panic!("Expected Array"),
         }
     }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = ParseLimits {
+            max_bulk_len: 10,
+            max_multibulk_len: 3,
+            max_inline_len: 16,
+            max_depth: 2,
+        };
+        let parse = |data: &[u8]| RespHandler::parse_request_with_limits(data, &limits);
+
+        assert!(parse(b"$11\r\n").unwrap_err().contains("invalid bulk length"));
+        assert!(parse(b"$-5\r\n").unwrap_err().contains("invalid bulk length"));
+        assert!(parse(b"$99999999999999999999\r\n").is_err());
+        assert!(parse(b"$abc\r\n").is_err());
+        assert!(parse(b"*4\r\n").unwrap_err().contains("invalid multibulk length"));
+        assert!(parse(b"*1\r\n*1\r\n*1\r\n$1\r\na\r\n").unwrap_err().contains("nested"));
+        assert!(parse(&[b'x'; 20]).unwrap_err().contains("inline"));
+        assert!(parse(b"$3\r\nabcde\r\n").unwrap_err().contains("CRLF"));
+        // An unterminated length line must not be buffered forever
+        assert!(parse(&[b'$'; 40]).is_err());
+
+        // Within limits, partial input still waits for more data
+        assert_eq!(parse(b"$10\r\nabc").unwrap(), None);
+        assert_eq!(parse(b"*3\r\n$1\r\na\r\n").unwrap(), None);
+        assert_eq!(parse(&[b'x'; 16]).unwrap(), None);
+        assert!(parse(b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_decoder_resumes_across_reads() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0xdec0de);
+        let stream: &[u8] = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$10\r\n0123456789\r\n\
+            PING\r\n*1\r\n*2\r\n:1\r\n*0\r\n*2\r\n$3\r\nGET\r\n$-1\r\n";
+        // Requests and the stream offset where each ends
+        let mut expected = Vec::new();
+        let mut ends = vec![0];
+        while let Some((value, used)) = RespHandler::parse_request(&stream[ends[expected.len()]..]).unwrap() {
+            expected.push(value);
+            ends.push(ends[expected.len() - 1] + used);
+        }
+        assert_eq!(expected.len(), 4);
+
+        for _ in 0..200 {
+            let mut decoder = RespDecoder::new(ParseLimits::default());
+            let mut decoded = Vec::new();
+            let mut pos = 0;
+            while pos < stream.len() {
+                let end = (pos + rng.gen_range(1..8)).min(stream.len());
+                decoder.extend(&stream[pos..end]);
+                pos = end;
+                while let Some(value) = decoder.decode().unwrap() {
+                    decoded.push(value);
+                }
+                // Parsed elements of an unfinished request still count as buffered
+                assert_eq!(decoder.buffered(), pos - ends[decoded.len()]);
+            }
+            assert_eq!(decoded, expected);
+            assert!(decoder.is_empty());
+        }
+
+        let mut decoder = RespDecoder::new(ParseLimits { max_depth: 1, ..ParseLimits::default() });
+        decoder.extend(b"*1\r\n");
+        assert_eq!(decoder.decode(), Ok(None));
+        decoder.extend(b"*1\r\n:1\r\n");
+        assert!(decoder.decode().unwrap_err().contains("nested"));
+    }
+
+    /// Random and mutated input must never panic, and a parsed value never
+    /// claims more bytes than were given
+    #[test]
+    fn test_parse_fuzz() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+        let seeds: [&[u8]; 5] = [
+            b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+            b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$-1\r\n",
+            b"+OK\r\n:42\r\n-ERR x\r\n",
+            b"*1\r\n*2\r\n:1\r\n:2\r\n",
+            b"PING hello\r\n",
+        ];
+        let alphabet = b"*$:+-\r\n0123456789-abc ";
+        let limits = ParseLimits::default();
+        for _ in 0..20_000 {
+            let mut data = seeds[rng.gen_range(0..seeds.len())].to_vec();
+            for _ in 0..rng.gen_range(0..6) {
+                let pos = rng.gen_range(0..=data.len());
+                match rng.gen_range(0..3) {
+                    0 if pos < data.len() => data[pos] = alphabet[rng.gen_range(0..alphabet.len())],
+                    1 => data.insert(pos, rng.gen()),
+                    _ => data.truncate(pos),
+                }
+            }
+            if let Ok(Some((_, used))) = RespHandler::parse_request_with_limits(&data, &limits) {
+                assert!(used <= data.len(), "{:?}", data);
+            }
+        }
+        for _ in 0..5_000 {
+            let data: Vec<u8> = (0..rng.gen_range(0..64)).map(|_| rng.gen()).collect();
+            let _ = RespHandler::parse_request(&data);
+        }
+    }
+
+    #[test]
+    fn test_parse_inline_commands() {
+        // nc / echo send a bare LF
+        let (val, len) = RespHandler::parse_request(b"PING\n").unwrap().unwrap();
+        assert_eq!((val, len), (RespValue::Array(Some(vec![RespValue::BulkString(Some("PING".to_string()))])), 5));
+
+        // Blank lines are skipped, pipelined lines are parsed one at a time
+        let data = b"\r\n\nSET k \"hello world\"\r\nGET k\r\n";
+        let (val, len) = RespHandler::parse_request(data).unwrap().unwrap();
+        assert_eq!(&data[len..], b"GET k\r\n");
+        let args: Vec<String> = match val {
+            RespValue::Array(Some(items)) => items
+                .into_iter()
+                .map(|i| match i {
+                    RespValue::BulkString(Some(s)) => s,
+                    _ => panic!("Expected bulk string"),
+                })
+                .collect(),
+            _ => panic!("Expected Array"),
+        };
+        assert_eq!(args, vec!["SET", "k", "hello world"]);
+        assert_eq!(RespHandler::parse_request(b"\r\n").unwrap(), None);
+
+        assert_eq!(
+            split_inline_args(r#"a "b\tc\x41" 'it\'s' """#).unwrap(),
+            vec!["a", "b\tcA", "it's", ""]
+        );
+        assert!(split_inline_args(r#"SET k "open"#).is_none());
+        assert!(split_inline_args(r#"SET "k"x v"#).is_none());
+        assert!(RespHandler::parse_request(b"SET k \"x\n").unwrap_err().contains("unbalanced"));
+    }
 }
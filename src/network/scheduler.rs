@@ -0,0 +1,153 @@
+//! Round-robin command scheduling.
+//!
+//! With a quantum set, connections take turns executing decoded commands.
+//! A turn runs at most `quantum` commands, after which the connection goes
+//! to the back of the queue. A client streaming a huge pipeline therefore
+//! cannot monopolize the database lock while interactive clients wait.
+//!
+//! Only one connection executes at a time while turns are taken, which
+//! costs throughput when clients do not contend, so it is off by default
+//! (`server.command_quantum = 0`) and connections run concurrently.
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Hands out execution turns to connections in FIFO order
+pub struct CommandScheduler {
+    /// Commands a connection may run per turn (0 = scheduling disabled)
+    quantum: usize,
+    /// Single permit; tokio's semaphore queues waiters fairly
+    turn: Semaphore,
+}
+
+/// An execution turn held by one connection
+pub struct Turn<'a> {
+    _permit: Option<SemaphorePermit<'a>>,
+    /// Commands left in this turn (None = unlimited)
+    remaining: Option<usize>,
+}
+
+impl CommandScheduler {
+    /// Create a scheduler. A quantum of 0 disables turn-taking.
+    pub fn new(quantum: usize) -> Self {
+        CommandScheduler {
+            quantum,
+            turn: Semaphore::new(1),
+        }
+    }
+
+    /// Commands a connection may run per turn
+    pub fn quantum(&self) -> usize {
+        self.quantum
+    }
+
+    /// Wait for this connection's next turn
+    pub async fn turn(&self) -> Turn<'_> {
+        if self.quantum == 0 {
+            return Turn {
+                _permit: None,
+                remaining: None,
+            };
+        }
+        let permit = self
+            .turn
+            .acquire()
+            .await
+            .expect("scheduler semaphore is never closed");
+        Turn {
+            _permit: Some(permit),
+            remaining: Some(self.quantum),
+        }
+    }
+}
+
+impl Default for CommandScheduler {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Turn<'_> {
+    /// Account for one command. Returns false once the quantum is used up and
+    /// the connection should yield to the next one in line.
+    pub fn consume(&mut self) -> bool {
+        match self.remaining.as_mut() {
+            None => true,
+            Some(0) => false,
+            Some(n) => {
+                *n -= 1;
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_quantum_is_enforced() {
+        let scheduler = CommandScheduler::new(2);
+        let mut turn = scheduler.turn().await;
+        assert!(turn.consume());
+        assert!(turn.consume());
+        assert!(!turn.consume());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_scheduler_is_unlimited() {
+        let scheduler = CommandScheduler::new(0);
+        let mut first = scheduler.turn().await;
+        let _second = scheduler.turn().await;
+        assert!((0..1000).all(|_| first.consume()));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_default_lets_clients_run_concurrently() {
+        let scheduler = Arc::new(CommandScheduler::new(crate::config::Config::default().server.command_quantum));
+        // Each client holds its turn until both are executing at once
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let clients: Vec<_> = (0..2)
+            .map(|_| {
+                let scheduler = Arc::clone(&scheduler);
+                let barrier = Arc::clone(&barrier);
+                tokio::spawn(async move {
+                    let mut turn = scheduler.turn().await;
+                    assert!(turn.consume());
+                    barrier.wait().await;
+                })
+            })
+            .collect();
+        for client in clients {
+            tokio::time::timeout(std::time::Duration::from_secs(5), client)
+                .await
+                .expect("clients waited on each other")
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_turns_are_handed_out_in_order() {
+        let scheduler = Arc::new(CommandScheduler::new(1));
+        let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        let first = scheduler.turn().await;
+        let mut handles = Vec::new();
+        for id in 0..3 {
+            let scheduler = Arc::clone(&scheduler);
+            let order = Arc::clone(&order);
+            handles.push(tokio::spawn(async move {
+                let _turn = scheduler.turn().await;
+                order.lock().push(id);
+            }));
+            // Make sure each task is queued before the next one
+            tokio::task::yield_now().await;
+        }
+        drop(first);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*order.lock(), vec![0, 1, 2]);
+    }
+}
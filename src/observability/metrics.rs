@@ -1,13 +1,17 @@
 use metrics_exporter_prometheus::PrometheusBuilder;
 use std::net::SocketAddr;
 
-pub fn init_metrics() {
+pub fn init_metrics(instance_name: &str, shard_id: u32) {
     // Initialize Prometheus recorder
     // By default, it listens on 0.0.0.0:9000, but we can customize it.
     // We'll use port 9100 which is standard for node_exporter/prometheus metrics.
     let addr: SocketAddr = "0.0.0.0:9100".parse().expect("Invalid metrics address");
 
-    let builder = PrometheusBuilder::new().with_http_listener(addr);
+    // Label every series so nodes can be told apart on shared dashboards
+    let builder = PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .add_global_label("instance_name", instance_name)
+        .add_global_label("shard_id", shard_id.to_string());
 
     builder
         .install()
@@ -19,4 +23,13 @@ pub const METRIC_CONNECTIONS_TOTAL: &str = "hexagondb_connections_total";
 pub const METRIC_COMMANDS_TOTAL: &str = "hexagondb_commands_total";
 pub const METRIC_COMMAND_LATENCY: &str = "hexagondb_command_latency_seconds";
 pub const METRIC_ACTIVE_CONNECTIONS: &str = "hexagondb_active_connections";
+pub const METRIC_PUBSUB_DROPPED_MESSAGES: &str = "hexagondb_pubsub_dropped_messages_total";
+pub const METRIC_OUTPUT_LIMIT_DISCONNECTS: &str = "hexagondb_output_buffer_disconnects_total";
 pub const METRIC_KEYS_TOTAL: &str = "hexagondb_keys_total";
+pub const METRIC_AOF_PENDING_FSYNC_BYTES: &str = "hexagondb_aof_pending_fsync_bytes";
+pub const METRIC_AOF_LAST_FSYNC_AGE: &str = "hexagondb_aof_last_fsync_age_seconds";
+pub const METRIC_RDB_LAST_SAVE_AGE: &str = "hexagondb_rdb_last_save_age_seconds";
+pub const METRIC_AOF_REWRITE_PROGRESS: &str = "hexagondb_aof_rewrite_progress_ratio";
+pub const METRIC_FUNCTION_CALLS: &str = "hexagondb_function_calls_total";
+pub const METRIC_FUNCTION_ERRORS: &str = "hexagondb_function_errors_total";
+pub const METRIC_FUNCTION_DURATION: &str = "hexagondb_function_duration_seconds";
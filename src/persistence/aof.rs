@@ -2,22 +2,138 @@
 //!
 //! Every write command is logged to the AOF file for durability.
 //! On restart, commands are replayed to restore state.
+//!
+//! Records apply to the logical database chosen by the last `SELECT n`
+//! record, database 0 at the start of a file. The keyspace only has
+//! database 0 for now, so everything is logged for it, and loading refuses
+//! a file with records for any other rather than fold them into it or
+//! drop them.
+//!
+//! A rewritten AOF may start with an RDB preamble (`aof_use_rdb_preamble`):
+//! a snapshot of the dataset in the RDB format, followed by the format
+//! marker and the records appended since. Loading restores the snapshot,
+//! then replays the records.
+//!
+//! The AOF is a base file (`database.aof`) plus numbered segments
+//! (`database.aof.000001`, ...). Appends go to the newest segment, and a new
+//! one is started once it reaches `aof_segment_size`. A compaction writes a
+//! new base from the dataset, naming the first segment that follows it, and
+//! archives or deletes the segments it replaces. Loading replays the base,
+//! then every segment from that one on, so a crash between writing the base
+//! and removing the old segments does not apply them twice.
 
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufReader, Read, Write};
-use std::path::Path;
+use std::io::{self, BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{error, info};
 
 use crate::db::DB;
 use crate::network::resp::RespValue;
+use crate::persistence::durability::DURABILITY;
+use crate::persistence::loading::LoadProgress;
+use crate::persistence::snapshot::{self, RdbReader, RDB_MAGIC_PREFIX};
+
+/// First record of AOF files written by this engine. Files without it come
+/// from the legacy server and are migrated on startup.
+pub const AOF_FORMAT_MARKER: &str = "HEXAGONDB-AOF";
+pub const AOF_FORMAT_VERSION: &str = "2";
+
+/// Records replayed per keyspace lock while loading
+const LOAD_BATCH: usize = 10_000;
+/// How often load progress is logged
+const LOAD_REPORT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Record in a base file naming the first segment that follows it
+pub const AOF_SEGMENTS_MARKER: &str = "HEXAGONDB-AOF-SEGMENTS";
+
+/// Default size at which a new segment is started
+pub const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Whether rewrites start the new file with an RDB preamble
+static USE_RDB_PREAMBLE: AtomicBool = AtomicBool::new(true);
+
+/// Segment size in bytes, 0 to never start a new segment
+static SEGMENT_SIZE: AtomicU64 = AtomicU64::new(DEFAULT_SEGMENT_SIZE);
+
+/// Write the dataset as an RDB preamble, rather than as commands, in AOF
+/// files rewritten from now on
+pub fn set_rdb_preamble(enabled: bool) {
+    USE_RDB_PREAMBLE.store(enabled, Ordering::Relaxed);
+}
+
+/// Start a new segment once the current one reaches `bytes` (0 = never)
+pub fn set_segment_size(bytes: u64) {
+    SEGMENT_SIZE.store(bytes, Ordering::Relaxed);
+}
+
+/// Path of segment `seq` of the AOF with base file `base`
+pub fn segment_path(base: &Path, seq: u64) -> PathBuf {
+    let mut name = base.as_os_str().to_owned();
+    name.push(format!(".{:06}", seq));
+    PathBuf::from(name)
+}
+
+/// Segments of the AOF with base file `base`, oldest first
+pub fn segment_paths(base: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let prefix = match base.file_name().and_then(|n| n.to_str()) {
+        Some(name) => format!("{}.", name),
+        None => return Ok(vec![]),
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut segments = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let seq = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|n| n.parse::<u64>().ok());
+        if let Some(seq) = seq {
+            segments.push((seq, segment_path(base, seq)));
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
 
 /// Append-Only File handler
 pub struct Aof {
-    file: File,
+    /// Current segment, None when the AOF is disabled
+    file: Option<File>,
+    /// Path of the base file
+    path: Option<PathBuf>,
+    /// Number of the current segment
+    segment: u64,
+    /// Bytes in the current segment
+    segment_bytes: u64,
+    /// Oldest segment on disk
+    oldest_segment: u64,
     fsync_policy: FsyncPolicy,
     last_fsync: std::time::Instant,
+    /// Whether the next record applies to database 0 without a SELECT;
+    /// false when unknown (the tail of an existing file)
+    db_selected: bool,
+}
+
+/// What loading an AOF file restored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AofLoad {
+    /// Keys read from the RDB preamble, None if the file has none
+    pub preamble_keys: Option<usize>,
+    /// Records replayed after the preamble
+    pub commands: usize,
 }
 
 /// Fsync policies
@@ -34,45 +150,156 @@ pub enum FsyncPolicy {
 impl Aof {
     /// Create a new AOF handler
     pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let file = OpenOptions::new()
+        let base = path.as_ref();
+        let mut base_file = OpenOptions::new()
             .create(true)
             .write(true)
             .append(true)
-            .open(path)?;
+            .open(base)?;
+        if base_file.metadata()?.len() == 0 {
+            base_file.write_all(format_marker().serialize().as_bytes())?;
+        }
+
+        // Keep appending to the newest segment
+        let segments = segment_paths(base)?;
+        let oldest = segments.first().map_or(1, |(seq, _)| *seq);
+        let newest = segments.last().map_or(1, |(seq, _)| *seq);
+        let file = open_segment(base, newest)?;
+        let segment_bytes = file.metadata()?.len();
+        DURABILITY.record_fsync();
 
         Ok(Aof {
-            file,
+            file: Some(file),
+            path: Some(base.to_path_buf()),
+            segment: newest,
+            segment_bytes,
+            oldest_segment: oldest,
             fsync_policy: FsyncPolicy::Everysec,
             last_fsync: std::time::Instant::now(),
+            db_selected: segment_bytes == 0,
         })
     }
 
+    /// Create a handler that logs nothing (`aof_enabled = false`)
+    pub fn disabled() -> Self {
+        Aof {
+            file: None,
+            path: None,
+            segment: 0,
+            segment_bytes: 0,
+            oldest_segment: 0,
+            fsync_policy: FsyncPolicy::No,
+            last_fsync: std::time::Instant::now(),
+            db_selected: false,
+        }
+    }
+
+    /// Whether commands are being logged
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Path of the base file, None when disabled
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Segments on disk with their numbers, oldest first
+    pub fn segments(&self) -> Vec<(u64, PathBuf)> {
+        match &self.path {
+            Some(base) => (self.oldest_segment..=self.segment)
+                .map(|seq| (seq, segment_path(base, seq)))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Segments no longer appended to
+    pub fn sealed_segments(&self) -> u64 {
+        self.segment - self.oldest_segment
+    }
+
+    /// Check whether an AOF file was written by this engine. Missing and
+    /// empty files count as current, and so do files with an RDB preamble.
+    pub fn is_current_format<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+        if !path.as_ref().exists() || std::fs::metadata(&path)?.len() == 0 {
+            return Ok(true);
+        }
+        // Only the header is needed, not the whole file
+        let header = format!("*2\r\n${}\r\n{}\r\n", AOF_FORMAT_MARKER.len(), AOF_FORMAT_MARKER);
+        let mut prefix = vec![0u8; header.len()];
+        let mut file = File::open(path)?;
+        match file.read_exact(&mut prefix) {
+            Ok(()) => Ok(prefix == header.as_bytes() || prefix.starts_with(RDB_MAGIC_PREFIX)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Current size of the AOF (base and segments) in bytes
+    pub fn size(&self) -> u64 {
+        self.path
+            .iter()
+            .cloned()
+            .chain(self.segments().into_iter().map(|(_, path)| path))
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
     /// Set fsync policy
     pub fn set_fsync_policy(&mut self, policy: FsyncPolicy) {
         self.fsync_policy = policy;
     }
 
-    /// Append a command to the AOF
+    /// Append a command for database 0 to the AOF
     pub fn append(&mut self, command: Vec<String>) -> io::Result<()> {
-        // Convert command to RESP format
-        let resp_args: Vec<RespValue> = command
-            .into_iter()
-            .map(|s| RespValue::BulkString(Some(s)))
-            .collect();
+        self.write_record(None, &command)
+    }
+
+    /// Append `name args...` for database 0, encoding the record straight
+    /// from the borrowed arguments instead of an owned copy of them
+    pub fn append_command(&mut self, name: &str, args: &[String]) -> io::Result<()> {
+        self.write_record(Some(name), args)
+    }
 
-        let resp = RespValue::Array(Some(resp_args));
-        let serialized = resp.serialize();
+    /// Write a record, preceded by a SELECT 0 record when the tail of the
+    /// file may be for another database
+    fn write_record(&mut self, name: Option<&str>, args: &[String]) -> io::Result<()> {
+        if self.file.is_none() || crate::failpoint::hit("aof-append") {
+            return Ok(());
+        }
 
-        self.file.write_all(serialized.as_bytes())?;
+        let mut serialized = String::new();
+        if !self.db_selected {
+            serialized.push_str(&select_record(0).serialize());
+        }
+        encode_record(&mut serialized, name, args);
+
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = file.write_all(serialized.as_bytes()) {
+                DURABILITY.record_write_error();
+                return Err(e);
+            }
+            DURABILITY.record_write(serialized.len());
+        }
+        self.db_selected = true;
+        self.segment_bytes += serialized.len() as u64;
+
+        let segment_size = SEGMENT_SIZE.load(Ordering::Relaxed);
+        if segment_size > 0 && self.segment_bytes >= segment_size {
+            self.start_segment(self.segment + 1)?;
+            return Ok(());
+        }
 
         // Apply fsync policy
         match self.fsync_policy {
             FsyncPolicy::Always => {
-                self.file.sync_all()?;
+                self.timed_sync()?;
             }
             FsyncPolicy::Everysec => {
                 if self.last_fsync.elapsed().as_secs() >= 1 {
-                    self.file.sync_all()?;
+                    self.timed_sync()?;
                     self.last_fsync = std::time::Instant::now();
                 }
             }
@@ -86,233 +313,839 @@ impl Aof {
 
     /// Force fsync
     pub fn fsync(&mut self) -> io::Result<()> {
-        self.file.sync_all()?;
+        self.timed_sync()?;
         self.last_fsync = std::time::Instant::now();
         Ok(())
     }
 
+    /// fsync the file, reporting slow syncs to the latency monitor
+    fn timed_sync(&mut self) -> io::Result<()> {
+        let file = match self.file.as_ref() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let start = std::time::Instant::now();
+        file.sync_all()?;
+        DURABILITY.record_fsync();
+        crate::latency::record(crate::latency::EVENT_AOF_FSYNC, start.elapsed());
+        Ok(())
+    }
+
+    /// Switch to a new AOF file at `path`, seeded with the full dataset.
+    /// The current file is synced first and left in place. The dataset is
+    /// written as an RDB preamble, or else every key as a RESTORE record,
+    /// so all types and TTLs survive a replay. The caller must hold the
+    /// keyspace lock so no write slips in between.
+    pub fn relocate_to<P: AsRef<Path>>(&mut self, path: P, db: &DB) -> io::Result<()> {
+        self.fsync()?;
+        let base = path.as_ref();
+        let first = segment_paths(base)?.last().map_or(1, |(seq, _)| seq + 1);
+        write_dataset(base, db, first)?;
+
+        self.path = Some(base.to_path_buf());
+        self.oldest_segment = first;
+        self.start_segment(first)?;
+        info!("AOF relocated to {}", base.display());
+        Ok(())
+    }
+
+    /// Sync the current segment and continue in segment `seq`
+    fn start_segment(&mut self, seq: u64) -> io::Result<()> {
+        let base = match &self.path {
+            Some(base) => base.clone(),
+            None => return Ok(()),
+        };
+        self.timed_sync()?;
+        self.file = Some(open_segment(&base, seq)?);
+        self.segment = seq;
+        self.segment_bytes = 0;
+        self.db_selected = true;
+        self.last_fsync = std::time::Instant::now();
+        Ok(())
+    }
+
+    /// Write a new base from the dataset and retire the segments it
+    /// replaces: moved into `archive_dir` if set, deleted otherwise. The
+    /// old base is copied into the archive too. Returns the number of
+    /// segments retired. The caller must hold the keyspace lock.
+    pub fn compact(&mut self, db: &DB, archive_dir: Option<&Path>) -> io::Result<u64> {
+        let base = match &self.path {
+            Some(base) => base.clone(),
+            None => return Ok(0),
+        };
+        let first = self.segment + 1;
+        self.start_segment(first)?;
+
+        if let Some(dir) = archive_dir {
+            std::fs::create_dir_all(dir)?;
+            if let Some(name) = base.file_name() {
+                let mut archived = name.to_owned();
+                archived.push(format!(".base.{:06}", self.oldest_segment));
+                std::fs::copy(&base, dir.join(archived))?;
+            }
+        }
+        write_dataset(&base, db, first)?;
+
+        let retired = retire_segments(&base, first, archive_dir)?;
+        self.oldest_segment = first;
+        info!("AOF compacted: new base written, {} segments retired", retired);
+        Ok(retired)
+    }
+
     /// Load and replay AOF file
-    pub async fn load<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<usize> {
-        use crate::db::{GenericOps, HashOps, ListOps, SetOps, StringOps, ZSetOps};
-        use crate::network::resp::RespHandler;
+    pub async fn load<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<AofLoad> {
+        Self::load_with_progress(path, db, &LoadProgress::new()).await
+    }
 
+    /// Load and replay AOF file, reporting progress. The keyspace lock is
+    /// released between batches so reads can be served while loading.
+    pub async fn load_with_progress<P: AsRef<Path>>(
+        path: P,
+        db: &Arc<RwLock<DB>>,
+        progress: &LoadProgress,
+    ) -> io::Result<AofLoad> {
         if !path.as_ref().exists() {
-            return Ok(0);
-        }
-
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
-
-        let mut current_pos = 0;
-        let mut count = 0;
-
-        while current_pos < buffer.len() {
-            match RespHandler::parse_request(&buffer[current_pos..]) {
-                Ok(Some((value, len))) => {
-                    current_pos += len;
-
-                    // Convert RESP value to arguments
-                    let args = match value {
-                        RespValue::Array(Some(items)) => items
-                            .into_iter()
-                            .filter_map(|item| match item {
-                                RespValue::BulkString(Some(s)) => Some(s),
-                                RespValue::SimpleString(s) => Some(s),
-                                _ => None,
-                            })
-                            .collect::<Vec<String>>(),
-                        _ => Vec::new(),
-                    };
-
-                    if !args.is_empty() {
-                        let cmd = args[0].to_uppercase();
-                        let mut db_guard = db.write().await;
-
-                        // Replay write commands
-                        match cmd.as_str() {
-                            "SET" if args.len() >= 3 => {
-                                db_guard.set(args[1].clone(), args[2].clone());
-                            }
-                            "DEL" if args.len() >= 2 => {
-                                db_guard.del(&args[1]);
-                            }
-                            "INCR" if args.len() >= 2 => {
-                                let _ = db_guard.incr(args[1].clone());
-                            }
-                            "DECR" if args.len() >= 2 => {
-                                let _ = db_guard.decr(args[1].clone());
-                            }
-                            "INCRBY" if args.len() >= 3 => {
-                                if let Ok(delta) = args[2].parse::<i64>() {
-                                    let _ = db_guard.incrby(args[1].clone(), delta);
-                                }
-                            }
-                            "EXPIRE" if args.len() >= 3 => {
-                                if let Ok(secs) = args[2].parse::<u64>() {
-                                    db_guard.expire(&args[1], secs);
-                                }
-                            }
-                            "PERSIST" if args.len() >= 2 => {
-                                db_guard.persist(&args[1]);
-                            }
-                            "LPUSH" | "RPUSH" if args.len() >= 3 => {
-                                let values = args[2..].to_vec();
-                                if cmd == "LPUSH" {
-                                    let _ = db_guard.lpush(args[1].clone(), values);
-                                } else {
-                                    let _ = db_guard.rpush(args[1].clone(), values);
-                                }
-                            }
-                            "LPOP" if args.len() >= 2 => {
-                                let _ = db_guard.lpop(args[1].clone());
-                            }
-                            "RPOP" if args.len() >= 2 => {
-                                let _ = db_guard.rpop(args[1].clone());
-                            }
-                            "HSET" if args.len() >= 4 => {
-                                let _ = db_guard.hset(
-                                    args[1].clone(),
-                                    args[2].clone(),
-                                    args[3].clone(),
-                                );
-                            }
-                            "HDEL" if args.len() >= 3 => {
-                                let _ = db_guard.hdel(args[1].clone(), args[2].clone());
-                            }
-                            "SADD" if args.len() >= 3 => {
-                                let members = args[2..].to_vec();
-                                let _ = db_guard.sadd(args[1].clone(), members);
-                            }
-                            "SREM" if args.len() >= 3 => {
-                                let _ = db_guard.srem(args[1].clone(), args[2].clone());
-                            }
-                            "ZADD" if args.len() >= 4 => {
-                                if let Ok(score) = args[2].parse::<f64>() {
-                                    let _ = db_guard.zadd(
-                                        args[1].clone(),
-                                        vec![(score, args[3].clone())],
-                                    );
-                                }
-                            }
-                            "ZREM" if args.len() >= 3 => {
-                                let _ = db_guard.zrem(args[1].clone(), vec![args[2].clone()]);
-                            }
-                            _ => {
-                                // Unknown or read-only command, skip
-                            }
-                        }
-
-                        count += 1;
+            return Ok(AofLoad::default());
+        }
+
+        let buffer = std::fs::read(&path)?;
+        let (preamble_keys, offset) = if buffer.starts_with(RDB_MAGIC_PREFIX) {
+            let (keys, len) = load_preamble(&buffer, db).await?;
+            info!("Loaded {} keys from the AOF's RDB preamble", keys);
+            (Some(keys), len)
+        } else {
+            (None, 0)
+        };
+
+        // The base, then the segments written after it
+        let mut files = vec![parse_records(&buffer[offset..])];
+        drop(buffer);
+        let first = first_segment(&files[0]).unwrap_or(0);
+        for (_, segment) in segment_paths(path.as_ref())?.into_iter().filter(|(seq, _)| *seq >= first) {
+            files.push(parse_records(&std::fs::read(segment)?));
+        }
+        if let Some(index) = files.iter().flatten().filter_map(|args| select_index(args)).find(|&i| i != 0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("AOF has records for database {}, but only database 0 exists", index),
+            ));
+        }
+        let total: usize = files.iter().map(Vec::len).sum();
+
+        progress.begin(total as u64);
+        let mut last_report = std::time::Instant::now();
+        for records in &files {
+            for batch in records.chunks(LOAD_BATCH) {
+                {
+                    let mut db_guard = db.write().await;
+                    for args in batch {
+                        replay_command(&mut db_guard, args);
                     }
                 }
-                Ok(None) => break,
-                Err(e) => {
-                    error!("Error parsing AOF: {}", e);
-                    break;
+                progress.advance(batch.len() as u64);
+                if last_report.elapsed() >= LOAD_REPORT_INTERVAL {
+                    info!(
+                        "Loading AOF: {:.1}% ({} commands, {:.0} commands/s)",
+                        progress.percent(),
+                        progress.loaded(),
+                        progress.rate()
+                    );
+                    last_report = std::time::Instant::now();
                 }
+                tokio::task::yield_now().await;
             }
         }
+        progress.finish();
 
-        info!("Loaded {} commands from AOF", count);
-        Ok(count)
+        info!("Loaded {} commands from AOF ({} segments)", total, files.len() - 1);
+        Ok(AofLoad {
+            preamble_keys,
+            commands: total,
+        })
     }
 
-    /// Rewrite AOF file (compact it)
+    /// Rewrite AOF file (compact it). Like `relocate_to`, the dataset is
+    /// written as an RDB preamble or as RESTORE records.
     pub async fn rewrite<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<()> {
-        use crate::db::types::DataType;
+        let base = path.as_ref();
+        let first = segment_paths(base)?.last().map_or(1, |(seq, _)| seq + 1);
+        write_dataset(base, &*db.read().await, first)?;
+        retire_segments(base, first, None)?;
+        info!("AOF rewrite completed");
+        Ok(())
+    }
+}
+
+/// Open segment `seq` for appending, creating it if needed
+fn open_segment(base: &Path, seq: u64) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(base, seq))
+}
+
+/// Move segments before `first` into `archive_dir`, or delete them
+fn retire_segments(base: &Path, first: u64, archive_dir: Option<&Path>) -> io::Result<u64> {
+    let mut retired = 0;
+    for (_, path) in segment_paths(base)?.into_iter().filter(|(seq, _)| *seq < first) {
+        match (archive_dir, path.file_name()) {
+            (Some(dir), Some(name)) => {
+                let target = dir.join(name);
+                // A rename cannot cross filesystems
+                if std::fs::rename(&path, &target).is_err() {
+                    std::fs::copy(&path, &target)?;
+                    std::fs::remove_file(&path)?;
+                }
+            }
+            _ => std::fs::remove_file(&path)?,
+        }
+        retired += 1;
+    }
+    Ok(retired)
+}
+
+/// First segment named by a base file's segments record, if it has one
+fn first_segment(records: &[Vec<String>]) -> Option<u64> {
+    records.iter().find_map(|args| match args.as_slice() {
+        [marker, seq] if marker == AOF_SEGMENTS_MARKER => seq.parse().ok(),
+        _ => None,
+    })
+}
+
+/// Write the dataset to `out` as a standalone AOF: the base file a
+/// rewrite would produce, with no segments after it (DUMPALL AOF)
+pub fn export<W: Write>(out: W, db: &DB) -> io::Result<W> {
+    write_records(out, db, 1, || {})
+}
 
-        let temp_path = format!("{}.tmp", path.as_ref().display());
-        let mut file = OpenOptions::new()
+/// Write the dataset to a new AOF file at `path`, replacing it atomically.
+/// Progress is reported as a rewrite.
+fn write_dataset(path: &Path, db: &DB, first_segment: u64) -> io::Result<()> {
+    DURABILITY.begin_rewrite(db.items.len());
+    let result = write_dataset_records(path, db, first_segment);
+    DURABILITY.finish_rewrite();
+    result
+}
+
+fn write_dataset_records(path: &Path, db: &DB, first_segment: u64) -> io::Result<()> {
+    let temp_path = format!("{}.tmp", path.display());
+    let file = io::BufWriter::new(
+        OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(&temp_path)?;
+            .open(&temp_path)?,
+    );
+    let file = write_records(file, db, first_segment, || DURABILITY.advance_rewrite())?;
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::rename(&temp_path, path)
+}
 
-        let db_guard = db.read().await;
+/// The records of a base file naming `first_segment`, with an RDB preamble
+/// if enabled. `on_entry` runs after each key is written.
+fn write_records<W: Write>(mut out: W, db: &DB, first_segment: u64, mut on_entry: impl FnMut()) -> io::Result<W> {
+    use crate::db::ops::generic::unix_millis_from_instant;
+    use crate::persistence::dump;
 
-        for (key, entry) in db_guard.items.iter() {
-            let commands = match &entry.value {
-                DataType::String(val) => {
-                    vec![vec!["SET".to_string(), key.clone(), val.clone()]]
-                }
-                DataType::List(list) => {
-                    if !list.is_empty() {
-                        let mut cmd = vec!["RPUSH".to_string(), key.clone()];
-                        cmd.extend(list.clone());
-                        vec![cmd]
-                    } else {
-                        vec![]
-                    }
-                }
-                DataType::Hash(hash) => {
-                    let mut cmds = vec![];
-                    for (field, value) in hash {
-                        cmds.push(vec![
-                            "HSET".to_string(),
-                            key.clone(),
-                            field.clone(),
-                            value.clone(),
-                        ]);
-                    }
-                    cmds
-                }
-                DataType::Set(set) => {
-                    if !set.is_empty() {
-                        let mut cmd = vec!["SADD".to_string(), key.clone()];
-                        cmd.extend(set.iter().cloned());
-                        vec![cmd]
-                    } else {
-                        vec![]
+    let now = std::time::Instant::now();
+    let mut tail = format_marker().serialize();
+    tail.push_str(&record(vec![AOF_SEGMENTS_MARKER.to_string(), first_segment.to_string()]).serialize());
+    if USE_RDB_PREAMBLE.load(Ordering::Relaxed) {
+        return snapshot::write_db(out, db, tail.as_bytes(), on_entry);
+    }
+
+    // Both the RDB encoding and RESTORE payloads keep hash field TTLs
+    out.write_all(tail.as_bytes())?;
+    for (key, entry) in db.items.iter().filter(|(_, entry)| entry.expires_at.is_none_or(|at| at > now)) {
+        on_entry();
+        let abs_ms = entry.expires_at.map(unix_millis_from_instant).unwrap_or(0);
+        let cmd = record(vec![
+            "RESTORE".to_string(),
+            key.clone(),
+            abs_ms.to_string(),
+            dump::dump(entry),
+            "REPLACE".to_string(),
+            "ABSTTL".to_string(),
+        ]);
+        out.write_all(cmd.serialize().as_bytes())?;
+    }
+    Ok(out)
+}
+
+/// A command as an AOF record
+fn record(args: Vec<String>) -> RespValue {
+    RespValue::Array(Some(args.into_iter().map(|s| RespValue::BulkString(Some(s))).collect()))
+}
+
+/// Header record identifying the current AOF format
+fn format_marker() -> RespValue {
+    RespValue::Array(Some(vec![
+        RespValue::BulkString(Some(AOF_FORMAT_MARKER.to_string())),
+        RespValue::BulkString(Some(AOF_FORMAT_VERSION.to_string())),
+    ]))
+}
+
+/// Encode `name args...` as a RESP array of bulk strings onto `out`
+fn encode_record(out: &mut String, name: Option<&str>, args: &[String]) {
+    use std::fmt::Write as _;
+
+    let _ = write!(out, "*{}\r\n", args.len() + name.is_some() as usize);
+    for part in name.into_iter().chain(args.iter().map(String::as_str)) {
+        let _ = write!(out, "${}\r\n", part.len());
+        out.push_str(part);
+        out.push_str("\r\n");
+    }
+}
+
+/// SELECT record switching the following records to database `db`
+fn select_record(db: usize) -> RespValue {
+    RespValue::Array(Some(vec![
+        RespValue::BulkString(Some("SELECT".to_string())),
+        RespValue::BulkString(Some(db.to_string())),
+    ]))
+}
+
+/// Database index of a SELECT record, None for any other record
+pub(crate) fn select_index(args: &[String]) -> Option<usize> {
+    match args {
+        [cmd, index] if cmd.eq_ignore_ascii_case("SELECT") => index.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parse every command record in an AOF, the base file and then the
+/// segments that follow it, skipping an RDB preamble. Parsing of each file
+/// stops at its first truncated or malformed record.
+pub(crate) fn read_records<P: AsRef<Path>>(path: P) -> io::Result<Vec<Vec<String>>> {
+    let mut records = read_file_records(path.as_ref())?;
+    let first = first_segment(&records).unwrap_or(0);
+    for (_, segment) in segment_paths(path.as_ref())?.into_iter().filter(|(seq, _)| *seq >= first) {
+        records.extend(read_file_records(&segment)?);
+    }
+    Ok(records)
+}
+
+/// Parse the command records of a single AOF file
+fn read_file_records(path: &Path) -> io::Result<Vec<Vec<String>>> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    let mut offset = 0;
+    if buffer.starts_with(RDB_MAGIC_PREFIX) {
+        let mut cursor = Cursor::new(&buffer[..]);
+        let mut preamble = RdbReader::new(&mut cursor)?;
+        while preamble.next_entry()?.is_some() {}
+        offset = cursor.position() as usize;
+    }
+    Ok(parse_records(&buffer[offset..]))
+}
+
+/// Load the RDB preamble at the start of `buffer` into `db`, a batch of
+/// keys per lock. Returns the number of keys and the preamble's length.
+async fn load_preamble(buffer: &[u8], db: &Arc<RwLock<DB>>) -> io::Result<(usize, usize)> {
+    let mut cursor = Cursor::new(buffer);
+    let mut reader = RdbReader::new(&mut cursor)?;
+    let mut keys = 0;
+    let mut done = false;
+    while !done {
+        {
+            let mut db_guard = db.write().await;
+            for _ in 0..LOAD_BATCH {
+                match reader.next_entry()? {
+                    Some(entry) => {
+                        snapshot::insert_entry(&mut db_guard, entry);
+                        keys += 1;
                     }
-                }
-                DataType::ZSet(zset) => {
-                    let mut cmds = vec![];
-                    for (member, score) in &zset.members {
-                        cmds.push(vec![
-                            "ZADD".to_string(),
-                            key.clone(),
-                            score.to_string(),
-                            member.clone(),
-                        ]);
+                    None => {
+                        done = true;
+                        break;
                     }
-                    cmds
                 }
-                _ => vec![],
-            };
-
-            for cmd in commands {
-                let resp_args: Vec<RespValue> = cmd
-                    .into_iter()
-                    .map(|s| RespValue::BulkString(Some(s)))
-                    .collect();
-                let resp = RespValue::Array(Some(resp_args));
-                file.write_all(resp.serialize().as_bytes())?;
-            }
-
-            // Handle expiration
-            if let Some(expires_at) = entry.expires_at {
-                let now = std::time::Instant::now();
-                if expires_at > now {
-                    let ttl = expires_at.duration_since(now).as_secs();
-                    let cmd = vec!["EXPIRE".to_string(), key.clone(), ttl.to_string()];
-                    let resp_args: Vec<RespValue> = cmd
+            }
+        }
+        tokio::task::yield_now().await;
+    }
+    Ok((keys, cursor.position() as usize))
+}
+
+/// Parse AOF records from `buffer`, up to the first truncated or malformed one
+fn parse_records(buffer: &[u8]) -> Vec<Vec<String>> {
+    use crate::network::resp::RespHandler;
+
+    let mut records = Vec::new();
+    let mut current_pos = 0;
+
+    while current_pos < buffer.len() {
+        match RespHandler::parse_request(&buffer[current_pos..]) {
+            Ok(Some((value, len))) => {
+                current_pos += len;
+
+                // Convert RESP value to arguments
+                let args = match value {
+                    RespValue::Array(Some(items)) => items
                         .into_iter()
-                        .map(|s| RespValue::BulkString(Some(s)))
-                        .collect();
-                    let resp = RespValue::Array(Some(resp_args));
-                    file.write_all(resp.serialize().as_bytes())?;
+                        .filter_map(|item| match item {
+                            RespValue::BulkString(Some(s)) => Some(s),
+                            RespValue::SimpleString(s) => Some(s),
+                            _ => None,
+                        })
+                        .collect::<Vec<String>>(),
+                    _ => Vec::new(),
+                };
+
+                if !args.is_empty() {
+                    records.push(args);
                 }
             }
+            Ok(None) => break,
+            Err(e) => {
+                error!("Error parsing AOF: {}", e);
+                break;
+            }
         }
+    }
 
-        file.sync_all()?;
+    records
+}
 
-        // Atomic rename
-        std::fs::rename(&temp_path, path)?;
+/// Apply one AOF record to the database. Read-only and unknown commands are skipped.
+pub(crate) fn replay_command(db_guard: &mut DB, args: &[String]) {
+    use crate::db::ops::bloom::BloomOptions;
+    use crate::db::ops::cuckoo::CuckooOptions;
+    use crate::db::ops::hash::FieldTtlArgs;
+    use crate::db::ops::string::CounterBounds;
+    use crate::db::ops::timeseries::{RangeQuery, SeriesOptions};
+    use crate::db::ops::vector::parse_vector;
+    use crate::db::types::CompactionRule;
+    use crate::db::{
+        BitmapOps, BloomOps, CuckooOps, GenericOps, GeoOps, HashOps, HyperLogLogOps, ListOps, QueueOps, SetOps,
+        StreamOps, StreamTrim, StringOps, TimeSeriesOps, VectorOps, ZSetOps,
+    };
 
-        info!("AOF rewrite completed");
-        Ok(())
+    let cmd = match args.first() {
+        Some(cmd) => cmd.to_uppercase(),
+        None => return,
+    };
+
+    match cmd.as_str() {
+        "SET" if args.len() >= 3 => {
+            db_guard.set(args[1].clone(), args[2].clone());
+        }
+        "DEL" if args.len() >= 2 => {
+            for key in &args[1..] {
+                db_guard.del(key);
+            }
+        }
+        "UNLINK" if args.len() >= 2 => {
+            db_guard.unlink(args[1..].iter().map(String::as_str).collect());
+        }
+        "UNDELETE" if args.len() >= 2 => {
+            // Only in older files: UNDELETE is now logged as a RESTORE
+            db_guard.undelete(&args[1]);
+        }
+        "FLUSHDB" | "FLUSHALL" => {
+            db_guard.flushdb();
+        }
+        "INCR" if args.len() >= 2 => {
+            let _ = db_guard.incr(&args[1]);
+        }
+        "DECR" if args.len() >= 2 => {
+            let _ = db_guard.decr(&args[1]);
+        }
+        "INCRBY" if args.len() >= 3 => {
+            if let (Ok(delta), Ok(bounds)) =
+                (args[2].parse::<i64>(), CounterBounds::parse(&args[3..]))
+            {
+                let _ = db_guard.incrby_bounded(&args[1], delta, bounds);
+            }
+        }
+        "DECRBY" if args.len() >= 3 => {
+            if let Ok(delta) = args[2].parse::<i64>() {
+                let _ = db_guard.decrby(&args[1], delta);
+            }
+        }
+        "INCRBYFLOAT" if args.len() >= 3 => {
+            if let Ok(delta) = args[2].parse::<f64>() {
+                let _ = db_guard.incrbyfloat(&args[1], delta);
+            }
+        }
+        "GETSET" if args.len() >= 3 => {
+            let _ = db_guard.getset(args[1].clone(), args[2].clone());
+        }
+        "APPEND" if args.len() >= 3 => {
+            let _ = db_guard.append(args[1].clone(), args[2].clone());
+        }
+        "EXPIRE" if args.len() >= 3 => {
+            if let Ok(secs) = args[2].parse::<u64>() {
+                db_guard.expire(&args[1], secs);
+            }
+        }
+        "PEXPIRE" if args.len() >= 3 => {
+            if let Ok(millis) = args[2].parse::<u64>() {
+                db_guard.pexpire(&args[1], millis);
+            }
+        }
+        "EXPIREAT" if args.len() >= 3 => {
+            if let Ok(timestamp) = args[2].parse::<u64>() {
+                db_guard.expireat(&args[1], timestamp);
+            }
+        }
+        "PEXPIREAT" if args.len() >= 3 => {
+            if let Ok(timestamp_ms) = args[2].parse::<u64>() {
+                db_guard.pexpireat(&args[1], timestamp_ms);
+            }
+        }
+        "PERSIST" if args.len() >= 2 => {
+            db_guard.persist(&args[1]);
+        }
+        "RESTORE" if args.len() >= 4 => {
+            // Logged as RESTORE key <unix-ms or 0> payload REPLACE ABSTTL
+            if let (Ok(timestamp_ms), Ok(mut entry)) = (args[2].parse::<u64>(), crate::persistence::dump::restore(&args[3])) {
+                entry.expires_at = match timestamp_ms {
+                    0 => None,
+                    ms => crate::db::ops::generic::instant_from_unix_millis(ms),
+                };
+                db_guard.restore(&args[1], entry, true);
+            }
+        }
+        "LPUSH" | "RPUSH" if args.len() >= 3 => {
+            let values = args[2..].to_vec();
+            if cmd == "LPUSH" {
+                let _ = db_guard.lpush(args[1].clone(), values);
+            } else {
+                let _ = db_guard.rpush(args[1].clone(), values);
+            }
+        }
+        "LPOP" | "RPOP" if args.len() >= 2 => {
+            let count = args.get(2).and_then(|c| c.parse().ok()).unwrap_or(1);
+            if cmd == "LPOP" {
+                let _ = db_guard.lpop_count(args[1].clone(), count);
+            } else {
+                let _ = db_guard.rpop_count(args[1].clone(), count);
+            }
+        }
+        "HSET" | "HMSET" if args.len() >= 4 => {
+            let pairs = args[2..]
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            let _ = db_guard.hset_multi(args[1].clone(), pairs);
+        }
+        "HDEL" if args.len() >= 3 => {
+            let _ = db_guard.hdel_multi(args[1].clone(), args[2..].to_vec());
+        }
+        "HSETNX" if args.len() >= 4 => {
+            let _ = db_guard.hsetnx(
+                args[1].clone(),
+                args[2].clone(),
+                args[3].clone(),
+            );
+        }
+        "HINCRBY" if args.len() >= 4 => {
+            if let Ok(delta) = args[3].parse::<i64>() {
+                let _ = db_guard.hincrby(args[1].clone(), args[2].clone(), delta);
+            }
+        }
+        "HINCRBYFLOAT" if args.len() >= 4 => {
+            if let Ok(delta) = args[3].parse::<f64>() {
+                let _ = db_guard.hincrbyfloat(args[1].clone(), args[2].clone(), delta);
+            }
+        }
+        "HEXPIRE" | "HPEXPIRE" | "HEXPIREAT" | "HPEXPIREAT" if args.len() >= 6 => {
+            // Logged as HPEXPIREAT; relative forms come from older files
+            if let (Ok(n), Ok(parsed)) =
+                (args[2].parse::<u64>(), FieldTtlArgs::parse(&args[3..], true))
+            {
+                let ms = if cmd.starts_with("HEXPIRE") { n.saturating_mul(1000) } else { n };
+                let at = if cmd.ends_with("AT") {
+                    crate::db::ops::generic::instant_from_unix_millis(ms)
+                } else {
+                    std::time::Instant::now().checked_add(std::time::Duration::from_millis(ms))
+                };
+                if let Some(at) = at {
+                    let _ = db_guard.hexpire(args[1].clone(), at, parsed.condition, parsed.fields);
+                }
+            }
+        }
+        "HPERSIST" if args.len() >= 5 => {
+            if let Ok(parsed) = FieldTtlArgs::parse(&args[2..], false) {
+                let _ = db_guard.hpersist(args[1].clone(), parsed.fields);
+            }
+        }
+        "PQ.PUSH" if args.len() >= 4 => {
+            let messages: Option<Vec<(i64, String)>> = args[2..]
+                .chunks_exact(2)
+                .map(|pair| pair[0].parse::<i64>().ok().map(|priority| (priority, pair[1].clone())))
+                .collect();
+            if let Some(messages) = messages {
+                let _ = db_guard.pq_push(args[1].clone(), messages);
+            }
+        }
+        "PQ.LEASE" if args.len() >= 4 => {
+            // Written for PQ.POP: PQ.LEASE key <unix-ms deadline> id [id ...]
+            if let Ok(deadline) = args[2].parse::<u64>() {
+                let ids: Vec<u64> = args[3..].iter().filter_map(|id| id.parse().ok()).collect();
+                let _ = db_guard.pq_lease(&args[1], &ids, deadline);
+            }
+        }
+        "PQ.ACK" if args.len() >= 3 => {
+            let ids: Vec<u64> = args[2..].iter().filter_map(|id| id.parse().ok()).collect();
+            let _ = db_guard.pq_ack(&args[1], &ids);
+        }
+        "TS.CREATE" if args.len() >= 2 => {
+            if let Ok(options) = SeriesOptions::parse(&args[2..]) {
+                let _ = db_guard.ts_create(args[1].clone(), options);
+            }
+        }
+        "TS.ADD" if args.len() >= 4 => {
+            // Written with the timestamp resolved, also for compacted samples
+            if let (Ok(timestamp), Ok(value), Ok(options)) =
+                (args[2].parse::<u64>(), args[3].parse::<f64>(), SeriesOptions::parse(&args[4..]))
+            {
+                let _ = db_guard.ts_add(args[1].clone(), timestamp, value, options);
+            }
+        }
+        "TS.CREATERULE" if args.len() >= 6 => {
+            if let Ok((RangeQuery { aggregation: Some((aggregation, bucket_ms)), .. }, _)) =
+                RangeQuery::parse(&args[3..])
+            {
+                let rule = CompactionRule { dest: args[2].clone(), aggregation, bucket_ms };
+                let _ = db_guard.ts_createrule(&args[1], rule);
+            }
+        }
+        "TS.DELETERULE" if args.len() >= 3 => {
+            let _ = db_guard.ts_deleterule(&args[1], &args[2]);
+        }
+        "BF.RESERVE" if args.len() >= 4 => {
+            if let Ok(options) = BloomOptions::parse(&args[2..]) {
+                let _ = db_guard.bf_reserve(args[1].clone(), options);
+            }
+        }
+        "BF.ADD" | "BF.MADD" if args.len() >= 3 => {
+            let _ = db_guard.bf_madd(args[1].clone(), &args[2..]);
+        }
+        "CF.RESERVE" if args.len() >= 3 => {
+            if let Ok(options) = CuckooOptions::parse(&args[2..]) {
+                let _ = db_guard.cf_reserve(args[1].clone(), options);
+            }
+        }
+        "CF.ADD" | "CF.ADDNX" if args.len() >= 3 => {
+            let _ = db_guard.cf_add(args[1].clone(), &args[2], cmd == "CF.ADDNX");
+        }
+        "CF.DEL" if args.len() >= 3 => {
+            let _ = db_guard.cf_del(&args[1], &args[2]);
+        }
+        "VECTOR.ADD" if args.len() >= 4 && args.len().is_multiple_of(2) => {
+            if let Ok(values) = parse_vector(&args[3]) {
+                let meta = args[4..].chunks_exact(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+                let _ = db_guard.vector_add(args[1].clone(), args[2].clone(), values, meta);
+            }
+        }
+        "VECTOR.DEL" if args.len() >= 3 => {
+            let _ = db_guard.vector_del(&args[1], &args[2..]);
+        }
+        "SADD" if args.len() >= 3 => {
+            let members = args[2..].to_vec();
+            let _ = db_guard.sadd(args[1].clone(), members);
+        }
+        "SREM" if args.len() >= 3 => {
+            let _ = db_guard.srem_multi(args[1].clone(), args[2..].to_vec());
+        }
+        "ZADD" if args.len() >= 4 => {
+            let members: Option<Vec<(f64, String)>> = args[2..]
+                .chunks_exact(2)
+                .map(|pair| pair[0].parse::<f64>().ok().map(|score| (score, pair[1].clone())))
+                .collect();
+            if let Some(members) = members {
+                let _ = db_guard.zadd(args[1].clone(), members);
+            }
+        }
+        "ZREM" if args.len() >= 3 => {
+            let _ = db_guard.zrem(args[1].clone(), args[2..].to_vec());
+        }
+        "ZDIFFSTORE" if args.len() >= 4 => {
+            if let Ok(numkeys) = args[2].parse::<usize>() {
+                let keys = args.iter().skip(3).take(numkeys).cloned().collect();
+                let _ = db_guard.zdiffstore(args[1].clone(), keys);
+            }
+        }
+        "PFADD" if args.len() >= 3 => {
+            let _ = db_guard.pfadd(args[1].clone(), args[2..].to_vec());
+        }
+        "SETBIT" if args.len() >= 4 => {
+            if let Ok(offset) = args[2].parse::<usize>() {
+                let value = args[3].parse::<u8>().unwrap_or(0) != 0;
+                let _ = db_guard.setbit(args[1].clone(), offset, value);
+            }
+        }
+        "XADD" if args.len() >= 5 => {
+            // The entry id is always explicit in the log
+            let fields = args[3..]
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            let _ = db_guard.xadd(args[1].clone(), Some(args[2].clone()), fields);
+        }
+        "XTRIM" if args.len() >= 4 => {
+            if let Ok((trim, _)) = StreamTrim::parse(&args[2..]) {
+                let _ = db_guard.xtrim(args[1].clone(), trim);
+            }
+        }
+        "GEOADD" if args.len() >= 5 => {
+            let locations = args[2..]
+                .chunks_exact(3)
+                .filter_map(|triple| match (triple[0].parse::<f64>(), triple[1].parse::<f64>()) {
+                    (Ok(lon), Ok(lat)) => Some((lon, lat, triple[2].clone())),
+                    _ => None,
+                })
+                .collect();
+            let _ = db_guard.geoadd(args[1].clone(), locations);
+        }
+        "RENAME" if args.len() >= 3 => {
+            let _ = db_guard.rename(&args[1], &args[2]);
+        }
+        _ => {
+            // Unknown or read-only command, skip
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::StringOps;
+
+    fn cmd(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_append_command_encodes_like_resp() {
+        let args = cmd(&["k", "", "çay"]);
+        let mut encoded = String::new();
+        encode_record(&mut encoded, Some("SET"), &args);
+        let expected = RespValue::Array(Some(
+            cmd(&["SET", "k", "", "çay"]).into_iter().map(|s| RespValue::BulkString(Some(s))).collect(),
+        ));
+        assert_eq!(encoded, expected.serialize());
+
+        let mut bare = String::new();
+        encode_record(&mut bare, None, &cmd(&["FLUSHALL"]));
+        assert_eq!(bare, "*1\r\n$8\r\nFLUSHALL\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_select_records() {
+        let path = std::env::temp_dir().join(format!("hexagondb-aof-{}.aof", uuid::Uuid::new_v4()));
+        let mut aof = Aof::new(&path).unwrap();
+        aof.append(cmd(&["SET", "a", "1"])).unwrap();
+        aof.append(cmd(&["SET", "b", "1"])).unwrap();
+        aof.fsync().unwrap();
+        assert_eq!(read_records(&path).unwrap().iter().filter_map(|r| select_index(r)).count(), 0);
+
+        // Reopening does not know the tail's database, so it selects again
+        let mut aof = Aof::new(&path).unwrap();
+        aof.append(cmd(&["SET", "c", "0"])).unwrap();
+        aof.fsync().unwrap();
+        let records = read_records(&path).unwrap();
+        assert_eq!(records.iter().filter_map(|r| select_index(r)).collect::<Vec<_>>(), vec![0]);
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        Aof::load(&path, &db).await.unwrap();
+        assert_eq!(db.write().await.get("c").unwrap(), Some("0".to_string()));
+
+        // Records for another database are refused rather than dropped
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        let mut other = select_record(3).serialize();
+        encode_record(&mut other, None, &cmd(&["SET", "d", "3"]));
+        file.write_all(other.as_bytes()).unwrap();
+        let err = Aof::load(&path, &Arc::new(RwLock::new(DB::new()))).await.unwrap_err();
+        assert!(err.to_string().contains("database 3"), "{}", err);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_segments_and_compaction() {
+        let dir = std::env::temp_dir().join(format!("hexagondb-aof-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("database.aof");
+        let archive = dir.join("archive");
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        let mut aof = Aof::new(&path).unwrap();
+        for (i, key) in ["a", "b", "c"].into_iter().enumerate() {
+            db.write().await.set(key.to_string(), i.to_string());
+            aof.append(cmd(&["SET", key, &i.to_string()])).unwrap();
+            aof.start_segment(aof.segment + 1).unwrap();
+        }
+        assert_eq!(aof.segments().len(), 4);
+        assert_eq!(aof.sealed_segments(), 3);
+
+        // Records written after the compaction land in the new segment
+        let retired = aof.compact(&*db.read().await, Some(&archive)).unwrap();
+        assert_eq!(retired, 4);
+        assert_eq!(aof.sealed_segments(), 0);
+        aof.append(cmd(&["SET", "d", "3"])).unwrap();
+        aof.fsync().unwrap();
+        assert!(archive.join("database.aof.000001").exists());
+        assert!(archive.join("database.aof.base.000001").exists());
+
+        // A segment left behind by an interrupted compaction is not replayed
+        std::fs::write(segment_path(&path, 2), record(cmd(&["SET", "a", "stale"])).serialize()).unwrap();
+        let loaded = Arc::new(RwLock::new(DB::new()));
+        Aof::load(&path, &loaded).await.unwrap();
+        let mut loaded = loaded.write().await;
+        assert_eq!(loaded.get("a").unwrap(), Some("0".to_string()));
+        assert_eq!(loaded.get("d").unwrap(), Some("3".to_string()));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replay_all_types() {
+        use crate::db::{
+            BitmapOps, GenericOps, GeoOps, HyperLogLogOps, SetOps, StreamOps, ZSetOps,
+        };
+
+        let mut db = DB::new();
+        for args in [
+            &["ZADD", "z", "1", "a", "2", "b", "3", "c"][..],
+            &["ZREM", "z", "a", "b"],
+            &["SADD", "s", "x", "y", "z"],
+            &["SREM", "s", "x", "y"],
+            &["SETBIT", "bits", "7", "1"],
+            &["XADD", "events", "4-0", "kind", "signup"],
+            &["XADD", "events", "5-0", "kind", "login"],
+            &["XTRIM", "events", "MINID", "5"],
+            &["GEOADD", "places", "13.361389", "38.115556", "palermo", "15.087269", "37.502669", "catania"],
+            &["PFADD", "visitors", "u1", "u2", "u3"],
+            &["SET", "old", "v"],
+            &["RENAME", "old", "new"],
+            &["DECRBY", "n", "3"],
+            &["INCRBYFLOAT", "f", "1.5"],
+            &["GETSET", "g", "v"],
+            &["APPEND", "g", "w"],
+        ] {
+            replay_command(&mut db, &cmd(args));
+        }
+
+        assert_eq!(db.zcard("z"), 1);
+        assert_eq!(db.zscore("z", "c"), Some(3.0));
+        assert_eq!(db.smembers("s").unwrap(), vec!["z".to_string()]);
+        assert_eq!(db.getbit("bits", 7), Ok(1));
+        assert_eq!(
+            db.xrange("events", "-", "+", None).unwrap(),
+            vec![("5-0".to_string(), vec![("kind".to_string(), "login".to_string())])]
+        );
+        assert!(db.geopos("places", vec!["catania".to_string()]).unwrap()[0].is_some());
+        assert_eq!(db.pfcount(vec!["visitors".to_string()]), Ok(3));
+        assert!(!db.exists("old"));
+        assert_eq!(db.get("new").unwrap(), Some("v".to_string()));
+        assert_eq!(db.get("n").unwrap(), Some("-3".to_string()));
+        assert_eq!(db.get("f").unwrap(), Some("1.5".to_string()));
+        assert_eq!(db.get("g").unwrap(), Some("vw".to_string()));
     }
 }
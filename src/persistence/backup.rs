@@ -0,0 +1,253 @@
+//! Online backups (`BACKUP TO dir`) and restoring them at startup
+//! (`--restore-from dir`).
+//!
+//! A backup is a directory named after the UTC time it was taken. It holds
+//! an RDB snapshot and, when the AOF is enabled, a copy of the AOF (base
+//! and segments) up to the same point. Both are taken under a keyspace read
+//! lock so they agree: reads are served meanwhile and writers wait while
+//! the snapshot is written. The AOF files are copied after the lock is
+//! released, from handles opened while it was held and only up to the
+//! lengths they had then, so later appends and compactions do not leak
+//! into the backup. A `MANIFEST` file is
+//! written last; a directory without one is an incomplete backup.
+
+use crate::db::DB;
+use crate::persistence::aof::{self, Aof};
+use crate::persistence::{paths, snapshot, stats};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Name of the file marking a complete backup
+pub const MANIFEST_FILE: &str = "MANIFEST";
+
+/// Outcome of a backup
+#[derive(Debug, Clone)]
+pub struct BackupReport {
+    pub dir: PathBuf,
+    pub keys: usize,
+    /// Bytes of AOF files copied, None when the AOF is disabled
+    pub aof_bytes: Option<u64>,
+    pub elapsed: Duration,
+}
+
+/// Outcome of a restore
+#[derive(Debug, Clone)]
+pub struct RestoreReport {
+    pub keys: usize,
+    pub aof_restored: bool,
+    /// Suffix given to data files that were moved aside
+    pub moved_aside: Option<String>,
+}
+
+/// `YYYYMMDDTHHMMSSZ` for a Unix time
+fn utc_stamp(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+    let secs = unix_secs % 86_400;
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Write a backup into a new timestamped directory under `parent`
+pub async fn backup(parent: &Path, db: &Arc<RwLock<DB>>, aof: &Arc<RwLock<Aof>>) -> io::Result<BackupReport> {
+    let started = Instant::now();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let dir = parent.join(format!("backup-{}", utc_stamp(now.as_secs())));
+    if dir.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dir.display()),
+        ));
+    }
+    std::fs::create_dir_all(&dir)?;
+
+    // Writers append to the AOF while holding the keyspace write lock, so a
+    // read lock here means the snapshot and the AOF length agree.
+    let (keys, aof_sources) = {
+        let db_guard = db.read().await;
+        let mut aof_guard = aof.write().await;
+        snapshot::save_db(dir.join(paths::RDB_FILE), &db_guard)?;
+        let mut sources = Vec::new();
+        if let Some(base) = aof_guard.path().map(Path::to_path_buf) {
+            aof_guard.fsync()?;
+            // Copies use the standard names so a restore finds them
+            let segments = aof_guard.segments().into_iter().map(|(seq, path)| {
+                (aof::segment_path(Path::new(paths::AOF_FILE), seq), path)
+            });
+            for (name, path) in std::iter::once((PathBuf::from(paths::AOF_FILE), base)).chain(segments) {
+                let file = File::open(&path)?;
+                let len = file.metadata()?.len();
+                sources.push((name, file, len));
+            }
+        }
+        (db_guard.items.len(), aof_guard.is_enabled().then_some(sources))
+    };
+
+    let aof_bytes = match aof_sources {
+        Some(sources) => {
+            let mut copied = 0;
+            for (name, file, len) in sources {
+                let mut out = File::create(dir.join(name))?;
+                copied += io::copy(&mut file.take(len), &mut out)?;
+                out.sync_all()?;
+            }
+            Some(copied)
+        }
+        None => None,
+    };
+
+    let mut manifest = File::create(dir.join(MANIFEST_FILE))?;
+    writeln!(manifest, "created_unix_ms:{}", now.as_millis())?;
+    writeln!(manifest, "keys:{}", keys)?;
+    writeln!(manifest, "aof_bytes:{}", aof_bytes.map_or(-1, |b| b as i64))?;
+    manifest.sync_all()?;
+
+    let report = BackupReport {
+        dir,
+        keys,
+        aof_bytes,
+        elapsed: started.elapsed(),
+    };
+    info!(
+        "Backup written to {} ({} keys in {:?})",
+        report.dir.display(),
+        report.keys,
+        report.elapsed
+    );
+    Ok(report)
+}
+
+/// Copy the backup in `backup_dir` into `data_dir` before the dataset is
+/// loaded. The snapshot is verified first. Data files already in
+/// `data_dir` are renamed with a `.pre-restore-<time>` suffix rather than
+/// overwritten. The AOF is only restored when `with_aof` is set.
+pub fn restore(backup_dir: &Path, data_dir: &Path, with_aof: bool) -> io::Result<RestoreReport> {
+    if !backup_dir.join(MANIFEST_FILE).exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no {}; the backup is incomplete", backup_dir.display(), MANIFEST_FILE),
+        ));
+    }
+    let rdb = backup_dir.join(paths::RDB_FILE);
+    let keys = stats::rdb_stats(&rdb)?.keys;
+    let aof = backup_dir.join(paths::AOF_FILE);
+    let aof_restored = with_aof && aof.exists();
+
+    std::fs::create_dir_all(data_dir)?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let suffix = format!("pre-restore-{}", utc_stamp(now.as_secs()));
+    let data_aof = data_dir.join(paths::AOF_FILE);
+    let current = [data_dir.join(paths::RDB_FILE), data_aof.clone()]
+        .into_iter()
+        .filter(|path| path.exists())
+        .chain(aof::segment_paths(&data_aof)?.into_iter().map(|(_, path)| path));
+    let mut moved_aside = None;
+    for path in current {
+        let mut aside = path.clone().into_os_string();
+        aside.push(format!(".{}", suffix));
+        std::fs::rename(&path, aside)?;
+        moved_aside = Some(suffix.clone());
+    }
+
+    std::fs::copy(&rdb, data_dir.join(paths::RDB_FILE))?;
+    if aof_restored {
+        std::fs::copy(&aof, &data_aof)?;
+        for (seq, path) in aof::segment_paths(&aof)? {
+            std::fs::copy(path, aof::segment_path(&data_aof, seq))?;
+        }
+    }
+    Ok(RestoreReport {
+        keys,
+        aof_restored,
+        moved_aside,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{GenericOps, StringOps};
+    use crate::persistence::recovery::{self, Source};
+    use crate::persistence::loading::LoadProgress;
+
+    #[test]
+    fn test_utc_stamp() {
+        assert_eq!(utc_stamp(0), "19700101T000000Z");
+        assert_eq!(utc_stamp(951_782_400), "20000229T000000Z");
+        assert_eq!(utc_stamp(1_792_153_530), "20261016T122530Z");
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let base = std::env::temp_dir().join(format!("hexagondb-backup-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        let live_aof = base.join("live.aof");
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        let aof = Arc::new(RwLock::new(Aof::new(&live_aof).unwrap()));
+        for (key, value) in [("a", "1"), ("b", "2")] {
+            db.write().await.set(key.to_string(), value.to_string());
+            aof.write().await.append(vec!["SET".to_string(), key.to_string(), value.to_string()]).unwrap();
+        }
+
+        let report = backup(&base, &db, &aof).await.unwrap();
+        assert_eq!(report.keys, 2);
+        assert!(report.aof_bytes.unwrap() > 0);
+        assert!(report.dir.join(MANIFEST_FILE).exists());
+
+        // Appends after the backup stay out of it
+        aof.write().await.append(vec!["SET".to_string(), "c".to_string(), "3".to_string()]).unwrap();
+        let backup_aof = report.dir.join(paths::AOF_FILE);
+        let copied: u64 = std::iter::once(backup_aof.clone())
+            .chain(aof::segment_paths(&backup_aof).unwrap().into_iter().map(|(_, path)| path))
+            .map(|path| std::fs::metadata(path).unwrap().len())
+            .sum();
+        assert_eq!(copied, report.aof_bytes.unwrap());
+
+        let data_dir = base.join("data");
+        std::fs::create_dir_all(&data_dir).unwrap();
+        std::fs::write(data_dir.join(paths::RDB_FILE), b"old").unwrap();
+        let restored = restore(&report.dir, &data_dir, true).unwrap();
+        assert_eq!(restored.keys, 2);
+        assert!(restored.aof_restored);
+        let suffix = restored.moved_aside.unwrap();
+        assert!(data_dir.join(format!("{}.{}", paths::RDB_FILE, suffix)).exists());
+
+        let aof_path = data_dir.join(paths::AOF_FILE);
+        let source = Source::detect(true, &aof_path, &data_dir.join(paths::RDB_FILE));
+        assert_eq!(source, Source::Aof(aof_path.clone()));
+        let loaded = Arc::new(RwLock::new(DB::new()));
+        let disabled = Arc::new(RwLock::new(Aof::disabled()));
+        recovery::recover(&source, &loaded, &disabled, &LoadProgress::new()).await.unwrap();
+        let mut loaded = loaded.write().await;
+        assert_eq!(loaded.get("b").unwrap(), Some("2".to_string()));
+        assert!(!loaded.exists("c"));
+
+        // A directory without a manifest is refused
+        std::fs::remove_file(report.dir.join(MANIFEST_FILE)).unwrap();
+        assert!(restore(&report.dir, &data_dir, true).is_err());
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
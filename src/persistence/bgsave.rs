@@ -0,0 +1,130 @@
+//! Background snapshots and save points.
+//!
+//! BGSAVE and the automatic save task both go through `start`, which refuses
+//! to run while another snapshot is in progress. The automatic task takes a
+//! snapshot when a save point is reached: at least `changes` writes and
+//! `seconds` since the last successful save, like `save 900 1` in Redis.
+//! After a failed snapshot, automatic saves wait `RETRY_DELAY` before trying
+//! again.
+
+use crate::config::SavePoint;
+use crate::db::DB;
+use crate::persistence::{paths, snapshot};
+use crate::server_info::ServerInfo;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// Wait between automatic attempts after a failed snapshot
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// The first save point reached with `changes` writes `elapsed` after the
+/// last save
+pub fn due(points: &[SavePoint], changes: u64, elapsed: Duration) -> Option<SavePoint> {
+    points
+        .iter()
+        .find(|p| changes > 0 && changes >= p.changes && elapsed.as_secs() >= p.seconds)
+        .copied()
+}
+
+/// Snapshot the database into the RDB file and take the saved changes off
+/// the change counter. Writes only wait for one batch of keys at a time,
+/// and those made during the snapshot still count toward the next save.
+pub async fn save(db: &Arc<RwLock<DB>>) -> std::io::Result<()> {
+    let changes = db.read().await.get_changes();
+    snapshot::save(paths::rdb_path(), db).await?;
+    db.read().await.discount_changes(changes);
+    Ok(())
+}
+
+/// Start a snapshot in the background. Returns false without starting one
+/// if a snapshot is already in progress.
+pub fn start(db: &Arc<RwLock<DB>>, server_info: &Arc<ServerInfo>) -> bool {
+    if !server_info.try_begin_bgsave() {
+        return false;
+    }
+    let db = Arc::clone(db);
+    let server_info = Arc::clone(server_info);
+    tokio::spawn(async move {
+        match save(&db).await {
+            Ok(()) => {
+                server_info.finish_bgsave(true);
+                info!("Background save completed successfully");
+            }
+            Err(e) => {
+                server_info.finish_bgsave(false);
+                error!("Background save failed: {}", e);
+            }
+        }
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::StringOps;
+
+    #[test]
+    fn test_save_points() {
+        let points = [
+            SavePoint { seconds: 900, changes: 1 },
+            SavePoint { seconds: 60, changes: 10000 },
+        ];
+        assert_eq!(due(&points, 0, Duration::from_secs(3600)), None);
+        assert_eq!(due(&points, 5, Duration::from_secs(120)), None);
+        assert_eq!(due(&points, 5, Duration::from_secs(900)), Some(points[0]));
+        assert_eq!(due(&points, 20000, Duration::from_secs(61)), Some(points[1]));
+        assert_eq!(due(&points, 20000, Duration::from_secs(30)), None);
+    }
+
+    #[test]
+    fn test_bgsave_exclusive() {
+        let info = ServerInfo::new();
+        assert!(info.try_begin_bgsave());
+        assert!(!info.try_begin_bgsave());
+        info.finish_bgsave(false);
+        assert!(!info.last_bgsave_ok());
+        assert!(info.try_begin_bgsave());
+        info.finish_bgsave(true);
+        assert!(info.last_bgsave_ok() && !info.bgsave_in_progress());
+    }
+
+    #[tokio::test]
+    async fn test_save_alongside_writes() {
+        let path = std::env::temp_dir().join(format!("hexagondb-bgsave-{}.rdb", uuid::Uuid::new_v4()));
+        let db = Arc::new(RwLock::new(DB::new()));
+        {
+            let mut guard = db.write().await;
+            for i in 0..5 * snapshot::SAVE_BATCH {
+                guard.set(format!("key:{}", i), i.to_string());
+            }
+        }
+
+        let changes = db.read().await.get_changes();
+        let writer = {
+            let db = Arc::clone(&db);
+            tokio::spawn(async move {
+                for i in 0..100 {
+                    db.write().await.set(format!("new:{}", i), i.to_string());
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+        snapshot::save(&path, &db).await.unwrap();
+        writer.await.unwrap();
+        db.read().await.discount_changes(changes);
+        assert_eq!(db.read().await.get_changes(), 100);
+
+        let loaded = Arc::new(RwLock::new(DB::new()));
+        let count = snapshot::load(&path, &loaded).await.unwrap();
+        assert!(count >= 5 * snapshot::SAVE_BATCH);
+        assert_eq!(
+            loaded.write().await.get("key:42").unwrap(),
+            Some("42".to_string())
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
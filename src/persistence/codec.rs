@@ -0,0 +1,470 @@
+//! Binary value encoding.
+//!
+//! Shared by RDB snapshots and DUMP/RESTORE payloads. A value is written as
+//! its type opcode followed by a type-specific body; strings and lengths are
+//! little-endian length-prefixed.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::time::Instant;
+use tracing::warn;
+
+use crate::db::filters::{BloomFilter, BloomLayer, CuckooFilter, CuckooLayer};
+use crate::db::ops::generic::{instant_from_unix_millis, unix_millis_from_instant};
+use crate::db::types::{
+    Aggregation, CompactionRule, DataType, GeoData, GeoLocation, HyperLogLogData, QueueData, QueueMessage, StreamData, StreamEntry,
+    StreamId, TimeSeriesData, VectorData, VectorEntry, ZSetData,
+};
+
+/// Type and control opcodes
+pub(crate) mod opcodes {
+    pub const EOF: u8 = 0xFF;
+    pub const STRING: u8 = 0x00;
+    pub const LIST: u8 = 0x01;
+    pub const SET: u8 = 0x02;
+    pub const ZSET: u8 = 0x03;
+    pub const HASH: u8 = 0x04;
+    pub const BITMAP: u8 = 0x05;
+    pub const STREAM: u8 = 0x06;
+    pub const GEO: u8 = 0x07;
+    pub const HYPERLOGLOG: u8 = 0x08;
+    pub const QUEUE: u8 = 0x09;
+    pub const TIMESERIES: u8 = 0x0A;
+    pub const BLOOM: u8 = 0x0B;
+    pub const CUCKOO: u8 = 0x0C;
+    pub const VECTOR: u8 = 0x0D;
+    pub const EXPIRE: u8 = 0xFD;
+    /// RDB v3: the next value is LZ4-compressed
+    pub const COMPRESSED: u8 = 0xFC;
+    /// RDB v4: expiry times of the next value's hash fields
+    pub const FIELD_EXPIRES: u8 = 0xFB;
+}
+
+/// Opcode identifying the type of a value
+pub(crate) fn opcode_of(value: &DataType) -> u8 {
+    match value {
+        DataType::String(_) => opcodes::STRING,
+        DataType::List(_) => opcodes::LIST,
+        DataType::Set(_) => opcodes::SET,
+        DataType::Hash(_) => opcodes::HASH,
+        DataType::ZSet(_) => opcodes::ZSET,
+        DataType::Bitmap(_) => opcodes::BITMAP,
+        DataType::Stream(_) => opcodes::STREAM,
+        DataType::Geo(_) => opcodes::GEO,
+        DataType::HyperLogLog(_) => opcodes::HYPERLOGLOG,
+        DataType::Queue(_) => opcodes::QUEUE,
+        DataType::TimeSeries(_) => opcodes::TIMESERIES,
+        DataType::Bloom(_) => opcodes::BLOOM,
+        DataType::Cuckoo(_) => opcodes::CUCKOO,
+        DataType::Vector(_) => opcodes::VECTOR,
+    }
+}
+
+/// Write the body of a value (without its opcode)
+pub(crate) fn write_value<W: Write>(writer: &mut W, value: &DataType) -> io::Result<()> {
+    match value {
+        DataType::String(val) => {
+            write_string(writer, val)?;
+        }
+        DataType::List(list) => {
+            write_length(writer, list.len())?;
+            for item in list {
+                write_string(writer, item)?;
+            }
+        }
+        DataType::Set(set) => {
+            write_length(writer, set.len())?;
+            for member in set {
+                write_string(writer, member)?;
+            }
+        }
+        DataType::Hash(hash) => {
+            write_length(writer, hash.len())?;
+            for (field, value) in hash {
+                write_string(writer, field)?;
+                write_string(writer, value)?;
+            }
+        }
+        DataType::ZSet(zset) => {
+            write_length(writer, zset.members.len())?;
+            for (member, score) in &zset.members {
+                write_string(writer, member)?;
+                writer.write_all(&score.to_le_bytes())?;
+            }
+        }
+        DataType::Bitmap(data) => {
+            write_length(writer, data.len())?;
+            writer.write_all(data)?;
+        }
+        DataType::Stream(stream) => {
+            write_length(writer, stream.entries.len())?;
+            for entry in &stream.entries {
+                write_string(writer, &entry.id.to_string())?;
+                writer.write_all(&entry.timestamp.to_le_bytes())?;
+                write_length(writer, entry.fields.len())?;
+                for (field, value) in &entry.fields {
+                    write_string(writer, field)?;
+                    write_string(writer, value)?;
+                }
+            }
+            // Only the millisecond part of last_id is stored; the sequence
+            // is recovered from the last entry on load
+            writer.write_all(&stream.last_id.ms.to_le_bytes())?;
+        }
+        DataType::Geo(geo) => {
+            write_length(writer, geo.locations.len())?;
+            for (name, loc) in &geo.locations {
+                write_string(writer, name)?;
+                writer.write_all(&loc.latitude.to_le_bytes())?;
+                writer.write_all(&loc.longitude.to_le_bytes())?;
+            }
+        }
+        DataType::HyperLogLog(hll) => {
+            // Registers are a fixed size array
+            write_length(writer, hll.registers.len())?;
+            writer.write_all(&hll.registers)?;
+        }
+        DataType::Queue(queue) => {
+            writer.write_all(&queue.last_id.to_le_bytes())?;
+            write_length(writer, queue.messages.len())?;
+            for (id, message) in &queue.messages {
+                writer.write_all(&id.to_le_bytes())?;
+                writer.write_all(&message.priority.to_le_bytes())?;
+                write_string(writer, &message.payload)?;
+                // 0 while waiting for delivery
+                writer.write_all(&message.deadline.unwrap_or(0).to_le_bytes())?;
+            }
+        }
+        DataType::TimeSeries(series) => {
+            writer.write_all(&series.retention_ms.to_le_bytes())?;
+            write_length(writer, series.labels.len())?;
+            for (label, value) in &series.labels {
+                write_string(writer, label)?;
+                write_string(writer, value)?;
+            }
+            write_length(writer, series.rules.len())?;
+            for rule in &series.rules {
+                write_string(writer, &rule.dest)?;
+                write_string(writer, rule.aggregation.name())?;
+                writer.write_all(&rule.bucket_ms.to_le_bytes())?;
+            }
+            write_length(writer, series.samples.len())?;
+            for (timestamp, value) in &series.samples {
+                writer.write_all(&timestamp.to_le_bytes())?;
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        DataType::Bloom(bloom) => {
+            writer.write_all(&bloom.error_rate.to_le_bytes())?;
+            writer.write_all(&(bloom.expansion as u64).to_le_bytes())?;
+            write_length(writer, bloom.layers.len())?;
+            for layer in &bloom.layers {
+                writer.write_all(&layer.capacity.to_le_bytes())?;
+                writer.write_all(&layer.items.to_le_bytes())?;
+                writer.write_all(&(layer.hashes as u64).to_le_bytes())?;
+                write_length(writer, layer.bits.len())?;
+                writer.write_all(&layer.bits)?;
+            }
+        }
+        DataType::Cuckoo(cuckoo) => {
+            write_length(writer, cuckoo.bucket_size)?;
+            writer.write_all(&(cuckoo.max_iterations as u64).to_le_bytes())?;
+            writer.write_all(&(cuckoo.expansion as u64).to_le_bytes())?;
+            writer.write_all(&cuckoo.items.to_le_bytes())?;
+            writer.write_all(&cuckoo.deleted.to_le_bytes())?;
+            write_length(writer, cuckoo.layers.len())?;
+            for layer in &cuckoo.layers {
+                write_length(writer, layer.slots.len())?;
+                for slot in &layer.slots {
+                    writer.write_all(&slot.to_le_bytes())?;
+                }
+            }
+        }
+        DataType::Vector(index) => {
+            write_length(writer, index.dim)?;
+            write_length(writer, index.vectors.len())?;
+            for (id, entry) in &index.vectors {
+                write_string(writer, id)?;
+                for value in &entry.values {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                write_length(writer, entry.meta.len())?;
+                for (field, value) in &entry.meta {
+                    write_string(writer, field)?;
+                    write_string(writer, value)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read the body of a value of the given type opcode.
+/// Returns None if the value was readable but unusable and has been skipped.
+pub(crate) fn read_value<R: Read>(reader: &mut R, opcode: u8) -> io::Result<Option<DataType>> {
+    let value = match opcode {
+        opcodes::STRING => DataType::String(read_string(reader)?),
+        opcodes::LIST => {
+            let len = read_length(reader)?;
+            let mut list = VecDeque::with_capacity(len);
+            for _ in 0..len {
+                list.push_back(read_string(reader)?);
+            }
+            DataType::List(list)
+        }
+        opcodes::SET => {
+            let len = read_length(reader)?;
+            let mut set = HashSet::with_capacity(len);
+            for _ in 0..len {
+                set.insert(read_string(reader)?);
+            }
+            DataType::Set(set)
+        }
+        opcodes::HASH => {
+            let len = read_length(reader)?;
+            let mut hash = HashMap::with_capacity(len);
+            for _ in 0..len {
+                let field = read_string(reader)?;
+                let value = read_string(reader)?;
+                hash.insert(field, value);
+            }
+            DataType::Hash(hash)
+        }
+        opcodes::ZSET => {
+            let len = read_length(reader)?;
+            let mut zset = ZSetData::new();
+            for _ in 0..len {
+                let member = read_string(reader)?;
+                zset.insert(member, read_f64(reader)?);
+            }
+            DataType::ZSet(zset)
+        }
+        opcodes::BITMAP => {
+            let len = read_length(reader)?;
+            let mut data = vec![0u8; len];
+            reader.read_exact(&mut data)?;
+            DataType::Bitmap(data)
+        }
+        opcodes::STREAM => {
+            let entry_count = read_length(reader)?;
+            let mut stream = StreamData::new();
+            for _ in 0..entry_count {
+                let id = read_string(reader)?;
+                let id = StreamId::parse(&id, 0).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Invalid stream ID: {}", id))
+                })?;
+                let timestamp = read_u64(reader)?;
+                let field_count = read_length(reader)?;
+                let mut fields = HashMap::new();
+                for _ in 0..field_count {
+                    let field = read_string(reader)?;
+                    let value = read_string(reader)?;
+                    fields.insert(field, value);
+                }
+                stream.entries.push(StreamEntry { id, fields, timestamp });
+            }
+            // Older files hold entries in insertion order and a counter here
+            stream.entries.sort_by_key(|e| e.id);
+            let last_ms = StreamId { ms: read_u64(reader)?, seq: 0 };
+            stream.last_id = stream.entries.last().map_or(last_ms, |e| e.id.max(last_ms));
+            DataType::Stream(stream)
+        }
+        opcodes::GEO => {
+            let loc_count = read_length(reader)?;
+            let mut geo = GeoData::new();
+            for _ in 0..loc_count {
+                let name = read_string(reader)?;
+                let latitude = read_f64(reader)?;
+                let longitude = read_f64(reader)?;
+                geo.locations.insert(name, GeoLocation { latitude, longitude });
+            }
+            DataType::Geo(geo)
+        }
+        opcodes::HYPERLOGLOG => {
+            let reg_count = read_length(reader)?;
+            let mut registers = vec![0u8; reg_count];
+            reader.read_exact(&mut registers)?;
+            let mut hll = HyperLogLogData::new();
+            if reg_count != hll.registers.len() {
+                warn!("HyperLogLog register count mismatch ({}), skipping value", reg_count);
+                return Ok(None);
+            }
+            hll.registers.copy_from_slice(&registers);
+            DataType::HyperLogLog(hll)
+        }
+        opcodes::QUEUE => {
+            let mut queue = QueueData::new();
+            queue.last_id = read_u64(reader)?;
+            let len = read_length(reader)?;
+            for _ in 0..len {
+                let id = read_u64(reader)?;
+                let priority = read_u64(reader)? as i64;
+                let payload = read_string(reader)?;
+                let deadline = Some(read_u64(reader)?).filter(|&d| d != 0);
+                queue.insert(id, QueueMessage { priority, payload, deadline });
+            }
+            DataType::Queue(queue)
+        }
+        opcodes::TIMESERIES => {
+            let mut series = TimeSeriesData::new(read_u64(reader)?, Vec::new());
+            for _ in 0..read_length(reader)? {
+                let label = read_string(reader)?;
+                series.labels.push((label, read_string(reader)?));
+            }
+            for _ in 0..read_length(reader)? {
+                let dest = read_string(reader)?;
+                let name = read_string(reader)?;
+                let aggregation = Aggregation::parse(&name).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("Unknown aggregation: {}", name))
+                })?;
+                let bucket_ms = read_u64(reader)?;
+                series.rules.push(CompactionRule { dest, aggregation, bucket_ms });
+            }
+            for _ in 0..read_length(reader)? {
+                let timestamp = read_u64(reader)?;
+                series.samples.push_back((timestamp, read_f64(reader)?));
+            }
+            DataType::TimeSeries(series)
+        }
+        opcodes::BLOOM => {
+            let mut bloom = BloomFilter {
+                error_rate: read_f64(reader)?,
+                expansion: read_u64(reader)? as u32,
+                layers: Vec::new(),
+            };
+            for _ in 0..read_length(reader)? {
+                let capacity = read_u64(reader)?;
+                let items = read_u64(reader)?;
+                let hashes = read_u64(reader)? as u32;
+                let mut bits = vec![0u8; read_length(reader)?];
+                reader.read_exact(&mut bits)?;
+                if bits.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty bloom filter layer"));
+                }
+                bloom.layers.push(BloomLayer { bits, hashes, capacity, items });
+            }
+            if bloom.layers.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Bloom filter without layers"));
+            }
+            DataType::Bloom(bloom)
+        }
+        opcodes::CUCKOO => {
+            let bucket_size = read_length(reader)?;
+            let mut cuckoo = CuckooFilter {
+                bucket_size,
+                max_iterations: read_u64(reader)? as u32,
+                expansion: read_u64(reader)? as u32,
+                items: read_u64(reader)?,
+                deleted: read_u64(reader)?,
+                layers: Vec::new(),
+            };
+            for _ in 0..read_length(reader)? {
+                let len = read_length(reader)?;
+                // Layers hold a power of two number of buckets
+                if bucket_size == 0 || len % bucket_size != 0 || !(len / bucket_size).is_power_of_two() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Malformed cuckoo filter layer"));
+                }
+                let mut bytes = vec![0u8; len * 2];
+                reader.read_exact(&mut bytes)?;
+                let slots = bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+                cuckoo.layers.push(CuckooLayer { slots });
+            }
+            if cuckoo.layers.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Cuckoo filter without layers"));
+            }
+            DataType::Cuckoo(cuckoo)
+        }
+        opcodes::VECTOR => {
+            let mut index = VectorData { dim: read_length(reader)?, ..Default::default() };
+            for _ in 0..read_length(reader)? {
+                let id = read_string(reader)?;
+                let mut bytes = vec![0u8; index.dim * 4];
+                reader.read_exact(&mut bytes)?;
+                let values = bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect();
+                let mut meta = Vec::new();
+                for _ in 0..read_length(reader)? {
+                    let field = read_string(reader)?;
+                    meta.push((field, read_string(reader)?));
+                }
+                index.vectors.insert(id, VectorEntry { values, meta });
+            }
+            DataType::Vector(index)
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown opcode: {}", opcode),
+            ))
+        }
+    };
+    Ok(Some(value))
+}
+
+/// Write hash field expiry times: a count, then each field and its Unix
+/// time in milliseconds
+pub(crate) fn write_field_expires<W: Write>(writer: &mut W, expires: &HashMap<String, Instant>) -> io::Result<()> {
+    write_length(writer, expires.len())?;
+    for (field, &at) in expires {
+        write_string(writer, field)?;
+        writer.write_all(&unix_millis_from_instant(at).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Read hash field expiry times written by `write_field_expires`. Times
+/// that have passed are kept, so the fields expire as soon as they are
+/// looked at.
+pub(crate) fn read_field_expires<R: Read>(reader: &mut R) -> io::Result<HashMap<String, Instant>> {
+    let len = read_length(reader)?;
+    let mut expires = HashMap::with_capacity(len.min(1024));
+    for _ in 0..len {
+        let field = read_string(reader)?;
+        let at = instant_from_unix_millis(read_u64(reader)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "hash field expiry time out of range"))?;
+        expires.insert(field, at);
+    }
+    Ok(expires)
+}
+
+// Helper functions for reading/writing
+
+pub(crate) fn write_string<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    let bytes = s.as_bytes();
+    write_length(writer, bytes.len())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+pub(crate) fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = read_length(reader)?;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub(crate) fn write_length<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
+    let len = len as u32;
+    writer.write_all(&len.to_le_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn read_length<R: Read>(reader: &mut R) -> io::Result<usize> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf) as usize)
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
@@ -0,0 +1,123 @@
+//! CRC-64/XZ (ECMA-182 polynomial, reflected), the checksum of DUMP
+//! payloads and RDB files, computed a byte at a time from a table.
+
+use std::io::{self, Read, Write};
+
+const POLY: u64 = 0xC96C_5795_D787_0F42;
+
+const TABLE: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Checksum of `data`
+pub fn checksum(data: &[u8]) -> u64 {
+    !update(!0, data)
+}
+
+/// Feed more data into a running (not yet inverted) CRC register
+fn update(mut crc: u64, data: &[u8]) -> u64 {
+    for &byte in data {
+        crc = TABLE[((crc ^ byte as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+/// Writer that checksums everything written through it
+pub struct Crc64Writer<W: Write> {
+    inner: W,
+    crc: u64,
+}
+
+impl<W: Write> Crc64Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Crc64Writer { inner, crc: !0 }
+    }
+
+    /// Checksum of the bytes written so far
+    pub fn crc(&self) -> u64 {
+        !self.crc
+    }
+
+    /// Underlying writer, for bytes that must not be checksummed
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Give back the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for Crc64Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that checksums everything read through it
+pub struct Crc64Reader<R: Read> {
+    inner: R,
+    crc: u64,
+}
+
+impl<R: Read> Crc64Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Crc64Reader { inner, crc: !0 }
+    }
+
+    /// Checksum of the bytes read so far
+    pub fn crc(&self) -> u64 {
+        !self.crc
+    }
+
+    /// Underlying reader, for bytes that must not be checksummed
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+impl<R: Read> Read for Crc64Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = update(self.crc, &buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_matches_checksum() {
+        let mut writer = Crc64Writer::new(Vec::new());
+        writer.write_all(b"1234").unwrap();
+        writer.write_all(b"56789").unwrap();
+        assert_eq!(writer.crc(), checksum(b"123456789"));
+
+        let mut reader = Crc64Reader::new(&b"123456789"[..]);
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        assert_eq!(reader.crc(), checksum(b"123456789"));
+        assert_eq!(checksum(b""), 0);
+    }
+}
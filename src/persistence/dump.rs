@@ -0,0 +1,290 @@
+//! DUMP/RESTORE payloads.
+//!
+//! A payload is the value's opcode and body as written by the snapshot
+//! codec, then the expiry times of its hash fields (version 2), followed by
+//! a 2-byte format version and a CRC-64 of everything before it. RESP
+//! strings here are UTF-8, so the bytes travel hex-encoded. Key TTLs are
+//! passed separately to RESTORE. Version 1 payloads, which have no field
+//! expiry times, can still be restored.
+//!
+//! DUMPALL sends the whole dataset the same way, as an RDB file or a
+//! standalone AOF, so backups can be pulled over a client connection. The
+//! file is built in memory and never touches the disk.
+
+use crate::db::types::Entry;
+use crate::db::DB;
+use crate::persistence::codec::{opcode_of, read_field_expires, read_value, write_field_expires, write_value};
+use crate::persistence::crc64::checksum as crc64;
+use crate::persistence::{aof, snapshot};
+use std::io::{self, Cursor, Write};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Payload format version
+pub const DUMP_VERSION: u16 = 2;
+/// Version of payloads without field expiry times
+const DUMP_VERSION_V1: u16 = 1;
+
+const BAD_PAYLOAD: &str = "ERR DUMP payload version or checksum are wrong";
+
+/// Serialize a key's value and hash field expiry times into a hex-encoded
+/// DUMP payload
+pub fn dump(entry: &Entry) -> String {
+    let mut buf = vec![opcode_of(&entry.value)];
+    write_value(&mut buf, &entry.value).expect("writing to a Vec cannot fail");
+    write_field_expires(&mut buf, entry.field_expires.as_deref().unwrap_or(&Default::default()))
+        .expect("writing to a Vec cannot fail");
+    buf.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    let crc = crc64(&buf);
+    buf.extend_from_slice(&crc.to_le_bytes());
+
+    encode_hex(&buf)
+}
+
+/// File format of a DUMPALL download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Rdb,
+    Aof,
+}
+
+/// Largest file DUMPALL sends. Hex-encoded it stays within the 512 MB a
+/// bulk string may hold.
+pub const DUMPALL_MAX_BYTES: usize = 256 * 1024 * 1024;
+
+/// The whole dataset as an RDB file or a standalone AOF (as an AOF rewrite
+/// would write it), hex-encoded. Keys are copied in batches under short
+/// read locks as BGSAVE does, then encoded on a blocking thread straight
+/// into the hex string. Fails once the file would exceed `limit` bytes.
+pub async fn dump_all(db: &Arc<RwLock<DB>>, format: DumpFormat, limit: usize) -> io::Result<String> {
+    let copy = snapshot::copy_db(db).await;
+    tokio::task::spawn_blocking(move || {
+        let out = HexWriter { hex: String::new(), limit };
+        let out = match format {
+            DumpFormat::Rdb => snapshot::write_db(out, &copy, &[], || {})?,
+            DumpFormat::Aof => aof::export(out, &copy)?,
+        };
+        Ok(out.hex)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+/// Hex-encodes the bytes written to it, up to `limit` of them
+struct HexWriter {
+    hex: String,
+    limit: usize,
+}
+
+impl Write for HexWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.hex.len() / 2 + buf.len() > self.limit {
+            return Err(io::Error::other(format!("the dataset is over the {} byte limit", self.limit)));
+        }
+        push_hex(&mut self.hex, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Lowercase hex, two digits per byte
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    push_hex(&mut hex, bytes);
+    hex
+}
+
+fn push_hex(hex: &mut String, bytes: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for b in bytes {
+        hex.push(DIGITS[(b >> 4) as usize] as char);
+        hex.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+}
+
+/// Decode and verify a DUMP payload. The entry has no key TTL; RESTORE
+/// sets it from its own argument.
+pub fn restore(payload: &str) -> Result<Entry, String> {
+    let bytes = decode_hex(payload).ok_or_else(|| BAD_PAYLOAD.to_string())?;
+    // opcode + version + checksum
+    if bytes.len() < 1 + 2 + 8 {
+        return Err(BAD_PAYLOAD.to_string());
+    }
+
+    let (data, crc) = bytes.split_at(bytes.len() - 8);
+    if crc64(data).to_le_bytes() != crc {
+        return Err(BAD_PAYLOAD.to_string());
+    }
+    let (body, version) = data.split_at(data.len() - 2);
+    let version = u16::from_le_bytes([version[0], version[1]]);
+    if version != DUMP_VERSION && version != DUMP_VERSION_V1 {
+        return Err(BAD_PAYLOAD.to_string());
+    }
+
+    let mut reader = Cursor::new(&body[1..]);
+    let value = match read_value(&mut reader, body[0]) {
+        Ok(Some(value)) => value,
+        _ => return Err("ERR Bad data format".to_string()),
+    };
+    let field_expires = match version {
+        DUMP_VERSION_V1 => Default::default(),
+        _ => read_field_expires(&mut reader).map_err(|_| "ERR Bad data format".to_string())?,
+    };
+    if reader.position() as usize != body.len() - 1 {
+        return Err("ERR Bad data format".to_string());
+    }
+    Ok(Entry {
+        value,
+        expires_at: None,
+        field_expires: (!field_expires.is_empty()).then(|| Box::new(field_expires)),
+    })
+}
+
+/// Inverse of `encode_hex`
+pub fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::types::DataType;
+
+    fn entry(value: DataType) -> Entry {
+        Entry {
+            value,
+            expires_at: None,
+            field_expires: None,
+        }
+    }
+
+    #[test]
+    fn test_crc64_check_value() {
+        assert_eq!(crc64(b"123456789"), 0x995D_C9BB_DF19_39FA);
+    }
+
+    #[test]
+    fn test_dump_restore_roundtrip() {
+        let value = DataType::List(vec!["a".to_string(), "b".to_string()].into());
+        let payload = dump(&entry(value));
+        match restore(&payload).unwrap().value {
+            DataType::List(list) => assert_eq!(list, vec!["a", "b"]),
+            _ => panic!("wrong type"),
+        }
+
+        // Flip one nibble and the checksum no longer matches
+        let mut corrupt = payload.into_bytes();
+        corrupt[4] = if corrupt[4] == b'0' { b'1' } else { b'0' };
+        assert!(restore(&String::from_utf8(corrupt).unwrap()).is_err());
+        assert!(restore("zz").is_err());
+    }
+
+    #[test]
+    fn test_field_expires_roundtrip() {
+        use crate::db::ops::generic::ExpireCondition;
+        use crate::db::HashOps;
+
+        let mut db = DB::new();
+        db.hset("h".to_string(), "a".to_string(), "1".to_string()).unwrap();
+        db.hset("h".to_string(), "b".to_string(), "2".to_string()).unwrap();
+        let at = std::time::Instant::now() + std::time::Duration::from_secs(100);
+        db.hexpire("h".to_string(), at, ExpireCondition::Always, vec!["a".to_string()]).unwrap();
+
+        let restored = restore(&dump(&db.items["h"])).unwrap();
+        let expires = restored.field_expires.unwrap();
+        assert_eq!(expires.len(), 1);
+        let left = expires["a"].saturating_duration_since(std::time::Instant::now());
+        assert!(left.as_secs() >= 98, "{:?}", left);
+
+        // Version 1 payloads have no field expiry times
+        let mut v1 = vec![crate::persistence::codec::opcodes::STRING];
+        write_value(&mut v1, &DataType::String("v".to_string())).unwrap();
+        v1.extend_from_slice(&DUMP_VERSION_V1.to_le_bytes());
+        let crc = crc64(&v1);
+        v1.extend_from_slice(&crc.to_le_bytes());
+        let restored = restore(&encode_hex(&v1)).unwrap();
+        assert!(matches!(restored.value, DataType::String(s) if s == "v"));
+        assert!(restored.field_expires.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dump_all_limit() {
+        use crate::db::ops::string::StringOps;
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        db.write().await.set("k".to_string(), "v".repeat(100));
+        let rdb = decode_hex(&dump_all(&db, DumpFormat::Rdb, DUMPALL_MAX_BYTES).await.unwrap()).unwrap();
+        assert!(rdb.starts_with(snapshot::RDB_MAGIC_PREFIX));
+        assert!(dump_all(&db, DumpFormat::Aof, DUMPALL_MAX_BYTES).await.is_ok());
+        assert!(dump_all(&db, DumpFormat::Rdb, 50).await.is_err());
+    }
+
+    #[test]
+    fn test_filter_roundtrip() {
+        use crate::db::filters::{BloomFilter, CuckooFilter};
+
+        let mut bloom = BloomFilter::new(0.01, 10, 2);
+        let mut cuckoo = CuckooFilter::new(16, 2, 20, 1);
+        for i in 0..50 {
+            bloom.add(&i.to_string()).unwrap();
+            cuckoo.add(&i.to_string()).unwrap();
+        }
+        cuckoo.delete("7");
+        match restore(&dump(&entry(DataType::Bloom(bloom.clone())))).unwrap().value {
+            DataType::Bloom(restored) => assert_eq!(restored, bloom),
+            _ => panic!("wrong type"),
+        }
+        match restore(&dump(&entry(DataType::Cuckoo(cuckoo.clone())))).unwrap().value {
+            DataType::Cuckoo(restored) => assert_eq!(restored, cuckoo),
+            _ => panic!("wrong type"),
+        }
+    }
+
+    #[test]
+    fn test_vector_roundtrip() {
+        use crate::db::types::VectorData;
+
+        let mut index = VectorData::default();
+        index.upsert("a".to_string(), vec![0.25, -1.5, 3.0], vec![("lang".to_string(), "en".to_string())]).unwrap();
+        index.upsert("b".to_string(), vec![1.0, 0.0, f32::MIN_POSITIVE], vec![]).unwrap();
+        match restore(&dump(&entry(DataType::Vector(index.clone())))).unwrap().value {
+            DataType::Vector(restored) => {
+                assert_eq!(restored.dim, 3);
+                assert_eq!(restored.vectors, index.vectors);
+            }
+            _ => panic!("wrong type"),
+        }
+    }
+
+    #[test]
+    fn test_timeseries_roundtrip() {
+        use crate::db::types::{Aggregation, CompactionRule, TimeSeriesData};
+
+        let mut series = TimeSeriesData::new(1000, vec![("room".to_string(), "a".to_string())]);
+        series.add(5, 1.5).unwrap();
+        series.add(9, -2.0).unwrap();
+        series.rules.push(CompactionRule {
+            dest: "room:a:avg".to_string(),
+            aggregation: Aggregation::Avg,
+            bucket_ms: 60_000,
+        });
+        match restore(&dump(&entry(DataType::TimeSeries(series.clone())))).unwrap().value {
+            DataType::TimeSeries(restored) => {
+                assert_eq!(restored.samples, series.samples);
+                assert_eq!(restored.retention_ms, 1000);
+                assert_eq!(restored.labels, series.labels);
+                assert_eq!(restored.rules, series.rules);
+            }
+            _ => panic!("wrong type"),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Durability lag tracking.
+//!
+//! Counts what has been written to the AOF but not yet fsynced, when the
+//! last fsync succeeded, and how far a running AOF rewrite has got. A disk
+//! that stops accepting writes shows up here as pending bytes that never
+//! drain and an fsync age that keeps growing, well before data is lost.
+//! The values back `INFO persistence` and the Prometheus gauges.
+
+use crate::observability::metrics::{
+    METRIC_AOF_LAST_FSYNC_AGE, METRIC_AOF_PENDING_FSYNC_BYTES, METRIC_AOF_REWRITE_PROGRESS,
+    METRIC_RDB_LAST_SAVE_AGE,
+};
+use metrics::gauge;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Process-wide durability state
+pub static DURABILITY: Durability = Durability::new();
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Counters behind the durability gauges
+pub struct Durability {
+    pending_fsync_bytes: AtomicU64,
+    /// Unix ms of the last successful fsync, 0 if none yet
+    last_fsync_ms: AtomicU64,
+    last_write_ok: AtomicBool,
+    rewrite_in_progress: AtomicBool,
+    rewrite_total: AtomicU64,
+    rewrite_done: AtomicU64,
+}
+
+impl Durability {
+    pub const fn new() -> Self {
+        Durability {
+            pending_fsync_bytes: AtomicU64::new(0),
+            last_fsync_ms: AtomicU64::new(0),
+            last_write_ok: AtomicBool::new(true),
+            rewrite_in_progress: AtomicBool::new(false),
+            rewrite_total: AtomicU64::new(0),
+            rewrite_done: AtomicU64::new(0),
+        }
+    }
+
+    /// Bytes were appended to the AOF
+    pub fn record_write(&self, bytes: usize) {
+        self.pending_fsync_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.last_write_ok.store(true, Ordering::Relaxed);
+    }
+
+    /// An append to the AOF failed
+    pub fn record_write_error(&self) {
+        self.last_write_ok.store(false, Ordering::Relaxed);
+    }
+
+    /// The AOF was fsynced (or freshly opened), so nothing is pending
+    pub fn record_fsync(&self) {
+        self.pending_fsync_bytes.store(0, Ordering::Relaxed);
+        self.last_fsync_ms.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    /// Bytes appended since the last fsync
+    pub fn pending_fsync_bytes(&self) -> u64 {
+        self.pending_fsync_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Seconds since the last fsync, None if the AOF was never synced
+    pub fn last_fsync_age_secs(&self) -> Option<u64> {
+        match self.last_fsync_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(unix_millis().saturating_sub(ms) / 1000),
+        }
+    }
+
+    /// Whether the last append to the AOF succeeded
+    pub fn last_write_ok(&self) -> bool {
+        self.last_write_ok.load(Ordering::Relaxed)
+    }
+
+    /// A rewrite of `total` keys started
+    pub fn begin_rewrite(&self, total: usize) {
+        self.rewrite_total.store(total as u64, Ordering::Relaxed);
+        self.rewrite_done.store(0, Ordering::Relaxed);
+        self.rewrite_in_progress.store(true, Ordering::Release);
+    }
+
+    /// One more key was written by the running rewrite
+    pub fn advance_rewrite(&self) {
+        self.rewrite_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The running rewrite ended, successfully or not
+    pub fn finish_rewrite(&self) {
+        self.rewrite_in_progress.store(false, Ordering::Release);
+    }
+
+    /// Fraction of keys written by the running rewrite, None if none runs
+    pub fn rewrite_progress(&self) -> Option<f64> {
+        if !self.rewrite_in_progress.load(Ordering::Acquire) {
+            return None;
+        }
+        let total = self.rewrite_total.load(Ordering::Relaxed);
+        let done = self.rewrite_done.load(Ordering::Relaxed).min(total);
+        Some(if total == 0 { 1.0 } else { done as f64 / total as f64 })
+    }
+
+    /// Set the Prometheus gauges. `snapshot_age_secs` is the time since the
+    /// last RDB snapshot. A rewrite that is not running reports -1.
+    pub fn publish_metrics(&self, snapshot_age_secs: u64) {
+        gauge!(METRIC_AOF_PENDING_FSYNC_BYTES).set(self.pending_fsync_bytes() as f64);
+        if let Some(age) = self.last_fsync_age_secs() {
+            gauge!(METRIC_AOF_LAST_FSYNC_AGE).set(age as f64);
+        }
+        gauge!(METRIC_RDB_LAST_SAVE_AGE).set(snapshot_age_secs as f64);
+        gauge!(METRIC_AOF_REWRITE_PROGRESS).set(self.rewrite_progress().unwrap_or(-1.0));
+    }
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_bytes_and_rewrite_progress() {
+        let durability = Durability::new();
+        assert_eq!(durability.last_fsync_age_secs(), None);
+
+        durability.record_write(100);
+        durability.record_write(20);
+        assert_eq!(durability.pending_fsync_bytes(), 120);
+        durability.record_fsync();
+        assert_eq!(durability.pending_fsync_bytes(), 0);
+        assert_eq!(durability.last_fsync_age_secs(), Some(0));
+
+        durability.record_write_error();
+        assert!(!durability.last_write_ok());
+
+        assert_eq!(durability.rewrite_progress(), None);
+        durability.begin_rewrite(4);
+        durability.advance_rewrite();
+        assert_eq!(durability.rewrite_progress(), Some(0.25));
+        durability.finish_rewrite();
+        assert_eq!(durability.rewrite_progress(), None);
+    }
+}
@@ -0,0 +1,118 @@
+//! Dataset loading state.
+//!
+//! The dataset is restored (see `recovery`) in the background after the
+//! listener is up. While that runs, clients get a LOADING error (read-only commands may be served from
+//! the part already loaded if `serve_reads_while_loading` is set) and INFO
+//! reports the progress.
+
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Error returned to clients while the dataset is loading
+pub const LOADING_ERROR: &str = "LOADING HexagonDB is loading the dataset in memory";
+
+/// Commands that never touch the keyspace and are always served
+const ALWAYS_ALLOWED: &[&str] = &[
+    "PING", "ECHO", "INFO", "SLOWLOG", "LATENCY", "CLIENT", "FUNCTION", "READONLY", "READWRITE",
+];
+
+/// Commands that only read the keyspace
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "GET", "STRLEN", "EXISTS", "KEYS", "TYPE", "TTL", "PTTL", "EXPIRETIME", "PEXPIRETIME", "DUMP", "DBSIZE",
+    "LLEN", "LRANGE", "LPOS", "HGET", "HGETALL", "HMGET", "HRANDFIELD", "HLEN", "HEXISTS", "HKEYS",
+    "HVALS", "HSTRLEN", "HTTL", "HPTTL", "SMEMBERS", "SISMEMBER", "SCARD", "ZRANGE", "ZSCORE",
+    "ZRANK", "ZREVRANK", "ZAROUND", "ZCARD", "ZRANDMEMBER", "ZUNION", "ZINTER", "ZDIFF", "PFCOUNT", "GETBIT", "BITCOUNT", "XLEN",
+    "XRANGE", "XREVRANGE", "XREAD", "GEODIST", "HSCAN", "SSCAN", "SINTERCARD", "FCALL_RO", "DBSTATS", "SCAN",
+    "TS.GET", "TS.RANGE", "TS.MRANGE", "BF.EXISTS", "BF.MEXISTS", "BF.INFO", "CF.EXISTS", "CF.MEXISTS", "CF.COUNT",
+    "CF.INFO", "VECTOR.GET", "VECTOR.CARD", "VECTOR.SEARCH",
+];
+
+/// Whether a command may run while the dataset is still loading
+pub fn allowed_while_loading(cmd_upper: &str, serve_reads: bool) -> bool {
+    ALWAYS_ALLOWED.contains(&cmd_upper) || (serve_reads && READ_ONLY_COMMANDS.contains(&cmd_upper))
+}
+
+/// Progress of a dataset load
+#[derive(Debug, Default)]
+pub struct LoadProgress {
+    loading: AtomicBool,
+    total: AtomicU64,
+    loaded: AtomicU64,
+    started: Mutex<Option<Instant>>,
+}
+
+impl LoadProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a load of `total` records
+    pub fn begin(&self, total: u64) {
+        *self.started.lock() = Some(Instant::now());
+        self.total.store(total, Ordering::Relaxed);
+        self.loaded.store(0, Ordering::Relaxed);
+        self.loading.store(true, Ordering::Release);
+    }
+
+    /// Mark `records` more records as loaded
+    pub fn advance(&self, records: u64) {
+        self.loaded.fetch_add(records, Ordering::Relaxed);
+    }
+
+    /// Mark the load as complete
+    pub fn finish(&self) {
+        self.loading.store(false, Ordering::Release);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.load(Ordering::Acquire)
+    }
+
+    /// Records loaded so far
+    pub fn loaded(&self) -> u64 {
+        self.loaded.load(Ordering::Relaxed)
+    }
+
+    /// Percentage loaded (100 when there is nothing to load)
+    pub fn percent(&self) -> f64 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return if self.is_loading() { 0.0 } else { 100.0 };
+        }
+        self.loaded() as f64 * 100.0 / total as f64
+    }
+
+    /// Records loaded per second since the load started
+    pub fn rate(&self) -> f64 {
+        match *self.started.lock() {
+            Some(started) => self.loaded() as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON),
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_progress() {
+        let progress = LoadProgress::new();
+        assert!(!progress.is_loading());
+
+        progress.begin(200);
+        progress.advance(50);
+        assert!(progress.is_loading());
+        assert_eq!(progress.percent(), 25.0);
+        progress.advance(150);
+        progress.finish();
+        assert!(!progress.is_loading());
+        assert_eq!(progress.percent(), 100.0);
+
+        assert!(allowed_while_loading("PING", false));
+        assert!(!allowed_while_loading("GET", false));
+        assert!(allowed_while_loading("GET", true));
+        assert!(!allowed_while_loading("SET", true));
+    }
+}
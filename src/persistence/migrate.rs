@@ -0,0 +1,193 @@
+//! One-shot migration of legacy AOF files.
+//!
+//! The legacy server wrote `database.aof` with commands the current replay
+//! path does not understand (SETEX, MSET, LSET, FLUSHALL, ...). On startup a
+//! file without the current format header is replayed here through the DB
+//! traits, the original is kept as `<path>.legacy`, and the AOF is rewritten
+//! in the current format.
+//!
+//! Files written by earlier builds of this engine lack the header too; they
+//! go through the same path, which is harmless since the legacy replay is a
+//! superset of the regular one.
+
+use crate::db::{GenericOps, ListOps, SetOps, StringOps, ZSetOps, DB};
+use crate::persistence::aof::{read_records, replay_command, Aof};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+/// Migrate a legacy AOF file into `db` and rewrite it in the current format.
+/// Returns the number of replayed records, or None if no migration was needed.
+pub async fn migrate_legacy_aof<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<Option<usize>> {
+    let path = path.as_ref();
+    if Aof::is_current_format(path)? {
+        return Ok(None);
+    }
+
+    info!("Legacy AOF detected at {}, migrating...", path.display());
+    let records = read_records(path)?;
+    {
+        let mut db_guard = db.write().await;
+        for args in &records {
+            replay_legacy_command(&mut db_guard, args);
+        }
+    }
+
+    // Keep the original around in case something went wrong
+    let backup = format!("{}.legacy", path.display());
+    std::fs::copy(path, &backup)?;
+    Aof::rewrite(path, db).await?;
+
+    info!(
+        "Migrated {} legacy AOF commands; original kept at {}",
+        records.len(),
+        backup
+    );
+    Ok(Some(records.len()))
+}
+
+/// Apply one legacy AOF record. Commands only the legacy server logged are
+/// handled here; everything else goes through the regular replay path.
+fn replay_legacy_command(db: &mut DB, args: &[String]) {
+    let cmd = args[0].to_uppercase();
+    match cmd.as_str() {
+        "SETNX" if args.len() >= 3 => {
+            db.setnx(args[1].clone(), args[2].clone());
+        }
+        "SETEX" if args.len() >= 4 => {
+            if let Ok(secs) = args[2].parse::<u64>() {
+                db.setex(args[1].clone(), secs, args[3].clone());
+            }
+        }
+        "MSET" if args.len() >= 3 => {
+            let pairs = args[1..]
+                .chunks_exact(2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect();
+            db.mset(pairs);
+        }
+        "APPEND" if args.len() >= 3 => {
+            let _ = db.append(args[1].clone(), args[2].clone());
+        }
+        "DECRBY" if args.len() >= 3 => {
+            if let Ok(delta) = args[2].parse::<i64>() {
+                let _ = db.decrby(&args[1], delta);
+            }
+        }
+        "INCRBYFLOAT" if args.len() >= 3 => {
+            if let Ok(delta) = args[2].parse::<f64>() {
+                let _ = db.incrbyfloat(&args[1], delta);
+            }
+        }
+        "RENAME" if args.len() >= 3 => {
+            let _ = db.rename(&args[1], &args[2]);
+        }
+        "LSET" if args.len() >= 4 => {
+            if let Ok(index) = args[2].parse::<i64>() {
+                let _ = db.lset(args[1].clone(), index, args[3].clone());
+            }
+        }
+        "LTRIM" if args.len() >= 4 => {
+            if let (Ok(start), Ok(stop)) = (args[2].parse::<i64>(), args[3].parse::<i64>()) {
+                db.ltrim(args[1].clone(), start, stop);
+            }
+        }
+        "LREM" if args.len() >= 4 => {
+            if let Ok(count) = args[2].parse::<i64>() {
+                db.lrem(args[1].clone(), count, args[3].clone());
+            }
+        }
+        "SMOVE" if args.len() >= 4 => {
+            db.smove(args[1].clone(), args[2].clone(), args[3].clone());
+        }
+        "SPOP" if args.len() >= 2 => {
+            // The legacy server logged SPOP itself rather than the popped
+            // members, so the replayed result may differ from the original
+            warn!("Replaying non-deterministic SPOP on '{}'", args[1]);
+            let count = args.get(2).and_then(|s| s.parse().ok());
+            db.spop(args[1].clone(), count);
+        }
+        "ZREM" if args.len() >= 3 => {
+            let _ = db.zrem(args[1].clone(), args[2..].to_vec());
+        }
+        "ZINCRBY" if args.len() >= 4 => {
+            if let Ok(increment) = args[2].parse::<f64>() {
+                let _ = db.zincrby(args[1].clone(), increment, args[3].clone());
+            }
+        }
+        "ZREMRANGEBYRANK" if args.len() >= 4 => {
+            if let (Ok(start), Ok(stop)) = (args[2].parse::<i64>(), args[3].parse::<i64>()) {
+                db.zremrangebyrank(args[1].clone(), start, stop);
+            }
+        }
+        "ZREMRANGEBYSCORE" if args.len() >= 4 => {
+            if let (Ok(min), Ok(max)) = (args[2].parse::<f64>(), args[3].parse::<f64>()) {
+                db.zremrangebyscore(args[1].clone(), min, max);
+            }
+        }
+        "FLUSHALL" => {
+            db.flushdb();
+        }
+        _ => replay_command(db, args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::HashOps;
+    use crate::network::resp::RespValue;
+    use std::io::Write;
+
+    fn write_legacy(path: &Path, commands: &[&[&str]]) {
+        let mut file = std::fs::File::create(path).unwrap();
+        for cmd in commands {
+            let resp = RespValue::Array(Some(
+                cmd.iter().map(|s| RespValue::BulkString(Some(s.to_string()))).collect(),
+            ));
+            file.write_all(resp.serialize().as_bytes()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy_aof() {
+        let path = std::env::temp_dir().join(format!("hexagondb-test-{}.aof", uuid::Uuid::new_v4()));
+        write_legacy(
+            &path,
+            &[
+                &["MSET", "a", "1", "b", "2"],
+                &["APPEND", "a", "0"],
+                &["RPUSH", "list", "x", "y", "z"],
+                &["LSET", "list", "0", "w"],
+                &["HSET", "h", "f", "v"],
+                &["GET", "a"],
+            ],
+        );
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        assert_eq!(migrate_legacy_aof(&path, &db).await.unwrap(), Some(6));
+        {
+            let mut guard = db.write().await;
+            assert_eq!(guard.get("a").unwrap(), Some("10".to_string()));
+            assert_eq!(guard.lrange("list", 0, -1).unwrap(), vec!["w", "y", "z"]);
+            assert_eq!(guard.hget("h", "f").unwrap(), Some("v".to_string()));
+        }
+
+        // The rewritten file is in the current format and loads on its own
+        assert!(Aof::is_current_format(&path).unwrap());
+        assert_eq!(migrate_legacy_aof(&path, &db).await.unwrap(), None);
+        let reloaded = Arc::new(RwLock::new(DB::new()));
+        Aof::load(&path, &reloaded).await.unwrap();
+        assert_eq!(
+            reloaded.write().await.get("b").unwrap(),
+            Some("2".to_string())
+        );
+
+        let backup = format!("{}.legacy", path.display());
+        assert!(Path::new(&backup).exists());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&backup);
+    }
+}
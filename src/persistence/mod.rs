@@ -3,4 +3,16 @@
 //! Provides AOF (Append-Only File) and RDB (Snapshot) persistence.
 
 pub mod aof;
+pub mod backup;
+pub mod bgsave;
+pub mod codec;
+pub mod crc64;
+pub mod dump;
+pub mod durability;
+pub mod loading;
+pub mod migrate;
+pub mod paths;
+pub mod recovery;
+pub mod relocate;
 pub mod snapshot;
+pub mod stats;
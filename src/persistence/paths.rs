@@ -0,0 +1,50 @@
+//! Location of the persistence files.
+//!
+//! The AOF, RDB and ACL files live in a single data directory, set from
+//! `persistence.data_dir` at startup and changed at runtime by
+//! `PERSISTENCE RELOCATE`.
+
+use parking_lot::RwLock;
+use std::path::{Path, PathBuf};
+
+/// AOF file name inside the data directory
+pub const AOF_FILE: &str = "database.aof";
+/// RDB file name inside the data directory
+pub const RDB_FILE: &str = "dump.rdb";
+/// ACL users file name inside the data directory
+pub const ACL_FILE: &str = "users.acl";
+
+static DATA_DIR: RwLock<Option<PathBuf>> = parking_lot::const_rwlock(None);
+
+/// Current data directory (the working directory unless configured)
+pub fn data_dir() -> PathBuf {
+    DATA_DIR.read().clone().unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Change the data directory
+pub fn set_data_dir<P: AsRef<Path>>(dir: P) {
+    *DATA_DIR.write() = Some(dir.as_ref().to_path_buf());
+}
+
+/// Path of the AOF file
+pub fn aof_path() -> PathBuf {
+    data_dir().join(AOF_FILE)
+}
+
+/// Path of the RDB snapshot
+pub fn rdb_path() -> PathBuf {
+    data_dir().join(RDB_FILE)
+}
+
+/// Path of the ACL users file written by ACL SAVE
+pub fn acl_path() -> PathBuf {
+    data_dir().join(ACL_FILE)
+}
+
+/// Per-user data directory of the platform (`%LOCALAPPDATA%\HexagonDB`
+/// on Windows, `~/.local/share/hexagondb` on Linux), used by the Windows
+/// service when `persistence.data_dir` is not set
+pub fn platform_data_dir() -> Option<PathBuf> {
+    let name = if cfg!(windows) { "HexagonDB" } else { "hexagondb" };
+    dirs::data_local_dir().map(|p| p.join(name))
+}
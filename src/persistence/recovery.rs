@@ -0,0 +1,171 @@
+//! Startup recovery.
+//!
+//! With the AOF enabled it is the source of truth: an RDB preamble at its
+//! start is restored first, then the records after it are replayed. When
+//! there is no AOF yet but there is an RDB snapshot, the snapshot is loaded
+//! and the AOF is seeded from it, so the next start finds those keys in the
+//! AOF. With the AOF disabled only the RDB snapshot is loaded.
+
+use crate::db::DB;
+use crate::persistence::aof::Aof;
+use crate::persistence::loading::LoadProgress;
+use crate::persistence::snapshot;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Where the dataset is restored from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Nothing to load
+    Empty,
+    /// Replay the AOF (and its RDB preamble, if any)
+    Aof(PathBuf),
+    /// Load an RDB snapshot, then write the AOF at `seed_aof` from it
+    Rdb { path: PathBuf, seed_aof: Option<PathBuf> },
+}
+
+impl Source {
+    /// Pick the source for the files on disk. Must run before the AOF is
+    /// opened for appending, which creates the file.
+    pub fn detect(aof_enabled: bool, aof_path: &Path, rdb_path: &Path) -> Source {
+        let non_empty = |path: &Path| std::fs::metadata(path).is_ok_and(|m| m.len() > 0);
+        if aof_enabled && non_empty(aof_path) {
+            Source::Aof(aof_path.to_path_buf())
+        } else if non_empty(rdb_path) {
+            Source::Rdb {
+                path: rdb_path.to_path_buf(),
+                seed_aof: aof_enabled.then(|| aof_path.to_path_buf()),
+            }
+        } else {
+            Source::Empty
+        }
+    }
+}
+
+/// Restore the dataset from `source`, logging what was restored. `aof`
+/// must be open on the AOF path when the source seeds it.
+pub async fn recover(
+    source: &Source,
+    db: &Arc<RwLock<DB>>,
+    aof: &Arc<RwLock<Aof>>,
+    progress: &LoadProgress,
+) -> io::Result<()> {
+    match source {
+        Source::Empty => info!("No AOF or RDB file found, starting with an empty dataset"),
+        Source::Aof(path) => {
+            let loaded = Aof::load_with_progress(path, db, progress).await?;
+            match loaded.preamble_keys {
+                Some(keys) => info!(
+                    "Recovered from AOF {}: {} keys from its RDB preamble, then {} commands",
+                    path.display(),
+                    keys,
+                    loaded.commands
+                ),
+                None => info!(
+                    "Recovered from AOF {}: {} commands",
+                    path.display(),
+                    loaded.commands
+                ),
+            }
+        }
+        Source::Rdb { path, seed_aof } => {
+            let keys = snapshot::load(path, db).await?;
+            if let Some(aof_path) = seed_aof {
+                let db_guard = db.read().await;
+                aof.write().await.relocate_to(aof_path, &db_guard)?;
+                info!(
+                    "Recovered {} keys from RDB {} (no AOF yet); seeded {} from them",
+                    keys,
+                    path.display(),
+                    aof_path.display()
+                );
+            } else {
+                info!("Recovered {} keys from RDB {} (AOF disabled)", keys, path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{GenericOps, StringOps};
+
+    fn temp_path(ext: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hexagondb-recovery-{}.{}", uuid::Uuid::new_v4(), ext))
+    }
+
+    #[tokio::test]
+    async fn test_preamble_then_tail() {
+        let aof_path = temp_path("aof");
+        let rdb_path = temp_path("rdb");
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        {
+            let mut guard = db.write().await;
+            guard.set("base".to_string(), "1".to_string());
+            guard.expire("base", 100);
+        }
+        crate::persistence::aof::set_rdb_preamble(true);
+        Aof::rewrite(&aof_path, &db).await.unwrap();
+        assert!(Aof::is_current_format(&aof_path).unwrap());
+
+        let mut aof = Aof::new(&aof_path).unwrap();
+        aof.append(vec!["SET".to_string(), "tail".to_string(), "2".to_string()]).unwrap();
+        aof.fsync().unwrap();
+
+        assert_eq!(
+            Source::detect(true, &aof_path, &rdb_path),
+            Source::Aof(aof_path.clone())
+        );
+        let restored = Arc::new(RwLock::new(DB::new()));
+        let aof = Arc::new(RwLock::new(Aof::disabled()));
+        recover(&Source::Aof(aof_path.clone()), &restored, &aof, &LoadProgress::new())
+            .await
+            .unwrap();
+        {
+            let mut guard = restored.write().await;
+            assert_eq!(guard.get("base").unwrap(), Some("1".to_string()));
+            assert!(guard.ttl("base") > 0);
+            assert_eq!(guard.get("tail").unwrap(), Some("2".to_string()));
+        }
+        assert_eq!(
+            crate::persistence::aof::read_records(&aof_path).unwrap().last().unwrap(),
+            &vec!["SET", "tail", "2"]
+        );
+
+        // Without the AOF only the snapshot is used
+        assert_eq!(Source::detect(false, &aof_path, &rdb_path), Source::Empty);
+        snapshot::save_db(&rdb_path, &*db.read().await).unwrap();
+        assert_eq!(
+            Source::detect(false, &aof_path, &rdb_path),
+            Source::Rdb { path: rdb_path.clone(), seed_aof: None }
+        );
+
+        // With the AOF enabled but missing, the snapshot seeds it
+        let seeded_path = temp_path("aof");
+        let source = Source::detect(true, &seeded_path, &rdb_path);
+        assert_eq!(
+            source,
+            Source::Rdb { path: rdb_path.clone(), seed_aof: Some(seeded_path.clone()) }
+        );
+        let aof = Arc::new(RwLock::new(Aof::new(&seeded_path).unwrap()));
+        recover(&source, &Arc::new(RwLock::new(DB::new())), &aof, &LoadProgress::new())
+            .await
+            .unwrap();
+        let reloaded = Arc::new(RwLock::new(DB::new()));
+        Aof::load(&seeded_path, &reloaded).await.unwrap();
+        assert_eq!(
+            reloaded.write().await.get("base").unwrap(),
+            Some("1".to_string())
+        );
+
+        for path in [&aof_path, &rdb_path, &seeded_path] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
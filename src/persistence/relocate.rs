@@ -0,0 +1,106 @@
+//! Online relocation of the persistence files (`PERSISTENCE RELOCATE`).
+//!
+//! Moves the data directory without a restart: the current AOF is synced, a
+//! snapshot and a compacted AOF are written into the new directory, and
+//! appends continue there. Writers are paused for the duration. The old
+//! files are left in place for the operator to remove.
+
+use crate::db::DB;
+use crate::persistence::aof::Aof;
+use crate::persistence::{paths, snapshot};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::info;
+
+/// Outcome of a relocation
+#[derive(Debug, Clone)]
+pub struct RelocateReport {
+    pub dir: PathBuf,
+    pub keys: usize,
+    pub elapsed: Duration,
+}
+
+/// Move the AOF and RDB files (and a saved ACL file) to `dir`, creating it if needed
+pub async fn relocate(dir: &Path, db: &Arc<RwLock<DB>>, aof: &Arc<RwLock<Aof>>) -> io::Result<RelocateReport> {
+    let started = Instant::now();
+    std::fs::create_dir_all(dir)?;
+    let dir = dir.canonicalize()?;
+    if paths::data_dir().canonicalize().ok().as_deref() == Some(dir.as_path()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "target is already the data directory",
+        ));
+    }
+
+    // Writers append to the AOF while holding the keyspace write lock, so a
+    // read lock here means no write is half-logged.
+    let db_guard = db.read().await;
+    let mut aof_guard = aof.write().await;
+
+    snapshot::save_db(dir.join(paths::RDB_FILE), &db_guard)?;
+    if aof_guard.is_enabled() {
+        aof_guard.relocate_to(dir.join(paths::AOF_FILE), &db_guard)?;
+    }
+    // Saved ACL users are read from the data directory at startup
+    let acl = paths::acl_path();
+    if acl.exists() {
+        std::fs::copy(&acl, dir.join(paths::ACL_FILE))?;
+    }
+    paths::set_data_dir(&dir);
+
+    let report = RelocateReport {
+        dir,
+        keys: db_guard.items.len(),
+        elapsed: started.elapsed(),
+    };
+    info!(
+        "Persistence relocated to {} ({} keys in {:?})",
+        report.dir.display(),
+        report.keys,
+        report.elapsed
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{GenericOps, ListOps, StringOps};
+
+    #[tokio::test]
+    async fn test_relocate() {
+        let base = std::env::temp_dir().join(format!("hexagondb-relocate-{}", uuid::Uuid::new_v4()));
+        let old_aof = base.join("old.aof");
+        std::fs::create_dir_all(&base).unwrap();
+
+        let db = Arc::new(RwLock::new(DB::new()));
+        let aof = Arc::new(RwLock::new(Aof::new(&old_aof).unwrap()));
+        {
+            let mut guard = db.write().await;
+            guard.set("a".to_string(), "1".to_string());
+            guard.rpush("l".to_string(), vec!["x".to_string(), "y".to_string()]).unwrap();
+            guard.expire("a", 100);
+        }
+
+        let target = base.join("new");
+        let report = relocate(&target, &db, &aof).await.unwrap();
+        assert_eq!(report.keys, 2);
+        assert!(target.join(paths::RDB_FILE).exists());
+        assert_eq!(paths::aof_path(), report.dir.join(paths::AOF_FILE));
+        assert!(relocate(&target, &db, &aof).await.is_err());
+
+        aof.write().await.append(vec!["SET".to_string(), "b".to_string(), "2".to_string()]).unwrap();
+        let replayed = Arc::new(RwLock::new(DB::new()));
+        Aof::load(target.join(paths::AOF_FILE), &replayed).await.unwrap();
+        let mut replayed = replayed.write().await;
+        assert_eq!(replayed.get("a").unwrap(), Some("1".to_string()));
+        assert!(replayed.ttl("a") > 0);
+        assert_eq!(replayed.llen("l").unwrap(), 2);
+        assert_eq!(replayed.get("b").unwrap(), Some("2".to_string()));
+
+        let _ = std::fs::remove_dir_all(&base);
+    }
+}
@@ -1,484 +1,595 @@
 //! RDB Snapshot persistence.
 //!
-//! Creates point-in-time snapshots of the database.
+//! Creates snapshots of the database. `save_db` is point-in-time under
+//! the caller's lock; `save` copies keys in batches so writes keep going.
 //! Supports all data types including Bitmap, Stream, Geo, and HyperLogLog.
+//!
+//! Version 3 files end with a CRC-64 of everything before it, checked on
+//! load, and may LZ4-compress large values (see `set_compression`).
+//! Version 4 adds the expiry times of hash fields. Version 1 and 2 files,
+//! which have no checksum or compression, can still be read.
+//!
+//! The same format is used for the RDB preamble of an AOF file, where the
+//! snapshot is followed by AOF records instead of ending the file.
 
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{error, info, warn};
 
-use crate::db::types::{DataType, Entry, ZSetData, StreamData, GeoData, HyperLogLogData};
+use crate::db::types::{DataType, Entry};
 use crate::db::DB;
-
-/// Magic bytes for RDB file - version 02 includes all types
-const RDB_MAGIC: &[u8] = b"HEXRDB02";
-
-/// RDB opcodes
-mod opcodes {
-    pub const EOF: u8 = 0xFF;
-    pub const STRING: u8 = 0x00;
-    pub const LIST: u8 = 0x01;
-    pub const SET: u8 = 0x02;
-    pub const ZSET: u8 = 0x03;
-    pub const HASH: u8 = 0x04;
-    pub const BITMAP: u8 = 0x05;
-    pub const STREAM: u8 = 0x06;
-    pub const GEO: u8 = 0x07;
-    pub const HYPERLOGLOG: u8 = 0x08;
-    pub const EXPIRE: u8 = 0xFD;
+use crate::persistence::codec::{
+    opcode_of, opcodes, read_field_expires, read_length, read_string, read_u64, read_value,
+    write_field_expires, write_length, write_string, write_value,
+};
+use crate::persistence::crc64::{Crc64Reader, Crc64Writer};
+
+/// Magic bytes for RDB file - version 04 adds hash field expiry times
+const RDB_MAGIC: &[u8] = b"HEXRDB04";
+/// Version 03 adds compression and a checksum
+const RDB_MAGIC_V3: &[u8] = b"HEXRDB03";
+/// Version 02 includes all types
+const RDB_MAGIC_V2: &[u8] = b"HEXRDB02";
+/// Version 01 only knows the original five types
+const RDB_MAGIC_V1: &[u8] = b"HEXRDB01";
+/// Start of every RDB file, whatever its version
+pub(crate) const RDB_MAGIC_PREFIX: &[u8] = b"HEXRDB";
+
+/// Serialized size from which values are compressed, 0 when disabled
+static COMPRESSION_THRESHOLD: AtomicUsize = AtomicUsize::new(0);
+
+/// Compress values of at least `threshold` serialized bytes in snapshots
+/// written from now on, or stop compressing with None
+pub fn set_compression(threshold: Option<usize>) {
+    COMPRESSION_THRESHOLD.store(threshold.map_or(0, |t| t.max(1)), Ordering::Relaxed);
 }
 
-/// Save database to RDB file
+/// Keys copied per read lock by `save`
+pub const SAVE_BATCH: usize = 1000;
+
+/// Save database to RDB file.
+///
+/// The lock is not held across serialization: the key list is taken once,
+/// then entries are cloned `SAVE_BATCH` at a time under short read locks
+/// and written with the lock released, so writers only wait for one batch.
+/// A key changed meanwhile is saved with its value when its batch is
+/// copied; keys created after the key list was taken are left out.
 pub async fn save<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<()> {
-    let temp_path = format!("{}.tmp", path.as_ref().display());
-    let file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&temp_path)?;
+    let mut snapshot = SnapshotWriter::create(path.as_ref())?;
+    let keys = KeyList::of(&*db.read().await);
+
+    let mut batch = Vec::with_capacity(SAVE_BATCH);
+    for start in (0..keys.len()).step_by(SAVE_BATCH) {
+        {
+            let db_guard = db.read().await;
+            for i in start..keys.len().min(start + SAVE_BATCH) {
+                let key = keys.get(i);
+                if let Some(entry) = db_guard.items.get(key) {
+                    batch.push((key, entry.clone()));
+                }
+            }
+        }
+        for (key, entry) in batch.drain(..) {
+            snapshot.write_entry(key, &entry)?;
+        }
+        tokio::task::yield_now().await;
+    }
+
+    snapshot.finish(path.as_ref())
+}
+
+/// Copy of the database, taken `SAVE_BATCH` keys per read lock with the
+/// same consistency as `save`
+pub(crate) async fn copy_db(db: &Arc<RwLock<DB>>) -> DB {
+    let keys = KeyList::of(&*db.read().await);
+    let mut copy = DB::with_capacity(keys.len());
+    for start in (0..keys.len()).step_by(SAVE_BATCH) {
+        {
+            let db_guard = db.read().await;
+            for i in start..keys.len().min(start + SAVE_BATCH) {
+                let key = keys.get(i);
+                if let Some(entry) = db_guard.items.get(key) {
+                    copy.items.insert(key.to_string(), entry.clone());
+                }
+            }
+        }
+        tokio::task::yield_now().await;
+    }
+    copy
+}
 
-    let mut writer = BufWriter::new(file);
+/// Keys packed into one buffer, so copying the key list under the lock
+/// costs a couple of allocations rather than one per key
+struct KeyList {
+    buf: String,
+    ends: Vec<usize>,
+}
 
-    // Write magic
-    writer.write_all(RDB_MAGIC)?;
+impl KeyList {
+    fn of(db: &DB) -> Self {
+        let mut buf = String::new();
+        let mut ends = Vec::with_capacity(db.items.len());
+        for key in db.items.keys() {
+            buf.push_str(key);
+            ends.push(buf.len());
+        }
+        KeyList { buf, ends }
+    }
+
+    fn len(&self) -> usize {
+        self.ends.len()
+    }
 
-    let db_guard = db.read().await;
-    let mut saved_count = 0usize;
-    let skipped_count = 0usize;
+    fn get(&self, i: usize) -> &str {
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+        &self.buf[start..self.ends[i]]
+    }
+}
+
+/// Save an already locked database to an RDB file
+pub fn save_db<P: AsRef<Path>>(path: P, db_guard: &DB) -> io::Result<()> {
+    save_db_with_tail(path, db_guard, &[], || {})
+}
 
+/// Encode an already locked database as an RDB file into `out`, followed
+/// by `tail` as in `save_db_with_tail`. `on_entry` runs after each key.
+pub(crate) fn write_db<W: Write>(out: W, db_guard: &DB, tail: &[u8], mut on_entry: impl FnMut()) -> io::Result<W> {
+    let mut encoder = RdbEncoder::new(out)?;
     for (key, entry) in db_guard.items.iter() {
+        encoder.write_entry(key, entry)?;
+        on_entry();
+    }
+    encoder.finish(tail)
+}
+
+/// Save an already locked database followed by `tail`, which is written
+/// after the checksum (the first records of an AOF with an RDB preamble).
+/// `on_entry` runs after each key is written.
+pub(crate) fn save_db_with_tail<P: AsRef<Path>>(
+    path: P,
+    db_guard: &DB,
+    tail: &[u8],
+    mut on_entry: impl FnMut(),
+) -> io::Result<()> {
+    let mut snapshot = SnapshotWriter::create(path.as_ref())?;
+    for (key, entry) in db_guard.items.iter() {
+        snapshot.write_entry(key, entry)?;
+        on_entry();
+    }
+    snapshot.finish_with_tail(path.as_ref(), tail)
+}
+
+/// RDB encoding into any writer: the magic, then each entry, then EOF and
+/// the checksum of everything before it
+struct RdbEncoder<W: Write> {
+    writer: Crc64Writer<W>,
+    compress_at: usize,
+    saved_count: usize,
+    skipped_count: usize,
+}
+
+impl<W: Write> RdbEncoder<W> {
+    fn new(inner: W) -> io::Result<Self> {
+        let mut writer = Crc64Writer::new(inner);
+
+        // Write magic
+        writer.write_all(RDB_MAGIC)?;
+
+        Ok(RdbEncoder {
+            writer,
+            compress_at: COMPRESSION_THRESHOLD.load(Ordering::Relaxed),
+            saved_count: 0,
+            skipped_count: 0,
+        })
+    }
+
+    fn write_entry(&mut self, key: &str, entry: &Entry) -> io::Result<()> {
         // Write expiration if exists
         if let Some(expires_at) = entry.expires_at {
             let now = std::time::Instant::now();
             if expires_at > now {
-                writer.write_all(&[opcodes::EXPIRE])?;
+                self.writer.write_all(&[opcodes::EXPIRE])?;
                 let ttl_ms = expires_at.duration_since(now).as_millis() as u64;
-                writer.write_all(&ttl_ms.to_le_bytes())?;
+                self.writer.write_all(&ttl_ms.to_le_bytes())?;
             } else {
                 // Key has expired, skip it
-                continue;
+                self.skipped_count += 1;
+                return Ok(());
             }
         }
+        if let Some(expires) = entry.field_expires.as_deref() {
+            self.writer.write_all(&[opcodes::FIELD_EXPIRES])?;
+            write_field_expires(&mut self.writer, expires)?;
+        }
 
-        match &entry.value {
-            DataType::String(val) => {
-                writer.write_all(&[opcodes::STRING])?;
-                write_string(&mut writer, key)?;
-                write_string(&mut writer, val)?;
-                saved_count += 1;
-            }
-            DataType::List(list) => {
-                writer.write_all(&[opcodes::LIST])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, list.len())?;
-                for item in list {
-                    write_string(&mut writer, item)?;
-                }
-                saved_count += 1;
-            }
-            DataType::Set(set) => {
-                writer.write_all(&[opcodes::SET])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, set.len())?;
-                for member in set {
-                    write_string(&mut writer, member)?;
-                }
-                saved_count += 1;
-            }
-            DataType::Hash(hash) => {
-                writer.write_all(&[opcodes::HASH])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, hash.len())?;
-                for (field, value) in hash {
-                    write_string(&mut writer, field)?;
-                    write_string(&mut writer, value)?;
-                }
-                saved_count += 1;
-            }
-            DataType::ZSet(zset) => {
-                writer.write_all(&[opcodes::ZSET])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, zset.members.len())?;
-                for (member, score) in &zset.members {
-                    write_string(&mut writer, member)?;
-                    writer.write_all(&score.to_le_bytes())?;
-                }
-                saved_count += 1;
-            }
-            DataType::Bitmap(data) => {
-                writer.write_all(&[opcodes::BITMAP])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, data.len())?;
-                writer.write_all(data)?;
-                saved_count += 1;
-            }
-            DataType::Stream(stream) => {
-                // Serialize stream entries
-                writer.write_all(&[opcodes::STREAM])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, stream.entries.len())?;
-                for entry in &stream.entries {
-                    write_string(&mut writer, &entry.id)?;
-                    writer.write_all(&entry.timestamp.to_le_bytes())?;
-                    write_length(&mut writer, entry.fields.len())?;
-                    for (field, value) in &entry.fields {
-                        write_string(&mut writer, field)?;
-                        write_string(&mut writer, value)?;
-                    }
-                }
-                // Write last_id as u64
-                writer.write_all(&stream.last_id.to_le_bytes())?;
-                saved_count += 1;
-            }
-            DataType::Geo(geo) => {
-                writer.write_all(&[opcodes::GEO])?;
-                write_string(&mut writer, key)?;
-                write_length(&mut writer, geo.locations.len())?;
-                for (name, loc) in &geo.locations {
-                    write_string(&mut writer, name)?;
-                    writer.write_all(&loc.latitude.to_le_bytes())?;
-                    writer.write_all(&loc.longitude.to_le_bytes())?;
-                }
-                saved_count += 1;
-            }
-            DataType::HyperLogLog(hll) => {
-                writer.write_all(&[opcodes::HYPERLOGLOG])?;
-                write_string(&mut writer, key)?;
-                // Write registers (fixed size array)
-                write_length(&mut writer, hll.registers.len())?;
-                for &reg in &hll.registers {
-                    writer.write_all(&[reg])?;
-                }
-                saved_count += 1;
+        let opcode = opcode_of(&entry.value);
+        self.saved_count += 1;
+        if self.compress_at == 0 {
+            self.writer.write_all(&[opcode])?;
+            write_string(&mut self.writer, key)?;
+            return write_value(&mut self.writer, &entry.value);
+        }
+
+        let mut body = Vec::new();
+        write_value(&mut body, &entry.value)?;
+        if body.len() >= self.compress_at {
+            let packed = lz4::block::compress(&body, None, false)?;
+            // Incompressible values are stored as they are
+            if packed.len() < body.len() {
+                self.writer.write_all(&[opcodes::COMPRESSED, opcode])?;
+                write_string(&mut self.writer, key)?;
+                write_length(&mut self.writer, body.len())?;
+                write_length(&mut self.writer, packed.len())?;
+                return self.writer.write_all(&packed);
             }
         }
+        self.writer.write_all(&[opcode])?;
+        write_string(&mut self.writer, key)?;
+        self.writer.write_all(&body)
     }
 
-    // Write EOF
-    writer.write_all(&[opcodes::EOF])?;
+    /// Write EOF and the checksum, then `tail`, and give back the writer
+    fn finish(mut self, tail: &[u8]) -> io::Result<W> {
+        self.writer.write_all(&[opcodes::EOF])?;
+        let crc = self.writer.crc();
+        self.writer.get_mut().write_all(&crc.to_le_bytes())?;
+        self.writer.get_mut().write_all(tail)?;
+        self.writer.flush()?;
+        Ok(self.writer.into_inner())
+    }
+}
 
-    writer.flush()?;
-    drop(writer);
+/// RDB file being written to a temporary path, renamed into place when
+/// finished
+struct SnapshotWriter {
+    encoder: RdbEncoder<BufWriter<File>>,
+    temp_path: String,
+    started: std::time::Instant,
+}
 
-    // Atomic rename
-    std::fs::rename(&temp_path, path)?;
+impl SnapshotWriter {
+    fn create(path: &Path) -> io::Result<Self> {
+        if crate::failpoint::hit("snapshot-save") {
+            return Err(io::Error::other("snapshot dropped by failpoint"));
+        }
 
-    info!("RDB snapshot saved: {} keys ({} skipped)", saved_count, skipped_count);
-    Ok(())
-}
+        let started = std::time::Instant::now();
+        let temp_path = format!("{}.tmp", path.display());
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_path)?;
+
+        Ok(SnapshotWriter {
+            encoder: RdbEncoder::new(BufWriter::new(file))?,
+            temp_path,
+            started,
+        })
+    }
 
-/// Load database from RDB file
-pub async fn load<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<usize> {
-    if !path.as_ref().exists() {
-        return Ok(0);
+    fn write_entry(&mut self, key: &str, entry: &Entry) -> io::Result<()> {
+        self.encoder.write_entry(key, entry)
+    }
+
+    fn finish(self, path: &Path) -> io::Result<()> {
+        self.finish_with_tail(path, &[])
     }
 
-    let file = File::open(&path)?;
-    let mut reader = BufReader::new(file);
+    fn finish_with_tail(self, path: &Path, tail: &[u8]) -> io::Result<()> {
+        let (saved_count, skipped_count) = (self.encoder.saved_count, self.encoder.skipped_count);
+        let file = self.encoder.finish(tail)?;
+        file.get_ref().sync_all()?;
+        drop(file);
 
-    // Verify magic
-    let mut magic = [0u8; 8];
-    reader.read_exact(&mut magic)?;
-    
-    // Support both v1 and v2 formats
-    if &magic != RDB_MAGIC && &magic != b"HEXRDB01" {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid RDB magic"));
+        // Atomic rename
+        std::fs::rename(&self.temp_path, path)?;
+
+        crate::latency::record(crate::latency::EVENT_SAVE, self.started.elapsed());
+        info!("RDB snapshot saved: {} keys ({} skipped)", saved_count, skipped_count);
+        Ok(())
     }
-    let is_v2 = &magic == RDB_MAGIC;
+}
 
-    let mut count = 0;
-    let mut pending_expire: Option<u64> = None;
+/// A key read from an RDB file
+pub struct RdbEntry {
+    pub key: String,
+    /// Remaining TTL in milliseconds when the snapshot was taken
+    pub ttl_ms: Option<u64>,
+    pub value: DataType,
+    /// Expiry times of the hash fields that have one
+    pub field_expires: HashMap<String, std::time::Instant>,
+}
 
-    loop {
-        let mut opcode = [0u8; 1];
-        if reader.read(&mut opcode)? == 0 {
-            break;
-        }
+/// Sequential reader over the keys of an RDB file
+pub struct RdbReader<R: Read> {
+    reader: Crc64Reader<R>,
+    version: u8,
+}
 
-        match opcode[0] {
-            opcodes::EOF => break,
-            opcodes::EXPIRE => {
-                let mut ttl_bytes = [0u8; 8];
-                reader.read_exact(&mut ttl_bytes)?;
-                pending_expire = Some(u64::from_le_bytes(ttl_bytes));
-            }
-            opcodes::STRING => {
-                let key = read_string(&mut reader)?;
-                let value = read_string(&mut reader)?;
-
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::String(value),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::LIST => {
-                let key = read_string(&mut reader)?;
-                let len = read_length(&mut reader)?;
-                let mut list = Vec::with_capacity(len);
-                for _ in 0..len {
-                    list.push(read_string(&mut reader)?);
-                }
+impl RdbReader<BufReader<File>> {
+    /// Open an RDB file and verify its magic
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
 
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::List(list),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::SET => {
-                let key = read_string(&mut reader)?;
-                let len = read_length(&mut reader)?;
-                let mut set = std::collections::HashSet::with_capacity(len);
-                for _ in 0..len {
-                    set.insert(read_string(&mut reader)?);
-                }
+impl<R: Read> RdbReader<R> {
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut reader = Crc64Reader::new(reader);
+
+        // Verify magic
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic).map_err(describe)?;
+
+        let version = match &magic[..] {
+            RDB_MAGIC => 4,
+            RDB_MAGIC_V3 => 3,
+            RDB_MAGIC_V2 => 2,
+            RDB_MAGIC_V1 => 1,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid RDB magic")),
+        };
+        Ok(RdbReader { reader, version })
+    }
 
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::Set(set),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::HASH => {
-                let key = read_string(&mut reader)?;
-                let len = read_length(&mut reader)?;
-                let mut hash = std::collections::HashMap::with_capacity(len);
-                for _ in 0..len {
-                    let field = read_string(&mut reader)?;
-                    let value = read_string(&mut reader)?;
-                    hash.insert(field, value);
-                }
+    /// Read the next key, or None at the end of the file. Values of an
+    /// unknown encoding are skipped with a warning. For version 3 files the
+    /// checksum is verified when the end is reached.
+    pub fn next_entry(&mut self) -> io::Result<Option<RdbEntry>> {
+        self.read_entry().map_err(describe)
+    }
 
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::Hash(hash),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::ZSET => {
-                let key = read_string(&mut reader)?;
-                let len = read_length(&mut reader)?;
-                let mut zset = ZSetData::new();
-                for _ in 0..len {
-                    let member = read_string(&mut reader)?;
-                    let mut score_bytes = [0u8; 8];
-                    reader.read_exact(&mut score_bytes)?;
-                    let score = f64::from_le_bytes(score_bytes);
-                    zset.insert(member, score);
-                }
+    fn read_entry(&mut self) -> io::Result<Option<RdbEntry>> {
+        let mut pending_expire: Option<u64> = None;
+        let mut pending_field_expires = HashMap::new();
 
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::ZSet(zset),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::BITMAP if is_v2 => {
-                let key = read_string(&mut reader)?;
-                let len = read_length(&mut reader)?;
-                let mut data = vec![0u8; len];
-                reader.read_exact(&mut data)?;
-
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::Bitmap(data),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
+        loop {
+            let mut opcode = [0u8; 1];
+            if self.reader.read(&mut opcode)? == 0 {
+                // Older files may end without an EOF opcode
+                if self.version >= 3 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                return Ok(None);
             }
-            opcodes::STREAM if is_v2 => {
-                let key = read_string(&mut reader)?;
-                let entry_count = read_length(&mut reader)?;
-                
-                let mut stream = StreamData::new();
-                for _ in 0..entry_count {
-                    let id = read_string(&mut reader)?;
-                    let mut ts_bytes = [0u8; 8];
-                    reader.read_exact(&mut ts_bytes)?;
-                    let timestamp = u64::from_le_bytes(ts_bytes);
-                    let field_count = read_length(&mut reader)?;
-                    let mut fields = std::collections::HashMap::new();
-                    for _ in 0..field_count {
-                        let field = read_string(&mut reader)?;
-                        let value = read_string(&mut reader)?;
-                        fields.insert(field, value);
+
+            match opcode[0] {
+                opcodes::EOF => {
+                    if self.version >= 3 {
+                        self.verify_checksum()?;
                     }
-                    stream.entries.push(crate::db::types::StreamEntry { id, fields, timestamp });
+                    return Ok(None);
                 }
-                // Read last_id as u64
-                let mut last_id_bytes = [0u8; 8];
-                reader.read_exact(&mut last_id_bytes)?;
-                stream.last_id = u64::from_le_bytes(last_id_bytes);
-
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::Stream(stream),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::GEO if is_v2 => {
-                let key = read_string(&mut reader)?;
-                let loc_count = read_length(&mut reader)?;
-                
-                let mut geo = GeoData::new();
-                for _ in 0..loc_count {
-                    let name = read_string(&mut reader)?;
-                    let mut lat_bytes = [0u8; 8];
-                    let mut lon_bytes = [0u8; 8];
-                    reader.read_exact(&mut lat_bytes)?;
-                    reader.read_exact(&mut lon_bytes)?;
-                    let lat = f64::from_le_bytes(lat_bytes);
-                    let lon = f64::from_le_bytes(lon_bytes);
-                    geo.locations.insert(name, crate::db::types::GeoLocation {
-                        latitude: lat,
-                        longitude: lon,
-                    });
+                opcodes::EXPIRE => {
+                    pending_expire = Some(read_u64(&mut self.reader)?);
                 }
-
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::Geo(geo),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            opcodes::HYPERLOGLOG if is_v2 => {
-                let key = read_string(&mut reader)?;
-                let reg_count = read_length(&mut reader)?;
-                
-                let mut hll = HyperLogLogData::new();
-                if reg_count == hll.registers.len() {
-                    for i in 0..reg_count {
-                        let mut reg = [0u8; 1];
-                        reader.read_exact(&mut reg)?;
-                        hll.registers[i] = reg[0];
+                opcodes::FIELD_EXPIRES if self.version >= 4 => {
+                    pending_field_expires = read_field_expires(&mut self.reader)?;
+                }
+                opcodes::COMPRESSED if self.version >= 3 => {
+                    let mut opcode = [0u8; 1];
+                    self.reader.read_exact(&mut opcode)?;
+                    let key = read_string(&mut self.reader)?;
+                    let raw_len = read_length(&mut self.reader)?;
+                    let mut packed = vec![0u8; read_length(&mut self.reader)?];
+                    self.reader.read_exact(&mut packed)?;
+                    let body = i32::try_from(raw_len)
+                        .ok()
+                        .and_then(|len| lz4::block::decompress(&packed, Some(len)).ok())
+                        .ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("RDB value for key {} does not decompress", key),
+                            )
+                        })?;
+                    let (ttl_ms, field_expires) = (pending_expire.take(), std::mem::take(&mut pending_field_expires));
+                    if let Some(entry) = read_entry_value(&mut Cursor::new(body), key, ttl_ms, field_expires, opcode[0])? {
+                        return Ok(Some(entry));
                     }
-                } else {
-                    warn!("HyperLogLog register count mismatch, skipping key {}", key);
-                    // Skip remaining bytes
-                    for _ in 0..reg_count {
-                        let mut reg = [0u8; 1];
-                        reader.read_exact(&mut reg)?;
+                }
+                // v1 files only know the original five types
+                opcode @ opcodes::STRING..=opcodes::VECTOR
+                    if self.version >= 2 || opcode <= opcodes::HASH =>
+                {
+                    let key = read_string(&mut self.reader)?;
+                    let (ttl_ms, field_expires) = (pending_expire.take(), std::mem::take(&mut pending_field_expires));
+                    if let Some(entry) = read_entry_value(&mut self.reader, key, ttl_ms, field_expires, opcode)? {
+                        return Ok(Some(entry));
                     }
-                    pending_expire = None;
-                    continue;
                 }
-
-                let mut db_guard = db.write().await;
-                let expires_at = pending_expire.map(|ms| {
-                    std::time::Instant::now() + std::time::Duration::from_millis(ms)
-                });
-                db_guard.items.insert(
-                    key,
-                    Entry {
-                        value: DataType::HyperLogLog(hll),
-                        expires_at,
-                    },
-                );
-                pending_expire = None;
-                count += 1;
-            }
-            _ => {
-                error!("Unknown RDB opcode: {} (v2: {})", opcode[0], is_v2);
-                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unknown opcode: {}", opcode[0])));
+                _ => {
+                    error!("Unknown RDB opcode: {} (version {})", opcode[0], self.version);
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Unknown opcode: {}", opcode[0]),
+                    ));
+                }
             }
         }
     }
 
-    info!("Loaded {} keys from RDB", count);
-    Ok(count)
+    /// Compare the checksum trailing the EOF opcode with the data read
+    fn verify_checksum(&mut self) -> io::Result<()> {
+        let computed = self.reader.crc();
+        let stored = read_u64(self.reader.get_mut())?;
+        if stored != computed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "RDB checksum mismatch (stored {:016x}, computed {:016x}): the file is corrupt",
+                    stored, computed
+                ),
+            ));
+        }
+        Ok(())
+    }
 }
 
-// Helper functions for reading/writing
+/// Read the value of `key`, or None if it was skipped as unreadable
+fn read_entry_value<R: Read>(
+    reader: &mut R,
+    key: String,
+    ttl_ms: Option<u64>,
+    field_expires: HashMap<String, std::time::Instant>,
+    opcode: u8,
+) -> io::Result<Option<RdbEntry>> {
+    match read_value(reader, opcode) {
+        Ok(Some(value)) => Ok(Some(RdbEntry { key, ttl_ms, value, field_expires })),
+        Ok(None) => {
+            warn!("Skipping unreadable value for key {}", key);
+            Ok(None)
+        }
+        Err(e) => {
+            error!("Failed to read RDB key {}: {}", key, e);
+            Err(e)
+        }
+    }
+}
 
-fn write_string<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
-    let bytes = s.as_bytes();
-    write_length(writer, bytes.len())?;
-    writer.write_all(bytes)?;
-    Ok(())
+/// Give a truncated file a descriptive error
+fn describe(e: io::Error) -> io::Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "RDB file is truncated: it ends before the EOF marker",
+        )
+    } else {
+        e
+    }
 }
 
-fn read_string<R: Read>(reader: &mut R) -> io::Result<String> {
-    let len = read_length(reader)?;
-    let mut buf = vec![0u8; len];
-    reader.read_exact(&mut buf)?;
-    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+/// Load database from RDB file
+pub async fn load<P: AsRef<Path>>(path: P, db: &Arc<RwLock<DB>>) -> io::Result<usize> {
+    if !path.as_ref().exists() {
+        return Ok(0);
+    }
+
+    let mut reader = RdbReader::open(&path)?;
+    let mut count = 0;
+
+    while let Some(entry) = reader.next_entry()? {
+        insert_entry(&mut *db.write().await, entry);
+        count += 1;
+    }
+
+    info!("Loaded {} keys from RDB", count);
+    Ok(count)
 }
 
-fn write_length<W: Write>(writer: &mut W, len: usize) -> io::Result<()> {
-    let len = len as u32;
-    writer.write_all(&len.to_le_bytes())?;
-    Ok(())
+/// Add a key read from an RDB file to the database
+pub(crate) fn insert_entry(db_guard: &mut DB, entry: RdbEntry) {
+    let RdbEntry { key, ttl_ms, value, field_expires } = entry;
+    let expires_at =
+        ttl_ms.map(|ms| std::time::Instant::now() + std::time::Duration::from_millis(ms));
+    db_guard.items.insert(
+        key.clone(),
+        Entry {
+            value,
+            expires_at,
+            field_expires: (!field_expires.is_empty()).then(|| Box::new(field_expires)),
+        },
+    );
+    db_guard.track_entry(&key);
 }
 
-fn read_length<R: Read>(reader: &mut R) -> io::Result<usize> {
-    let mut buf = [0u8; 4];
-    reader.read_exact(&mut buf)?;
-    Ok(u32::from_le_bytes(buf) as usize)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{GenericOps, StringOps};
+
+    fn temp_rdb() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("hexagondb-snapshot-{}.rdb", uuid::Uuid::new_v4()))
+    }
+
+    fn read_all(path: &Path) -> io::Result<Vec<RdbEntry>> {
+        let mut reader = RdbReader::open(path)?;
+        let mut entries = Vec::new();
+        while let Some(entry) = reader.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    #[test]
+    fn test_compression_and_checksum() {
+        let path = temp_rdb();
+        let mut db = DB::new();
+        db.set("big".to_string(), "x".repeat(10_000));
+        db.set("small".to_string(), "y".to_string());
+        db.expire("big", 100);
+
+        set_compression(Some(64));
+        save_db(&path, &db).unwrap();
+        set_compression(None);
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(bytes.len() < 1000);
+
+        let mut entries = read_all(&path).unwrap();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(entries.len(), 2);
+        assert!(matches!(&entries[0].value, DataType::String(s) if s.len() == 10_000));
+        assert!(entries[0].ttl_ms.is_some());
+
+        // A flipped byte ("y" becomes "x") fails the checksum
+        let mut corrupt = bytes.clone();
+        let at = corrupt.windows(5).position(|w| w == b"\x01\x00\x00\x00y").unwrap() + 4;
+        corrupt[at] ^= 0x01;
+        std::fs::write(&path, &corrupt).unwrap();
+        let err = read_all(&path).err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+
+        // So does a file cut short
+        std::fs::write(&path, &bytes[..bytes.len() - 20]).unwrap();
+        let err = read_all(&path).err().unwrap();
+        assert!(err.to_string().contains("truncated"), "{}", err);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_field_expires_roundtrip() {
+        use crate::db::ops::generic::ExpireCondition;
+        use crate::db::HashOps;
+
+        let path = temp_rdb();
+        let mut db = DB::new();
+        db.hset("h".to_string(), "a".to_string(), "1".to_string()).unwrap();
+        db.hset("h".to_string(), "b".to_string(), "2".to_string()).unwrap();
+        let at = std::time::Instant::now() + std::time::Duration::from_secs(100);
+        db.hexpire("h".to_string(), at, ExpireCondition::Always, vec!["a".to_string()]).unwrap();
+        save_db(&path, &db).unwrap();
+
+        let mut loaded = DB::new();
+        for entry in read_all(&path).unwrap() {
+            insert_entry(&mut loaded, entry);
+        }
+        let ttls = loaded.hpttl("h", vec!["a".to_string(), "b".to_string()]).unwrap();
+        assert!(ttls[0] > 90_000 && ttls[0] <= 100_000, "{:?}", ttls);
+        assert_eq!(ttls[1], -1);
+        assert!(loaded.hash_field_ttl_keys.contains("h"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reads_v2_files() {
+        let path = temp_rdb();
+        let mut bytes = RDB_MAGIC_V2.to_vec();
+        bytes.push(opcodes::STRING);
+        write_string(&mut bytes, "k").unwrap();
+        write_string(&mut bytes, "v").unwrap();
+        bytes.push(opcodes::EOF);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let entries = read_all(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(&entries[0].value, DataType::String(s) if s == "v"));
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
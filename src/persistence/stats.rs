@@ -0,0 +1,187 @@
+//! Offline AOF and RDB analysis.
+//!
+//! Backs `hexagondb-cli aof-stats` and `rdb-stats`: reads a persistence file
+//! with the same readers the server uses on startup and summarizes it
+//! without a running server.
+
+use crate::commands::command_keys;
+use crate::db::introspect;
+use crate::db::types::DataType;
+use crate::persistence::aof::{read_records, AOF_FORMAT_MARKER, AOF_SEGMENTS_MARKER};
+use crate::persistence::snapshot::RdbReader;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::Path;
+
+/// Largest records kept in a summary
+pub const TOP_RECORDS: usize = 10;
+
+/// A record ranked by size
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LargeRecord {
+    pub bytes: usize,
+    /// Command name (AOF) or value type (RDB)
+    pub kind: String,
+    pub key: String,
+}
+
+/// Keep the `TOP_RECORDS` largest records, largest first
+fn push_largest(largest: &mut Vec<LargeRecord>, record: LargeRecord) {
+    if largest.len() == TOP_RECORDS && largest.last().is_some_and(|l| l.bytes >= record.bytes) {
+        return;
+    }
+    let pos = largest.partition_point(|l| l.bytes >= record.bytes);
+    largest.insert(pos, record);
+    largest.truncate(TOP_RECORDS);
+}
+
+/// Summary of an AOF file
+#[derive(Debug, Clone, Default)]
+pub struct AofStats {
+    pub records: usize,
+    /// Payload bytes (sum of argument lengths)
+    pub bytes: usize,
+    /// Records per command name
+    pub commands: BTreeMap<String, usize>,
+    /// Distinct keys written
+    pub keys: usize,
+    pub largest: Vec<LargeRecord>,
+}
+
+/// Summarize an AOF file
+pub fn aof_stats<P: AsRef<Path>>(path: P) -> io::Result<AofStats> {
+    let mut stats = AofStats::default();
+    let mut keys = HashSet::new();
+
+    for args in read_records(path)? {
+        let cmd = args[0].to_uppercase();
+        if cmd == AOF_FORMAT_MARKER || cmd == AOF_SEGMENTS_MARKER {
+            continue;
+        }
+        let bytes = args.iter().map(String::len).sum();
+        stats.records += 1;
+        stats.bytes += bytes;
+        *stats.commands.entry(cmd.clone()).or_default() += 1;
+
+        if let Some(key) = command_keys(&cmd, &args[1..]).first().map(|k| k.to_string()) {
+            keys.insert(key.clone());
+            push_largest(
+                &mut stats.largest,
+                LargeRecord {
+                    bytes,
+                    kind: cmd,
+                    key: key.clone(),
+                },
+            );
+        }
+    }
+
+    stats.keys = keys.len();
+    Ok(stats)
+}
+
+/// Summary of an RDB snapshot
+#[derive(Debug, Clone, Default)]
+pub struct RdbStats {
+    pub keys: usize,
+    /// Keys with a TTL
+    pub expires: usize,
+    /// Serialized value bytes
+    pub bytes: usize,
+    /// (keys, bytes) per value type
+    pub types: BTreeMap<String, (usize, usize)>,
+    pub largest: Vec<LargeRecord>,
+}
+
+fn type_name(value: &DataType) -> &'static str {
+    match value {
+        DataType::String(_) => "string",
+        DataType::List(_) => "list",
+        DataType::Hash(_) => "hash",
+        DataType::Set(_) => "set",
+        DataType::ZSet(_) => "zset",
+        DataType::Stream(_) => "stream",
+        DataType::Bitmap(_) => "bitmap",
+        DataType::Geo(_) => "geo",
+        DataType::HyperLogLog(_) => "hyperloglog",
+        DataType::Queue(_) => "queue",
+        DataType::TimeSeries(_) => "timeseries",
+        DataType::Bloom(_) => "bloom",
+        DataType::Cuckoo(_) => "cuckoo",
+        DataType::Vector(_) => "vector",
+    }
+}
+
+/// Summarize an RDB snapshot
+pub fn rdb_stats<P: AsRef<Path>>(path: P) -> io::Result<RdbStats> {
+    let mut stats = RdbStats::default();
+    let mut reader = RdbReader::open(path)?;
+
+    while let Some(entry) = reader.next_entry()? {
+        let bytes = introspect::serialized_length(&entry.value);
+        let kind = type_name(&entry.value);
+        stats.keys += 1;
+        stats.bytes += bytes;
+        if entry.ttl_ms.is_some() {
+            stats.expires += 1;
+        }
+        let per_type = stats.types.entry(kind.to_string()).or_default();
+        per_type.0 += 1;
+        per_type.1 += bytes;
+        push_largest(
+            &mut stats.largest,
+            LargeRecord {
+                bytes,
+                kind: kind.to_string(),
+                key: entry.key,
+            },
+        );
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{GenericOps, ListOps, StringOps, DB};
+    use crate::persistence::aof::Aof;
+    use crate::persistence::snapshot;
+
+    fn cmd(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_persistence_stats() {
+        let dir = std::env::temp_dir().join(format!("hexagondb-stats-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let aof_path = dir.join("appendonly.aof");
+        let mut aof = Aof::new(&aof_path).unwrap();
+        aof.append(cmd(&["SET", "a", "1"])).unwrap();
+        aof.append(cmd(&["SET", "a", "2"])).unwrap();
+        aof.append(cmd(&["RPUSH", "big", "xxxxxxxxxx", "yyyyyyyyyy"])).unwrap();
+        aof.append(cmd(&["FLUSHDB"])).unwrap();
+        aof.fsync().unwrap();
+        let stats = aof_stats(&aof_path).unwrap();
+        assert_eq!(stats.records, 4);
+        assert_eq!(stats.commands["SET"], 2);
+        assert_eq!(stats.keys, 2);
+        assert_eq!(stats.largest[0].key, "big");
+        assert_eq!(stats.largest.len(), 3);
+
+        let rdb_path = dir.join("dump.rdb");
+        let mut db = DB::new();
+        db.set("s".to_string(), "v".to_string());
+        db.expire("s", 100);
+        db.rpush("l".to_string(), vec!["x".repeat(100)]).unwrap();
+        snapshot::save_db(&rdb_path, &db).unwrap();
+        let stats = rdb_stats(&rdb_path).unwrap();
+        assert_eq!((stats.keys, stats.expires), (2, 1));
+        assert_eq!(stats.types["list"].0, 1);
+        assert_eq!(stats.largest[0].key, "l");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,6 +2,8 @@
 //!
 //! Provides master-slave replication support.
 
+use crate::config::ReplicationConfig;
+use crate::db::ops::generic::glob_match;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering};
@@ -52,6 +54,62 @@ pub enum SlaveState {
     Disconnected,
 }
 
+/// Write commands that take no key; only the command filter applies to them
+const KEYLESS_WRITES: &[&str] = &["FLUSHDB", "FLUSHALL", "PUBLISH", "EXPIREMANY", "MIGRATE"];
+
+/// Decides which writes are left out of the replication stream
+#[derive(Debug, Clone, Default)]
+pub struct ReplicationFilter {
+    exclude_keys: Vec<String>,
+    /// Uppercased command names
+    exclude_commands: Vec<String>,
+}
+
+impl ReplicationFilter {
+    pub fn from_config(config: &ReplicationConfig) -> Self {
+        ReplicationFilter {
+            exclude_keys: config.exclude_keys.clone(),
+            exclude_commands: config.exclude_commands.iter().map(|c| c.to_uppercase()).collect(),
+        }
+    }
+
+    fn excludes_key(&self, key: &str) -> bool {
+        self.exclude_keys.iter().any(|pattern| glob_match(pattern, key))
+    }
+
+    /// The command to propagate, or None if it is filtered out entirely.
+    /// Multi-key DEL/UNLINK and MSET/MSETNX lose their excluded keys and are
+    /// dropped only when none are left; other commands are judged by their key.
+    pub fn apply(&self, command: Vec<String>) -> Option<Vec<String>> {
+        let name = command.first()?.to_uppercase();
+        if self.exclude_commands.contains(&name) {
+            return None;
+        }
+        if self.exclude_keys.is_empty() || command.len() < 2 || KEYLESS_WRITES.contains(&name.as_str()) {
+            return Some(command);
+        }
+
+        match name.as_str() {
+            "DEL" | "UNLINK" => {
+                let mut kept = vec![command[0].clone()];
+                kept.extend(command[1..].iter().filter(|k| !self.excludes_key(k)).cloned());
+                (kept.len() > 1).then_some(kept)
+            }
+            "MSET" | "MSETNX" => {
+                let mut kept = vec![command[0].clone()];
+                for pair in command[1..].chunks(2) {
+                    if !self.excludes_key(&pair[0]) {
+                        kept.extend(pair.iter().cloned());
+                    }
+                }
+                (kept.len() > 1).then_some(kept)
+            }
+            _ if self.excludes_key(&command[1]) => None,
+            _ => Some(command),
+        }
+    }
+}
+
 /// Replication manager
 pub struct ReplicationManager {
     /// Current role
@@ -72,6 +130,8 @@ pub struct ReplicationManager {
     active: AtomicBool,
     /// Command broadcast channel for slaves
     command_tx: broadcast::Sender<ReplicationCommand>,
+    /// Writes left out of the stream
+    filter: RwLock<ReplicationFilter>,
 }
 
 /// Command to replicate
@@ -113,6 +173,7 @@ impl ReplicationManager {
             backlog: RwLock::new(ReplicationBacklog::default()),
             active: AtomicBool::new(false),
             command_tx: tx,
+            filter: RwLock::new(ReplicationFilter::default()),
         }
     }
 
@@ -185,11 +246,21 @@ impl ReplicationManager {
         self.slaves.read().values().cloned().collect()
     }
 
-    /// Add command to replication stream (called by master on writes)
+    /// Replace the replication filter (at startup and on config reload)
+    pub fn set_filter(&self, filter: ReplicationFilter) {
+        *self.filter.write() = filter;
+    }
+
+    /// Add command to replication stream (called by master on writes).
+    /// Commands excluded by the filter are not propagated and take no offset.
     pub fn replicate_command(&self, command: Vec<String>) {
         if *self.role.read() != ReplicationRole::Master {
             return;
         }
+        let command = match self.filter.read().apply(command) {
+            Some(command) => command,
+            None => return,
+        };
 
         let offset = self.repl_offset.fetch_add(1, Ordering::SeqCst);
         
@@ -211,6 +282,9 @@ impl ReplicationManager {
         }
 
         // Broadcast to slaves
+        if crate::failpoint::hit("repl-broadcast") {
+            return;
+        }
         let _ = self.command_tx.send(cmd);
     }
 
@@ -331,4 +405,27 @@ mod tests {
         manager.remove_slave("slave1");
         assert_eq!(manager.list_slaves().len(), 0);
     }
+
+    #[test]
+    fn test_replication_filter() {
+        let filter = ReplicationFilter::from_config(&ReplicationConfig {
+            exclude_keys: vec!["local:*".to_string()],
+            exclude_commands: vec!["debug".to_string()],
+        });
+        let cmd = |parts: &[&str]| parts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(filter.apply(cmd(&["DEBUG", "SLEEP", "1"])), None);
+        assert_eq!(filter.apply(cmd(&["SET", "local:tmp", "1"])), None);
+        assert_eq!(filter.apply(cmd(&["SET", "user:1", "1"])), Some(cmd(&["SET", "user:1", "1"])));
+        assert_eq!(filter.apply(cmd(&["DEL", "local:a", "b"])), Some(cmd(&["DEL", "b"])));
+        assert_eq!(filter.apply(cmd(&["MSET", "local:a", "1", "b", "2"])), Some(cmd(&["MSET", "b", "2"])));
+        assert_eq!(filter.apply(cmd(&["FLUSHDB"])), Some(cmd(&["FLUSHDB"])));
+
+        let manager = ReplicationManager::new();
+        manager.set_filter(filter);
+        manager.replicate_command(cmd(&["SET", "local:tmp", "1"]));
+        assert_eq!(manager.offset(), 0);
+        manager.replicate_command(cmd(&["SET", "user:1", "1"]));
+        assert_eq!(manager.offset(), 1);
+    }
 }
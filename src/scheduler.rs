@@ -0,0 +1,463 @@
+//! Scheduled commands (SCHEDULE).
+//!
+//! A job runs a command on a cron schedule (`*/5 * * * *`, in UTC), every
+//! N seconds (`@every 30`) or whenever a key matching a pattern expires
+//! (`@expire session:*`, with `{key}` in the command replaced by the key).
+//! Jobs are stored in the `__schedule__` hash, so they are saved, replayed
+//! and copied along with the rest of the keyspace. The key is reserved:
+//! commands other than SCHEDULE cannot read or write it.
+//!
+//! A background task checks the jobs once a second and runs due ones as the
+//! user who added them, so ACL rules apply as if that user had sent the
+//! command; jobs added without a user run as the default user. Keys only
+//! expire when they are read, so while an `@expire` job exists the task also
+//! expires the keys it watches whose TTL has run out. Every run is appended
+//! to a stream (`[scheduler] log_stream_key`) with the job, the command and
+//! its reply.
+
+use crate::cli::parser::parse_command;
+use crate::commands::{ExecutionResult, Interpreter};
+use crate::config::SchedulerConfig;
+use crate::db::ops::generic::glob_match;
+use crate::db::{GenericOps, HashOps, StreamOps, StreamTrim, DB};
+use crate::network::resp::RespValue;
+use crate::persistence::loading::LoadProgress;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, RwLock};
+
+/// Hash holding the jobs, by name
+pub const SCHEDULE_KEY: &str = "__schedule__";
+
+/// Expired keys waiting for the scheduler; later ones are dropped
+pub const EXPIRED_QUEUE_LEN: usize = 10_000;
+
+/// When a job runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    Cron(CronSpec),
+    /// Every this many seconds
+    Every(u64),
+    /// When a key matching the pattern expires
+    Expire(String),
+}
+
+impl Trigger {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        if let Some(secs) = spec.strip_prefix("@every ") {
+            return match secs.trim().parse::<u64>() {
+                Ok(secs) if secs > 0 => Ok(Trigger::Every(secs)),
+                _ => Err("@every needs a positive number of seconds".to_string()),
+            };
+        }
+        if let Some(pattern) = spec.strip_prefix("@expire ") {
+            return match pattern.trim() {
+                "" => Err("@expire needs a key pattern".to_string()),
+                pattern => Ok(Trigger::Expire(pattern.to_string())),
+            };
+        }
+        CronSpec::parse(spec).map(Trigger::Cron)
+    }
+}
+
+/// A five-field cron expression: minute, hour, day of month, month and day
+/// of week (0 or 7 is Sunday). Fields take `*`, numbers, ranges `a-b`,
+/// steps `*/n` or `a-b/n`, and comma-separated lists of those.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSpec {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day of month and day of week were both restricted, in which
+    /// case matching either is enough
+    either_day: bool,
+}
+
+impl CronSpec {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("invalid cron expression '{}': expected 5 fields", spec));
+        };
+        let mut weekday_bits = parse_field(weekdays, 0, 7)?;
+        // 7 is another name for Sunday
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits | 1) & !(1 << 7);
+        }
+        Ok(CronSpec {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            either_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+        })
+    }
+
+    /// Whether the spec matches the minute containing `unix_secs`
+    pub fn matches(&self, unix_secs: u64) -> bool {
+        let days = unix_secs / 86_400;
+        let secs = unix_secs % 86_400;
+        let (_, month, day) = civil_from_days(days as i64);
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4) % 7;
+        let bit = |mask: u64, n: u64| mask & (1 << n) != 0;
+        let day_matches = if self.either_day {
+            bit(self.days, day) || bit(self.weekdays, weekday)
+        } else {
+            bit(self.days, day) && bit(self.weekdays, weekday)
+        };
+        bit(self.minutes, secs / 60 % 60) && bit(self.hours, secs / 3600) && bit(self.months, month) && day_matches
+    }
+}
+
+/// Bitmask of the values a cron field allows
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+    let invalid = || format!("invalid cron field '{}'", field);
+    let number = |s: &str| s.parse::<u64>().ok().filter(|n| (min..=max).contains(n)).ok_or_else(invalid);
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>().ok().filter(|&s| s > 0).ok_or_else(invalid)?),
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                // `n/step` runs from n to the end of the range
+                None if item.contains('/') => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+/// Year, month and day of a number of days since the epoch
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month as u64, day as u64)
+}
+
+/// A scheduled command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub name: String,
+    /// The trigger as given, e.g. `*/5 * * * *`
+    pub spec: String,
+    pub trigger: Trigger,
+    /// The command line, with arguments quoted as in the CLI
+    pub command: String,
+    /// ACL user the job runs as (None = the default user)
+    pub owner: Option<String>,
+}
+
+impl Job {
+    pub fn new(name: &str, spec: &str, command: &str) -> Result<Self, String> {
+        let trigger = Trigger::parse(spec)?;
+        if spec.contains('\n') || command.contains('\n') {
+            return Err("the trigger and command must be on one line".to_string());
+        }
+        if parse_command(command).is_empty() {
+            return Err("the command is empty".to_string());
+        }
+        Ok(Job {
+            name: name.to_string(),
+            spec: spec.trim().to_string(),
+            trigger,
+            command: command.to_string(),
+            owner: None,
+        })
+    }
+
+    /// Run the job as `owner`
+    pub fn with_owner(mut self, owner: Option<String>) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    /// Parse a job from its `__schedule__` field and value: the trigger, the
+    /// command and, if the job has one, its owner, one per line
+    pub fn decode(name: &str, value: &str) -> Result<Self, String> {
+        let mut lines = value.splitn(3, '\n');
+        let spec = lines.next().unwrap_or_default();
+        let command = lines.next().ok_or("missing command")?;
+        let owner = lines.next().map(str::to_string);
+        Ok(Self::new(name, spec, command)?.with_owner(owner))
+    }
+
+    /// Value stored in `__schedule__`
+    pub fn encode(&self) -> String {
+        match &self.owner {
+            Some(owner) => format!("{}\n{}\n{}", self.spec, self.command, owner),
+            None => format!("{}\n{}", self.spec, self.command),
+        }
+    }
+
+    /// Arguments of the command, with `{key}` replaced by `key` if given
+    pub fn args(&self, key: Option<&str>) -> Vec<String> {
+        parse_command(&self.command)
+            .into_iter()
+            .map(|arg| match key {
+                Some(key) => arg.replace("{key}", key),
+                None => arg,
+            })
+            .collect()
+    }
+}
+
+/// The stored jobs; ones that no longer parse are skipped
+pub fn jobs(db: &mut DB) -> Vec<Job> {
+    let Ok(fields) = db.hgetall(SCHEDULE_KEY) else {
+        return Vec::new();
+    };
+    fields
+        .chunks(2)
+        .filter_map(|pair| match pair {
+            [name, value] => Job::decode(name, value).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Decides which jobs are due
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// Per job, its trigger and the minute (cron) or second (`@every`) it
+    /// last ran or was first seen
+    last_run: HashMap<String, (String, u64)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time-triggered jobs due at `unix_secs`. A cron job runs at most once
+    /// per minute; an `@every` job first runs one interval after it is seen.
+    pub fn due<'a>(&mut self, jobs: &'a [Job], unix_secs: u64) -> Vec<&'a Job> {
+        self.last_run.retain(|name, _| jobs.iter().any(|job| &job.name == name));
+        let mut due = Vec::new();
+        for job in jobs {
+            let last = match self.last_run.get(&job.name) {
+                Some((spec, last)) if *spec == job.spec => Some(*last),
+                _ => None,
+            };
+            let mark = match &job.trigger {
+                Trigger::Cron(cron) => {
+                    let minute = unix_secs / 60;
+                    if last == Some(minute) || !cron.matches(unix_secs) {
+                        continue;
+                    }
+                    due.push(job);
+                    minute
+                }
+                Trigger::Every(secs) => match last {
+                    Some(last) if unix_secs < last + secs => continue,
+                    Some(_) => {
+                        due.push(job);
+                        unix_secs
+                    }
+                    None => unix_secs,
+                },
+                Trigger::Expire(_) => continue,
+            };
+            self.last_run.insert(job.name.clone(), (job.spec.clone(), mark));
+        }
+        due
+    }
+}
+
+/// Jobs to run because `key` expired
+pub fn on_expire<'a>(jobs: &'a [Job], key: &str) -> impl Iterator<Item = &'a Job> {
+    let key = key.to_string();
+    jobs.iter()
+        .filter(move |job| matches!(&job.trigger, Trigger::Expire(pattern) if glob_match(pattern, &key)))
+}
+
+/// Expire the keys watched by `@expire` jobs whose TTL has run out, so the
+/// jobs run even if nothing reads them
+pub fn expire_watched(db: &mut DB, jobs: &[Job]) {
+    let patterns: Vec<&str> = jobs
+        .iter()
+        .filter_map(|job| match &job.trigger {
+            Trigger::Expire(pattern) => Some(pattern.as_str()),
+            _ => None,
+        })
+        .collect();
+    if patterns.is_empty() {
+        return;
+    }
+    let now = Instant::now();
+    let expired: Vec<String> = db
+        .items
+        .iter()
+        .filter(|(key, entry)| {
+            entry.expires_at.is_some_and(|at| at <= now) && patterns.iter().any(|p| glob_match(p, key))
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in expired {
+        db.check_expiration(&key);
+    }
+}
+
+/// Text of a reply for the log
+fn describe(reply: &RespValue) -> String {
+    match reply {
+        RespValue::SimpleString(s) => s.clone(),
+        RespValue::Error(e) => format!("ERR {}", e),
+        RespValue::Integer(n) => n.to_string(),
+        RespValue::BulkString(Some(s)) => s.clone(),
+        RespValue::BulkString(None) | RespValue::Array(None) => "(nil)".to_string(),
+        RespValue::Array(Some(items)) => {
+            format!("[{}]", items.iter().map(describe).collect::<Vec<_>>().join(", "))
+        }
+    }
+}
+
+/// Run a job's command as its owner and log it
+pub async fn run_job(
+    interp: &mut Interpreter,
+    db: &Arc<RwLock<DB>>,
+    config: &SchedulerConfig,
+    job: &Job,
+    key: Option<&str>,
+) -> RespValue {
+    let args = job.args(key);
+    let request = RespValue::Array(Some(args.iter().map(|a| RespValue::BulkString(Some(a.clone()))).collect()));
+    let previous = interp.set_user(job.owner.clone());
+    let reply = match interp.execute(request).await {
+        ExecutionResult::Response(reply) => reply,
+        ExecutionResult::Subscribe(..) | ExecutionResult::Block(_) => {
+            RespValue::Error("blocking and subscribe commands cannot be scheduled".to_string())
+        }
+    };
+    interp.set_user(previous);
+    let mut fields = vec![
+        ("job".to_string(), job.name.clone()),
+        ("trigger".to_string(), job.spec.clone()),
+        ("command".to_string(), args.join(" ")),
+        ("reply".to_string(), describe(&reply)),
+    ];
+    if let Some(key) = key {
+        fields.insert(2, ("key".to_string(), key.to_string()));
+    }
+    let mut db = db.write().await;
+    if db.xadd(config.log_stream_key.clone(), None, fields).is_ok() {
+        let _ = db.xtrim(config.log_stream_key.clone(), StreamTrim::MaxLen(config.log_max_len));
+    }
+    reply
+}
+
+/// Run jobs until the process exits. `expired` receives the keys that
+/// expired, from an `on_expire` hook on the database.
+pub async fn run(
+    mut interp: Interpreter,
+    db: Arc<RwLock<DB>>,
+    config: Arc<RwLock<crate::config::Config>>,
+    loading: Arc<LoadProgress>,
+    mut expired: mpsc::Receiver<String>,
+) {
+    let mut scheduler = Scheduler::new();
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        let expired_key = tokio::select! {
+            _ = tick.tick() => None,
+            Some(key) = expired.recv() => Some(key),
+        };
+        let scheduler_config = config.read().await.scheduler.clone();
+        if !scheduler_config.enabled || loading.is_loading() {
+            continue;
+        }
+        let jobs = jobs(&mut *db.write().await);
+        match expired_key {
+            Some(key) => {
+                for job in on_expire(&jobs, &key) {
+                    run_job(&mut interp, &db, &scheduler_config, job, Some(&key)).await;
+                }
+            }
+            None => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                for job in scheduler.due(&jobs, now) {
+                    run_job(&mut interp, &db, &scheduler_config, job, None).await;
+                }
+                expire_watched(&mut *db.write().await, &jobs);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-03-04 (a Monday) 10:15:00 UTC
+    const MONDAY: u64 = 1_709_547_300;
+
+    #[test]
+    fn test_cron_spec() {
+        assert!(CronSpec::parse("* * * * *").unwrap().matches(MONDAY));
+        assert!(CronSpec::parse("*/5 10 * * *").unwrap().matches(MONDAY));
+        assert!(!CronSpec::parse("*/10 * * * *").unwrap().matches(MONDAY));
+        assert!(CronSpec::parse("15 9-11 4 3 1").unwrap().matches(MONDAY));
+        assert!(CronSpec::parse("0,15,30 * * * 1-5").unwrap().matches(MONDAY));
+        assert!(!CronSpec::parse("15 10 * * 0,7").unwrap().matches(MONDAY));
+        // Either the day of month or the day of week may match
+        assert!(CronSpec::parse("15 10 1 * 1").unwrap().matches(MONDAY));
+        assert!(!CronSpec::parse("15 10 1 * 2").unwrap().matches(MONDAY));
+
+        assert!(CronSpec::parse("* * * *").is_err());
+        assert!(CronSpec::parse("60 * * * *").is_err());
+        assert!(CronSpec::parse("*/0 * * * *").is_err());
+        assert!(CronSpec::parse("5-1 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_scheduler_due() {
+        let jobs = vec![
+            Job::new("cron", "*/5 * * * *", "DEL a").unwrap(),
+            Job::new("every", "@every 30", "DEL b").unwrap(),
+            Job::new("expire", "@expire tmp:*", "LPUSH expired {key}").unwrap(),
+        ];
+        let names = |due: Vec<&Job>| due.iter().map(|j| j.name.clone()).collect::<Vec<_>>();
+        let mut scheduler = Scheduler::new();
+        assert_eq!(names(scheduler.due(&jobs, MONDAY)), vec!["cron"]);
+        assert!(scheduler.due(&jobs, MONDAY + 20).is_empty());
+        assert_eq!(names(scheduler.due(&jobs, MONDAY + 30)), vec!["every"]);
+        assert_eq!(names(scheduler.due(&jobs, MONDAY + 300)), vec!["cron", "every"]);
+
+        assert_eq!(on_expire(&jobs, "tmp:1").map(|j| j.args(Some("tmp:1"))).collect::<Vec<_>>(), vec![vec![
+            "LPUSH", "expired", "tmp:1"
+        ]]);
+        assert_eq!(on_expire(&jobs, "other").count(), 0);
+
+        let job = Job::new("quoted", "@every 1", "SET k \"a b\"").unwrap();
+        assert_eq!(Job::decode("quoted", &job.encode()), Ok(job.clone()));
+        assert_eq!(job.args(None), vec!["SET", "k", "a b"]);
+        assert!(Job::new("bad", "@every 0", "DEL a").is_err());
+        assert!(Job::new("bad", "@every 1", "  ").is_err());
+        assert!(Job::new("bad", "@every 1", "PING\nFLUSHALL").is_err());
+
+        let owned = job.clone().with_owner(Some("ops".to_string()));
+        assert_eq!(Job::decode("quoted", &owned.encode()), Ok(owned));
+    }
+}
@@ -4,27 +4,89 @@
 
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use parking_lot::RwLock;
 use tracing::{info, warn};
 use std::hash::{Hash, Hasher};
 use siphasher::sip::SipHasher;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use subtle::ConstantTimeEq;
+use crate::config::SecurityConfig;
 
-/// Hash a password using SipHash (fast, suitable for non-persistent auth)
-/// For persistent storage, consider using bcrypt/argon2 crate
+/// Name of the user that legacy `AUTH <password>` logs in as
+pub const DEFAULT_USER: &str = "default";
+
+/// Prefix of the current hash format (a PHC string, which records the
+/// algorithm version and parameters alongside the salt)
+const HASH_PREFIX: &str = "$argon2id$";
+
+/// Prefix of a hash from before Argon2
+const LEGACY_PREFIX: &str = "$siphash$";
+
+/// Hash a password with Argon2id and a random per-password salt
 pub fn hash_password(password: &str) -> String {
+    let salt: [u8; 16] = rand::random();
+    let salt = SaltString::encode_b64(&salt).expect("16-byte salt is valid");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("default Argon2 parameters are valid")
+        .to_string()
+}
+
+/// Hash from before Argon2: unsalted SipHash, 16 hex digits
+fn legacy_hash(password: &str) -> String {
     let mut hasher = SipHasher::new();
     password.hash(&mut hasher);
-    format!("{:016x}", hasher.finish())
+    format!("{}{:016x}", LEGACY_PREFIX, hasher.finish())
 }
 
-/// Verify a password against a stored hash
+/// Verify a password against a stored hash, an Argon2 PHC string or a
+/// `$siphash$` legacy hash (see `needs_rehash`). Anything else never matches.
 pub fn verify_password(password: &str, stored_hash: &str) -> bool {
-    // If stored_hash looks like a hex hash, compare hashes
-    if stored_hash.len() == 16 && stored_hash.chars().all(|c| c.is_ascii_hexdigit()) {
-        hash_password(password) == stored_hash
+    if stored_hash.starts_with("$argon2") {
+        // Argon2 compares the digests in constant time itself
+        return PasswordHash::new(stored_hash)
+            .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+            .unwrap_or(false);
+    }
+    if stored_hash.starts_with(LEGACY_PREFIX) {
+        return legacy_hash(password).as_bytes().ct_eq(stored_hash.as_bytes()).into();
+    }
+    false
+}
+
+/// The stored form of a `#hash` ACL rule. ACL files saved before Argon2
+/// hold the bare 16 hex digit SipHash, which `#` marks as a hash; it gets
+/// the `$siphash$` prefix here, once, when the rule is loaded.
+fn parse_stored_hash(hash: &str) -> Option<String> {
+    if hash.starts_with("$argon2") {
+        PasswordHash::new(hash).ok().map(|_| hash.to_string())
+    } else if let Some(digest) = hash.strip_prefix(LEGACY_PREFIX) {
+        is_siphash_digest(digest).then(|| hash.to_string())
+    } else {
+        is_siphash_digest(hash).then(|| format!("{}{}", LEGACY_PREFIX, hash))
+    }
+}
+
+fn is_siphash_digest(digest: &str) -> bool {
+    digest.len() == 16 && digest.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Whether a stored hash predates the current format and should be replaced
+/// after the next successful login
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    !stored_hash.starts_with(HASH_PREFIX)
+}
+
+/// Stored form of a configured password: kept if already an Argon2 hash
+fn stored_password(password: String) -> String {
+    if password.starts_with("$argon2") {
+        password
     } else {
-        // Legacy: plain text comparison for backward compatibility
-        password == stored_hash
+        hash_password(&password)
     }
 }
 
@@ -40,6 +102,12 @@ pub struct Security {
     ip_blacklist: RwLock<HashSet<IpAddr>>,
     /// Command rate limiter
     rate_limiter: RwLock<HashMap<String, RateLimitState>>,
+    /// Commands per second allowed to each connection (0 = unlimited)
+    max_commands_per_second: RwLock<f64>,
+    /// Open connections allowed from one IP (0 = unlimited)
+    max_connections_per_ip: AtomicUsize,
+    /// Open connections per IP
+    ip_connections: parking_lot::Mutex<HashMap<IpAddr, usize>>,
     /// Commands that are always allowed without auth
     pub no_auth_commands: HashSet<String>,
 }
@@ -74,6 +142,129 @@ impl Default for User {
     }
 }
 
+impl User {
+    /// ACL rules that recreate this user, as shown by ACL LIST and written by ACL SAVE
+    pub fn rules(&self) -> String {
+        let mut rules = vec![
+            format!("user {}", self.name),
+            if self.enabled { "on" } else { "off" }.to_string(),
+        ];
+        if let Some(hash) = &self.password_hash {
+            rules.push(format!("#{}", hash));
+        }
+        if self.denied_commands.contains("*") {
+            rules.push("-@all".to_string());
+        } else if self.allowed_commands.is_empty() {
+            rules.push("+@all".to_string());
+        }
+        let mut allowed: Vec<_> = self.allowed_commands.iter().collect();
+        allowed.sort();
+        rules.extend(allowed.into_iter().map(|c| format!("+{}", c.to_lowercase())));
+        let mut denied: Vec<_> = self.denied_commands.iter().filter(|c| *c != "*").collect();
+        denied.sort();
+        rules.extend(denied.into_iter().map(|c| format!("-{}", c.to_lowercase())));
+        if self.allowed_keys.is_empty() {
+            rules.push("~*".to_string());
+        }
+        rules.extend(self.allowed_keys.iter().map(|k| format!("~{}", k)));
+        if self.allowed_channels.is_empty() {
+            rules.push("&*".to_string());
+        }
+        rules.extend(self.allowed_channels.iter().map(|c| format!("&{}", c)));
+        rules.join(" ")
+    }
+}
+
+/// ACL command categories (`+@name` / `-@name`) and their commands
+pub const ACL_CATEGORIES: &[(&str, &[&str])] = &[
+    ("keyspace", &[
+        "DEL", "UNLINK", "UNDELETE", "EXISTS", "KEYS", "EXPIRE", "PEXPIRE", "EXPIREAT", "PEXPIREAT", "TTL",
+        "PTTL", "EXPIRETIME", "PERSIST", "TYPE", "RENAME", "COPY", "DUMP", "RESTORE", "MIGRATE",
+        "EXPIREMANY", "DBSIZE", "DBSTATS", "SCAN", "FLUSHDB", "FLUSHALL",
+    ]),
+    ("read", &[
+        "GET", "GETRANGE", "STRLEN", "GETBIT", "BITCOUNT", "EXISTS", "KEYS", "TTL", "PTTL",
+        "EXPIRETIME", "TYPE", "DUMP", "DBSIZE", "DBSTATS", "SCAN", "LLEN", "LRANGE", "LPOS", "HGET", "HGETALL", "HMGET", "HLEN",
+        "HEXISTS", "HKEYS", "HVALS", "HSTRLEN", "HRANDFIELD", "HTTL", "HPTTL", "HSCAN", "SMEMBERS",
+        "SISMEMBER", "SCARD", "SSCAN", "SINTERCARD", "ZRANGE", "ZSCORE", "ZRANK", "ZREVRANK", "ZAROUND", "ZCARD", "ZRANDMEMBER",
+        "ZUNION", "ZINTER",
+        "ZDIFF", "XLEN", "XRANGE", "XREVRANGE", "XREAD", "GEODIST", "PFCOUNT",
+        "TS.GET", "TS.RANGE", "TS.MRANGE", "BF.EXISTS", "BF.MEXISTS", "BF.INFO", "CF.EXISTS", "CF.MEXISTS",
+        "CF.COUNT", "CF.INFO", "VECTOR.GET", "VECTOR.CARD", "VECTOR.SEARCH",
+    ]),
+    ("write", &[
+        "SET", "INCR", "DECR", "INCRBY", "DECRBY", "INCRBYFLOAT", "GETSET", "APPEND", "SETBIT", "DEL", "UNLINK", "UNDELETE", "EXPIRE", "PEXPIRE",
+        "EXPIREAT", "PEXPIREAT", "PERSIST", "RENAME", "COPY", "RESTORE", "MIGRATE", "EXPIREMANY",
+        "FLUSHDB", "FLUSHALL", "LPUSH", "RPUSH", "LPOP", "RPOP", "HSET", "HMSET", "HSETNX", "HDEL",
+        "HINCRBY", "HINCRBYFLOAT", "HEXPIRE", "HPEXPIRE", "HEXPIREAT", "HPEXPIREAT", "HPERSIST", "SADD",
+        "SREM", "ZADD", "ZREM", "ZDIFFSTORE", "XADD", "XTRIM", "GEOADD", "PFADD", "SESSION.CREATE", "SESSION.GET",
+        "SESSION.REFRESH", "PQ.PUSH", "PQ.POP", "PQ.ACK", "LMPOP", "BLMPOP", "ZMPOP", "BZMPOP", "SORT",
+        "TS.CREATE", "TS.ADD", "TS.CREATERULE", "TS.DELETERULE", "BF.RESERVE", "BF.ADD", "BF.MADD",
+        "CF.RESERVE", "CF.ADD", "CF.ADDNX", "CF.DEL", "VECTOR.ADD", "VECTOR.DEL",
+    ]),
+    ("string", &[
+        "GET", "SET", "INCR", "DECR", "INCRBY", "DECRBY", "INCRBYFLOAT", "GETSET", "APPEND", "GETRANGE",
+        "STRLEN",
+    ]),
+    ("bitmap", &["SETBIT", "GETBIT", "BITCOUNT"]),
+    ("list", &["LPUSH", "RPUSH", "LPOP", "RPOP", "LLEN", "LRANGE", "LPOS", "LMPOP",
+        "BLMPOP", "SORT",
+    ]),
+    ("hash", &[
+        "HSET", "HMSET", "HSETNX", "HGET", "HGETALL", "HMGET", "HDEL", "HLEN", "HEXISTS", "HKEYS",
+        "HVALS", "HSTRLEN", "HINCRBY", "HINCRBYFLOAT", "HRANDFIELD", "HEXPIRE", "HPEXPIRE", "HTTL",
+        "HEXPIREAT", "HPEXPIREAT", "HPTTL", "HPERSIST", "HSCAN", "SESSION.CREATE", "SESSION.GET", "SESSION.REFRESH",
+    ]),
+    ("set", &["SADD", "SREM", "SMEMBERS", "SISMEMBER", "SCARD", "SSCAN", "SINTERCARD", "SORT"]),
+    ("sortedset", &[
+        "ZADD", "ZREM", "ZRANGE", "ZSCORE", "ZRANK", "ZREVRANK", "ZAROUND", "ZCARD", "ZRANDMEMBER", "ZUNION",
+        "ZINTER", "ZDIFF", "ZDIFFSTORE", "ZMPOP", "BZMPOP", "SORT",
+    ]),
+    ("stream", &["XADD", "XTRIM", "XLEN", "XRANGE", "XREVRANGE", "XREAD"]),
+    ("geo", &["GEOADD", "GEODIST"]),
+    ("hyperloglog", &["PFADD", "PFCOUNT"]),
+    ("queue", &["PQ.PUSH", "PQ.POP", "PQ.ACK"]),
+    ("timeseries", &[
+        "TS.CREATE", "TS.ADD", "TS.GET", "TS.RANGE", "TS.MRANGE", "TS.CREATERULE", "TS.DELETERULE",
+    ]),
+    ("bf", &["BF.RESERVE", "BF.ADD", "BF.MADD", "BF.EXISTS", "BF.MEXISTS", "BF.INFO"]),
+    ("cf", &["CF.RESERVE", "CF.ADD", "CF.ADDNX", "CF.EXISTS", "CF.MEXISTS", "CF.COUNT", "CF.DEL", "CF.INFO"]),
+    ("vector", &["VECTOR.ADD", "VECTOR.GET", "VECTOR.DEL", "VECTOR.CARD", "VECTOR.SEARCH"]),
+    ("scripting", &["FCALL", "FCALL_RO", "FUNCTION"]),
+    ("pubsub", &["PUBLISH", "SUBSCRIBE", "PSUBSCRIBE", "SPUBLISH", "SSUBSCRIBE", "PUBSUB"]),
+    ("connection", &["AUTH", "PING", "ECHO", "HELLO", "QUIT", "RESET", "READONLY", "READWRITE"]),
+    ("admin", &[
+        "ACL", "SAVE", "BGSAVE", "BACKUP", "DUMPALL", "PERSISTENCE", "DEBUG", "FAILPOINT", "SLOWLOG", "LATENCY",
+        "MIGRATE", "INFO", "ANALYZE", "MEMORY", "CLIENT", "SCHEDULE",
+    ]),
+    ("dangerous", &[
+        "ACL", "FLUSHDB", "FLUSHALL", "KEYS", "DEBUG", "FAILPOINT", "SAVE", "BGSAVE",
+        "BACKUP", "DUMPALL", "PERSISTENCE", "MIGRATE", "RESTORE", "EXPIREMANY", "SCHEDULE",
+    ]),
+];
+
+/// Commands of an ACL category
+pub fn category_commands(name: &str) -> Option<&'static [&'static str]> {
+    ACL_CATEGORIES
+        .iter()
+        .find(|(category, _)| category.eq_ignore_ascii_case(name))
+        .map(|(_, commands)| *commands)
+}
+
+/// Whether a command is in the `write` category
+pub fn is_write_command(cmd_upper: &str) -> bool {
+    category_commands("write").is_some_and(|commands| commands.contains(&cmd_upper))
+}
+
+/// Random password of `bits` bits (rounded up to a hex digit), as ACL GENPASS returns
+pub fn generate_password(bits: usize) -> String {
+    use rand::Rng;
+    let mut rng = rand::thread_rng();
+    (0..bits.div_ceil(4))
+        .map(|_| char::from_digit(rng.gen_range(0..16), 16).unwrap_or('0'))
+        .collect()
+}
+
 /// Rate limit state per connection
 #[derive(Debug, Clone)]
 struct RateLimitState {
@@ -89,9 +280,9 @@ impl Security {
         let mut no_auth = HashSet::new();
         // Commands allowed without authentication
         no_auth.insert("AUTH".to_string());
-        no_auth.insert("PING".to_string());
-        no_auth.insert("QUIT".to_string());
         no_auth.insert("HELLO".to_string());
+        no_auth.insert("QUIT".to_string());
+        no_auth.insert("RESET".to_string());
 
         Security {
             default_password: RwLock::new(None),
@@ -99,24 +290,53 @@ impl Security {
             ip_whitelist: RwLock::new(HashSet::new()),
             ip_blacklist: RwLock::new(HashSet::new()),
             rate_limiter: RwLock::new(HashMap::new()),
+            max_commands_per_second: RwLock::new(0.0),
+            max_connections_per_ip: AtomicUsize::new(0),
+            ip_connections: parking_lot::Mutex::new(HashMap::new()),
             no_auth_commands: no_auth,
         }
     }
 
-    /// Set the default password (for legacy AUTH command)
+    /// Create a Security manager from the `[security]` config section
+    pub fn from_config(config: &SecurityConfig) -> Self {
+        let security = Self::new();
+        security.apply_config(config);
+        security
+    }
+
+    /// Replace the password, IP lists and client limits with the ones in
+    /// `config`. ACL users are kept.
+    pub fn apply_config(&self, config: &SecurityConfig) {
+        self.set_password(config.password.clone());
+        *self.ip_whitelist.write() = parse_ips(&config.ip_whitelist);
+        *self.ip_blacklist.write() = parse_ips(&config.ip_blacklist);
+        *self.max_commands_per_second.write() = config.max_commands_per_second.max(0.0);
+        self.max_connections_per_ip
+            .store(config.max_connections_per_ip, Ordering::Relaxed);
+    }
+
+    /// Set the default password (for legacy AUTH command). Plain text
+    /// passwords are hashed; Argon2 hashes are stored as given.
     pub fn set_password(&self, password: Option<String>) {
-        *self.default_password.write() = password;
+        *self.default_password.write() = password.map(stored_password);
     }
 
-    /// Check if authentication is required
+    /// Check if authentication is required: a password is set, or the
+    /// `default` ACL user has a password or is disabled. Other ACL users only
+    /// apply to connections that log in as them.
     pub fn is_auth_required(&self) -> bool {
-        self.default_password.read().is_some() || !self.users.read().is_empty()
+        self.default_password.read().is_some()
+            || self
+                .users
+                .read()
+                .get(DEFAULT_USER)
+                .is_some_and(|u| u.password_hash.is_some() || !u.enabled)
     }
 
     /// Authenticate with password (legacy AUTH)
     pub fn auth(&self, password: &str) -> bool {
         if let Some(ref stored) = *self.default_password.read() {
-            if stored == password {
+            if verify_password(password, stored) {
                 info!("Authentication successful (default user)");
                 return true;
             }
@@ -127,18 +347,20 @@ impl Security {
 
     /// Authenticate with username and password (AUTH username password)
     pub fn auth_user(&self, username: &str, password: &str) -> Option<User> {
-        let users = self.users.read();
-        if let Some(user) = users.get(username) {
+        let user = self.acl_getuser(username);
+        if let Some(user) = user {
             if !user.enabled {
                 warn!("User {} is disabled", username);
                 return None;
             }
-            
+
             if let Some(ref stored_hash) = user.password_hash {
-                // Use SHA256 for password verification
                 if verify_password(password, stored_hash) {
                     info!("User {} authenticated successfully", username);
-                    return Some(user.clone());
+                    if needs_rehash(stored_hash) {
+                        self.upgrade_hash(username, stored_hash, password);
+                    }
+                    return Some(user);
                 }
             }
         }
@@ -146,6 +368,128 @@ impl Security {
         None
     }
 
+    /// Replace a legacy hash with an Argon2 one, unless the password
+    /// changed in the meantime
+    fn upgrade_hash(&self, username: &str, old_hash: &str, password: &str) {
+        let new_hash = hash_password(password);
+        if let Some(user) = self.users.write().get_mut(username) {
+            if user.password_hash.as_deref() == Some(old_hash) {
+                user.password_hash = Some(new_hash);
+                info!("Upgraded password hash of user {}", username);
+            }
+        }
+    }
+
+    /// Log in as `username`. The default user authenticates against the
+    /// configured password unless an ACL user of that name exists.
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        if username == DEFAULT_USER && !self.users.read().contains_key(DEFAULT_USER) {
+            return self.auth(password);
+        }
+        self.auth_user(username, password).is_some()
+    }
+
+    /// Check a command for the user logged in on a connection (`None` if not
+    /// authenticated, which acts as `default` while no auth is required).
+    /// Returns the error reply when it must be rejected. The user is looked
+    /// up on every call so ACL changes apply at once.
+    pub fn check_command(&self, username: Option<&str>, command: &str, keys: &[&str]) -> Result<(), String> {
+        let cmd_upper = command.to_uppercase();
+        if self.no_auth_commands.contains(&cmd_upper) {
+            return Ok(());
+        }
+        let (name, user) = match self.session_user(username)? {
+            (name, Some(user)) => (name, user),
+            (_, None) => return Ok(()),
+        };
+        if Self::user_permits(&user, &cmd_upper, keys) {
+            Ok(())
+        } else {
+            Err(format!(
+                "NOPERM User {} has no permissions to run the '{}' command or its keys",
+                name,
+                command.to_lowercase()
+            ))
+        }
+    }
+
+    /// Check the channels a pub/sub command names against the user's
+    /// `&pattern` rules. A channel must match one of the patterns; a
+    /// PSUBSCRIBE pattern must be one of them verbatim.
+    pub fn check_channels(&self, username: Option<&str>, command: &str, args: &[String]) -> Result<(), String> {
+        let (channels, literal) = match command.to_uppercase().as_str() {
+            "SUBSCRIBE" | "SSUBSCRIBE" => (args, false),
+            "PSUBSCRIBE" => (args, true),
+            "PUBLISH" | "SPUBLISH" => (args.get(..1).unwrap_or_default(), false),
+            _ => return Ok(()),
+        };
+        let (name, user) = match self.session_user(username)? {
+            (name, Some(user)) => (name, user),
+            (_, None) => return Ok(()),
+        };
+        let allowed = &user.allowed_channels;
+        if allowed.is_empty() || allowed.iter().any(|p| p == "*") {
+            return Ok(());
+        }
+        let denied = channels.iter().find(|channel| {
+            !allowed.iter().any(|pattern| if literal { pattern == *channel } else { key_matches(channel, pattern) })
+        });
+        match denied {
+            Some(channel) => Err(format!(
+                "NOPERM User {} has no permissions to access the '{}' channel",
+                name, channel
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that a connection may call the function `function` in
+    /// `categories`. `+fcall|name` / `-fcall|name` decide first, then the
+    /// categories (a revoked one wins), then whether FCALL itself is allowed.
+    pub fn check_function(&self, username: Option<&str>, function: &str, categories: &[&str]) -> Result<(), String> {
+        let (name, user) = match self.session_user(username)? {
+            (name, Some(user)) => (name, user),
+            (_, None) => return Ok(()),
+        };
+        let own = format!("FCALL|{}", function.to_uppercase());
+        let markers: Vec<String> = categories.iter().map(|c| format!("@{}", c.to_uppercase())).collect();
+        let permitted = if !user.enabled || user.denied_commands.contains("*") || user.denied_commands.contains(&own) {
+            false
+        } else if user.allowed_commands.contains(&own) {
+            true
+        } else if markers.iter().any(|m| user.denied_commands.contains(m)) {
+            false
+        } else {
+            markers.iter().any(|m| user.allowed_commands.contains(m)) || Self::user_permits(&user, "FCALL", &[])
+        };
+        if permitted {
+            Ok(())
+        } else {
+            Err(format!(
+                "NOPERM User {} has no permissions to run the '{}' function",
+                name,
+                function.to_lowercase()
+            ))
+        }
+    }
+
+    /// The user a connection runs as, or None for the default user without
+    /// ACL rules (full access)
+    fn session_user<'a>(&self, username: Option<&'a str>) -> Result<(&'a str, Option<User>), String> {
+        let name = match username {
+            Some(name) => name,
+            None if self.is_auth_required() => return Err("NOAUTH Authentication required.".to_string()),
+            None => DEFAULT_USER,
+        };
+        match self.acl_getuser(name) {
+            Some(user) => Ok((name, Some(user))),
+            // The default user without ACL rules has full access
+            None if name == DEFAULT_USER => Ok((name, None)),
+            // The user was deleted after logging in
+            None => Err("NOAUTH Authentication required.".to_string()),
+        }
+    }
+
     /// Add or update a user
     pub fn acl_setuser(&self, name: String, rules: Vec<AclRule>) -> Result<(), String> {
         let mut users = self.users.write();
@@ -158,7 +502,8 @@ impl Security {
             match rule {
                 AclRule::On => user.enabled = true,
                 AclRule::Off => user.enabled = false,
-                AclRule::Password(p) => user.password_hash = Some(p),
+                AclRule::Password(p) => user.password_hash = Some(hash_password(&p)),
+                AclRule::PasswordHash(h) => user.password_hash = Some(h),
                 AclRule::NoPass => user.password_hash = None,
                 AclRule::AllCommands => {
                     user.allowed_commands.clear();
@@ -175,6 +520,25 @@ impl Security {
                     user.denied_commands.insert(cmd.to_uppercase());
                     user.allowed_commands.remove(&cmd.to_uppercase());
                 }
+                AclRule::AllowCategory(category) => {
+                    for cmd in category_commands(&category).unwrap_or_default() {
+                        user.allowed_commands.insert(cmd.to_string());
+                        user.denied_commands.remove(*cmd);
+                    }
+                    // Kept so functions in the category are covered too
+                    let marker = format!("@{}", category.to_uppercase());
+                    user.denied_commands.remove(&marker);
+                    user.allowed_commands.insert(marker);
+                }
+                AclRule::DenyCategory(category) => {
+                    for cmd in category_commands(&category).unwrap_or_default() {
+                        user.denied_commands.insert(cmd.to_string());
+                        user.allowed_commands.remove(*cmd);
+                    }
+                    let marker = format!("@{}", category.to_uppercase());
+                    user.allowed_commands.remove(&marker);
+                    user.denied_commands.insert(marker);
+                }
                 AclRule::AllKeys => {
                     user.allowed_keys = vec!["*".to_string()];
                 }
@@ -217,6 +581,55 @@ impl Security {
         self.users.read().keys().cloned().collect()
     }
 
+    /// ACL rules of every user, sorted by name
+    pub fn acl_rules(&self) -> Vec<String> {
+        let users = self.users.read();
+        let mut names: Vec<_> = users.keys().collect();
+        names.sort();
+        names.into_iter().map(|name| users[name].rules()).collect()
+    }
+
+    /// Write all users to an ACL file, one `user ...` line each
+    pub fn save_acl<P: AsRef<Path>>(&self, path: P) -> std::io::Result<usize> {
+        let rules = self.acl_rules();
+        let mut contents = rules.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        let tmp = path.as_ref().with_extension("tmp");
+        std::fs::write(&tmp, contents)?;
+        std::fs::rename(&tmp, path.as_ref())?;
+        info!("Saved {} ACL users to {}", rules.len(), path.as_ref().display());
+        Ok(rules.len())
+    }
+
+    /// Replace all users with the ones in an ACL file. Nothing changes if
+    /// any line is invalid.
+    pub fn load_acl<P: AsRef<Path>>(&self, path: P) -> Result<usize, String> {
+        let contents = std::fs::read_to_string(path.as_ref()).map_err(|e| e.to_string())?;
+        let loaded = Security::new();
+        for (number, line) in contents.lines().enumerate() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => continue,
+                Some("user") => {}
+                Some(_) => return Err(format!("line {}: should start with 'user'", number + 1)),
+            }
+            let name = words
+                .next()
+                .ok_or_else(|| format!("line {}: missing user name", number + 1))?;
+            let rules = words
+                .map(|w| parse_acl_rule(w).ok_or_else(|| format!("line {}: invalid rule '{}'", number + 1, w)))
+                .collect::<Result<Vec<_>, _>>()?;
+            loaded.acl_setuser(name.to_string(), rules)?;
+        }
+        let users = std::mem::take(&mut *loaded.users.write());
+        let count = users.len();
+        *self.users.write() = users;
+        info!("Loaded {} ACL users from {}", count, path.as_ref().display());
+        Ok(count)
+    }
+
     /// Get user details
     pub fn acl_getuser(&self, name: &str) -> Option<User> {
         self.users.read().get(name).cloned()
@@ -236,21 +649,24 @@ impl Security {
             return true;
         }
 
-        let user = match user {
-            Some(u) => u,
-            None => return false, // Auth required but no user
-        };
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        match user {
+            Some(u) => Self::user_permits(u, &cmd_upper, &keys),
+            None => false, // Auth required but no user
+        }
+    }
 
+    fn user_permits(user: &User, cmd_upper: &str, keys: &[&str]) -> bool {
         if !user.enabled {
             return false;
         }
 
         // Check command permissions
-        if user.denied_commands.contains("*") || user.denied_commands.contains(&cmd_upper) {
+        if user.denied_commands.contains("*") || user.denied_commands.contains(cmd_upper) {
             return false;
         }
 
-        if !user.allowed_commands.is_empty() && !user.allowed_commands.contains(&cmd_upper) {
+        if !user.allowed_commands.is_empty() && !user.allowed_commands.contains(cmd_upper) {
             // Check if * is in allowed
             if !user.allowed_commands.contains("*") {
                 return false;
@@ -337,6 +753,47 @@ impl Security {
     pub fn clear_rate_limit(&self, client_id: &str) {
         self.rate_limiter.write().remove(client_id);
     }
+
+    /// Whether a client may run another command under the configured
+    /// `max_commands_per_second`
+    pub fn allow_command(&self, client_id: &str) -> bool {
+        let limit = *self.max_commands_per_second.read();
+        limit <= 0.0 || self.check_rate_limit(client_id, limit)
+    }
+
+    /// Count a new connection from `ip`. Returns None if the IP already has
+    /// `max_connections_per_ip` open; the guard releases the slot on drop.
+    pub fn register_connection(self: &Arc<Self>, ip: IpAddr) -> Option<IpConnectionGuard> {
+        let max = self.max_connections_per_ip.load(Ordering::Relaxed);
+        let mut connections = self.ip_connections.lock();
+        let count = connections.entry(ip).or_insert(0);
+        if max > 0 && *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(IpConnectionGuard {
+            security: Arc::clone(self),
+            ip,
+        })
+    }
+}
+
+/// An open connection counted against its IP's limit
+pub struct IpConnectionGuard {
+    security: Arc<Security>,
+    ip: IpAddr,
+}
+
+impl Drop for IpConnectionGuard {
+    fn drop(&mut self) {
+        let mut connections = self.security.ip_connections.lock();
+        if let Some(count) = connections.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                connections.remove(&self.ip);
+            }
+        }
+    }
 }
 
 impl Default for Security {
@@ -351,11 +808,15 @@ pub enum AclRule {
     On,
     Off,
     Password(String),
+    /// Password given as its stored hash (`#hash`)
+    PasswordHash(String),
     NoPass,
     AllCommands,
     NoCommands,
     AllowCommand(String),
     DenyCommand(String),
+    AllowCategory(String),
+    DenyCategory(String),
     AllKeys,
     KeyPattern(String),
     AllChannels,
@@ -363,6 +824,18 @@ pub enum AclRule {
     Reset,
 }
 
+fn parse_ips(list: &[String]) -> HashSet<IpAddr> {
+    list.iter()
+        .filter_map(|s| match s.trim().parse() {
+            Ok(ip) => Some(ip),
+            Err(_) => {
+                warn!("Ignoring invalid IP address in security config: {}", s);
+                None
+            }
+        })
+        .collect()
+}
+
 /// Parse ACL rule from string
 pub fn parse_acl_rule(s: &str) -> Option<AclRule> {
     let s = s.trim();
@@ -394,6 +867,15 @@ pub fn parse_acl_rule(s: &str) -> Option<AclRule> {
     if s.starts_with('>') {
         return Some(AclRule::Password(s[1..].to_string()));
     }
+    if let Some(hash) = s.strip_prefix('#') {
+        return parse_stored_hash(hash).map(AclRule::PasswordHash);
+    }
+    if let Some(category) = s.strip_prefix("+@") {
+        return category_commands(category).map(|_| AclRule::AllowCategory(category.to_string()));
+    }
+    if let Some(category) = s.strip_prefix("-@") {
+        return category_commands(category).map(|_| AclRule::DenyCategory(category.to_string()));
+    }
     if s.starts_with('+') {
         return Some(AclRule::AllowCommand(s[1..].to_string()));
     }
@@ -491,6 +973,158 @@ mod tests {
         assert!(security.auth_user("testuser", "wrong").is_none());
     }
 
+    #[test]
+    fn test_check_command() {
+        let security = Security::new();
+        assert!(security.check_command(None, "get", &["k"]).is_ok());
+
+        security.set_password(Some("pw".to_string()));
+        assert!(security.check_command(None, "get", &[]).unwrap_err().starts_with("NOAUTH"));
+        assert!(security.check_command(None, "reset", &[]).is_ok());
+        assert!(security.check_command(None, "ping", &[]).is_err());
+        assert!(security.authenticate(DEFAULT_USER, "pw"));
+        assert!(security.check_command(Some(DEFAULT_USER), "flushall", &[]).is_ok());
+
+        security.acl_setuser("reader".to_string(), vec![
+            AclRule::Password("r".to_string()),
+            AclRule::AllowCommand("get".to_string()),
+            AclRule::KeyPattern("pub:*".to_string()),
+        ]).unwrap();
+        assert!(security.authenticate("reader", "r"));
+        assert!(security.check_command(Some("reader"), "get", &["pub:1"]).is_ok());
+        assert!(security.check_command(Some("reader"), "get", &["priv"]).unwrap_err().starts_with("NOPERM"));
+        assert!(security.check_command(Some("reader"), "set", &["pub:1"]).is_err());
+
+        security.acl_deluser(vec!["reader".to_string()]);
+        assert!(security.check_command(Some("reader"), "get", &[]).unwrap_err().starts_with("NOAUTH"));
+    }
+
+    #[test]
+    fn test_check_channels() {
+        let security = Security::new();
+        let args = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(security.check_channels(None, "subscribe", &args(&["any"])).is_ok());
+
+        security.acl_setuser("news".to_string(), vec![
+            AclRule::AllCommands,
+            AclRule::ChannelPattern("news:*".to_string()),
+        ]).unwrap();
+        let user = Some("news");
+        assert!(security.check_channels(user, "subscribe", &args(&["news:eu", "news:us"])).is_ok());
+        let denied = security.check_channels(user, "subscribe", &args(&["news:eu", "admin"])).unwrap_err();
+        assert!(denied.starts_with("NOPERM") && denied.contains("'admin'"));
+        assert!(security.check_channels(user, "publish", &args(&["admin", "hi"])).is_err());
+        assert!(security.check_channels(user, "psubscribe", &args(&["news:*"])).is_ok());
+        assert!(security.check_channels(user, "psubscribe", &args(&["news:e*"])).is_err());
+        // Leaving a channel is never restricted
+        assert!(security.check_channels(user, "unsubscribe", &args(&["admin"])).is_ok());
+    }
+
+    #[test]
+    fn test_check_function() {
+        let security = Security::new();
+        assert!(security.check_function(None, "stats", &["read"]).is_ok());
+
+        security.acl_setuser("app".to_string(), vec![
+            parse_acl_rule("+@read").unwrap(),
+            parse_acl_rule("+fcall|audit").unwrap(),
+        ]).unwrap();
+        // Granted through its category or by name, not by FCALL alone
+        assert!(security.check_function(Some("app"), "stats", &["read"]).is_ok());
+        assert!(security.check_function(Some("app"), "audit", &["write"]).is_ok());
+        assert!(security.check_function(Some("app"), "purge", &["write"]).unwrap_err().starts_with("NOPERM"));
+
+        security.acl_setuser("ops".to_string(), vec![
+            parse_acl_rule("+@all").unwrap(),
+            parse_acl_rule("-@dangerous").unwrap(),
+        ]).unwrap();
+        assert!(security.check_function(Some("ops"), "stats", &["read"]).is_ok());
+        assert!(security.check_function(Some("ops"), "purge", &["write", "dangerous"]).is_err());
+    }
+
+    #[test]
+    fn test_acl_save_load() {
+        let security = Security::new();
+        security.acl_setuser("app".to_string(), vec![
+            parse_acl_rule(">secret").unwrap(),
+            parse_acl_rule("+@read").unwrap(),
+            parse_acl_rule("-keys").unwrap(),
+            parse_acl_rule("~app:*").unwrap(),
+        ]).unwrap();
+        let rules = security.acl_rules();
+        assert!(rules[0].starts_with("user app on #$argon2id$"));
+        assert!(rules[0].contains("+get") && rules[0].contains("-keys") && rules[0].ends_with("~app:* &*"));
+
+        let path = std::env::temp_dir().join(format!("hexagondb-acl-{}.acl", std::process::id()));
+        assert_eq!(security.save_acl(&path).unwrap(), 1);
+        let restored = Security::new();
+        restored.acl_setuser("old".to_string(), vec![]).unwrap();
+        assert_eq!(restored.load_acl(&path).unwrap(), 1);
+        assert_eq!(restored.acl_rules(), rules);
+        assert!(restored.authenticate("app", "secret"));
+
+        std::fs::write(&path, "user bad on ^oops\n").unwrap();
+        assert!(restored.load_acl(&path).is_err());
+        assert_eq!(restored.acl_rules(), rules);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_password_hashing() {
+        let hash = hash_password("secret");
+        assert!(hash.starts_with(HASH_PREFIX));
+        assert_ne!(hash, hash_password("secret"));
+        assert!(verify_password("secret", &hash));
+        assert!(!verify_password("wrong", &hash));
+        assert!(!needs_rehash(&hash));
+
+        // Legacy SipHash hashes still log in and are upgraded on success
+        let security = Security::new();
+        let bare = legacy_hash("secret")[LEGACY_PREFIX.len()..].to_string();
+        let Some(AclRule::PasswordHash(stored)) = parse_acl_rule(&format!("#{}", bare)) else { panic!() };
+        assert_eq!(stored, legacy_hash("secret"));
+        security.acl_setuser("old".to_string(), vec![AclRule::PasswordHash(stored)]).unwrap();
+        assert!(security.auth_user("old", "wrong").is_none());
+        assert!(needs_rehash(security.acl_getuser("old").unwrap().password_hash.as_deref().unwrap()));
+        assert!(security.auth_user("old", "secret").is_some());
+        let upgraded = security.acl_getuser("old").unwrap().password_hash.unwrap();
+        assert!(upgraded.starts_with(HASH_PREFIX));
+        assert!(security.auth_user("old", "secret").is_some());
+
+        // Configured passwords are hashed, Argon2 hashes kept as given
+        security.set_password(Some(hash));
+        assert!(security.auth("secret"));
+
+        // Plain text is never compared as is, whatever it looks like
+        let hex_password = "0123456789abcdef";
+        security.set_password(Some(hex_password.to_string()));
+        assert!(security.auth(hex_password));
+        assert!(!verify_password(hex_password, hex_password));
+        assert!(!verify_password("secret", "secret"));
+        assert!(parse_acl_rule("#secret").is_none());
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let config = SecurityConfig {
+            max_commands_per_second: 2.0,
+            max_connections_per_ip: 1,
+            ..Default::default()
+        };
+        let security = Arc::new(Security::from_config(&config));
+        assert!(security.allow_command("c1"));
+        assert!(security.allow_command("c1"));
+        assert!(!security.allow_command("c1"));
+        assert!(security.allow_command("c2"));
+
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let guard = security.register_connection(ip).unwrap();
+        assert!(security.register_connection(ip).is_none());
+        assert!(security.register_connection("10.0.0.2".parse().unwrap()).is_some());
+        drop(guard);
+        assert!(security.register_connection(ip).is_some());
+    }
+
     #[test]
     fn test_key_pattern() {
         assert!(key_matches("user:123", "user:*"));
@@ -0,0 +1,200 @@
+//! Startup self-check (`--selftest`).
+//!
+//! Runs a few quick micro-benchmarks of the paths that matter most in
+//! production (RESP parsing, keyspace lock contention and disk fsync) and
+//! prints a capability report with a verdict for each.
+
+use crate::db::{StringOps, DB};
+use crate::network::resp::{RespHandler, RespValue};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Minimum parsed commands per second before the parser is flagged
+const MIN_PARSE_OPS: f64 = 200_000.0;
+/// Minimum contended writes per second before locking is flagged
+const MIN_LOCK_OPS: f64 = 100_000.0;
+/// Maximum acceptable worst-case fsync latency
+const MAX_FSYNC: Duration = Duration::from_millis(20);
+
+/// Iteration counts for the checks
+#[derive(Debug, Clone, Copy)]
+pub struct SelfTestOptions {
+    pub parse_iterations: usize,
+    pub lock_tasks: usize,
+    pub lock_iterations: usize,
+    pub fsync_iterations: usize,
+}
+
+impl Default for SelfTestOptions {
+    fn default() -> Self {
+        SelfTestOptions {
+            parse_iterations: 200_000,
+            lock_tasks: 8,
+            lock_iterations: 20_000,
+            fsync_iterations: 50,
+        }
+    }
+}
+
+/// Result of a single check
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub detail: String,
+    pub passed: bool,
+}
+
+/// Full self-test report
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    pub cpus: usize,
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Whether every check passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "HexagonDB self-test")?;
+        writeln!(f, "  cpus: {}", self.cpus)?;
+        for check in &self.checks {
+            let verdict = if check.passed { "ok" } else { "WARN" };
+            writeln!(f, "  [{:>4}] {}: {}", verdict, check.name, check.detail)?;
+        }
+        let summary = if self.passed() {
+            "environment looks ready"
+        } else {
+            "some checks are below recommended levels"
+        };
+        write!(f, "Result: {}", summary)
+    }
+}
+
+/// Run all checks. The fsync check writes a scratch file in `data_dir`.
+pub async fn run(data_dir: &Path, options: SelfTestOptions) -> SelfTestReport {
+    let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let checks = vec![
+        check_parser(options.parse_iterations),
+        check_lock_contention(options.lock_tasks, options.lock_iterations).await,
+        check_fsync(data_dir, options.fsync_iterations),
+    ];
+    SelfTestReport { cpus, checks }
+}
+
+fn check_parser(iterations: usize) -> CheckResult {
+    let request = RespValue::Array(Some(
+        ["SET", "selftest:key", "some moderately sized value"]
+            .iter()
+            .map(|s| RespValue::BulkString(Some(s.to_string())))
+            .collect(),
+    ))
+    .serialize();
+
+    let start = Instant::now();
+    let mut parsed = 0usize;
+    for _ in 0..iterations {
+        if let Ok(Some(_)) = RespHandler::parse_request(request.as_bytes()) {
+            parsed += 1;
+        }
+    }
+    let ops = per_second(parsed, start.elapsed());
+    CheckResult {
+        name: "parser throughput",
+        detail: format!("{:.0} commands/s", ops),
+        passed: parsed == iterations && ops >= MIN_PARSE_OPS,
+    }
+}
+
+async fn check_lock_contention(tasks: usize, iterations: usize) -> CheckResult {
+    let db = Arc::new(RwLock::new(DB::new()));
+    let start = Instant::now();
+    let handles: Vec<_> = (0..tasks)
+        .map(|t| {
+            let db = Arc::clone(&db);
+            tokio::spawn(async move {
+                for i in 0..iterations {
+                    db.write().await.set(format!("selftest:{}:{}", t, i % 64), i.to_string());
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        let _ = handle.await;
+    }
+    let ops = per_second(tasks * iterations, start.elapsed());
+    CheckResult {
+        name: "lock contention",
+        detail: format!("{:.0} writes/s across {} tasks", ops, tasks),
+        passed: ops >= MIN_LOCK_OPS,
+    }
+}
+
+fn check_fsync(data_dir: &Path, iterations: usize) -> CheckResult {
+    let path: PathBuf = data_dir.join(".hexagondb-selftest.tmp");
+    let result = (|| -> std::io::Result<(Duration, Duration)> {
+        let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(&path)?;
+        let mut total = Duration::ZERO;
+        let mut worst = Duration::ZERO;
+        for _ in 0..iterations {
+            file.write_all(b"*1\r\n$4\r\nPING\r\n")?;
+            let start = Instant::now();
+            file.sync_data()?;
+            let elapsed = start.elapsed();
+            total += elapsed;
+            worst = worst.max(elapsed);
+        }
+        Ok((total / iterations.max(1) as u32, worst))
+    })();
+    let _ = std::fs::remove_file(&path);
+
+    match result {
+        Ok((avg, worst)) => CheckResult {
+            name: "fsync latency",
+            detail: format!(
+                "avg {:.2}ms, max {:.2}ms in {}",
+                avg.as_secs_f64() * 1000.0,
+                worst.as_secs_f64() * 1000.0,
+                data_dir.display()
+            ),
+            passed: worst <= MAX_FSYNC,
+        },
+        Err(e) => CheckResult {
+            name: "fsync latency",
+            detail: format!("failed in {}: {}", data_dir.display(), e),
+            passed: false,
+        },
+    }
+}
+
+fn per_second(count: usize, elapsed: Duration) -> f64 {
+    count as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_selftest_report() {
+        let options = SelfTestOptions {
+            parse_iterations: 100,
+            lock_tasks: 2,
+            lock_iterations: 100,
+            fsync_iterations: 2,
+        };
+        let report = run(&std::env::temp_dir(), options).await;
+        assert_eq!(report.checks.len(), 3);
+        assert!(!report.checks[2].detail.starts_with("failed"));
+        assert!(report.to_string().contains("parser throughput"));
+    }
+}
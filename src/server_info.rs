@@ -2,8 +2,39 @@
 //!
 //! Provides runtime information about the HexagonDB server.
 
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::time::Instant;
+use crate::network::clients::ClientRegistry;
+use crate::persistence::durability::DURABILITY;
+use crate::persistence::loading::LoadProgress;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::fmt::Write;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// INFO sections in output order
+pub const INFO_SECTIONS: &[&str] = &[
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "keyspace",
+];
+
+/// Dataset figures gathered from the DB and AOF for INFO
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatasetStats {
+    /// Number of keys
+    pub keys: usize,
+    /// Number of keys with a TTL
+    pub expires: usize,
+    /// Keys removed because their TTL ran out
+    pub expired_keys: u64,
+    /// Writes since the last snapshot
+    pub changes_since_save: usize,
+    /// Current AOF file size in bytes
+    pub aof_size: u64,
+}
 
 /// Server information and statistics
 pub struct ServerInfo {
@@ -21,8 +52,28 @@ pub struct ServerInfo {
     bytes_sent: AtomicU64,
     /// Rejected connections (over limit)
     rejected_connections: AtomicU64,
-    /// Expired keys counter
-    expired_keys: AtomicU64,
+    /// Keys evicted to stay under maxmemory
+    evicted_keys: AtomicU64,
+    /// Key lookups by read commands that found the key
+    keyspace_hits: AtomicU64,
+    /// Key lookups by read commands that did not find the key
+    keyspace_misses: AtomicU64,
+    /// Unix time of the last successful snapshot
+    last_save_time: AtomicU64,
+    /// Whether a background snapshot is running
+    bgsave_in_progress: AtomicBool,
+    /// Whether the last background snapshot succeeded
+    last_bgsave_ok: AtomicBool,
+    /// Unix time the last background snapshot finished
+    last_bgsave_time: AtomicU64,
+    /// Configured node name
+    instance_name: String,
+    /// Configured shard id
+    shard_id: u32,
+    /// Progress of the startup dataset load
+    loading: Arc<LoadProgress>,
+    /// Open connections, for CLIENT LIST and idle eviction
+    clients: Arc<ClientRegistry>,
 }
 
 impl ServerInfo {
@@ -36,10 +87,27 @@ impl ServerInfo {
             bytes_received: AtomicU64::new(0),
             bytes_sent: AtomicU64::new(0),
             rejected_connections: AtomicU64::new(0),
-            expired_keys: AtomicU64::new(0),
+            evicted_keys: AtomicU64::new(0),
+            keyspace_hits: AtomicU64::new(0),
+            keyspace_misses: AtomicU64::new(0),
+            last_save_time: AtomicU64::new(unix_secs()),
+            bgsave_in_progress: AtomicBool::new(false),
+            last_bgsave_ok: AtomicBool::new(true),
+            last_bgsave_time: AtomicU64::new(0),
+            instance_name: String::new(),
+            shard_id: 0,
+            loading: Arc::new(LoadProgress::new()),
+            clients: Arc::new(ClientRegistry::new()),
         }
     }
 
+    /// Set the node identity reported by INFO
+    pub fn with_identity(mut self, instance_name: String, shard_id: u32) -> Self {
+        self.instance_name = instance_name;
+        self.shard_id = shard_id;
+        self
+    }
+
     /// Increment total commands counter
     pub fn increment_commands(&self) {
         self.total_commands.fetch_add(1, Ordering::Relaxed);
@@ -75,9 +143,76 @@ impl ServerInfo {
         self.rejected_connections.fetch_add(1, Ordering::Relaxed);
     }
 
-    /// Increment expired keys counter
-    pub fn increment_expired_keys(&self) {
-        self.expired_keys.fetch_add(1, Ordering::Relaxed);
+    /// Increment evicted keys counter
+    pub fn increment_evicted_keys(&self) {
+        self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a key lookup by a read command
+    pub fn record_keyspace_lookup(&self, hit: bool) {
+        if hit {
+            self.keyspace_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.keyspace_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Remember that a snapshot was just written
+    pub fn record_save(&self) {
+        self.last_save_time.store(unix_secs(), Ordering::Relaxed);
+    }
+
+    /// Unix time of the last successful snapshot (server start if none yet)
+    pub fn last_save_time(&self) -> u64 {
+        self.last_save_time.load(Ordering::Relaxed)
+    }
+
+    /// Seconds since the last successful snapshot (or server start)
+    pub fn last_save_age_secs(&self) -> u64 {
+        unix_secs().saturating_sub(self.last_save_time())
+    }
+
+    /// Mark a background snapshot as started. Returns false if one is
+    /// already running.
+    pub fn try_begin_bgsave(&self) -> bool {
+        self.bgsave_in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Mark the running background snapshot as finished
+    pub fn finish_bgsave(&self, ok: bool) {
+        if ok {
+            self.record_save();
+        }
+        self.last_bgsave_ok.store(ok, Ordering::Relaxed);
+        self.last_bgsave_time.store(unix_secs(), Ordering::Relaxed);
+        self.bgsave_in_progress.store(false, Ordering::Release);
+    }
+
+    /// Whether a background snapshot is running
+    pub fn bgsave_in_progress(&self) -> bool {
+        self.bgsave_in_progress.load(Ordering::Acquire)
+    }
+
+    /// Whether the last background snapshot succeeded (true if none ran yet)
+    pub fn last_bgsave_ok(&self) -> bool {
+        self.last_bgsave_ok.load(Ordering::Relaxed)
+    }
+
+    /// Unix time the last background snapshot finished (0 if none yet)
+    pub fn last_bgsave_time(&self) -> u64 {
+        self.last_bgsave_time.load(Ordering::Relaxed)
+    }
+
+    /// Progress of the startup dataset load
+    pub fn loading(&self) -> &Arc<LoadProgress> {
+        &self.loading
+    }
+
+    /// Registry of open connections
+    pub fn clients(&self) -> &Arc<ClientRegistry> {
+        &self.clients
     }
 
     /// Get uptime in seconds
@@ -85,64 +220,114 @@ impl ServerInfo {
         self.start_time.elapsed().as_secs()
     }
 
-    /// Generate INFO command response
-    pub fn generate_info(&self, db_size: usize) -> String {
-        let uptime = self.uptime_seconds();
-        let total_cmds = self.total_commands.load(Ordering::Relaxed);
-        let total_conns = self.total_connections.load(Ordering::Relaxed);
-        let connected = self.connected_clients.load(Ordering::Relaxed);
-        let bytes_in = self.bytes_received.load(Ordering::Relaxed);
-        let bytes_out = self.bytes_sent.load(Ordering::Relaxed);
-        let rejected = self.rejected_connections.load(Ordering::Relaxed);
-        let expired = self.expired_keys.load(Ordering::Relaxed);
-        let (used_memory, used_memory_human) = get_memory_usage();
-
-        format!(
-            r#"# Server
-hexagondb_version:0.1.0
-os:{}
-arch:{}
-process_id:{}
-uptime_in_seconds:{}
-uptime_in_days:{}
-
-# Clients
-connected_clients:{}
-total_connections_received:{}
-rejected_connections:{}
-
-# Stats
-total_commands_processed:{}
-total_net_input_bytes:{}
-total_net_output_bytes:{}
-expired_keys:{}
-
-# Memory
-used_memory:{}
-used_memory_human:{}
-
-# Keyspace
-db0:keys={}
-"#,
-            std::env::consts::OS,
-            std::env::consts::ARCH,
-            std::process::id(),
-            uptime,
-            uptime / 86400,
-            connected,
-            total_conns,
-            rejected,
-            total_cmds,
-            bytes_in,
-            bytes_out,
-            expired,
-            used_memory,
-            used_memory_human,
-            db_size
-        )
+    /// Generate the INFO response for one section, or for all of them when
+    /// `section` is `None`, "all", "default" or "everything". An unknown
+    /// section yields an empty string, as in Redis.
+    pub fn generate_info(&self, section: Option<&str>, dataset: &DatasetStats) -> String {
+        let section = section.map(|s| s.to_lowercase());
+        let wanted: Vec<&str> = match section.as_deref() {
+            None | Some("all") | Some("default") | Some("everything") => INFO_SECTIONS.to_vec(),
+            Some(name) => INFO_SECTIONS.iter().copied().filter(|s| *s == name).collect(),
+        };
+
+        let mut out = String::new();
+        for name in wanted {
+            if !out.is_empty() {
+                out.push_str("\r\n");
+            }
+            self.write_section(&mut out, name, dataset);
+        }
+        out
+    }
+
+    fn write_section(&self, out: &mut String, name: &str, dataset: &DatasetStats) {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        // Writing to a String cannot fail
+        let _ = match name {
+            "server" => {
+                let uptime = self.uptime_seconds();
+                write!(
+                    out,
+                    "# Server\r\nhexagondb_version:{}\r\ninstance_name:{}\r\nshard_id:{}\r\nos:{}\r\narch:{}\r\nprocess_id:{}\r\nuptime_in_seconds:{}\r\nuptime_in_days:{}\r\n",
+                    env!("CARGO_PKG_VERSION"),
+                    self.instance_name,
+                    self.shard_id,
+                    std::env::consts::OS,
+                    std::env::consts::ARCH,
+                    std::process::id(),
+                    uptime,
+                    uptime / 86400
+                )
+            }
+            "clients" => write!(
+                out,
+                "# Clients\r\nconnected_clients:{}\r\ntotal_connections_received:{}\r\nrejected_connections:{}\r\n",
+                self.connected_clients.load(Ordering::Relaxed),
+                load(&self.total_connections),
+                load(&self.rejected_connections)
+            ),
+            "memory" => {
+                let (used_memory, used_memory_human) = get_memory_usage();
+                write!(
+                    out,
+                    "# Memory\r\nused_memory:{}\r\nused_memory_human:{}\r\nlazyfree_pending_objects:{}\r\n",
+                    used_memory,
+                    used_memory_human,
+                    crate::db::lazyfree::pending()
+                )
+            }
+            "persistence" => write!(
+                out,
+                "# Persistence\r\nloading:{}\r\nloading_loaded_perc:{:.2}\r\nloading_loaded_records:{}\r\nloading_records_per_sec:{:.0}\r\nrdb_changes_since_last_save:{}\r\nrdb_bgsave_in_progress:{}\r\nrdb_last_save_time:{}\r\nrdb_last_bgsave_status:{}\r\nrdb_last_save_age_seconds:{}\r\naof_size:{}\r\naof_pending_fsync_bytes:{}\r\naof_last_fsync_age_seconds:{}\r\naof_last_write_status:{}\r\naof_rewrite_in_progress:{}\r\naof_rewrite_progress_perc:{:.2}\r\n",
+                self.loading.is_loading() as u8,
+                self.loading.percent(),
+                self.loading.loaded(),
+                self.loading.rate(),
+                dataset.changes_since_save,
+                self.bgsave_in_progress() as u8,
+                self.last_save_time(),
+                if self.last_bgsave_ok() { "ok" } else { "err" },
+                self.last_save_age_secs(),
+                dataset.aof_size,
+                DURABILITY.pending_fsync_bytes(),
+                DURABILITY.last_fsync_age_secs().map_or(-1, |age| age as i64),
+                if DURABILITY.last_write_ok() { "ok" } else { "err" },
+                DURABILITY.rewrite_progress().is_some() as u8,
+                DURABILITY.rewrite_progress().unwrap_or(0.0) * 100.0
+            ),
+            "stats" => write!(
+                out,
+                "# Stats\r\ntotal_commands_processed:{}\r\ntotal_net_input_bytes:{}\r\ntotal_net_output_bytes:{}\r\nexpired_keys:{}\r\nevicted_keys:{}\r\nkeyspace_hits:{}\r\nkeyspace_misses:{}\r\n",
+                load(&self.total_commands),
+                load(&self.bytes_received),
+                load(&self.bytes_sent),
+                dataset.expired_keys,
+                load(&self.evicted_keys),
+                load(&self.keyspace_hits),
+                load(&self.keyspace_misses)
+            ),
+            // No replication yet: every node is a standalone master
+            "replication" => write!(out, "# Replication\r\nrole:master\r\nconnected_slaves:0\r\n"),
+            "keyspace" => {
+                let _ = write!(out, "# Keyspace\r\n");
+                if dataset.keys > 0 {
+                    write!(out, "db0:keys={},expires={}\r\n", dataset.keys, dataset.expires)
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        };
     }
 }
 
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 impl Default for ServerInfo {
     fn default() -> Self {
         Self::new()
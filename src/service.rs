@@ -0,0 +1,231 @@
+//! Windows service integration.
+//!
+//! `hexagondb --install-service` registers the server with the Service
+//! Control Manager to start at boot with `--service` and the absolute
+//! config path; `--uninstall-service` removes it. Under the service the
+//! server logs to the Windows event log (Application log, source
+//! `HexagonDB`) instead of stdout and stops cleanly on Stop or system
+//! shutdown.
+
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::{error, Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::EventLog::{
+    RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+    EVENTLOG_WARNING_TYPE, REPORT_EVENT_TYPE,
+};
+
+/// Service and event source name
+pub const SERVICE_NAME: &str = "HexagonDB";
+
+/// Runs the server until the receiver fires
+pub type ServeFn = fn(oneshot::Receiver<()>) -> io::Result<()>;
+
+static SERVE: OnceLock<ServeFn> = OnceLock::new();
+
+fn to_io(e: windows_service::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// Register the service to start at boot with the given config file
+pub fn install(config_path: &str) -> io::Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(to_io)?;
+    let config_path = std::path::absolute(Path::new(config_path))?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from("HexagonDB"),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![
+            OsString::from("--service"),
+            OsString::from("--config"),
+            config_path.into_os_string(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(to_io)?;
+    service
+        .set_description("HexagonDB in-memory key-value database")
+        .map_err(to_io)?;
+    println!("Service {} installed", SERVICE_NAME);
+    Ok(())
+}
+
+/// Remove the service. It is deleted once stopped.
+pub fn uninstall() -> io::Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(to_io)?;
+    let service = manager
+        .open_service(SERVICE_NAME, ServiceAccess::DELETE)
+        .map_err(to_io)?;
+    service.delete().map_err(to_io)?;
+    println!("Service {} removed", SERVICE_NAME);
+    Ok(())
+}
+
+/// Hand the process over to the Service Control Manager, which calls
+/// `serve` on a service thread. Returns when the service has stopped.
+pub fn run(serve: ServeFn) -> io::Result<()> {
+    let _ = SERVE.set(serve);
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(to_io)
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("Service failed: {}", e);
+    }
+}
+
+fn status(state: ServiceState, exit_code: u32) -> ServiceStatus {
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted: if state == ServiceState::Running {
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+        } else {
+            ServiceControlAccept::empty()
+        },
+        exit_code: ServiceExitCode::Win32(exit_code),
+        checkpoint: 0,
+        wait_hint: Duration::from_secs(10),
+        process_id: None,
+    }
+}
+
+fn run_service() -> windows_service::Result<()> {
+    let (stop_tx, stop_rx) = oneshot::channel();
+    let stop_tx = Mutex::new(Some(stop_tx));
+    let handler = move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            if let Some(tx) = stop_tx.lock().unwrap().take() {
+                let _ = tx.send(());
+            }
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    };
+    let status_handle = service_control_handler::register(SERVICE_NAME, handler)?;
+    status_handle.set_service_status(status(ServiceState::Running, 0))?;
+
+    let serve = SERVE.get().expect("service entry point not set");
+    let exit_code = match serve(stop_rx) {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("Server error: {}", e);
+            1
+        }
+    };
+    status_handle.set_service_status(status(ServiceState::Stopped, exit_code))
+}
+
+/// Event log handle, usable from any thread
+struct EventSource(HANDLE);
+
+unsafe impl Send for EventSource {}
+unsafe impl Sync for EventSource {}
+
+/// `tracing` writer reporting each formatted event to the event log
+pub struct EventLogWriter {
+    source: EventSource,
+}
+
+impl EventLogWriter {
+    /// Open the `HexagonDB` event source
+    pub fn open() -> io::Result<Self> {
+        let name: Vec<u16> = SERVICE_NAME.encode_utf16().chain(Some(0)).collect();
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(EventLogWriter {
+            source: EventSource(handle),
+        })
+    }
+}
+
+/// One event, reported when the formatter drops it
+pub struct EventRecord<'a> {
+    source: &'a EventSource,
+    event_type: REPORT_EVENT_TYPE,
+    buf: Vec<u8>,
+}
+
+impl Write for EventRecord<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventRecord<'_> {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.buf);
+        let text: Vec<u16> = text.trim_end().encode_utf16().chain(Some(0)).collect();
+        let strings = [text.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.source.0,
+                self.event_type,
+                0,
+                0,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            );
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for EventLogWriter {
+    type Writer = EventRecord<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        EventRecord {
+            source: &self.source,
+            event_type: EVENTLOG_INFORMATION_TYPE,
+            buf: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let mut record = self.make_writer();
+        record.event_type = match *meta.level() {
+            Level::ERROR => EVENTLOG_ERROR_TYPE,
+            Level::WARN => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        record
+    }
+}
@@ -2,10 +2,16 @@
 //!
 //! Tracks slow commands for performance analysis.
 
+use crate::config::SlowLogConfig;
 use parking_lot::RwLock;
 use std::collections::VecDeque;
 use std::time::Instant;
 
+/// Maximum number of arguments kept per entry
+const MAX_ARGS: usize = 32;
+/// Maximum length of a single argument kept in an entry
+const MAX_ARG_LEN: usize = 128;
+
 /// Slow log entry
 #[derive(Debug, Clone)]
 pub struct SlowLogEntry {
@@ -29,8 +35,9 @@ pub struct SlowLog {
     entries: RwLock<VecDeque<SlowLogEntry>>,
     /// Maximum entries to keep
     max_len: RwLock<usize>,
-    /// Threshold in microseconds (commands slower than this are logged)
-    threshold_us: RwLock<u64>,
+    /// Threshold in microseconds (commands slower than this are logged),
+    /// None when disabled
+    threshold_us: RwLock<Option<u64>>,
     /// Next log ID
     next_id: RwLock<u64>,
 }
@@ -41,7 +48,7 @@ impl SlowLog {
         SlowLog {
             entries: RwLock::new(VecDeque::with_capacity(128)),
             max_len: RwLock::new(128),
-            threshold_us: RwLock::new(10000), // 10ms default
+            threshold_us: RwLock::new(Some(10000)), // 10ms default
             next_id: RwLock::new(0),
         }
     }
@@ -56,16 +63,34 @@ impl SlowLog {
         }
     }
 
+    /// Create a slow log from configuration
+    pub fn from_config(config: &SlowLogConfig) -> Self {
+        let slowlog = Self::new();
+        slowlog.apply_config(config);
+        slowlog
+    }
+
+    /// Apply (possibly reloaded) configuration
+    pub fn apply_config(&self, config: &SlowLogConfig) {
+        *self.threshold_us.write() = config.threshold_us();
+        self.set_max_len(config.max_len);
+    }
+
     /// Set threshold in microseconds
     pub fn set_threshold(&self, threshold_us: u64) {
-        *self.threshold_us.write() = threshold_us;
+        *self.threshold_us.write() = Some(threshold_us);
     }
 
-    /// Get threshold
-    pub fn get_threshold(&self) -> u64 {
+    /// Get threshold (None when disabled)
+    pub fn get_threshold(&self) -> Option<u64> {
         *self.threshold_us.read()
     }
 
+    /// Whether commands are being logged at all
+    pub fn is_enabled(&self) -> bool {
+        self.threshold_us.read().is_some()
+    }
+
     /// Log a command if it exceeds the threshold
     pub fn log_if_slow(
         &self,
@@ -77,8 +102,9 @@ impl SlowLog {
         let duration = start_time.elapsed();
         let duration_us = duration.as_micros() as u64;
 
-        if duration_us < *self.threshold_us.read() {
-            return;
+        match *self.threshold_us.read() {
+            Some(threshold_us) if duration_us >= threshold_us => {}
+            _ => return,
         }
 
         let id = {
@@ -95,7 +121,7 @@ impl SlowLog {
                 .unwrap_or_default()
                 .as_secs(),
             duration_us,
-            command,
+            command: truncate_args(command),
             client_addr,
             client_name,
         };
@@ -142,6 +168,28 @@ impl Default for SlowLog {
     }
 }
 
+/// Shorten a command for storage: at most `MAX_ARGS` arguments of at most
+/// `MAX_ARG_LEN` bytes each, noting how much was left out
+fn truncate_args(mut command: Vec<String>) -> Vec<String> {
+    if command.len() > MAX_ARGS {
+        let more = command.len() - (MAX_ARGS - 1);
+        command.truncate(MAX_ARGS - 1);
+        command.push(format!("... ({} more arguments)", more));
+    }
+    for arg in command.iter_mut() {
+        if arg.len() > MAX_ARG_LEN {
+            let mut cut = MAX_ARG_LEN;
+            while !arg.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let more = arg.len() - cut;
+            arg.truncate(cut);
+            arg.push_str(&format!("... ({} more bytes)", more));
+        }
+    }
+    command
+}
+
 /// Memory usage information
 #[derive(Debug, Clone)]
 pub struct MemoryInfo {
@@ -237,4 +285,21 @@ mod tests {
 
         assert_eq!(slowlog.len(), 0);
     }
+
+    #[test]
+    fn test_slowlog_truncates_args() {
+        let slowlog = SlowLog::from_config(&SlowLogConfig { log_slower_than: 0, max_len: 2 });
+        let mut command = vec!["x".repeat(200)];
+        command.extend((0..40).map(|i| i.to_string()));
+        slowlog.log_if_slow(Instant::now(), command, "127.0.0.1:1".to_string(), None);
+
+        let entry = &slowlog.get(None)[0];
+        assert_eq!(entry.command.len(), MAX_ARGS);
+        assert!(entry.command[0].ends_with("... (72 more bytes)"));
+        assert_eq!(entry.command[MAX_ARGS - 1], "... (10 more arguments)");
+
+        slowlog.apply_config(&SlowLogConfig { log_slower_than: -1, max_len: 2 });
+        slowlog.log_if_slow(Instant::now(), vec!["GET".to_string()], "127.0.0.1:1".to_string(), None);
+        assert_eq!(slowlog.len(), 1);
+    }
 }
@@ -0,0 +1,504 @@
+//! Subscriber mode (SUBSCRIBE, PSUBSCRIBE, SSUBSCRIBE).
+//!
+//! A client that subscribes stops getting replies to its own requests only:
+//! the connection hands over to a `Subscriber`, which pushes the messages
+//! published to its channels, patterns and shard channels. While at least
+//! one subscription is left only (P|S)SUBSCRIBE, (P|S)UNSUBSCRIBE, PING,
+//! QUIT and RESET are accepted; anything else gets an error reply. Dropping
+//! the last subscription returns the connection to normal mode. Commands
+//! are checked against the connection user's ACL rules as in normal mode.
+
+use crate::db::pubsub::PubSub;
+use crate::network::resp::RespValue;
+use crate::security::Security;
+use std::future::Future;
+use std::sync::Arc;
+use std::task::Poll;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// How subscriber mode ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionEnd {
+    /// Close the connection (QUIT, socket or protocol error)
+    Close,
+    /// RESET: the connection state is reset and normal mode resumes
+    Reset,
+    /// The last subscription was dropped; normal mode resumes
+    Unsubscribed,
+}
+
+/// The channels and patterns one client is subscribed to
+pub struct Subscriber {
+    pubsub: Arc<PubSub>,
+    security: Arc<Security>,
+    /// The user logged in on the connection
+    user: Option<String>,
+    channels: Vec<(String, broadcast::Receiver<String>)>,
+    patterns: Vec<(String, broadcast::Receiver<(String, String)>)>,
+    shards: Vec<(String, broadcast::Receiver<String>)>,
+    /// Where the next `recv` starts polling, so a busy channel does not
+    /// starve the others
+    next: usize,
+}
+
+impl Subscriber {
+    /// A subscriber with no subscriptions yet, running commands as `user`
+    pub fn new(pubsub: Arc<PubSub>, security: Arc<Security>, user: Option<String>) -> Self {
+        Subscriber {
+            pubsub,
+            security,
+            user,
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            shards: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Number of channels, patterns and shard channels subscribed to
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len() + self.shards.len()
+    }
+
+    /// Run a command sent in subscriber mode, appending its replies to
+    /// `replies`. Returns how subscriber mode ends, if the command ends it.
+    pub async fn execute(&mut self, request: RespValue, replies: &mut Vec<RespValue>) -> Option<SubscriptionEnd> {
+        let mut tokens = match request {
+            RespValue::Array(Some(tokens)) => tokens.into_iter(),
+            _ => {
+                replies.push(RespValue::Error("ERR invalid command format".to_string()));
+                return None;
+            }
+        };
+        let cmd = match tokens.next() {
+            Some(RespValue::BulkString(Some(s))) | Some(RespValue::SimpleString(s)) => s,
+            _ => {
+                replies.push(RespValue::Error("ERR invalid command format".to_string()));
+                return None;
+            }
+        };
+        let args: Vec<String> = tokens
+            .filter_map(|t| match t {
+                RespValue::BulkString(Some(s)) | RespValue::SimpleString(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+
+        let cmd_upper = cmd.to_uppercase();
+        let permitted = self
+            .security
+            .check_command(self.user.as_deref(), &cmd_upper, &[])
+            .and_then(|_| self.security.check_channels(self.user.as_deref(), &cmd_upper, &args));
+        if let Err(e) = permitted {
+            replies.push(RespValue::Error(e));
+            return None;
+        }
+
+        match cmd_upper.as_str() {
+            "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" if args.is_empty() => {
+                replies.push(wrong_arity(&cmd));
+                None
+            }
+            "SUBSCRIBE" => {
+                self.subscribe(&args, replies).await;
+                None
+            }
+            "PSUBSCRIBE" => {
+                self.psubscribe(&args, replies).await;
+                None
+            }
+            "SSUBSCRIBE" => {
+                self.ssubscribe(&args, replies).await;
+                None
+            }
+            "UNSUBSCRIBE" => {
+                self.unsubscribe(&args, replies).await;
+                (self.count() == 0).then_some(SubscriptionEnd::Unsubscribed)
+            }
+            "PUNSUBSCRIBE" => {
+                self.punsubscribe(&args, replies).await;
+                (self.count() == 0).then_some(SubscriptionEnd::Unsubscribed)
+            }
+            "SUNSUBSCRIBE" => {
+                self.sunsubscribe(&args, replies).await;
+                (self.count() == 0).then_some(SubscriptionEnd::Unsubscribed)
+            }
+            "PING" if args.len() > 1 => {
+                replies.push(wrong_arity(&cmd));
+                None
+            }
+            "PING" => {
+                let message = args.into_iter().next().unwrap_or_default();
+                replies.push(push("pong", vec![RespValue::BulkString(Some(message))]));
+                None
+            }
+            "QUIT" => {
+                self.clear().await;
+                replies.push(RespValue::ok());
+                Some(SubscriptionEnd::Close)
+            }
+            "RESET" => {
+                self.clear().await;
+                replies.push(RespValue::SimpleString("RESET".to_string()));
+                Some(SubscriptionEnd::Reset)
+            }
+            _ => {
+                replies.push(RespValue::Error(format!(
+                    "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                    cmd.to_lowercase()
+                )));
+                None
+            }
+        }
+    }
+
+    /// Subscribe to `channels`, confirming each. A channel's retained
+    /// message follows its confirmation.
+    pub async fn subscribe(&mut self, channels: &[String], replies: &mut Vec<RespValue>) {
+        for channel in channels {
+            let mut retained = None;
+            if !self.channels.iter().any(|(name, _)| name == channel) {
+                let receiver = self.pubsub.subscribe(channel).await;
+                self.channels.push((channel.clone(), receiver));
+                retained = self.pubsub.retained(channel).await;
+            }
+            replies.push(self.confirmation("subscribe", Some(channel)));
+            if let Some(content) = retained {
+                replies.push(message(channel, content));
+            }
+        }
+    }
+
+    /// Subscribe to glob `patterns`, confirming each
+    pub async fn psubscribe(&mut self, patterns: &[String], replies: &mut Vec<RespValue>) {
+        for pattern in patterns {
+            if !self.patterns.iter().any(|(name, _)| name == pattern) {
+                let receiver = self.pubsub.psubscribe(pattern).await;
+                self.patterns.push((pattern.clone(), receiver));
+            }
+            replies.push(self.confirmation("psubscribe", Some(pattern)));
+        }
+    }
+
+    /// Subscribe to shard `channels`, confirming each
+    pub async fn ssubscribe(&mut self, channels: &[String], replies: &mut Vec<RespValue>) {
+        for channel in channels {
+            if !self.shards.iter().any(|(name, _)| name == channel) {
+                let receiver = self.pubsub.ssubscribe(channel).await;
+                self.shards.push((channel.clone(), receiver));
+            }
+            replies.push(self.confirmation("ssubscribe", Some(channel)));
+        }
+    }
+
+    /// Unsubscribe from `channels`, or from every channel if empty
+    pub async fn unsubscribe(&mut self, channels: &[String], replies: &mut Vec<RespValue>) {
+        let channels = match channels {
+            [] => self.channels.iter().map(|(name, _)| name.clone()).collect(),
+            channels => channels.to_vec(),
+        };
+        if channels.is_empty() {
+            replies.push(self.confirmation("unsubscribe", None));
+        }
+        for channel in channels {
+            if let Some(pos) = self.channels.iter().position(|(name, _)| *name == channel) {
+                // The receiver must be gone before the channel can be released
+                self.channels.remove(pos);
+                self.pubsub.unsubscribe(&channel).await;
+            }
+            replies.push(self.confirmation("unsubscribe", Some(&channel)));
+        }
+    }
+
+    /// Unsubscribe from `patterns`, or from every pattern if empty
+    pub async fn punsubscribe(&mut self, patterns: &[String], replies: &mut Vec<RespValue>) {
+        let patterns = match patterns {
+            [] => self.patterns.iter().map(|(name, _)| name.clone()).collect(),
+            patterns => patterns.to_vec(),
+        };
+        if patterns.is_empty() {
+            replies.push(self.confirmation("punsubscribe", None));
+        }
+        for pattern in patterns {
+            if let Some(pos) = self.patterns.iter().position(|(name, _)| *name == pattern) {
+                self.patterns.remove(pos);
+                self.pubsub.punsubscribe(&pattern).await;
+            }
+            replies.push(self.confirmation("punsubscribe", Some(&pattern)));
+        }
+    }
+
+    /// Unsubscribe from shard `channels`, or from every shard channel if empty
+    pub async fn sunsubscribe(&mut self, channels: &[String], replies: &mut Vec<RespValue>) {
+        let channels = match channels {
+            [] => self.shards.iter().map(|(name, _)| name.clone()).collect(),
+            channels => channels.to_vec(),
+        };
+        if channels.is_empty() {
+            replies.push(self.confirmation("sunsubscribe", None));
+        }
+        for channel in channels {
+            if let Some(pos) = self.shards.iter().position(|(name, _)| *name == channel) {
+                self.shards.remove(pos);
+                self.pubsub.sunsubscribe(&channel).await;
+            }
+            replies.push(self.confirmation("sunsubscribe", Some(&channel)));
+        }
+    }
+
+    /// Drop every subscription without replying
+    pub async fn clear(&mut self) {
+        for (channel, receiver) in std::mem::take(&mut self.channels) {
+            drop(receiver);
+            self.pubsub.unsubscribe(&channel).await;
+        }
+        for (pattern, receiver) in std::mem::take(&mut self.patterns) {
+            drop(receiver);
+            self.pubsub.punsubscribe(&pattern).await;
+        }
+        for (channel, receiver) in std::mem::take(&mut self.shards) {
+            drop(receiver);
+            self.pubsub.sunsubscribe(&channel).await;
+        }
+    }
+
+    /// Wait for the next message on any subscription. Waits forever while
+    /// there are none.
+    pub async fn recv(&mut self) -> Result<RespValue, RecvError> {
+        let start = self.next;
+        self.next = self.next.wrapping_add(1);
+
+        let mut channels: Vec<_> = self
+            .channels
+            .iter_mut()
+            .map(|(name, receiver)| (name.as_str(), Box::pin(receiver.recv())))
+            .collect();
+        let mut patterns: Vec<_> = self
+            .patterns
+            .iter_mut()
+            .map(|(name, receiver)| (name.as_str(), Box::pin(receiver.recv())))
+            .collect();
+        let mut shards: Vec<_> = self
+            .shards
+            .iter_mut()
+            .map(|(name, receiver)| (name.as_str(), Box::pin(receiver.recv())))
+            .collect();
+
+        std::future::poll_fn(|cx| {
+            let len = channels.len();
+            for i in 0..len {
+                let (channel, recv) = &mut channels[(start + i) % len];
+                if let Poll::Ready(result) = recv.as_mut().poll(cx) {
+                    return Poll::Ready(result.map(|content| message(channel, content)));
+                }
+            }
+            let len = patterns.len();
+            for i in 0..len {
+                let (pattern, recv) = &mut patterns[(start + i) % len];
+                if let Poll::Ready(result) = recv.as_mut().poll(cx) {
+                    return Poll::Ready(result.map(|(channel, content)| {
+                        push(
+                            "pmessage",
+                            vec![
+                                RespValue::BulkString(Some(pattern.to_string())),
+                                RespValue::BulkString(Some(channel)),
+                                RespValue::BulkString(Some(content)),
+                            ],
+                        )
+                    }));
+                }
+            }
+            let len = shards.len();
+            for i in 0..len {
+                let (channel, recv) = &mut shards[(start + i) % len];
+                if let Poll::Ready(result) = recv.as_mut().poll(cx) {
+                    return Poll::Ready(result.map(|content| {
+                        push(
+                            "smessage",
+                            vec![RespValue::BulkString(Some(channel.to_string())), RespValue::BulkString(Some(content))],
+                        )
+                    }));
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// `[kind, name, subscription count]`. Shard channel replies count
+    /// shard channels only, the others channels and patterns.
+    fn confirmation(&self, kind: &str, name: Option<&str>) -> RespValue {
+        let count = match kind {
+            "ssubscribe" | "sunsubscribe" => self.shards.len(),
+            _ => self.channels.len() + self.patterns.len(),
+        };
+        push(
+            kind,
+            vec![RespValue::BulkString(name.map(str::to_string)), RespValue::Integer(count as i64)],
+        )
+    }
+}
+
+/// A message published to `channel`
+fn message(channel: &str, content: String) -> RespValue {
+    push(
+        "message",
+        vec![RespValue::BulkString(Some(channel.to_string())), RespValue::BulkString(Some(content))],
+    )
+}
+
+/// A push reply: its kind followed by `fields`
+fn push(kind: &str, fields: Vec<RespValue>) -> RespValue {
+    let mut items = vec![RespValue::BulkString(Some(kind.to_string()))];
+    items.extend(fields);
+    RespValue::Array(Some(items))
+}
+
+fn wrong_arity(cmd: &str) -> RespValue {
+    RespValue::Error(format!("ERR wrong number of arguments for '{}' command", cmd.to_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::AclRule;
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::BulkString(Some(s.to_string()))
+    }
+
+    fn command(parts: &[&str]) -> RespValue {
+        RespValue::Array(Some(parts.iter().map(|s| bulk(s)).collect()))
+    }
+
+    fn confirmation(kind: &str, name: Option<&str>, count: i64) -> RespValue {
+        RespValue::Array(Some(vec![
+            bulk(kind),
+            RespValue::BulkString(name.map(str::to_string)),
+            RespValue::Integer(count),
+        ]))
+    }
+
+    async fn run(subscriber: &mut Subscriber, parts: &[&str]) -> (Vec<RespValue>, Option<SubscriptionEnd>) {
+        let mut replies = Vec::new();
+        let end = subscriber.execute(command(parts), &mut replies).await;
+        (replies, end)
+    }
+
+    #[tokio::test]
+    async fn test_only_subscriber_commands_are_allowed() {
+        let mut subscriber = Subscriber::new(Arc::new(PubSub::new()), Arc::new(Security::new()), None);
+        let (replies, end) = run(&mut subscriber, &["SUBSCRIBE", "a", "b", "a"]).await;
+        assert_eq!(
+            replies,
+            vec![
+                confirmation("subscribe", Some("a"), 1),
+                confirmation("subscribe", Some("b"), 2),
+                confirmation("subscribe", Some("a"), 2),
+            ]
+        );
+        assert_eq!(end, None);
+
+        let (replies, end) = run(&mut subscriber, &["GET", "k"]).await;
+        assert!(matches!(&replies[..], [RespValue::Error(e)] if e.starts_with("ERR Can't execute 'get'")));
+        assert_eq!(end, None);
+
+        let (replies, _) = run(&mut subscriber, &["ping", "hi"]).await;
+        assert_eq!(replies, vec![RespValue::Array(Some(vec![bulk("pong"), bulk("hi")]))]);
+
+        assert_eq!(run(&mut subscriber, &["QUIT"]).await, (vec![RespValue::ok()], Some(SubscriptionEnd::Close)));
+        assert_eq!(subscriber.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_last_unsubscribe_leaves_subscriber_mode() {
+        let pubsub = Arc::new(PubSub::new());
+        let mut subscriber = Subscriber::new(Arc::clone(&pubsub), Arc::new(Security::new()), None);
+        run(&mut subscriber, &["SUBSCRIBE", "a", "b"]).await;
+        run(&mut subscriber, &["PSUBSCRIBE", "news.*"]).await;
+
+        let (replies, end) = run(&mut subscriber, &["UNSUBSCRIBE"]).await;
+        assert_eq!(
+            replies,
+            vec![confirmation("unsubscribe", Some("a"), 2), confirmation("unsubscribe", Some("b"), 1)]
+        );
+        assert_eq!(end, None);
+        assert_eq!(pubsub.numsub("a").await, 0);
+
+        let (replies, end) = run(&mut subscriber, &["PUNSUBSCRIBE", "news.*"]).await;
+        assert_eq!(replies, vec![confirmation("punsubscribe", Some("news.*"), 0)]);
+        assert_eq!(end, Some(SubscriptionEnd::Unsubscribed));
+        assert_eq!(pubsub.numpat().await, 0);
+
+        let (replies, _) = run(&mut subscriber, &["UNSUBSCRIBE"]).await;
+        assert_eq!(replies, vec![confirmation("unsubscribe", None, 0)]);
+
+        run(&mut subscriber, &["SUBSCRIBE", "a"]).await;
+        assert_eq!(run(&mut subscriber, &["RESET"]).await.1, Some(SubscriptionEnd::Reset));
+        assert_eq!(pubsub.numsub("a").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_shard_channel_subscriptions() {
+        let pubsub = Arc::new(PubSub::new());
+        let mut subscriber = Subscriber::new(Arc::clone(&pubsub), Arc::new(Security::new()), None);
+        run(&mut subscriber, &["SUBSCRIBE", "a"]).await;
+        let (replies, _) = run(&mut subscriber, &["SSUBSCRIBE", "orders", "users"]).await;
+        assert_eq!(
+            replies,
+            vec![confirmation("ssubscribe", Some("orders"), 1), confirmation("ssubscribe", Some("users"), 2)]
+        );
+
+        assert_eq!(pubsub.spublish("orders", "1").await, 1);
+        assert_eq!(subscriber.recv().await.unwrap(), command(&["smessage", "orders", "1"]));
+
+        run(&mut subscriber, &["UNSUBSCRIBE"]).await;
+        let (replies, end) = run(&mut subscriber, &["SUNSUBSCRIBE"]).await;
+        assert_eq!(
+            replies,
+            vec![confirmation("sunsubscribe", Some("orders"), 1), confirmation("sunsubscribe", Some("users"), 0)]
+        );
+        assert_eq!(end, Some(SubscriptionEnd::Unsubscribed));
+        assert_eq!(pubsub.shard_numsub("orders").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_recv_channel_and_pattern_messages() {
+        let pubsub = Arc::new(PubSub::new());
+        let mut subscriber = Subscriber::new(Arc::clone(&pubsub), Arc::new(Security::new()), None);
+        run(&mut subscriber, &["SUBSCRIBE", "chat"]).await;
+        run(&mut subscriber, &["PSUBSCRIBE", "news.*"]).await;
+
+        assert_eq!(pubsub.publish("chat", "hi").await, 1);
+        assert_eq!(subscriber.recv().await.unwrap(), command(&["message", "chat", "hi"]));
+        assert_eq!(pubsub.publish("news.sport", "goal").await, 1);
+        assert_eq!(subscriber.recv().await.unwrap(), command(&["pmessage", "news.*", "news.sport", "goal"]));
+    }
+
+    #[tokio::test]
+    async fn test_acl_applies_in_subscriber_mode() {
+        let pubsub = Arc::new(PubSub::new());
+        let security = Arc::new(Security::new());
+        security
+            .acl_setuser(
+                "news".to_string(),
+                vec![AclRule::AllCommands, AclRule::ChannelPattern("news:*".to_string())],
+            )
+            .unwrap();
+        let mut subscriber = Subscriber::new(Arc::clone(&pubsub), Arc::clone(&security), Some("news".to_string()));
+        run(&mut subscriber, &["SUBSCRIBE", "news:eu"]).await;
+
+        let (replies, end) = run(&mut subscriber, &["SUBSCRIBE", "admin"]).await;
+        assert!(matches!(&replies[..], [RespValue::Error(e)] if e.starts_with("NOPERM")));
+        assert_eq!(end, None);
+        let (replies, _) = run(&mut subscriber, &["PSUBSCRIBE", "*"]).await;
+        assert!(matches!(&replies[..], [RespValue::Error(e)] if e.starts_with("NOPERM")));
+        assert_eq!(pubsub.numsub("admin").await, 0);
+        assert_eq!(subscriber.count(), 1);
+
+        // Rule changes apply to a connection that is already subscribed
+        security.acl_setuser("news".to_string(), vec![AclRule::DenyCommand("ping".to_string())]).unwrap();
+        let (replies, _) = run(&mut subscriber, &["PING"]).await;
+        assert!(matches!(&replies[..], [RespValue::Error(e)] if e.starts_with("NOPERM")));
+    }
+}